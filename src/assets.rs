@@ -0,0 +1,258 @@
+//! The images the client loads and checking they all loaded before the game starts.
+//!
+//! All textures are loaded at once. Without checking each result, the first missing file
+//! panicked without saying which others are missing and an image which decoded as 0x0
+//! wasn't an error at all - it just drew nothing. `Loading` records every result
+//! so all problems are reported together by the asset's name, which is its path
+//! without `assets/` and the extension, e.g. `weapons/rocket`.
+//!
+//! The loading screen polls the same `Loading` to draw its progress.
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+use crate::map::Surface;
+
+/// Paths of the textures `MacroquadClient` loads, grouped by what they're used for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TexturePaths {
+    /// In the same order as surfaces so maps can use surface indices for both.
+    pub tiles: Vec<String>,
+    /// Chassis and turret of each vehicle type.
+    pub vehicles: Vec<String>,
+    pub wrecks: Vec<String>,
+    pub weapon_icons: Vec<String>,
+    /// Projectiles and explosions.
+    pub rest: Vec<String>,
+}
+
+impl TexturePaths {
+    pub fn new(surfaces: &[Surface]) -> Self {
+        let owned = |paths: &[&str]| paths.iter().map(|&path| path.to_owned()).collect();
+        Self {
+            tiles: surfaces
+                .iter()
+                .map(|surface| format!("assets/tiles/{}.bmp", surface.name))
+                .collect(),
+            vehicles: owned(&[
+                "assets/vehicles/tank_chassis_flames.png",
+                "assets/vehicles/tank_turret_flames.png",
+                "assets/vehicles/hovercraft_chassis_flames.png",
+                "assets/vehicles/hovercraft_turret_flames.png",
+                "assets/vehicles/hummer_chassis_flames.png",
+                "assets/vehicles/hummer_turret_flames.png",
+            ]),
+            wrecks: owned(&[
+                "assets/wrecks/tank.png",
+                "assets/wrecks/hovercraft.png",
+                "assets/wrecks/hummer.png",
+            ]),
+            weapon_icons: owned(&[
+                "assets/weapon_icons/mg.png",
+                "assets/weapon_icons/rail.png",
+                "assets/weapon_icons/cb.png",
+                "assets/weapon_icons/rockets.png",
+                "assets/weapon_icons/hm.png",
+                "assets/weapon_icons/gm.png",
+                "assets/weapon_icons/bfg.png",
+            ]),
+            rest: owned(&[
+                "assets/weapons/rocket.png",
+                "assets/weapons/hm.png",
+                "assets/weapons/gm.png",
+                "assets/explosion.png",
+                "assets/explosion_cyan.png",
+            ]),
+        }
+    }
+
+    /// All the paths, group after group.
+    pub fn all(&self) -> Vec<&str> {
+        [
+            &self.tiles,
+            &self.vehicles,
+            &self.wrecks,
+            &self.weapon_icons,
+            &self.rest,
+        ]
+        .iter()
+        .flat_map(|group| group.iter().map(String::as_str))
+        .collect()
+    }
+}
+
+/// E.g. `assets/weapons/rocket.png` -> `weapons/rocket`.
+pub fn asset_name(path: &str) -> &str {
+    let name = path.strip_prefix("assets/").unwrap_or(path);
+    match name.rfind('.') {
+        Some(dot) if !name[dot..].contains('/') => &name[..dot],
+        _ => name,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetProblem {
+    pub name: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetError {
+    pub problems: Vec<AssetProblem>,
+}
+
+impl Display for AssetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} missing or broken assets:", self.problems.len())?;
+        for problem in &self.problems {
+            write!(f, "\n    {}: {}", problem.name, problem.reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for AssetError {}
+
+/// Which assets have finished loading and what went wrong with them.
+#[derive(Debug, Clone)]
+pub struct Loading {
+    names: Vec<String>,
+    finished: Vec<bool>,
+    /// In the order they were recorded.
+    problems: Vec<AssetProblem>,
+}
+
+impl Loading {
+    pub fn new(paths: &[&str]) -> Self {
+        Self {
+            names: paths
+                .iter()
+                .map(|path| asset_name(path).to_owned())
+                .collect(),
+            finished: vec![false; paths.len()],
+            problems: Vec::new(),
+        }
+    }
+
+    /// Record the result of loading the asset at `index` in the paths -
+    /// the image's width and height or why it failed.
+    pub fn record(&mut self, index: usize, size: Result<(f32, f32), String>) {
+        self.finished[index] = true;
+        let reason = match size {
+            Ok((w, h)) if w > 0.0 && h > 0.0 => return,
+            Ok((w, h)) => format!("empty image ({}x{})", w, h),
+            Err(err) => err,
+        };
+        self.problems.push(AssetProblem {
+            name: self.names[index].clone(),
+            reason,
+        });
+    }
+
+    /// How many assets finished loading (successfully or not) and how many there are.
+    pub fn progress(&self) -> (usize, usize) {
+        let finished = self.finished.iter().filter(|&&finished| finished).count();
+        (finished, self.names.len())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished.iter().all(|&finished| finished)
+    }
+
+    /// Everything loaded and nothing is broken.
+    pub fn ready(&self) -> bool {
+        self.is_finished() && self.problems.is_empty()
+    }
+
+    /// Names of assets which failed or haven't loaded yet.
+    pub fn missing(&self) -> Vec<&str> {
+        let pending = self
+            .names
+            .iter()
+            .zip(&self.finished)
+            .filter(|&(_, &finished)| !finished)
+            .map(|(name, _)| name.as_str());
+        let failed = self.problems.iter().map(|problem| problem.name.as_str());
+        failed.chain(pending).collect()
+    }
+
+    /// Every problem at once, assets which never finished loading count too.
+    pub fn finish(self) -> Result<(), AssetError> {
+        let mut problems = self.problems;
+        for (name, finished) in self.names.into_iter().zip(self.finished) {
+            if !finished {
+                problems.push(AssetProblem {
+                    name,
+                    reason: "not loaded".to_owned(),
+                });
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(AssetError { problems })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use crate::map;
+
+    use super::*;
+
+    #[test]
+    fn test_texture_paths_exist() {
+        let tex_list_text = fs::read_to_string("assets/texture_list.txt").unwrap();
+        let surfaces = map::load_tex_list(&tex_list_text).unwrap();
+        let paths = TexturePaths::new(&surfaces);
+        assert_eq!(paths.all().len(), surfaces.len() + 21);
+        let missing: Vec<_> = paths
+            .all()
+            .into_iter()
+            .filter(|path| !Path::new(path).exists())
+            .collect();
+        assert!(missing.is_empty(), "missing: {:?}", missing);
+    }
+
+    #[test]
+    fn test_loading() {
+        assert_eq!(asset_name("assets/weapons/rocket.png"), "weapons/rocket");
+        assert_eq!(asset_name("assets/tiles/g1.bmp"), "tiles/g1");
+        assert_eq!(asset_name("some.dir/file"), "some.dir/file");
+
+        let paths = [
+            "assets/explosion.png",
+            "assets/weapons/gm.png",
+            "assets/weapons/hm.png",
+            "assets/weapons/rocket.png",
+        ];
+        let mut loading = Loading::new(&paths);
+        assert_eq!(loading.progress(), (0, 4));
+        loading.record(3, Ok((32.0, 16.0)));
+        loading.record(1, Err("file not found".to_owned()));
+        loading.record(0, Ok((0.0, 0.0)));
+        assert_eq!(loading.progress(), (3, 4));
+        assert!(!loading.is_finished());
+        assert!(!loading.ready());
+        assert_eq!(loading.missing(), ["weapons/gm", "explosion", "weapons/hm"]);
+
+        // Everything is reported, not just the first problem.
+        let err = loading.finish().unwrap_err();
+        let expected = "3 missing or broken assets:
+    weapons/gm: file not found
+    explosion: empty image (0x0)
+    weapons/hm: not loaded";
+        assert_eq!(err.to_string(), expected);
+
+        let mut loading = Loading::new(&paths[..1]);
+        loading.record(0, Ok((1.0, 1.0)));
+        assert!(loading.ready());
+        assert!(loading.missing().is_empty());
+        assert_eq!(loading.finish(), Ok(()));
+    }
+}
@@ -0,0 +1,451 @@
+//! Key bindings - which keys produce which `Input`.
+//!
+//! The frontend doesn't fill in `Input` itself, it forwards raw key events
+//! (`key_event`) and the binding table turns them into input.
+//! Keys are named like the JS `KeyboardEvent.code` (`KeyW`, `ArrowUp`, `ShiftLeft`, ...)
+//! so the browser can pass them through unchanged, the macroquad client translates its `KeyCode`s.
+//!
+//! Any number of keys can be bound to the same action, the action is active
+//! while at least one of them is held. A key is bound to at most one action.
+//!
+//! A key pressed and released between two frames still counts for the next frame,
+//! otherwise quick taps (e.g. switching weapons) would sometimes get lost.
+//!
+//! Saving the table is the frontend's job, it can get and set the whole table
+//! as a flat JSON object from key to action name, e.g. `{"KeyW": "forward", "Space": "fire"}`.
+//!
+//! LATER Rebinding from the console or a menu.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+use crate::{
+    entities::{Weapon, WEAPS_CNT},
+    game_state::Input,
+    json::{self, JsonError, Value},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Action {
+    Forward,
+    Back,
+    Left,
+    Right,
+    TurretCcw,
+    TurretCw,
+    Fire,
+    PrevWeapon,
+    NextWeapon,
+    /// Switch directly to a weapon, numbered from 1 like the HUD.
+    SelectWeapon(u8),
+    Mine,
+    SelfDestruct,
+    Horn,
+    Flare,
+    Chat,
+    Pause,
+}
+
+impl Action {
+    const SIMPLE: [(Action, &'static str); 15] = [
+        (Action::Forward, "forward"),
+        (Action::Back, "back"),
+        (Action::Left, "left"),
+        (Action::Right, "right"),
+        (Action::TurretCcw, "turret_ccw"),
+        (Action::TurretCw, "turret_cw"),
+        (Action::Fire, "fire"),
+        (Action::PrevWeapon, "prev_weapon"),
+        (Action::NextWeapon, "next_weapon"),
+        (Action::Mine, "mine"),
+        (Action::SelfDestruct, "self_destruct"),
+        (Action::Horn, "horn"),
+        (Action::Flare, "flare"),
+        (Action::Chat, "chat"),
+        (Action::Pause, "pause"),
+    ];
+    const SELECT_WEAPON: &'static str = "select_weapon_";
+
+    /// Parse the name used in the binding table, e.g. `turret_cw` or `select_weapon_3`.
+    pub fn from_name(name: &str) -> Option<Action> {
+        if let Some(num) = name.strip_prefix(Action::SELECT_WEAPON) {
+            // Not `parse` directly - it would accept `+3`.
+            if !num.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            return match num.parse() {
+                Ok(num) if (1..=WEAPS_CNT).contains(&num) => Some(Action::SelectWeapon(num)),
+                _ => None,
+            };
+        }
+        Action::SIMPLE
+            .iter()
+            .find(|(_, simple)| *simple == name)
+            .map(|&(action, _)| action)
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            Action::SelectWeapon(num) => format!("{}{}", Action::SELECT_WEAPON, num),
+            _ => {
+                let (_, name) = Action::SIMPLE
+                    .iter()
+                    .find(|&&(simple, _)| simple == self)
+                    .unwrap();
+                (*name).to_owned()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Bindings {
+    table: BTreeMap<String, Action>,
+    /// Keys currently held.
+    down: BTreeSet<String>,
+    /// Keys pressed since the last `take_input`, even if already released.
+    pressed: BTreeSet<String>,
+}
+
+impl Bindings {
+    /// No keys bound.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Keys to avoid in defaults:
+    //  - Ctrl - ctrl+W closes the browser tab
+    //  - Alt - shows/hides the firefox menu bar on linux
+    //  - Numpad - Some keyboards might not have it
+    //  - Keys that often depend on layout - https://github.com/not-fl3/macroquad/issues/260
+
+    /// Default bindings of the first player (or the only one in singleplayer).
+    ///
+    /// Shared actions like chat and pause are only bound here.
+    pub fn player1() -> Self {
+        let mut bindings = Self::new();
+        for &(key, action) in &[
+            ("KeyA", "left"),
+            ("KeyD", "right"),
+            ("KeyW", "forward"),
+            ("KeyS", "back"),
+            ("KeyQ", "turret_ccw"),
+            ("KeyE", "turret_cw"),
+            ("KeyV", "prev_weapon"),
+            ("ShiftLeft", "next_weapon"),
+            ("KeyC", "next_weapon"),
+            ("Space", "fire"),
+            ("Digit1", "select_weapon_1"),
+            ("Digit2", "select_weapon_2"),
+            ("Digit3", "select_weapon_3"),
+            ("Digit4", "select_weapon_4"),
+            ("Digit5", "select_weapon_5"),
+            ("Digit6", "select_weapon_6"),
+            ("Digit7", "select_weapon_7"),
+            ("KeyX", "mine"),
+            ("KeyG", "self_destruct"),
+            ("KeyR", "horn"),
+            ("KeyF", "flare"),
+            ("Enter", "chat"),
+            ("KeyT", "chat"),
+            ("Pause", "pause"),
+            ("KeyP", "pause"),
+        ] {
+            bindings.bind(key, action).unwrap();
+        }
+        bindings
+    }
+
+    /// Default bindings of the second player in splitscreen.
+    pub fn player2() -> Self {
+        let mut bindings = Self::new();
+        for &(key, action) in &[
+            ("ArrowLeft", "left"),
+            ("ArrowRight", "right"),
+            ("ArrowUp", "forward"),
+            ("ArrowDown", "back"),
+            ("Comma", "turret_ccw"),
+            ("Period", "turret_cw"),
+            ("KeyL", "prev_weapon"),
+            ("Slash", "next_weapon"),   // US layout
+            ("Minus", "next_weapon"),   // Same key, CZ layout
+            ("Numpad0", "next_weapon"), // Exception to the numpad rule, it's only an alternative
+            ("ShiftRight", "fire"),
+            ("KeyM", "mine"),
+            ("KeyJ", "self_destruct"),
+            ("KeyK", "horn"),
+            ("KeyN", "flare"),
+        ] {
+            bindings.bind(key, action).unwrap();
+        }
+        bindings
+    }
+
+    /// Bind the key to the action, replacing what it was bound to before (returned).
+    /// Other keys bound to the same action stay bound.
+    pub fn bind(&mut self, key: &str, action: &str) -> Result<Option<Action>, BindingError> {
+        if key.is_empty() || key.chars().any(char::is_whitespace) {
+            return Err(BindingError::key(key, "invalid key name"));
+        }
+        let action = Action::from_name(action)
+            .ok_or_else(|| BindingError::key(key, &format!("unknown action {:?}", action)))?;
+        Ok(self.table.insert(key.to_owned(), action))
+    }
+
+    /// Returns what the key was bound to.
+    #[allow(dead_code)] // LATER Used once there's rebinding in the game itself.
+    pub fn unbind(&mut self, key: &str) -> Option<Action> {
+        self.table.remove(key)
+    }
+
+    /// All bindings ordered by action, then by key.
+    pub fn bindings_list(&self) -> Vec<(&str, Action)> {
+        let mut list: Vec<_> = self
+            .table
+            .iter()
+            .map(|(key, &action)| (key.as_str(), action))
+            .collect();
+        list.sort_by_key(|&(key, action)| (action, key));
+        list
+    }
+
+    /// The whole table as a JSON object, in the same order as `bindings_list`.
+    pub fn to_json(&self) -> String {
+        let fields: Vec<_> = self
+            .bindings_list()
+            .into_iter()
+            .map(|(key, action)| format!("{}: {}", json::quote(key), json::quote(&action.name())))
+            .collect();
+        format!("{{{}}}", fields.join(", "))
+    }
+
+    /// Replace the whole table. On error, the table is left unchanged.
+    ///
+    /// Held keys stay held so an action whose key is still down becomes active immediately.
+    pub fn set_json(&mut self, json: &str) -> Result<(), BindingError> {
+        let mut new = Self::new();
+        for (key, value) in json::parse_object(json)? {
+            match value {
+                Value::Str(action) => {
+                    new.bind(&key, &action)?;
+                }
+                _ => return Err(BindingError::key(&key, "action must be a string")),
+            }
+        }
+        self.table = new.table;
+        Ok(())
+    }
+
+    /// A raw key event from the frontend. Repeated presses (autorepeat) are harmless.
+    ///
+    /// Unbound keys are tracked too so binding a key while it's held takes effect right away.
+    pub fn key_event(&mut self, key: &str, pressed: bool) {
+        if pressed {
+            self.down.insert(key.to_owned());
+            self.pressed.insert(key.to_owned());
+        } else {
+            self.down.remove(key);
+        }
+    }
+
+    /// The input for this frame. Forgets keys which were only tapped since the last call.
+    pub fn take_input(&mut self) -> Input {
+        let mut input = Input::new();
+        for key in self.down.iter().chain(&self.pressed) {
+            let action = match self.table.get(key) {
+                Some(&action) => action,
+                None => continue,
+            };
+            match action {
+                Action::Forward => input.up = true,
+                Action::Back => input.down = true,
+                Action::Left => input.left = true,
+                Action::Right => input.right = true,
+                Action::TurretCcw => input.turret_left = true,
+                Action::TurretCw => input.turret_right = true,
+                Action::Fire => input.fire = true,
+                Action::PrevWeapon => input.prev_weapon = true,
+                Action::NextWeapon => input.next_weapon = true,
+                Action::SelectWeapon(num) => {
+                    // If several are held, the highest wins, regardless of key order.
+                    let weapon = Weapon::n(num - 1).unwrap();
+                    if input
+                        .select_weapon
+                        .map_or(true, |prev| prev as u8 <= weapon as u8)
+                    {
+                        input.select_weapon = Some(weapon);
+                    }
+                }
+                Action::Mine => input.mine = true,
+                Action::SelfDestruct => input.self_destruct = true,
+                Action::Horn => input.horn = true,
+                Action::Flare => input.flare = true,
+                Action::Chat => input.chat = true,
+                Action::Pause => input.pause = true,
+            }
+        }
+        self.pressed.clear();
+        input
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindingError {
+    /// Not a flat JSON object, `pos` is the byte offset.
+    Syntax { pos: usize, msg: String },
+    /// The key name or the action it's bound to is invalid.
+    Key { key: String, msg: String },
+}
+
+impl BindingError {
+    fn key(key: &str, msg: &str) -> Self {
+        BindingError::Key {
+            key: key.to_owned(),
+            msg: msg.to_owned(),
+        }
+    }
+}
+
+impl Display for BindingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingError::Syntax { pos, msg } => write!(f, "byte {}: {}", pos, msg),
+            BindingError::Key { key, msg } => write!(f, "key {:?}: {}", key, msg),
+        }
+    }
+}
+
+impl Error for BindingError {}
+
+impl From<JsonError> for BindingError {
+    fn from(err: JsonError) -> Self {
+        BindingError::Syntax {
+            pos: err.pos,
+            msg: err.msg,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_action_names() {
+        for &(action, name) in &Action::SIMPLE {
+            assert_eq!(action.name(), name);
+            assert_eq!(Action::from_name(name), Some(action));
+        }
+        for num in 1..=WEAPS_CNT {
+            let action = Action::SelectWeapon(num);
+            assert_eq!(Action::from_name(&action.name()), Some(action));
+        }
+        for name in [
+            "",
+            "Forward",
+            "select_weapon_0",
+            "select_weapon_8",
+            "select_weapon_+3",
+        ] {
+            assert_eq!(Action::from_name(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_multiple_keys_or() {
+        let mut bindings = Bindings::player1();
+        bindings.key_event("ShiftLeft", true);
+        bindings.key_event("KeyC", true);
+        assert!(bindings.take_input().next_weapon);
+        // Releasing one of the two keys doesn't stop the action.
+        bindings.key_event("ShiftLeft", false);
+        assert!(bindings.take_input().next_weapon);
+        bindings.key_event("KeyC", false);
+        assert_eq!(bindings.take_input(), Input::new());
+
+        // Holding one and tapping the other.
+        bindings.key_event("KeyC", true);
+        bindings.key_event("ShiftLeft", true);
+        bindings.key_event("ShiftLeft", false);
+        assert!(bindings.take_input().next_weapon);
+        assert!(bindings.take_input().next_weapon);
+        bindings.key_event("KeyC", false);
+        assert!(!bindings.take_input().next_weapon);
+    }
+
+    #[test]
+    fn test_tap_between_frames() {
+        let mut bindings = Bindings::player1();
+        bindings.key_event("Digit3", true);
+        bindings.key_event("Digit3", false);
+        bindings.key_event("Digit5", true);
+        bindings.key_event("Digit5", false);
+        let input = bindings.take_input();
+        assert_eq!(input.select_weapon, Some(Weapon::Hm));
+        assert_eq!(bindings.take_input(), Input::new());
+
+        // Unbound keys and other players' keys do nothing.
+        bindings.key_event("ArrowUp", true);
+        bindings.key_event("F13", true);
+        assert_eq!(bindings.take_input(), Input::new());
+    }
+
+    #[test]
+    fn test_bind_unbind() {
+        let mut bindings = Bindings::new();
+        assert_eq!(bindings.bind("KeyW", "forward"), Ok(None));
+        assert_eq!(bindings.bind("KeyW", "fire"), Ok(Some(Action::Forward)));
+        assert_eq!(bindings.bind("KeyF", "fire"), Ok(None));
+        assert!(matches!(
+            bindings.bind("KeyZ", "jump"),
+            Err(BindingError::Key { .. })
+        ));
+        assert!(bindings.bind("", "fire").is_err());
+        assert_eq!(
+            bindings.bindings_list(),
+            vec![("KeyF", Action::Fire), ("KeyW", Action::Fire)]
+        );
+
+        // Binding a held key takes effect immediately.
+        bindings.key_event("KeyA", true);
+        assert_eq!(bindings.take_input(), Input::new());
+        bindings.bind("KeyA", "left").unwrap();
+        assert!(bindings.take_input().left);
+
+        assert_eq!(bindings.unbind("KeyA"), Some(Action::Left));
+        assert_eq!(bindings.unbind("KeyA"), None);
+        assert!(!bindings.take_input().left);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        for original in [Bindings::new(), Bindings::player1(), Bindings::player2()] {
+            let json = original.to_json();
+            let mut loaded = Bindings::player1();
+            loaded.set_json(&json).unwrap();
+            assert_eq!(loaded.bindings_list(), original.bindings_list());
+        }
+        assert!(Bindings::player1()
+            .to_json()
+            .starts_with(r#"{"KeyW": "forward", "KeyS": "back", "#));
+
+        // Invalid tables are rejected whole.
+        let mut bindings = Bindings::player2();
+        let before = bindings.to_json();
+        for json in [
+            r#"{"KeyW": "forward", "KeyF": "jump"}"#,
+            r#"{"KeyW": "forward", "KeyF": 1}"#,
+            r#"{"KeyW": "forward""#,
+        ] {
+            assert!(bindings.set_json(json).is_err(), "{}", json);
+            assert_eq!(bindings.to_json(), before);
+        }
+        assert!(matches!(
+            bindings.set_json(r#"{"KeyW": "forward", "KeyW": "back"}"#),
+            Err(BindingError::Syntax { .. })
+        ));
+    }
+}
@@ -0,0 +1,335 @@
+//! Text drawn from a font atlas instead of rasterizing a TTF font each time.
+//!
+//! macroquad's `draw_text` looks slightly different on each platform and is expensive
+//! with lots of debug text. A bitmap font is an image with all the glyphs (`assets/font.png`)
+//! and a descriptor (`assets/font.txt`) which says where each glyph is:
+//!
+//! ```text
+//! # Comments start with '#'.
+//! line_height 16
+//! base 12
+//! char 65 0 0 8 12 9
+//! ```
+//!
+//! `base` is the distance from the top of a line to the baseline. Each `char` line is the char's code
+//! (so space and '#' need no escaping), the glyph's position and size in the atlas and how far to move after it.
+//! Glyphs are drawn from the top of the line so their rects should start at the same height as the font's line.
+//!
+//! The glyphs should be white, color is applied by tinting. Chars missing from the atlas
+//! are drawn as a placeholder box so they're easy to notice.
+//!
+//! Layout doesn't need a window so it's tested and benchmarked separately from drawing.
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+use fnv::FnvHashMap;
+use macroquad::prelude::*;
+
+/// A glyph's rect in the atlas in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glyph {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+    pub advance: f32,
+}
+
+/// Where to draw one glyph, `glyph` is `None` for the placeholder box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quad {
+    pub glyph: Option<Glyph>,
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontDescriptor {
+    pub line_height: f32,
+    pub base: f32,
+    pub glyphs: FnvHashMap<char, Glyph>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FontError {
+    /// Starting from 1, 0 if something is missing from the whole descriptor.
+    pub line: usize,
+    pub msg: String,
+}
+
+impl Display for FontError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+impl Error for FontError {}
+
+impl FontDescriptor {
+    pub fn parse(text: &str) -> Result<Self, FontError> {
+        let mut line_height = None;
+        let mut base = None;
+        let mut glyphs = FnvHashMap::default();
+        for (i, line) in text.lines().enumerate() {
+            let err = |msg: String| FontError { line: i + 1, msg };
+            let line = line.split('#').next().unwrap().trim();
+            let mut parts = line.split_whitespace();
+            let key = match parts.next() {
+                Some(key) => key,
+                None => continue,
+            };
+            let mut nums = Vec::new();
+            for part in parts {
+                let num: f32 = part
+                    .parse()
+                    .map_err(|_| err(format!("invalid number {:?}", part)))?;
+                nums.push(num);
+            }
+            let expected = match key {
+                "line_height" | "base" => 1,
+                "char" => 6,
+                _ => return Err(err(format!("unknown item {:?}", key))),
+            };
+            if nums.len() != expected {
+                return Err(err(format!(
+                    "{} needs {} numbers, got {}",
+                    key,
+                    expected,
+                    nums.len()
+                )));
+            }
+            match key {
+                "line_height" => line_height = Some(nums[0]),
+                "base" => base = Some(nums[0]),
+                _ => {
+                    let c = char::from_u32(nums[0] as u32)
+                        .ok_or_else(|| err(format!("invalid char code {}", nums[0])))?;
+                    let glyph = Glyph {
+                        x: nums[1],
+                        y: nums[2],
+                        w: nums[3],
+                        h: nums[4],
+                        advance: nums[5],
+                    };
+                    if glyphs.insert(c, glyph).is_some() {
+                        return Err(err(format!("char {:?} is defined twice", c)));
+                    }
+                }
+            }
+        }
+        let line_height = line_height.ok_or_else(|| FontError {
+            line: 0,
+            msg: "missing line_height".to_owned(),
+        })?;
+        Ok(Self {
+            line_height,
+            // Without a baseline, glyphs sit on the bottom of the line.
+            base: base.unwrap_or(line_height),
+            glyphs,
+        })
+    }
+
+    /// The scale which makes text as tall as macroquad's `draw_text` with this `font_size`.
+    pub fn scale(&self, font_size: f64) -> f32 {
+        font_size as f32 / self.line_height
+    }
+
+    /// Where to draw each glyph. Like macroquad's `draw_text`, `y` is the baseline of the first line.
+    pub fn layout(&self, text: &str, x: f32, y: f32, scale: f32) -> Vec<Quad> {
+        let mut quads = Vec::with_capacity(text.len());
+        let mut pen_x = x;
+        let mut top = y - self.base * scale;
+        for c in text.chars() {
+            match (c, self.glyphs.get(&c)) {
+                ('\n', _) => {
+                    pen_x = x;
+                    top += self.line_height * scale;
+                }
+                (_, Some(glyph)) => {
+                    quads.push(Quad {
+                        glyph: Some(*glyph),
+                        x: pen_x,
+                        y: top,
+                        w: glyph.w * scale,
+                        h: glyph.h * scale,
+                    });
+                    pen_x += glyph.advance * scale;
+                }
+                (' ', None) => pen_x += self.placeholder_advance() * scale,
+                (_, None) => {
+                    quads.push(Quad {
+                        glyph: None,
+                        x: pen_x,
+                        y: top,
+                        w: (self.placeholder_advance() - 1.0).max(1.0) * scale,
+                        h: self.base * scale,
+                    });
+                    pen_x += self.placeholder_advance() * scale;
+                }
+            }
+        }
+        quads
+    }
+
+    /// Same fields as macroquad's `measure_text` so the two are interchangeable:
+    /// the widest line, from the top of the first line to the baseline of the last one
+    /// and from the top to the first baseline.
+    pub fn measure(&self, text: &str, scale: f32) -> TextDimensions {
+        let mut width = 0.0_f32;
+        let mut lines = 0;
+        for line in text.split('\n') {
+            lines += 1;
+            let line_width: f32 = line
+                .chars()
+                .map(|c| match self.glyphs.get(&c) {
+                    Some(glyph) => glyph.advance,
+                    None => self.placeholder_advance(),
+                })
+                .sum();
+            width = width.max(line_width);
+        }
+        TextDimensions {
+            width: width * scale,
+            height: ((lines - 1) as f32 * self.line_height + self.base) * scale,
+            offset_y: self.base * scale,
+        }
+    }
+
+    /// How much space missing glyphs take, spaces too unless the atlas has one.
+    fn placeholder_advance(&self) -> f32 {
+        (self.line_height / 2.0).round()
+    }
+}
+
+#[derive(Debug)]
+pub struct BitmapFont {
+    pub atlas: Texture2D,
+    pub descriptor: FontDescriptor,
+}
+
+impl BitmapFont {
+    pub fn new(atlas: Texture2D, descriptor: FontDescriptor) -> Self {
+        atlas.set_filter(FilterMode::Nearest);
+        Self { atlas, descriptor }
+    }
+
+    /// Draw `text` with its first baseline at `y`, `\n` starts a new line.
+    pub fn draw_text(&self, text: &str, x: f32, y: f32, scale: f32, color: Color) {
+        for quad in self.descriptor.layout(text, x, y, scale) {
+            match quad.glyph {
+                Some(glyph) => draw_texture_ex(
+                    self.atlas,
+                    quad.x,
+                    quad.y,
+                    color,
+                    DrawTextureParams {
+                        dest_size: Some(Vec2::new(quad.w, quad.h)),
+                        source: Some(Rect::new(glyph.x, glyph.y, glyph.w, glyph.h)),
+                        ..Default::default()
+                    },
+                ),
+                None => draw_rectangle_lines(quad.x, quad.y, quad.w, quad.h, scale.max(1.0), color),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    const DESCRIPTOR: &str = "
+        # Two glyphs, 'g' has a descender.
+        line_height 10
+        base 8
+        char 65 0 0 6 8 7
+        char 103 6 0 5 9 6
+    ";
+
+    #[test]
+    fn test_parse() {
+        let font = FontDescriptor::parse(DESCRIPTOR).unwrap();
+        assert_eq!(font.line_height, 10.0);
+        assert_eq!(font.base, 8.0);
+        assert_eq!(font.glyphs.len(), 2);
+        assert_eq!(
+            font.glyphs[&'g'],
+            Glyph {
+                x: 6.0,
+                y: 0.0,
+                w: 5.0,
+                h: 9.0,
+                advance: 6.0,
+            }
+        );
+
+        let err = FontDescriptor::parse("line_height 10\nchar 65 0 0 6 8").unwrap_err();
+        assert_eq!(err.line, 2);
+        let err = FontDescriptor::parse("line_height 10\nkerning 1").unwrap_err();
+        assert_eq!(err.to_string(), r#"line 2: unknown item "kerning""#);
+        let err = FontDescriptor::parse("char 65 0 0 6 8 7\nchar 65 0 0 6 8 7").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(FontDescriptor::parse("base 8").is_err());
+    }
+
+    #[test]
+    fn test_layout() {
+        let font = FontDescriptor::parse(DESCRIPTOR).unwrap();
+
+        // 'B' isn't in the atlas, space is skipped without a placeholder.
+        let quads = font.layout("A B\ng", 100.0, 50.0, 2.0);
+        assert_eq!(quads.len(), 3);
+        assert_eq!((quads[0].x, quads[0].y), (100.0, 34.0));
+        assert_eq!((quads[0].w, quads[0].h), (12.0, 16.0));
+        assert_eq!(quads[1].glyph, None);
+        assert_eq!((quads[1].x, quads[1].y), (124.0, 34.0));
+        assert_eq!((quads[1].w, quads[1].h), (8.0, 16.0));
+        // Next line, back at the start.
+        assert_eq!(quads[2].glyph, Some(font.glyphs[&'g']));
+        assert_eq!((quads[2].x, quads[2].y), (100.0, 54.0));
+
+        let size = font.measure("A B\ng", 2.0);
+        assert_eq!(size.width, 34.0);
+        assert_eq!(size.height, 36.0);
+        assert_eq!(size.offset_y, 16.0);
+        assert_eq!(font.measure("", 1.0).width, 0.0);
+        assert_eq!(font.scale(20.0), 2.0);
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_layout() {
+        // The HUD and debug text in a busy frame.
+        let mut descriptor = String::from("line_height 16\nbase 12\n");
+        for code in 32..127 {
+            descriptor.push_str(&format!("char {} {} 0 8 12 9\n", code, (code - 32) * 8));
+        }
+        let font = FontDescriptor::parse(&descriptor).unwrap();
+        let lines: Vec<_> = (0..50)
+            .map(|i| format!("debug text line {}: pos 123.456 789.012 vel 3.5 -1.25", i))
+            .collect();
+
+        let frames = 1000;
+        let mut quads = 0;
+        let start = Instant::now();
+        for _ in 0..frames {
+            for (i, line) in lines.iter().enumerate() {
+                quads += font.layout(line, 20.0, i as f32 * 16.0, 1.0).len();
+            }
+        }
+        let elapsed = start.elapsed();
+        println!(
+            "{} frames of 50 lines: {:?} per frame, {} quads",
+            frames,
+            elapsed / frames,
+            quads
+        );
+    }
+}
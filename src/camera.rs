@@ -0,0 +1,654 @@
+//! Where viewports look - the gameplay camera and scripted camera tracks like the round intro.
+
+use thunderdome::Index;
+use vek::Clamp;
+
+use crate::{
+    game_state::{GameState, Input},
+    map::{Map, Vec2f},
+};
+
+/// Camera center in world coords when following an entity at `entity_pos`.
+///
+/// Doesn't put the camera so close to the edge that it would render area outside the map.
+/// Also properly handles maps smaller than the viewport. Note they can be smaller along X, Y or both.
+/// Example maps for testing: Joust (2), extra/OK Corral (2)
+pub fn camera_center(map_size: Vec2f, viewport_size: Vec2f, entity_pos: Vec2f) -> Vec2f {
+    let view_size = Vec2f::partial_min(viewport_size, map_size);
+    let camera_center_min = view_size / 2.0;
+    let camera_center_max = map_size - camera_center_min;
+    entity_pos.clamped(camera_center_min, camera_center_max)
+}
+
+/// Size of the area a viewport shows in world units - zooming out makes it bigger.
+pub fn world_viewport_size(viewport_size: Vec2f, zoom: f64) -> Vec2f {
+    viewport_size / zoom
+}
+
+/// Conversion between world coords and screen coords (pixels from the viewport's top left corner).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewTransform {
+    /// World coords of the top left corner of the visible part of the map.
+    pub camera_top_left: Vec2f,
+    /// Screen coords of the same corner, nonzero when the map is smaller than the (zoomed) viewport.
+    pub view_pos: Vec2f,
+    pub zoom: f64,
+}
+
+impl ViewTransform {
+    /// Camera looking at `center`, clamped the same way as during gameplay.
+    pub fn new(map_size: Vec2f, viewport_size: Vec2f, zoom: f64, center: Vec2f) -> Self {
+        let world_viewport = world_viewport_size(viewport_size, zoom);
+        let view_size = Vec2f::partial_min(world_viewport, map_size);
+        let center = camera_center(map_size, world_viewport, center);
+        Self {
+            camera_top_left: center - view_size / 2.0,
+            view_pos: (world_viewport - view_size) / 2.0 * zoom,
+            zoom,
+        }
+    }
+
+    pub fn world_to_screen(&self, world_pos: Vec2f) -> Vec2f {
+        (world_pos - self.camera_top_left) * self.zoom + self.view_pos
+    }
+
+    pub fn screen_to_world(&self, screen_pos: Vec2f) -> Vec2f {
+        (screen_pos - self.view_pos) / self.zoom + self.camera_top_left
+    }
+}
+
+/// What a local player's gameplay camera looks at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CameraMode {
+    /// The vehicle of this player - their own or someone else's when spectating.
+    FollowVehicle(Index),
+    /// The player's own guided missile.
+    FollowMissile,
+    /// Moved by the player's movement keys while they're dead. The center, already clamped.
+    Free(Vec2f),
+}
+
+/// Camera state of one local player.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayerCamera {
+    pub mode: CameraMode,
+    /// Movement keys used to move the free camera are kept from the vehicle until released
+    /// so it doesn't start driving when it respawns while they're held.
+    pub suppress_movement: bool,
+}
+
+impl PlayerCamera {
+    pub fn new(player_handle: Index) -> Self {
+        Self {
+            mode: CameraMode::FollowVehicle(player_handle),
+            suppress_movement: false,
+        }
+    }
+
+    /// Switch modes when the player dies or respawns.
+    ///
+    /// After death the camera becomes free, starting at the wreck.
+    /// Once the player has a living vehicle again, it follows it (or their missile).
+    pub fn update(
+        &mut self,
+        gs: &GameState,
+        map_size: Vec2f,
+        viewport_size: Vec2f,
+        player_handle: Index,
+    ) {
+        let player = &gs.players[player_handle];
+        let vehicle = player.vehicle.map(|handle| &gs.vehicles[handle]);
+        let alive = vehicle.map_or(false, |vehicle| !vehicle.destroyed());
+        self.mode = if alive {
+            if player.guided_missile.is_some() {
+                CameraMode::FollowMissile
+            } else {
+                CameraMode::FollowVehicle(player_handle)
+            }
+        } else {
+            match self.mode {
+                CameraMode::FollowVehicle(handle) if handle != player_handle => self.mode,
+                CameraMode::Free(center) => CameraMode::Free(center),
+                _ => {
+                    let wreck_pos = vehicle.map_or(map_size / 2.0, |vehicle| *vehicle.pos);
+                    CameraMode::Free(camera_center(map_size, viewport_size, wreck_pos))
+                }
+            }
+        };
+    }
+
+    /// Move the free camera by the movement keys and keep them from the vehicle.
+    /// Returns the input to send to the server.
+    pub fn process_input(
+        &mut self,
+        mut input: Input,
+        speed: f64,
+        dt: f64,
+        map_size: Vec2f,
+        viewport_size: Vec2f,
+    ) -> Input {
+        let moving = input.up
+            || input.down
+            || input.left
+            || input.right
+            || input.steer.is_some()
+            || input.throttle.is_some();
+        if let CameraMode::Free(center) = self.mode {
+            let mut dir = Vec2f::zero();
+            dir.x = f64::from(i8::from(input.right) - i8::from(input.left));
+            dir.y = f64::from(i8::from(input.down) - i8::from(input.up));
+            let dir = dir.try_normalized().unwrap_or_default();
+            let center = center + dir * speed * dt;
+            self.mode = CameraMode::Free(camera_center(map_size, viewport_size, center));
+            self.suppress_movement = moving;
+        } else if !moving {
+            self.suppress_movement = false;
+        }
+        if self.suppress_movement {
+            input.up = false;
+            input.down = false;
+            input.left = false;
+            input.right = false;
+            input.steer = None;
+            input.throttle = None;
+        }
+        input
+    }
+
+    /// Follow the next player with a living vehicle (in arena order, wrapping around).
+    /// Nothing changes if there's nobody else to follow.
+    pub fn spectate_next(&mut self, gs: &GameState, player_handle: Index) {
+        let living: Vec<Index> = gs
+            .players
+            .iter()
+            .filter(|&(handle, player)| {
+                handle != player_handle
+                    && player
+                        .vehicle
+                        .map_or(false, |vehicle| !gs.vehicles[vehicle].destroyed())
+            })
+            .map(|(handle, _)| handle)
+            .collect();
+        let next = match self.mode {
+            CameraMode::FollowVehicle(current) => living
+                .iter()
+                .position(|&handle| handle == current)
+                .map_or(0, |i| i + 1),
+            _ => 0,
+        };
+        if !living.is_empty() {
+            self.mode = CameraMode::FollowVehicle(living[next % living.len()]);
+        }
+    }
+
+    /// Who the player is watching if it's not themselves.
+    pub fn spectated(&self, player_handle: Index) -> Option<Index> {
+        match self.mode {
+            CameraMode::FollowVehicle(handle) if handle != player_handle => Some(handle),
+            _ => None,
+        }
+    }
+
+    /// Where the camera looks if it's not at the player's own vehicle or missile.
+    pub fn center_override(&self, gs: &GameState, player_handle: Index) -> Option<Vec2f> {
+        match self.mode {
+            CameraMode::Free(center) => Some(center),
+            CameraMode::FollowVehicle(handle) if handle != player_handle => {
+                let vehicle = gs.players.get(handle)?.vehicle?;
+                Some(*gs.vehicles[vehicle].pos)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    /// Seconds since the start of the track
+    pub time: f64,
+    pub center: Vec2f,
+}
+
+/// Camera path through keyframes, smoothly slowing down and speeding up at each.
+#[derive(Debug, Clone)]
+pub struct CameraTrack {
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl CameraTrack {
+    pub fn duration(&self) -> f64 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    pub fn end(&self) -> Vec2f {
+        self.keyframes.last().unwrap().center
+    }
+
+    /// Camera center at `time`, times after the end return the last keyframe.
+    pub fn center(&self, time: f64) -> Vec2f {
+        let next = self
+            .keyframes
+            .iter()
+            .position(|keyframe| keyframe.time > time);
+        match next {
+            None => self.end(),
+            Some(0) => self.keyframes[0].center,
+            Some(i) => {
+                let a = self.keyframes[i - 1];
+                let b = self.keyframes[i];
+                let t = (time - a.time) / (b.time - a.time);
+                let t = t * t * (3.0 - 2.0 * t); // smoothstep
+                Vec2f::lerp(a.center, b.center, t)
+            }
+        }
+    }
+}
+
+/// Camera track showing the map before the round starts, ending exactly
+/// where the gameplay camera starts when following a vehicle at `spawn_pos`.
+///
+/// Visits the bases if the map has any, otherwise sweeps along the map's longer axis
+/// starting from the end farther from the spawn.
+/// The camera moves at constant speed between keyframes (apart from easing)
+/// so short hops don't take as long as crossing the map.
+pub fn flyby_track(
+    map: &Map,
+    viewport_size: Vec2f,
+    spawn_pos: Vec2f,
+    duration: f64,
+) -> CameraTrack {
+    let map_size = map.maxs();
+    let mut points: Vec<Vec2f> = if map.bases().is_empty() {
+        let mid = map_size / 2.0;
+        let (a, b) = if map_size.x >= map_size.y {
+            (Vec2f::new(0.0, mid.y), Vec2f::new(map_size.x, mid.y))
+        } else {
+            (Vec2f::new(mid.x, 0.0), Vec2f::new(mid.x, map_size.y))
+        };
+        if a.distance(spawn_pos) >= b.distance(spawn_pos) {
+            vec![a, b]
+        } else {
+            vec![b, a]
+        }
+    } else {
+        let mut bases: Vec<_> = map
+            .bases()
+            .iter()
+            .map(|&index| map.tile_center(index))
+            .collect();
+        // Farthest first so the path ends near the spawn.
+        bases.sort_by(|a, b| {
+            let a = a.distance(spawn_pos);
+            let b = b.distance(spawn_pos);
+            b.partial_cmp(&a).unwrap()
+        });
+        bases
+    };
+    points.push(spawn_pos);
+
+    let mut centers: Vec<Vec2f> = points
+        .into_iter()
+        .map(|point| camera_center(map_size, viewport_size, point))
+        .collect();
+    // Points that end up in the same place after clamping would just make the camera pause.
+    centers.dedup();
+
+    let lengths: Vec<f64> = centers.windows(2).map(|w| w[0].distance(w[1])).collect();
+    let total: f64 = lengths.iter().sum();
+
+    let mut keyframes = vec![Keyframe {
+        time: 0.0,
+        center: centers[0],
+    }];
+    let mut traveled = 0.0;
+    for (&center, &length) in centers[1..].iter().zip(&lengths) {
+        traveled += length;
+        keyframes.push(Keyframe {
+            time: duration * traveled / total,
+            center,
+        });
+    }
+    if keyframes.len() == 1 {
+        // Everything fits on screen, just hold the view.
+        keyframes.push(Keyframe {
+            time: duration,
+            center: centers[0],
+        });
+    }
+    // Exactly, not just approximately because of float errors.
+    keyframes.last_mut().unwrap().time = duration;
+
+    CameraTrack { keyframes }
+}
+
+/// Round intro - each local player gets a flyby ending at their spawn.
+///
+/// Gamelogic doesn't run until all local players have watched or skipped their flyby
+/// (or `max_wait` passes) so the round starts for everyone at the same time.
+#[derive(Debug, Clone)]
+pub struct Intro {
+    /// Real time (not game time) when the intro started
+    pub start: f64,
+    pub max_wait: f64,
+    /// Local player, their track and whether they skipped it
+    pub tracks: Vec<(Index, CameraTrack, bool)>,
+}
+
+impl Intro {
+    pub fn new(start: f64, max_wait: f64, tracks: Vec<(Index, CameraTrack)>) -> Self {
+        Self {
+            start,
+            max_wait,
+            tracks: tracks
+                .into_iter()
+                .map(|(player_handle, track)| (player_handle, track, false))
+                .collect(),
+        }
+    }
+
+    /// Jump to the end of the player's track.
+    pub fn skip(&mut self, player_handle: Index) {
+        for (handle, _, skipped) in &mut self.tracks {
+            if *handle == player_handle {
+                *skipped = true;
+            }
+        }
+    }
+
+    /// Camera center for the player or `None` if the player has no track.
+    pub fn camera_center(&self, player_handle: Index, real_time: f64) -> Option<Vec2f> {
+        let (_, track, skipped) = self
+            .tracks
+            .iter()
+            .find(|(handle, _, _)| *handle == player_handle)?;
+        if *skipped {
+            Some(track.end())
+        } else {
+            Some(track.center(real_time - self.start))
+        }
+    }
+
+    pub fn is_finished(&self, real_time: f64) -> bool {
+        let elapsed = real_time - self.start;
+        elapsed >= self.max_wait
+            || self
+                .tracks
+                .iter()
+                .all(|(_, track, skipped)| *skipped || elapsed >= track.duration())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use thunderdome::Arena;
+
+    use crate::{
+        cvars::Cvars,
+        entities::{Player, Vehicle, VehicleType},
+        map::{self, Vec2u, TILE_SIZE},
+    };
+
+    fn load(path: &str) -> Map {
+        map::tests::load_test_map(&fs::read_to_string(path).unwrap())
+    }
+
+    fn assert_visits(track: &CameraTrack, center: Vec2f) {
+        assert!(
+            track
+                .keyframes
+                .iter()
+                .any(|keyframe| keyframe.center == center),
+            "{:?} not in {:?}",
+            center,
+            track.keyframes,
+        );
+    }
+
+    #[test]
+    fn test_flyby_bases() {
+        // Base is surface 15 - each tile value is surface_index * 4 + rotation.
+        let mut rows = vec![vec!["0"; 40]; 30];
+        rows[5][5] = "60";
+        rows[25][35] = "60";
+        let text = rows
+            .iter()
+            .map(|row| row.join(" "))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let map = map::tests::load_test_map(&text);
+        assert_eq!(map.bases().len(), 2);
+
+        let viewport_size = Vec2f::new(800.0, 600.0);
+        let spawn_pos = map.tile_center(Vec2u::new(30, 20));
+        let track = flyby_track(&map, viewport_size, spawn_pos, 4.0);
+
+        for &base in map.bases() {
+            let center = camera_center(map.maxs(), viewport_size, map.tile_center(base));
+            assert_visits(&track, center);
+        }
+        // The base near the spawn is visited last.
+        let near_base = map.tile_center(Vec2u::new(35, 25));
+        let near = camera_center(map.maxs(), viewport_size, near_base);
+        assert_eq!(track.keyframes[track.keyframes.len() - 2].center, near);
+    }
+
+    #[test]
+    fn test_flyby_sweep() {
+        let map = load("maps/Atrium.map");
+        assert!(map.bases().is_empty());
+        let viewport_size = Vec2f::new(640.0, 480.0);
+        let spawn_pos = map.tile_center(map.spawns()[0]);
+        let track = flyby_track(&map, viewport_size, spawn_pos, 4.0);
+
+        // Both ends of the longer axis
+        let map_size = map.maxs();
+        let mid = map_size / 2.0;
+        let (a, b) = if map_size.x >= map_size.y {
+            (Vec2f::new(0.0, mid.y), Vec2f::new(map_size.x, mid.y))
+        } else {
+            (Vec2f::new(mid.x, 0.0), Vec2f::new(mid.x, map_size.y))
+        };
+        assert_visits(&track, camera_center(map_size, viewport_size, a));
+        assert_visits(&track, camera_center(map_size, viewport_size, b));
+
+        // Times increase and the track stays on the map.
+        for pair in track.keyframes.windows(2) {
+            assert!(pair[0].time < pair[1].time);
+        }
+        for i in 0..=40 {
+            let center = track.center(i as f64 * 0.1);
+            assert!(center.x >= 0.0 && center.y >= 0.0);
+            assert!(center.x <= map_size.x && center.y <= map_size.y);
+        }
+    }
+
+    #[test]
+    fn test_flyby_ends_at_gameplay_camera() {
+        let map = load("maps/Atrium.map");
+        // Viewports bigger and smaller than the map
+        for viewport_size in [Vec2f::new(640.0, 480.0), Vec2f::new(5000.0, 300.0)] {
+            for &spawn in map.spawns() {
+                let spawn_pos = map.tile_center(spawn);
+                let track = flyby_track(&map, viewport_size, spawn_pos, 3.5);
+                let gameplay = camera_center(map.maxs(), viewport_size, spawn_pos);
+                assert_eq!(track.end(), gameplay);
+                assert_eq!(track.center(3.5), gameplay);
+                assert_eq!(track.center(100.0), gameplay);
+                assert_eq!(track.duration(), 3.5);
+            }
+        }
+
+        // Small map which fits on screen - no movement needed.
+        let text = ["0 0 0", "0 36 0", "0 0 0"].join("\n");
+        let map = map::tests::load_test_map(&text);
+        let spawn_pos = Vec2f::broadcast(1.5 * TILE_SIZE);
+        let track = flyby_track(&map, Vec2f::new(640.0, 480.0), spawn_pos, 3.0);
+        assert_eq!(track.duration(), 3.0);
+        assert_eq!(track.center(1.0), track.end());
+    }
+
+    #[test]
+    fn test_view_transform() {
+        let map_size = Vec2f::new(2000.0, 1500.0);
+        let viewport_size = Vec2f::new(800.0, 600.0);
+        let center = Vec2f::new(1000.0, 700.0);
+        for zoom in [0.5, 0.8, 1.0, 1.25, 2.0, 3.0] {
+            let transform = ViewTransform::new(map_size, viewport_size, zoom, center);
+            for world_pos in [center, Vec2f::new(0.0, 0.0), Vec2f::new(1234.5, 67.25)] {
+                let screen_pos = transform.world_to_screen(world_pos);
+                let back = transform.screen_to_world(screen_pos);
+                assert!(back.distance(world_pos) < 1e-9, "{} {}", zoom, world_pos);
+            }
+            let scr_center = transform.world_to_screen(center);
+            if zoom >= 1.0 {
+                // Nothing to clamp, the center is in the middle of the screen.
+                assert!(scr_center.distance(viewport_size / 2.0) < 1e-9);
+            }
+            // Distances scale with zoom.
+            let step = transform.world_to_screen(center + Vec2f::new(10.0, 0.0)) - scr_center;
+            assert!((step.x - 10.0 * zoom).abs() < 1e-9);
+        }
+
+        // Zooming out far enough makes the map smaller than the viewport - it's centered.
+        let transform = ViewTransform::new(map_size, viewport_size, 0.25, center);
+        assert_eq!(transform.camera_top_left, Vec2f::zero());
+        let corner = transform.world_to_screen(map_size);
+        assert!((transform.view_pos + corner - viewport_size).magnitude() < 1e-9);
+        assert!(transform.view_pos.y > 0.0);
+    }
+
+    #[test]
+    fn test_spectating() {
+        let cvars = Cvars::default();
+        let mut gs = GameState::new(0);
+        let mut players = Vec::new();
+        for (i, &x) in [100.0, 500.0, 900.0].iter().enumerate() {
+            let player = gs.players.insert(Player::new(format!("Player {}", i)));
+            let pos = Vec2f::new(x, 300.0);
+            let vehicle = Vehicle::new(&cvars, pos, 0.0, VehicleType::Tank, 0, 0.0, player);
+            gs.players[player].vehicle = Some(gs.vehicles.insert(vehicle));
+            players.push(player);
+        }
+        let local = players[0];
+        let local_vehicle = gs.players[local].vehicle.unwrap();
+        let map_size = Vec2f::new(1000.0, 600.0);
+        let viewport_size = Vec2f::new(400.0, 300.0);
+        let mut camera = PlayerCamera::new(local);
+        camera.update(&gs, map_size, viewport_size, local);
+        assert_eq!(camera.mode, CameraMode::FollowVehicle(local));
+        assert_eq!(camera.center_override(&gs, local), None);
+
+        // Dying frees the camera at the wreck, clamped like the gameplay camera.
+        gs.vehicles[local_vehicle].hp = 0;
+        camera.update(&gs, map_size, viewport_size, local);
+        let wreck = camera_center(map_size, viewport_size, Vec2f::new(100.0, 300.0));
+        assert_eq!(camera.mode, CameraMode::Free(wreck));
+        assert_eq!(wreck, Vec2f::new(200.0, 300.0));
+
+        // Movement keys pan it instead of going to the vehicle and it stays on the map.
+        let left = Input {
+            left: true,
+            fire: true,
+            ..Input::new()
+        };
+        let sent = camera.process_input(left, 100.0, 1.0, map_size, viewport_size);
+        assert_eq!(
+            sent,
+            Input {
+                fire: true,
+                ..Input::new()
+            }
+        );
+        assert_eq!(camera.mode, CameraMode::Free(wreck));
+        let down = Input {
+            down: true,
+            ..Input::new()
+        };
+        camera.process_input(down, 100.0, 0.5, map_size, viewport_size);
+        assert_eq!(camera.mode, CameraMode::Free(Vec2f::new(200.0, 350.0)));
+
+        // Following others cycles through living vehicles only.
+        let dead_other = gs.players[players[2]].vehicle.unwrap();
+        camera.spectate_next(&gs, local);
+        assert_eq!(camera.spectated(local), Some(players[1]));
+        camera.spectate_next(&gs, local);
+        assert_eq!(camera.spectated(local), Some(players[2]));
+        camera.spectate_next(&gs, local);
+        assert_eq!(camera.spectated(local), Some(players[1]));
+        gs.vehicles[dead_other].hp = 0;
+        camera.spectate_next(&gs, local);
+        assert_eq!(camera.spectated(local), Some(players[1]));
+        assert_eq!(
+            camera.center_override(&gs, local),
+            Some(Vec2f::new(500.0, 300.0))
+        );
+        camera.update(&gs, map_size, viewport_size, local);
+        assert_eq!(camera.spectated(local), Some(players[1]));
+
+        // Respawning with the key still held - back to the vehicle which doesn't move until it's released.
+        camera.mode = CameraMode::Free(wreck);
+        camera.process_input(left, 100.0, 0.1, map_size, viewport_size);
+        gs.vehicles[local_vehicle].hp = 1;
+        camera.update(&gs, map_size, viewport_size, local);
+        assert_eq!(camera.mode, CameraMode::FollowVehicle(local));
+        let sent = camera.process_input(left, 100.0, 0.1, map_size, viewport_size);
+        assert!(!sent.left);
+        camera.process_input(Input::new(), 100.0, 0.1, map_size, viewport_size);
+        let sent = camera.process_input(left, 100.0, 0.1, map_size, viewport_size);
+        assert!(sent.left);
+
+        // Maps smaller than the viewport keep the free camera centered.
+        gs.vehicles[local_vehicle].hp = 0;
+        let small_map = Vec2f::new(300.0, 200.0);
+        let mut camera = PlayerCamera::new(local);
+        camera.update(&gs, small_map, viewport_size, local);
+        camera.process_input(left, 1000.0, 1.0, small_map, viewport_size);
+        assert_eq!(camera.mode, CameraMode::Free(small_map / 2.0));
+    }
+
+    #[test]
+    fn test_intro_skip() {
+        let map = load("maps/Atrium.map");
+        let viewport_size = Vec2f::new(640.0, 480.0);
+        let mut players = Arena::new();
+        let p1 = players.insert(());
+        let p2 = players.insert(());
+        let track1 = flyby_track(&map, viewport_size, map.tile_center(map.spawns()[0]), 4.0);
+        let track2 = flyby_track(&map, viewport_size, map.tile_center(map.spawns()[1]), 4.0);
+        let end1 = track1.end();
+        let end2 = track2.end();
+
+        for skip_time in [0.0, 1.3, 3.99] {
+            let start = 10.0;
+            let mut intro = Intro::new(
+                start,
+                10.0,
+                vec![(p1, track1.clone()), (p2, track2.clone())],
+            );
+            let now = start + skip_time;
+            assert!(!intro.is_finished(now));
+
+            // Both players have to skip.
+            intro.skip(p1);
+            assert_eq!(intro.camera_center(p1, now), Some(end1));
+            assert!(!intro.is_finished(now));
+            intro.skip(p2);
+            assert_eq!(intro.camera_center(p2, now), Some(end2));
+            assert!(intro.is_finished(now));
+        }
+
+        // Without skipping it finishes when the tracks end.
+        let intro = Intro::new(0.0, 10.0, vec![(p1, track1.clone()), (p2, track2)]);
+        assert!(!intro.is_finished(3.9));
+        assert!(intro.is_finished(4.0));
+        assert_eq!(intro.camera_center(p1, 4.0), Some(end1));
+
+        // A long track is cut short by max wait.
+        let intro = Intro::new(0.0, 2.0, vec![(p1, track1)]);
+        assert!(intro.is_finished(2.0));
+    }
+}
@@ -0,0 +1,793 @@
+//! Collision dumps for bug reports.
+//!
+//! `export` writes everything collision-related in a region of the map as text:
+//! tiles, vehicle hitboxes, projectile sweeps and recent collision decisions
+//! (when `d_collision_trace` is on). `import` turns such a dump back into a `Scene`
+//! which can create a `GameState` for a unit test so a reported bug becomes a regression test.
+//!
+//! There are no destructible tiles, smoke, props or mines yet so they're not part of the dump.
+//!
+//! The format is one item per line, a keyword followed by space separated values.
+//! Floats are written so they parse back to exactly the same value.
+//! Handles are `thunderdome::Index::to_bits` of the original entities.
+//! - `region <mins.x> <mins.y> <maxs.x> <maxs.y>`
+//! - `map <width> <height>` in tiles
+//! - `frame <frame_num> <dt>`
+//! - `surface <index> <kind> <friction> <speed> <name>`
+//! - `tile <col> <row> <surface index> <angle>`, tiles not in the dump are plain ground
+//! - `vehicle <handle> <owner> <veh_type> <pos.x> <pos.y> <angle> <vel.x> <vel.y> <turn_rate>
+//!   <mins.x> <mins.y> <maxs.x> <maxs.y>`
+//! - `projectile <handle> <owner> <weapon> <start.x> <start.y> <end.x> <end.y> <vel.x> <vel.y>`
+//! - `trace <frame_num> <vehicle|projectile> <handle> <from.x> <from.y> <to.x> <to.y>
+//!   <from_angle> <to_angle> <corner or -> <contact.x> <contact.y> <resolution>`
+
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt::{self, Display, Formatter, Write},
+    str::FromStr,
+};
+
+use thunderdome::Index;
+
+use crate::{
+    cvars::Cvars,
+    entities::{Hitbox, Player, Projectile, Vehicle, VehicleType, Weapon},
+    game_state::GameState,
+    map::{self, Kind, Map, Surface, Vec2f, Vec2u, VecExt, TILE_SIZE},
+};
+
+/// One collision decision made by the movement or projectile systems.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CollisionEntry {
+    pub frame_num: u64,
+    pub entity: TraceEntity,
+    /// Position before the attempted move.
+    pub from: Vec2f,
+    /// Where the entity tried to go.
+    pub to: Vec2f,
+    pub from_angle: f64,
+    pub to_angle: f64,
+    /// Index of the first blocked hitbox corner (in `Hitbox::corners` order), `None` for projectiles.
+    pub corner: Option<usize>,
+    /// The blocked corner or where the projectile hit the wall.
+    pub contact: Vec2f,
+    pub resolution: Resolution,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceEntity {
+    Vehicle(Index),
+    Projectile(Index),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// The vehicle didn't turn, its turn rate got zeroed (or reversed and halved with `g_vehicle_wall_bounce`).
+    TurnBounce,
+    /// The vehicle stopped at the wall (an inside corner or head-on), see `MoveSlide` for its velocity.
+    MoveBounce,
+    /// The vehicle slid along the wall, the part of its velocity going into it got zeroed
+    /// (or reversed and halved with `g_vehicle_wall_bounce`).
+    MoveSlide,
+    /// The projectile hit the wall.
+    Impact,
+    /// The projectile bounced off the wall.
+    Ricochet,
+}
+
+impl Resolution {
+    fn name(self) -> &'static str {
+        match self {
+            Resolution::TurnBounce => "turn_bounce",
+            Resolution::MoveBounce => "move_bounce",
+            Resolution::MoveSlide => "move_slide",
+            Resolution::Impact => "impact",
+            Resolution::Ricochet => "ricochet",
+        }
+    }
+}
+
+/// Add an entry to the trace if it's enabled, dropping the oldest if it's full.
+pub fn trace(cvars: &Cvars, collision_trace: &mut VecDeque<CollisionEntry>, entry: CollisionEntry) {
+    if !cvars.d_collision_trace {
+        return;
+    }
+    collision_trace.push_back(entry);
+    while collision_trace.len() > cvars.d_collision_trace_len {
+        collision_trace.pop_front();
+    }
+}
+
+/// Dump everything collision-related that overlaps the rectangle between `mins` and `maxs`.
+pub fn export(gs: &GameState, map: &Map, mins: Vec2f, maxs: Vec2f) -> String {
+    let mut out = String::new();
+    let overlaps = |a: Vec2f, b: Vec2f| {
+        let (lo, hi) = (Vec2f::partial_min(a, b), Vec2f::partial_max(a, b));
+        lo.x <= maxs.x && hi.x >= mins.x && lo.y <= maxs.y && hi.y >= mins.y
+    };
+
+    writeln!(out, "region {} {} {} {}", mins.x, mins.y, maxs.x, maxs.y).unwrap();
+    writeln!(out, "map {} {}", map.width(), map.height()).unwrap();
+    writeln!(out, "frame {} {}", gs.frame_num, gs.dt).unwrap();
+
+    let mut tiles = Vec::new();
+    let mut surfaces = Vec::new();
+    for r in 0..map.height() {
+        for c in 0..map.width() {
+            let tile_mins = Vec2u::new(c, r).as_() * TILE_SIZE;
+            if !overlaps(tile_mins, tile_mins + TILE_SIZE) {
+                continue;
+            }
+            let tile = map.col_row(c, r);
+            if !surfaces.contains(&tile.surface_index) {
+                surfaces.push(tile.surface_index);
+                let surface = map.surface_of(tile);
+                writeln!(
+                    out,
+                    "surface {} {} {} {} {}",
+                    tile.surface_index,
+                    surface.kind as u8,
+                    surface.friction,
+                    surface.speed,
+                    surface.name
+                )
+                .unwrap();
+            }
+            tiles.push((c, r, tile));
+        }
+    }
+    for (c, r, tile) in tiles {
+        writeln!(
+            out,
+            "tile {} {} {} {}",
+            c, r, tile.surface_index, tile.angle
+        )
+        .unwrap();
+    }
+
+    for (handle, vehicle) in gs.vehicles.iter() {
+        if !vehicle.obb.aabb_overlaps(mins, maxs) {
+            continue;
+        }
+        writeln!(
+            out,
+            "vehicle {} {} {} {} {} {} {} {} {} {} {} {} {}",
+            handle.to_bits(),
+            vehicle.owner.to_bits(),
+            vehicle.veh_type as u8,
+            vehicle.pos.x,
+            vehicle.pos.y,
+            *vehicle.angle,
+            vehicle.vel.x,
+            vehicle.vel.y,
+            vehicle.turn_rate,
+            vehicle.hitbox.mins.x,
+            vehicle.hitbox.mins.y,
+            vehicle.hitbox.maxs.x,
+            vehicle.hitbox.maxs.y,
+        )
+        .unwrap();
+    }
+
+    for (handle, projectile) in gs.projectiles.iter() {
+        // The segment swept during the last gamelogic frame.
+        let start = projectile.pos - projectile.vel * gs.dt;
+        let end = projectile.pos;
+        if !overlaps(start, end) {
+            continue;
+        }
+        writeln!(
+            out,
+            "projectile {} {} {} {} {} {} {} {} {}",
+            handle.to_bits(),
+            projectile.owner.to_bits(),
+            projectile.weapon as u8,
+            start.x,
+            start.y,
+            end.x,
+            end.y,
+            projectile.vel.x,
+            projectile.vel.y,
+        )
+        .unwrap();
+    }
+
+    for entry in &gs.collision_trace {
+        if !overlaps(entry.contact, entry.contact) {
+            continue;
+        }
+        let (kind, handle) = match entry.entity {
+            TraceEntity::Vehicle(handle) => ("vehicle", handle),
+            TraceEntity::Projectile(handle) => ("projectile", handle),
+        };
+        let corner = match entry.corner {
+            Some(corner) => corner.to_string(),
+            None => "-".to_owned(),
+        };
+        writeln!(
+            out,
+            "trace {} {} {} {} {} {} {} {} {} {} {} {} {}",
+            entry.frame_num,
+            kind,
+            handle.to_bits(),
+            entry.from.x,
+            entry.from.y,
+            entry.to.x,
+            entry.to.y,
+            entry.from_angle,
+            entry.to_angle,
+            corner,
+            entry.contact.x,
+            entry.contact.y,
+            entry.resolution.name(),
+        )
+        .unwrap();
+    }
+
+    out
+}
+
+/// A reconstructed collision dump.
+#[derive(Debug, Clone)]
+pub struct Scene {
+    pub mins: Vec2f,
+    pub maxs: Vec2f,
+    pub frame_num: u64,
+    pub dt: f64,
+    /// Same size as the original, tiles outside the region are plain ground.
+    pub map: Map,
+    pub vehicles: Vec<VehicleBody>,
+    pub projectiles: Vec<ProjectileBody>,
+    pub trace: Vec<CollisionEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct VehicleBody {
+    pub handle: Index,
+    pub owner: Index,
+    pub veh_type: VehicleType,
+    pub pos: Vec2f,
+    pub angle: f64,
+    pub vel: Vec2f,
+    pub turn_rate: f64,
+    pub hitbox: Hitbox,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProjectileBody {
+    pub handle: Index,
+    pub owner: Index,
+    pub weapon: Weapon,
+    pub start: Vec2f,
+    pub end: Vec2f,
+    pub vel: Vec2f,
+}
+
+impl Scene {
+    /// A game state with the dumped bodies under their original handles, ready to run systems on.
+    ///
+    /// Each owner gets a player with no input, set inputs before running movement.
+    /// Vehicles keep their dumped hitboxes even if they differ from `cvars`.
+    pub fn game_state(&self, cvars: &Cvars) -> GameState {
+        let mut gs = GameState::new(cvars.d_seed);
+        gs.frame_num = self.frame_num;
+        gs.dt = self.dt;
+        gs.collision_trace = self.trace.iter().copied().collect();
+
+        let owners = self
+            .vehicles
+            .iter()
+            .map(|body| body.owner)
+            .chain(self.projectiles.iter().map(|body| body.owner));
+        for owner in owners {
+            if !gs.players.contains(owner) {
+                gs.players
+                    .insert_at(owner, Player::new("Player".to_owned()));
+            }
+        }
+
+        for body in &self.vehicles {
+            let mut vehicle = Vehicle::new(
+                cvars,
+                body.pos,
+                body.angle,
+                body.veh_type,
+                self.frame_num,
+                0.0,
+                body.owner,
+            );
+            vehicle.vel = body.vel;
+            vehicle.turn_rate = body.turn_rate;
+            vehicle.hitbox = body.hitbox;
+            vehicle.update_obb();
+            gs.vehicles.insert_at(body.handle, vehicle);
+            gs.players[body.owner].vehicle = Some(body.handle);
+        }
+
+        for body in &self.projectiles {
+            let projectile = Projectile {
+                weapon: body.weapon,
+                pos: body.end,
+                vel: body.vel,
+                angle: body.vel.to_angle(),
+                turn_rate: 0.0,
+                explode_time: f64::MAX,
+                spawn_time: 0.0,
+                owner: body.owner,
+                lock: None,
+                retarget_time: 0.0,
+                altitude: None,
+            };
+            gs.projectiles.insert_at(body.handle, projectile);
+        }
+
+        gs
+    }
+}
+
+/// Error in a collision dump, line is 1-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl Display for DumpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.line, self.msg)
+    }
+}
+
+impl Error for DumpError {}
+
+/// Parse a dump created by `export`.
+pub fn import(text: &str) -> Result<Scene, DumpError> {
+    let mut region = None;
+    let mut size = None;
+    let mut frame = (0, 0.0);
+    let mut surfaces = Vec::new();
+    let mut tiles = Vec::new();
+    let mut vehicles = Vec::new();
+    let mut projectiles = Vec::new();
+    let mut trace = Vec::new();
+
+    for (line_index, line) in text.lines().enumerate() {
+        let line_num = line_index + 1;
+        let err = |msg: String| DumpError {
+            line: line_num,
+            msg,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut fields = Fields {
+            parts: line.split(' '),
+            line_num,
+        };
+        match fields.next_str()? {
+            "region" => region = Some((fields.vec2()?, fields.vec2()?)),
+            "map" => size = Some(Vec2u::new(fields.next()?, fields.next()?)),
+            "frame" => frame = (fields.next()?, fields.next()?),
+            "surface" => {
+                let index: usize = fields.next()?;
+                let kind_num: u8 = fields.next()?;
+                let kind =
+                    Kind::n(kind_num).ok_or_else(|| err(format!("bad kind {}", kind_num)))?;
+                let friction = fields.next()?;
+                let speed = fields.next()?;
+                // The name can contain spaces.
+                let name = fields.parts.collect::<Vec<_>>().join(" ");
+                let surface = Surface {
+                    name,
+                    kind,
+                    friction,
+                    speed,
+                    destructible: None,
+                };
+                surfaces.push((index, surface));
+            }
+            "tile" => {
+                let c: usize = fields.next()?;
+                let r: usize = fields.next()?;
+                let surface_index: usize = fields.next()?;
+                let angle: f64 = fields.next()?;
+                tiles.push((c, r, surface_index, angle));
+            }
+            "vehicle" => {
+                let handle = fields.handle()?;
+                let owner = fields.handle()?;
+                let type_num: u8 = fields.next()?;
+                let veh_type = VehicleType::n(type_num)
+                    .ok_or_else(|| err(format!("bad vehicle type {}", type_num)))?;
+                vehicles.push(VehicleBody {
+                    handle,
+                    owner,
+                    veh_type,
+                    pos: fields.vec2()?,
+                    angle: fields.next()?,
+                    vel: fields.vec2()?,
+                    turn_rate: fields.next()?,
+                    hitbox: Hitbox {
+                        mins: fields.vec2()?,
+                        maxs: fields.vec2()?,
+                    },
+                });
+            }
+            "projectile" => {
+                let handle = fields.handle()?;
+                let owner = fields.handle()?;
+                let weapon_num: u8 = fields.next()?;
+                let weapon = Weapon::n(weapon_num)
+                    .ok_or_else(|| err(format!("bad weapon {}", weapon_num)))?;
+                projectiles.push(ProjectileBody {
+                    handle,
+                    owner,
+                    weapon,
+                    start: fields.vec2()?,
+                    end: fields.vec2()?,
+                    vel: fields.vec2()?,
+                });
+            }
+            "trace" => {
+                let frame_num = fields.next()?;
+                let entity = match fields.next_str()? {
+                    "vehicle" => TraceEntity::Vehicle(fields.handle()?),
+                    "projectile" => TraceEntity::Projectile(fields.handle()?),
+                    other => return Err(err(format!("bad entity \"{}\"", other))),
+                };
+                let from = fields.vec2()?;
+                let to = fields.vec2()?;
+                let from_angle = fields.next()?;
+                let to_angle = fields.next()?;
+                let corner = match fields.next_str()? {
+                    "-" => None,
+                    corner => Some(
+                        corner
+                            .parse()
+                            .map_err(|_| err(format!("bad corner \"{}\"", corner)))?,
+                    ),
+                };
+                let contact = fields.vec2()?;
+                let resolution = match fields.next_str()? {
+                    "turn_bounce" => Resolution::TurnBounce,
+                    "move_bounce" => Resolution::MoveBounce,
+                    "move_slide" => Resolution::MoveSlide,
+                    "impact" => Resolution::Impact,
+                    "ricochet" => Resolution::Ricochet,
+                    other => return Err(err(format!("bad resolution \"{}\"", other))),
+                };
+                trace.push(CollisionEntry {
+                    frame_num,
+                    entity,
+                    from,
+                    to,
+                    from_angle,
+                    to_angle,
+                    corner,
+                    contact,
+                    resolution,
+                });
+            }
+            other => return Err(err(format!("unknown item \"{}\"", other))),
+        }
+    }
+
+    let missing = |what: &str| DumpError {
+        line: 1,
+        msg: format!("missing {}", what),
+    };
+    let (mins, maxs) = region.ok_or_else(|| missing("region"))?;
+    let size = size.ok_or_else(|| missing("map"))?;
+    let map = build_map(size, surfaces, &tiles)?;
+
+    Ok(Scene {
+        mins,
+        maxs,
+        frame_num: frame.0,
+        dt: frame.1,
+        map,
+        vehicles,
+        projectiles,
+        trace,
+    })
+}
+
+/// Create a map of the given size from the dumped tiles.
+///
+/// Goes through the map text format so the result is exactly what `map::load_map` would produce.
+/// Dumped surfaces keep their indices, the gaps and tiles outside the dump are plain ground.
+fn build_map(
+    size: Vec2u,
+    surfaces: Vec<(usize, Surface)>,
+    tiles: &[(usize, usize, usize, f64)],
+) -> Result<Map, DumpError> {
+    let err = |msg: String| DumpError { line: 1, msg };
+    let ground = Surface {
+        name: "ground".to_owned(),
+        kind: Kind::Normal,
+        friction: 1.0,
+        speed: 1.0,
+        destructible: None,
+    };
+    let cnt = surfaces
+        .iter()
+        .map(|(index, _)| index + 1)
+        .max()
+        .unwrap_or(0);
+    let mut map_surfaces = vec![ground; cnt + 1];
+    let mut known = vec![false; cnt];
+    for (index, surface) in surfaces {
+        map_surfaces[index] = surface;
+        known[index] = true;
+    }
+
+    let mut values = vec![vec![cnt * 4; size.x]; size.y];
+    for &(c, r, surface_index, angle) in tiles {
+        if surface_index >= cnt || !known[surface_index] {
+            return Err(err(format!("tile uses unknown surface {}", surface_index)));
+        }
+        if c >= size.x || r >= size.y {
+            return Err(err(format!("tile {} {} outside the map", c, r)));
+        }
+        // Inverse of the rotation in `load_map`.
+        let rotation = (angle / (-std::f64::consts::PI / 2.0)).round() as usize % 4;
+        values[r][c] = surface_index * 4 + rotation;
+    }
+
+    let text = values
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    map::load_map(&text, map_surfaces).map_err(|e| DumpError {
+        line: 1,
+        msg: format!("invalid map: {}", e),
+    })
+}
+
+struct Fields<'a, I: Iterator<Item = &'a str>> {
+    parts: I,
+    line_num: usize,
+}
+
+impl<'a, I: Iterator<Item = &'a str>> Fields<'a, I> {
+    fn next_str(&mut self) -> Result<&'a str, DumpError> {
+        self.parts.next().ok_or(DumpError {
+            line: self.line_num,
+            msg: "not enough values".to_owned(),
+        })
+    }
+
+    fn next<T: FromStr>(&mut self) -> Result<T, DumpError> {
+        let s = self.next_str()?;
+        s.parse().map_err(|_| DumpError {
+            line: self.line_num,
+            msg: format!("invalid value \"{}\"", s),
+        })
+    }
+
+    fn vec2(&mut self) -> Result<Vec2f, DumpError> {
+        Ok(Vec2f::new(self.next()?, self.next()?))
+    }
+
+    fn handle(&mut self) -> Result<Index, DumpError> {
+        let bits = self.next()?;
+        Index::from_bits(bits).ok_or(DumpError {
+            line: self.line_num,
+            msg: format!("invalid handle {}", bits),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{game_state::Input, systems};
+
+    /// Spawn in the top left, a wall column in the middle, water left of it.
+    fn test_map() -> Map {
+        let text = [
+            "0 0 0 0 0 0",
+            "0 36 0 16 0 0",
+            "0 0 44 16 0 0",
+            "0 0 0 0 0 0",
+        ]
+        .join("\n");
+        map::tests::load_test_map(&text)
+    }
+
+    /// A tank facing the wall and another one far away.
+    fn setup(cvars: &Cvars, map: &Map) -> (GameState, Index, Index) {
+        let mut gs = GameState::new(0);
+        let mut vehicles = Vec::new();
+        for pos in [map.tile_center(map.spawns()[0]), Vec2f::new(352.0, 224.0)] {
+            let player_handle = gs.players.insert(Player::new("Player".to_owned()));
+            let vehicle = Vehicle::new(cvars, pos, 0.0, VehicleType::Tank, 0, 0.0, player_handle);
+            let vehicle_handle = gs.vehicles.insert(vehicle);
+            gs.players[player_handle].vehicle = Some(vehicle_handle);
+            vehicles.push(vehicle_handle);
+        }
+        (gs, vehicles[0], vehicles[1])
+    }
+
+    fn drive_into_wall(cvars: &Cvars, gs: &mut GameState, map: &Map, vehicle_handle: Index) {
+        let owner = gs.vehicles[vehicle_handle].owner;
+        gs.players[owner].input = Input::new_up();
+        gs.dt = 1.0 / 60.0;
+        for _ in 0..60 {
+            gs.frame_num += 1;
+            systems::vehicle_movement(cvars, gs, map);
+        }
+    }
+
+    #[test]
+    fn test_trace_wall_impact() {
+        let cvars = Cvars {
+            d_collision_trace: true,
+            ..Cvars::default()
+        };
+        let map = test_map();
+        let (mut gs, vehicle_handle, _) = setup(&cvars, &map);
+        drive_into_wall(&cvars, &mut gs, &map, vehicle_handle);
+
+        let entry = gs.collision_trace[0];
+        assert_eq!(entry.entity, TraceEntity::Vehicle(vehicle_handle));
+        assert_eq!(entry.resolution, Resolution::MoveBounce);
+        assert!(entry.from.x < entry.to.x);
+        assert_eq!(entry.from.y, entry.to.y);
+        assert_eq!(entry.from_angle, entry.to_angle);
+        // One of the front corners
+        let corner = entry.corner.unwrap();
+        assert!(corner == 1 || corner == 2);
+        assert!(map.is_wall(entry.contact));
+        assert!(entry.contact.x >= 192.0);
+        // The vehicle stayed where it was.
+        assert!(*gs.vehicles[vehicle_handle].pos != entry.to);
+
+        // Bouncing against the wall keeps adding entries but only the last few are kept.
+        let cvars = Cvars {
+            d_collision_trace_len: 2,
+            ..cvars
+        };
+        drive_into_wall(&cvars, &mut gs, &map, vehicle_handle);
+        assert_eq!(gs.collision_trace.len(), 2);
+        assert!(gs.collision_trace[0].frame_num < gs.collision_trace[1].frame_num);
+
+        let cvars = Cvars::default();
+        let (mut gs, vehicle_handle, _) = setup(&cvars, &map);
+        drive_into_wall(&cvars, &mut gs, &map, vehicle_handle);
+        assert!(gs.collision_trace.is_empty());
+    }
+
+    #[test]
+    fn test_export_import() {
+        let cvars = Cvars {
+            d_collision_trace: true,
+            ..Cvars::default()
+        };
+        let map = test_map();
+        let (mut gs, vehicle_handle, _) = setup(&cvars, &map);
+        drive_into_wall(&cvars, &mut gs, &map, vehicle_handle);
+        let projectile = Projectile {
+            weapon: Weapon::Rockets,
+            pos: Vec2f::new(100.0, 180.0),
+            vel: Vec2f::new(600.0, -50.0),
+            angle: 0.0,
+            turn_rate: 0.0,
+            explode_time: f64::MAX,
+            spawn_time: 0.0,
+            owner: gs.vehicles[vehicle_handle].owner,
+            lock: None,
+            retarget_time: 0.0,
+            altitude: None,
+        };
+        gs.projectiles.insert(projectile);
+
+        let mins = Vec2f::new(10.0, 10.0);
+        let maxs = Vec2f::new(300.0, 200.0);
+        let dump = export(&gs, &map, mins, maxs);
+        let scene = import(&dump).unwrap();
+        assert_eq!(scene.vehicles.len(), 1);
+        assert_eq!(scene.projectiles.len(), 1);
+        assert!(!scene.trace.is_empty());
+
+        // Exporting the reconstructed scene gives the same dump.
+        let gs2 = scene.game_state(&cvars);
+        assert_eq!(export(&gs2, &scene.map, mins, maxs), dump);
+
+        // Collision queries give the same results.
+        let vehicle = &gs.vehicles[vehicle_handle];
+        let vehicle2 = &gs2.vehicles[vehicle_handle];
+        assert_eq!(vehicle.obb, vehicle2.obb);
+        for x in (10..300).step_by(7) {
+            for y in (10..200).step_by(7) {
+                let pos = Vec2f::new(x as f64, y as f64);
+                assert_eq!(map.is_wall(pos), scene.map.is_wall(pos));
+                assert_eq!(
+                    map.surface_at_pos(pos).kind,
+                    scene.map.surface_at_pos(pos).kind
+                );
+                assert_eq!(
+                    map.is_wall_trace(*vehicle.pos, pos),
+                    scene.map.is_wall_trace(*vehicle2.pos, pos)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_region_filter() {
+        let cvars = Cvars::default();
+        let map = test_map();
+        let (gs, vehicle_handle, far_handle) = setup(&cvars, &map);
+        let dump = export(&gs, &map, Vec2f::new(0.0, 0.0), Vec2f::new(150.0, 150.0));
+        let scene = import(&dump).unwrap();
+
+        let handles: Vec<_> = scene.vehicles.iter().map(|body| body.handle).collect();
+        assert_eq!(handles, [vehicle_handle]);
+        assert!(!handles.contains(&far_handle));
+        // Only tiles touching the region, the wall column is outside.
+        assert_eq!(
+            dump.lines()
+                .filter(|line| line.starts_with("tile "))
+                .count(),
+            9
+        );
+        assert!(!scene.map.is_wall(Vec2f::new(224.0, 96.0)));
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let err = import("region 0 0 1 1\nmap 1 1\nbogus 1").unwrap_err();
+        assert_eq!(err.line, 3);
+        let err = import("region 0 0 1\nmap 1 1").unwrap_err();
+        assert_eq!((err.line, err.msg.as_str()), (1, "not enough values"));
+        let err = import("map 1 1").unwrap_err();
+        assert_eq!(err.msg, "missing region");
+        let err = import("region 0 0 1 1\nmap 1 1\ntile 0 0 4 0").unwrap_err();
+        assert_eq!(err.msg, "tile uses unknown surface 4");
+    }
+
+    /// How to turn a bug report into a test: paste the dump, set up inputs, assert the expected behavior.
+    ///
+    /// This one is synthetic: a tank driving at full speed into a wall
+    /// must not end up with any part of its hitbox inside it.
+    #[test]
+    fn test_dump_tank_into_wall() {
+        let dump = "\
+region 128 64 256 128
+map 6 4
+frame 120 0.016666666666666666
+surface 0 0 1 1 g1
+surface 4 2 1 1 bunker1
+tile 2 1 0 0
+tile 3 1 4 0
+vehicle 4294967296 4294967296 0 170 96 0 300 0 0 -19 -12 19 12
+";
+        let cvars = Cvars {
+            d_collision_trace: true,
+            ..Cvars::default()
+        };
+        let scene = import(dump).unwrap();
+        let mut gs = scene.game_state(&cvars);
+        let body = &scene.vehicles[0];
+        gs.players[body.owner].input = Input::new_up();
+
+        for _ in 0..60 {
+            gs.frame_num += 1;
+            systems::vehicle_movement(&cvars, &mut gs, &scene.map);
+            let vehicle = &gs.vehicles[body.handle];
+            for corner in vehicle.obb.corners {
+                assert!(!scene.map.is_wall(corner));
+            }
+        }
+        assert!(!gs.collision_trace.is_empty());
+    }
+}
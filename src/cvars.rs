@@ -1,11 +1,19 @@
 //! Console variables - configuration options for anything and everything.
 
-use std::default::Default;
+use std::{
+    default::Default,
+    fmt::{self, Debug, Display, Formatter},
+    str::FromStr,
+};
 
 use cvars::SetGet;
 use strum_macros::{Display, EnumString};
 
-use crate::{entities::Hitbox, entities::VehicleType, entities::Weapon, map::Vec2f};
+use crate::{
+    entities::Facing, entities::Hitbox, entities::PickupKind, entities::PowerupKind,
+    entities::VehicleType, entities::Weapon, entities::RESERVE_UNLIMITED, game_state::GameMode,
+    game_state::TRAIL_CAPACITY, map::Kind, map::Vec2f,
+};
 
 /// Console variables - configuration options for anything and everything.
 ///
@@ -26,16 +34,169 @@ pub struct Cvars {
     /// Master switch for AI - disable if you want stationary targets
     pub ai: bool,
 
+    /// Standard deviation of random error added to bots' shot angles.
+    pub bots_aim_error: f64,
+    /// Bots with less than this fraction of their HP drive to the nearest cover from the enemy they see.
+    pub bots_cover_hp: f64,
+    /// How far bots look for cover, capped at `visibility::COVER_RADIUS_MAX`.
+    pub bots_cover_radius: f64,
+    /// Difficulty of bots, 0 (easiest) to 4 (hardest).
+    /// Scales reaction time, aim error and fire alignment, sets how well they lead moving targets
+    /// and gates weapons (see `bots_railgun_difficulty`).
+    pub bots_difficulty: u32,
+    /// Bots only fire when their turret is within this angle (radians) of where they want to aim.
+    /// Scaled by difficulty like aim error.
+    pub bots_fire_alignment: f64,
+    /// Bots don't fire at targets further away than this.
+    pub bots_fire_range: f64,
     pub bots_max: usize,
+    /// Bots below this difficulty never use the railgun.
+    pub bots_railgun_difficulty: u32,
+    /// Delay between a bot seeing something and reacting to it.
+    pub bots_reaction_time: f64,
+    /// Bots only notice enemies closer than this with no walls in the way.
+    pub bots_vision_range: f64,
+
+    /// Projectiles flying in an arc look this much bigger per pixel of height
+    /// and their shadow moves away from them by the same factor.
+    pub cl_arc_scale: f64,
+
+    /// Max queued audio events, the oldest are dropped if the frontend doesn't drain them.
+    pub cl_audio_events_max: usize,
+
+    /// How long BFG beams stay visible, 0 means one frame.
+    pub cl_bfg_beam_duration: f64,
+
+    /// Draw HUD and debug text with the font atlas (see `bitmap_font`) if it's in assets.
+    /// Without the atlas, macroquad's `draw_text` is used either way.
+    pub cl_bitmap_font: bool,
 
     pub cl_cluster_bomb_size: f64,
 
+    /// Show where the current weapon would shoot - an aim line,
+    /// a dotted trajectory for cluster bombs and rockets or where a railgun shot would hit a wall.
+    pub cl_crosshair: bool,
+    pub cl_crosshair_alpha: f64,
+    /// Number of dots in the trajectory preview.
+    pub cl_crosshair_dots: usize,
+    /// Seconds of flight between dots in the trajectory preview.
+    pub cl_crosshair_dot_interval: f64,
+    pub cl_crosshair_dot_size: f64,
+    /// Length of the aim line of weapons without a trajectory preview.
+    pub cl_crosshair_length: f64,
+    /// Size of the railgun's wall hit marker.
+    pub cl_crosshair_marker_size: f64,
+    pub cl_crosshair_thickness: f64,
+
+    /// Max track and scorch marks on the ground, the oldest are removed first, 0 disables them.
+    pub cl_decals_max: usize,
+    /// Fraction of `cl_decals_max` over which the oldest marks fade out before they're removed.
+    pub cl_decals_fade: f64,
+
+    pub cl_debris_size: f64,
+
+    /// Flares are drawn as dots of this radius, fading out as they burn down.
+    pub cl_flare_size: f64,
+
+    /// Projectiles passing closer than this to a listener produce a flyby sound event, 0 disables.
+    pub cl_flyby_distance_bfg: f64,
+    pub cl_flyby_distance_cluster_bomb: f64,
+    pub cl_flyby_distance_guided_missile: f64,
+    pub cl_flyby_distance_homing_missile: f64,
+    pub cl_flyby_distance_machine_gun: f64,
+    pub cl_flyby_distance_railgun: f64,
+    pub cl_flyby_distance_rockets: f64,
+    /// Max flyby events per listener per frame, the closest ones are kept.
+    pub cl_flyby_max_per_frame: usize,
+    /// Projectiles slower than this never produce flyby events.
+    pub cl_flyby_speed_min: f64,
+
+    /// Slow the game down briefly when a local player's vehicle is destroyed.
+    ///
+    /// The time scale drops to `cl_hit_stop_scale` for `cl_hit_stop_duration` seconds
+    /// of real time, then ramps back to normal over `cl_hit_stop_ramp` seconds.
+    pub cl_hit_stop: bool,
+    pub cl_hit_stop_duration: f64,
+    pub cl_hit_stop_ramp: f64,
+    pub cl_hit_stop_scale: f64,
+
+    /// Entities whose state arrives less often than frames are rendered (see `interpolation`)
+    /// are drawn this many seconds in the past so there's usually a newer state to move towards.
+    pub cl_interp: f64,
+    /// When no newer state arrives in time, keep moving in the same direction for at most this many seconds.
+    pub cl_interp_extrapolate_max: f64,
+    /// A new state further than this many pixels from where the entity was expected is shown immediately.
+    pub cl_interp_snap_distance: f64,
+
     pub cl_machine_gun_trail_length: f64,
     pub cl_machine_gun_trail_thickness: f64,
 
+    /// Edit the map with the mouse while playing (singleplayer only).
+    ///
+    /// Left button paints, right button picks the tile under the cursor,
+    /// the wheel changes the surface, with shift the rotation.
+    /// F6 / F7 add a column / row, with shift they remove one,
+    /// F5 saves to the path given by `--map-editor-path`.
+    pub cl_map_editor: bool,
+
+    /// Draw a flash at each barrel when it fires.
+    pub cl_muzzle_flash: bool,
+    pub cl_muzzle_flash_duration: f64,
+    pub cl_muzzle_flash_size: f64,
+
+    /// Max queued notifications, the oldest are dropped if the frontend doesn't drain them.
+    pub cl_notifications_max: usize,
+
+    /// Drive with the mouse like an analog stick centered on the screen (singleplayer only).
+    ///
+    /// Horizontal offset steers, vertical is the throttle, both are full at `cl_mouse_joystick_radius`.
+    pub cl_mouse_joystick: bool,
+    pub cl_mouse_joystick_radius: f64,
+
+    /// Predict the local player's vehicle from its inputs instead of waiting for the server (singleplayer only).
+    ///
+    /// Assumes `sv_tickrate_mode` Synchronized, other modes mispredict and keep getting corrected.
+    pub cl_prediction: bool,
+    /// Mispredictions smaller than `cl_prediction_snap_distance` are blended out over this many frames.
+    pub cl_prediction_blend_frames: usize,
+    /// At most this many unacknowledged inputs are replayed after each server update, older ones are dropped.
+    pub cl_prediction_max_replay: usize,
+    /// Remove predicted projectiles the server hasn't confirmed after this many seconds
+    /// even if no update acknowledged the input that fired them.
+    pub cl_prediction_projectile_timeout: f64,
+    /// Mispredictions larger than this many pixels snap the vehicle instead of blending.
+    pub cl_prediction_snap_distance: f64,
+
     pub cl_railgun_trail_thickness: f64,
     pub cl_railgun_trail_duration: f64,
 
+    /// Push the turret (or the chassis for weapons mounted on it) back when firing.
+    ///
+    /// The sprite moves `cl_recoil_distance` pixels (`cl_recoil_distance_heavy` for the railgun and BFG)
+    /// against the shot and returns exponentially with the time constant `cl_recoil_time_constant`.
+    /// Heavy weapons also add `cl_recoil_kick` trauma to the shooter's camera shake (see `cl_shake`),
+    /// biased against the shot by `cl_recoil_kick_bias` (0 is random, 1 straight back).
+    pub cl_recoil: bool,
+    pub cl_recoil_distance: f64,
+    pub cl_recoil_distance_heavy: f64,
+    pub cl_recoil_kick: f64,
+    pub cl_recoil_kick_bias: f64,
+    pub cl_recoil_time_constant: f64,
+
+    /// Radius of a scorch mark under an explosion of scale 1.
+    pub cl_scorch_radius: f64,
+
+    /// Shake the camera when explosions happen near it.
+    ///
+    /// Each explosion closer than `cl_shake_radius` adds `cl_shake_trauma` times its scale,
+    /// less the farther it is. Trauma is capped at 1 and goes down by `cl_shake_decay` per second.
+    /// The view moves by up to `cl_shake_max_offset` pixels times trauma squared.
+    pub cl_shake: bool,
+    pub cl_shake_decay: f64,
+    pub cl_shake_max_offset: f64,
+    pub cl_shake_radius: f64,
+    pub cl_shake_trauma: f64,
+
     pub cl_spawn_indicator_animation_time: f64,
     pub cl_spawn_indicator_blinking_period: f64,
     pub cl_spawn_indicator_duration: f64,
@@ -43,6 +204,27 @@ pub struct Cvars {
     pub cl_spawn_indicator_square_side_end: f32,
     pub cl_spawn_indicator_thickness: f32,
 
+    /// Loud projectiles (BFG, guided missiles) closer than this to a listener get a continuous sound channel.
+    pub cl_sound_channel_range: f64,
+
+    /// Speed of the free camera while dead, in pixels per second. Tab follows other players instead.
+    pub cl_spectate_speed: f64,
+
+    /// `[` and `]` halve and double the time scale, `\` pauses and steps one tick.
+    pub cl_time_controls: bool,
+
+    /// Distance between track marks left by vehicles.
+    pub cl_tracks_interval: f64,
+
+    /// Aim the turret at the mouse cursor instead of turning it by steps (singleplayer only).
+    pub cl_turret_follows_mouse: bool,
+
+    /// Zooming in shows less of the map, out shows more. The mouse wheel zooms (except in the map editor).
+    pub cl_zoom_max: f64,
+    pub cl_zoom_min: f64,
+    /// Each step of the mouse wheel multiplies or divides the zoom by this.
+    pub cl_zoom_step: f64,
+
     pub con_background_alpha: f32,
     pub con_prompt_group_x: f32,
     pub con_prompt_group_y_offset: f32,
@@ -54,6 +236,10 @@ pub struct Cvars {
     pub con_prompt_label_x: f32,
     pub con_prompt_label_y_offset: f32,
 
+    /// Record recent collision decisions so they can be included in collision dumps.
+    pub d_collision_trace: bool,
+    /// How many collision decisions to keep.
+    pub d_collision_trace_len: usize,
     /// "Temporary" cvar for quick testing. Normally unused but kept here
     /// so I don't have to add a cvar each time I want a quick toggle.
     pub d_dbg: bool,
@@ -61,24 +247,68 @@ pub struct Cvars {
     /// Master switch for debug output - the d_draw_* group.
     pub d_draw: bool,
     pub d_draw_crosses: bool,
+    /// Graph of the last `d_frametime_graph_frames` frame times, makes hitches visible during play.
+    /// Not in the d_draw_* group, same as `d_fps`.
+    pub d_draw_frametime_graph: bool,
+    /// Tile borders.
+    pub d_draw_grid: bool,
+    /// Outlines of vehicles' oriented hitboxes.
     pub d_draw_hitboxes: bool,
     pub d_draw_lines: bool,
     pub d_draw_lines_ends_length: f64,
     pub d_draw_perf: bool,
+    /// The segments projectiles were tested for collisions along during the last gamelogic frame.
+    pub d_draw_projectile_rays: bool,
     pub d_draw_text: bool,
+    /// Draw this many extra lines of debug text each frame
+    /// to compare the cost of `cl_bitmap_font` with macroquad's `draw_text` (see `d_timing_systems`).
+    pub d_draw_text_bench: usize,
     pub d_draw_text_line_height: f64,
+    /// Arrows showing the velocity of vehicles and projectiles.
+    pub d_draw_velocities: bool,
+    /// Velocity arrows point where the entity will be after this many seconds.
+    pub d_draw_velocities_scale: f64,
     pub d_draw_world_text: bool,
+    /// Log game events (notifications) to the console, checked at startup.
+    pub d_events: bool,
     pub d_explosion_radius: bool,
     /// Draw FPS counter. Intentionally not in the d_draw_* group
     /// so I can easily check perf with and without the other debug output.
+    /// Show flyby sound events and continuous sound channels
+    pub d_flybys: bool,
     pub d_fps: bool,
     pub d_fps_period: f64,
     pub d_fps_x: f64,
     pub d_fps_y: f64,
+    pub d_frametime_graph_frames: usize,
+    /// Pixels per millisecond.
+    pub d_frametime_graph_scale: f64,
+    pub d_frametime_graph_x: f64,
+    pub d_frametime_graph_y: f64,
     /// Display the last pressed key. Useful for debugging MQ's issues with keyboard layouts.
     pub d_last_key: bool,
+    /// Fake latency - delay inputs sent to the server and updates sent back by this many frames each way (singleplayer only).
+    pub d_latency_frames: usize,
+    /// After a crash, also print the map and seed needed to reproduce it.
+    /// Only read at startup.
+    pub d_panic_context: bool,
+    /// Log performance counters as JSON every this many seconds, 0 disables.
+    /// Per-system numbers need `d_timing_systems`.
+    pub d_perf_stats_period: f64,
+    /// Pretend other vehicles' state only arrives every this many gamelogic frames
+    /// and draw them interpolated (singleplayer only), 0 disables.
+    pub d_remote_update_interval: u64,
+    /// How often (in gamelogic frames) replays store a checksum of the game state, 0 disables.
+    pub d_replay_checksum_interval: u64,
     pub d_tickrate_remaining: bool,
     pub d_timing_samples: usize,
+    /// Measure how long each gamelogic system and drawing phase takes, see `PerfStats`.
+    /// Off by default because the timers themselves aren't free.
+    pub d_timing_systems: bool,
+    /// Record what the gamelogic did each tick for finding where two runs diverged, see `trace`.
+    pub d_trace: bool,
+    /// How many of the most recent gamelogic ticks `d_trace` keeps.
+    pub d_trace_ticks: u64,
     pub d_tracing: bool,
     /// The seed to initialize the RNG.
     ///
@@ -91,74 +321,241 @@ pub struct Cvars {
     pub d_seed: u64,
     /// Change speed of everything in the game
     pub d_speed: f64,
+    /// Entity counts and (with `d_timing_systems`) the slowest gamelogic systems last frame.
+    /// In the d_draw_* group.
+    pub d_stats: bool,
+
+    pub g_ammo_crate_radius: f64,
 
     /// Hit points. Recommended values are between 1 and 500, original RecWar used 100 as default.
     ///
     /// Note that the actual number of hitpoints depends on vehicle type, this is just the base value.
     /// By default, the tank uses this value, other vehicles scale it by some multiplier.
     pub g_armor: f64,
-
+    /// How many armor pickups are on the map at the same time.
+    pub g_armor_pickup_count: usize,
+    /// Pickups can't lower a facing's damage multiplier below this.
+    pub g_armor_pickup_min: f64,
+    pub g_armor_pickup_radius: f64,
+    /// How much one pickup lowers the weakest facing's damage multiplier.
+    pub g_armor_pickup_step: f64,
+
+    pub g_bfg_allowed: bool,
+    pub g_bfg_angle_spread: f64,
+    pub g_bfg_arc_gravity: f64,
+    pub g_bfg_arc_speed: f64,
     pub g_bfg_beam_damage_per_sec: f64,
     pub g_bfg_beam_range: f64,
+    pub g_bfg_cooldown_group: CooldownGroup,
     pub g_bfg_damage_direct: f64,
+    pub g_bfg_damage_type: DamageType,
     pub g_bfg_explosion_damage: f64,
     pub g_bfg_explosion_radius: f64,
     pub g_bfg_explosion_scale: f64,
+    pub g_bfg_fire_limiter: FireLimiter,
+    pub g_bfg_heat_dissipation: f64,
+    pub g_bfg_heat_per_shot: f64,
+    pub g_bfg_lifetime: f64,
     pub g_bfg_radius: f64,
     pub g_bfg_reload_ammo: u32,
     pub g_bfg_reload_time: f64,
+    /// Ammo for reloads on top of the first magazine, negative is unlimited.
+    pub g_bfg_reserve: i32,
     pub g_bfg_speed: f64,
     pub g_bfg_vehicle_velocity_factor: f64,
 
+    pub g_cluster_bomb_allowed: bool,
+    pub g_cluster_bomb_angle_spread: f64,
+    /// Pulls cluster bombs back to the ground in pixels per second squared, 0 means they fly straight
+    /// along the ground. See `g_weapon_arc_gravity`.
+    pub g_cluster_bomb_arc_gravity: f64,
+    /// Initial upward speed of arcing cluster bombs - they land after `2 * arc_speed / arc_gravity` seconds.
+    pub g_cluster_bomb_arc_speed: f64,
+    /// Bounce off walls instead of flying over them. The timer still decides when they explode.
+    pub g_cluster_bomb_bounce: bool,
+    /// Fraction of speed kept after each bounce.
+    pub g_cluster_bomb_bounce_retention: f64,
+    pub g_cluster_bomb_cooldown_group: CooldownGroup,
     pub g_cluster_bomb_count: i32,
     pub g_cluster_bomb_damage_direct: f64,
+    pub g_cluster_bomb_damage_type: DamageType,
     pub g_cluster_bomb_explosion_damage: f64,
     pub g_cluster_bomb_explosion_radius: f64,
     pub g_cluster_bomb_explosion_scale: f64,
+    pub g_cluster_bomb_fire_limiter: FireLimiter,
+    pub g_cluster_bomb_heat_dissipation: f64,
+    pub g_cluster_bomb_heat_per_shot: f64,
+    pub g_cluster_bomb_lifetime: f64,
     pub g_cluster_bomb_reload_ammo: u32,
     pub g_cluster_bomb_reload_time: f64,
+    pub g_cluster_bomb_reserve: i32,
     pub g_cluster_bomb_shadow_alpha: f64,
     pub g_cluster_bomb_shadow_x: f64,
     pub g_cluster_bomb_shadow_y: f64,
     pub g_cluster_bomb_speed: f64,
     pub g_cluster_bomb_speed_spread_forward: f64,
-    pub g_cluster_bomb_speed_spread_gaussian: bool,
     pub g_cluster_bomb_speed_spread_sideways: f64,
     pub g_cluster_bomb_time: f64,
     pub g_cluster_bomb_time_spread: f64,
     pub g_cluster_bomb_vehicle_velocity_factor: f64,
 
+    /// Weapons in the same group share this cooldown (in seconds) in addition to their own refire
+    /// so alternating between them isn't faster than using just one.
+    pub g_cooldown_group_energy: f64,
+    pub g_cooldown_group_explosive: f64,
+    pub g_cooldown_group_heavy: f64,
+
+    /// A vehicle picks up or returns a flag when the flag is this close to its hitbox.
+    pub g_ctf_flag_radius: f64,
+    /// Dropped flags go back to their base after this many seconds.
+    pub g_ctf_flag_return_time: f64,
+    pub g_ctf_score_capture: i32,
+
+    /// How much the adjustment level can change the bots' aim error in each direction.
+    pub g_dda_aim_error_range: f64,
+    /// Dynamic difficulty adjustment - make bots easier or harder depending on how well the human is doing.
+    ///
+    /// Only used when there's exactly one human player.
+    /// The current adjustment is shown in the debug text and on the scoreboard.
+    pub g_dda_enabled: bool,
+    pub g_dda_gain_integral: f64,
+    pub g_dda_gain_proportional: f64,
+    /// Max change of the adjustment level per minute. The level is between -1 and 1.
+    pub g_dda_rate_max: f64,
+    /// How much the adjustment level can change the bots' reaction time in each direction.
+    pub g_dda_reaction_time_range: f64,
+    /// The human's kill/death ratio DDA tries to achieve.
+    pub g_dda_target_ratio: f64,
+    /// Kills and deaths older than this (in seconds) don't affect the adjustment.
+    pub g_dda_window: f64,
+
+    /// Dust particles thrown out by each explosion, scaled by the explosion's size.
+    pub g_dust_count: u32,
+    pub g_dust_lifetime: f64,
+    pub g_dust_size: f64,
+    pub g_dust_speed: f64,
+
+    /// Damage multiplier for hits to the turret of any vehicle, see `g_tank_facing_front` and co for the chassis.
+    pub g_facing_turret: f64,
+    /// Direct hits passing this close to the turret's center hit the turret.
+    pub g_facing_turret_radius: f64,
+
     pub g_ffa_score_kill: i32,
     pub g_ffa_score_death: i32,
 
+    /// Max shots per second of each vehicle across all weapons. Shots over the limit are delayed, not lost.
+    /// Protects against absurd refire settings, infinite (no limit) by default.
+    pub g_fire_rate_cap: f64,
+
+    /// Seconds between deploying flares, see `sys_homing`.
+    pub g_flare_cooldown: f64,
+    /// Flares each vehicle spawns with.
+    pub g_flare_count: u32,
+    /// How long a flare burns and decoys homing missiles.
+    pub g_flare_lifetime: f64,
+
+    /// Whether weapons, ramming and hazards set off by teammates hurt them.
+    /// Vehicles can always damage themselves.
+    pub g_friendly_fire: FriendlyFire,
+
+    /// Takes effect when the server is created.
+    pub g_game_mode: GameMode,
+
+    pub g_homing_missile_allowed: bool,
+    pub g_homing_missile_angle_spread: f64,
+    pub g_homing_missile_arc_gravity: f64,
+    pub g_homing_missile_arc_speed: f64,
+    pub g_homing_missile_cooldown_group: CooldownGroup,
     pub g_homing_missile_damage_direct: f64,
+    pub g_homing_missile_damage_type: DamageType,
     pub g_homing_missile_explosion_damage: f64,
     pub g_homing_missile_explosion_radius: f64,
     pub g_homing_missile_explosion_scale: f64,
+    pub g_homing_missile_fire_limiter: FireLimiter,
+    /// Chance to go for the closest enemy flare in the lock cone instead of a vehicle each time the missile retargets.
+    /// Once it chooses a flare, it stays on it until the flare burns out.
+    pub g_homing_missile_flare_preference: f64,
+    pub g_homing_missile_heat_dissipation: f64,
+    pub g_homing_missile_heat_per_shot: f64,
+    pub g_homing_missile_lifetime: f64,
+    /// Half of the cone in front of the missile where it can see targets.
+    pub g_homing_missile_lock_angle_deg: f64,
+    pub g_homing_missile_lock_range: f64,
     pub g_homing_missile_reload_ammo: u32,
     pub g_homing_missile_reload_time: f64,
+    pub g_homing_missile_reserve: i32,
+    /// Seconds between choosing a target, unless it's locked on a flare.
+    pub g_homing_missile_retarget_interval: f64,
     pub g_homing_missile_speed_initial: f64,
+    pub g_homing_missile_turn_rate_deg: f64,
     pub g_homing_missile_vehicle_velocity_factor: f64,
 
+    pub g_machine_gun_allowed: bool,
     pub g_machine_gun_angle_spread: f64,
+    pub g_machine_gun_arc_gravity: f64,
+    pub g_machine_gun_arc_speed: f64,
+    pub g_machine_gun_cooldown_group: CooldownGroup,
     pub g_machine_gun_damage: f64,
+    pub g_machine_gun_damage_type: DamageType,
+    pub g_machine_gun_fire_limiter: FireLimiter,
+    pub g_machine_gun_heat_dissipation: f64,
+    pub g_machine_gun_heat_per_shot: f64,
+    pub g_machine_gun_lifetime: f64,
     pub g_machine_gun_refire: f64,
     pub g_machine_gun_reload_ammo: u32,
     pub g_machine_gun_reload_time: f64,
+    pub g_machine_gun_reserve: i32,
+    /// Chance that a round hitting a wall at a shallow angle ricochets instead of exploding.
+    pub g_machine_gun_ricochet_chance: f64,
+    /// Only rounds hitting at most this many degrees from parallel to the wall can ricochet.
+    pub g_machine_gun_ricochet_max_angle: f64,
+    /// Fraction of speed kept after a ricochet.
+    pub g_machine_gun_ricochet_retention: f64,
     pub g_machine_gun_speed: f64,
     pub g_machine_gun_vehicle_velocity_factor: f64,
 
+    /// A team wins capture the flag once it has this many captures, 0 means no limit.
+    pub g_match_capture_limit: i32,
+    /// Seconds before the match starts during which nobody can move, shoot or get hurt.
+    pub g_match_countdown: f64,
+    /// A player wins free for all once they have this many kills, 0 means no limit.
+    pub g_match_kill_limit: i32,
+    /// Seconds the victory screen is shown before a new match starts.
+    pub g_match_restart_delay: f64,
+    /// The leader wins when the match has been going on for this many seconds (ties are a draw), 0 means no limit.
+    pub g_match_time_limit: f64,
+
+    pub g_guided_missile_allowed: bool,
+    pub g_guided_missile_angle_spread: f64,
+    pub g_guided_missile_arc_gravity: f64,
+    pub g_guided_missile_arc_speed: f64,
     pub g_guided_missile_accel_forward: f64,
+    pub g_guided_missile_cooldown_group: CooldownGroup,
     pub g_guided_missile_damage_direct: f64,
+    pub g_guided_missile_damage_type: DamageType,
+    /// Pressing fire again while guiding detonates the missile instead of shooting.
+    pub g_guided_missile_detonate_on_fire: bool,
+    /// Detonate the missile when its owner dies, otherwise it keeps flying straight.
+    pub g_guided_missile_detonate_on_owner_death: bool,
     pub g_guided_missile_explosion_damage: f64,
     pub g_guided_missile_explosion_radius: f64,
     pub g_guided_missile_explosion_scale: f64,
+    pub g_guided_missile_fire_limiter: FireLimiter,
     pub g_guided_missile_friction_const: f64,
     pub g_guided_missile_friction_linear: f64,
+    pub g_guided_missile_heat_dissipation: f64,
+    pub g_guided_missile_heat_per_shot: f64,
+    pub g_guided_missile_lateral_friction_const: f64,
+    pub g_guided_missile_lateral_friction_linear: f64,
+    pub g_guided_missile_lifetime: f64,
     pub g_guided_missile_reload_ammo: u32,
     pub g_guided_missile_reload_time: f64,
+    pub g_guided_missile_reserve: i32,
     pub g_guided_missile_speed_initial: f64,
     pub g_guided_missile_speed_max: f64,
+    /// Fuel - how long it flies before exploding on its own, infinite means until it hits something.
+    /// Limited so players can't guide one forever while their vehicle sits safely behind a wall.
+    pub g_guided_missile_time: f64,
     pub g_guided_missile_turn_effectiveness: f64,
     pub g_guided_missile_turn_rate_increase: f64,
     pub g_guided_missile_turn_rate_friction_const: f64,
@@ -166,83 +563,73 @@ pub struct Cvars {
     pub g_guided_missile_turn_rate_max: f64,
     pub g_guided_missile_vehicle_velocity_factor: f64,
 
-    pub g_hardpoint_hovercraft_machine_gun: Hardpoint,
-    pub g_hardpoint_hovercraft_machine_gun_x: f64,
-    pub g_hardpoint_hovercraft_machine_gun_y: f64,
-    pub g_hardpoint_hovercraft_railgun: Hardpoint,
-    pub g_hardpoint_hovercraft_railgun_x: f64,
-    pub g_hardpoint_hovercraft_railgun_y: f64,
-    pub g_hardpoint_hovercraft_cluster_bomb: Hardpoint,
-    pub g_hardpoint_hovercraft_cluster_bomb_x: f64,
-    pub g_hardpoint_hovercraft_cluster_bomb_y: f64,
-    pub g_hardpoint_hovercraft_rockets: Hardpoint,
-    pub g_hardpoint_hovercraft_rockets_x: f64,
-    pub g_hardpoint_hovercraft_rockets_y: f64,
-    pub g_hardpoint_hovercraft_homing_missile: Hardpoint,
-    pub g_hardpoint_hovercraft_homing_missile_x: f64,
-    pub g_hardpoint_hovercraft_homing_missile_y: f64,
-    pub g_hardpoint_hovercraft_guided_missile: Hardpoint,
-    pub g_hardpoint_hovercraft_guided_missile_x: f64,
-    pub g_hardpoint_hovercraft_guided_missile_y: f64,
-    pub g_hardpoint_hovercraft_bfg: Hardpoint,
-    pub g_hardpoint_hovercraft_bfg_x: f64,
-    pub g_hardpoint_hovercraft_bfg_y: f64,
-
-    pub g_hardpoint_hummer_machine_gun: Hardpoint,
-    pub g_hardpoint_hummer_machine_gun_x: f64,
-    pub g_hardpoint_hummer_machine_gun_y: f64,
-    pub g_hardpoint_hummer_railgun: Hardpoint,
-    pub g_hardpoint_hummer_railgun_x: f64,
-    pub g_hardpoint_hummer_railgun_y: f64,
-    pub g_hardpoint_hummer_cluster_bomb: Hardpoint,
-    pub g_hardpoint_hummer_cluster_bomb_x: f64,
-    pub g_hardpoint_hummer_cluster_bomb_y: f64,
-    pub g_hardpoint_hummer_rockets: Hardpoint,
-    pub g_hardpoint_hummer_rockets_x: f64,
-    pub g_hardpoint_hummer_rockets_y: f64,
-    pub g_hardpoint_hummer_homing_missile: Hardpoint,
-    pub g_hardpoint_hummer_homing_missile_x: f64,
-    pub g_hardpoint_hummer_homing_missile_y: f64,
-    pub g_hardpoint_hummer_guided_missile: Hardpoint,
-    pub g_hardpoint_hummer_guided_missile_x: f64,
-    pub g_hardpoint_hummer_guided_missile_y: f64,
-    pub g_hardpoint_hummer_bfg: Hardpoint,
-    pub g_hardpoint_hummer_bfg_x: f64,
-    pub g_hardpoint_hummer_bfg_y: f64,
-
-    pub g_hardpoint_tank_machine_gun: Hardpoint,
-    pub g_hardpoint_tank_machine_gun_x: f64,
-    pub g_hardpoint_tank_machine_gun_y: f64,
-    pub g_hardpoint_tank_railgun: Hardpoint,
-    pub g_hardpoint_tank_railgun_x: f64,
-    pub g_hardpoint_tank_railgun_y: f64,
-    pub g_hardpoint_tank_cluster_bomb: Hardpoint,
-    pub g_hardpoint_tank_cluster_bomb_x: f64,
-    pub g_hardpoint_tank_cluster_bomb_y: f64,
-    pub g_hardpoint_tank_rockets: Hardpoint,
-    pub g_hardpoint_tank_rockets_x: f64,
-    pub g_hardpoint_tank_rockets_y: f64,
-    pub g_hardpoint_tank_homing_missile: Hardpoint,
-    pub g_hardpoint_tank_homing_missile_x: f64,
-    pub g_hardpoint_tank_homing_missile_y: f64,
-    pub g_hardpoint_tank_guided_missile: Hardpoint,
-    pub g_hardpoint_tank_guided_missile_x: f64,
-    pub g_hardpoint_tank_guided_missile_y: f64,
-    pub g_hardpoint_tank_bfg: Hardpoint,
-    pub g_hardpoint_tank_bfg_x: f64,
-    pub g_hardpoint_tank_bfg_y: f64,
+    /// Where each barrel of a weapon is mounted and where relative to that part's center,
+    /// see `Hardpoints` for the format. Each shot fires from all of them.
+    pub g_hardpoint_hovercraft_machine_gun: Hardpoints,
+    pub g_hardpoint_hovercraft_railgun: Hardpoints,
+    pub g_hardpoint_hovercraft_cluster_bomb: Hardpoints,
+    pub g_hardpoint_hovercraft_rockets: Hardpoints,
+    pub g_hardpoint_hovercraft_homing_missile: Hardpoints,
+    pub g_hardpoint_hovercraft_guided_missile: Hardpoints,
+    pub g_hardpoint_hovercraft_bfg: Hardpoints,
+
+    pub g_hardpoint_hummer_machine_gun: Hardpoints,
+    pub g_hardpoint_hummer_railgun: Hardpoints,
+    pub g_hardpoint_hummer_cluster_bomb: Hardpoints,
+    pub g_hardpoint_hummer_rockets: Hardpoints,
+    pub g_hardpoint_hummer_homing_missile: Hardpoints,
+    pub g_hardpoint_hummer_guided_missile: Hardpoints,
+    pub g_hardpoint_hummer_bfg: Hardpoints,
+
+    pub g_hardpoint_tank_machine_gun: Hardpoints,
+    pub g_hardpoint_tank_railgun: Hardpoints,
+    pub g_hardpoint_tank_cluster_bomb: Hardpoints,
+    pub g_hardpoint_tank_rockets: Hardpoints,
+    pub g_hardpoint_tank_homing_missile: Hardpoints,
+    pub g_hardpoint_tank_guided_missile: Hardpoints,
+    pub g_hardpoint_tank_bfg: Hardpoints,
+
+    /// Damage each frame a crusher is down, it's meant to be lethal.
+    /// Maps can override this and the other crusher and spike trap values per tile.
+    pub g_hazard_crusher_damage: f64,
+    /// How long a crusher stays down in each period.
+    pub g_hazard_crusher_duration: f64,
+    pub g_hazard_crusher_period: f64,
+    /// Time within the period when the crusher comes down.
+    pub g_hazard_crusher_phase: f64,
+    /// How long before coming down a crusher starts shaking.
+    pub g_hazard_crusher_telegraph: f64,
+    pub g_hazard_damage_floor_dps: f64,
+    pub g_hazard_spikes_damage: f64,
+    /// Delay after a spike trap fires before it can fire again.
+    pub g_hazard_spikes_rearm: f64,
 
     pub g_hitcircle_radius: f64, // TODO proper hitbox
 
     pub g_hovercraft_armor_scale: f64,
     pub g_hovercraft_accel_backward: f64,
     pub g_hovercraft_accel_forward: f64,
+    /// Damage multipliers for hits to each part of the vehicle, applied before resistances. Lower is better.
+    /// Pickups can improve them for individual vehicles.
+    pub g_hovercraft_facing_front: f64,
+    pub g_hovercraft_facing_rear: f64,
+    pub g_hovercraft_facing_side: f64,
     pub g_hovercraft_friction_const: f64,
     pub g_hovercraft_friction_linear: f64,
+    /// Friction against sliding sideways, the other friction cvars only slow down movement along the heading.
+    pub g_hovercraft_lateral_friction_const: f64,
+    pub g_hovercraft_lateral_friction_linear: f64,
+    pub g_hovercraft_mass: f64,
     pub g_hovercraft_maxs_x: f64,
     pub g_hovercraft_maxs_y: f64,
     pub g_hovercraft_mins_x: f64,
     pub g_hovercraft_mins_y: f64,
+    /// Damage multipliers per damage type, applied after armor facings. Lower is better.
+    /// Multiplies the reserve ammo of all weapons.
+    pub g_hovercraft_reserve_scale: f64,
+    pub g_hovercraft_resistance_energy: f64,
+    pub g_hovercraft_resistance_explosive: f64,
+    pub g_hovercraft_resistance_kinetic: f64,
     pub g_hovercraft_speed_max: f64,
     pub g_hovercraft_steering_car: f64,
     pub g_hovercraft_turn_effectiveness: f64,
@@ -250,20 +637,43 @@ pub struct Cvars {
     pub g_hovercraft_turn_rate_friction_linear: f64,
     pub g_hovercraft_turn_rate_increase: f64,
     pub g_hovercraft_turn_rate_max: f64,
+    /// Turret traverse limits relative to the chassis, -180 and 180 mean unlimited.
+    pub g_hovercraft_turret_angle_max_deg: f64,
+    pub g_hovercraft_turret_angle_min_deg: f64,
     pub g_hovercraft_turret_offset_chassis_x: f64,
     pub g_hovercraft_turret_offset_chassis_y: f64,
     pub g_hovercraft_turret_offset_turret_x: f64,
     pub g_hovercraft_turret_offset_turret_y: f64,
+    /// Hovercraft over water drift further and turn their velocity less than over land.
+    /// The stats not overridden for water are the same as on land.
+    pub g_hovercraft_water_friction_const: f64,
+    pub g_hovercraft_water_friction_linear: f64,
+    pub g_hovercraft_water_lateral_friction_const: f64,
+    pub g_hovercraft_water_lateral_friction_linear: f64,
+    pub g_hovercraft_water_passable: bool,
+    pub g_hovercraft_water_turn_effectiveness: f64,
+    /// Bit mask of weapons the vehicle carries, bit N is `Weapon` N (MG, Rail, CB, Rockets, HM, GM, BFG).
+    pub g_hovercraft_weapons: u32,
 
     pub g_hummer_armor_scale: f64,
     pub g_hummer_accel_backward: f64,
     pub g_hummer_accel_forward: f64,
+    pub g_hummer_facing_front: f64,
+    pub g_hummer_facing_rear: f64,
+    pub g_hummer_facing_side: f64,
     pub g_hummer_friction_const: f64,
     pub g_hummer_friction_linear: f64,
+    pub g_hummer_lateral_friction_const: f64,
+    pub g_hummer_lateral_friction_linear: f64,
+    pub g_hummer_mass: f64,
     pub g_hummer_maxs_x: f64,
     pub g_hummer_maxs_y: f64,
     pub g_hummer_mins_x: f64,
     pub g_hummer_mins_y: f64,
+    pub g_hummer_reserve_scale: f64,
+    pub g_hummer_resistance_energy: f64,
+    pub g_hummer_resistance_explosive: f64,
+    pub g_hummer_resistance_kinetic: f64,
     pub g_hummer_speed_max: f64,
     pub g_hummer_steering_car: f64,
     pub g_hummer_turn_effectiveness: f64,
@@ -271,44 +681,159 @@ pub struct Cvars {
     pub g_hummer_turn_rate_friction_linear: f64,
     pub g_hummer_turn_rate_increase: f64,
     pub g_hummer_turn_rate_max: f64,
+    pub g_hummer_turret_angle_max_deg: f64,
+    pub g_hummer_turret_angle_min_deg: f64,
     pub g_hummer_turret_offset_chassis_x: f64,
     pub g_hummer_turret_offset_chassis_y: f64,
     pub g_hummer_turret_offset_turret_x: f64,
     pub g_hummer_turret_offset_turret_y: f64,
-
+    pub g_hummer_water_passable: bool,
+    pub g_hummer_weapons: u32,
+
+    /// Max number of saved loadout presets per player.
+    pub g_loadout_presets_max: usize,
+
+    /// Enemy homing and guided missiles closer than this and approaching trigger a warning.
+    pub g_missile_warning_range: f64,
+
+    /// How long a collected ammo crate stays inactive.
+    pub g_pickup_respawn_time: f64,
+
+    pub g_powerup_radius: f64,
+    pub g_powerup_rapid_fire_duration: f64,
+    /// Time between shots is multiplied by this.
+    pub g_powerup_rapid_fire_refire_multiplier: f64,
+    /// How long a collected powerup stays inactive.
+    pub g_powerup_respawn_time: f64,
+    pub g_powerup_shield_duration: f64,
+    /// How much damage the shield absorbs before it breaks.
+    pub g_powerup_shield_hp: f64,
+    pub g_powerup_speed_boost_duration: f64,
+    /// Forward acceleration and max speed are multiplied by this.
+    pub g_powerup_speed_boost_multiplier: f64,
+
+    /// After this many ricochets or bounces, the projectile explodes (or stops for cluster bombs)
+    /// instead of rattling in a corner forever.
+    pub g_projectile_max_bounces: u32,
+
+    /// When there are more projectiles, the oldest are removed.
+    /// Also see `g_weapon_lifetime`.
+    pub g_projectiles_max: usize,
+
+    pub g_railgun_allowed: bool,
+    pub g_railgun_angle_spread: f64,
+    pub g_railgun_arc_gravity: f64,
+    pub g_railgun_arc_speed: f64,
+    /// Enemies closer than this see a vehicle charging its railgun.
+    pub g_railgun_charge_exposure_radius: f64,
+    /// How long the railgun charges before firing, 0 means it fires immediately like in the original.
+    pub g_railgun_charge_time: f64,
+    pub g_railgun_cooldown_group: CooldownGroup,
     pub g_railgun_damage: f64,
+    pub g_railgun_damage_type: DamageType,
+    pub g_railgun_fire_limiter: FireLimiter,
+    pub g_railgun_heat_dissipation: f64,
+    pub g_railgun_heat_per_shot: f64,
+    pub g_railgun_lifetime: f64,
     pub g_railgun_push: f64,
     pub g_railgun_reload_ammo: u32,
     pub g_railgun_reload_time: f64,
+    pub g_railgun_reserve: i32,
     pub g_railgun_speed: f64,
     pub g_railgun_vehicle_velocity_factor: f64,
+    /// Destructible walls only take this fraction of railgun damage so it rarely breaks through them.
+    pub g_railgun_wall_damage_factor: f64,
+
+    /// Damage per second to a vehicle pushed into a wall by another.
+    pub g_ram_crush_dps: f64,
+    /// Ramming damage per unit of the velocity change each vehicle gets from the collision,
+    /// so the lighter one takes more, see `g_tank_mass` and co.
+    pub g_ram_damage_factor: f64,
+    /// Vehicles colliding faster than this (along the collision normal) damage each other.
+    pub g_ram_damage_threshold: f64,
+
+    /// Training range - target dummies instead of bots and no scoring, see `sys_range`.
+    /// Only takes effect when the map is loaded.
+    pub g_range: bool,
+    /// DPS is averaged over this many seconds.
+    pub g_range_dps_window: f64,
+    pub g_range_dummy_hp: f64,
+    /// Seconds without taking damage before a dummy is fully repaired (if it repairs at all).
+    pub g_range_dummy_repair_delay: f64,
+    pub g_range_dummy_respawn_delay: f64,
+    pub g_range_dummy_speed: f64,
+    /// Number of recent MG impacts used for the grouping statistics.
+    pub g_range_grouping_samples: usize,
+
+    /// HP per second repair pads give to vehicles on them.
+    pub g_repair_amount: f64,
+    pub g_repair_pad_radius: f64,
 
     pub g_respawn_delay: f64,
 
+    pub g_rockets_allowed: bool,
+    pub g_rockets_angle_spread: f64,
+    pub g_rockets_arc_gravity: f64,
+    pub g_rockets_arc_speed: f64,
+    pub g_rockets_cooldown_group: CooldownGroup,
     pub g_rockets_damage_direct: f64,
+    pub g_rockets_damage_type: DamageType,
     pub g_rockets_explosion_damage: f64,
     pub g_rockets_explosion_radius: f64,
     pub g_rockets_explosion_scale: f64,
+    pub g_rockets_fire_limiter: FireLimiter,
+    pub g_rockets_heat_dissipation: f64,
+    pub g_rockets_heat_per_shot: f64,
+    pub g_rockets_lifetime: f64,
     pub g_rockets_refire: f64,
     pub g_rockets_reload_ammo: u32,
     pub g_rockets_reload_time: f64,
+    pub g_rockets_reserve: i32,
     pub g_rockets_speed: f64,
     pub g_rockets_vehicle_velocity_factor: f64,
 
+    /// Guided missiles and rockets can't hit their owner's vehicle directly for this many seconds after launch.
+    /// Explosions always damage the owner.
+    pub g_self_collision_delay: f64,
+
     pub g_self_destruct_damage_center: f64,
     pub g_self_destruct_damage_edge: f64,
+    pub g_self_destruct_damage_type: DamageType,
     pub g_self_destruct_explosion_scale: f64, // TODO radius
     pub g_self_destruct_radius: f64,
 
+    /// Rockets, missiles and damaged vehicles leave a puff of smoke this often (in seconds).
+    pub g_smoke_interval: f64,
+    pub g_smoke_lifetime: f64,
+    pub g_smoke_size: f64,
+    /// Vehicles with less HP than this fraction smoke.
+    pub g_smoke_vehicle_hp_fraction: f64,
+
+    /// Sparks from each projectile hitting a wall.
+    pub g_sparks_count: u32,
+    pub g_sparks_lifetime: f64,
+    pub g_sparks_size: f64,
+    pub g_sparks_speed: f64,
+
     pub g_tank_armor_scale: f64,
     pub g_tank_accel_backward: f64,
     pub g_tank_accel_forward: f64,
+    pub g_tank_facing_front: f64,
+    pub g_tank_facing_rear: f64,
+    pub g_tank_facing_side: f64,
     pub g_tank_friction_const: f64,
     pub g_tank_friction_linear: f64,
+    pub g_tank_lateral_friction_const: f64,
+    pub g_tank_lateral_friction_linear: f64,
+    pub g_tank_mass: f64,
     pub g_tank_maxs_x: f64,
     pub g_tank_maxs_y: f64,
     pub g_tank_mins_x: f64,
     pub g_tank_mins_y: f64,
+    pub g_tank_reserve_scale: f64,
+    pub g_tank_resistance_energy: f64,
+    pub g_tank_resistance_explosive: f64,
+    pub g_tank_resistance_kinetic: f64,
     pub g_tank_speed_max: f64,
     pub g_tank_steering_car: f64,
     pub g_tank_turn_effectiveness: f64,
@@ -316,14 +841,65 @@ pub struct Cvars {
     pub g_tank_turn_rate_friction_linear: f64,
     pub g_tank_turn_rate_increase: f64,
     pub g_tank_turn_rate_max: f64,
+    pub g_tank_turret_angle_max_deg: f64,
+    pub g_tank_turret_angle_min_deg: f64,
     pub g_tank_turret_offset_chassis_x: f64,
     pub g_tank_turret_offset_chassis_y: f64,
     pub g_tank_turret_offset_turret_x: f64,
     pub g_tank_turret_offset_turret_y: f64,
+    pub g_tank_water_passable: bool,
+    pub g_tank_weapons: u32,
 
     pub g_turret_turn_speed_deg: f64,
     pub g_turret_turn_step_angle_deg: f64,
 
+    /// Chunks thrown by destroyed vehicles. They damage other vehicles they hit.
+    pub g_vehicle_debris_count: u32,
+    pub g_vehicle_debris_damage: f64,
+    pub g_vehicle_debris_damage_type: DamageType,
+    pub g_vehicle_debris_speed_max: f64,
+    pub g_vehicle_debris_speed_min: f64,
+    pub g_vehicle_debris_time: f64,
+    /// Splash damage around destroyed vehicles. Vehicles it destroys explode too.
+    pub g_vehicle_explosion_damage: f64,
+    pub g_vehicle_explosion_damage_type: DamageType,
+    pub g_vehicle_explosion_radius: f64,
+    /// Vehicles move in substeps no longer than this many pixels
+    /// so they can't skip over thin walls at high speed or low FPS.
+    pub g_vehicle_max_step: f64,
+    /// Seconds it takes to switch between land and water movement stats (see `g_vehicle_movement_stats`)
+    /// after crossing a shoreline so a drift doesn't suddenly stop or start. 0 switches immediately.
+    pub g_vehicle_surface_blend_time: f64,
+
+    /// Old collision response - the part of the velocity going into a wall gets reversed and halved
+    /// and so does the turn rate when turning into one.
+    /// Otherwise vehicles stop moving and turning into walls and slide along them.
+    pub g_vehicle_wall_bounce: bool,
+    /// Fraction of the speed along a wall lost per second while sliding along it.
+    pub g_vehicle_wall_friction: f64,
+
+    /// Drift of vehicles on water in pixels per second, added to their own velocity.
+    pub g_water_current_x: f64,
+    pub g_water_current_y: f64,
+
+    /// Projectiles flying in an arc (see `g_weapon_arc_gravity`) also explode when their timer
+    /// (e.g. `g_cluster_bomb_time`) runs out before they land. Otherwise only landing sets them off.
+    pub g_weapon_arc_fuse: bool,
+    /// Weapons using `FireLimiter::Heat` stay locked out after overheating until they cool below this.
+    pub g_weapon_heat_unlock: f64,
+    /// Sample all weapon spread (see `g_projectile_params`) from a normal distribution
+    /// with the spread as its standard deviation, otherwise uniformly between minus and plus the spread.
+    pub g_weapon_spread_gaussian: bool,
+
+    /// Acceleration of rockets, missiles and cluster bombs in pixels per second squared.
+    ///
+    /// Fast weapons which behave like hitscan (MG, railgun, BFG) aren't affected.
+    pub g_wind_x: f64,
+    pub g_wind_y: f64,
+
+    /// Master switch for the screen-space HUD (score, bars, weapon, minimap, ...).
+    pub hud: bool,
+
     pub hud_ammo_x: f64,
     pub hud_ammo_y: f64,
     /// Original RecWar had 99.
@@ -331,6 +907,18 @@ pub struct Cvars {
     /// Original RecWar had 4.
     pub hud_ammo_height: f64,
 
+    pub hud_armor: bool,
+    pub hud_armor_x: f64,
+    pub hud_armor_y: f64,
+    pub hud_armor_size: f64,
+
+    /// Red flash at the view's edges when the player takes damage, fading out over this many seconds.
+    pub hud_damage_flash_duration: f64,
+    /// Initial alpha per HP of damage, up to `hud_damage_flash_max`.
+    pub hud_damage_flash_scale: f64,
+    pub hud_damage_flash_max: f64,
+    pub hud_damage_flash_width: f64,
+
     pub hud_hp_x: f64,
     pub hud_hp_y: f64,
     /// Original RecWar had 99.
@@ -338,16 +926,48 @@ pub struct Cvars {
     /// Original RecWar had 9.
     pub hud_hp_height: f64,
 
+    pub hud_kill_feed: bool,
+    /// How long each line stays, in seconds.
+    pub hud_kill_feed_duration: f64,
+    pub hud_kill_feed_font_size: f64,
+    pub hud_kill_feed_line_height: f64,
+    /// Older lines are removed when there are more.
+    pub hud_kill_feed_lines: usize,
+    pub hud_kill_feed_x: f64,
+    pub hud_kill_feed_y: f64,
+
     pub hud_names: bool,
     pub hud_names_alpha: f64,
     pub hud_names_brightness: f64,
     pub hud_names_font_size: f64,
+    /// Longer names are cut off with "...".
+    pub hud_names_max_width: f32,
+    /// Also show the local player's own name.
+    pub hud_names_self: bool,
     pub hud_names_shadow_alpha: f64,
     pub hud_names_shadow_x: f32,
     pub hud_names_shadow_y: f32,
     pub hud_names_x: f64,
     pub hud_names_y: f64,
 
+    /// Countdown and victory screen, see `sys_match`.
+    pub hud_match_font_size: f64,
+    /// How long "GO!" stays on screen after the countdown.
+    pub hud_match_go_duration: f64,
+    /// Relative to the center of the viewport.
+    pub hud_match_y: f64,
+
+    pub hud_minimap: bool,
+    pub hud_minimap_alpha: f64,
+    pub hud_minimap_blip_size: f64,
+    /// Own guided missile blinks with this period in seconds.
+    pub hud_minimap_gm_blink_period: f64,
+    /// The minimap is fit into this area keeping the map's aspect ratio.
+    pub hud_minimap_height: f64,
+    pub hud_minimap_width: f64,
+    pub hud_minimap_x: f64,
+    pub hud_minimap_y: f64,
+
     pub hud_missile_indicator_dash_length: f64,
     pub hud_missile_indicator_radius: f64,
 
@@ -355,6 +975,19 @@ pub struct Cvars {
     pub hud_pause_shadow_x: f32,
     pub hud_pause_shadow_y: f32,
 
+    /// Icons of active powerups with a bar showing the time left.
+    pub hud_powerups: bool,
+    pub hud_powerups_size: f64,
+    pub hud_powerups_spacing: f64,
+    pub hud_powerups_x: f64,
+    pub hud_powerups_y: f64,
+
+    /// Training range statistics, see `sys_range`.
+    pub hud_range_font_size: f64,
+    pub hud_range_line_height: f64,
+    pub hud_range_x: f64,
+    pub hud_range_y: f64,
+
     pub hud_ranking_font_size: f64,
     /// Original RW uses 1
     pub hud_ranking_shadow_x: f32,
@@ -363,6 +996,20 @@ pub struct Cvars {
     pub hud_ranking_x: f64,
     pub hud_ranking_y: f64,
 
+    /// Circle around the weapon icon which fills up while reloading.
+    /// Text shown to the attacker after hitting a vehicle in the rear.
+    pub hud_rear_hit: bool,
+    pub hud_rear_hit_duration: f64,
+    pub hud_rear_hit_font_size: f64,
+    /// Offset of the text's center from the view's center.
+    pub hud_rear_hit_x: f64,
+    pub hud_rear_hit_y: f64,
+
+    pub hud_reload_indicator: bool,
+    pub hud_reload_indicator_radius: f64,
+    pub hud_reload_indicator_segments: u32,
+    pub hud_reload_indicator_thickness: f64,
+
     pub hud_score_font_size: f64,
     /// Original RW uses 2
     pub hud_score_shadow_x: f32,
@@ -381,26 +1028,85 @@ pub struct Cvars {
     pub hud_scoreboard_width_name: f32,
     pub hud_scoreboard_width_points: f32,
 
+    /// "SPECTATING <name>" while following someone else after death.
+    pub hud_spectating_font_size: f64,
+    pub hud_spectating_y: f64,
+
+    /// Current speed in px/s.
+    pub hud_speed: bool,
+    pub hud_speed_font_size: f64,
+    pub hud_speed_shadow_x: f32,
+    pub hud_speed_shadow_y: f32,
+    pub hud_speed_x: f64,
+    pub hud_speed_y: f64,
+
     pub hud_weapon_icon_shadow_alpha: f64,
     pub hud_weapon_icon_shadow_x: f32,
     pub hud_weapon_icon_shadow_y: f32,
     pub hud_weapon_icon_x: f64,
     pub hud_weapon_icon_y: f64,
 
+    /// Weapon name and ammo, remaining time when controlling a guided missile.
+    pub hud_weapon_name: bool,
+    pub hud_weapon_name_font_size: f64,
+    pub hud_weapon_name_shadow_x: f32,
+    pub hud_weapon_name_shadow_y: f32,
+    pub hud_weapon_name_x: f64,
+    pub hud_weapon_name_y: f64,
+
+    /// Arrow showing the direction and strength of the wind, hidden when there's none.
+    /// The position is its center, by default under the minimap.
+    pub hud_wind: bool,
+    /// Arrow length per unit of wind acceleration.
+    pub hud_wind_scale: f64,
+    pub hud_wind_x: f64,
+    pub hud_wind_y: f64,
+
     /// This is in a way the opposite of smoothing
     pub r_align_to_pixels_background: bool,
     pub r_align_to_pixels_text: bool,
     pub r_draw_cluster_bombs: bool,
-    pub r_explosion_duration: f64,
     pub r_explosions_reverse_order: bool,
+    /// Show a camera flyby of the map before the round starts. Any key skips it.
+    pub r_round_intro: bool,
+    pub r_round_intro_duration: f64,
+    /// The round starts after this many seconds even if some local players haven't finished the intro.
+    pub r_round_intro_max_wait: f64,
     pub r_smoothing: bool,
     pub r_splitscreen_gap: f64,
+    /// Color of the trail's newest end, it fades out towards the oldest, e.g. `1 0.9 0.5 0.8`.
+    pub r_trail_machine_gun_color: Rgba,
+    /// How many of the last positions (one per gamelogic frame) the trail goes through,
+    /// at most `TRAIL_CAPACITY`, 0 disables it.
+    pub r_trail_machine_gun_length: usize,
+    pub r_trail_machine_gun_width: f64,
+    pub r_trail_rockets_color: Rgba,
+    pub r_trail_rockets_length: usize,
+    pub r_trail_rockets_width: f64,
+    /// Recolor vehicles by team, or by player color outside team modes.
+    pub r_vehicle_colors: bool,
 
     /// LATER fix - Does not work in MQ: https://github.com/not-fl3/macroquad/issues/264
     pub sv_auto_pause_on_minimize: bool,
     /// LATER fix - Does not work in MQ: https://github.com/not-fl3/macroquad/issues/264
     pub sv_auto_unpause_on_restore: bool,
 
+    /// Size of cells in the broad-phase grid used for projectile-vehicle collisions.
+    pub sv_collision_grid_cell_size: f64,
+
+    /// Time per frame spent building derived map data (nav grid, minimap) after a map loads.
+    pub sv_derive_budget_ms: f64,
+
+    /// MG and BFG beam hits closer than this (in seconds) are merged in life records.
+    pub sv_life_record_burst_gap: f64,
+    /// Max entries per life in life records.
+    pub sv_life_record_entries_max: usize,
+    /// Max completed lives per player in life records.
+    pub sv_life_records_max: usize,
+
+    /// Max number of particles, new ones aren't emitted while it's full.
+    pub sv_particles_max: usize,
+
     pub sv_tickrate_mode: TickrateMode,
     pub sv_tickrate_fixed_fps: f64,
 }
@@ -411,186 +1117,138 @@ impl Cvars {
         Self::default()
     }
 
-    /// Create a new Cvars object with an approximation of the original RecWar settings.
-    #[allow(dead_code)] // LATER allow using balance presets again
-    pub fn new_rec_war() -> Self {
-        Self {
-            // This is 15625 tiles - should be more than enough, biggest original maps have 59.
-            // Can't use infinity - it would break the math.
-            g_railgun_speed: 1_000_000.0,
-            ..Self::default()
+    /// Set cvars from alternating names and values, e.g. from the command line.
+    ///
+    /// Stops at the first invalid pair, the error says which one and why.
+    pub fn set_pairs(&mut self, args: &[String]) -> Result<(), String> {
+        for pair in args.chunks(2) {
+            let name = &pair[0];
+            let value = pair
+                .get(1)
+                .ok_or_else(|| format!("cvar {} has no value", name))?;
+            self.set_str(name, value)
+                .map_err(|err| format!("can't set cvar {} to {}: {}", name, value, err))?;
         }
+        Ok(())
+    }
+
+    /// Reset all cvars to the default RecWars settings.
+    pub fn reset(&mut self) {
+        *self = Self::new_rec_wars();
     }
 
-    /// Reset this Cvars object to the default RecWars settings.
+    /// All cvars as name-value pairs, values in the format `set_str` accepts.
     ///
-    /// LATER Allow calling this from the console to change settings on the fly.
-    #[allow(dead_code)]
-    pub fn load_rec_wars(&mut self) {
-        *self = Self {
-            ..Self::new_rec_wars()
+    /// There's no way to list cvars so this relies on the derived `Debug` impl for the names.
+    pub fn to_pairs(&self) -> Vec<(String, String)> {
+        let text = format!("{:?}", self);
+        let fields = text
+            .trim_start_matches("Cvars {")
+            .trim_end_matches('}')
+            .trim();
+        fields
+            .split(", ")
+            .map(|field| {
+                let (name, _) = field.split_once(": ").unwrap();
+                let value = self.get_string(name).unwrap();
+                (name.to_owned(), value)
+            })
+            .collect()
+    }
+
+    /// Cvars which differ from the defaults, one `name value` per line so presets are easy to diff.
+    pub fn export(&self) -> String {
+        let defaults = Self::default().to_pairs();
+        let mut text = String::new();
+        for ((name, value), (_, default)) in self.to_pairs().into_iter().zip(defaults) {
+            if value != default {
+                text.push_str(&format!("{} {}\n", name, value));
+            }
         }
+        text
     }
 
-    /// Reset this Cvars object to an approximation of the original RecWar settings.
+    /// Apply a preset in the `export` format, returns how many cvars were set.
     ///
-    /// LATER Allow calling this from the console to change settings on the fly.
-    #[allow(dead_code)]
-    pub fn load_rec_war(&mut self) {
-        *self = Self {
-            ..Self::new_rec_war()
+    /// Empty lines and lines starting with `//` are skipped.
+    /// Invalid lines don't stop the rest from being applied, the error lists all of them.
+    pub fn import(&mut self, text: &str) -> Result<u32, String> {
+        let mut count = 0;
+        let mut errors = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            let (name, value) = match line.split_once(char::is_whitespace) {
+                Some((name, value)) => (name, value.trim()),
+                None => {
+                    errors.push(format!("line {}: cvar {} has no value", i + 1, line));
+                    continue;
+                }
+            };
+            match self.set_str(name, value) {
+                Ok(()) => count += 1,
+                Err(err) => errors.push(format!(
+                    "line {}: can't set cvar {} to {}: {}",
+                    i + 1,
+                    name,
+                    value,
+                    err
+                )),
+            }
+        }
+        if errors.is_empty() {
+            Ok(count)
+        } else {
+            Err(format!(
+                "set {} cvars, {} failed:\n{}",
+                count,
+                errors.len(),
+                errors.join("\n")
+            ))
         }
     }
 
-    /// Returns whether the weapon is on the chassis or turret and where relative to that part's center.
-    pub fn g_hardpoint(&self, veh_type: VehicleType, weapon: Weapon) -> (Hardpoint, Vec2f) {
+    /// Reset to defaults and apply one of `PRESETS`, returns how many cvars were set.
+    pub fn load_preset(&mut self, name: &str) -> Result<u32, String> {
+        let (_, text) = PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .ok_or_else(|| format!("no preset named {}", name))?;
+        self.reset();
+        self.import(text)
+    }
+
+    /// The barrels of the weapon - whether each is on the chassis or turret and where relative to that part's center.
+    pub fn g_hardpoints(&self, veh_type: VehicleType, weapon: Weapon) -> &Hardpoints {
         match veh_type {
             VehicleType::Tank => match weapon {
-                Weapon::Mg => (
-                    self.g_hardpoint_tank_machine_gun,
-                    Vec2f::new(
-                        self.g_hardpoint_tank_machine_gun_x,
-                        self.g_hardpoint_tank_machine_gun_y,
-                    ),
-                ),
-                Weapon::Rail => (
-                    self.g_hardpoint_tank_railgun,
-                    Vec2f::new(
-                        self.g_hardpoint_tank_railgun_x,
-                        self.g_hardpoint_tank_railgun_y,
-                    ),
-                ),
-                Weapon::Cb => (
-                    self.g_hardpoint_tank_cluster_bomb,
-                    Vec2f::new(
-                        self.g_hardpoint_tank_cluster_bomb_x,
-                        self.g_hardpoint_tank_cluster_bomb_y,
-                    ),
-                ),
-                Weapon::Rockets => (
-                    self.g_hardpoint_tank_rockets,
-                    Vec2f::new(
-                        self.g_hardpoint_tank_rockets_x,
-                        self.g_hardpoint_tank_rockets_y,
-                    ),
-                ),
-                Weapon::Hm => (
-                    self.g_hardpoint_tank_homing_missile,
-                    Vec2f::new(
-                        self.g_hardpoint_tank_homing_missile_x,
-                        self.g_hardpoint_tank_homing_missile_y,
-                    ),
-                ),
-                Weapon::Gm => (
-                    self.g_hardpoint_tank_guided_missile,
-                    Vec2f::new(
-                        self.g_hardpoint_tank_guided_missile_x,
-                        self.g_hardpoint_tank_guided_missile_y,
-                    ),
-                ),
-                Weapon::Bfg => (
-                    self.g_hardpoint_tank_bfg,
-                    Vec2f::new(self.g_hardpoint_tank_bfg_x, self.g_hardpoint_tank_bfg_y),
-                ),
+                Weapon::Mg => &self.g_hardpoint_tank_machine_gun,
+                Weapon::Rail => &self.g_hardpoint_tank_railgun,
+                Weapon::Cb => &self.g_hardpoint_tank_cluster_bomb,
+                Weapon::Rockets => &self.g_hardpoint_tank_rockets,
+                Weapon::Hm => &self.g_hardpoint_tank_homing_missile,
+                Weapon::Gm => &self.g_hardpoint_tank_guided_missile,
+                Weapon::Bfg => &self.g_hardpoint_tank_bfg,
             },
             VehicleType::Hovercraft => match weapon {
-                Weapon::Mg => (
-                    self.g_hardpoint_hovercraft_machine_gun,
-                    Vec2f::new(
-                        self.g_hardpoint_hovercraft_machine_gun_x,
-                        self.g_hardpoint_hovercraft_machine_gun_y,
-                    ),
-                ),
-                Weapon::Rail => (
-                    self.g_hardpoint_hovercraft_railgun,
-                    Vec2f::new(
-                        self.g_hardpoint_hovercraft_railgun_x,
-                        self.g_hardpoint_hovercraft_railgun_y,
-                    ),
-                ),
-                Weapon::Cb => (
-                    self.g_hardpoint_hovercraft_cluster_bomb,
-                    Vec2f::new(
-                        self.g_hardpoint_hovercraft_cluster_bomb_x,
-                        self.g_hardpoint_hovercraft_cluster_bomb_y,
-                    ),
-                ),
-                Weapon::Rockets => (
-                    self.g_hardpoint_hovercraft_rockets,
-                    Vec2f::new(
-                        self.g_hardpoint_hovercraft_rockets_x,
-                        self.g_hardpoint_hovercraft_rockets_y,
-                    ),
-                ),
-                Weapon::Hm => (
-                    self.g_hardpoint_hovercraft_homing_missile,
-                    Vec2f::new(
-                        self.g_hardpoint_hovercraft_homing_missile_x,
-                        self.g_hardpoint_hovercraft_homing_missile_y,
-                    ),
-                ),
-                Weapon::Gm => (
-                    self.g_hardpoint_hovercraft_guided_missile,
-                    Vec2f::new(
-                        self.g_hardpoint_hovercraft_guided_missile_x,
-                        self.g_hardpoint_hovercraft_guided_missile_y,
-                    ),
-                ),
-                Weapon::Bfg => (
-                    self.g_hardpoint_hovercraft_bfg,
-                    Vec2f::new(
-                        self.g_hardpoint_hovercraft_bfg_x,
-                        self.g_hardpoint_hovercraft_bfg_y,
-                    ),
-                ),
+                Weapon::Mg => &self.g_hardpoint_hovercraft_machine_gun,
+                Weapon::Rail => &self.g_hardpoint_hovercraft_railgun,
+                Weapon::Cb => &self.g_hardpoint_hovercraft_cluster_bomb,
+                Weapon::Rockets => &self.g_hardpoint_hovercraft_rockets,
+                Weapon::Hm => &self.g_hardpoint_hovercraft_homing_missile,
+                Weapon::Gm => &self.g_hardpoint_hovercraft_guided_missile,
+                Weapon::Bfg => &self.g_hardpoint_hovercraft_bfg,
             },
             VehicleType::Hummer => match weapon {
-                Weapon::Mg => (
-                    self.g_hardpoint_hummer_machine_gun,
-                    Vec2f::new(
-                        self.g_hardpoint_hummer_machine_gun_x,
-                        self.g_hardpoint_hummer_machine_gun_y,
-                    ),
-                ),
-                Weapon::Rail => (
-                    self.g_hardpoint_hummer_railgun,
-                    Vec2f::new(
-                        self.g_hardpoint_hummer_railgun_x,
-                        self.g_hardpoint_hummer_railgun_y,
-                    ),
-                ),
-                Weapon::Cb => (
-                    self.g_hardpoint_hummer_cluster_bomb,
-                    Vec2f::new(
-                        self.g_hardpoint_hummer_cluster_bomb_x,
-                        self.g_hardpoint_hummer_cluster_bomb_y,
-                    ),
-                ),
-                Weapon::Rockets => (
-                    self.g_hardpoint_hummer_rockets,
-                    Vec2f::new(
-                        self.g_hardpoint_hummer_rockets_x,
-                        self.g_hardpoint_hummer_rockets_y,
-                    ),
-                ),
-                Weapon::Hm => (
-                    self.g_hardpoint_hummer_homing_missile,
-                    Vec2f::new(
-                        self.g_hardpoint_hummer_homing_missile_x,
-                        self.g_hardpoint_hummer_homing_missile_y,
-                    ),
-                ),
-                Weapon::Gm => (
-                    self.g_hardpoint_hummer_guided_missile,
-                    Vec2f::new(
-                        self.g_hardpoint_hummer_guided_missile_x,
-                        self.g_hardpoint_hummer_guided_missile_y,
-                    ),
-                ),
-                Weapon::Bfg => (
-                    self.g_hardpoint_hummer_bfg,
-                    Vec2f::new(self.g_hardpoint_hummer_bfg_x, self.g_hardpoint_hummer_bfg_y),
-                ),
+                Weapon::Mg => &self.g_hardpoint_hummer_machine_gun,
+                Weapon::Rail => &self.g_hardpoint_hummer_railgun,
+                Weapon::Cb => &self.g_hardpoint_hummer_cluster_bomb,
+                Weapon::Rockets => &self.g_hardpoint_hummer_rockets,
+                Weapon::Hm => &self.g_hardpoint_hummer_homing_missile,
+                Weapon::Gm => &self.g_hardpoint_hummer_guided_missile,
+                Weapon::Bfg => &self.g_hardpoint_hummer_bfg,
             },
         }
     }
@@ -612,6 +1270,23 @@ impl Cvars {
         }
     }
 
+    pub fn g_pickup_radius(&self, kind: PickupKind) -> f64 {
+        match kind {
+            PickupKind::Armor => self.g_armor_pickup_radius,
+            PickupKind::Ammo => self.g_ammo_crate_radius,
+            PickupKind::Repair => self.g_repair_pad_radius,
+            PickupKind::Powerup(_) => self.g_powerup_radius,
+        }
+    }
+
+    pub fn g_powerup_duration(&self, kind: PowerupKind) -> f64 {
+        match kind {
+            PowerupKind::SpeedBoost => self.g_powerup_speed_boost_duration,
+            PowerupKind::RapidFire => self.g_powerup_rapid_fire_duration,
+            PowerupKind::Shield => self.g_powerup_shield_duration,
+        }
+    }
+
     pub fn g_vehicle_hp(&self, veh_type: VehicleType) -> f64 {
         let scale = match veh_type {
             VehicleType::Tank => self.g_tank_armor_scale,
@@ -621,13 +1296,76 @@ impl Cvars {
         self.g_armor * scale
     }
 
-    pub fn g_vehicle_movement_stats(&self, veh_type: VehicleType) -> MovementStats {
+    pub fn g_vehicle_resistance(&self, veh_type: VehicleType, dmg_type: DamageType) -> f64 {
+        match (veh_type, dmg_type) {
+            (VehicleType::Tank, DamageType::Kinetic) => self.g_tank_resistance_kinetic,
+            (VehicleType::Tank, DamageType::Explosive) => self.g_tank_resistance_explosive,
+            (VehicleType::Tank, DamageType::Energy) => self.g_tank_resistance_energy,
+            (VehicleType::Hovercraft, DamageType::Kinetic) => self.g_hovercraft_resistance_kinetic,
+            (VehicleType::Hovercraft, DamageType::Explosive) => {
+                self.g_hovercraft_resistance_explosive
+            }
+            (VehicleType::Hovercraft, DamageType::Energy) => self.g_hovercraft_resistance_energy,
+            (VehicleType::Hummer, DamageType::Kinetic) => self.g_hummer_resistance_kinetic,
+            (VehicleType::Hummer, DamageType::Explosive) => self.g_hummer_resistance_explosive,
+            (VehicleType::Hummer, DamageType::Energy) => self.g_hummer_resistance_energy,
+            (_, DamageType::Environmental) => 1.0,
+        }
+    }
+
+    /// Relative, only the ratios matter when vehicles push each other, see `sys_ramming`.
+    pub fn g_vehicle_mass(&self, veh_type: VehicleType) -> f64 {
+        match veh_type {
+            VehicleType::Tank => self.g_tank_mass,
+            VehicleType::Hovercraft => self.g_hovercraft_mass,
+            VehicleType::Hummer => self.g_hummer_mass,
+        }
+    }
+
+    /// Armor multiplier of a new vehicle for hits to `facing`.
+    pub fn g_vehicle_facing(&self, veh_type: VehicleType, facing: Facing) -> f64 {
+        match (veh_type, facing) {
+            (_, Facing::Turret) => self.g_facing_turret,
+            (VehicleType::Tank, Facing::Front) => self.g_tank_facing_front,
+            (VehicleType::Tank, Facing::Side) => self.g_tank_facing_side,
+            (VehicleType::Tank, Facing::Rear) => self.g_tank_facing_rear,
+            (VehicleType::Hovercraft, Facing::Front) => self.g_hovercraft_facing_front,
+            (VehicleType::Hovercraft, Facing::Side) => self.g_hovercraft_facing_side,
+            (VehicleType::Hovercraft, Facing::Rear) => self.g_hovercraft_facing_rear,
+            (VehicleType::Hummer, Facing::Front) => self.g_hummer_facing_front,
+            (VehicleType::Hummer, Facing::Side) => self.g_hummer_facing_side,
+            (VehicleType::Hummer, Facing::Rear) => self.g_hummer_facing_rear,
+        }
+    }
+
+    /// Movement stats of `veh_type` on a surface of kind `surface`.
+    ///
+    /// Only hovercraft have different stats over water, other vehicles use their land stats everywhere
+    /// (and usually can't drive on water at all, see `water_passable`).
+    pub fn g_vehicle_movement_stats(&self, veh_type: VehicleType, surface: Kind) -> MovementStats {
+        let land = self.g_vehicle_land_stats(veh_type);
+        match (veh_type, surface) {
+            (VehicleType::Hovercraft, Kind::Water) => MovementStats {
+                friction_const: self.g_hovercraft_water_friction_const,
+                friction_linear: self.g_hovercraft_water_friction_linear,
+                lateral_friction_const: self.g_hovercraft_water_lateral_friction_const,
+                lateral_friction_linear: self.g_hovercraft_water_lateral_friction_linear,
+                turn_effectiveness: self.g_hovercraft_water_turn_effectiveness,
+                ..land
+            },
+            _ => land,
+        }
+    }
+
+    fn g_vehicle_land_stats(&self, veh_type: VehicleType) -> MovementStats {
         match veh_type {
             VehicleType::Tank => MovementStats {
                 accel_backward: self.g_tank_accel_backward,
                 accel_forward: self.g_tank_accel_forward,
                 friction_const: self.g_tank_friction_const,
                 friction_linear: self.g_tank_friction_linear,
+                lateral_friction_const: self.g_tank_lateral_friction_const,
+                lateral_friction_linear: self.g_tank_lateral_friction_linear,
                 speed_max: self.g_tank_speed_max,
                 steering_car: self.g_tank_steering_car,
                 turn_effectiveness: self.g_tank_turn_effectiveness,
@@ -635,12 +1373,15 @@ impl Cvars {
                 turn_rate_friction_linear: self.g_tank_turn_rate_friction_linear,
                 turn_rate_increase: self.g_tank_turn_rate_increase,
                 turn_rate_max: self.g_tank_turn_rate_max,
+                water_passable: self.g_tank_water_passable,
             },
             VehicleType::Hovercraft => MovementStats {
                 accel_backward: self.g_hovercraft_accel_backward,
                 accel_forward: self.g_hovercraft_accel_forward,
                 friction_const: self.g_hovercraft_friction_const,
                 friction_linear: self.g_hovercraft_friction_linear,
+                lateral_friction_const: self.g_hovercraft_lateral_friction_const,
+                lateral_friction_linear: self.g_hovercraft_lateral_friction_linear,
                 speed_max: self.g_hovercraft_speed_max,
                 steering_car: self.g_hovercraft_steering_car,
                 turn_effectiveness: self.g_hovercraft_turn_effectiveness,
@@ -648,12 +1389,15 @@ impl Cvars {
                 turn_rate_friction_linear: self.g_hovercraft_turn_rate_friction_linear,
                 turn_rate_increase: self.g_hovercraft_turn_rate_increase,
                 turn_rate_max: self.g_hovercraft_turn_rate_max,
+                water_passable: self.g_hovercraft_water_passable,
             },
             VehicleType::Hummer => MovementStats {
                 accel_backward: self.g_hummer_accel_backward,
                 accel_forward: self.g_hummer_accel_forward,
                 friction_const: self.g_hummer_friction_const,
                 friction_linear: self.g_hummer_friction_linear,
+                lateral_friction_const: self.g_hummer_lateral_friction_const,
+                lateral_friction_linear: self.g_hummer_lateral_friction_linear,
                 speed_max: self.g_hummer_speed_max,
                 steering_car: self.g_hummer_steering_car,
                 turn_effectiveness: self.g_hummer_turn_effectiveness,
@@ -661,10 +1405,48 @@ impl Cvars {
                 turn_rate_friction_linear: self.g_hummer_turn_rate_friction_linear,
                 turn_rate_increase: self.g_hummer_turn_rate_increase,
                 turn_rate_max: self.g_hummer_turn_rate_max,
+                water_passable: self.g_hummer_water_passable,
             },
         }
     }
 
+    /// Weapons the vehicle type carries, see `g_hovercraft_weapons`.
+    pub fn g_vehicle_weapons(&self, veh_type: VehicleType) -> Vec<Weapon> {
+        let mask = match veh_type {
+            VehicleType::Tank => self.g_tank_weapons,
+            VehicleType::Hovercraft => self.g_hovercraft_weapons,
+            VehicleType::Hummer => self.g_hummer_weapons,
+        };
+        Weapon::all()
+            .filter(|&weapon| mask & (1 << weapon as u32) != 0)
+            .collect()
+    }
+
+    /// Min and max turret angle relative to the chassis in radians, `None` if it can rotate freely.
+    ///
+    /// The allowed range can't include the angle directly behind the vehicle.
+    pub fn g_vehicle_turret_limits(&self, veh_type: VehicleType) -> Option<(f64, f64)> {
+        let (min, max) = match veh_type {
+            VehicleType::Tank => (
+                self.g_tank_turret_angle_min_deg,
+                self.g_tank_turret_angle_max_deg,
+            ),
+            VehicleType::Hovercraft => (
+                self.g_hovercraft_turret_angle_min_deg,
+                self.g_hovercraft_turret_angle_max_deg,
+            ),
+            VehicleType::Hummer => (
+                self.g_hummer_turret_angle_min_deg,
+                self.g_hummer_turret_angle_max_deg,
+            ),
+        };
+        if min <= -180.0 && max >= 180.0 {
+            None
+        } else {
+            Some((min.to_radians(), max.to_radians()))
+        }
+    }
+
     /// Where the turret-chassis connection is on the chassis.
     /// E.g. (0, 0) means the turret rotates around the vehicle's origin.
     pub fn g_vehicle_turret_offset_chassis(&self, veh_type: VehicleType) -> Vec2f {
@@ -715,6 +1497,31 @@ impl Cvars {
         }
     }
 
+    /// Can players use this weapon in the current match.
+    pub fn g_weapon_allowed(&self, weapon: Weapon) -> bool {
+        match weapon {
+            Weapon::Mg => self.g_machine_gun_allowed,
+            Weapon::Rail => self.g_railgun_allowed,
+            Weapon::Cb => self.g_cluster_bomb_allowed,
+            Weapon::Rockets => self.g_rockets_allowed,
+            Weapon::Hm => self.g_homing_missile_allowed,
+            Weapon::Gm => self.g_guided_missile_allowed,
+            Weapon::Bfg => self.g_bfg_allowed,
+        }
+    }
+
+    pub fn g_weapon_damage_type(&self, weapon: Weapon) -> DamageType {
+        match weapon {
+            Weapon::Mg => self.g_machine_gun_damage_type,
+            Weapon::Rail => self.g_railgun_damage_type,
+            Weapon::Cb => self.g_cluster_bomb_damage_type,
+            Weapon::Rockets => self.g_rockets_damage_type,
+            Weapon::Hm => self.g_homing_missile_damage_type,
+            Weapon::Gm => self.g_guided_missile_damage_type,
+            Weapon::Bfg => self.g_bfg_damage_type,
+        }
+    }
+
     pub fn g_weapon_explosion_damage(&self, weapon: Weapon) -> f64 {
         match weapon {
             Weapon::Mg => 0.0,
@@ -727,6 +1534,31 @@ impl Cvars {
         }
     }
 
+    /// Weapons with gravity fly in an arc over vehicles and only hit them after landing.
+    pub fn g_weapon_arc_gravity(&self, weapon: Weapon) -> f64 {
+        match weapon {
+            Weapon::Mg => self.g_machine_gun_arc_gravity,
+            Weapon::Rail => self.g_railgun_arc_gravity,
+            Weapon::Cb => self.g_cluster_bomb_arc_gravity,
+            Weapon::Rockets => self.g_rockets_arc_gravity,
+            Weapon::Hm => self.g_homing_missile_arc_gravity,
+            Weapon::Gm => self.g_guided_missile_arc_gravity,
+            Weapon::Bfg => self.g_bfg_arc_gravity,
+        }
+    }
+
+    pub fn g_weapon_arc_speed(&self, weapon: Weapon) -> f64 {
+        match weapon {
+            Weapon::Mg => self.g_machine_gun_arc_speed,
+            Weapon::Rail => self.g_railgun_arc_speed,
+            Weapon::Cb => self.g_cluster_bomb_arc_speed,
+            Weapon::Rockets => self.g_rockets_arc_speed,
+            Weapon::Hm => self.g_homing_missile_arc_speed,
+            Weapon::Gm => self.g_guided_missile_arc_speed,
+            Weapon::Bfg => self.g_bfg_arc_speed,
+        }
+    }
+
     pub fn g_weapon_explosion_radius(&self, weapon: Weapon) -> f64 {
         match weapon {
             Weapon::Mg => 0.0,
@@ -757,6 +1589,8 @@ impl Cvars {
             accel_forward: self.g_guided_missile_accel_forward,
             friction_const: self.g_guided_missile_friction_const,
             friction_linear: self.g_guided_missile_friction_linear,
+            lateral_friction_const: self.g_guided_missile_lateral_friction_const,
+            lateral_friction_linear: self.g_guided_missile_lateral_friction_linear,
             speed_max: self.g_guided_missile_speed_max,
             steering_car: 0.0,
             turn_effectiveness: self.g_guided_missile_turn_effectiveness,
@@ -764,6 +1598,42 @@ impl Cvars {
             turn_rate_friction_linear: self.g_guided_missile_turn_rate_friction_linear,
             turn_rate_increase: self.g_guided_missile_turn_rate_increase,
             turn_rate_max: self.g_guided_missile_turn_rate_max,
+            water_passable: true,
+        }
+    }
+
+    pub fn g_weapon_cooldown_group(&self, weapon: Weapon) -> CooldownGroup {
+        match weapon {
+            Weapon::Mg => self.g_machine_gun_cooldown_group,
+            Weapon::Rail => self.g_railgun_cooldown_group,
+            Weapon::Cb => self.g_cluster_bomb_cooldown_group,
+            Weapon::Rockets => self.g_rockets_cooldown_group,
+            Weapon::Hm => self.g_homing_missile_cooldown_group,
+            Weapon::Gm => self.g_guided_missile_cooldown_group,
+            Weapon::Bfg => self.g_bfg_cooldown_group,
+        }
+    }
+
+    /// Shared cooldown of the group, `None` has none.
+    pub fn g_cooldown_group(&self, group: CooldownGroup) -> f64 {
+        match group {
+            CooldownGroup::None => 0.0,
+            CooldownGroup::Explosive => self.g_cooldown_group_explosive,
+            CooldownGroup::Energy => self.g_cooldown_group_energy,
+            CooldownGroup::Heavy => self.g_cooldown_group_heavy,
+        }
+    }
+
+    /// Projectiles which haven't hit anything by this many seconds after being fired are removed.
+    pub fn g_weapon_lifetime(&self, weapon: Weapon) -> f64 {
+        match weapon {
+            Weapon::Mg => self.g_machine_gun_lifetime,
+            Weapon::Rail => self.g_railgun_lifetime,
+            Weapon::Cb => self.g_cluster_bomb_lifetime,
+            Weapon::Rockets => self.g_rockets_lifetime,
+            Weapon::Hm => self.g_homing_missile_lifetime,
+            Weapon::Gm => self.g_guided_missile_lifetime,
+            Weapon::Bfg => self.g_bfg_lifetime,
         }
     }
 
@@ -791,6 +1661,40 @@ impl Cvars {
         }
     }
 
+    pub fn cl_flyby_distance(&self, weapon: Weapon) -> f64 {
+        match weapon {
+            Weapon::Mg => self.cl_flyby_distance_machine_gun,
+            Weapon::Rail => self.cl_flyby_distance_railgun,
+            Weapon::Cb => self.cl_flyby_distance_cluster_bomb,
+            Weapon::Rockets => self.cl_flyby_distance_rockets,
+            Weapon::Hm => self.cl_flyby_distance_homing_missile,
+            Weapon::Gm => self.cl_flyby_distance_guided_missile,
+            Weapon::Bfg => self.cl_flyby_distance_bfg,
+        }
+    }
+
+    /// Initial and maximum reserve ammo, `RESERVE_UNLIMITED` if negative.
+    pub fn g_weapon_reserve(&self, weapon: Weapon, veh_type: VehicleType) -> u32 {
+        let reserve = match weapon {
+            Weapon::Mg => self.g_machine_gun_reserve,
+            Weapon::Rail => self.g_railgun_reserve,
+            Weapon::Cb => self.g_cluster_bomb_reserve,
+            Weapon::Rockets => self.g_rockets_reserve,
+            Weapon::Hm => self.g_homing_missile_reserve,
+            Weapon::Gm => self.g_guided_missile_reserve,
+            Weapon::Bfg => self.g_bfg_reserve,
+        };
+        if reserve < 0 {
+            return RESERVE_UNLIMITED;
+        }
+        let scale = match veh_type {
+            VehicleType::Tank => self.g_tank_reserve_scale,
+            VehicleType::Hovercraft => self.g_hovercraft_reserve_scale,
+            VehicleType::Hummer => self.g_hummer_reserve_scale,
+        };
+        (f64::from(reserve) * scale).round() as u32
+    }
+
     pub fn g_weapon_reload_time(&self, weapon: Weapon) -> f64 {
         match weapon {
             Weapon::Mg => self.g_machine_gun_reload_time,
@@ -802,23 +1706,264 @@ impl Cvars {
             Weapon::Bfg => self.g_bfg_reload_time,
         }
     }
+
+    pub fn g_weapon_fire_limiter(&self, weapon: Weapon) -> FireLimiter {
+        match weapon {
+            Weapon::Mg => self.g_machine_gun_fire_limiter,
+            Weapon::Rail => self.g_railgun_fire_limiter,
+            Weapon::Cb => self.g_cluster_bomb_fire_limiter,
+            Weapon::Rockets => self.g_rockets_fire_limiter,
+            Weapon::Hm => self.g_homing_missile_fire_limiter,
+            Weapon::Gm => self.g_guided_missile_fire_limiter,
+            Weapon::Bfg => self.g_bfg_fire_limiter,
+        }
+    }
+
+    pub fn g_weapon_heat_dissipation(&self, weapon: Weapon) -> f64 {
+        match weapon {
+            Weapon::Mg => self.g_machine_gun_heat_dissipation,
+            Weapon::Rail => self.g_railgun_heat_dissipation,
+            Weapon::Cb => self.g_cluster_bomb_heat_dissipation,
+            Weapon::Rockets => self.g_rockets_heat_dissipation,
+            Weapon::Hm => self.g_homing_missile_heat_dissipation,
+            Weapon::Gm => self.g_guided_missile_heat_dissipation,
+            Weapon::Bfg => self.g_bfg_heat_dissipation,
+        }
+    }
+
+    pub fn g_weapon_heat_per_shot(&self, weapon: Weapon) -> f64 {
+        match weapon {
+            Weapon::Mg => self.g_machine_gun_heat_per_shot,
+            Weapon::Rail => self.g_railgun_heat_per_shot,
+            Weapon::Cb => self.g_cluster_bomb_heat_per_shot,
+            Weapon::Rockets => self.g_rockets_heat_per_shot,
+            Weapon::Hm => self.g_homing_missile_heat_per_shot,
+            Weapon::Gm => self.g_guided_missile_heat_per_shot,
+            Weapon::Bfg => self.g_bfg_heat_per_shot,
+        }
+    }
+
+    /// Initial speed of the weapon's projectiles relative to the vehicle.
+    pub fn g_weapon_speed(&self, weapon: Weapon) -> f64 {
+        match weapon {
+            Weapon::Mg => self.g_machine_gun_speed,
+            Weapon::Rail => self.g_railgun_speed,
+            Weapon::Cb => self.g_cluster_bomb_speed,
+            Weapon::Rockets => self.g_rockets_speed,
+            Weapon::Hm => self.g_homing_missile_speed_initial,
+            Weapon::Gm => self.g_guided_missile_speed_initial,
+            Weapon::Bfg => self.g_bfg_speed,
+        }
+    }
+
+    /// How a shot of `weapon` spawns its projectiles, see `systems::shooting`.
+    pub fn g_projectile_params(&self, weapon: Weapon) -> ProjectileSpawnParams {
+        let no_spread = ProjectileSpawnParams {
+            speed: self.g_weapon_speed(weapon),
+            angular_spread: 0.0,
+            forward_spread: 0.0,
+            sideways_spread: 0.0,
+            velocity_factor: self.g_weapon_vehicle_velocity_factor(weapon),
+            count: 1,
+            fuse: f64::INFINITY,
+            fuse_spread: 0.0,
+        };
+        match weapon {
+            Weapon::Mg => ProjectileSpawnParams {
+                angular_spread: self.g_machine_gun_angle_spread,
+                ..no_spread
+            },
+            Weapon::Rail => ProjectileSpawnParams {
+                angular_spread: self.g_railgun_angle_spread,
+                ..no_spread
+            },
+            Weapon::Cb => ProjectileSpawnParams {
+                angular_spread: self.g_cluster_bomb_angle_spread,
+                forward_spread: self.g_cluster_bomb_speed_spread_forward,
+                sideways_spread: self.g_cluster_bomb_speed_spread_sideways,
+                count: self.g_cluster_bomb_count.max(0) as u32,
+                fuse: self.g_cluster_bomb_time,
+                fuse_spread: self.g_cluster_bomb_time_spread,
+                ..no_spread
+            },
+            Weapon::Rockets => ProjectileSpawnParams {
+                angular_spread: self.g_rockets_angle_spread,
+                ..no_spread
+            },
+            Weapon::Hm => ProjectileSpawnParams {
+                angular_spread: self.g_homing_missile_angle_spread,
+                ..no_spread
+            },
+            Weapon::Gm => ProjectileSpawnParams {
+                angular_spread: self.g_guided_missile_angle_spread,
+                fuse: self.g_guided_missile_time,
+                ..no_spread
+            },
+            Weapon::Bfg => ProjectileSpawnParams {
+                angular_spread: self.g_bfg_angle_spread,
+                ..no_spread
+            },
+        }
+    }
+
+    pub fn g_weapon_vehicle_velocity_factor(&self, weapon: Weapon) -> f64 {
+        match weapon {
+            Weapon::Mg => self.g_machine_gun_vehicle_velocity_factor,
+            Weapon::Rail => self.g_railgun_vehicle_velocity_factor,
+            Weapon::Cb => self.g_cluster_bomb_vehicle_velocity_factor,
+            Weapon::Rockets => self.g_rockets_vehicle_velocity_factor,
+            Weapon::Hm => self.g_homing_missile_vehicle_velocity_factor,
+            Weapon::Gm => self.g_guided_missile_vehicle_velocity_factor,
+            Weapon::Bfg => self.g_bfg_vehicle_velocity_factor,
+        }
+    }
+
+    /// Length, width and color of the weapon's trail, `None` if it doesn't have one.
+    pub fn r_trail(&self, weapon: Weapon) -> Option<(usize, f64, Rgba)> {
+        let (length, width, color) = match weapon {
+            Weapon::Mg => (
+                self.r_trail_machine_gun_length,
+                self.r_trail_machine_gun_width,
+                self.r_trail_machine_gun_color,
+            ),
+            Weapon::Rockets => (
+                self.r_trail_rockets_length,
+                self.r_trail_rockets_width,
+                self.r_trail_rockets_color,
+            ),
+            _ => return None,
+        };
+        if length == 0 {
+            None
+        } else {
+            Some((length.min(TRAIL_CAPACITY), width, color))
+        }
+    }
 }
 
+/// Built-in presets for `Cvars::load_preset`, in the `Cvars::export` format.
+pub const PRESETS: [(&str, &str); 2] = [
+    (
+        "classic",
+        "\
+// An approximation of the original RecWar.
+// This is 15625 tiles - should be more than enough, biggest original maps have 59.
+// Can't use infinity - it would break the math.
+g_railgun_speed 1000000
+",
+    ),
+    (
+        "arcade",
+        "\
+// Faster vehicles, quicker reloads, longer powerups and no waiting to respawn.
+g_armor_pickup_count 4
+g_hovercraft_accel_forward 600
+g_hummer_accel_forward 900
+g_machine_gun_reload_time 0.5
+g_powerup_rapid_fire_duration 15
+g_powerup_speed_boost_duration 15
+g_respawn_delay 0
+g_rockets_reload_time 0.75
+g_tank_accel_forward 825
+",
+    ),
+];
+
 impl Default for Cvars {
     fn default() -> Self {
         Self {
             ai: true,
 
+            bots_aim_error: 0.0,
+            bots_cover_hp: 0.3,
+            bots_cover_radius: 256.0,
+            bots_difficulty: 2,
+            bots_fire_alignment: 0.15,
+            bots_fire_range: 700.0,
             bots_max: 20,
+            bots_railgun_difficulty: 2,
+            bots_reaction_time: 0.0,
+            bots_vision_range: 900.0,
+
+            cl_arc_scale: 0.01,
+            cl_audio_events_max: 256,
+
+            cl_bfg_beam_duration: 0.0,
+            cl_bitmap_font: true,
 
             cl_cluster_bomb_size: 1.5,
+            cl_crosshair: true,
+            cl_crosshair_alpha: 0.5,
+            cl_crosshair_dots: 12,
+            cl_crosshair_dot_interval: 0.05,
+            cl_crosshair_dot_size: 1.5,
+            cl_crosshair_length: 60.0,
+            cl_crosshair_marker_size: 5.0,
+            cl_crosshair_thickness: 1.0,
+
+            cl_decals_max: 1024,
+            cl_decals_fade: 0.25,
+            cl_debris_size: 3.0,
+
+            cl_flare_size: 4.0,
+
+            cl_flyby_distance_bfg: 150.0,
+            cl_flyby_distance_cluster_bomb: 0.0,
+            cl_flyby_distance_guided_missile: 150.0,
+            cl_flyby_distance_homing_missile: 150.0,
+            cl_flyby_distance_machine_gun: 40.0,
+            cl_flyby_distance_railgun: 60.0,
+            cl_flyby_distance_rockets: 200.0,
+            cl_flyby_max_per_frame: 4,
+            cl_flyby_speed_min: 300.0,
+
+            cl_hit_stop: true,
+            cl_hit_stop_duration: 0.15,
+            cl_hit_stop_ramp: 0.15,
+            cl_hit_stop_scale: 0.2,
+
+            cl_interp: 0.1,
+            cl_interp_extrapolate_max: 0.25,
+            cl_interp_snap_distance: 200.0,
 
             cl_machine_gun_trail_length: 10.0,
             cl_machine_gun_trail_thickness: 1.5,
 
+            cl_map_editor: false,
+
+            cl_mouse_joystick: false,
+            cl_mouse_joystick_radius: 200.0,
+
+            cl_muzzle_flash: true,
+            cl_muzzle_flash_duration: 0.04,
+            cl_muzzle_flash_size: 4.0,
+
+            cl_notifications_max: 256,
+
+            cl_prediction: false,
+            cl_prediction_blend_frames: 6,
+            cl_prediction_max_replay: 60,
+            cl_prediction_projectile_timeout: 1.0,
+            cl_prediction_snap_distance: 64.0,
+
             cl_railgun_trail_thickness: 1.5,
             cl_railgun_trail_duration: 0.05,
 
+            cl_recoil: true,
+            cl_recoil_distance: 1.5,
+            cl_recoil_distance_heavy: 5.0,
+            cl_recoil_kick: 0.3,
+            cl_recoil_kick_bias: 0.7,
+            cl_recoil_time_constant: 0.025,
+
+            cl_scorch_radius: 24.0,
+
+            cl_shake: true,
+            cl_shake_decay: 1.5,
+            cl_shake_max_offset: 12.0,
+            cl_shake_radius: 400.0,
+            cl_shake_trauma: 0.5,
+
             cl_spawn_indicator_animation_time: 0.3,
             cl_spawn_indicator_blinking_period: 0.3,
             cl_spawn_indicator_duration: 1.5,
@@ -826,6 +1971,20 @@ impl Default for Cvars {
             cl_spawn_indicator_square_side_end: 40.0,
             cl_spawn_indicator_thickness: 2.0,
 
+            cl_sound_channel_range: 600.0,
+
+            cl_spectate_speed: 600.0,
+
+            cl_time_controls: true,
+
+            cl_tracks_interval: 12.0,
+
+            cl_turret_follows_mouse: false,
+
+            cl_zoom_max: 2.0,
+            cl_zoom_min: 0.5,
+            cl_zoom_step: 1.25,
+
             con_background_alpha: 0.8,
             con_prompt_group_x: 16.0,
             con_prompt_group_y_offset: 26.0,
@@ -837,92 +1996,230 @@ impl Default for Cvars {
             con_prompt_label_x: 8.0,
             con_prompt_label_y_offset: 22.0,
 
+            d_collision_trace: false,
+            d_collision_trace_len: 64,
             d_dbg: false,
 
             d_draw: true,
             d_draw_crosses: true,
+            d_draw_frametime_graph: false,
+            d_draw_grid: false,
             d_draw_hitboxes: false,
             d_draw_lines: true,
             d_draw_lines_ends_length: 3.0,
             d_draw_perf: true,
+            d_draw_projectile_rays: false,
             d_draw_text: true,
+            d_draw_text_bench: 0,
             d_draw_text_line_height: 14.0,
+            d_draw_velocities: false,
+            d_draw_velocities_scale: 0.25,
             d_draw_world_text: true,
+            d_events: false,
             d_explosion_radius: false,
+            d_flybys: false,
             d_fps: true,
             d_fps_period: 1.0,
             d_fps_x: -300.0,
             d_fps_y: -15.0,
+            d_frametime_graph_frames: 120,
+            d_frametime_graph_scale: 2.0,
+            d_frametime_graph_x: -300.0,
+            d_frametime_graph_y: -130.0,
             d_last_key: false,
+            d_latency_frames: 0,
+            d_panic_context: false,
+            d_perf_stats_period: 0.0,
+            d_remote_update_interval: 0,
+            d_replay_checksum_interval: 60,
             d_tickrate_remaining: false,
             d_timing_samples: 60,
+            d_timing_systems: false,
+            d_trace: false,
+            d_trace_ticks: 120,
             d_tracing: false,
             d_seed: 0,
             d_speed: 1.0,
+            d_stats: false,
 
             g_armor: 50.0,
+            g_ammo_crate_radius: 12.0,
+
+            g_armor_pickup_count: 2,
+            g_armor_pickup_min: 0.5,
+            g_armor_pickup_radius: 16.0,
+            g_armor_pickup_step: 0.1,
 
+            g_bfg_allowed: true,
+            g_bfg_angle_spread: 0.0,
+            g_bfg_arc_gravity: 0.0,
+            g_bfg_arc_speed: 0.0,
             g_bfg_beam_damage_per_sec: 25.0,
             g_bfg_beam_range: 125.0,
+            g_bfg_cooldown_group: CooldownGroup::None,
             g_bfg_damage_direct: 0.0,
+            g_bfg_damage_type: DamageType::Energy,
             g_bfg_explosion_damage: 100.0, // pretty sure from orig RW testing
             g_bfg_explosion_radius: 40.0,
             g_bfg_explosion_scale: 1.0,
+            g_bfg_fire_limiter: FireLimiter::Magazine,
+            g_bfg_heat_dissipation: 0.2,
+            g_bfg_heat_per_shot: 0.6,
+            g_bfg_lifetime: 30.0,
             g_bfg_radius: 4.0,
             g_bfg_reload_ammo: 1,
             g_bfg_reload_time: 2.5,
+            g_bfg_reserve: -1,
             g_bfg_speed: 150.0,
             g_bfg_vehicle_velocity_factor: 1.0,
 
+            g_cluster_bomb_allowed: true,
+            g_cluster_bomb_angle_spread: 0.0,
+            g_cluster_bomb_arc_gravity: 500.0,
+            g_cluster_bomb_arc_speed: 200.0,
+            g_cluster_bomb_bounce: false,
+            g_cluster_bomb_bounce_retention: 0.5,
+            g_cluster_bomb_cooldown_group: CooldownGroup::None,
             g_cluster_bomb_count: 40,
             g_cluster_bomb_damage_direct: 0.0, // best guess - same as rockets
+            g_cluster_bomb_damage_type: DamageType::Explosive,
             g_cluster_bomb_explosion_damage: 25.0,
             g_cluster_bomb_explosion_radius: 20.0,
             g_cluster_bomb_explosion_scale: 0.5,
+            g_cluster_bomb_fire_limiter: FireLimiter::Magazine,
+            g_cluster_bomb_heat_dissipation: 0.25,
+            g_cluster_bomb_heat_per_shot: 0.4,
+            g_cluster_bomb_lifetime: 10.0,
             g_cluster_bomb_reload_ammo: 1,
             g_cluster_bomb_reload_time: 1.5,
+            g_cluster_bomb_reserve: -1,
             g_cluster_bomb_shadow_alpha: 1.0,
             g_cluster_bomb_shadow_x: 2.0,
             g_cluster_bomb_shadow_y: 2.0,
             g_cluster_bomb_speed: 400.0,
             g_cluster_bomb_speed_spread_forward: 50.0,
-            g_cluster_bomb_speed_spread_gaussian: true,
             g_cluster_bomb_speed_spread_sideways: 50.0,
             g_cluster_bomb_time: 0.8,
             g_cluster_bomb_time_spread: 0.2,
             g_cluster_bomb_vehicle_velocity_factor: 1.0,
 
+            g_cooldown_group_energy: 0.0,
+            g_cooldown_group_explosive: 0.0,
+            g_cooldown_group_heavy: 0.0,
+
+            g_ctf_flag_radius: 8.0,
+            g_ctf_flag_return_time: 30.0,
+            g_ctf_score_capture: 5,
+
+            g_dda_aim_error_range: 0.05,
+            g_dda_enabled: false,
+            g_dda_gain_integral: 0.01,
+            g_dda_gain_proportional: 0.5,
+            g_dda_rate_max: 0.2,
+            g_dda_reaction_time_range: 0.15,
+            g_dda_target_ratio: 1.0,
+            g_dda_window: 180.0,
+
+            g_dust_count: 8,
+            g_dust_lifetime: 0.6,
+            g_dust_size: 5.0,
+            g_dust_speed: 60.0,
+
+            g_facing_turret: 1.0,
+            g_facing_turret_radius: 8.0,
+
             g_ffa_score_kill: 1,
             g_ffa_score_death: -1,
 
+            g_fire_rate_cap: f64::INFINITY,
+
+            g_flare_cooldown: 2.0,
+            g_flare_count: 3,
+            g_flare_lifetime: 1.5,
+
+            g_friendly_fire: FriendlyFire::Off,
+
+            g_game_mode: GameMode::FreeForAll,
+
+            g_homing_missile_allowed: true,
+            g_homing_missile_angle_spread: 0.0,
+            g_homing_missile_arc_gravity: 0.0,
+            g_homing_missile_arc_speed: 0.0,
+            g_homing_missile_cooldown_group: CooldownGroup::None,
             g_homing_missile_damage_direct: 0.0,
+            g_homing_missile_damage_type: DamageType::Explosive,
             g_homing_missile_explosion_damage: 56.0, // assumed same as GM
             g_homing_missile_explosion_radius: 40.0,
             g_homing_missile_explosion_scale: 1.0,
+            g_homing_missile_fire_limiter: FireLimiter::Magazine,
+            g_homing_missile_flare_preference: 0.75,
+            g_homing_missile_heat_dissipation: 0.25,
+            g_homing_missile_heat_per_shot: 0.3,
+            g_homing_missile_lifetime: 20.0,
+            g_homing_missile_lock_angle_deg: 45.0,
+            g_homing_missile_lock_range: 500.0,
             g_homing_missile_reload_ammo: 1,
             g_homing_missile_reload_time: 1.5,
+            g_homing_missile_reserve: -1,
+            g_homing_missile_retarget_interval: 0.25,
             g_homing_missile_speed_initial: 360.0,
+            g_homing_missile_turn_rate_deg: 120.0,
             g_homing_missile_vehicle_velocity_factor: 1.0,
 
+            g_machine_gun_allowed: true,
             g_machine_gun_angle_spread: 0.015,
+            g_machine_gun_arc_gravity: 0.0,
+            g_machine_gun_arc_speed: 0.0,
+            g_machine_gun_cooldown_group: CooldownGroup::None,
             g_machine_gun_damage: 2.5, // exact from orig RW
+            g_machine_gun_damage_type: DamageType::Kinetic,
+            g_machine_gun_fire_limiter: FireLimiter::Magazine,
+            g_machine_gun_heat_dissipation: 0.4,
+            g_machine_gun_heat_per_shot: 0.025,
+            g_machine_gun_lifetime: 10.0,
             g_machine_gun_refire: 0.050,
             g_machine_gun_reload_ammo: 50,
             g_machine_gun_reload_time: 1.0,
+            g_machine_gun_reserve: -1,
+            g_machine_gun_ricochet_chance: 0.5,
+            g_machine_gun_ricochet_max_angle: 20.0,
+            g_machine_gun_ricochet_retention: 0.7,
             g_machine_gun_speed: 1000.0,
             g_machine_gun_vehicle_velocity_factor: 1.0,
 
+            g_match_capture_limit: 5,
+            g_match_countdown: 3.0,
+            g_match_kill_limit: 20,
+            g_match_restart_delay: 10.0,
+            g_match_time_limit: 0.0,
+
+            g_guided_missile_allowed: true,
+            g_guided_missile_angle_spread: 0.0,
+            g_guided_missile_arc_gravity: 0.0,
+            g_guided_missile_arc_speed: 0.0,
             g_guided_missile_accel_forward: 2000.0,
+            g_guided_missile_cooldown_group: CooldownGroup::None,
             g_guided_missile_damage_direct: 0.0,
+            g_guided_missile_damage_type: DamageType::Explosive,
+            g_guided_missile_detonate_on_fire: false,
+            g_guided_missile_detonate_on_owner_death: false,
             g_guided_missile_explosion_damage: 56.0, // exact from orig RW
             g_guided_missile_explosion_radius: 40.0,
             g_guided_missile_explosion_scale: 1.0,
+            g_guided_missile_fire_limiter: FireLimiter::Magazine,
             g_guided_missile_friction_const: 0.0,
             g_guided_missile_friction_linear: 0.99,
+            g_guided_missile_heat_dissipation: 0.2,
+            g_guided_missile_heat_per_shot: 0.5,
+            g_guided_missile_lateral_friction_const: 0.0,
+            g_guided_missile_lateral_friction_linear: 0.99,
+            g_guided_missile_lifetime: 60.0,
             g_guided_missile_reload_ammo: 1,
             g_guided_missile_reload_time: 1.5,
+            g_guided_missile_reserve: -1,
             g_guided_missile_speed_initial: 100.0,
             g_guided_missile_speed_max: f64::INFINITY,
+            g_guided_missile_time: 10.0,
             g_guided_missile_turn_effectiveness: 1.0,
             g_guided_missile_turn_rate_friction_const: 0.10,
             g_guided_missile_turn_rate_friction_linear: 0.995,
@@ -930,83 +2227,60 @@ impl Default for Cvars {
             g_guided_missile_turn_rate_max: f64::INFINITY,
             g_guided_missile_vehicle_velocity_factor: 1.0,
 
-            g_hardpoint_hovercraft_machine_gun: Hardpoint::Turret,
-            g_hardpoint_hovercraft_machine_gun_x: 19.0,
-            g_hardpoint_hovercraft_machine_gun_y: 0.0,
-            g_hardpoint_hovercraft_railgun: Hardpoint::Turret,
-            g_hardpoint_hovercraft_railgun_x: 19.0,
-            g_hardpoint_hovercraft_railgun_y: 0.0,
-            g_hardpoint_hovercraft_cluster_bomb: Hardpoint::Turret,
-            g_hardpoint_hovercraft_cluster_bomb_x: 19.0,
-            g_hardpoint_hovercraft_cluster_bomb_y: 0.0,
-            g_hardpoint_hovercraft_rockets: Hardpoint::Turret,
-            g_hardpoint_hovercraft_rockets_x: 19.0,
-            g_hardpoint_hovercraft_rockets_y: 0.0,
-            g_hardpoint_hovercraft_homing_missile: Hardpoint::Chassis,
-            g_hardpoint_hovercraft_homing_missile_x: 0.0,
-            g_hardpoint_hovercraft_homing_missile_y: -16.0,
-            g_hardpoint_hovercraft_guided_missile: Hardpoint::Chassis,
-            g_hardpoint_hovercraft_guided_missile_x: 0.0,
-            g_hardpoint_hovercraft_guided_missile_y: -16.0,
-            g_hardpoint_hovercraft_bfg: Hardpoint::Turret,
-            g_hardpoint_hovercraft_bfg_x: 19.0,
-            g_hardpoint_hovercraft_bfg_y: 0.0,
-
-            g_hardpoint_hummer_machine_gun: Hardpoint::Chassis,
-            g_hardpoint_hummer_machine_gun_x: 10.0,
-            g_hardpoint_hummer_machine_gun_y: 9.0,
-            g_hardpoint_hummer_railgun: Hardpoint::Chassis,
-            g_hardpoint_hummer_railgun_x: 10.0,
-            g_hardpoint_hummer_railgun_y: 9.0,
-            g_hardpoint_hummer_cluster_bomb: Hardpoint::Turret,
-            g_hardpoint_hummer_cluster_bomb_x: 0.0,
-            g_hardpoint_hummer_cluster_bomb_y: 0.0,
-            g_hardpoint_hummer_rockets: Hardpoint::Turret,
-            g_hardpoint_hummer_rockets_x: 0.0,
-            g_hardpoint_hummer_rockets_y: 0.0,
-            g_hardpoint_hummer_homing_missile: Hardpoint::Chassis,
-            g_hardpoint_hummer_homing_missile_x: 0.0,
-            g_hardpoint_hummer_homing_missile_y: -10.0,
-            g_hardpoint_hummer_guided_missile: Hardpoint::Chassis,
-            g_hardpoint_hummer_guided_missile_x: 0.0,
-            g_hardpoint_hummer_guided_missile_y: -10.0,
-            g_hardpoint_hummer_bfg: Hardpoint::Chassis,
-            g_hardpoint_hummer_bfg_x: 10.0,
-            g_hardpoint_hummer_bfg_y: 9.0,
-
-            g_hardpoint_tank_machine_gun: Hardpoint::Turret,
-            g_hardpoint_tank_machine_gun_x: 12.0,
-            g_hardpoint_tank_machine_gun_y: -5.0,
-            g_hardpoint_tank_railgun: Hardpoint::Turret,
-            g_hardpoint_tank_railgun_x: 35.0,
-            g_hardpoint_tank_railgun_y: 0.0,
-            g_hardpoint_tank_cluster_bomb: Hardpoint::Turret,
-            g_hardpoint_tank_cluster_bomb_x: 35.0,
-            g_hardpoint_tank_cluster_bomb_y: 0.0,
-            g_hardpoint_tank_rockets: Hardpoint::Turret,
-            g_hardpoint_tank_rockets_x: 35.0,
-            g_hardpoint_tank_rockets_y: 0.0,
-            g_hardpoint_tank_homing_missile: Hardpoint::Chassis,
-            g_hardpoint_tank_homing_missile_x: 0.0,
-            g_hardpoint_tank_homing_missile_y: -14.0,
-            g_hardpoint_tank_guided_missile: Hardpoint::Chassis,
-            g_hardpoint_tank_guided_missile_x: 0.0,
-            g_hardpoint_tank_guided_missile_y: -14.0,
-            g_hardpoint_tank_bfg: Hardpoint::Turret,
-            g_hardpoint_tank_bfg_x: 35.0,
-            g_hardpoint_tank_bfg_y: 0.0,
+            g_hardpoint_hovercraft_machine_gun: Hardpoints::new(Hardpoint::Turret, 19.0, 0.0),
+            g_hardpoint_hovercraft_railgun: Hardpoints::new(Hardpoint::Turret, 19.0, 0.0),
+            g_hardpoint_hovercraft_cluster_bomb: Hardpoints::new(Hardpoint::Turret, 19.0, 0.0),
+            g_hardpoint_hovercraft_rockets: Hardpoints::new(Hardpoint::Turret, 19.0, 0.0),
+            g_hardpoint_hovercraft_homing_missile: Hardpoints::new(Hardpoint::Chassis, 0.0, -16.0),
+            g_hardpoint_hovercraft_guided_missile: Hardpoints::new(Hardpoint::Chassis, 0.0, -16.0),
+            g_hardpoint_hovercraft_bfg: Hardpoints::new(Hardpoint::Turret, 19.0, 0.0),
+
+            g_hardpoint_hummer_machine_gun: Hardpoints::new(Hardpoint::Chassis, 10.0, 9.0),
+            g_hardpoint_hummer_railgun: Hardpoints::new(Hardpoint::Chassis, 10.0, 9.0),
+            g_hardpoint_hummer_cluster_bomb: Hardpoints::new(Hardpoint::Turret, 0.0, 0.0),
+            g_hardpoint_hummer_rockets: Hardpoints::new(Hardpoint::Turret, 0.0, 0.0),
+            g_hardpoint_hummer_homing_missile: Hardpoints::new(Hardpoint::Chassis, 0.0, -10.0),
+            g_hardpoint_hummer_guided_missile: Hardpoints::new(Hardpoint::Chassis, 0.0, -10.0),
+            g_hardpoint_hummer_bfg: Hardpoints::new(Hardpoint::Chassis, 10.0, 9.0),
+
+            g_hardpoint_tank_machine_gun: Hardpoints::new(Hardpoint::Turret, 12.0, -5.0),
+            g_hardpoint_tank_railgun: Hardpoints::new(Hardpoint::Turret, 35.0, 0.0),
+            g_hardpoint_tank_cluster_bomb: Hardpoints::new(Hardpoint::Turret, 35.0, 0.0),
+            g_hardpoint_tank_rockets: Hardpoints::new(Hardpoint::Turret, 35.0, 0.0),
+            g_hardpoint_tank_homing_missile: Hardpoints::new(Hardpoint::Chassis, 0.0, -14.0),
+            g_hardpoint_tank_guided_missile: Hardpoints::new(Hardpoint::Chassis, 0.0, -14.0),
+            g_hardpoint_tank_bfg: Hardpoints::new(Hardpoint::Turret, 35.0, 0.0),
+
+            g_hazard_crusher_damage: 1000.0,
+            g_hazard_crusher_duration: 1.0,
+            g_hazard_crusher_period: 5.0,
+            g_hazard_crusher_phase: 0.0,
+            g_hazard_crusher_telegraph: 1.0,
+            g_hazard_damage_floor_dps: 20.0,
+            g_hazard_spikes_damage: 50.0,
+            g_hazard_spikes_rearm: 5.0,
 
             g_hitcircle_radius: 24.0,
 
             g_hovercraft_armor_scale: 0.65,
             g_hovercraft_accel_backward: 400.0,
             g_hovercraft_accel_forward: 400.0,
+            g_hovercraft_facing_front: 1.0,
+            g_hovercraft_facing_rear: 1.0,
+            g_hovercraft_facing_side: 1.0,
             g_hovercraft_friction_const: 0.0,
             g_hovercraft_friction_linear: 0.6,
+            g_hovercraft_lateral_friction_const: 0.0,
+            g_hovercraft_lateral_friction_linear: 0.6,
+            g_hovercraft_mass: 8.0,
             g_hovercraft_maxs_x: 22.0,
             g_hovercraft_maxs_y: 14.0,
             g_hovercraft_mins_x: -22.0,
             g_hovercraft_mins_y: -14.0,
+            g_hovercraft_reserve_scale: 1.0,
+            g_hovercraft_resistance_energy: 1.0,
+            g_hovercraft_resistance_explosive: 1.0,
+            g_hovercraft_resistance_kinetic: 1.0,
             g_hovercraft_speed_max: f64::INFINITY,
             g_hovercraft_steering_car: 0.0,
             g_hovercraft_turn_effectiveness: 0.0,
@@ -1014,20 +2288,39 @@ impl Default for Cvars {
             g_hovercraft_turn_rate_friction_linear: 0.92,
             g_hovercraft_turn_rate_increase: 10.0,
             g_hovercraft_turn_rate_max: f64::INFINITY,
+            g_hovercraft_turret_angle_max_deg: 180.0,
+            g_hovercraft_turret_angle_min_deg: -180.0,
             g_hovercraft_turret_offset_chassis_x: -9.0,
             g_hovercraft_turret_offset_chassis_y: 5.0,
             g_hovercraft_turret_offset_turret_x: -8.0,
             g_hovercraft_turret_offset_turret_y: 0.0,
+            g_hovercraft_water_friction_const: 15.0,
+            g_hovercraft_water_friction_linear: 0.3,
+            g_hovercraft_water_lateral_friction_const: 0.0,
+            g_hovercraft_water_lateral_friction_linear: 0.1,
+            g_hovercraft_water_passable: true,
+            g_hovercraft_water_turn_effectiveness: 0.0,
+            g_hovercraft_weapons: 0b111_1111,
 
             g_hummer_armor_scale: 0.625,
             g_hummer_accel_backward: 600.0,
             g_hummer_accel_forward: 600.0,
+            g_hummer_facing_front: 1.0,
+            g_hummer_facing_rear: 1.0,
+            g_hummer_facing_side: 1.0,
             g_hummer_friction_const: 11.0,
             g_hummer_friction_linear: 0.8,
+            g_hummer_lateral_friction_const: 300.0,
+            g_hummer_lateral_friction_linear: 0.95,
+            g_hummer_mass: 10.0,
             g_hummer_maxs_x: 20.0,
             g_hummer_maxs_y: 9.0,
             g_hummer_mins_x: -20.0,
             g_hummer_mins_y: -9.0,
+            g_hummer_reserve_scale: 1.0,
+            g_hummer_resistance_energy: 1.0,
+            g_hummer_resistance_explosive: 1.0,
+            g_hummer_resistance_kinetic: 1.0,
             g_hummer_speed_max: f64::INFINITY,
             g_hummer_steering_car: 200.0,
             g_hummer_turn_effectiveness: 1.0,
@@ -1035,80 +2328,228 @@ impl Default for Cvars {
             g_hummer_turn_rate_friction_linear: 0.97,
             g_hummer_turn_rate_increase: 18.0,
             g_hummer_turn_rate_max: f64::INFINITY,
+            g_hummer_turret_angle_max_deg: 180.0,
+            g_hummer_turret_angle_min_deg: -180.0,
             g_hummer_turret_offset_chassis_x: -12.0,
             g_hummer_turret_offset_chassis_y: 0.0,
             g_hummer_turret_offset_turret_x: 0.0,
             g_hummer_turret_offset_turret_y: 0.0,
-
+            g_hummer_water_passable: false,
+            g_hummer_weapons: 0b111_1111,
+
+            g_loadout_presets_max: 8,
+
+            g_missile_warning_range: 600.0,
+
+            g_pickup_respawn_time: 20.0,
+
+            g_powerup_radius: 14.0,
+            g_powerup_rapid_fire_duration: 10.0,
+            g_powerup_rapid_fire_refire_multiplier: 0.5,
+            g_powerup_respawn_time: 30.0,
+            g_powerup_shield_duration: 15.0,
+            g_powerup_shield_hp: 30.0,
+            g_powerup_speed_boost_duration: 10.0,
+            g_powerup_speed_boost_multiplier: 1.5,
+
+            g_projectile_max_bounces: 3,
+            g_projectiles_max: 2000,
+
+            g_railgun_allowed: true,
+            g_railgun_angle_spread: 0.0,
+            g_railgun_arc_gravity: 0.0,
+            g_railgun_arc_speed: 0.0,
+            g_railgun_charge_exposure_radius: 800.0,
+            g_railgun_charge_time: 0.0,
+            g_railgun_cooldown_group: CooldownGroup::None,
             g_railgun_damage: 47.0, // exact from orig RW
+            g_railgun_damage_type: DamageType::Kinetic,
+            g_railgun_fire_limiter: FireLimiter::Magazine,
+            g_railgun_heat_dissipation: 0.25,
+            g_railgun_heat_per_shot: 0.5,
+            g_railgun_lifetime: 1.0,
             g_railgun_push: 300.0,
             g_railgun_reload_ammo: 1,
             g_railgun_reload_time: 1.0,
+            g_railgun_reserve: -1,
             g_railgun_speed: 2500.0,
             g_railgun_vehicle_velocity_factor: 0.0,
+            g_railgun_wall_damage_factor: 0.1,
+
+            g_ram_crush_dps: 30.0,
+            g_ram_damage_factor: 0.05,
+            g_ram_damage_threshold: 100.0,
+
+            g_range: false,
+            g_range_dps_window: 3.0,
+            g_range_dummy_hp: 200.0,
+            g_range_dummy_repair_delay: 3.0,
+            g_range_dummy_respawn_delay: 2.0,
+            g_range_dummy_speed: 60.0,
+            g_range_grouping_samples: 20,
 
             // LATER this used to be 2 s, did RW use 2 s?
+            g_repair_amount: 10.0,
+            g_repair_pad_radius: 32.0,
+
             g_respawn_delay: 0.5,
 
+            g_rockets_allowed: true,
+            g_rockets_angle_spread: 0.0,
+            g_rockets_arc_gravity: 0.0,
+            g_rockets_arc_speed: 0.0,
+            g_rockets_cooldown_group: CooldownGroup::None,
             g_rockets_damage_direct: 25.0, // pretty sure from orig RW testing
+            g_rockets_damage_type: DamageType::Explosive,
             g_rockets_explosion_damage: 0.0,
             g_rockets_explosion_radius: 20.0,
             g_rockets_explosion_scale: 0.5,
+            g_rockets_fire_limiter: FireLimiter::Magazine,
+            g_rockets_heat_dissipation: 0.3,
+            g_rockets_heat_per_shot: 0.15,
+            g_rockets_lifetime: 15.0,
             g_rockets_refire: 0.200,
             g_rockets_reload_ammo: 6,
             g_rockets_reload_time: 1.5,
+            g_rockets_reserve: -1,
             g_rockets_speed: 600.0,
             g_rockets_vehicle_velocity_factor: 1.0,
 
+            g_self_collision_delay: 0.5,
+
             g_self_destruct_damage_center: 150.0,
             g_self_destruct_damage_edge: 0.0,
+            g_self_destruct_damage_type: DamageType::Explosive,
             g_self_destruct_explosion_scale: 2.0,
             g_self_destruct_radius: 175.0,
 
+            g_smoke_interval: 0.03,
+            g_smoke_lifetime: 0.8,
+            g_smoke_size: 4.0,
+            g_smoke_vehicle_hp_fraction: 0.5,
+
+            g_sparks_count: 5,
+            g_sparks_lifetime: 0.25,
+            g_sparks_size: 1.5,
+            g_sparks_speed: 200.0,
+
             g_tank_armor_scale: 1.0,
             g_tank_accel_backward: 550.0,
             g_tank_accel_forward: 550.0,
+            g_tank_facing_front: 1.0,
+            g_tank_facing_rear: 1.0,
+            g_tank_facing_side: 1.0,
             g_tank_friction_const: 50.0,
             g_tank_friction_linear: 0.9,
+            g_tank_lateral_friction_const: 500.0,
+            g_tank_lateral_friction_linear: 0.99,
+            g_tank_mass: 40.0,
             g_tank_maxs_x: 19.0,
             g_tank_maxs_y: 12.0,
             g_tank_mins_x: -19.0,
             g_tank_mins_y: -12.0,
+            g_tank_reserve_scale: 1.0,
+            g_tank_resistance_energy: 1.0,
+            g_tank_resistance_explosive: 1.0,
+            g_tank_resistance_kinetic: 1.0,
             g_tank_speed_max: f64::INFINITY,
             g_tank_steering_car: 0.0,
-            g_tank_turn_effectiveness: 1.0,
+            g_tank_turn_effectiveness: 0.5,
             g_tank_turn_rate_friction_const: 0.05,
             g_tank_turn_rate_friction_linear: 0.96,
             g_tank_turn_rate_increase: 8.0,
             g_tank_turn_rate_max: f64::INFINITY,
+            g_tank_turret_angle_max_deg: 180.0,
+            g_tank_turret_angle_min_deg: -180.0,
             g_tank_turret_offset_chassis_x: -5.0,
             g_tank_turret_offset_chassis_y: 0.0,
             g_tank_turret_offset_turret_x: -14.0,
             g_tank_turret_offset_turret_y: 0.0,
+            g_tank_water_passable: false,
+            g_tank_weapons: 0b111_1111,
 
             g_turret_turn_speed_deg: 120.0,
             g_turret_turn_step_angle_deg: 45.0,
 
+            g_vehicle_debris_count: 4,
+            g_vehicle_debris_damage: 2.0,
+            g_vehicle_debris_damage_type: DamageType::Kinetic,
+            g_vehicle_debris_speed_max: 300.0,
+            g_vehicle_debris_speed_min: 100.0,
+            g_vehicle_debris_time: 0.4,
+            g_vehicle_explosion_damage: 20.0,
+            g_vehicle_explosion_damage_type: DamageType::Explosive,
+            g_vehicle_explosion_radius: 30.0,
+            g_vehicle_max_step: 32.0,
+            g_vehicle_surface_blend_time: 0.25,
+            g_vehicle_wall_bounce: false,
+            g_vehicle_wall_friction: 0.5,
+
+            g_water_current_x: 0.0,
+            g_water_current_y: 0.0,
+
+            g_weapon_arc_fuse: true,
+            g_weapon_heat_unlock: 0.3,
+            g_weapon_spread_gaussian: true,
+
+            g_wind_x: 0.0,
+            g_wind_y: 0.0,
+
+            hud: true,
+
             hud_ammo_x: 30.0,
             hud_ammo_y: -30.0,
             hud_ammo_width: 100.0,
             hud_ammo_height: 4.0,
 
+            hud_armor: true,
+            hud_armor_x: 150.0,
+            hud_armor_y: -45.0,
+            hud_armor_size: 6.0,
+
+            hud_damage_flash_duration: 0.3,
+            hud_damage_flash_scale: 0.01,
+            hud_damage_flash_max: 0.5,
+            hud_damage_flash_width: 40.0,
+
             hud_hp_x: 30.0,
             hud_hp_y: -50.0,
             hud_hp_width: 100.0,
             hud_hp_height: 9.0,
 
+            hud_kill_feed: true,
+            hud_kill_feed_duration: 5.0,
+            hud_kill_feed_font_size: 16.0,
+            hud_kill_feed_line_height: 18.0,
+            hud_kill_feed_lines: 5,
+            hud_kill_feed_x: -300.0,
+            hud_kill_feed_y: 20.0,
+
             hud_names: true,
             hud_names_alpha: 1.0,
             hud_names_brightness: 255.0,
             hud_names_font_size: 16.0,
+            hud_names_max_width: 120.0,
+            hud_names_self: true,
             hud_names_shadow_alpha: 1.0,
             hud_names_shadow_x: 1.0,
             hud_names_shadow_y: 1.0,
             hud_names_x: -20.0,
             hud_names_y: 30.0,
 
+            hud_match_font_size: 64.0,
+            hud_match_go_duration: 1.0,
+            hud_match_y: -100.0,
+
+            hud_minimap: true,
+            hud_minimap_alpha: 0.8,
+            hud_minimap_blip_size: 3.0,
+            hud_minimap_gm_blink_period: 0.3,
+            hud_minimap_height: 150.0,
+            hud_minimap_width: 150.0,
+            hud_minimap_x: -160.0,
+            hud_minimap_y: 10.0,
+
             hud_missile_indicator_dash_length: 3.3,
             hud_missile_indicator_radius: 18.0,
 
@@ -1116,12 +2557,34 @@ impl Default for Cvars {
             hud_pause_shadow_x: 2.0,
             hud_pause_shadow_y: 2.0,
 
+            hud_powerups: true,
+            hud_powerups_size: 8.0,
+            hud_powerups_spacing: 30.0,
+            hud_powerups_x: 38.0,
+            hud_powerups_y: -100.0,
+
+            hud_range_font_size: 16.0,
+            hud_range_line_height: 18.0,
+            hud_range_x: 30.0,
+            hud_range_y: 30.0,
+
             hud_ranking_font_size: 16.0,
             hud_ranking_shadow_x: 1.0,
             hud_ranking_shadow_y: 1.0,
             hud_ranking_x: 80.0,
             hud_ranking_y: -70.0,
 
+            hud_rear_hit: true,
+            hud_rear_hit_duration: 0.5,
+            hud_rear_hit_font_size: 20.0,
+            hud_rear_hit_x: 0.0,
+            hud_rear_hit_y: -80.0,
+
+            hud_reload_indicator: true,
+            hud_reload_indicator_radius: 20.0,
+            hud_reload_indicator_segments: 32,
+            hud_reload_indicator_thickness: 2.0,
+
             hud_score_font_size: 32.0,
             hud_score_shadow_x: 2.0,
             hud_score_shadow_y: 2.0,
@@ -1137,27 +2600,68 @@ impl Default for Cvars {
             hud_scoreboard_width_name: 150.0,
             hud_scoreboard_width_points: 50.0,
 
+            hud_spectating_font_size: 24.0,
+            hud_spectating_y: 60.0,
+
+            hud_speed: true,
+            hud_speed_font_size: 16.0,
+            hud_speed_shadow_x: 1.0,
+            hud_speed_shadow_y: 1.0,
+            hud_speed_x: -80.0,
+            hud_speed_y: -15.0,
+
             hud_weapon_icon_shadow_alpha: 0.5,
             hud_weapon_icon_shadow_x: 2.0,
             hud_weapon_icon_shadow_y: 2.0,
             hud_weapon_icon_x: 170.0,
             hud_weapon_icon_y: -28.0,
 
+            hud_weapon_name: true,
+            hud_weapon_name_font_size: 16.0,
+            hud_weapon_name_shadow_x: 1.0,
+            hud_weapon_name_shadow_y: 1.0,
+            hud_weapon_name_x: 200.0,
+            hud_weapon_name_y: -24.0,
+
+            hud_wind: true,
+            hud_wind_scale: 0.25,
+            hud_wind_x: -85.0,
+            hud_wind_y: 200.0,
+
             r_align_to_pixels_background: true,
             r_align_to_pixels_text: true,
             r_draw_cluster_bombs: true,
-            r_explosion_duration: 0.5,
             // After trying true for a while, I think false looks better:
             // - CB looks smoother. With true it sometimes looked like it had 2 stages
             //   because the later explosions were suddenly revealed after the first ones disappeared.
             // - Rockets look better if hitting the same spot.
             r_explosions_reverse_order: false,
+            r_round_intro: false,
+            r_round_intro_duration: 4.0,
+            r_round_intro_max_wait: 10.0,
             r_smoothing: false,
             r_splitscreen_gap: 8.0,
+            r_trail_machine_gun_color: Rgba([1.0, 0.9, 0.5, 0.6]),
+            r_trail_machine_gun_length: 4,
+            r_trail_machine_gun_width: 1.0,
+            r_trail_rockets_color: Rgba([0.8, 0.8, 0.8, 0.5]),
+            r_trail_rockets_length: 8,
+            r_trail_rockets_width: 2.0,
+            r_vehicle_colors: true,
 
             sv_auto_pause_on_minimize: true,
             sv_auto_unpause_on_restore: false,
 
+            sv_collision_grid_cell_size: 64.0,
+
+            sv_derive_budget_ms: 2.0,
+
+            sv_life_record_burst_gap: 0.5,
+            sv_life_record_entries_max: 64,
+            sv_life_records_max: 10,
+
+            sv_particles_max: 4000,
+
             sv_tickrate_mode: TickrateMode::Synchronized,
             sv_tickrate_fixed_fps: 150.0,
         }
@@ -1170,6 +2674,160 @@ pub enum Hardpoint {
     Turret,
 }
 
+/// The barrels of one weapon, each with its mount and offset from the mount's center.
+///
+/// As a cvar, barrels are separated by `;`, each is the mount and the offset,
+/// e.g. `Turret 12 -5; Chassis 10 9` for one barrel on the turret and one fixed forward on the chassis.
+///
+/// A fixed number of slots instead of a `Vec` because cvars have to be `Copy`.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Hardpoints {
+    slots: [(Hardpoint, Vec2f); Hardpoints::MAX],
+    len: usize,
+}
+
+impl Hardpoints {
+    /// Barrels per weapon.
+    pub const MAX: usize = 4;
+
+    /// A single barrel.
+    pub fn new(hardpoint: Hardpoint, x: f64, y: f64) -> Self {
+        let mut slots = [(Hardpoint::Chassis, Vec2f::zero()); Self::MAX];
+        slots[0] = (hardpoint, Vec2f::new(x, y));
+        Self { slots, len: 1 }
+    }
+
+    pub fn barrels(&self) -> &[(Hardpoint, Vec2f)] {
+        &self.slots[..self.len]
+    }
+}
+
+impl FromStr for Hardpoints {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut slots = [(Hardpoint::Chassis, Vec2f::zero()); Self::MAX];
+        let mut len = 0;
+        for barrel in s.split(';') {
+            let parts: Vec<_> = barrel.split_whitespace().collect();
+            let (hardpoint, x, y) = match parts[..] {
+                [hardpoint, x, y] => (hardpoint, x, y),
+                _ => return Err(format!("expected mount, x and y, got {:?}", barrel.trim())),
+            };
+            let hardpoint = hardpoint
+                .parse()
+                .map_err(|_| format!("unknown mount {}", hardpoint))?;
+            let x = x.parse().map_err(|_| format!("invalid x {}", x))?;
+            let y = y.parse().map_err(|_| format!("invalid y {}", y))?;
+            if len == Self::MAX {
+                return Err(format!("at most {} barrels", Self::MAX));
+            }
+            slots[len] = (hardpoint, Vec2f::new(x, y));
+            len += 1;
+        }
+        Ok(Self { slots, len })
+    }
+}
+
+impl Display for Hardpoints {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (i, (hardpoint, offset)) in self.barrels().iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{} {} {}", hardpoint, offset.x, offset.y)?;
+        }
+        Ok(())
+    }
+}
+
+// The same as `Display` so `Cvars::to_pairs` can split the output of the derived `Debug` for `Cvars`.
+impl Debug for Hardpoints {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+/// A color with components from 0 to 1.
+///
+/// As a cvar, it's the components separated by spaces, e.g. `1 0.5 0 1` for opaque orange.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Rgba(pub [f32; 4]);
+
+impl FromStr for Rgba {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<_> = s.split_whitespace().collect();
+        if parts.len() != 4 {
+            return Err(format!("expected r, g, b and a, got {:?}", s.trim()));
+        }
+        let mut rgba = [0.0; 4];
+        for (component, part) in rgba.iter_mut().zip(parts) {
+            *component = part
+                .parse()
+                .map_err(|_| format!("invalid component {}", part))?;
+        }
+        Ok(Self(rgba))
+    }
+}
+
+impl Display for Rgba {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let [r, g, b, a] = self.0;
+        write!(f, "{} {} {} {}", r, g, b, a)
+    }
+}
+
+// The same as `Display`, see `Hardpoints`.
+impl Debug for Rgba {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+/// What kind of damage a source deals, vehicles have a resistance for each.
+///
+/// The type of each weapon (both direct hits and explosions) is configurable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+pub enum DamageType {
+    Kinetic,
+    Explosive,
+    Energy,
+    /// Map hazards. Not affected by vehicle resistances.
+    Environmental,
+}
+
+/// Weapons in the same group share a cooldown, see `g_cooldown_group_*`.
+///
+/// LATER Abilities (when there are any) should be able to join groups too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+pub enum CooldownGroup {
+    None,
+    Explosive,
+    Energy,
+    Heavy,
+}
+
+/// How damage to teammates is handled, see `g_friendly_fire`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+pub enum FriendlyFire {
+    Off,
+    On,
+    /// The attacker takes the damage the teammate would have taken instead of them.
+    Reflect,
+}
+
+/// What stops a weapon from firing continuously.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+pub enum FireLimiter {
+    /// A magazine of `g_*_reload_ammo` shots, then `g_*_reload_time` to reload it from the reserve.
+    Magazine,
+    /// No ammo, each shot adds `g_*_heat_per_shot` and `g_*_heat_dissipation` is lost per second.
+    /// Reaching 1 locks the weapon out until it cools below `g_weapon_heat_unlock`.
+    Heat,
+}
+
 /// Various options how to handle different physics/gamelogic and rendering framerates.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
 pub enum TickrateMode {
@@ -1186,6 +2844,10 @@ pub enum TickrateMode {
     /// resumes from the last full step so it's deterministic. Too small steps are skipped.
     /// This is described by Jonathan Blow here: https://youtu.be/fdAOPHgW7qM?t=7149
     FixedOrSmaller,
+    /// Exactly one fixed step per render frame, game time is derived from the tick count.
+    /// The game runs slower or faster than real time when rendering isn't at `sv_tickrate_fixed_fps`.
+    /// This is how the simulation has to run for lockstep networking, see `Server::tick`.
+    Lockstep,
     // There is another option - FixedWithInterpolation:
     // Instead of running with shorter dt to create the intermediate frame which is thrown away,
     // we'd wait till the next full simulation frame and interpolate to get the intermediate render frame.
@@ -1194,12 +2856,38 @@ pub enum TickrateMode {
     // to avoid interpolating between death and spawn location.
 }
 
+/// Everything random about a shot's projectiles, see `Cvars::g_projectile_params`.
+///
+/// Spreads are standard deviations or half-widths depending on `g_weapon_spread_gaussian`, 0 means none.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProjectileSpawnParams {
+    pub speed: f64,
+    /// Radians added to the shot's angle.
+    pub angular_spread: f64,
+    /// Speed added along the shot's direction.
+    pub forward_spread: f64,
+    /// Speed added perpendicular to it.
+    pub sideways_spread: f64,
+    /// How much of the vehicle's velocity the projectiles inherit.
+    pub velocity_factor: f64,
+    /// Projectiles per shot, each with its own spread.
+    pub count: u32,
+    /// Seconds after the shot when the projectiles explode on their own, infinite for never.
+    ///
+    /// Unrelated to `g_weapon_lifetime` which removes them without exploding.
+    pub fuse: f64,
+    pub fuse_spread: f64,
+}
+
 #[derive(Debug, Clone)]
 pub struct MovementStats {
     pub accel_backward: f64,
     pub accel_forward: f64,
     pub friction_const: f64,
     pub friction_linear: f64,
+    /// Friction against sliding sideways.
+    pub lateral_friction_const: f64,
+    pub lateral_friction_linear: f64,
     pub speed_max: f64,
     pub steering_car: f64,
     pub turn_effectiveness: f64,
@@ -1207,4 +2895,150 @@ pub struct MovementStats {
     pub turn_rate_friction_linear: f64,
     pub turn_rate_increase: f64,
     pub turn_rate_max: f64,
+    /// Whether water tiles behave like walls for movement
+    pub water_passable: bool,
+}
+
+impl MovementStats {
+    /// Between `self` (at 0) and `other` (at 1), `water_passable` is taken from `self`.
+    ///
+    /// Stats which are the same in both stay exactly the same, even infinite ones.
+    pub fn lerp(&self, other: &MovementStats, t: f64) -> MovementStats {
+        let mix = |a: f64, b: f64| if a == b { a } else { a * (1.0 - t) + b * t };
+        MovementStats {
+            accel_backward: mix(self.accel_backward, other.accel_backward),
+            accel_forward: mix(self.accel_forward, other.accel_forward),
+            friction_const: mix(self.friction_const, other.friction_const),
+            friction_linear: mix(self.friction_linear, other.friction_linear),
+            lateral_friction_const: mix(self.lateral_friction_const, other.lateral_friction_const),
+            lateral_friction_linear: mix(
+                self.lateral_friction_linear,
+                other.lateral_friction_linear,
+            ),
+            speed_max: mix(self.speed_max, other.speed_max),
+            steering_car: mix(self.steering_car, other.steering_car),
+            turn_effectiveness: mix(self.turn_effectiveness, other.turn_effectiveness),
+            turn_rate_friction_const: mix(
+                self.turn_rate_friction_const,
+                other.turn_rate_friction_const,
+            ),
+            turn_rate_friction_linear: mix(
+                self.turn_rate_friction_linear,
+                other.turn_rate_friction_linear,
+            ),
+            turn_rate_increase: mix(self.turn_rate_increase, other.turn_rate_increase),
+            turn_rate_max: mix(self.turn_rate_max, other.turn_rate_max),
+            water_passable: self.water_passable,
+        }
+    }
+
+    /// About the fastest forward speed - where acceleration is canceled by friction, capped at `speed_max`.
+    pub fn top_speed(&self) -> f64 {
+        // Linear friction keeps `(1 - friction_linear)` of the speed per second.
+        let decay = -(1.0 - self.friction_linear).ln();
+        let terminal = ((self.accel_forward - self.friction_const) / decay).max(0.0);
+        terminal.min(self.speed_max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_pairs_errors() {
+        let mut cvars = Cvars::default();
+        let args = |args: &[&str]| args.iter().map(|&arg| arg.to_owned()).collect::<Vec<_>>();
+
+        let err = cvars.set_pairs(&args(&["g_armor"])).unwrap_err();
+        assert_eq!(err, "cvar g_armor has no value");
+        let err = cvars.set_pairs(&args(&["no_such_cvar", "1"])).unwrap_err();
+        assert!(
+            err.starts_with("can't set cvar no_such_cvar to 1"),
+            "{}",
+            err
+        );
+        assert!(cvars.set_pairs(&[]).is_ok());
+    }
+
+    #[test]
+    fn test_export_import_round_trip() {
+        let mut cvars = Cvars::default();
+        assert_eq!(cvars.export(), "");
+
+        cvars.g_armor = 150.0;
+        cvars.hud_names = !cvars.hud_names;
+        cvars.g_railgun_damage_type = DamageType::Energy;
+        cvars.g_tank_speed_max = 300.5;
+        cvars.g_bfg_damage_type = DamageType::Kinetic;
+        let exported = cvars.export();
+        assert_eq!(exported.lines().count(), 5, "{}", exported);
+
+        cvars.reset();
+        assert_eq!(cvars.export(), "");
+        assert_eq!(cvars.import(&exported), Ok(5));
+        assert_eq!(cvars.export(), exported);
+        assert_eq!(cvars.g_bfg_damage_type, DamageType::Kinetic);
+    }
+
+    #[test]
+    fn test_import_typo() {
+        let mut cvars = Cvars::default();
+        let text = "// Comments and empty lines are fine\n\ng_armour 150\ng_armor 150\nhud_names maybe\ng_respawn_delay 2\n";
+        let err = cvars.import(text).unwrap_err();
+        assert!(err.starts_with("set 2 cvars, 2 failed:"), "{}", err);
+        assert!(
+            err.contains("line 3: can't set cvar g_armour to 150"),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains("line 5: can't set cvar hud_names to maybe"),
+            "{}",
+            err
+        );
+        // The rest still got applied.
+        assert_eq!(cvars.g_armor, 150.0);
+        assert_eq!(cvars.g_respawn_delay, 2.0);
+    }
+
+    #[test]
+    fn test_presets() {
+        let mut cvars = Cvars::default();
+        for (name, text) in PRESETS {
+            cvars.g_armor = 1.0;
+            let count = cvars.load_preset(name).unwrap();
+            let set = text
+                .lines()
+                .filter(|line| !line.is_empty() && !line.starts_with("//"))
+                .count();
+            assert_eq!(count as usize, set);
+            // Starts from the defaults.
+            assert_eq!(cvars.g_armor, Cvars::default().g_armor);
+        }
+        assert!(cvars.load_preset("clasic").is_err());
+    }
+
+    #[test]
+    fn test_hardpoints_parse() {
+        let hardpoints: Hardpoints = "Turret 12 -5; Chassis 10 9".parse().unwrap();
+        assert_eq!(
+            hardpoints.barrels(),
+            &[
+                (Hardpoint::Turret, Vec2f::new(12.0, -5.0)),
+                (Hardpoint::Chassis, Vec2f::new(10.0, 9.0)),
+            ]
+        );
+
+        let max = ["Turret 0 0"; Hardpoints::MAX].join("; ");
+        assert_eq!(
+            max.parse::<Hardpoints>().unwrap().barrels().len(),
+            Hardpoints::MAX
+        );
+        let too_many = ["Turret 0 0"; Hardpoints::MAX + 1].join("; ");
+        assert_eq!(
+            too_many.parse::<Hardpoints>().unwrap_err(),
+            format!("at most {} barrels", Hardpoints::MAX)
+        );
+    }
 }
@@ -11,9 +11,9 @@
 
 #![allow(dead_code)]
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 
-use crate::map::Vec2f;
+use crate::{cvars::Cvars, map::Vec2f};
 
 #[macro_export]
 macro_rules! soft_assert {
@@ -56,6 +56,11 @@ thread_local! {
     pub static DEBUG_TEXTS_WORLD: RefCell<Vec<WorldText>> = RefCell::new(Vec::new());
     pub static DEBUG_LINES: RefCell<Vec<Line>> = RefCell::new(Vec::new());
     pub static DEBUG_CROSSES: RefCell<Vec<Cross>> = RefCell::new(Vec::new());
+
+    /// Whether the text would be drawn, updated from cvars in `cleanup`.
+    /// When it wouldn't, the macros skip formatting so they cost almost nothing.
+    pub static DEBUG_TEXTS_ENABLED: Cell<bool> = Cell::new(true);
+    pub static DEBUG_TEXTS_WORLD_ENABLED: Cell<bool> = Cell::new(true);
 }
 
 /// Print text into the console. Uses `println!(..)`-style formatting.
@@ -82,10 +87,12 @@ macro_rules! dbg_logd {
 #[macro_export]
 macro_rules! dbg_textf {
     ( $( $t:tt )* ) => {
-        let s = format!( $( $t )* );
-        $crate::debugging::DEBUG_TEXTS.with(|texts| {
-            texts.borrow_mut().push(s)
-        });
+        if $crate::debugging::DEBUG_TEXTS_ENABLED.with(|enabled| enabled.get()) {
+            let s = format!( $( $t )* );
+            $crate::debugging::DEBUG_TEXTS.with(|texts| {
+                texts.borrow_mut().push(s)
+            });
+        }
     };
 }
 
@@ -95,10 +102,12 @@ macro_rules! dbg_textf {
 #[macro_export]
 macro_rules! dbg_textd {
     ( $( $e:expr ),* ) => {
-        let s = $crate::__format_pairs!( $( $e ),* );
-        $crate::debugging::DEBUG_TEXTS.with(|texts| {
-            texts.borrow_mut().push(s)
-        });
+        if $crate::debugging::DEBUG_TEXTS_ENABLED.with(|enabled| enabled.get()) {
+            let s = $crate::__format_pairs!( $( $e ),* );
+            $crate::debugging::DEBUG_TEXTS.with(|texts| {
+                texts.borrow_mut().push(s)
+            });
+        }
     };
 }
 
@@ -108,14 +117,16 @@ macro_rules! dbg_textd {
 #[macro_export]
 macro_rules! dbg_world_textf {
     ( $pos:expr, $( $t:tt )* ) => {
-        let s = format!( $( $t )* );
-        let text = $crate::debugging::WorldText {
-            msg: s,
-            pos: $pos,
-        };
-        $crate::debugging::DEBUG_TEXTS_WORLD.with(|texts| {
-            texts.borrow_mut().push(text)
-        });
+        if $crate::debugging::DEBUG_TEXTS_WORLD_ENABLED.with(|enabled| enabled.get()) {
+            let s = format!( $( $t )* );
+            let text = $crate::debugging::WorldText {
+                msg: s,
+                pos: $pos,
+            };
+            $crate::debugging::DEBUG_TEXTS_WORLD.with(|texts| {
+                texts.borrow_mut().push(text)
+            });
+        }
     };
 }
 
@@ -125,14 +136,16 @@ macro_rules! dbg_world_textf {
 #[macro_export]
 macro_rules! dbg_world_textd {
     ( $pos:expr, $( $e:expr ),* ) => {
-        let s = $crate::__format_pairs!( $( $e ),* );
-        let text = $crate::debugging::WorldText {
-            msg: s,
-            pos: $pos,
-        };
-        $crate::debugging::DEBUG_TEXTS_WORLD.with(|texts| {
-            texts.borrow_mut().push(text)
-        });
+        if $crate::debugging::DEBUG_TEXTS_WORLD_ENABLED.with(|enabled| enabled.get()) {
+            let s = $crate::__format_pairs!( $( $e ),* );
+            let text = $crate::debugging::WorldText {
+                msg: s,
+                pos: $pos,
+            };
+            $crate::debugging::DEBUG_TEXTS_WORLD.with(|texts| {
+                texts.borrow_mut().push(text)
+            });
+        }
     };
 }
 
@@ -264,7 +277,9 @@ where
     }
 }
 
-pub fn cleanup() {
+pub fn cleanup(cvars: &Cvars) {
+    DEBUG_TEXTS_ENABLED.with(|enabled| enabled.set(cvars.d_draw && cvars.d_draw_text));
+    DEBUG_TEXTS_WORLD_ENABLED.with(|enabled| enabled.set(cvars.d_draw && cvars.d_draw_world_text));
     DEBUG_LINES.with(|lines| lines.borrow_mut().retain(|line| line.time > 0.0));
     DEBUG_CROSSES.with(|crosses| crosses.borrow_mut().retain(|cross| cross.time > 0.0));
     DEBUG_TEXTS.with(|texts| texts.borrow_mut().clear());
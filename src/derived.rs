@@ -0,0 +1,179 @@
+//! Data derived from the map which is too slow to build all at once when the map loads.
+//!
+//! Only what the first gamelogic frame needs (collisions, spawns, hazards) is part of `Map` itself.
+//! Everything else is built in slices during the following frames within `sv_derive_budget_ms`.
+//! Consumers either wait for it (the minimap shows a placeholder)
+//! or force the rest of just the one structure they need to be built immediately.
+//!
+//! Incremental and eager building give exactly the same results.
+
+use crate::{
+    map::Map,
+    minimap::MinimapTerrainBuilder,
+    nav::{NavGrid, NavGridBuilder},
+};
+
+/// Whether a builder has finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Done,
+    NotDone,
+}
+
+/// Something that builds derived data a slice at a time.
+pub trait Derivation {
+    fn name(&self) -> &'static str;
+
+    /// Lower is built first.
+    fn priority(&self) -> i32;
+
+    /// Rough estimate of the total work, in tiles.
+    fn cost(&self) -> usize;
+
+    fn is_done(&self) -> bool;
+
+    /// Do some work, return as soon as possible after `deadline` (same clock as `now`).
+    ///
+    /// Always makes some progress, even if the deadline has already passed.
+    fn step(&mut self, map: &Map, deadline: f64) -> Step;
+}
+
+/// Current time in seconds, the same clock as the frame timing stats.
+pub fn now() -> f64 {
+    macroquad::miniquad::date::now()
+}
+
+#[derive(Debug, Clone)]
+pub struct Derived {
+    pub nav_grid: NavGridBuilder,
+    pub minimap_terrain: MinimapTerrainBuilder,
+}
+
+impl Derived {
+    /// Doesn't build anything yet so it's cheap.
+    pub fn new(map: &Map) -> Self {
+        Self {
+            nav_grid: NavGridBuilder::new(map),
+            minimap_terrain: MinimapTerrainBuilder::new(map),
+        }
+    }
+
+    /// By priority
+    fn all(&self) -> Vec<&dyn Derivation> {
+        let mut all: Vec<&dyn Derivation> = vec![&self.nav_grid, &self.minimap_terrain];
+        all.sort_by_key(|derivation| derivation.priority());
+        all
+    }
+
+    fn all_mut(&mut self) -> Vec<&mut dyn Derivation> {
+        let mut all: Vec<&mut dyn Derivation> = vec![&mut self.nav_grid, &mut self.minimap_terrain];
+        all.sort_by_key(|derivation| derivation.priority());
+        all
+    }
+
+    /// Build for at most about `budget` seconds, highest priority first.
+    pub fn run(&mut self, map: &Map, budget: f64) {
+        let deadline = now() + budget;
+        for derivation in self.all_mut() {
+            while !derivation.is_done() {
+                derivation.step(map, deadline);
+                if now() >= deadline {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Number of finished structures, total number and the name of the one being built.
+    pub fn derivation_progress(&self) -> (usize, usize, Option<&'static str>) {
+        let all = self.all();
+        let done = all.iter().filter(|derivation| derivation.is_done()).count();
+        let current = all
+            .iter()
+            .find(|derivation| !derivation.is_done())
+            .map(|derivation| derivation.name());
+        (done, all.len(), current)
+    }
+
+    /// Finishes building the nav grid if needed.
+    pub fn nav_grid(&mut self, map: &Map) -> &NavGrid {
+        while self.nav_grid.step(map, f64::NEG_INFINITY) == Step::NotDone {}
+        self.nav_grid.grid().unwrap()
+    }
+
+    /// RGBA pixels, see `minimap::terrain_pixels`. `None` until built.
+    pub fn minimap_terrain(&self) -> Option<&[u8]> {
+        self.minimap_terrain.pixels()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{map, minimap};
+
+    /// Big map with some walls.
+    fn big_map() -> Map {
+        let mut text = String::new();
+        for r in 0..300 {
+            let row: Vec<_> = (0..300)
+                .map(|c| {
+                    if (r * 7 + c * 13) % 31 == 0 {
+                        "16"
+                    } else {
+                        "0"
+                    }
+                })
+                .collect();
+            text.push_str(&row.join(" "));
+            text.push('\n');
+        }
+        map::tests::load_test_map(&text)
+    }
+
+    #[test]
+    fn test_incremental_same_as_eager() {
+        let map = big_map();
+
+        // Creating it is cheap - nothing is built before the first frame.
+        let start = now();
+        let mut derived = Derived::new(&map);
+        assert!(now() - start < 0.05);
+        assert_eq!(derived.derivation_progress(), (0, 2, Some("nav grid")));
+
+        let budget = 0.001;
+        let mut frames = 0;
+        while derived.derivation_progress().0 < 2 {
+            let start = now();
+            derived.run(&map, budget);
+            // One slice is a row so it shouldn't overshoot by much.
+            assert!(now() - start < budget + 0.02, "{}", now() - start);
+            frames += 1;
+            assert!(frames < 100_000);
+        }
+        assert_eq!(derived.derivation_progress(), (2, 2, None));
+
+        assert_eq!(
+            derived.minimap_terrain().unwrap(),
+            minimap::terrain_pixels(&map)
+        );
+        assert_eq!(*derived.nav_grid(&map), NavGrid::build(&map));
+    }
+
+    #[test]
+    fn test_on_demand() {
+        let map = big_map();
+        let mut derived = Derived::new(&map);
+        derived.run(&map, 0.0);
+        assert!(!derived.nav_grid.is_done());
+
+        // The consumer doesn't have to wait, the minimap does.
+        assert_eq!(*derived.nav_grid(&map), NavGrid::build(&map));
+        assert_eq!(
+            derived.derivation_progress(),
+            (1, 2, Some("minimap terrain"))
+        );
+        assert!(derived.minimap_terrain().is_none());
+    }
+}
@@ -0,0 +1,75 @@
+//! Runtime map editing - keeping a running match consistent with a changed map.
+//!
+//! The map itself is edited with `Map::set_tile` and `Map::resize`,
+//! `Server` wraps them and calls `map_edited` on its game states.
+//! Edits aren't part of replays.
+
+use thunderdome::Index;
+
+use crate::{
+    game_state::GameState,
+    map::{Kind, Map, Vec2f, Vec2u},
+    sys_ctf::FlagState,
+};
+
+/// Fix up the game state after the map changed.
+///
+/// Returns the vehicles which had to be moved out of walls.
+pub fn map_edited(gs: &mut GameState, map: &Map) -> Vec<Index> {
+    // Hazards might have been added, removed or reordered.
+    gs.hazards.clear();
+    push_out_of_walls(gs, map)
+}
+
+/// Move vehicles and flags whose center is now inside a wall or outside the map
+/// to the center of the nearest tile which isn't a wall.
+///
+/// Vehicles which only overlap a wall partially are left to the normal wall collisions.
+/// Projectiles inside walls explode on their own next frame.
+pub fn push_out_of_walls(gs: &mut GameState, map: &Map) -> Vec<Index> {
+    let mut moved = Vec::new();
+    for (handle, vehicle) in gs.vehicles.iter_mut() {
+        if !map.is_wall(*vehicle.pos) {
+            continue;
+        }
+        if let Some(pos) = nearest_open_tile(map, *vehicle.pos) {
+            vehicle.pos.set(pos, gs.frame_num);
+            vehicle.vel = Vec2f::zero();
+            vehicle.update_obb();
+            moved.push(handle);
+        }
+    }
+
+    if let Some(ctf) = &mut gs.ctf {
+        for flag in &mut ctf.flags {
+            if matches!(flag.state, FlagState::Carried(_)) || !map.is_wall(flag.pos) {
+                continue;
+            }
+            if let Some(pos) = nearest_open_tile(map, flag.pos) {
+                flag.pos = pos;
+            }
+        }
+    }
+
+    moved
+}
+
+/// Center of the tile closest to `pos` which isn't a wall, `None` if the whole map is walls.
+pub fn nearest_open_tile(map: &Map, pos: Vec2f) -> Option<Vec2f> {
+    let mut nearest: Option<Vec2f> = None;
+    for r in 0..map.height() {
+        for c in 0..map.width() {
+            let index = Vec2u::new(c, r);
+            if map.surface_at_index(index).kind == Kind::Wall {
+                continue;
+            }
+            let center = map.tile_center(index);
+            if nearest.map_or(true, |nearest| {
+                center.distance_squared(pos) < nearest.distance_squared(pos)
+            }) {
+                nearest = Some(center);
+            }
+        }
+    }
+    nearest
+}
@@ -0,0 +1,137 @@
+//! Short-lived visual effects - explosions, railgun and BFG beams and muzzle flashes.
+//!
+//! Systems create them through `GameState::spawn_effect`, `retire` removes them
+//! once they're older than the lifetime of their kind - for explosions that's when their animation ends.
+//! Rendering uses the remaining lifetime to animate or fade them out.
+
+use thunderdome::Index;
+use vek::Clamp;
+
+use crate::{cvars::Cvars, entities::Weapon, game_state::GameState, map::Vec2f, sprites};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    Explosion {
+        pos: Vec2f,
+        scale: f64,
+        start_time: f64,
+        bfg: bool,
+    },
+    /// The part of a railgun's path it flew along in one frame.
+    RailBeam {
+        begin: Vec2f,
+        end: Vec2f,
+        start_time: f64,
+    },
+    /// From a BFG ball to a vehicle it's damaging.
+    BfgBeam {
+        begin: Vec2f,
+        end: Vec2f,
+        start_time: f64,
+    },
+    /// One barrel firing one shot, at the barrel's position at the time of the shot.
+    /// The client also uses it for recoil (see `feedback::Recoils`).
+    MuzzleFlash {
+        pos: Vec2f,
+        angle: f64,
+        weapon: Weapon,
+        vehicle: Index,
+        start_time: f64,
+    },
+}
+
+impl Effect {
+    pub fn start_time(&self) -> f64 {
+        match *self {
+            Effect::Explosion { start_time, .. }
+            | Effect::RailBeam { start_time, .. }
+            | Effect::BfgBeam { start_time, .. }
+            | Effect::MuzzleFlash { start_time, .. } => start_time,
+        }
+    }
+
+    /// Seconds, 0 means the effect only lasts the frame it was spawned in.
+    pub fn lifetime(&self, cvars: &Cvars) -> f64 {
+        match self {
+            Effect::Explosion { bfg, .. } => sprites::explosion_sheet(*bfg).duration(),
+            Effect::RailBeam { .. } => cvars.cl_railgun_trail_duration,
+            Effect::BfgBeam { .. } => cvars.cl_bfg_beam_duration,
+            Effect::MuzzleFlash { .. } => cvars.cl_muzzle_flash_duration,
+        }
+    }
+
+    /// 1 when just spawned, going down to 0 at the end of its lifetime.
+    pub fn remaining(&self, cvars: &Cvars, game_time: f64) -> f64 {
+        let lifetime = self.lifetime(cvars);
+        if lifetime <= 0.0 {
+            return 1.0;
+        }
+        let age = game_time - self.start_time();
+        (1.0 - age / lifetime).clamped(0.0, 1.0)
+    }
+}
+
+/// Remove effects older than their lifetime and explosions whose animation has finished.
+pub fn retire(cvars: &Cvars, gs: &mut GameState) {
+    let game_time = gs.game_time; // borrowck
+    gs.effects.retain(|effect| {
+        let age = game_time - effect.start_time();
+        match *effect {
+            Effect::Explosion { bfg, .. } => sprites::explosion_frame(bfg, age).is_some(),
+            _ => age <= effect.lifetime(cvars),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retire() {
+        let cvars = Cvars {
+            cl_bfg_beam_duration: 0.0,
+            ..Cvars::default()
+        };
+        let mut gs = GameState::new(0);
+        gs.game_time = 1.0;
+        gs.spawn_effect(Effect::Explosion {
+            pos: Vec2f::zero(),
+            scale: 1.0,
+            start_time: gs.game_time,
+            bfg: false,
+        });
+        gs.spawn_effect(Effect::RailBeam {
+            begin: Vec2f::zero(),
+            end: Vec2f::new(10.0, 0.0),
+            start_time: gs.game_time,
+        });
+        gs.spawn_effect(Effect::BfgBeam {
+            begin: Vec2f::zero(),
+            end: Vec2f::new(0.0, 10.0),
+            start_time: gs.game_time,
+        });
+        retire(&cvars, &mut gs);
+        assert_eq!(gs.effects.len(), 3);
+        assert_eq!(gs.effects[2].remaining(&cvars, gs.game_time), 1.0);
+
+        // BFG beams only last one frame.
+        gs.game_time += 0.01;
+        retire(&cvars, &mut gs);
+        assert_eq!(gs.effects.len(), 2);
+
+        let halfway = 1.0 + sprites::EXPLOSION.duration() / 2.0;
+        let remaining = gs.effects[0].remaining(&cvars, halfway);
+        assert!((remaining - 0.5).abs() < 1e-9);
+
+        // Past every lifetime with nothing new happening, nothing is left.
+        let longest = gs
+            .effects
+            .iter()
+            .map(|effect| effect.lifetime(&cvars))
+            .fold(0.0, f64::max);
+        gs.game_time = 1.0 + longest + 0.01;
+        retire(&cvars, &mut gs);
+        assert!(gs.effects.is_empty());
+    }
+}
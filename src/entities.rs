@@ -8,11 +8,24 @@
 //! This is not a violation of the ECS pattern,
 //! because they don't modify game state - they're not behavior.
 
+use std::{collections::VecDeque, ops::Deref};
+
 use enumn::N;
 use thunderdome::Index;
+use vek::LineSegment2;
 
-use crate::{cvars::Cvars, game_state::Input, map::Vec2f};
+use crate::{
+    cvars::{CooldownGroup, Cvars, FireLimiter},
+    fixed::{self, MilliHp},
+    game_state::Input,
+    map::Vec2f,
+};
 
+/// Everything that should outlive the player's vehicle.
+///
+/// Player handles stay the same for the whole connection (and across `Server::reset_match`)
+/// while each respawn creates a new vehicle. That's why projectiles, scores, kill attribution
+/// and client-side state (name labels, camera, audio) refer to players, not vehicles.
 #[derive(Debug, Clone)]
 pub struct Player {
     pub name: String,
@@ -25,10 +38,22 @@ pub struct Player {
     pub input: Input,
     pub respawn: Respawn,
     pub death_time: f64,
+    /// The current vehicle, the wreck while dead until respawning replaces it.
     pub vehicle: Option<Index>,
     pub guided_missile: Option<Index>,
     pub cur_weapon: Weapon,
     pub score: Score,
+    /// What the player's current vehicle spawned with.
+    pub loadout: Loadout,
+    /// What the player's next vehicle will spawn with.
+    pub pending_loadout: Loadout,
+    /// Saved loadouts, see `loadout`.
+    pub loadout_presets: Vec<LoadoutPreset>,
+    /// Name tag color, `None` means the default set by `hud_names_brightness`.
+    /// Bots get theirs from their persona, see `roster`.
+    pub color: Option<[u8; 3]>,
+    /// `None` outside team modes. Stays the same across respawns.
+    pub team: Option<Team>,
 }
 
 impl Player {
@@ -42,10 +67,53 @@ impl Player {
             guided_missile: None,
             cur_weapon: Weapon::Mg,
             score: Score::default(),
+            loadout: Loadout::default(),
+            pending_loadout: Loadout::default(),
+            loadout_presets: Vec::new(),
+            color: None,
+            team: None,
+        }
+    }
+}
+
+/// Vehicle type and weapons a player chose to spawn with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loadout {
+    /// `None` means random.
+    pub veh_type: Option<VehicleType>,
+    /// Weapons the player can switch between, the order doesn't matter.
+    pub weapons: Vec<Weapon>,
+}
+
+impl Loadout {
+    /// Same vehicle type and weapons regardless of the order of weapons.
+    pub fn matches(&self, other: &Loadout) -> bool {
+        let sorted = |weapons: &[Weapon]| {
+            let mut nums: Vec<_> = weapons.iter().map(|&weapon| weapon as u8).collect();
+            nums.sort_unstable();
+            nums.dedup();
+            nums
+        };
+        self.veh_type == other.veh_type && sorted(&self.weapons) == sorted(&other.weapons)
+    }
+}
+
+impl Default for Loadout {
+    /// Random vehicle, all weapons.
+    fn default() -> Self {
+        Self {
+            veh_type: None,
+            weapons: Weapon::all().collect(),
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadoutPreset {
+    pub name: String,
+    pub loadout: Loadout,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Respawn {
     No,
@@ -53,56 +121,189 @@ pub enum Respawn {
     Scheduled,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, N)]
+pub enum Team {
+    Red,
+    Blue,
+}
+
+impl Team {
+    pub fn all() -> [Team; 2] {
+        [Team::Red, Team::Blue]
+    }
+
+    pub fn enemy(self) -> Team {
+        match self {
+            Team::Red => Team::Blue,
+            Team::Blue => Team::Red,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Team::Red => "Red",
+            Team::Blue => "Blue",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct Score {
     pub kills: i32,
     pub deaths: i32,
     pub suicides: i32,
+    /// Enemy flags brought home, see `sys_ctf`.
+    pub captures: i32,
+    /// Indexed by `Weapon as usize`, see `summary`.
+    pub weapons: [WeaponStats; WEAPS_CNT as usize],
+}
+
+/// Accuracy and damage of one weapon during a match.
+///
+/// Counted per projectile so each cluster bomb is a shot.
+/// A projectile is a hit the first time it hurts another player,
+/// its splash and BFG beams after that only add damage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WeaponStats {
+    pub shots: u32,
+    pub hits: u32,
+    /// Dealt to other players, self-damage doesn't count.
+    pub damage: MilliHp,
 }
 
 impl Score {
     pub fn points(&self, cvars: &Cvars) -> i32 {
-        self.kills * cvars.g_ffa_score_kill + self.deaths * cvars.g_ffa_score_death
+        self.kills * cvars.g_ffa_score_kill
+            + self.deaths * cvars.g_ffa_score_death
+            + self.captures * cvars.g_ctf_score_capture
     }
 }
 
+/// Hardest bot difficulty, see `bots_difficulty`.
+pub const BOTS_DIFFICULTY_MAX: u32 = 4;
+
+/// What a bot saw at some point - it acts on what it saw `Ai::reaction_time` ago, see `sys_ai`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Perception {
+    pub time: f64,
+    /// The vehicle the bot would attack, `None` if it saw no enemy.
+    pub target: Option<Index>,
+    pub pos: Vec2f,
+    pub vel: Vec2f,
+}
+
 #[derive(Debug, Clone)]
 pub struct Ai {
     pub player: Index,
+    /// 0 (easiest) to `BOTS_DIFFICULTY_MAX`, see `bots_difficulty`.
+    pub difficulty: u32,
+    /// Oldest first, only the newest one old enough to react to and the ones after it are kept.
+    pub perceptions: VecDeque<Perception>,
     pub movement: i32,
     pub turning: i32,
     pub firing: bool,
+    /// When the bot decided to start firing.
+    pub firing_decision_time: f64,
+    /// Current reaction time, can differ from `bots_reaction_time` because of difficulty and DDA.
+    pub reaction_time: f64,
+    /// Current aim error, can differ from `bots_aim_error` because of difficulty and DDA.
+    pub aim_error: f64,
+    /// Persona's multiplier of `bots_reaction_time`, applied before DDA.
+    pub reaction_time_scale: f64,
+    /// Persona's multiplier of `bots_aim_error`, applied before DDA.
+    pub aim_error_scale: f64,
 }
 
 impl Ai {
-    pub fn new(player: Index) -> Self {
-        Self {
+    pub fn new(cvars: &Cvars, player: Index, difficulty: u32) -> Self {
+        let mut ai = Self {
             player,
+            difficulty: difficulty.min(BOTS_DIFFICULTY_MAX),
+            perceptions: VecDeque::new(),
             movement: 0,
             turning: 0,
             firing: false,
-        }
+            firing_decision_time: 0.0,
+            reaction_time: 0.0,
+            aim_error: 0.0,
+            reaction_time_scale: 1.0,
+            aim_error_scale: 1.0,
+        };
+        ai.reaction_time = ai.base_reaction_time(cvars);
+        ai.aim_error = ai.base_aim_error(cvars);
+        ai
+    }
+
+    /// Multiplier of reaction time, aim error and fire alignment.
+    /// 1 at difficulty 2, doubles every 2 levels easier and halves every 2 levels harder.
+    pub fn difficulty_scale(&self) -> f64 {
+        2_f64.powf(1.0 - f64::from(self.difficulty) / 2.0)
+    }
+
+    /// Fraction of the correct lead applied to moving targets - none at 0, perfect at max difficulty.
+    pub fn lead_quality(&self) -> f64 {
+        f64::from(self.difficulty) / f64::from(BOTS_DIFFICULTY_MAX)
+    }
+
+    /// Reaction time before DDA.
+    pub fn base_reaction_time(&self, cvars: &Cvars) -> f64 {
+        cvars.bots_reaction_time * self.reaction_time_scale * self.difficulty_scale()
+    }
+
+    /// Aim error before DDA.
+    pub fn base_aim_error(&self, cvars: &Cvars) -> f64 {
+        cvars.bots_aim_error * self.aim_error_scale * self.difficulty_scale()
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Vehicle {
-    pub pos: Vec2f,
+    pub pos: Tracked<Vec2f>,
     pub vel: Vec2f,
-    pub angle: f64,
+    /// How far `vehicle_movement` and getting pushed by other vehicles moved it this frame,
+    /// shots fired during the frame start from the matching point along it.
+    pub displacement: Vec2f,
+    pub angle: Tracked<f64>,
     pub turn_rate: f64,
+    /// How much `vehicle_movement` uses water movement stats instead of land ones, 0 to 1.
+    /// Moves towards the surface under the vehicle's center over `g_vehicle_surface_blend_time`.
+    pub water_blend: f64,
     pub veh_type: VehicleType,
     pub hitbox: Hitbox,
+    /// `hitbox` at the current `pos` and `angle`.
+    /// Anything which changes them has to call `update_obb` right after
+    /// so the rest of the frame sees the new box.
+    pub obb: Obb,
     /// Angle from vehicle, see Coord system for more
     pub turret_angle_current: f64,
     pub turret_angle_wanted: f64,
-    /// HP between 0 and 1 - saving the fraction here instead of absolute hit points so armor cvars can be adjusted during a match.
-    pub hp_fraction: f64,
+    /// Hit points in milli-hp, see `fixed`.
+    /// Changing the max HP cvars during a match only affects newly spawned vehicles.
+    pub hp: MilliHp,
+    /// Rounding error of damage over time carried over to the next tick, see `fixed::rate`.
+    pub damage_remainder: f64,
+    pub armor: Armor,
     /// Each weapon has a separate reload status even if they all reload at the same time.
     /// I plan to generalize this and have a cvar to choose between multiple reload mechanisms.
     pub ammos: Vec<Ammo>,
+    /// Ammo left for reloads per weapon, see `g_weapon_reserve`. It's taken when a reload finishes.
+    pub reserves: Vec<u32>,
+    /// When the railgun started charging, see `g_railgun_charge_time`.
+    pub rail_charge_start: Option<f64>,
+    /// When each cooldown group can fire again, groups which aren't listed are ready.
+    pub group_cooldowns: Vec<(CooldownGroup, f64)>,
+    /// Time of the last shot from any weapon, for `g_fire_rate_cap`.
+    pub last_shot_time: f64,
+    /// At most one of each kind, expired ones are removed by `systems::pickups`.
+    pub powerups: Vec<Powerup>,
+    /// Flares left, see `sys_homing`.
+    pub flares: u32,
+    /// Game time when the next flare can be deployed.
+    pub flare_ready: f64,
     pub spawn_time: f64,
     pub owner: Index,
+    /// Training range target - no AI or weapons, see `sys_range`.
+    pub is_dummy: bool,
 }
 
 impl Vehicle {
@@ -112,56 +313,508 @@ impl Vehicle {
         pos: Vec2f,
         angle: f64,
         veh_type: VehicleType,
+        frame_num: u64,
         spawn_time: f64,
         owner: Index,
     ) -> Vehicle {
         let hitbox = cvars.g_vehicle_hitbox(veh_type);
         let ammos = vec![
-            Ammo::Loaded(0.0, cvars.g_weapon_reload_ammo(Weapon::Mg)),
-            Ammo::Loaded(0.0, cvars.g_weapon_reload_ammo(Weapon::Rail)),
-            Ammo::Loaded(0.0, cvars.g_weapon_reload_ammo(Weapon::Cb)),
-            Ammo::Loaded(0.0, cvars.g_weapon_reload_ammo(Weapon::Rockets)),
-            Ammo::Loaded(0.0, cvars.g_weapon_reload_ammo(Weapon::Hm)),
-            Ammo::Loaded(0.0, cvars.g_weapon_reload_ammo(Weapon::Gm)),
-            Ammo::Loaded(0.0, cvars.g_weapon_reload_ammo(Weapon::Bfg)),
+            Ammo::new(cvars, Weapon::Mg),
+            Ammo::new(cvars, Weapon::Rail),
+            Ammo::new(cvars, Weapon::Cb),
+            Ammo::new(cvars, Weapon::Rockets),
+            Ammo::new(cvars, Weapon::Hm),
+            Ammo::new(cvars, Weapon::Gm),
+            Ammo::new(cvars, Weapon::Bfg),
         ];
+        let reserves = Weapon::all()
+            .map(|weapon| cvars.g_weapon_reserve(weapon, veh_type))
+            .collect();
 
         Vehicle {
-            pos,
+            pos: Tracked::new(pos, frame_num),
             vel: Vec2f::zero(),
-            angle,
+            displacement: Vec2f::zero(),
+            angle: Tracked::new(angle, frame_num),
             turn_rate: 0.0,
+            water_blend: 0.0,
             veh_type,
             hitbox,
+            obb: Obb::new(hitbox, pos, angle),
             turret_angle_current: 0.0,
             turret_angle_wanted: 0.0,
-            hp_fraction: 1.0,
+            hp: fixed::to_milli(cvars.g_vehicle_hp(veh_type)),
+            damage_remainder: 0.0,
+            armor: Armor::new(cvars, veh_type),
             ammos,
+            reserves,
+            rail_charge_start: None,
+            group_cooldowns: Vec::new(),
+            last_shot_time: f64::NEG_INFINITY,
+            powerups: Vec::new(),
+            flares: cvars.g_flare_count,
+            flare_ready: 0.0,
             spawn_time,
             owner,
+            is_dummy: false,
         }
     }
 
     pub fn destroyed(&self) -> bool {
-        self.hp_fraction <= 0.0
+        self.hp <= 0
+    }
+
+    pub fn hp_max(&self, cvars: &Cvars) -> MilliHp {
+        let hp_max = if self.is_dummy {
+            cvars.g_range_dummy_hp
+        } else {
+            cvars.g_vehicle_hp(self.veh_type)
+        };
+        fixed::to_milli(hp_max)
+    }
+
+    /// HP between 0 and 1, for presentation and bots.
+    /// Recompute the cached `obb` after changing `pos`, `angle` or `hitbox`.
+    pub fn update_obb(&mut self) {
+        self.obb = Obb::new(self.hitbox, *self.pos, *self.angle);
+    }
+
+    pub fn hp_fraction(&self, cvars: &Cvars) -> f64 {
+        self.hp as f64 / self.hp_max(cvars) as f64
+    }
+
+    /// What the HUD shows about the weapon.
+    pub fn weapon_status(&self, cvars: &Cvars, weapon: Weapon, game_time: f64) -> WeaponStatus {
+        let (ammo, reload_progress, heat) = match self.ammos[weapon as usize] {
+            Ammo::Loaded(_ready_time, count) => (count, None, None),
+            Ammo::Reloading(start, end) => (0, Some((game_time - start) / (end - start)), None),
+            Ammo::Heat(_ready_time, heat, locked_until) => {
+                // Cooling down after overheating is shown like reloading.
+                let progress = if game_time < locked_until {
+                    let cooled = (1.0 - heat) / (1.0 - cvars.g_weapon_heat_unlock);
+                    Some(cooled.clamp(0.0, 1.0))
+                } else {
+                    None
+                };
+                (0, progress, Some(heat))
+            }
+        };
+        WeaponStatus {
+            weapon,
+            ammo,
+            ammo_max: cvars.g_weapon_reload_ammo(weapon),
+            reserve: self.reserves[weapon as usize],
+            reload_progress,
+            heat,
+        }
+    }
+
+    /// Nothing in the magazine and nothing to reload it with - only an ammo pickup helps.
+    ///
+    /// Weapons limited by heat are never empty, when locked out they're like reloading ones.
+    pub fn weapon_empty(&self, weapon: Weapon) -> bool {
+        self.reserves[weapon as usize] == 0
+            && matches!(self.ammos[weapon as usize], Ammo::Loaded(_, 0))
+    }
+
+    /// Refill the weapon's reserve to its maximum.
+    /// Returns whether anything changed.
+    pub fn refill_reserve(&mut self, cvars: &Cvars, weapon: Weapon) -> bool {
+        let max = cvars.g_weapon_reserve(weapon, self.veh_type);
+        let reserve = &mut self.reserves[weapon as usize];
+        if *reserve >= max {
+            return false;
+        }
+        *reserve = max;
+        true
+    }
+
+    /// Start reloading the weapon if the magazine is empty and there's reserve ammo for it.
+    pub fn start_reload(&mut self, cvars: &Cvars, weapon: Weapon, time: f64) {
+        let ammo = &mut self.ammos[weapon as usize];
+        if let Ammo::Loaded(_, 0) = ammo {
+            if self.reserves[weapon as usize] > 0 {
+                let reload_time = cvars.g_weapon_reload_time(weapon);
+                *ammo = Ammo::Reloading(time, time + reload_time);
+            }
+        }
+    }
+
+    /// Finish reloading by moving up to a magazine from the reserve.
+    /// Without reserve the weapon stays empty.
+    pub fn finish_reload(&mut self, cvars: &Cvars, weapon: Weapon, time: f64) {
+        let reserve = &mut self.reserves[weapon as usize];
+        let count = cvars.g_weapon_reload_ammo(weapon).min(*reserve);
+        if *reserve != RESERVE_UNLIMITED {
+            *reserve -= count;
+        }
+        self.ammos[weapon as usize] = Ammo::Loaded(time, count);
+    }
+
+    /// Cool down all weapons limited by heat, the lockout ends on its own (see `Ammo::Heat`).
+    pub fn dissipate_heat(&mut self, cvars: &Cvars, dt: f64) {
+        for weapon in Weapon::all() {
+            if let Ammo::Heat(_, heat, _) = &mut self.ammos[weapon as usize] {
+                *heat = (*heat - cvars.g_weapon_heat_dissipation(weapon) * dt).max(0.0);
+            }
+        }
+    }
+
+    /// Earliest time `weapon` can fire according to its cooldown group and `g_fire_rate_cap`.
+    ///
+    /// The weapon's own refire and reloading are in `ammos`, this is checked in addition to them.
+    pub fn fire_limits_ready(&self, cvars: &Cvars, weapon: Weapon) -> f64 {
+        let group = cvars.g_weapon_cooldown_group(weapon);
+        let group_ready = self
+            .group_cooldowns
+            .iter()
+            .find(|&&(g, _)| g == group)
+            .map_or(f64::NEG_INFINITY, |&(_, ready)| ready);
+        let cap_ready = self.last_shot_time + 1.0 / cvars.g_fire_rate_cap;
+        group_ready.max(cap_ready)
+    }
+
+    /// Start the group cooldown and the fire rate cap interval after a shot.
+    pub fn fire_limits_shot(&mut self, cvars: &Cvars, weapon: Weapon, shot_time: f64) {
+        self.last_shot_time = shot_time;
+        let group = cvars.g_weapon_cooldown_group(weapon);
+        if group == CooldownGroup::None {
+            return;
+        }
+        let ready = shot_time + cvars.g_cooldown_group(group);
+        match self.group_cooldowns.iter_mut().find(|(g, _)| *g == group) {
+            Some(entry) => entry.1 = ready,
+            None => self.group_cooldowns.push((group, ready)),
+        }
+    }
+
+    /// The powerup of this kind if it's still active at `time`.
+    pub fn powerup(&self, kind: PowerupKind, time: f64) -> Option<&Powerup> {
+        self.powerups
+            .iter()
+            .find(|powerup| powerup.kind == kind && powerup.active(time))
+    }
+
+    /// Collecting a powerup which is already active restarts it instead of stacking.
+    pub fn add_powerup(&mut self, cvars: &Cvars, kind: PowerupKind, time: f64) {
+        self.powerups.retain(|powerup| powerup.kind != kind);
+        let shield = match kind {
+            PowerupKind::Shield => fixed::to_milli(cvars.g_powerup_shield_hp),
+            _ => 0,
+        };
+        self.powerups.push(Powerup {
+            kind,
+            until: time + cvars.g_powerup_duration(kind),
+            shield,
+        });
+    }
+
+    pub fn expire_powerups(&mut self, time: f64) {
+        self.powerups.retain(|powerup| powerup.active(time));
+    }
+
+    /// Multiplier of forward acceleration and max speed.
+    pub fn speed_multiplier(&self, cvars: &Cvars, time: f64) -> f64 {
+        match self.powerup(PowerupKind::SpeedBoost, time) {
+            Some(_) => cvars.g_powerup_speed_boost_multiplier,
+            None => 1.0,
+        }
+    }
+
+    /// Multiplier of the time between shots.
+    pub fn refire_multiplier(&self, cvars: &Cvars, time: f64) -> f64 {
+        match self.powerup(PowerupKind::RapidFire, time) {
+            Some(_) => cvars.g_powerup_rapid_fire_refire_multiplier,
+            None => 1.0,
+        }
+    }
+
+    /// Let an active shield take as much of `amount` as it can.
+    /// Returns the rest which goes through to hp.
+    ///
+    /// The shield is gone once it's used up, even if it still has time left.
+    pub fn shield_absorb(&mut self, amount: MilliHp, time: f64) -> MilliHp {
+        let index = self
+            .powerups
+            .iter()
+            .position(|powerup| powerup.kind == PowerupKind::Shield && powerup.active(time));
+        let index = match index {
+            Some(index) => index,
+            None => return amount,
+        };
+        let shield = &mut self.powerups[index].shield;
+        let absorbed = amount.clamp(0, *shield);
+        *shield -= absorbed;
+        if *shield == 0 {
+            self.powerups.remove(index);
+        }
+        amount - absorbed
     }
 }
 
-#[derive(Debug, Clone, Copy, N)]
+/// Current weapon, ammo and reload progress for the HUD, see `Vehicle::weapon_status`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WeaponStatus {
+    pub weapon: Weapon,
+    pub ammo: u32,
+    pub ammo_max: u32,
+    /// `RESERVE_UNLIMITED` if it doesn't run out.
+    pub reserve: u32,
+    /// Between 0 and 1 while reloading or cooling down after overheating.
+    pub reload_progress: Option<f64>,
+    /// Between 0 and 1 for weapons limited by heat, they have no ammo count.
+    pub heat: Option<f64>,
+}
+
+impl WeaponStatus {
+    /// How full the ammo bar is - heat, remaining ammo or how far along reloading is.
+    pub fn fraction(&self) -> f64 {
+        match (self.heat, self.reload_progress) {
+            (Some(heat), _) => heat,
+            (None, Some(progress)) => progress,
+            (None, None) => f64::from(self.ammo) / f64::from(self.ammo_max),
+        }
+    }
+}
+
+/// Which part of a vehicle got hit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Facing {
+    Front,
+    Side,
+    Rear,
+    Turret,
+}
+
+/// Damage multipliers for each facing, lower is better.
+///
+/// Starts with the values from cvars for the vehicle type, pickups can improve it.
+/// A new vehicle gets a new `Armor` so respawning resets it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Armor {
+    pub front: f64,
+    pub side: f64,
+    pub rear: f64,
+    pub turret: f64,
+}
+
+impl Armor {
+    /// The order in which facings get upgraded when they're equally weak.
+    /// Rear first because that's where you get hit when running away.
+    pub const UPGRADE_ORDER: [Facing; 4] =
+        [Facing::Rear, Facing::Side, Facing::Front, Facing::Turret];
+
+    pub fn new(cvars: &Cvars, veh_type: VehicleType) -> Self {
+        Self {
+            front: cvars.g_vehicle_facing(veh_type, Facing::Front),
+            side: cvars.g_vehicle_facing(veh_type, Facing::Side),
+            rear: cvars.g_vehicle_facing(veh_type, Facing::Rear),
+            turret: cvars.g_vehicle_facing(veh_type, Facing::Turret),
+        }
+    }
+
+    pub fn get(&self, facing: Facing) -> f64 {
+        match facing {
+            Facing::Front => self.front,
+            Facing::Side => self.side,
+            Facing::Rear => self.rear,
+            Facing::Turret => self.turret,
+        }
+    }
+
+    fn get_mut(&mut self, facing: Facing) -> &mut f64 {
+        match facing {
+            Facing::Front => &mut self.front,
+            Facing::Side => &mut self.side,
+            Facing::Rear => &mut self.rear,
+            Facing::Turret => &mut self.turret,
+        }
+    }
+
+    /// Lower the multiplier of the weakest facing by `step` but not below `min`.
+    ///
+    /// Returns the upgraded facing or `None` if all are already at `min`.
+    pub fn upgrade(&mut self, step: f64, min: f64) -> Option<Facing> {
+        let mut weakest = None;
+        for facing in Self::UPGRADE_ORDER {
+            let value = self.get(facing);
+            if value <= min {
+                continue;
+            }
+            match weakest {
+                Some(w) if self.get(w) >= value => {}
+                _ => weakest = Some(facing),
+            }
+        }
+
+        let facing = weakest?;
+        let value = self.get_mut(facing);
+        *value = (*value - step).max(min);
+        Some(facing)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PickupKind {
+    /// Placed randomly, collected ones are replaced elsewhere.
+    Armor,
+    /// Placed by the map, refills the current weapon, then respawns in place.
+    Ammo,
+    /// Placed by the map, repairs vehicles standing on it, never runs out.
+    Repair,
+    /// Placed by the map, gives a temporary powerup, then respawns in place.
+    Powerup(PowerupKind),
+}
+
+#[derive(Debug, Clone)]
+pub struct Pickup {
+    pub pos: Vec2f,
+    pub kind: PickupKind,
+    /// Game time when a collected ammo crate or powerup becomes active again, `None` while active.
+    pub respawn: Option<f64>,
+}
+
+impl Pickup {
+    pub fn new(pos: Vec2f, kind: PickupKind) -> Self {
+        Self {
+            pos,
+            kind,
+            respawn: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerupKind {
+    /// Faster acceleration and higher top speed.
+    SpeedBoost,
+    /// Shorter time between shots, reloading is unaffected.
+    RapidFire,
+    /// Absorbs damage until it runs out of time or hp.
+    Shield,
+}
+
+impl PowerupKind {
+    pub const ALL: [PowerupKind; 3] = [
+        PowerupKind::SpeedBoost,
+        PowerupKind::RapidFire,
+        PowerupKind::Shield,
+    ];
+
+    /// Human readable name for the HUD.
+    pub fn name(self) -> &'static str {
+        match self {
+            PowerupKind::SpeedBoost => "Speed boost",
+            PowerupKind::RapidFire => "Rapid fire",
+            PowerupKind::Shield => "Shield",
+        }
+    }
+}
+
+/// A temporary powerup on a vehicle, see `g_powerup_*`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Powerup {
+    pub kind: PowerupKind,
+    /// Game time when it runs out.
+    pub until: f64,
+    /// What the shield can still absorb in milli-hp, 0 for other kinds.
+    pub shield: MilliHp,
+}
+
+impl Powerup {
+    /// Active until the frame when game time reaches `until`.
+    pub fn active(&self, time: f64) -> bool {
+        time < self.until
+    }
+}
+
+/// A value which remembers in which gamelogic frame it last changed
+/// so systems can skip work for entities which didn't change.
+///
+/// The only way to change it is `set` so it's impossible to forget to update the frame number.
+#[derive(Debug, Clone, Copy)]
+pub struct Tracked<T> {
+    value: T,
+    changed: u64,
+}
+
+impl<T: Copy> Tracked<T> {
+    pub fn new(value: T, frame_num: u64) -> Self {
+        Self {
+            value,
+            changed: frame_num,
+        }
+    }
+
+    pub fn set(&mut self, value: T, frame_num: u64) {
+        self.value = value;
+        self.changed = frame_num;
+    }
+
+    /// The frame in which the value was last set.
+    pub fn changed(&self) -> u64 {
+        self.changed
+    }
+
+    /// Was the value set during or after `frame_num`?
+    pub fn changed_since(&self, frame_num: u64) -> bool {
+        self.changed >= frame_num
+    }
+}
+
+impl<T> Deref for Tracked<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, N)]
 pub enum VehicleType {
     Tank,
     Hovercraft,
     Hummer,
 }
 
-#[derive(Debug, Clone, Copy)]
+impl VehicleType {
+    pub fn all() -> impl Iterator<Item = VehicleType> {
+        (0..3).map(|num| VehicleType::n(num).unwrap())
+    }
+
+    /// Lowercase name for the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            VehicleType::Tank => "tank",
+            VehicleType::Hovercraft => "hovercraft",
+            VehicleType::Hummer => "hummer",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Ammo {
     /// Refire delay end time, ammo count remaining
     Loaded(f64, u32),
     /// Start time, end time
     Reloading(f64, f64),
+    /// Refire delay end time, heat (locks out at 1), lockout end time (in the past when not locked out)
+    Heat(f64, f64, f64),
 }
 
+impl Ammo {
+    /// Ready to fire - a full magazine or a cold barrel depending on `g_weapon_fire_limiter`.
+    pub fn new(cvars: &Cvars, weapon: Weapon) -> Self {
+        match cvars.g_weapon_fire_limiter(weapon) {
+            FireLimiter::Magazine => Ammo::Loaded(0.0, cvars.g_weapon_reload_ammo(weapon)),
+            FireLimiter::Heat => Ammo::Heat(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// Reserve ammo of weapons which never run out.
+pub const RESERVE_UNLIMITED: u32 = u32::MAX;
+
 #[derive(Debug, Clone)]
 pub struct Projectile {
     pub weapon: Weapon,
@@ -170,6 +823,39 @@ pub struct Projectile {
     pub angle: f64,
     pub turn_rate: f64,
     pub explode_time: f64,
+    /// Game time when it was fired, see `Cvars::g_weapon_lifetime` and `g_projectiles_max`.
+    pub spawn_time: f64,
+    pub owner: Index,
+    /// What a homing missile flies towards, always `None` for other weapons. See `sys_homing`.
+    pub lock: Option<Lock>,
+    /// Game time when a homing missile chooses its target again.
+    pub retarget_time: f64,
+    /// Only for weapons which fly in an arc (see `Cvars::g_weapon_arc_gravity`),
+    /// `None` for those which travel along the ground.
+    pub altitude: Option<Altitude>,
+}
+
+/// Height of a projectile flying in an arc. While above the ground, it passes over vehicles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Altitude {
+    pub z: f64,
+    /// Vertical velocity, positive is up.
+    pub vz: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lock {
+    Vehicle(Index),
+    /// Kept until the flare burns out.
+    Flare(Index),
+}
+
+/// Dropped by vehicles to decoy homing missiles, see `sys_homing`.
+#[derive(Debug, Clone)]
+pub struct Flare {
+    pub pos: Vec2f,
+    /// Burns out `g_flare_lifetime` after this.
+    pub spawn_time: f64,
     pub owner: Index,
 }
 
@@ -187,6 +873,41 @@ pub enum Weapon {
     Bfg,
 }
 
+impl Weapon {
+    pub fn all() -> impl Iterator<Item = Weapon> {
+        (0..WEAPS_CNT).map(|num| Weapon::n(num).unwrap())
+    }
+
+    /// Human readable name for the HUD.
+    pub fn name(self) -> &'static str {
+        match self {
+            Weapon::Mg => "Machine gun",
+            Weapon::Rail => "Railgun",
+            Weapon::Cb => "Cluster bombs",
+            Weapon::Rockets => "Rockets",
+            Weapon::Hm => "Homing missile",
+            Weapon::Gm => "Guided missile",
+            Weapon::Bfg => "BFG",
+        }
+    }
+
+    /// Can hit the vehicle that fired it once `g_self_collision_delay` is over -
+    /// guided missiles can be steered back, rockets can be driven into.
+    pub fn can_hit_owner(self) -> bool {
+        matches!(self, Weapon::Rockets | Weapon::Gm)
+    }
+
+    /// Slow enough to be pushed around by wind - MG, railgun and BFG behave more like hitscan.
+    pub fn is_airborne(self) -> bool {
+        matches!(self, Weapon::Cb | Weapon::Rockets | Weapon::Hm | Weapon::Gm)
+    }
+
+    /// Recoils harder and kicks the camera, see `feedback::Recoils`.
+    pub fn is_heavy(self) -> bool {
+        matches!(self, Weapon::Rail | Weapon::Bfg)
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Hitbox {
     pub mins: Vec2f,
@@ -202,3 +923,67 @@ impl Hitbox {
         [back_left, front_left, front_right, back_right]
     }
 }
+
+/// Oriented bounding box - a `Hitbox` placed in the world
+/// and the axis-aligned box around it for cheap broad-phase rejects.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Obb {
+    /// In `Hitbox::corners` order.
+    pub corners: [Vec2f; 4],
+    pub mins: Vec2f,
+    pub maxs: Vec2f,
+}
+
+impl Obb {
+    pub fn new(hitbox: Hitbox, pos: Vec2f, angle: f64) -> Self {
+        let corners = hitbox.corners(pos, angle);
+        let mut mins = corners[0];
+        let mut maxs = corners[0];
+        for &corner in &corners[1..] {
+            mins = Vec2f::partial_min(mins, corner);
+            maxs = Vec2f::partial_max(maxs, corner);
+        }
+        Self {
+            corners,
+            mins,
+            maxs,
+        }
+    }
+
+    /// Whether the axis-aligned box overlaps the rectangle, edges touching counts.
+    ///
+    /// When this is false, none of the precise tests can succeed.
+    pub fn aabb_overlaps(&self, mins: Vec2f, maxs: Vec2f) -> bool {
+        self.mins.x <= maxs.x
+            && mins.x <= self.maxs.x
+            && self.mins.y <= maxs.y
+            && mins.y <= self.maxs.y
+    }
+
+    /// Distance from `pos` to the nearest point of the box, 0 inside.
+    pub fn distance(&self, pos: Vec2f) -> f64 {
+        let mut positive = 0;
+        let mut negative = 0;
+        let mut dist = f64::INFINITY;
+        for i in 0..4 {
+            let start = self.corners[i];
+            let end = self.corners[(i + 1) % 4];
+            let edge = end - start;
+            let to_pos = pos - start;
+            let cross = edge.x * to_pos.y - edge.y * to_pos.x;
+            if cross >= 0.0 {
+                positive += 1;
+            }
+            if cross <= 0.0 {
+                negative += 1;
+            }
+            dist = dist.min(LineSegment2 { start, end }.distance_to_point(pos));
+        }
+        // Inside means the same side of all edges, whichever way the corners wind.
+        if positive == 4 || negative == 4 {
+            0.0
+        } else {
+            dist
+        }
+    }
+}
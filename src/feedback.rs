@@ -0,0 +1,365 @@
+//! Screen shake, recoil and hit-stop - client-side feedback which makes explosions, shots and deaths feel heavier.
+//!
+//! None of them change the game state. Shake and recoil only move what's drawn,
+//! hit-stop only changes how fast the server advances game time (like `Server::time_scale`),
+//! not what it simulates, so replays and lockstep stay deterministic.
+
+use fnv::FnvHashMap;
+use rand::{prelude::SmallRng, Rng};
+use thunderdome::Index;
+
+use crate::{
+    cvars::{Cvars, Hardpoint},
+    effects::Effect,
+    game_state::GameState,
+    map::{F64Ext, Vec2f},
+};
+
+/// Camera shake of one viewport.
+#[derive(Debug, Clone)]
+pub struct Shake {
+    /// Between 0 and 1, the offset is proportional to its square
+    /// so small amounts barely move the view and large ones clearly do.
+    pub trauma: f64,
+    /// Unit vector against the last heavy shot of the viewport's vehicle, zero if there's none.
+    pub kick: Vec2f,
+    /// Explosions which started before this game time have already been added.
+    pub seen_until: f64,
+    /// A copy of the effects stream - drawing from it doesn't touch the game state.
+    pub rng: SmallRng,
+}
+
+impl Shake {
+    pub fn new(gs: &GameState) -> Self {
+        Self {
+            trauma: 0.0,
+            kick: Vec2f::zero(),
+            seen_until: gs.game_time,
+            rng: gs.rng.effects.rng.clone(),
+        }
+    }
+
+    /// Add trauma for explosions and heavy shots of `vehicle` which started since the last call.
+    ///
+    /// Each explosion adds `cl_shake_trauma` times its scale, falling off linearly
+    /// to nothing at `cl_shake_radius` from `camera_center`. Each shot adds `cl_recoil_kick`.
+    pub fn add_effects(
+        &mut self,
+        cvars: &Cvars,
+        gs: &GameState,
+        camera_center: Vec2f,
+        vehicle: Option<Index>,
+    ) {
+        for effect in &gs.effects {
+            if effect.start_time() <= self.seen_until {
+                continue;
+            }
+            match *effect {
+                Effect::Explosion { pos, scale, .. } => {
+                    self.add_trauma(cvars, pos.distance(camera_center), scale);
+                }
+                Effect::MuzzleFlash {
+                    angle,
+                    weapon,
+                    vehicle: shooter,
+                    ..
+                } if cvars.cl_recoil && weapon.is_heavy() && Some(shooter) == vehicle => {
+                    self.trauma = (self.trauma + cvars.cl_recoil_kick).min(1.0);
+                    self.kick = -angle.to_vec2f();
+                }
+                _ => {}
+            }
+        }
+        self.seen_until = gs.game_time;
+    }
+
+    pub fn add_trauma(&mut self, cvars: &Cvars, dist: f64, scale: f64) {
+        if dist >= cvars.cl_shake_radius {
+            return;
+        }
+        let falloff = 1.0 - dist / cvars.cl_shake_radius;
+        self.trauma = (self.trauma + cvars.cl_shake_trauma * scale * falloff).min(1.0);
+    }
+
+    /// `dt` is real time so the shake stops at the same speed in slow motion.
+    pub fn decay(&mut self, cvars: &Cvars, dt: f64) {
+        self.trauma = (self.trauma - cvars.cl_shake_decay * dt).max(0.0);
+        if self.trauma == 0.0 {
+            self.kick = Vec2f::zero();
+        }
+    }
+
+    /// Random displacement of the camera for this frame, zero when disabled.
+    pub fn offset(&mut self, cvars: &Cvars) -> Vec2f {
+        if !cvars.cl_shake || self.trauma <= 0.0 {
+            return Vec2f::zero();
+        }
+        let amount = self.trauma * self.trauma * cvars.cl_shake_max_offset;
+        let x = self.rng.gen_range(-1.0..=1.0);
+        let y = self.rng.gen_range(-1.0..=1.0);
+        let bias = if self.kick == Vec2f::zero() {
+            0.0
+        } else {
+            cvars.cl_recoil_kick_bias
+        };
+        (Vec2f::new(x, y) * (1.0 - bias) + self.kick * bias) * amount
+    }
+}
+
+/// How far one vehicle's sprites are pushed back by its last shot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Recoil {
+    /// Game time of the shot.
+    pub time: f64,
+    /// Displacement right after the shot, zero if the weapon isn't on the chassis.
+    pub chassis: Vec2f,
+    /// On top of the chassis, zero if the weapon isn't on the turret.
+    pub turret: Vec2f,
+}
+
+/// Recoil of all vehicles, made from muzzle flashes.
+#[derive(Debug, Clone, Default)]
+pub struct Recoils {
+    pub vehicles: FnvHashMap<Index, Recoil>,
+    /// Muzzle flashes which started before this game time have already been added.
+    pub seen_until: f64,
+}
+
+impl Recoils {
+    /// Push vehicles back for shots since the last call and forget those which have settled.
+    pub fn add_flashes(&mut self, cvars: &Cvars, gs: &GameState) {
+        for effect in &gs.effects {
+            let (angle, weapon, handle, start_time) = match *effect {
+                Effect::MuzzleFlash {
+                    angle,
+                    weapon,
+                    vehicle,
+                    start_time,
+                    ..
+                } if start_time > self.seen_until => (angle, weapon, vehicle, start_time),
+                _ => continue,
+            };
+            let vehicle = match gs.vehicles.get(handle) {
+                Some(vehicle) => vehicle,
+                None => continue,
+            };
+            let distance = if weapon.is_heavy() {
+                cvars.cl_recoil_distance_heavy
+            } else {
+                cvars.cl_recoil_distance
+            };
+            let push = -angle.to_vec2f() * distance;
+            // If some barrels are on the chassis, the turret moves with it.
+            let hardpoints = cvars.g_hardpoints(vehicle.veh_type, weapon).barrels();
+            let on_chassis = hardpoints.iter().any(|&(hp, _)| hp == Hardpoint::Chassis);
+            let (chassis, turret) = if on_chassis {
+                (push, Vec2f::zero())
+            } else {
+                (Vec2f::zero(), push)
+            };
+            // Each shot replaces what's left of the previous one so fast weapons don't drift away.
+            let recoil = Recoil {
+                time: start_time,
+                chassis,
+                turret,
+            };
+            self.vehicles.insert(handle, recoil);
+        }
+        self.seen_until = gs.game_time;
+
+        // Less than 1% left.
+        let settled = 5.0 * cvars.cl_recoil_time_constant;
+        self.vehicles.retain(|&handle, recoil| {
+            gs.vehicles.contains(handle) && gs.game_time - recoil.time < settled
+        });
+    }
+
+    /// Current chassis and turret displacement of `vehicle`.
+    pub fn offsets(&self, cvars: &Cvars, vehicle: Index, game_time: f64) -> (Vec2f, Vec2f) {
+        let recoil = match self.vehicles.get(&vehicle) {
+            Some(recoil) if cvars.cl_recoil => recoil,
+            _ => return (Vec2f::zero(), Vec2f::zero()),
+        };
+        let age = (game_time - recoil.time).max(0.0);
+        let remaining = (-age / cvars.cl_recoil_time_constant).exp();
+        (recoil.chassis * remaining, recoil.turret * remaining)
+    }
+}
+
+/// Brief slow motion when a local player's vehicle is destroyed.
+#[derive(Debug, Clone, Default)]
+pub struct HitStop {
+    /// Real time when it started.
+    pub start: Option<f64>,
+}
+
+impl HitStop {
+    pub fn start(&mut self, cvars: &Cvars, real_time: f64) {
+        if cvars.cl_hit_stop {
+            self.start = Some(real_time);
+        }
+    }
+
+    /// Multiplier of the game speed - `cl_hit_stop_scale` for `cl_hit_stop_duration` seconds,
+    /// then ramping linearly back to 1 over `cl_hit_stop_ramp` seconds.
+    pub fn scale(&mut self, cvars: &Cvars, real_time: f64) -> f64 {
+        let start = match self.start {
+            Some(start) if cvars.cl_hit_stop => start,
+            _ => return 1.0,
+        };
+        let elapsed = real_time - start;
+        if elapsed < cvars.cl_hit_stop_duration {
+            return cvars.cl_hit_stop_scale;
+        }
+        let ramp = (elapsed - cvars.cl_hit_stop_duration) / cvars.cl_hit_stop_ramp;
+        if ramp >= 1.0 || ramp.is_nan() {
+            self.start = None;
+            return 1.0;
+        }
+        cvars.cl_hit_stop_scale + (1.0 - cvars.cl_hit_stop_scale) * ramp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use thunderdome::Arena;
+
+    use super::*;
+
+    use crate::entities::{Vehicle, VehicleType, Weapon};
+
+    #[test]
+    fn test_trauma() {
+        let cvars = Cvars::default();
+        let mut shake = Shake::new(&GameState::new(1));
+
+        // Closer and bigger explosions shake more, outside the radius nothing.
+        shake.add_trauma(&cvars, cvars.cl_shake_radius, 1.0);
+        assert_eq!(shake.trauma, 0.0);
+        shake.add_trauma(&cvars, cvars.cl_shake_radius / 2.0, 1.0);
+        let half = shake.trauma;
+        assert!((half - cvars.cl_shake_trauma / 2.0).abs() < 1e-9);
+        shake.trauma = 0.0;
+        shake.add_trauma(&cvars, cvars.cl_shake_radius / 2.0, 0.5);
+        assert!((shake.trauma - half / 2.0).abs() < 1e-9);
+
+        // Accumulates up to 1.
+        for _ in 0..100 {
+            shake.add_trauma(&cvars, 0.0, 1.0);
+        }
+        assert_eq!(shake.trauma, 1.0);
+        let max = shake.offset(&cvars);
+        assert!(max.x.abs() <= cvars.cl_shake_max_offset);
+        assert!(max.y.abs() <= cvars.cl_shake_max_offset);
+
+        // Decays linearly and the offset with its square.
+        shake.decay(&cvars, 0.5 / cvars.cl_shake_decay);
+        assert!((shake.trauma - 0.5).abs() < 1e-9);
+        let offset = shake.offset(&cvars);
+        assert!(offset.x.abs() <= cvars.cl_shake_max_offset / 4.0);
+        assert!(offset.y.abs() <= cvars.cl_shake_max_offset / 4.0);
+        shake.decay(&cvars, 10.0);
+        assert_eq!(shake.trauma, 0.0);
+        assert_eq!(shake.offset(&cvars), Vec2f::zero());
+    }
+
+    #[test]
+    fn test_explosions_added_once() {
+        let cvars = Cvars::default();
+        let mut gs = GameState::new(1);
+        let mut shake = Shake::new(&gs);
+        let mut effects_rng = gs.rng.effects.clone();
+
+        gs.game_time = 1.0;
+        gs.effects.push(Effect::Explosion {
+            pos: Vec2f::new(10.0, 0.0),
+            scale: 1.0,
+            start_time: 1.0,
+            bfg: false,
+        });
+        shake.add_effects(&cvars, &gs, Vec2f::zero(), None);
+        let trauma = shake.trauma;
+        assert!(trauma > 0.0);
+        gs.game_time = 1.1;
+        shake.add_effects(&cvars, &gs, Vec2f::zero(), None);
+        assert_eq!(shake.trauma, trauma);
+
+        // The shake doesn't touch the game's RNG.
+        shake.offset(&cvars);
+        assert_eq!(gs.rng.effects.gen::<u64>(), effects_rng.gen::<u64>());
+    }
+
+    #[test]
+    fn test_recoil() {
+        let cvars = Cvars::default();
+        let mut gs = GameState::new(1);
+        let owner = Arena::<()>::new().insert(());
+        let vehicle = Vehicle::new(&cvars, Vec2f::zero(), 0.0, VehicleType::Tank, 0, 0.0, owner);
+        let handle = gs.vehicles.insert(vehicle);
+        let mut shake = Shake::new(&gs);
+        let mut recoils = Recoils::default();
+        let mut shoot = |gs: &mut GameState, weapon| {
+            gs.game_time += 1.0;
+            gs.effects.push(Effect::MuzzleFlash {
+                pos: Vec2f::zero(),
+                angle: 0.0,
+                weapon,
+                vehicle: handle,
+                start_time: gs.game_time,
+            });
+            shake.add_effects(&cvars, gs, Vec2f::zero(), Some(handle));
+            recoils.add_flashes(&cvars, gs);
+            (shake.trauma, shake.kick)
+        };
+
+        // Light weapons only push the vehicle, heavy ones also kick the camera backwards.
+        assert_eq!(shoot(&mut gs, Weapon::Mg), (0.0, Vec2f::zero()));
+        let (trauma, kick) = shoot(&mut gs, Weapon::Rail);
+        assert_eq!(trauma, cvars.cl_recoil_kick);
+        assert_eq!(kick, Vec2f::new(-1.0, 0.0));
+
+        // The railgun is on the turret and recoil decays with the time constant.
+        let time = gs.game_time;
+        let (chassis, turret) = recoils.offsets(&cvars, handle, time);
+        assert_eq!(chassis, Vec2f::zero());
+        assert_eq!(turret, Vec2f::new(-cvars.cl_recoil_distance_heavy, 0.0));
+        let (_, turret) = recoils.offsets(&cvars, handle, time + cvars.cl_recoil_time_constant);
+        let expected = cvars.cl_recoil_distance_heavy / std::f64::consts::E;
+        assert!((turret.x + expected).abs() < 1e-9);
+
+        // Settled and removed vehicles are forgotten.
+        gs.game_time += 1.0;
+        recoils.add_flashes(&cvars, &gs);
+        assert!(recoils.vehicles.is_empty());
+        gs.vehicles.remove(handle);
+        assert_eq!(
+            recoils.offsets(&cvars, handle, gs.game_time),
+            (Vec2f::zero(), Vec2f::zero())
+        );
+    }
+
+    #[test]
+    fn test_hit_stop() {
+        let cvars = Cvars::default();
+        let mut hit_stop = HitStop::default();
+        assert_eq!(hit_stop.scale(&cvars, 0.0), 1.0);
+
+        hit_stop.start(&cvars, 10.0);
+        assert_eq!(hit_stop.scale(&cvars, 10.0), cvars.cl_hit_stop_scale);
+        let ramping = hit_stop.scale(
+            &cvars,
+            10.0 + cvars.cl_hit_stop_duration + cvars.cl_hit_stop_ramp / 2.0,
+        );
+        assert!(ramping > cvars.cl_hit_stop_scale && ramping < 1.0);
+        let end = 10.0 + cvars.cl_hit_stop_duration + cvars.cl_hit_stop_ramp;
+        assert_eq!(hit_stop.scale(&cvars, end + 0.001), 1.0);
+        assert!(hit_stop.start.is_none());
+
+        let cvars = Cvars {
+            cl_hit_stop: false,
+            ..cvars
+        };
+        hit_stop.start(&cvars, 20.0);
+        assert_eq!(hit_stop.scale(&cvars, 20.0), 1.0);
+    }
+}
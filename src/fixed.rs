@@ -0,0 +1,119 @@
+//! Fixed-point hit points and damage.
+//!
+//! Hit points and damage amounts are integers in thousandths of a hit point (milli-hp)
+//! so a vehicle never survives or dies because of the last bit of a float
+//! and the outcome doesn't depend on the order of float operations.
+//!
+//! Cvars are still floats, they're converted at the boundary with `to_milli`
+//! and the HUD converts back with `from_milli` for presentation.
+//!
+//! Rounding rules:
+//! - Converting to milli-hp and applying a multiplier both round half away from zero.
+//!   Saturates at the limits of `MilliHp` so e.g. `f64::MAX` damage is still "kill anything".
+//! - Multipliers are applied one at a time, rounding after each,
+//!   in the order documented at the single place where they're applied (`systems::damage`).
+//!   The result only depends on that order, not on how the caller groups them.
+//! - Damage over time (damage per second * dt) can be a fraction of a milli-hp per tick.
+//!   Each tick deals the rounded amount and the rounding error carries over to the next tick
+//!   so the total only depends on the total time, not on the tick rate.
+
+/// Hit points or damage in thousandths of a hit point.
+pub type MilliHp = i64;
+
+pub const MILLI_PER_HP: f64 = 1000.0;
+
+/// Convert hit points from cvars to milli-hp, rounding half away from zero.
+pub fn to_milli(hp: f64) -> MilliHp {
+    // `as` saturates and maps NaN to 0.
+    (hp * MILLI_PER_HP).round() as MilliHp
+}
+
+/// Convert milli-hp to hit points for presentation.
+pub fn from_milli(milli: MilliHp) -> f64 {
+    milli as f64 / MILLI_PER_HP
+}
+
+/// Apply a multiplier (armor, resistance, falloff), rounding half away from zero.
+pub fn mul(amount: MilliHp, multiplier: f64) -> MilliHp {
+    if multiplier == 1.0 {
+        // Exact even for amounts which don't fit into f64's mantissa.
+        return amount;
+    }
+    (amount as f64 * multiplier).round() as MilliHp
+}
+
+/// Apply multipliers one at a time in order, rounding after each.
+pub fn mul_all(amount: MilliHp, multipliers: &[f64]) -> MilliHp {
+    multipliers
+        .iter()
+        .fold(amount, |acc, &multiplier| mul(acc, multiplier))
+}
+
+/// Damage over time for one tick.
+///
+/// `remainder` is the rounding error carried over between ticks, always within +-0.5 milli-hp.
+pub fn rate(per_sec: f64, dt: f64, remainder: &mut f64) -> MilliHp {
+    let exact = per_sec * MILLI_PER_HP * dt + *remainder;
+    let whole = exact.round();
+    *remainder = exact - whole;
+    whole as MilliHp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rounding() {
+        assert_eq!(to_milli(1.0), 1000);
+        assert_eq!(to_milli(2.5), 2500);
+        assert_eq!(to_milli(0.0005), 1);
+        assert_eq!(to_milli(0.00049), 0);
+        assert_eq!(to_milli(-0.0005), -1);
+        assert_eq!(to_milli(f64::MAX), MilliHp::MAX);
+        assert_eq!(to_milli(f64::INFINITY), MilliHp::MAX);
+        assert_eq!(to_milli(f64::NAN), 0);
+        assert_eq!(from_milli(1500), 1.5);
+
+        // Half away from zero, not to even.
+        assert_eq!(mul(5, 0.5), 3);
+        assert_eq!(mul(7, 0.5), 4);
+        assert_eq!(mul(-5, 0.5), -3);
+        assert_eq!(mul(1000, 0.3333), 333);
+        assert_eq!(mul(MilliHp::MAX, 1.0), MilliHp::MAX);
+        assert!(mul(MilliHp::MAX, 0.75) > MilliHp::MAX / 2);
+    }
+
+    #[test]
+    fn test_multiplier_order() {
+        // Rounding after each step: 25 * 0.5 = 12.5 -> 13, 13 * 0.5 = 6.5 -> 7.
+        // Multiplying the floats first would give 6.25 -> 6.
+        assert_eq!(mul_all(25, &[0.5, 0.5]), 7);
+        // Splitting the chain doesn't change the result as long as the order stays the same.
+        let multipliers = [0.75, 0.9, 1.25, 0.5];
+        for &amount in &[1, 7, 2500, 47_000, 123_456] {
+            let whole = mul_all(amount, &multipliers);
+            for split in 0..=multipliers.len() {
+                let (first, second) = multipliers.split_at(split);
+                assert_eq!(mul_all(mul_all(amount, first), second), whole);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rate_tick_independent() {
+        for &fps in &[7, 30, 60, 144, 1000] {
+            let dt = 1.0 / f64::from(fps);
+            let mut remainder = 0.0;
+            let total: MilliHp = (0..fps * 2)
+                .map(|_| rate(33.3333, dt, &mut remainder))
+                .sum();
+            assert_eq!(total, 66_667, "fps {}", fps);
+            assert!(remainder.abs() <= 0.5);
+        }
+        // Less than a milli-hp per tick still adds up.
+        let mut remainder = 0.0;
+        let total: MilliHp = (0..1000).map(|_| rate(0.1, 0.001, &mut remainder)).sum();
+        assert_eq!(total, 100);
+    }
+}
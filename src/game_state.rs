@@ -1,15 +1,70 @@
-use std::fmt::{self, Debug, Formatter};
+use std::{
+    collections::VecDeque,
+    f64::consts::PI,
+    fmt::{self, Debug, Formatter},
+};
 
-use fnv::FnvHashMap;
-use rand::prelude::*;
+use fnv::{FnvHashMap, FnvHashSet};
 use rand_distr::Uniform;
+use strum_macros::{Display, EnumString};
 use thunderdome::{Arena, Index};
+use vek::Clamp;
 
 use crate::{
-    entities::{Ai, Player, Projectile, Vehicle},
-    map::Vec2f,
+    collision_debug::CollisionEntry,
+    cvars::Cvars,
+    effects::Effect,
+    entities::{
+        Ai, Facing, Flare, Pickup, PickupKind, Player, Projectile, Team, Vehicle, VehicleType,
+        Weapon,
+    },
+    grid::VehicleGrid,
+    lives::LifeRecords,
+    map::{Vec2f, Vec2u},
+    rng::GameRng,
+    sys_ctf::Ctf,
+    sys_hazards::HazardState,
+    sys_range::Range,
+    sys_threats::Threat,
+    trace::Trace,
 };
 
+/// What the players are trying to do, chosen by `g_game_mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+pub enum GameMode {
+    /// Everyone against everyone, points for kills.
+    FreeForAll,
+    /// Two teams steal each other's flag, see `sys_ctf`.
+    CaptureTheFlag,
+}
+
+/// Where in its lifecycle the match is, see `sys_match`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatchState {
+    /// Nobody can move, shoot or get hurt until game time `until`.
+    Countdown {
+        until: f64,
+    },
+    Playing,
+    /// `winner` is `None` for a draw. A new match starts at game time `until_restart`.
+    Finished {
+        winner: Option<Winner>,
+        until_restart: f64,
+    },
+}
+
+impl Default for MatchState {
+    fn default() -> Self {
+        MatchState::Playing
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Winner {
+    Player(Index),
+    Team(Team),
+}
+
 /// Things that change during the game
 /// and might need to be taken back during frame interpolation / reconciliation.
 ///
@@ -19,22 +74,25 @@ use crate::{
 /// What does veloren do?
 #[derive(Debug, Clone)]
 pub struct GameState {
-    /// The RNG for all gamelogic
-    pub rng: SmallRng,
+    /// The RNG streams for all gamelogic, see `rng`.
+    pub rng: GameRng,
 
     /// Inclusive range [-1.0, 1.0].
     /// Creating it once and saving it here might be faster than using gen_range according to docs.
     pub range_uniform11: Uniform<f64>,
 
-    /// This gamelogic frame's time in seconds. Affected by d_speed and pause.
+    /// This gamelogic frame's time in seconds. Affected by d_speed, time scale and pause.
     pub game_time: f64,
 
-    /// The previous gamelogic frame's time in seconds. Affected by d_speed and pause.
+    /// The previous gamelogic frame's time in seconds. Affected by d_speed, time scale and pause.
     pub game_time_prev: f64,
 
     /// Delta time since last gamelogic frame in seconds
     pub dt: f64,
 
+    /// Number of gamelogic frames so far, used for change detection.
+    pub frame_num: u64,
+
     /// Map of projectile handles to vehicle handles.
     /// Prevents rail hitting the same vehicle twice
     /// when one segment ends inside the hitbox and the next starts inside it the next frame.
@@ -46,78 +104,499 @@ pub struct GameState {
     ///     2) Remove the entry after the projectile exits the hitbox - e.g. guided missiles that can pass through several times.
     ///     3) Make sure the HashMap doesn't grow indefinitely in case we forgot to remove in some cases.
     pub rail_hits: FnvHashMap<Index, Index>,
+    /// How many times each projectile has ricocheted or bounced off walls, see `g_projectile_max_bounces`.
+    pub bounces: FnvHashMap<Index, u32>,
+    /// Projectiles which are vehicle debris mapped to the player credited with their damage,
+    /// see `systems::vehicle_explosions`.
+    pub debris: FnvHashMap<Index, Index>,
+    /// Projectiles which already hurt another player so each counts as at most one hit, see `WeaponStats`.
+    pub projectile_hits: FnvHashSet<Index>,
+    /// Vehicles destroyed this frame which haven't exploded yet and who destroyed them, oldest first.
+    /// Emptied by `systems::vehicle_explosions` at the end of the frame so it's not in snapshots.
+    pub pending_explosions: VecDeque<(Index, Index)>,
+    /// Guided missiles whose owner died this frame, see `g_guided_missile_detonate_on_owner_death`.
+    /// Detonated by `systems::vehicle_explosions` at the end of the frame so it's not in snapshots.
+    pub pending_detonations: VecDeque<Index>,
 
-    pub rail_beams: Vec<RailBeam>,
-    pub bfg_beams: Vec<(Vec2f, Vec2f)>,
-    pub explosions: Vec<Explosion>,
+    /// Explosions and beams, oldest first, see `effects`.
+    pub effects: Vec<Effect>,
+    /// Cosmetic, oldest first, see `sys_particles`.
+    pub particles: Vec<Particle>,
+    /// Cosmetic ring buffer, oldest first, see `sys_decals`.
+    pub decals: VecDeque<Decal>,
+    /// Distance each vehicle traveled since its last track marks.
+    pub track_distances: FnvHashMap<Index, f64>,
+    /// Cosmetic position history of projectiles which have trails, see `sys_trails`.
+    pub trails: FnvHashMap<Index, Trail>,
     pub ais: Arena<Ai>,
     pub players: Arena<Player>,
     pub vehicles: Arena<Vehicle>,
     pub projectiles: Arena<Projectile>,
+    pub pickups: Arena<Pickup>,
+    pub flares: Arena<Flare>,
+
+    /// Projectiles fired this frame so the server can confirm clients' predicted shots, see `prediction`.
+    pub shots: Vec<Index>,
+
+    /// Vehicles which ran into a wall while moving this frame, for engine sounds.
+    pub scraping: Vec<Index>,
+
+    /// Sound data derived from the state each frame, see `sys_sound`.
+    pub flybys: Vec<Flyby>,
+    pub sound_channels: Vec<SoundChannel>,
+    /// One per vehicle sorted by handle, see `sys_sound::engine_sounds`.
+    pub engine_sounds: Vec<EngineSound>,
+
+    /// What's about to hurt each player this frame, most urgent first, see `sys_threats`.
+    pub threats: Vec<Threat>,
+
+    /// Sounds for a frontend to play, oldest first, see `sys_sound::drain_audio_events`.
+    /// The macroquad client has no playback yet so it doesn't drain it and the queue just stays at its cap.
+    pub audio_events: VecDeque<AudioEvent>,
+
+    /// Things to tell players about (kill feed, damage flashes, ...), oldest first, see `drain_notifications`.
+    pub notifications: VecDeque<Notification>,
+
+    /// Broad-phase for projectile-vehicle collisions, updated incrementally.
+    pub vehicle_grid: VehicleGrid,
+
+    /// Recent collision decisions, oldest first. Only recorded when `d_collision_trace` is on.
+    pub collision_trace: VecDeque<CollisionEntry>,
+
+    /// What the gamelogic did in recent ticks. Only recorded when `d_trace` is on.
+    pub trace: Trace,
+
+    /// Segments projectiles were tested for collisions along this frame.
+    /// Only recorded when `d_draw_projectile_rays` is on.
+    pub projectile_rays: Vec<(Vec2f, Vec2f)>,
+
+    pub dda: Dda,
+
+    /// Per-life timelines for post-match review, not gameplay.
+    pub life_records: LifeRecords,
+
+    /// State of each of the map's hazards, same order as `Map::hazards`.
+    /// Empty until the first run of the hazards system.
+    pub hazards: Vec<HazardState>,
+
+    /// Training range state, `None` outside of it. See `sys_range`.
+    pub range: Option<Range>,
+
+    /// Capture the flag state, `None` in other game modes. See `sys_ctf`.
+    pub ctf: Option<Ctf>,
+
+    pub match_state: MatchState,
+    /// Game time when the countdown ended, `g_match_time_limit` counts from here.
+    pub match_start: f64,
+    /// Game time when the match finished, only meaningful in `MatchState::Finished`.
+    pub match_end: f64,
+    /// Gamelogic ticks spent playing this match, not counting the countdown.
+    pub match_ticks: u64,
 
     /// Inputs of players last frame.
     pub inputs_prev: InputsPrev,
 }
 
 impl GameState {
-    pub fn new(rng: SmallRng) -> Self {
+    /// All randomness is derived from `seed`.
+    pub fn new(seed: u64) -> Self {
         Self {
-            rng,
+            rng: GameRng::new(seed),
             range_uniform11: Uniform::new_inclusive(-1.0, 1.0),
             game_time: 0.0,
             game_time_prev: 0.0,
             dt: 0.0,
-            rail_beams: Vec::new(),
+            frame_num: 0,
             rail_hits: FnvHashMap::default(),
-            bfg_beams: Vec::new(),
-            explosions: Vec::new(),
+            bounces: FnvHashMap::default(),
+            debris: FnvHashMap::default(),
+            projectile_hits: FnvHashSet::default(),
+            pending_explosions: VecDeque::new(),
+            pending_detonations: VecDeque::new(),
+            effects: Vec::new(),
+            particles: Vec::new(),
+            decals: VecDeque::new(),
+            track_distances: FnvHashMap::default(),
+            trails: FnvHashMap::default(),
             ais: Arena::new(),
             players: Arena::new(),
             vehicles: Arena::new(),
             projectiles: Arena::new(),
+            pickups: Arena::new(),
+            flares: Arena::new(),
+            shots: Vec::new(),
+            scraping: Vec::new(),
+            flybys: Vec::new(),
+            sound_channels: Vec::new(),
+            engine_sounds: Vec::new(),
+            threats: Vec::new(),
+            audio_events: VecDeque::new(),
+            notifications: VecDeque::new(),
+            vehicle_grid: VehicleGrid::default(),
+            collision_trace: VecDeque::new(),
+            trace: Trace::default(),
+            projectile_rays: Vec::new(),
+            dda: Dda::default(),
+            life_records: LifeRecords::default(),
+            hazards: Vec::new(),
+            range: None,
+            ctf: None,
+            match_state: MatchState::Playing,
+            match_start: 0.0,
+            match_end: 0.0,
+            match_ticks: 0,
             inputs_prev: InputsPrev(FnvHashMap::default()),
         }
     }
+
+    /// Start an effect, `effects::retire` removes it once its lifetime is over.
+    pub fn spawn_effect(&mut self, effect: Effect) {
+        self.effects.push(effect);
+    }
 }
 
-#[derive(Debug, Clone)]
-pub struct RailBeam {
-    pub begin: Vec2f,
-    pub end: Vec2f,
-    pub start_time: f64,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Particle {
+    pub pos: Vec2f,
+    pub vel: Vec2f,
+    pub spawn_time: f64,
+    pub lifetime: f64,
+    pub size: f64,
+    pub kind: ParticleKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParticleKind {
+    Smoke,
+    Spark,
+    Dust,
+}
+
+/// A mark on the ground - drawn above tiles, below everything else.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decal {
+    pub pos: Vec2f,
+    pub angle: f64,
+    /// Explosion scale for scorch marks, 1 for tracks.
+    pub scale: f64,
+    pub kind: DecalKind,
+    pub spawn_time: f64,
 }
 
-impl RailBeam {
-    pub fn new(begin: Vec2f, end: Vec2f, start_time: f64) -> Self {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecalKind {
+    /// Left by the vehicle type, they have different widths.
+    Tracks(VehicleType),
+    Scorch,
+}
+
+/// How many positions a projectile trail remembers, see `sys_trails`.
+pub const TRAIL_CAPACITY: usize = 8;
+
+/// The last few positions of a projectile, oldest first.
+///
+/// A fixed-size ring buffer instead of a `Vec` because there can be hundreds of projectiles
+/// and pushing happens every frame - this way it never allocates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trail {
+    samples: [TrailSample; TRAIL_CAPACITY],
+    /// Index of the oldest sample.
+    start: usize,
+    len: usize,
+    /// How many times the projectile had bounced at the last sample, see `GameState::bounces`.
+    pub bounces: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrailSample {
+    pub pos: Vec2f,
+    /// The projectile didn't move here in a straight line from the previous sample (e.g. it ricocheted),
+    /// so the trail should be broken instead of cutting through the wall.
+    pub discontinuity: bool,
+}
+
+impl Trail {
+    pub fn new(bounces: u32) -> Self {
+        let sample = TrailSample {
+            pos: Vec2f::zero(),
+            discontinuity: false,
+        };
         Self {
-            begin,
-            end,
-            start_time,
+            samples: [sample; TRAIL_CAPACITY],
+            start: 0,
+            len: 0,
+            bounces,
         }
     }
+
+    /// Add a sample, replacing the oldest one if the buffer is full.
+    pub fn push(&mut self, sample: TrailSample) {
+        if self.len < TRAIL_CAPACITY {
+            self.samples[(self.start + self.len) % TRAIL_CAPACITY] = sample;
+            self.len += 1;
+        } else {
+            self.samples[self.start] = sample;
+            self.start = (self.start + 1) % TRAIL_CAPACITY;
+        }
+    }
+
+    /// Oldest first, there are fewer than `TRAIL_CAPACITY` during the first few frames.
+    pub fn samples(&self) -> impl DoubleEndedIterator<Item = TrailSample> + '_ {
+        (0..self.len).map(move |i| self.samples[(self.start + i) % TRAIL_CAPACITY])
+    }
 }
 
+/// A projectile passed close to a listener this frame - for whoosh sounds.
 #[derive(Debug, Clone)]
-pub struct Explosion {
+pub struct Flyby {
+    /// The player who hears it
+    pub listener: Index,
+    pub projectile: Index,
+    pub weapon: Weapon,
+    /// Position of the closest approach
     pub pos: Vec2f,
-    pub scale: f64,
-    pub start_time: f64,
-    pub bfg: bool,
+    pub speed: f64,
+    pub distance: f64,
+    /// Radial velocity relative to the listener at the start of the frame (while still approaching).
+    /// Negative means approaching. For Doppler.
+    pub radial_vel: f64,
 }
 
-impl Explosion {
-    pub fn new(pos: Vec2f, scale: f64, start_time: f64, bfg: bool) -> Self {
-        Self {
-            pos,
-            scale,
-            start_time,
-            bfg,
+/// Continuous sound of a loud projectile near a listener, updated every frame.
+#[derive(Debug, Clone)]
+pub struct SoundChannel {
+    pub listener: Index,
+    pub projectile: Index,
+    pub weapon: Weapon,
+    pub pos: Vec2f,
+    pub vel: Vec2f,
+    /// Negative means approaching.
+    pub radial_vel: f64,
+}
+
+/// Continuous engine sound of one vehicle, updated every frame so the frontend can crossfade
+/// idle and drive loops and pitch-shift them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EngineSound {
+    /// Stable for the vehicle's whole life.
+    pub vehicle: Index,
+    /// World coords, for panning.
+    pub pos: Vec2f,
+    /// Between 0 and 1, reverse counts the same as forward.
+    pub throttle: f64,
+    /// Speed as a fraction of the vehicle type's max, can be more when boosted or pushed.
+    pub speed_fraction: f64,
+    /// Ran into a wall while moving this frame.
+    pub scraping: bool,
+    pub destroyed: bool,
+    /// The vehicle was removed, this is its last record.
+    pub removed: bool,
+}
+
+/// A sound the frontend should play once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioEvent {
+    pub kind: AudioEventKind,
+    /// World coords while queued, relative to the camera after draining.
+    pub pos: Vec2f,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioEventKind {
+    WeaponFired(Weapon),
+    Explosion {
+        large: bool,
+    },
+    /// Sent every frame for every living vehicle, throttle is between -1 (reverse) and 1.
+    VehicleEngine {
+        throttle: f64,
+    },
+    RailHit,
+    ReloadFinished(Weapon),
+    /// An enemy missile started approaching the player, bearing is the angle from their vehicle.
+    MissileWarning {
+        player: Index,
+        bearing: f64,
+    },
+}
+
+/// Dynamic difficulty adjustment state, see `sys_ai::dda`.
+///
+/// It's part of the game state because it changes how the bots behave.
+#[derive(Debug, Clone, Default)]
+pub struct Dda {
+    /// Between -1.0 (easiest) and 1.0 (hardest), 0.0 means the bots use their default settings.
+    pub level: f64,
+    /// The I in PI controller.
+    pub error_integral: f64,
+    /// Recent kills, oldest first.
+    pub kills: VecDeque<Kill>,
+}
+
+impl Dda {
+    pub fn record_kill(&mut self, time: f64, attacker: Index, victim: Index) {
+        self.kills.push_back(Kill {
+            time,
+            attacker,
+            victim,
+        });
+    }
+}
+
+/// Something players should be told about, for the frontend to show however it likes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub time: f64,
+    pub kind: NotificationKind,
+    /// Already given to the observers, see `observers`. Polling with `drain_notifications` still gets it.
+    pub observed: bool,
+}
+
+/// Players are player handles, not vehicles.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NotificationKind {
+    /// Damage to another player's vehicle.
+    /// `armor` is the multiplier of the facing that got hit, 1 without a facing.
+    DamageDealt {
+        attacker: Index,
+        target: Index,
+        amount: f64,
+        facing: Option<Facing>,
+        armor: f64,
+    },
+    /// Damage to the player's vehicle from any source including themselves.
+    DamageTaken {
+        player: Index,
+        amount: f64,
+        facing: Option<Facing>,
+        armor: f64,
+    },
+    /// The names are the same for suicides. No weapon means self-destruct or a hazard.
+    Kill {
+        killer_name: String,
+        victim_name: String,
+        weapon: Option<Weapon>,
+    },
+    /// Team of the flag.
+    FlagTaken {
+        team: Team,
+        name: String,
+    },
+    FlagDropped {
+        team: Team,
+    },
+    FlagReturned {
+        team: Team,
+    },
+    /// Team of the capturing player.
+    FlagCaptured {
+        team: Team,
+        name: String,
+    },
+    /// A pickup was collected, repair pads don't count.
+    Pickup {
+        player: Index,
+        kind: PickupKind,
+    },
+    /// A destructible wall was destroyed and is now rubble.
+    WallDestroyed {
+        index: Vec2u,
+    },
+    /// The countdown ended (or there was none), players can move and fight.
+    MatchStarted,
+    /// Name of the winning player or team, `None` for a draw.
+    MatchOver {
+        winner: Option<String>,
+    },
+}
+
+impl NotificationKind {
+    /// Identifies the kind for frontends which don't match on the enum, e.g. when logging.
+    pub fn name(&self) -> &'static str {
+        match self {
+            NotificationKind::DamageDealt { .. } => "damage_dealt",
+            NotificationKind::DamageTaken { .. } => "damage_taken",
+            NotificationKind::Kill { .. } => "kill",
+            NotificationKind::FlagTaken { .. } => "flag_taken",
+            NotificationKind::FlagDropped { .. } => "flag_dropped",
+            NotificationKind::FlagReturned { .. } => "flag_returned",
+            NotificationKind::FlagCaptured { .. } => "flag_captured",
+            NotificationKind::Pickup { .. } => "pickup",
+            NotificationKind::WallDestroyed { .. } => "wall_destroyed",
+            NotificationKind::MatchStarted => "match_started",
+            NotificationKind::MatchOver { .. } => "match_over",
         }
     }
+
+    /// Line for the kill feed, `None` for notifications which don't go there.
+    pub fn feed_line(&self) -> Option<String> {
+        let line = match self {
+            NotificationKind::DamageDealt { .. }
+            | NotificationKind::DamageTaken { .. }
+            | NotificationKind::Pickup { .. }
+            | NotificationKind::WallDestroyed { .. }
+            | NotificationKind::MatchStarted => return None,
+            NotificationKind::Kill {
+                killer_name,
+                victim_name,
+                weapon,
+            } => match weapon {
+                _ if killer_name == victim_name => format!("{} died", victim_name),
+                Some(weapon) => {
+                    format!("{} killed {} ({})", killer_name, victim_name, weapon.name())
+                }
+                None => format!("{} killed {}", killer_name, victim_name),
+            },
+            NotificationKind::FlagTaken { team, name } => {
+                format!("{} took the {} flag", name, team.name())
+            }
+            NotificationKind::FlagDropped { team } => format!("{} flag dropped", team.name()),
+            NotificationKind::FlagReturned { team } => format!("{} flag returned", team.name()),
+            NotificationKind::FlagCaptured { team, name } => {
+                format!("{} captured a flag for {}", name, team.name())
+            }
+            NotificationKind::MatchOver { winner } => match winner {
+                Some(winner) => format!("{} won the match", winner),
+                None => "The match ended in a draw".to_owned(),
+            },
+        };
+        Some(line)
+    }
+}
+
+/// Queue a notification, dropping the oldest ones if the frontend stopped draining the queue.
+///
+/// Takes just the queue so it can be called while other parts of the game state are borrowed.
+pub fn push_notification(
+    cvars: &Cvars,
+    notifications: &mut VecDeque<Notification>,
+    time: f64,
+    kind: NotificationKind,
+) {
+    notifications.push_back(Notification {
+        time,
+        kind,
+        observed: false,
+    });
+    while notifications.len() > cvars.cl_notifications_max {
+        notifications.pop_front();
+    }
+}
+
+/// Take all queued notifications, oldest first.
+pub fn drain_notifications(gs: &mut GameState) -> Vec<Notification> {
+    gs.notifications.drain(..).collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Kill {
+    pub time: f64,
+    pub attacker: Index,
+    pub victim: Index,
 }
 
 #[derive(Debug, Clone)]
-pub struct InputsPrev(FnvHashMap<Index, Input>);
+pub struct InputsPrev(pub FnvHashMap<Index, Input>);
 
 impl InputsPrev {
     /// The player's input last frame or empty input if the player wasn't connected last frame.
@@ -137,7 +616,7 @@ impl InputsPrev {
     }
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
 pub struct Input {
     pub left: bool,
     pub right: bool,
@@ -153,7 +632,23 @@ pub struct Input {
     pub horn: bool,
     pub chat: bool,
     pub pause: bool,
-    // ^ when adding fields, also add them to Debug
+    /// Deploy a flare, edge-triggered. See `sys_homing`.
+    pub flare: bool,
+    /// Turn the turret towards this absolute angle instead of using turret_left/right.
+    ///
+    /// Fixed point - a full turn is 65536 so `Input` can stay `Eq` and compact.
+    /// Use `turret_target_angle` and `set_turret_target_angle`.
+    pub turret_target: Option<u16>,
+    /// Switch directly to this weapon (number keys). Edge-triggered like prev/next weapon.
+    pub select_weapon: Option<Weapon>,
+    /// Analog steering from a gamepad or a virtual joystick, overrides left/right when present.
+    ///
+    /// Fixed point like `turret_target` - 127 is full right, -127 full left.
+    /// Use `right_left` and `set_analog`.
+    pub steer: Option<i8>,
+    /// Analog throttle, overrides up/down when present. 127 is full forward, -127 full reverse.
+    pub throttle: Option<i8>,
+    // ^ when adding fields, also add them to Debug and to_bits/from_bits
 }
 
 impl Input {
@@ -184,26 +679,154 @@ impl Input {
             horn: self.horn | other.horn,
             chat: self.chat | other.chat,
             pause: self.pause | other.pause,
+            flare: self.flare | other.flare,
+            turret_target: self.turret_target.or(other.turret_target),
+            select_weapon: self.select_weapon.or(other.select_weapon),
+            steer: self.steer.or(other.steer),
+            throttle: self.throttle.or(other.throttle),
         }
     }
 
+    pub fn turret_target_angle(&self) -> Option<f64> {
+        self.turret_target
+            .map(|target| f64::from(target) / 65536.0 * 2.0 * PI)
+    }
+
+    pub fn set_turret_target_angle(&mut self, angle: f64) {
+        let turns = angle.rem_euclid(2.0 * PI) / (2.0 * PI);
+        // Rounding can give a full turn which is the same as 0.
+        self.turret_target = Some(((turns * 65536.0).round() as u32 % 65536) as u16);
+    }
+
+    /// Set the analog axes, both are clamped to -1..=1.
+    ///
+    /// Dead zones are up to the frontend, the game uses whatever it gets.
+    pub fn set_analog(&mut self, steer: f64, throttle: f64) {
+        let to_fixed = |axis: f64| (axis.clamped(-1.0, 1.0) * 127.0).round() as i8;
+        self.steer = Some(to_fixed(steer));
+        self.throttle = Some(to_fixed(throttle));
+    }
+
+    /// Pack into one bit per bool field, used by replays.
+    ///
+    /// Bits 0-14 are the bools.
+    /// Bits 16-31 are the turret target, bit 15 says whether it's present.
+    /// Bits 32-34 are the selected weapon plus one, 0 means none.
+    /// Bits 36-43 are the steering axis, bit 35 says whether it's present,
+    /// bits 45-52 are the throttle axis, bit 44 says whether it's present.
+    pub fn to_bits(&self) -> u64 {
+        let fields = [
+            self.left,
+            self.right,
+            self.up,
+            self.down,
+            self.turret_left,
+            self.turret_right,
+            self.prev_weapon,
+            self.next_weapon,
+            self.fire,
+            self.mine,
+            self.self_destruct,
+            self.horn,
+            self.chat,
+            self.pause,
+            self.flare,
+        ];
+        let mut bits = 0;
+        for (i, &field) in fields.iter().enumerate() {
+            bits |= (field as u64) << i;
+        }
+        if let Some(target) = self.turret_target {
+            bits |= 1 << 15;
+            bits |= u64::from(target) << 16;
+        }
+        if let Some(weapon) = self.select_weapon {
+            bits |= (weapon as u64 + 1) << 32;
+        }
+        if let Some(steer) = self.steer {
+            bits |= 1 << 35;
+            bits |= u64::from(steer as u8) << 36;
+        }
+        if let Some(throttle) = self.throttle {
+            bits |= 1 << 44;
+            bits |= u64::from(throttle as u8) << 45;
+        }
+        bits
+    }
+
+    pub fn from_bits(bits: u64) -> Self {
+        let field = |i: u64| bits & (1 << i) != 0;
+        Self {
+            left: field(0),
+            right: field(1),
+            up: field(2),
+            down: field(3),
+            turret_left: field(4),
+            turret_right: field(5),
+            prev_weapon: field(6),
+            next_weapon: field(7),
+            fire: field(8),
+            mine: field(9),
+            self_destruct: field(10),
+            horn: field(11),
+            chat: field(12),
+            pause: field(13),
+            flare: field(14),
+            turret_target: if field(15) {
+                Some((bits >> 16) as u16)
+            } else {
+                None
+            },
+            // Invalid weapons are ignored, they can only come from a corrupted replay.
+            select_weapon: match (bits >> 32) & 0b111 {
+                0 => None,
+                num => Weapon::n(num as u8 - 1),
+            },
+            steer: if field(35) {
+                Some((bits >> 36) as u8 as i8)
+            } else {
+                None
+            },
+            throttle: if field(44) {
+                Some((bits >> 45) as u8 as i8)
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Steering between -1 (left) and 1 (right), keys give only -1, 0 or 1.
     pub fn right_left(&self) -> f64 {
-        self.right as i32 as f64 - self.left as i32 as f64
+        match self.steer {
+            Some(steer) => axis(steer),
+            None => self.right as i32 as f64 - self.left as i32 as f64,
+        }
     }
 
+    /// Forward part of the throttle between 0 and 1.
     pub fn up(&self) -> f64 {
-        self.up as i32 as f64
+        match self.throttle {
+            Some(throttle) => axis(throttle).max(0.0),
+            None => self.up as i32 as f64,
+        }
     }
 
+    /// Reverse part of the throttle between 0 and 1.
+    ///
+    /// Separate from `up` because holding both keys is not the same as holding neither.
     pub fn down(&self) -> f64 {
-        self.down as i32 as f64
+        match self.throttle {
+            Some(throttle) => (-axis(throttle)).max(0.0),
+            None => self.down as i32 as f64,
+        }
     }
 
-    /// Subset of inputs to control the missile
+    /// Subset of inputs to control the missile - it always flies at full throttle
     pub fn missile_while_guiding(&self) -> Self {
         Self {
             up: true,
             down: false,
+            throttle: None,
             ..*self
         }
     }
@@ -216,11 +839,18 @@ impl Input {
             right: false,
             up: false,
             down: false,
+            steer: None,
+            throttle: None,
             ..*self
         }
     }
 }
 
+/// Fixed point analog axis to -1..=1, -128 is clamped.
+fn axis(value: i8) -> f64 {
+    (f64::from(value) / 127.0).max(-1.0)
+}
+
 impl Debug for Input {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "Input {{ ")?;
@@ -266,6 +896,21 @@ impl Debug for Input {
         if self.pause {
             write!(f, "pause ")?;
         }
+        if self.flare {
+            write!(f, "flare ")?;
+        }
+        if let Some(target) = self.turret_target {
+            write!(f, "turret_target={} ", target)?;
+        }
+        if let Some(weapon) = self.select_weapon {
+            write!(f, "select_weapon={:?} ", weapon)?;
+        }
+        if let Some(steer) = self.steer {
+            write!(f, "steer={} ", steer)?;
+        }
+        if let Some(throttle) = self.throttle {
+            write!(f, "throttle={} ", throttle)?;
+        }
         write!(f, "}}")?;
         Ok(())
     }
@@ -0,0 +1,456 @@
+//! Broad-phase collision detection using a uniform grid.
+//!
+//! Kept between frames and updated incrementally -
+//! only vehicles whose position changed get moved to other cells.
+
+use fnv::FnvHashMap;
+use thunderdome::{Arena, Index};
+
+use crate::{
+    entities::Vehicle,
+    map::{Vec2f, Vec2u},
+};
+
+/// Uniform grid of vehicle handles so projectiles only need to test nearby vehicles.
+///
+/// Each vehicle is inserted into every cell its hit circle's bounding box touches.
+/// This means any point within the hit circle lies in a cell which contains the vehicle
+/// so it's enough to look at the cells a projectile's path crosses.
+#[derive(Debug, Clone, Default)]
+pub struct VehicleGrid {
+    cell_size: f64,
+    maxs: Vec2f,
+    radius: f64,
+    /// Number of cells along each axis
+    size: Vec2u,
+    cells: Vec<Vec<Index>>,
+    /// First and last cell each vehicle is in so it can be removed without searching.
+    entries: FnvHashMap<Index, (Vec2u, Vec2u)>,
+    /// Frame of the last `update` - vehicles which changed since then need to be re-bucketed.
+    frame_updated: u64,
+}
+
+impl VehicleGrid {
+    /// Create an empty grid covering the area from (0, 0) to `maxs`.
+    pub fn new(cell_size: f64, maxs: Vec2f) -> Self {
+        // Guard against nonsensical cvar values, nothing else would work with them.
+        let cell_size = cell_size.max(1.0);
+        let cols = (maxs.x / cell_size).ceil().max(1.0) as usize;
+        let rows = (maxs.y / cell_size).ceil().max(1.0) as usize;
+        Self {
+            cell_size,
+            maxs,
+            radius: 0.0,
+            size: Vec2u::new(cols, rows),
+            cells: vec![Vec::new(); cols * rows],
+            entries: FnvHashMap::default(),
+            frame_updated: 0,
+        }
+    }
+
+    /// Create a grid containing all vehicles which can still be hit.
+    pub fn build(cell_size: f64, maxs: Vec2f, vehicles: &Arena<Vehicle>, radius: f64) -> Self {
+        let mut grid = Self::new(cell_size, maxs);
+        grid.radius = radius;
+        for (handle, vehicle) in vehicles.iter() {
+            if !vehicle.destroyed() {
+                grid.insert(handle, *vehicle.pos, radius);
+            }
+        }
+        grid
+    }
+
+    /// Bring the grid up to date with the vehicles,
+    /// only re-bucketing those which were added, removed, destroyed or moved since the last update.
+    ///
+    /// If the cell size, map size or radius changed, the grid is rebuilt from scratch.
+    pub fn update(
+        &mut self,
+        cell_size: f64,
+        maxs: Vec2f,
+        vehicles: &Arena<Vehicle>,
+        radius: f64,
+        frame_num: u64,
+    ) {
+        if self.cells.is_empty()
+            || self.cell_size != cell_size.max(1.0)
+            || self.maxs != maxs
+            || self.radius != radius
+        {
+            *self = Self::build(cell_size, maxs, vehicles, radius);
+            self.frame_updated = frame_num;
+            return;
+        }
+
+        let gone: Vec<_> = self
+            .entries
+            .keys()
+            .copied()
+            .filter(|&handle| vehicles.get(handle).map_or(true, Vehicle::destroyed))
+            .collect();
+        for handle in gone {
+            self.remove(handle);
+        }
+
+        for (handle, vehicle) in vehicles.iter() {
+            if vehicle.destroyed() {
+                continue;
+            }
+            if !self.entries.contains_key(&handle) {
+                self.insert(handle, *vehicle.pos, radius);
+            } else if vehicle.pos.changed_since(self.frame_updated) {
+                let (min, max) = self.cell_range(*vehicle.pos, radius);
+                if self.entries[&handle] != (min, max) {
+                    self.remove(handle);
+                    self.insert(handle, *vehicle.pos, radius);
+                }
+            }
+        }
+        self.frame_updated = frame_num;
+
+        #[cfg(debug_assertions)]
+        self.debug_check(vehicles);
+    }
+
+    pub fn insert(&mut self, handle: Index, pos: Vec2f, radius: f64) {
+        let (min, max) = self.cell_range(pos, radius);
+        for r in min.y..=max.y {
+            for c in min.x..=max.x {
+                let i = self.flat_index(Vec2u::new(c, r));
+                self.cells[i].push(handle);
+            }
+        }
+        self.entries.insert(handle, (min, max));
+        self.radius = radius;
+    }
+
+    fn remove(&mut self, handle: Index) {
+        let (min, max) = self.entries.remove(&handle).unwrap();
+        for r in min.y..=max.y {
+            for c in min.x..=max.x {
+                let i = self.flat_index(Vec2u::new(c, r));
+                self.cells[i].retain(|&h| h != handle);
+            }
+        }
+    }
+
+    /// Compare against a grid built from scratch to catch missed changes.
+    #[cfg(debug_assertions)]
+    fn debug_check(&self, vehicles: &Arena<Vehicle>) {
+        let full = Self::build(self.cell_size, self.maxs, vehicles, self.radius);
+        for (i, (cell, full_cell)) in self.cells.iter().zip(&full.cells).enumerate() {
+            let mut cell = cell.clone();
+            let mut full_cell = full_cell.clone();
+            cell.sort_unstable();
+            full_cell.sort_unstable();
+            assert_eq!(cell, full_cell, "vehicle grid out of date in cell {}", i);
+        }
+    }
+
+    /// Vehicles which might be hit by something traveling from `begin` to `end`.
+    ///
+    /// The result is sorted in the same order as iterating the vehicle arena
+    /// so the narrow phase behaves the same as if it tested all vehicles.
+    pub fn query_segment(&self, begin: Vec2f, end: Vec2f) -> Vec<Index> {
+        let mut handles = Vec::new();
+        for cell in self.cells_along(begin, end) {
+            handles.extend_from_slice(&self.cells[self.flat_index(cell)]);
+        }
+        handles.sort_unstable();
+        handles.dedup();
+        handles
+    }
+
+    /// Vehicles which might be in the rectangle from `mins` to `maxs`.
+    ///
+    /// Sorted the same way as `query_segment`.
+    pub fn query_rect(&self, mins: Vec2f, maxs: Vec2f) -> Vec<Index> {
+        let min = self.cell_index(mins);
+        let max = self.cell_index(maxs);
+        let mut handles = Vec::new();
+        for r in min.y..=max.y {
+            for c in min.x..=max.x {
+                handles.extend_from_slice(&self.cells[self.flat_index(Vec2u::new(c, r))]);
+            }
+        }
+        handles.sort_unstable();
+        handles.dedup();
+        handles
+    }
+
+    fn cell_range(&self, pos: Vec2f, radius: f64) -> (Vec2u, Vec2u) {
+        (self.cell_index(pos - radius), self.cell_index(pos + radius))
+    }
+
+    /// Cell containing `pos`, positions outside the grid are clamped to the nearest cell.
+    fn cell_index(&self, pos: Vec2f) -> Vec2u {
+        let c = (pos.x / self.cell_size).floor().max(0.0) as usize;
+        let r = (pos.y / self.cell_size).floor().max(0.0) as usize;
+        Vec2u::new(c.min(self.size.x - 1), r.min(self.size.y - 1))
+    }
+
+    fn flat_index(&self, cell: Vec2u) -> usize {
+        cell.y * self.size.x + cell.x
+    }
+
+    /// All cells crossed by the segment, same algorithm as `Map::is_wall_trace`.
+    ///
+    /// When the segment passes exactly through a corner, all cells touching it are included
+    /// because the corner belongs to only one of them and it doesn't have to be the one we'd step into.
+    fn cells_along(&self, begin: Vec2f, end: Vec2f) -> Vec<Vec2u> {
+        let mut cur = self.cell_index(begin);
+        let last = self.cell_index(end);
+        let mut cells = vec![cur];
+
+        let delta = end - begin;
+        let step_x: isize = if delta.x > 0.0 { 1 } else { -1 };
+        let step_y: isize = if delta.y > 0.0 { 1 } else { -1 };
+        let mut t_x = if delta.x == 0.0 {
+            f64::INFINITY
+        } else {
+            let next_x = if delta.x > 0.0 {
+                (cur.x + 1) as f64 * self.cell_size
+            } else {
+                cur.x as f64 * self.cell_size
+            };
+            (next_x - begin.x) / delta.x
+        };
+        let mut t_y = if delta.y == 0.0 {
+            f64::INFINITY
+        } else {
+            let next_y = if delta.y > 0.0 {
+                (cur.y + 1) as f64 * self.cell_size
+            } else {
+                cur.y as f64 * self.cell_size
+            };
+            (next_y - begin.y) / delta.y
+        };
+        let t_step_x = self.cell_size / delta.x.abs();
+        let t_step_y = self.cell_size / delta.y.abs();
+
+        let offset = |cell: Vec2u, dx: isize, dy: isize| -> Option<Vec2u> {
+            let c = cell.x as isize + dx;
+            let r = cell.y as isize + dy;
+            if c < 0 || r < 0 || c as usize >= self.size.x || r as usize >= self.size.y {
+                None
+            } else {
+                Some(Vec2u::new(c as usize, r as usize))
+            }
+        };
+
+        while cur != last {
+            let t = t_x.min(t_y);
+            if t > 1.0 {
+                break;
+            }
+            let next = if t_x < t_y {
+                t_x += t_step_x;
+                offset(cur, step_x, 0)
+            } else if t_y < t_x {
+                t_y += t_step_y;
+                offset(cur, 0, step_y)
+            } else {
+                cells.extend(offset(cur, step_x, 0));
+                cells.extend(offset(cur, 0, step_y));
+                t_x += t_step_x;
+                t_y += t_step_y;
+                offset(cur, step_x, step_y)
+            };
+            match next {
+                Some(cell) => {
+                    cur = cell;
+                    cells.push(cur);
+                }
+                // Left the grid, the rest of the segment is clamped into the last cell.
+                None => break,
+            }
+        }
+        if cur != last {
+            cells.push(last);
+        }
+
+        cells
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{prelude::SmallRng, Rng, SeedableRng};
+    use vek::{Clamp, LineSegment2};
+
+    use crate::{cvars::Cvars, entities::VehicleType};
+
+    fn brute_force(vehicles: &Arena<Vec2f>, step: LineSegment2<f64>, radius: f64) -> Vec<Index> {
+        vehicles
+            .iter()
+            .filter(|(_, &pos)| step.projected_point(pos).distance(pos) <= radius)
+            .map(|(handle, _)| handle)
+            .collect()
+    }
+
+    fn broad_phase(
+        grid: &VehicleGrid,
+        vehicles: &Arena<Vec2f>,
+        step: LineSegment2<f64>,
+        radius: f64,
+    ) -> Vec<Index> {
+        grid.query_segment(step.start, step.end)
+            .into_iter()
+            .filter(|&handle| {
+                let pos = vehicles[handle];
+                step.projected_point(pos).distance(pos) <= radius
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_grid_same_hits_as_brute_force() {
+        let mut rng = SmallRng::seed_from_u64(42);
+        let maxs = Vec2f::new(40.0 * 64.0, 30.0 * 64.0);
+        let radius = 24.0;
+
+        let mut vehicles = Arena::new();
+        for _ in 0..32 {
+            let pos = Vec2f::new(rng.gen_range(0.0..maxs.x), rng.gen_range(0.0..maxs.y));
+            vehicles.insert(pos);
+        }
+        // Some vehicles exactly on cell boundaries and corners
+        vehicles.insert(Vec2f::new(640.0, 300.0));
+        vehicles.insert(Vec2f::new(300.0, 640.0));
+        vehicles.insert(Vec2f::new(640.0, 640.0));
+
+        let mut grid = VehicleGrid::new(64.0, maxs);
+        for (handle, &pos) in vehicles.iter() {
+            grid.insert(handle, pos, radius);
+        }
+
+        let mut hits = 0;
+        for i in 0..500 {
+            let start = Vec2f::new(rng.gen_range(0.0..maxs.x), rng.gen_range(0.0..maxs.y));
+            let end = if i % 5 == 0 {
+                // Long segments like railguns
+                Vec2f::new(rng.gen_range(0.0..maxs.x), rng.gen_range(0.0..maxs.y))
+            } else {
+                start + Vec2f::new(rng.gen_range(-100.0..100.0), rng.gen_range(-100.0..100.0))
+            };
+            let step = LineSegment2 { start, end };
+            let expected = brute_force(&vehicles, step, radius);
+            assert_eq!(broad_phase(&grid, &vehicles, step, radius), expected);
+            hits += expected.len();
+        }
+        // Make sure the test actually tests something
+        assert_ne!(hits, 0);
+    }
+
+    #[test]
+    fn test_grid_boundaries() {
+        let radius = 24.0;
+        let maxs = Vec2f::new(10.0 * 64.0, 10.0 * 64.0);
+        let mut vehicles = Arena::new();
+        // The hit circle touches the cell boundaries exactly.
+        vehicles.insert(Vec2f::new(64.0 + 24.0, 64.0 + 24.0));
+        let mut grid = VehicleGrid::new(64.0, maxs);
+        for (handle, &pos) in vehicles.iter() {
+            grid.insert(handle, pos, radius);
+        }
+
+        let segments = [
+            // Along the boundaries
+            (Vec2f::new(0.0, 64.0), Vec2f::new(300.0, 64.0)),
+            (Vec2f::new(64.0, 0.0), Vec2f::new(64.0, 300.0)),
+            // Through corners in all diagonal directions
+            (Vec2f::new(0.0, 0.0), Vec2f::new(256.0, 256.0)),
+            (Vec2f::new(256.0, 256.0), Vec2f::new(0.0, 0.0)),
+            (Vec2f::new(0.0, 256.0), Vec2f::new(256.0, 0.0)),
+            (Vec2f::new(256.0, 0.0), Vec2f::new(0.0, 256.0)),
+            // Outside the grid
+            (Vec2f::new(-100.0, 88.0), Vec2f::new(1000.0, 88.0)),
+        ];
+        for (start, end) in segments {
+            let step = LineSegment2 { start, end };
+            assert_eq!(
+                broad_phase(&grid, &vehicles, step, radius),
+                brute_force(&vehicles, step, radius),
+                "{start} -> {end}",
+            );
+        }
+    }
+
+    #[test]
+    fn test_grid_incremental_update() {
+        let cvars = Cvars::default();
+        let mut rng = SmallRng::seed_from_u64(7);
+        let maxs = Vec2f::new(40.0 * 64.0, 30.0 * 64.0);
+        let radius = 24.0;
+        let owner = Arena::<()>::new().insert(());
+
+        let mut vehicles = Arena::new();
+        for _ in 0..20 {
+            let pos = Vec2f::new(rng.gen_range(0.0..maxs.x), rng.gen_range(0.0..maxs.y));
+            vehicles.insert(Vehicle::new(
+                &cvars,
+                pos,
+                0.0,
+                VehicleType::Tank,
+                0,
+                0.0,
+                owner,
+            ));
+        }
+
+        let mut grid = VehicleGrid::default();
+        grid.update(64.0, maxs, &vehicles, radius, 0);
+
+        // `update` compares against a full rebuild in debug builds
+        // so all that's needed is to change things in all the possible ways.
+        for frame_num in 1..100 {
+            let handles: Vec<_> = vehicles.iter().map(|(handle, _)| handle).collect();
+            for &handle in &handles {
+                let vehicle = &mut vehicles[handle];
+                if rng.gen_bool(0.3) {
+                    let delta = Vec2f::new(rng.gen_range(-30.0..30.0), rng.gen_range(-30.0..30.0));
+                    let new_pos = (*vehicle.pos + delta).clamped(Vec2f::zero(), maxs);
+                    vehicle.pos.set(new_pos, frame_num);
+                    vehicle.update_obb();
+                }
+                if rng.gen_bool(0.02) {
+                    vehicle.hp = 0;
+                }
+                if rng.gen_bool(0.02) {
+                    vehicles.remove(handle);
+                }
+            }
+            if rng.gen_bool(0.2) {
+                let pos = Vec2f::new(rng.gen_range(0.0..maxs.x), rng.gen_range(0.0..maxs.y));
+                vehicles.insert(Vehicle::new(
+                    &cvars,
+                    pos,
+                    0.0,
+                    VehicleType::Hovercraft,
+                    frame_num,
+                    0.0,
+                    owner,
+                ));
+            }
+            grid.update(64.0, maxs, &vehicles, radius, frame_num);
+
+            let full = VehicleGrid::build(64.0, maxs, &vehicles, radius);
+            let start = Vec2f::new(rng.gen_range(0.0..maxs.x), rng.gen_range(0.0..maxs.y));
+            let end = Vec2f::new(rng.gen_range(0.0..maxs.x), rng.gen_range(0.0..maxs.y));
+            assert_eq!(
+                grid.query_segment(start, end),
+                full.query_segment(start, end)
+            );
+        }
+
+        // Changing cvars at runtime rebuilds the grid.
+        grid.update(100.0, maxs, &vehicles, 30.0, 100);
+        let full = VehicleGrid::build(100.0, maxs, &vehicles, 30.0);
+        assert_eq!(
+            grid.query_rect(Vec2f::zero(), maxs),
+            full.query_rect(Vec2f::zero(), maxs)
+        );
+    }
+}
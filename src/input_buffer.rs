@@ -0,0 +1,182 @@
+//! Inputs received between gamelogic ticks.
+//!
+//! Clients send input once per rendered frame but ticks don't line up with frames -
+//! at low tick rates (or after a long frame) several inputs arrive before the next tick
+//! and only looking at the last one would lose keys which were pressed and released in between.
+//!
+//! - Edge-triggered actions (weapon switching, self destruct, fire to detonate a guided missile, ...)
+//!   are latched until a tick consumes them.
+//! - Movement is averaged over the time since the last tick so a key held for half of it
+//!   gives half throttle for that tick.
+//!
+//! The server turns the buffer into a normal `Input` right before each tick, see `Server::input`.
+
+use crate::game_state::Input;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InputBuffer {
+    /// The last input received - what's held right now.
+    current: Input,
+    /// Everything pressed in inputs received since the last consumed tick,
+    /// the same as `current` if nothing was received.
+    latched: Input,
+    /// Whether any input was received since the last consumed tick.
+    received: bool,
+    /// `pause` went from released to pressed since `take_pause`.
+    pause_pressed: bool,
+    /// Integrals of steering and throttle (between -1 and 1) over `duration`.
+    steer: f64,
+    throttle: f64,
+    /// How long inputs were held since the last consumed tick.
+    duration: f64,
+}
+
+impl InputBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A new input from the client, it's held until the next one arrives.
+    pub fn push(&mut self, input: Input) {
+        if input.pause && !self.current.pause {
+            self.pause_pressed = true;
+        }
+        self.latched = if self.received {
+            self.latched.merged(input)
+        } else {
+            input
+        };
+        self.received = true;
+        self.current = input;
+    }
+
+    /// The current input was held for `duration` of game time.
+    pub fn hold(&mut self, duration: f64) {
+        self.steer += self.current.right_left() * duration;
+        self.throttle += (self.current.up() - self.current.down()) * duration;
+        self.duration += duration;
+    }
+
+    /// Was pause pressed since the last call?
+    ///
+    /// Pausing is handled outside gamelogic so it can't wait for a tick.
+    pub fn take_pause(&mut self) -> bool {
+        std::mem::take(&mut self.pause_pressed)
+    }
+
+    /// The input for the next tick.
+    ///
+    /// If movement didn't change since the last tick, it's exactly what was received,
+    /// otherwise it's replaced by analog axes with the average.
+    pub fn input(&self) -> Input {
+        // Aiming and movement are continuous so the latest values are the ones that matter.
+        let mut input = Input {
+            left: self.current.left,
+            right: self.current.right,
+            up: self.current.up,
+            down: self.current.down,
+            turret_target: self.current.turret_target,
+            steer: self.current.steer,
+            throttle: self.current.throttle,
+            ..self.latched
+        };
+        if self.duration > 0.0 {
+            let steer = self.steer / self.duration;
+            let throttle = self.throttle / self.duration;
+            if steer != input.right_left() || throttle != input.up() - input.down() {
+                input.set_analog(steer, throttle);
+            }
+        }
+        input
+    }
+
+    /// A tick used `input`, start collecting for the next one.
+    ///
+    /// Keys which are still held stay held, taps are forgotten.
+    pub fn consume(&mut self) {
+        self.latched = self.current;
+        self.received = false;
+        self.steer = 0.0;
+        self.throttle = 0.0;
+        self.duration = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::entities::Weapon;
+
+    #[test]
+    fn test_tap_between_ticks() {
+        let mut buffer = InputBuffer::new();
+        let tap = Input {
+            next_weapon: true,
+            select_weapon: Some(Weapon::Rail),
+            ..Input::new()
+        };
+        buffer.push(tap);
+        buffer.hold(0.01);
+        buffer.push(Input::new());
+        buffer.hold(0.01);
+        let input = buffer.input();
+        assert!(input.next_weapon);
+        assert_eq!(input.select_weapon, Some(Weapon::Rail));
+
+        // Forgotten once a tick used it.
+        buffer.consume();
+        assert_eq!(buffer.input(), Input::new());
+
+        // Released since the last tick.
+        buffer.push(tap);
+        buffer.consume();
+        buffer.push(Input::new());
+        assert_eq!(buffer.input(), Input::new());
+    }
+
+    #[test]
+    fn test_partial_hold() {
+        let mut full = InputBuffer::new();
+        full.push(Input::new_up());
+        full.hold(0.05);
+        full.hold(0.05);
+        // Held the whole time - the input is unchanged.
+        assert_eq!(full.input(), Input::new_up());
+
+        let mut half = InputBuffer::new();
+        half.push(Input::new_up());
+        half.hold(0.05);
+        half.push(Input::new());
+        half.hold(0.05);
+        let input = half.input();
+        assert!((input.up() - 0.5).abs() < 0.01, "{}", input.up());
+        assert_eq!(input.down(), 0.0);
+        assert_eq!(input.right_left(), 0.0);
+
+        // The next tick only sees what's held now.
+        half.consume();
+        half.hold(0.1);
+        assert_eq!(half.input(), Input::new());
+    }
+
+    #[test]
+    fn test_repeated_input() {
+        // Key repeat or several events per frame only record the state, they don't add up.
+        let input = Input {
+            left: true,
+            up: true,
+            ..Input::new()
+        };
+        let mut once = InputBuffer::new();
+        once.push(input);
+        once.hold(0.1);
+        let mut repeated = InputBuffer::new();
+        for _ in 0..100 {
+            repeated.push(input);
+        }
+        repeated.hold(0.1);
+        assert_eq!(repeated.input(), once.input());
+        assert_eq!(repeated.input(), input);
+    }
+}
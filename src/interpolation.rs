@@ -0,0 +1,217 @@
+//! Smooth movement of entities whose state arrives less often than frames are rendered.
+//!
+//! Once there's networking, other players' vehicles will only be updated a few times per second
+//! and drawing them at their latest state would make them jump. Instead, the last two states
+//! of each such entity are kept and it's drawn `cl_interp` seconds in the past, between them.
+//! If the next state is late (e.g. it was dropped), the entity keeps moving in the same direction
+//! for up to `cl_interp_extrapolate_max` seconds, then stops and waits.
+//! A state further than `cl_interp_snap_distance` from where the entity was expected to be
+//! (teleport, respawn) is shown immediately instead of sweeping across the map.
+//!
+//! Only drawing uses this - gamelogic always uses the authoritative `pos` and `angle`.
+//! Entities simulated locally (e.g. the predicted vehicle, see `prediction`) are never pushed here.
+//! There's no networking yet so `d_remote_update_interval` fakes the remote states from the local server.
+
+use fnv::FnvHashMap;
+use thunderdome::Index;
+
+use crate::{cvars::Cvars, map::Vec2f, prediction::wrap_angle};
+
+/// One authoritative state of an entity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RemoteState {
+    /// Server frame number, states older than the newest one are ignored.
+    pub tick: u64,
+    /// Server game time of the frame.
+    pub time: f64,
+    pub pos: Vec2f,
+    pub angle: f64,
+}
+
+/// The two newest states of one entity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interpolated {
+    /// None until the second state arrives and after a snap.
+    pub older: Option<RemoteState>,
+    pub newer: RemoteState,
+}
+
+impl Interpolated {
+    /// Position and angle at game time `time`, the interpolation delay is already subtracted.
+    pub fn pose(&self, cvars: &Cvars, time: f64) -> (Vec2f, f64) {
+        let newer = self.newer;
+        let older = match self.older {
+            Some(older) if newer.time > older.time => older,
+            _ => return (newer.pos, newer.angle),
+        };
+        let duration = newer.time - older.time;
+        // Never before the older state and not too far past the newer one.
+        let elapsed = (time - older.time)
+            .max(0.0)
+            .min(duration + cvars.cl_interp_extrapolate_max);
+        let fraction = elapsed / duration;
+        let pos = older.pos + (newer.pos - older.pos) * fraction;
+        let angle = older.angle + wrap_angle(newer.angle - older.angle) * fraction;
+        (pos, angle)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Interpolation {
+    entities: FnvHashMap<Index, Interpolated>,
+    /// States too far from the expected position, for the debug overlay.
+    pub snaps: u64,
+}
+
+impl Interpolation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the state of `entity` at the server's frame `tick` which happened at game time `time`.
+    pub fn push_remote_state(
+        &mut self,
+        cvars: &Cvars,
+        entity: Index,
+        tick: u64,
+        time: f64,
+        pos: Vec2f,
+        angle: f64,
+    ) {
+        let state = RemoteState {
+            tick,
+            time,
+            pos,
+            angle,
+        };
+        let interpolated = match self.entities.get_mut(&entity) {
+            Some(interpolated) => interpolated,
+            None => {
+                let interpolated = Interpolated {
+                    older: None,
+                    newer: state,
+                };
+                self.entities.insert(entity, interpolated);
+                return;
+            }
+        };
+        if tick <= interpolated.newer.tick {
+            return;
+        }
+
+        let (expected, _) = interpolated.pose(cvars, time);
+        if (pos - expected).magnitude() > cvars.cl_interp_snap_distance {
+            interpolated.older = None;
+            self.snaps += 1;
+        } else {
+            interpolated.older = Some(interpolated.newer);
+        }
+        interpolated.newer = state;
+    }
+
+    /// Where to draw `entity` at game time `render_time`, None if it's not interpolated.
+    pub fn pose(&self, cvars: &Cvars, entity: Index, render_time: f64) -> Option<(Vec2f, f64)> {
+        let interpolated = self.entities.get(&entity)?;
+        Some(interpolated.pose(cvars, render_time - cvars.cl_interp))
+    }
+
+    /// Forget entities for which `keep` returns false, e.g. because they were removed.
+    pub fn retain(&mut self, mut keep: impl FnMut(Index) -> bool) {
+        self.entities.retain(|&entity, _| keep(entity));
+    }
+
+    pub fn clear(&mut self) {
+        self.entities.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use thunderdome::Arena;
+
+    use super::*;
+
+    const SPEED: f64 = 120.0;
+
+    /// Displayed positions at 60 Hz of an entity moving right at `SPEED`
+    /// whose state arrives at 10 Hz except for the frames in `dropped`.
+    fn run(cvars: &Cvars, frames: u64, dropped: &[u64]) -> Vec<(f64, f64)> {
+        let entity = Arena::<()>::new().insert(());
+        let mut interpolation = Interpolation::new();
+        let mut displayed = Vec::new();
+        for frame in 0..frames {
+            let time = frame as f64 / 60.0;
+            if frame % 6 == 0 && !dropped.contains(&frame) {
+                let pos = Vec2f::new(SPEED * time, 100.0);
+                interpolation.push_remote_state(cvars, entity, frame, time, pos, 0.0);
+            }
+            let (pos, _) = interpolation.pose(cvars, entity, time).unwrap();
+            displayed.push((time, pos.x));
+        }
+        displayed
+    }
+
+    /// Where the entity really was `cl_interp` ago.
+    fn check_smooth(cvars: &Cvars, displayed: &[(f64, f64)]) {
+        for pair in displayed.windows(2) {
+            assert!(pair[1].1 >= pair[0].1, "{:?}", pair);
+        }
+        for &(time, x) in displayed {
+            if time - cvars.cl_interp >= 0.1 {
+                let expected = SPEED * (time - cvars.cl_interp);
+                assert!((x - expected).abs() < 1e-6, "{} {} {}", time, x, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_smooth() {
+        let cvars = Cvars::default();
+        let displayed = run(&cvars, 120, &[]);
+        check_smooth(&cvars, &displayed);
+        assert!(displayed.last().unwrap().1 > 200.0);
+    }
+
+    #[test]
+    fn test_dropped_state() {
+        let cvars = Cvars::default();
+        // The movement is linear so extrapolating over the gap is exact.
+        let displayed = run(&cvars, 120, &[30, 60, 66]);
+        check_smooth(&cvars, &displayed);
+
+        // Stops when nothing arrives for too long.
+        let displayed = run(&cvars, 120, &(36..120).collect::<Vec<_>>());
+        let last_time = 30.0 / 60.0;
+        let limit = SPEED * (last_time + cvars.cl_interp_extrapolate_max);
+        assert!((displayed.last().unwrap().1 - limit).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_teleport_snaps() {
+        let cvars = Cvars::default();
+        let entity = Arena::<()>::new().insert(());
+        let mut interpolation = Interpolation::new();
+        interpolation.push_remote_state(&cvars, entity, 0, 0.0, Vec2f::new(0.0, 0.0), 0.0);
+        interpolation.push_remote_state(&cvars, entity, 6, 0.1, Vec2f::new(10.0, 0.0), 0.0);
+        let (pos, _) = interpolation.pose(&cvars, entity, 0.15).unwrap();
+        assert!((pos.x - 5.0).abs() < 1e-6);
+
+        let respawn = Vec2f::new(1000.0, 500.0);
+        interpolation.push_remote_state(&cvars, entity, 12, 0.2, respawn, 1.0);
+        assert_eq!(
+            interpolation.pose(&cvars, entity, 0.2),
+            Some((respawn, 1.0))
+        );
+        assert_eq!(interpolation.snaps, 1);
+
+        // Late states don't undo it.
+        interpolation.push_remote_state(&cvars, entity, 9, 0.15, Vec2f::new(15.0, 0.0), 0.0);
+        assert_eq!(
+            interpolation.pose(&cvars, entity, 0.2),
+            Some((respawn, 1.0))
+        );
+
+        interpolation.retain(|_| false);
+        assert_eq!(interpolation.pose(&cvars, entity, 0.2), None);
+    }
+}
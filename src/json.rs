@@ -0,0 +1,287 @@
+//! Just enough JSON for small config objects (bot personas, key bindings).
+//!
+//! Config objects are flat - their values are strings, numbers, bools or null.
+//! Arrays and nested objects are only parsed by `parse_nested` to check generated JSON like the match summary.
+//! Not worth a dependency, these are a few lines typed by hand or written by the JS frontend.
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+/// Not a valid JSON object, `pos` is the byte offset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonError {
+    pub pos: usize,
+    pub msg: String,
+}
+
+impl JsonError {
+    fn new(pos: usize, msg: &str) -> Self {
+        JsonError {
+            pos,
+            msg: msg.to_owned(),
+        }
+    }
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "byte {}: {}", self.pos, self.msg)
+    }
+}
+
+impl Error for JsonError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    Num(f64),
+    Bool(bool),
+    Null,
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+/// Parse a JSON object whose values are strings, numbers, bools or null.
+pub fn parse_object(json: &str) -> Result<Vec<(String, Value)>, JsonError> {
+    parse(json, false)
+}
+
+/// Parse a JSON object whose values can also be arrays and objects.
+pub fn parse_nested(json: &str) -> Result<Vec<(String, Value)>, JsonError> {
+    parse(json, true)
+}
+
+fn parse(json: &str, nested: bool) -> Result<Vec<(String, Value)>, JsonError> {
+    let mut parser = Parser {
+        json,
+        pos: 0,
+        nested,
+    };
+    let fields = parser.object()?;
+    parser.skip_whitespace();
+    if parser.pos != json.len() {
+        return Err(JsonError::new(parser.pos, "trailing characters"));
+    }
+    Ok(fields)
+}
+
+struct Parser<'a> {
+    json: &'a str,
+    pos: usize,
+    /// Allow arrays and objects as values.
+    nested: bool,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.json[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), JsonError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(JsonError::new(self.pos, &format!("expected '{}'", c)))
+        }
+    }
+
+    fn object(&mut self) -> Result<Vec<(String, Value)>, JsonError> {
+        let mut fields: Vec<(String, Value)> = Vec::new();
+        self.expect('{')?;
+        if !self.eat('}') {
+            loop {
+                let key_pos = self.pos;
+                let key = self.string()?;
+                if fields.iter().any(|(other, _)| *other == key) {
+                    return Err(JsonError::new(key_pos, "duplicate key"));
+                }
+                self.expect(':')?;
+                let value = self.value()?;
+                fields.push((key, value));
+                if self.eat('}') {
+                    break;
+                }
+                self.expect(',')?;
+            }
+        }
+        Ok(fields)
+    }
+
+    fn array(&mut self) -> Result<Vec<Value>, JsonError> {
+        let mut items = Vec::new();
+        self.expect('[')?;
+        if !self.eat(']') {
+            loop {
+                items.push(self.value()?);
+                if self.eat(']') {
+                    break;
+                }
+                self.expect(',')?;
+            }
+        }
+        Ok(items)
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect('"')?;
+        let mut s = String::new();
+        let mut chars = self.rest().char_indices();
+        let start = self.pos;
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos = start + i + 1;
+                    return Ok(s);
+                }
+                '\\' => {
+                    let escaped = match chars.next() {
+                        Some((_, '"')) => '"',
+                        Some((_, '\\')) => '\\',
+                        Some((_, '/')) => '/',
+                        Some((_, 'n')) => '\n',
+                        Some((_, 't')) => '\t',
+                        Some((_, 'u')) => {
+                            let hex: String = (0..4)
+                                .filter_map(|_| chars.next())
+                                .map(|(_, c)| c)
+                                .collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| JsonError::new(start + i, "invalid \\u escape"))?
+                        }
+                        _ => return Err(JsonError::new(start + i, "invalid escape")),
+                    };
+                    s.push(escaped);
+                }
+                c if c.is_control() => {
+                    return Err(JsonError::new(start + i, "control character in string"));
+                }
+                c => s.push(c),
+            }
+        }
+        Err(JsonError::new(self.json.len(), "unterminated string"))
+    }
+
+    fn value(&mut self) -> Result<Value, JsonError> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        if rest.starts_with('"') {
+            return Ok(Value::Str(self.string()?));
+        }
+        if self.nested && rest.starts_with('{') {
+            return Ok(Value::Object(self.object()?));
+        }
+        if self.nested && rest.starts_with('[') {
+            return Ok(Value::Array(self.array()?));
+        }
+        for (word, value) in [
+            ("true", Value::Bool(true)),
+            ("false", Value::Bool(false)),
+            ("null", Value::Null),
+        ] {
+            if rest.starts_with(word) {
+                self.pos += word.len();
+                return Ok(value);
+            }
+        }
+        let len = rest
+            .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+            .unwrap_or(rest.len());
+        match rest[..len].parse() {
+            Ok(num) if len > 0 => {
+                self.pos += len;
+                Ok(Value::Num(num))
+            }
+            _ => Err(JsonError::new(self.pos, "expected a value")),
+        }
+    }
+}
+
+/// Quote and escape a string so `parse_object` reads it back unchanged.
+pub fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_round_trip() {
+        for s in [
+            "",
+            "KeyW",
+            "a \"b\" \\ c",
+            "tab\tnew\nline",
+            "bell\u{7}",
+            "ěšč",
+        ] {
+            let json = format!("{{{}: {}}}", quote("key"), quote(s));
+            assert_eq!(
+                parse_object(&json).unwrap(),
+                vec![("key".to_owned(), Value::Str(s.to_owned()))]
+            );
+        }
+    }
+
+    #[test]
+    fn test_nested() {
+        let json = r#"{"a": [1, "b", [], {}], "c": {"d": null, "e": [true]}}"#;
+        assert_eq!(
+            parse_nested(json).unwrap(),
+            vec![
+                (
+                    "a".to_owned(),
+                    Value::Array(vec![
+                        Value::Num(1.0),
+                        Value::Str("b".to_owned()),
+                        Value::Array(Vec::new()),
+                        Value::Object(Vec::new()),
+                    ])
+                ),
+                (
+                    "c".to_owned(),
+                    Value::Object(vec![
+                        ("d".to_owned(), Value::Null),
+                        ("e".to_owned(), Value::Array(vec![Value::Bool(true)])),
+                    ])
+                ),
+            ]
+        );
+        assert!(parse_nested(r#"{"a": [1,]}"#).is_err());
+        assert!(parse_nested(r#"{"a": {"b": 1, "b": 2}}"#).is_err());
+        // Config objects stay flat.
+        assert!(parse_object(json).is_err());
+    }
+}
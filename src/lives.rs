@@ -0,0 +1,344 @@
+//! Timeline of each life of each player - from spawn to death - for reviewing after a match.
+//!
+//! This is bookkeeping, not gameplay - it doesn't affect the simulation
+//! and isn't part of replay checksums or snapshots.
+//!
+//! Entries are bounded per life (`sv_life_record_entries_max`, oldest evicted first)
+//! and completed lives per player (`sv_life_records_max`, oldest evicted first).
+//!
+//! MG hits and BFG beam damage arrive many times per second
+//! so consecutive hits between the same pair of players with the same weapon
+//! are merged into one burst as long as the gap between them is below `sv_life_record_burst_gap`.
+//! Other weapons always get separate entries.
+//!
+//! Records start over with each match (see `Server::reset_match`), the UI asks for `life_records` whenever it wants to show them.
+//!
+//! LATER Region visits once maps have named regions.
+
+use std::collections::VecDeque;
+
+use fnv::FnvHashMap;
+use thunderdome::Index;
+
+use crate::{
+    cvars::Cvars,
+    entities::{PickupKind, VehicleType, Weapon, WEAPS_CNT},
+    map::Vec2f,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct LifeRecords {
+    pub players: FnvHashMap<Index, PlayerLives>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PlayerLives {
+    /// The life in progress.
+    pub current: Option<LifeRecord>,
+    /// Oldest first.
+    pub completed: VecDeque<LifeRecord>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LifeRecord {
+    pub spawn_time: f64,
+    pub spawn_pos: Vec2f,
+    pub veh_type: VehicleType,
+    /// Number of shots fired with each weapon, indexed by `Weapon as usize`.
+    pub shots: [u32; WEAPS_CNT as usize],
+    /// Oldest first.
+    pub entries: VecDeque<LifeEntry>,
+    /// How many entries were dropped because of the limit.
+    pub evicted: u32,
+    /// `None` while the player is still alive.
+    pub death: Option<Death>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LifeEntry {
+    DamageGiven(DamageEntry),
+    DamageTaken(DamageEntry),
+    Pickup { time: f64, kind: PickupKind },
+}
+
+/// One hit or a burst of merged hits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DamageEntry {
+    pub start: f64,
+    pub end: f64,
+    /// The victim of damage given or the attacker of damage taken.
+    pub other: Index,
+    /// `None` means self-destruct or a map hazard.
+    pub weapon: Option<Weapon>,
+    /// Hit points
+    pub amount: f64,
+    pub hits: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Death {
+    pub time: f64,
+    pub pos: Vec2f,
+    /// Same as the victim for suicides.
+    pub attacker: Index,
+    /// `None` means self-destruct or a map hazard.
+    pub weapon: Option<Weapon>,
+}
+
+impl LifeRecords {
+    /// Completed lives of the player, oldest first,
+    /// followed by the life in progress (with no death) if they're alive.
+    pub fn life_records(&self, player_handle: Index) -> Vec<&LifeRecord> {
+        match self.players.get(&player_handle) {
+            Some(lives) => lives.completed.iter().chain(&lives.current).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Forget everything, e.g. when a match restarts.
+    pub fn clear(&mut self) {
+        self.players.clear();
+    }
+
+    pub fn spawn(&mut self, player_handle: Index, time: f64, pos: Vec2f, veh_type: VehicleType) {
+        let lives = self.players.entry(player_handle).or_default();
+        // Normally finalized by death, this is only for a player who respawned without dying.
+        if let Some(life) = lives.current.take() {
+            lives.completed.push_back(life);
+        }
+        lives.current = Some(LifeRecord {
+            spawn_time: time,
+            spawn_pos: pos,
+            veh_type,
+            shots: [0; WEAPS_CNT as usize],
+            entries: VecDeque::new(),
+            evicted: 0,
+            death: None,
+        });
+    }
+
+    pub fn shot(&mut self, player_handle: Index, weapon: Weapon) {
+        if let Some(life) = self.current(player_handle) {
+            life.shots[weapon as usize] += 1;
+        }
+    }
+
+    pub fn pickup(&mut self, cvars: &Cvars, player_handle: Index, time: f64, kind: PickupKind) {
+        if let Some(life) = self.current(player_handle) {
+            life.push(cvars, LifeEntry::Pickup { time, kind });
+        }
+    }
+
+    /// Self-damage is only recorded as damage taken.
+    pub fn damage(
+        &mut self,
+        cvars: &Cvars,
+        time: f64,
+        attacker: Index,
+        victim: Index,
+        weapon: Option<Weapon>,
+        amount: f64,
+    ) {
+        let entry = |other| DamageEntry {
+            start: time,
+            end: time,
+            other,
+            weapon,
+            amount,
+            hits: 1,
+        };
+        if attacker != victim {
+            if let Some(life) = self.current(attacker) {
+                life.push_damage(cvars, LifeEntry::DamageGiven(entry(victim)));
+            }
+        }
+        if let Some(life) = self.current(victim) {
+            life.push_damage(cvars, LifeEntry::DamageTaken(entry(attacker)));
+        }
+    }
+
+    /// Finish the victim's current life.
+    pub fn death(&mut self, cvars: &Cvars, victim: Index, death: Death) {
+        let lives = match self.players.get_mut(&victim) {
+            Some(lives) => lives,
+            None => return,
+        };
+        if let Some(mut life) = lives.current.take() {
+            life.death = Some(death);
+            lives.completed.push_back(life);
+        }
+        while lives.completed.len() > cvars.sv_life_records_max {
+            lives.completed.pop_front();
+        }
+    }
+
+    fn current(&mut self, player_handle: Index) -> Option<&mut LifeRecord> {
+        self.players.get_mut(&player_handle)?.current.as_mut()
+    }
+}
+
+impl LifeRecord {
+    fn push(&mut self, cvars: &Cvars, entry: LifeEntry) {
+        self.entries.push_back(entry);
+        while self.entries.len() > cvars.sv_life_record_entries_max {
+            self.entries.pop_front();
+            self.evicted += 1;
+        }
+    }
+
+    /// Merge into the most recent entry with the same direction and player if it's a burst.
+    fn push_damage(&mut self, cvars: &Cvars, entry: LifeEntry) {
+        let (given, new) = match &entry {
+            LifeEntry::DamageGiven(new) => (true, new),
+            LifeEntry::DamageTaken(new) => (false, new),
+            LifeEntry::Pickup { .. } => unreachable!(),
+        };
+        let bursts = matches!(new.weapon, Some(Weapon::Mg) | Some(Weapon::Bfg));
+        if bursts {
+            let prev = self.entries.iter_mut().rev().find_map(|prev| match prev {
+                LifeEntry::DamageGiven(prev) if given && prev.other == new.other => Some(prev),
+                LifeEntry::DamageTaken(prev) if !given && prev.other == new.other => Some(prev),
+                _ => None,
+            });
+            if let Some(prev) = prev {
+                if prev.weapon == new.weapon
+                    && new.start - prev.end < cvars.sv_life_record_burst_gap
+                {
+                    prev.end = new.end;
+                    prev.amount += new.amount;
+                    prev.hits += 1;
+                    return;
+                }
+            }
+        }
+        self.push(cvars, entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handle(slot: u64) -> Index {
+        Index::from_bits((1 << 32) | slot).unwrap()
+    }
+
+    fn hit(
+        start: f64,
+        end: f64,
+        other: Index,
+        weapon: Weapon,
+        amount: f64,
+        hits: u32,
+    ) -> DamageEntry {
+        DamageEntry {
+            start,
+            end,
+            other,
+            weapon: Some(weapon),
+            amount,
+            hits,
+        }
+    }
+
+    #[test]
+    fn test_scripted_life() {
+        let cvars = Cvars::default();
+        let (me, enemy, other) = (handle(0), handle(1), handle(2));
+        let mut records = LifeRecords::default();
+        records.spawn(me, 1.0, Vec2f::new(10.0, 20.0), VehicleType::Tank);
+        records.spawn(enemy, 1.0, Vec2f::zero(), VehicleType::Hummer);
+
+        for i in 0..5 {
+            records.shot(me, Weapon::Mg);
+            records.damage(
+                &cvars,
+                2.0 + i as f64 * 0.1,
+                me,
+                enemy,
+                Some(Weapon::Mg),
+                2.0,
+            );
+        }
+        // Another attacker in between doesn't break the burst.
+        records.damage(&cvars, 2.45, other, me, Some(Weapon::Rockets), 25.0);
+        records.damage(&cvars, 2.5, me, enemy, Some(Weapon::Mg), 2.0);
+        // Too long a pause starts a new burst.
+        records.damage(&cvars, 5.0, me, enemy, Some(Weapon::Mg), 2.0);
+        records.pickup(&cvars, me, 6.0, PickupKind::Armor);
+        records.damage(&cvars, 7.0, enemy, me, Some(Weapon::Rail), 47.0);
+        records.damage(&cvars, 7.5, enemy, me, Some(Weapon::Rail), 47.0);
+        let death = Death {
+            time: 7.5,
+            pos: Vec2f::new(30.0, 40.0),
+            attacker: enemy,
+            weapon: Some(Weapon::Rail),
+        };
+        records.death(&cvars, me, death.clone());
+
+        let lives = records.life_records(me);
+        assert_eq!(lives.len(), 1);
+        let life = lives[0];
+        assert_eq!(life.spawn_time, 1.0);
+        assert_eq!(life.shots[Weapon::Mg as usize], 5);
+        assert_eq!(life.death, Some(death));
+        let expected = [
+            LifeEntry::DamageGiven(hit(2.0, 2.5, enemy, Weapon::Mg, 12.0, 6)),
+            LifeEntry::DamageTaken(hit(2.45, 2.45, other, Weapon::Rockets, 25.0, 1)),
+            LifeEntry::DamageGiven(hit(5.0, 5.0, enemy, Weapon::Mg, 2.0, 1)),
+            LifeEntry::Pickup {
+                time: 6.0,
+                kind: PickupKind::Armor,
+            },
+            LifeEntry::DamageTaken(hit(7.0, 7.0, enemy, Weapon::Rail, 47.0, 1)),
+            LifeEntry::DamageTaken(hit(7.5, 7.5, enemy, Weapon::Rail, 47.0, 1)),
+        ];
+        assert_eq!(life.entries, expected);
+
+        // The enemy's life is still going.
+        let enemy_lives = records.life_records(enemy);
+        assert_eq!(enemy_lives.len(), 1);
+        assert_eq!(enemy_lives[0].death, None);
+        assert_eq!(enemy_lives[0].entries.len(), 4);
+    }
+
+    #[test]
+    fn test_bounds() {
+        let cvars = Cvars {
+            sv_life_record_entries_max: 3,
+            sv_life_records_max: 2,
+            ..Cvars::default()
+        };
+        let (me, enemy) = (handle(0), handle(1));
+        let mut records = LifeRecords::default();
+        for life in 0..4 {
+            let time = life as f64 * 100.0;
+            records.spawn(me, time, Vec2f::zero(), VehicleType::Tank);
+            for i in 0..5 {
+                let time = time + i as f64;
+                records.damage(&cvars, time, enemy, me, Some(Weapon::Rockets), 1.0);
+            }
+            let death = Death {
+                time: time + 10.0,
+                pos: Vec2f::zero(),
+                attacker: enemy,
+                weapon: Some(Weapon::Rockets),
+            };
+            records.death(&cvars, me, death);
+        }
+
+        let lives = records.life_records(me);
+        assert_eq!(lives.len(), 2);
+        assert_eq!(lives[0].spawn_time, 200.0);
+        assert_eq!(lives[1].spawn_time, 300.0);
+        assert_eq!(lives[1].entries.len(), 3);
+        assert_eq!(lives[1].evicted, 2);
+        match &lives[1].entries[0] {
+            LifeEntry::DamageTaken(entry) => assert_eq!(entry.start, 302.0),
+            entry => panic!("unexpected entry {:?}", entry),
+        }
+
+        records.clear();
+        assert!(records.life_records(me).is_empty());
+    }
+}
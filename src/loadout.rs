@@ -0,0 +1,512 @@
+//! Named loadout presets so players can reuse their favorite loadout with one key.
+//!
+//! Presets are saved from and applied to the player's pending loadout
+//! which takes effect on their next spawn - applying one while alive doesn't change the current vehicle.
+//!
+//! There's no persistent player profile yet so presets are exported and imported
+//! separately as text, matched to players by name.
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+use thunderdome::Index;
+
+use crate::{
+    cvars::Cvars,
+    entities::{Loadout, LoadoutPreset, Player, VehicleType, Weapon},
+    game_state::GameState,
+};
+
+pub const PRESET_NAME_MAX_LEN: usize = 24;
+
+/// A banned weapon in a preset and what replaced it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Substitution {
+    pub banned: Weapon,
+    /// `None` if the replacement was already in the loadout or no weapon is allowed.
+    pub replacement: Option<Weapon>,
+}
+
+/// What the UI needs to show the player's loadout and presets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadoutPayload {
+    pub pending: Loadout,
+    pub presets: Vec<String>,
+    /// The preset which matches the pending loadout so it can be highlighted.
+    pub current_preset: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadoutError {
+    InvalidName(String),
+    TooManyPresets,
+    UnknownPreset(String),
+    UnknownVehicle(String),
+    /// Error in exported presets, line is 1-based.
+    Parse {
+        line: usize,
+        msg: String,
+    },
+}
+
+impl Display for LoadoutError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadoutError::InvalidName(name) => write!(f, "invalid preset name {:?}", name),
+            LoadoutError::TooManyPresets => write!(f, "too many presets"),
+            LoadoutError::UnknownPreset(name) => write!(f, "no preset named {:?}", name),
+            LoadoutError::UnknownVehicle(name) => write!(
+                f,
+                "unknown vehicle {:?}, expected tank, hovercraft or hummer",
+                name
+            ),
+            LoadoutError::Parse { line, msg } => write!(f, "{}: {}", line, msg),
+        }
+    }
+}
+
+impl Error for LoadoutError {}
+
+/// Names are 1 to `PRESET_NAME_MAX_LEN` letters, digits, spaces, dashes or underscores
+/// and don't start or end with a space.
+pub fn validate_name(name: &str) -> Result<(), LoadoutError> {
+    let valid_chars = name
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_');
+    let len = name.chars().count();
+    if valid_chars && len > 0 && len <= PRESET_NAME_MAX_LEN && name.trim() == name {
+        Ok(())
+    } else {
+        Err(LoadoutError::InvalidName(name.to_owned()))
+    }
+}
+
+/// Vehicle type by its lowercase name, e.g. `hovercraft`.
+pub fn parse_vehicle(name: &str) -> Result<VehicleType, LoadoutError> {
+    VehicleType::all()
+        .find(|veh_type| veh_type.name() == name)
+        .ok_or_else(|| LoadoutError::UnknownVehicle(name.to_owned()))
+}
+
+/// Choose the vehicle for the next spawn by name, `random` picks a random one each spawn.
+///
+/// The current vehicle doesn't change.
+pub fn select_vehicle(
+    gs: &mut GameState,
+    player_handle: Index,
+    name: &str,
+) -> Result<(), LoadoutError> {
+    let veh_type = if name == "random" {
+        None
+    } else {
+        Some(parse_vehicle(name)?)
+    };
+    gs.players[player_handle].pending_loadout.veh_type = veh_type;
+    Ok(())
+}
+
+/// Save the pending loadout under `name`, replacing a preset with the same name.
+pub fn save_loadout_preset(
+    cvars: &Cvars,
+    gs: &mut GameState,
+    player_handle: Index,
+    name: &str,
+) -> Result<(), LoadoutError> {
+    validate_name(name)?;
+    let player = &mut gs.players[player_handle];
+    let loadout = player.pending_loadout.clone();
+    if let Some(preset) = player.loadout_presets.iter_mut().find(|p| p.name == name) {
+        preset.loadout = loadout;
+    } else if player.loadout_presets.len() >= cvars.g_loadout_presets_max {
+        return Err(LoadoutError::TooManyPresets);
+    } else {
+        player.loadout_presets.push(LoadoutPreset {
+            name: name.to_owned(),
+            loadout,
+        });
+    }
+    Ok(())
+}
+
+/// Make the preset the pending loadout for the next spawn.
+///
+/// Weapons which aren't allowed in this match are replaced by the default weapon,
+/// the returned substitutions say which.
+pub fn apply_loadout_preset(
+    cvars: &Cvars,
+    gs: &mut GameState,
+    player_handle: Index,
+    name: &str,
+) -> Result<Vec<Substitution>, LoadoutError> {
+    let player = &mut gs.players[player_handle];
+    let preset = player
+        .loadout_presets
+        .iter()
+        .find(|p| p.name == name)
+        .ok_or_else(|| LoadoutError::UnknownPreset(name.to_owned()))?;
+    let (loadout, substitutions) = validate(cvars, &preset.loadout);
+    for sub in &substitutions {
+        dbg_logf!(
+            "Preset {:?} of {}: {:?} is not allowed, using {:?}",
+            name,
+            player.name,
+            sub.banned,
+            sub.replacement
+        );
+    }
+    player.pending_loadout = loadout;
+    Ok(substitutions)
+}
+
+pub fn list_loadout_presets(gs: &GameState, player_handle: Index) -> Vec<&str> {
+    gs.players[player_handle]
+        .loadout_presets
+        .iter()
+        .map(|preset| preset.name.as_str())
+        .collect()
+}
+
+pub fn delete_loadout_preset(
+    gs: &mut GameState,
+    player_handle: Index,
+    name: &str,
+) -> Result<(), LoadoutError> {
+    let presets = &mut gs.players[player_handle].loadout_presets;
+    let index = presets
+        .iter()
+        .position(|p| p.name == name)
+        .ok_or_else(|| LoadoutError::UnknownPreset(name.to_owned()))?;
+    presets.remove(index);
+    Ok(())
+}
+
+/// The first preset which matches the pending loadout.
+pub fn matching_preset(player: &Player) -> Option<&str> {
+    player
+        .loadout_presets
+        .iter()
+        .find(|preset| preset.loadout.matches(&player.pending_loadout))
+        .map(|preset| preset.name.as_str())
+}
+
+pub fn loadout_payload(player: &Player) -> LoadoutPayload {
+    LoadoutPayload {
+        pending: player.pending_loadout.clone(),
+        presets: player
+            .loadout_presets
+            .iter()
+            .map(|preset| preset.name.clone())
+            .collect(),
+        current_preset: matching_preset(player).map(str::to_owned),
+    }
+}
+
+/// Replace weapons which aren't allowed with the first allowed weapon.
+fn validate(cvars: &Cvars, loadout: &Loadout) -> (Loadout, Vec<Substitution>) {
+    let default = Weapon::all().find(|&weapon| cvars.g_weapon_allowed(weapon));
+    let mut weapons = Vec::new();
+    let mut substitutions = Vec::new();
+    for &weapon in &loadout.weapons {
+        if cvars.g_weapon_allowed(weapon) {
+            if !weapons.contains(&weapon) {
+                weapons.push(weapon);
+            }
+            continue;
+        }
+
+        let replacement = default
+            .filter(|default| !weapons.contains(default) && !loadout.weapons.contains(default));
+        if let Some(replacement) = replacement {
+            weapons.push(replacement);
+        }
+        substitutions.push(Substitution {
+            banned: weapon,
+            replacement,
+        });
+    }
+
+    let loadout = Loadout {
+        veh_type: loadout.veh_type,
+        weapons,
+    };
+    (loadout, substitutions)
+}
+
+/// One preset per line: player name, preset name, vehicle type (`-` for random)
+/// and comma separated weapons, all separated by tabs.
+pub fn export_presets(gs: &GameState) -> String {
+    let mut text = String::new();
+    for (_, player) in gs.players.iter() {
+        for preset in &player.loadout_presets {
+            let veh_type = match preset.loadout.veh_type {
+                Some(veh_type) => format!("{:?}", veh_type),
+                None => "-".to_owned(),
+            };
+            let weapons: Vec<_> = preset
+                .loadout
+                .weapons
+                .iter()
+                .map(|weapon| format!("{:?}", weapon))
+                .collect();
+            text.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                player.name,
+                preset.name,
+                veh_type,
+                weapons.join(",")
+            ));
+        }
+    }
+    text
+}
+
+/// Load presets exported by `export_presets`, replacing the presets of players with matching names.
+///
+/// Presets of players who aren't in the game are ignored.
+/// Returns the number of imported presets.
+pub fn import_presets(gs: &mut GameState, text: &str) -> Result<usize, LoadoutError> {
+    let mut parsed: Vec<(&str, LoadoutPreset)> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let err = |msg: String| LoadoutError::Parse { line: i + 1, msg };
+        let parts: Vec<_> = line.split('\t').collect();
+        if parts.len() != 4 {
+            return Err(err(format!("expected 4 fields, got {}", parts.len())));
+        }
+        validate_name(parts[1]).map_err(|e| err(e.to_string()))?;
+        let veh_type = if parts[2] == "-" {
+            None
+        } else {
+            let veh_type = VehicleType::all()
+                .find(|veh_type| format!("{:?}", veh_type) == parts[2])
+                .ok_or_else(|| err(format!("unknown vehicle type {:?}", parts[2])))?;
+            Some(veh_type)
+        };
+        let mut weapons = Vec::new();
+        for name in parts[3].split(',').filter(|name| !name.is_empty()) {
+            let weapon = Weapon::all()
+                .find(|weapon| format!("{:?}", weapon) == name)
+                .ok_or_else(|| err(format!("unknown weapon {:?}", name)))?;
+            weapons.push(weapon);
+        }
+        let preset = LoadoutPreset {
+            name: parts[1].to_owned(),
+            loadout: Loadout { veh_type, weapons },
+        };
+        parsed.push((parts[0], preset));
+    }
+
+    let mut cnt = 0;
+    for (_, player) in gs.players.iter_mut() {
+        let presets: Vec<_> = parsed
+            .iter()
+            .filter(|(player_name, _)| *player_name == player.name)
+            .map(|(_, preset)| preset.clone())
+            .collect();
+        if !presets.is_empty() {
+            cnt += presets.len();
+            player.loadout_presets = presets;
+        }
+    }
+    Ok(cnt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::{map, server::Server, systems};
+
+    fn new_game() -> (GameState, Index) {
+        let mut gs = GameState::new(0);
+        let handle = gs.players.insert(Player::new("Player 1".to_owned()));
+        (gs, handle)
+    }
+
+    fn hummer_rockets() -> Loadout {
+        Loadout {
+            veh_type: Some(VehicleType::Hummer),
+            weapons: vec![Weapon::Rockets, Weapon::Rail, Weapon::Bfg],
+        }
+    }
+
+    #[test]
+    fn test_save_apply_round_trip() {
+        let cvars = Cvars::default();
+        let (mut gs, handle) = new_game();
+        gs.players[handle].pending_loadout = hummer_rockets();
+        save_loadout_preset(&cvars, &mut gs, handle, "fast").unwrap();
+
+        gs.players[handle].pending_loadout = Loadout::default();
+        let subs = apply_loadout_preset(&cvars, &mut gs, handle, "fast").unwrap();
+        assert_eq!(subs, []);
+        assert_eq!(gs.players[handle].pending_loadout, hummer_rockets());
+
+        assert_eq!(list_loadout_presets(&gs, handle), ["fast"]);
+        delete_loadout_preset(&mut gs, handle, "fast").unwrap();
+        assert_eq!(list_loadout_presets(&gs, handle), Vec::<&str>::new());
+        assert_eq!(
+            apply_loadout_preset(&cvars, &mut gs, handle, "fast"),
+            Err(LoadoutError::UnknownPreset("fast".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_banned_weapon_substituted() {
+        let (mut gs, handle) = new_game();
+        gs.players[handle].pending_loadout = hummer_rockets();
+        save_loadout_preset(&Cvars::default(), &mut gs, handle, "fast").unwrap();
+
+        let cvars = Cvars {
+            g_bfg_allowed: false,
+            ..Cvars::default()
+        };
+        let subs = apply_loadout_preset(&cvars, &mut gs, handle, "fast").unwrap();
+        assert_eq!(
+            subs,
+            [Substitution {
+                banned: Weapon::Bfg,
+                replacement: Some(Weapon::Mg),
+            }]
+        );
+        let pending = &gs.players[handle].pending_loadout;
+        assert_eq!(pending.veh_type, Some(VehicleType::Hummer));
+        assert_eq!(pending.weapons, [Weapon::Rockets, Weapon::Rail, Weapon::Mg]);
+
+        // The default is already there so the banned weapon is just dropped.
+        gs.players[handle].pending_loadout = Loadout {
+            veh_type: None,
+            weapons: vec![Weapon::Mg, Weapon::Rail],
+        };
+        save_loadout_preset(&cvars, &mut gs, handle, "mg").unwrap();
+        let cvars = Cvars {
+            g_railgun_allowed: false,
+            ..Cvars::default()
+        };
+        let subs = apply_loadout_preset(&cvars, &mut gs, handle, "mg").unwrap();
+        assert_eq!(
+            subs,
+            [Substitution {
+                banned: Weapon::Rail,
+                replacement: None,
+            }]
+        );
+        assert_eq!(gs.players[handle].pending_loadout.weapons, [Weapon::Mg]);
+    }
+
+    #[test]
+    fn test_limits() {
+        let cvars = Cvars {
+            g_loadout_presets_max: 2,
+            ..Cvars::default()
+        };
+        let (mut gs, handle) = new_game();
+        for name in [
+            "",
+            " a",
+            "a\tb",
+            "a,b",
+            &"a".repeat(PRESET_NAME_MAX_LEN + 1),
+        ] {
+            assert_eq!(
+                save_loadout_preset(&cvars, &mut gs, handle, name),
+                Err(LoadoutError::InvalidName(name.to_owned()))
+            );
+        }
+        save_loadout_preset(&cvars, &mut gs, handle, "a").unwrap();
+        save_loadout_preset(&cvars, &mut gs, handle, "Long Range-2").unwrap();
+        assert_eq!(
+            save_loadout_preset(&cvars, &mut gs, handle, "c"),
+            Err(LoadoutError::TooManyPresets)
+        );
+        // Overwriting is fine.
+        save_loadout_preset(&cvars, &mut gs, handle, "a").unwrap();
+    }
+
+    #[test]
+    fn test_export_import() {
+        let cvars = Cvars::default();
+        let (mut gs, handle) = new_game();
+        gs.players[handle].pending_loadout = hummer_rockets();
+        save_loadout_preset(&cvars, &mut gs, handle, "fast").unwrap();
+        gs.players[handle].pending_loadout = Loadout::default();
+        save_loadout_preset(&cvars, &mut gs, handle, "everything").unwrap();
+        let text = export_presets(&gs);
+
+        let (mut gs2, handle2) = new_game();
+        gs2.players.insert(Player::new("Someone else".to_owned()));
+        assert_eq!(import_presets(&mut gs2, &text), Ok(2));
+        assert_eq!(
+            gs2.players[handle2].loadout_presets,
+            gs.players[handle].loadout_presets
+        );
+
+        let err = import_presets(&mut gs2, "Player 1\tx\tBoat\tMg\n").unwrap_err();
+        assert_eq!(
+            err,
+            LoadoutError::Parse {
+                line: 1,
+                msg: "unknown vehicle type \"Boat\"".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn test_highlight_ignores_order() {
+        let cvars = Cvars::default();
+        let (mut gs, handle) = new_game();
+        gs.players[handle].pending_loadout = hummer_rockets();
+        save_loadout_preset(&cvars, &mut gs, handle, "fast").unwrap();
+
+        gs.players[handle].pending_loadout = Loadout {
+            veh_type: Some(VehicleType::Hummer),
+            weapons: vec![Weapon::Bfg, Weapon::Rockets, Weapon::Rail],
+        };
+        let payload = loadout_payload(&gs.players[handle]);
+        assert_eq!(payload.current_preset.as_deref(), Some("fast"));
+        assert_eq!(payload.presets, ["fast"]);
+
+        gs.players[handle].pending_loadout.veh_type = Some(VehicleType::Tank);
+        assert_eq!(matching_preset(&gs.players[handle]), None);
+    }
+
+    #[test]
+    fn test_select_vehicle() {
+        let cvars = Cvars::default();
+        let map = map::tests::load_test_map(&fs::read_to_string("maps/Atrium.map").unwrap());
+        let mut server = Server::new(&cvars, map);
+        let handle =
+            server.connect_with_vehicle(&cvars, "Player 1", parse_vehicle("hovercraft").ok());
+        let gs = &mut server.gs;
+        let vehicle = &gs.vehicles[gs.players[handle].vehicle.unwrap()];
+        assert_eq!(vehicle.veh_type, VehicleType::Hovercraft);
+        assert_eq!(vehicle.hp_fraction(&cvars), 1.0);
+        let hitbox = cvars.g_vehicle_hitbox(VehicleType::Hovercraft);
+        assert_eq!(vehicle.hitbox.maxs, hitbox.maxs);
+
+        let err = select_vehicle(gs, handle, "Tank").unwrap_err();
+        assert_eq!(err, LoadoutError::UnknownVehicle("Tank".to_owned()));
+        assert_eq!(
+            gs.players[handle].pending_loadout.veh_type,
+            Some(VehicleType::Hovercraft)
+        );
+
+        // Takes effect on the next spawn.
+        select_vehicle(gs, handle, "hummer").unwrap();
+        let vehicle_handle = gs.players[handle].vehicle.unwrap();
+        assert_eq!(
+            gs.vehicles[vehicle_handle].veh_type,
+            VehicleType::Hovercraft
+        );
+        gs.vehicles.remove(vehicle_handle);
+        systems::spawn_vehicle(&cvars, gs, &server.map, handle, true);
+        let vehicle = &gs.vehicles[gs.players[handle].vehicle.unwrap()];
+        assert_eq!(vehicle.veh_type, VehicleType::Hummer);
+
+        select_vehicle(gs, handle, "random").unwrap();
+        assert_eq!(gs.players[handle].pending_loadout.veh_type, None);
+    }
+}
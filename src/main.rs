@@ -11,23 +11,76 @@
 #[macro_use]
 pub mod debugging; // keep first so the macros are available everywhere
 
+pub mod assets;
+pub mod bindings;
+pub mod bitmap_font;
+pub mod camera;
+pub mod collision_debug;
 pub mod cvars;
+pub mod derived;
+pub mod editor;
+pub mod effects;
 pub mod entities;
+pub mod feedback;
+pub mod fixed;
 pub mod game_state;
+pub mod grid;
+pub mod input_buffer;
+pub mod interpolation;
+pub mod json;
+pub mod lives;
+pub mod loadout;
 pub mod map;
+pub mod minimap;
 pub mod mq;
+pub mod nav;
+pub mod observers;
+pub mod palette;
+pub mod prediction;
+pub mod render_data;
 pub mod rendering;
+pub mod replay;
+pub mod rng;
+pub mod roster;
+#[cfg(test)]
+pub mod scenario;
+pub mod schedule;
 pub mod server;
+pub mod snapshot;
+pub mod sprites;
+pub mod summary;
 pub mod sys_ai;
+pub mod sys_ctf;
+pub mod sys_decals;
+pub mod sys_hazards;
+pub mod sys_homing;
+pub mod sys_match;
+pub mod sys_particles;
+pub mod sys_ramming;
+pub mod sys_range;
+pub mod sys_sound;
+pub mod sys_threats;
+pub mod sys_trails;
 pub mod systems;
 pub mod timing;
+pub mod trace;
+pub mod visibility;
 
-use std::str;
+use std::{fs, panic};
 
 use clap::Parser;
 use macroquad::prelude::*;
 
-use crate::{cvars::Cvars, mq::MacroquadClient, server::Server};
+use crate::{
+    bindings::Bindings,
+    cvars::Cvars,
+    entities::VehicleType,
+    map::{Map, Surface},
+    mq::MacroquadClient,
+    replay::Replay,
+    roster::Roster,
+    server::Server,
+};
 
 #[derive(Debug, Parser)]
 struct Opts {
@@ -39,6 +92,62 @@ struct Opts {
     #[arg(long)]
     map: Option<String>,
 
+    /// Where the map editor (cl_map_editor) saves the map when pressing F5
+    #[arg(long, default_value = "maps/edited.map")]
+    map_editor_path: String,
+
+    /// Record a replay and save it to this file when quitting
+    #[arg(long)]
+    record: Option<String>,
+
+    /// Play back a replay instead of playing (overrides map and cvars)
+    #[arg(long)]
+    replay: Option<String>,
+
+    /// Add bot personas from this file - one JSON object per line
+    #[arg(long)]
+    bot_personas: Option<String>,
+
+    /// Name of player 1 shown above the vehicle, in the scoreboard and kill feed
+    #[arg(long, default_value = "Player 1")]
+    name: String,
+
+    /// Name of player 2 (splitscreen)
+    #[arg(long, default_value = "Player 2")]
+    name2: String,
+
+    /// Vehicle of player 1: tank, hovercraft or hummer (random each spawn if not set)
+    #[arg(long, value_parser = loadout::parse_vehicle)]
+    vehicle: Option<VehicleType>,
+
+    /// Vehicle of player 2 (splitscreen)
+    #[arg(long, value_parser = loadout::parse_vehicle)]
+    vehicle2: Option<VehicleType>,
+
+    /// Load key bindings of player 1 from this file - a JSON object from key to action
+    #[arg(long)]
+    bindings: Option<String>,
+
+    /// Load key bindings of player 2 (splitscreen) from this file
+    #[arg(long)]
+    bindings2: Option<String>,
+
+    /// Print the default key bindings of both players and exit
+    #[arg(long)]
+    print_bindings: bool,
+
+    /// Start from a built-in cvar preset: classic or arcade
+    #[arg(long)]
+    preset: Option<String>,
+
+    /// Load cvars from this file - one name and value per line, applied after the preset
+    #[arg(long)]
+    cvars_file: Option<String>,
+
+    /// Print cvars which differ from the defaults (in the --cvars-file format) and exit
+    #[arg(long)]
+    print_cvars: bool,
+
     /// Set cvar values - use key value pairs (separated by space).
     /// Example: g_armor 150 hud_names false
     cvars: Vec<String>,
@@ -58,6 +167,73 @@ fn get_opts() -> Opts {
     opts
 }
 
+/// Read a file, the error says which one couldn't be read.
+async fn load_bytes(path: &str) -> Result<Vec<u8>, String> {
+    load_file(path)
+        .await
+        .map_err(|err| format!("Failed to read {}: {}", path, err))
+}
+
+async fn load_text(path: &str) -> Result<String, String> {
+    let bytes = load_bytes(path).await?;
+    decode_text(path, bytes)
+}
+
+fn decode_text(path: &str, bytes: Vec<u8>) -> Result<String, String> {
+    String::from_utf8(bytes).map_err(|err| format!("{} is not valid UTF-8: {}", path, err))
+}
+
+fn parse_tex_list(path: &str, text: &str) -> Result<Vec<Surface>, String> {
+    map::load_tex_list(text).map_err(|err| format!("Failed to load {}: {}", path, err))
+}
+
+fn parse_map(path: &str, text: &str, surfaces: Vec<Surface>) -> Result<Map, String> {
+    map::load_map(text, surfaces).map_err(|err| format!("Failed to load map {}: {}", path, err))
+}
+
+/// Decode the replay and switch to the cvars it was recorded with.
+fn parse_replay(path: &str, bytes: &[u8], cvars: &mut Cvars) -> Result<Replay, String> {
+    let replay =
+        Replay::decode(bytes).map_err(|err| format!("Failed to load replay {}: {}", path, err))?;
+    replay
+        .apply_cvars(cvars)
+        .map_err(|err| format!("Failed to apply cvars from replay {}: {}", path, err))?;
+    Ok(replay)
+}
+
+fn add_bot_personas(roster: &mut Roster, path: &str, text: &str) -> Result<(), String> {
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        roster
+            .add_bot_persona(line)
+            .map_err(|err| format!("Invalid bot persona on line {} of {}: {}", i + 1, path, err))?;
+    }
+    Ok(())
+}
+
+fn set_bindings(bindings: &mut Bindings, path: &str, text: &str) -> Result<(), String> {
+    bindings
+        .set_json(text)
+        .map_err(|err| format!("Invalid key bindings in {}: {}", path, err))
+}
+
+/// After the usual panic message, also log what's needed to reproduce the crash.
+fn set_panic_context(map_path: String, seed: u64) {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        dbg_logf!(
+            "Crashed on map {} with seed {}, reproduce with: --map \"{}\" d_seed {}",
+            map_path,
+            seed,
+            map_path,
+            seed
+        );
+    }));
+}
+
 fn window_conf() -> Conf {
     Conf {
         window_title: "RecWars".to_owned(),
@@ -75,8 +251,25 @@ fn window_conf() -> Conf {
 
 #[macroquad::main(window_conf)]
 async fn main() {
+    if let Err(err) = run().await {
+        error!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+/// Load everything and play until the window is closed.
+///
+/// Errors in user-provided files and options are returned instead of panicking
+/// so they're reported once, in `main`.
+async fn run() -> Result<(), String> {
     let opts = get_opts();
 
+    if opts.print_bindings {
+        println!("{}", Bindings::player1().to_json());
+        println!("{}", Bindings::player2().to_json());
+        return Ok(());
+    }
+
     // This is a hack.
     // It seems that in the browser, MQ redraws the screen several times between here and the main loop
     // (even though there are no next_frame().await calls) so this doesn't stay up for long.
@@ -86,12 +279,40 @@ async fn main() {
     show_mouse(false);
 
     let mut cvars = Cvars::new_rec_wars();
-    let mut cvars_iter = opts.cvars.iter();
-    while let Some(cvar_name) = cvars_iter.next() {
-        let str_value = cvars_iter.next().unwrap();
-        cvars.set_str(cvar_name, str_value).unwrap();
+    if let Some(preset) = &opts.preset {
+        cvars
+            .load_preset(preset)
+            .map_err(|err| format!("Invalid preset: {}", err))?;
+    }
+    if let Some(path) = &opts.cvars_file {
+        let text = load_text(path).await?;
+        // The valid lines are applied anyway, typos shouldn't stop the game.
+        match cvars.import(&text) {
+            Ok(count) => {
+                dbg_logf!("Set {} cvars from {}", count, path);
+            }
+            Err(err) => {
+                dbg_logf!("Invalid cvars in {}: {}", path, err);
+            }
+        }
+    }
+    cvars
+        .set_pairs(&opts.cvars)
+        .map_err(|err| format!("Invalid cvars on the command line: {}", err))?;
+    for cvar_name in opts.cvars.iter().step_by(2) {
         dbg_logf!("{} = {}", cvar_name, cvars.get_string(cvar_name).unwrap());
     }
+    if opts.print_cvars {
+        print!("{}", cvars.export());
+        return Ok(());
+    }
+
+    let replay = if let Some(path) = &opts.replay {
+        let bytes = load_bytes(path).await?;
+        Some(parse_replay(path, &bytes, &mut cvars)?)
+    } else {
+        None
+    };
 
     let time_seed = macroquad::miniquad::date::now();
     if cvars.d_seed == 0 {
@@ -100,10 +321,10 @@ async fn main() {
     dbg_logf!("Seed: {}", cvars.d_seed);
 
     // LATER Load texture list and map in parallel with other assets
-    let tex_list_bytes = load_file("assets/texture_list.txt").await.unwrap();
+    let tex_list_path = "assets/texture_list.txt";
+    let tex_list_text = load_text(tex_list_path).await?;
     draw_text("Loading...", 400.0, 400.0, 32.0, PURPLE);
-    let tex_list_text = str::from_utf8(&tex_list_bytes).unwrap();
-    let surfaces = map::load_tex_list(tex_list_text);
+    let surfaces = parse_tex_list(tex_list_path, &tex_list_text)?;
 
     // This is a subset of maps that are not blatantly broken with the current bots.
     let maps = [
@@ -158,12 +379,15 @@ async fn main() {
         //"extra2/Winter (4)",
         //"extra2/World War (2)",
     ];
-    let mut map_path = opts.map.unwrap_or_else(|| {
-        // Intentionally not using cvars.d_seed here
-        // so that setting the seed doesn't force a specific map.
-        let index = time_seed as usize % maps.len();
-        maps[index].to_owned()
-    });
+    let mut map_path = match &replay {
+        Some(replay) => replay.map_path.clone(),
+        None => opts.map.unwrap_or_else(|| {
+            // Intentionally not using cvars.d_seed here
+            // so that setting the seed doesn't force a specific map.
+            let index = time_seed as usize % maps.len();
+            maps[index].to_owned()
+        }),
+    };
     if !map_path.ends_with(".map") {
         map_path.push_str(".map");
     }
@@ -172,35 +396,113 @@ async fn main() {
     }
     dbg_logf!("Map: {}", map_path);
 
-    let map_bytes = load_file(&map_path).await.unwrap();
+    if cvars.d_panic_context {
+        set_panic_context(map_path.clone(), cvars.d_seed);
+    }
+
+    let map_text = load_text(&map_path).await?;
     draw_text("Loading...", 400.0, 400.0, 32.0, PURPLE);
-    let map_text = str::from_utf8(&map_bytes).unwrap();
-    let map = map::load_map(map_text, surfaces);
+    let map = parse_map(&map_path, &map_text, surfaces)?;
+
+    let mut roster = Roster::builtin();
+    if let Some(path) = &opts.bot_personas {
+        let text = load_text(path).await?;
+        add_bot_personas(&mut roster, path, &text)?;
+    }
 
-    let mut server = Server::new(&cvars, map);
+    let mut server = Server::with_roster(&cvars, map, &roster);
 
-    let player1_handle = server.connect(&cvars, "Player 1");
-    let player2_handle = if opts.splitscreen {
-        Some(server.connect(&cvars, "Player 2"))
+    let (player1_handle, player2_handle) = if let Some(replay) = replay {
+        let handles: Vec<_> = replay
+            .players
+            .iter()
+            .zip(&replay.vehicles)
+            .map(|(name, &veh_type)| server.connect_with_vehicle(&cvars, name, veh_type))
+            .collect();
+        let handles = (handles[0], handles.get(1).copied());
+        server.play_replay(replay);
+        handles
     } else {
-        None
+        let player1_handle = server.connect_with_vehicle(&cvars, &opts.name, opts.vehicle);
+        let player2_handle = if opts.splitscreen {
+            Some(server.connect_with_vehicle(&cvars, &opts.name2, opts.vehicle2))
+        } else {
+            None
+        };
+        (player1_handle, player2_handle)
     };
+    if cvars.d_events {
+        server.on_event(Box::new(|notification| {
+            dbg_logf!(
+                "{:.3} {}: {:?}",
+                notification.time,
+                notification.kind.name(),
+                notification.kind
+            );
+            Ok(())
+        }));
+    }
+    if opts.record.is_some() {
+        server.start_recording(&cvars, &map_path);
+        // Save the replay when the window is closed
+        prevent_quit();
+    }
     // LATER It can take some time for assets to load but the game is already running on the server.
     //       Load assets first, then connect.
-    let mut client = MacroquadClient::new(&cvars, player1_handle, player2_handle).await;
+    let client = MacroquadClient::new(
+        &cvars,
+        server.map.surfaces(),
+        player1_handle,
+        player2_handle,
+    )
+    .await;
+    let mut client = client.map_err(|err| format!("Failed to load assets: {}", err))?;
+    client.editor_path = opts.map_editor_path;
+    for (path, bindings) in [
+        (&opts.bindings, &mut client.bindings1),
+        (&opts.bindings2, &mut client.bindings2),
+    ] {
+        if let Some(path) = path {
+            let text = load_text(path).await?;
+            set_bindings(bindings, path, &text)?;
+        }
+    }
+    if cvars.r_round_intro {
+        client.start_intro(&cvars, &mut server);
+    }
     draw_text("Loading...", 400.0, 400.0, 32.0, PURPLE);
 
     loop {
+        if is_quit_requested() {
+            let data = server.stop_recording();
+            let path = opts.record.as_ref().unwrap();
+            match fs::write(path, data) {
+                Ok(()) => {
+                    dbg_logf!("Saved replay to {}", path);
+                }
+                Err(err) => {
+                    dbg_logf!("Failed to save replay to {}: {}", path, err);
+                }
+            }
+            break;
+        }
+
         let real_time = get_time();
 
         server.snapshot_inputs();
 
-        client.process_input(&mut server);
+        client.resize(&cvars, screen_width() as f64, screen_height() as f64);
+        client.process_input(&cvars, &mut server, real_time);
 
         server.update(&cvars, real_time);
 
+        client.receive_updates(&cvars, &mut server);
+        client.receive_notifications(&cvars, &mut server);
+        client.update_feedback(&cvars, &mut server);
+
         rendering::render(&mut client, &server, &cvars);
         client.console.update(&mut cvars);
+        client.log_perf_stats(&cvars, &server, real_time);
 
         let before = get_time();
         next_frame().await;
@@ -209,4 +511,93 @@ async fn main() {
             .rest_durations
             .add(cvars.d_timing_samples, after - before);
     }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_non_utf8_file() {
+        let err = decode_text("maps/Atrium.map", vec![b'0', b' ', 0xff]).unwrap_err();
+        assert_eq!(
+            err,
+            "maps/Atrium.map is not valid UTF-8: invalid utf-8 sequence of 1 bytes from index 2"
+        );
+    }
+
+    #[test]
+    fn test_malformed_tex_list() {
+        let path = "assets/texture_list.txt";
+        let err = parse_tex_list(path, "g1 0 1 1\ng2 0 1\n").unwrap_err();
+        assert_eq!(err, "Failed to load assets/texture_list.txt: 2:1: expected name, kind, friction and speed, got \"g2 0 1\"");
+        let err = parse_tex_list(path, "g1 0 1 1\ng1 0 1 1\n").unwrap_err();
+        assert_eq!(
+            err,
+            "Failed to load assets/texture_list.txt: 2:1: duplicate surface \"g1\""
+        );
+    }
+
+    #[test]
+    fn test_malformed_map() {
+        let surfaces = parse_tex_list("tex", "g1 0 1 1\n").unwrap();
+        let err = parse_map("maps/bad.map", "0 0\n0 x\n", surfaces.clone()).unwrap_err();
+        assert_eq!(
+            err,
+            "Failed to load map maps/bad.map: 2:3: unknown tiles: \"x\""
+        );
+        let err = parse_map("maps/bad.map", "0 0\n0\n", surfaces).unwrap_err();
+        assert_eq!(
+            err,
+            "Failed to load map maps/bad.map: 2:1: row has 1 tiles, expected 2"
+        );
+    }
+
+    #[test]
+    fn test_bad_replay() {
+        let mut cvars = Cvars::default();
+        let err = parse_replay("bad.rwr", b"nope", &mut cvars).unwrap_err();
+        assert_eq!(err, "Failed to load replay bad.rwr: byte 4: not a replay");
+
+        let mut replay = Replay::new(&cvars, "maps/Atrium.map", vec![], vec![]);
+        let data = replay.encode();
+        let err = parse_replay("short.rwr", &data[..data.len() - 1], &mut cvars).unwrap_err();
+        // The position depends on how many cvars there are.
+        assert!(err.starts_with("Failed to load replay short.rwr: byte "));
+        assert!(err.ends_with(": unexpected end of data"));
+
+        replay.cvars.push(("g_nope".to_owned(), "1".to_owned()));
+        let err = parse_replay("cvars.rwr", &replay.encode(), &mut cvars).unwrap_err();
+        assert_eq!(
+            err,
+            "Failed to apply cvars from replay cvars.rwr: Cvar named g_nope not found"
+        );
+    }
+
+    #[test]
+    fn test_bad_bot_personas() {
+        let mut roster = Roster::builtin();
+        let err = add_bot_personas(&mut roster, "bots.jsonl", "\n{\"name\": 5}\n").unwrap_err();
+        assert_eq!(
+            err,
+            "Invalid bot persona on line 2 of bots.jsonl: field \"name\": wrong type"
+        );
+    }
+
+    #[test]
+    fn test_bad_bindings() {
+        let mut bindings = Bindings::player1();
+        let err = set_bindings(&mut bindings, "keys.json", "{\"Q\": \"nope\"}").unwrap_err();
+        assert_eq!(
+            err,
+            "Invalid key bindings in keys.json: key \"Q\": unknown action \"nope\""
+        );
+        let err = set_bindings(&mut bindings, "keys.json", "[").unwrap_err();
+        assert_eq!(
+            err,
+            "Invalid key bindings in keys.json: byte 0: expected '{'"
+        );
+    }
 }
@@ -1,12 +1,19 @@
-//! Map data (everything static during a match) and coordinate system
+//! Map data (everything static during a match except destructible walls) and coordinate system
 
-use std::f64::consts::PI;
-use std::ops::Index;
+use std::{
+    error::Error,
+    f64::consts::PI,
+    fmt::{self, Display, Formatter},
+    ops::Index,
+};
 
 use enumn::N;
-use rand::{prelude::SmallRng, Rng};
+use fnv::FnvHashMap;
+use rand::Rng;
 use vek::{approx::AbsDiffEq, Clamp, Mat2, Vec2};
 
+use crate::entities::{PickupKind, PowerupKind, Team};
+
 /// Position in world or screen space.
 ///
 /// ### Coord system
@@ -62,28 +69,95 @@ pub struct Map {
     surfaces: Vec<Surface>,
     tiles: Vec<Vec<Tile>>,
     spawns: Vec<Vec2u>,
+    /// Spawns only one team uses, the rest are shared, see `random_spawn`.
+    team_spawns: Vec<(Vec2u, Team)>,
     bases: Vec<Vec2u>,
+    /// All hazard tiles so the hazards system doesn't have to scan the whole map.
+    hazards: Vec<Hazard>,
+    /// Ammo crates and repair pads placed by the map.
+    pickups: Vec<(Vec2u, PickupKind)>,
+    /// Bot personas which always play on this map, see `roster`.
+    pinned_bots: Vec<String>,
+    /// Row by row, whether the tile is a wall, so `is_wall` doesn't have to look up the surface.
+    walls: Vec<bool>,
+    /// Row by row, whether the tile or any of its 8 neighbors is a wall or outside the map.
+    /// `wall_trace` only needs to check exact positions in these tiles.
+    near_walls: Vec<bool>,
+    /// Damage taken by destructible walls which are still standing.
+    /// Sparse so maps without any damaged walls don't pay for it.
+    wall_damage: FnvHashMap<Vec2u, f64>,
+    /// Walls which were destroyed this match and the tiles they were before, oldest first.
+    destroyed_walls: Vec<(Vec2u, Tile)>,
 }
 
 impl Map {
     fn new(tiles: Vec<Vec<Tile>>, surfaces: Vec<Surface>) -> Self {
-        let mut spawns = Vec::new();
-        let mut bases = Vec::new();
-        for (r, row) in tiles.iter().enumerate() {
-            for (c, tile) in row.iter().enumerate() {
-                let kind = surfaces[tile.surface_index].kind;
+        let mut map = Map {
+            surfaces,
+            tiles,
+            spawns: Vec::new(),
+            team_spawns: Vec::new(),
+            bases: Vec::new(),
+            hazards: Vec::new(),
+            pickups: Vec::new(),
+            pinned_bots: Vec::new(),
+            walls: Vec::new(),
+            near_walls: Vec::new(),
+            wall_damage: FnvHashMap::default(),
+            destroyed_walls: Vec::new(),
+        };
+        map.index_tiles();
+        map
+    }
+
+    /// Rebuild the lists of special tiles after loading or editing.
+    ///
+    /// Hazards which are still hazards keep their parameters.
+    fn index_tiles(&mut self) {
+        let hazards_prev = std::mem::take(&mut self.hazards);
+        self.spawns.clear();
+        self.bases.clear();
+        for r in 0..self.height() {
+            for c in 0..self.width() {
+                let index = Vec2u::new(c, r);
+                let kind = self.surface_at_index(index).kind;
                 if kind == Kind::Spawn {
-                    spawns.push(Vec2u::new(c, r));
+                    self.spawns.push(index);
                 } else if kind == Kind::Base {
-                    bases.push(Vec2u::new(c, r));
+                    self.bases.push(index);
+                } else if kind.is_hazard() {
+                    let hazard = hazards_prev
+                        .iter()
+                        .find(|hazard| hazard.index == index)
+                        .cloned()
+                        .unwrap_or(Hazard {
+                            index,
+                            ..Hazard::default()
+                        });
+                    self.hazards.push(hazard);
                 }
             }
         }
-        Map {
-            surfaces,
-            tiles,
-            spawns,
-            bases,
+
+        let size = self.size();
+        self.walls.clear();
+        for r in 0..size.y {
+            for c in 0..size.x {
+                let wall = self.surface_at_index(Vec2u::new(c, r)).kind == Kind::Wall;
+                self.walls.push(wall);
+            }
+        }
+        self.near_walls.clear();
+        for r in 0..size.y {
+            for c in 0..size.x {
+                let near = c == 0
+                    || r == 0
+                    || c == size.x - 1
+                    || r == size.y - 1
+                    || (r - 1..=r + 1)
+                        .any(|nr| (c - 1..=c + 1).any(|nc| self.walls[nr * size.x + nc]));
+                self.near_walls.push(near);
+            }
         }
     }
 
@@ -132,10 +206,57 @@ impl Map {
         TilePos { index, offset }
     }
 
+    /// Like `tile_pos` but `None` outside the map instead of clamping.
+    pub fn tile_at(&self, pos: Vec2f) -> Option<TilePos> {
+        let maxs = self.maxs();
+        if pos.x < 0.0 || pos.y < 0.0 || pos.x >= maxs.x || pos.y >= maxs.y {
+            return None;
+        }
+        Some(self.tile_pos(pos))
+    }
+
     pub fn tile_center(&self, tile_index: Vec2u) -> Vec2f {
         tile_index.as_() * TILE_SIZE + TILE_SIZE / 2.0
     }
 
+    /// Indices of tiles overlapping the area from `min` (inclusive) to `max` (exclusive), row by row.
+    ///
+    /// Parts outside the map are skipped.
+    pub fn tiles_in_rect(&self, min: Vec2f, max: Vec2f) -> impl Iterator<Item = Vec2u> {
+        let maxs = self.maxs();
+        let empty = max.x <= min.x
+            || max.y <= min.y
+            || max.x <= 0.0
+            || max.y <= 0.0
+            || min.x >= maxs.x
+            || min.y >= maxs.y;
+        let first = self.tile_pos(min).index;
+        let last = ((max / TILE_SIZE).ceil() - 1.0)
+            .clamped(Vec2f::zero(), (self.size() - 1).as_())
+            .as_::<usize>();
+        let rows = if empty { 0..0 } else { first.y..last.y + 1 };
+        rows.flat_map(move |y| (first.x..=last.x).map(move |x| Vec2u::new(x, y)))
+    }
+
+    /// Indices of tiles the segment passes through, in order. Parts outside the map are skipped.
+    ///
+    /// Touching a tile only at its corner doesn't count.
+    pub fn tiles_on_segment(&self, from: Vec2f, to: Vec2f) -> impl Iterator<Item = Vec2u> {
+        let size = self.size().as_::<i64>();
+        GridTraversal::new(from, to).filter_map(move |cell| {
+            if cell.x < 0 || cell.y < 0 || cell.x >= size.x || cell.y >= size.y {
+                None
+            } else {
+                Some(cell.as_())
+            }
+        })
+    }
+
+    /// The tileset - the index of each surface is also the index of its image.
+    pub fn surfaces(&self) -> &[Surface] {
+        &self.surfaces
+    }
+
     pub fn surface_of(&self, tile: Tile) -> &Surface {
         &self.surfaces[tile.surface_index]
     }
@@ -166,79 +287,299 @@ impl Map {
             return true;
         }
 
-        let kind = self.surface_at_pos(pos).kind;
-        if kind == Kind::Wall {
-            return true;
-        }
-
-        false
+        let index = (pos / TILE_SIZE).as_::<usize>();
+        self.walls[index.y * self.width() + index.x]
     }
 
     /// Find first wall collision when traveling from `begin` to `end`.
     /// The returned point is nudged slightly inside the wall.
     /// Area outside the map is considered wall.
     pub fn is_wall_trace(&self, begin: Vec2f, end: Vec2f) -> Option<Vec2f> {
+        self.wall_trace(begin, end).map(|hit| hit.pos)
+    }
+
+    /// Like `is_wall_trace` but also says which face of the wall was hit.
+    pub fn wall_trace(&self, begin: Vec2f, end: Vec2f) -> Option<WallHit> {
         if self.is_wall(begin) {
-            return Some(begin);
+            return Some(WallHit {
+                pos: begin,
+                contact: begin,
+                normal: Vec2f::zero(),
+            });
         }
 
-        // similar to the simple, unoptimized version here:
-        // https://playtechs.blogspot.com/2007/03/raytracing-on-grid.html
+        // Most projectile steps stay inside one tile, skip the rest of the setup for them.
+        let traversal = GridTraversal::new(begin, end);
+        if traversal.stays_in_cell() {
+            return None;
+        }
 
+        // Walking the vertical and the horizontal grid lines separately is faster than
+        // alternating between them, the second walk stops at the first one's hit.
         let delta = end - begin;
         let nudge = delta.normalized() * 0.01;
+        let mut hit = None;
+        let mut t_max = 1.0;
+        self.wall_trace_axis::<0>(&traversal, begin, delta, nudge, &mut t_max, &mut hit);
+        self.wall_trace_axis::<1>(&traversal, begin, delta, nudge, &mut t_max, &mut hit);
+        hit
+    }
 
-        // Find closest vertical and horizontal intersections with the grid.
-        let mut t_x = if delta.x == 0.0 {
-            f64::INFINITY
-        } else {
-            // Next intersection along the X axis (with a vertical line).
-            let next_x = if delta.x > 0.0 {
-                (begin.x / TILE_SIZE).ceil() * TILE_SIZE
-            } else {
-                (begin.x / TILE_SIZE).floor() * TILE_SIZE
-            };
+    /// Check where the segment crosses the grid lines perpendicular to `AXIS` up to `t_max`.
+    /// On a hit, lower `t_max` to it. Exactly through a corner, both axes hit and y's normal wins.
+    fn wall_trace_axis<const AXIS: usize>(
+        &self,
+        traversal: &GridTraversal,
+        begin: Vec2f,
+        delta: Vec2f,
+        nudge: Vec2f,
+        t_max: &mut f64,
+        hit: &mut Option<WallHit>,
+    ) {
+        let other = 1 - AXIS;
+        let step = traversal.step[AXIS];
+        if step == 0 {
+            return;
+        }
+        let size = self.size().as_::<i64>();
+        let stride = Vec2::new(1, size.x);
+        let mut cell = traversal.cell[AXIS];
+        let mut t = traversal.t_next[AXIS];
+        while t <= *t_max {
+            cell += step;
+            // Truncating instead of flooring puts slightly negative coordinates into the first row
+            // which is along the border and therefore always checked.
+            let cell_other = ((begin[other] + delta[other] * t) / TILE_SIZE) as i64;
+            // The nudged point is at most 0.01 from the entered cell so it can only be
+            // in a wall if the cell is next to one. Most cells aren't, especially on long rays.
+            let inside =
+                cell >= 0 && cell_other >= 0 && cell < size[AXIS] && cell_other < size[other];
+            if !inside
+                || self.near_walls[(cell * stride[AXIS] + cell_other * stride[other]) as usize]
+            {
+                let intersection = begin + delta * t;
+                let wall = intersection + nudge;
+                if self.is_wall(wall) {
+                    let mut normal = Vec2f::zero();
+                    normal[AXIS] = -step as f64;
+                    *hit = Some(WallHit {
+                        pos: wall,
+                        contact: intersection,
+                        normal,
+                    });
+                    *t_max = t;
+                    return;
+                }
+            }
+            t += traversal.t_delta[AXIS];
+        }
+    }
 
-            // How far along the line are we?
-            let travelled_x = next_x - begin.x;
-            travelled_x / delta.x
+    /// Replace tiles of autotiled surfaces (see `AUTOTILE_FAMILIES`) with the piece which fits their 8 neighbors,
+    /// e.g. shores get water edges and corners rotated to face the land.
+    ///
+    /// Neighbors outside the map count as the same kind as the tile.
+    /// Tiles for which no piece fits (or the piece isn't in the texture list) get the family's plain surface.
+    pub fn autotile(&mut self) {
+        let find = |name: &str| {
+            self.surfaces
+                .iter()
+                .position(|surface| surface.name == name)
         };
-        let mut t_y = if delta.y == 0.0 {
-            f64::INFINITY
-        } else {
-            // Next intersection along the X axis (with a vertical line).
-            let next_y = if delta.y > 0.0 {
-                (begin.y / TILE_SIZE).ceil() * TILE_SIZE
-            } else {
-                (begin.y / TILE_SIZE).floor() * TILE_SIZE
+        let mut changes = Vec::new();
+        for family in &AUTOTILE_FAMILIES {
+            let plain = match find(family.plain) {
+                Some(index) => index,
+                None => continue,
             };
+            let pieces: Vec<_> = family
+                .pieces
+                .iter()
+                .filter_map(|piece| Some((find(piece.surface)?, piece)))
+                .collect();
+            let members: Vec<_> = family
+                .pieces
+                .iter()
+                .filter_map(|piece| find(piece.surface))
+                .chain(Some(plain))
+                .collect();
+
+            for r in 0..self.height() {
+                for c in 0..self.width() {
+                    if !members.contains(&self.col_row(c, r).surface_index) {
+                        continue;
+                    }
 
-            // How far along the line are we?
-            let travelled_y = next_y - begin.y;
-            travelled_y / delta.y
-        };
+                    let mut same = 0;
+                    for &(dc, dr, bit) in &NEIGHBORS {
+                        let nc = c as isize + dc;
+                        let nr = r as isize + dr;
+                        let outside = nc < 0
+                            || nr < 0
+                            || nc >= self.width() as isize
+                            || nr >= self.height() as isize;
+                        if outside
+                            || self
+                                .surface_at_index(Vec2u::new(nc as usize, nr as usize))
+                                .kind
+                                == family.kind
+                        {
+                            same |= bit;
+                        }
+                    }
 
-        // After finding the first intersection, the subsequent steps all have the same size.
-        let t_step_x = TILE_SIZE / delta.x.abs();
-        let t_step_y = TILE_SIZE / delta.y.abs();
-        loop {
-            let t;
-            if t_x < t_y {
-                t = t_x;
-                t_x += t_step_x;
-            } else {
-                t = t_y;
-                t_y += t_step_y;
-            };
-            if t > 1.0 {
-                return None;
-            }
-            let intersection = begin + delta * t;
-            let wall = intersection + nudge;
-            if self.is_wall(wall) {
-                return Some(wall);
+                    let (surface_index, rotation) = pieces
+                        .iter()
+                        .find(|(_, piece)| same & piece.care == piece.same)
+                        .map(|&(index, piece)| (index, piece.rotation))
+                        .unwrap_or((plain, 0));
+                    let tile = Tile {
+                        surface_index,
+                        angle: rotation as f64 * -PI / 2.0,
+                    };
+                    changes.push((c, r, tile));
+                }
             }
         }
+        // Applied at the end so all pieces are chosen based on the map as loaded.
+        for (c, r, tile) in changes {
+            self.tiles[r][c] = tile;
+        }
+        self.index_tiles();
+    }
+
+    /// The tile at `index`, unlike indexing it's an error if it's outside the map.
+    pub fn tile(&self, index: Vec2u) -> Result<Tile, EditError> {
+        self.check_index(index)?;
+        Ok(self[index])
+    }
+
+    /// Replace the tile at `index`, returns the previous one.
+    ///
+    /// Spawns, bases and hazards are updated, data derived from the map isn't - see `Server::set_tile`.
+    pub fn set_tile(&mut self, index: Vec2u, tile: Tile) -> Result<Tile, EditError> {
+        self.check_index(index)?;
+        self.check_surface(tile)?;
+        let prev = self[index];
+        self.tiles[index.y][index.x] = tile;
+        // The editor's tile is the new pristine state.
+        self.wall_damage.remove(&index);
+        self.destroyed_walls
+            .retain(|&(destroyed, _)| destroyed != index);
+        self.index_tiles();
+        Ok(prev)
+    }
+
+    /// Grow or crop the map to `size` (cols, rows), the top left corner stays in place.
+    /// New tiles are `fill`.
+    pub fn resize(&mut self, size: Vec2u, fill: Tile) -> Result<(), EditError> {
+        if size.x == 0 || size.y == 0 {
+            return Err(EditError::EmptySize);
+        }
+        self.check_surface(fill)?;
+        self.tiles.resize(size.y, vec![fill; size.x]);
+        for row in &mut self.tiles {
+            row.resize(size.x, fill);
+        }
+        let inside = |index: Vec2u| index.x < size.x && index.y < size.y;
+        self.pickups.retain(|&(index, _)| inside(index));
+        self.wall_damage.retain(|&index, _| inside(index));
+        self.destroyed_walls.retain(|&(index, _)| inside(index));
+        self.index_tiles();
+        Ok(())
+    }
+
+    /// Damage the wall at `index` if it's destructible, returns whether it was destroyed.
+    ///
+    /// A destroyed wall is replaced by its rubble tile immediately
+    /// so collisions later in the same frame already go through it.
+    pub fn damage_wall(&mut self, index: Vec2u, amount: f64) -> bool {
+        let tile = self[index];
+        let destructible = match self.surface_of(tile).destructible {
+            Some(destructible) if amount > 0.0 => destructible,
+            _ => return false,
+        };
+        let damage = self.wall_damage.entry(index).or_insert(0.0);
+        *damage += amount;
+        if *damage < destructible.hp {
+            return false;
+        }
+        self.wall_damage.remove(&index);
+        self.destroyed_walls.push((index, tile));
+        self.tiles[index.y][index.x] = Tile {
+            surface_index: destructible.rubble,
+            angle: tile.angle,
+        };
+        self.index_tiles();
+        true
+    }
+
+    /// Damage taken by the wall at `index`, 0 if it's pristine or not destructible.
+    pub fn wall_damage(&self, index: Vec2u) -> f64 {
+        self.wall_damage.get(&index).copied().unwrap_or(0.0)
+    }
+
+    /// Destructible walls which are damaged but still standing and how much damage they took.
+    pub fn damaged_walls(&self) -> impl Iterator<Item = (Vec2u, f64)> + '_ {
+        self.wall_damage
+            .iter()
+            .map(|(&index, &damage)| (index, damage))
+    }
+
+    /// Walls destroyed this match and the tiles they were before, oldest first.
+    pub fn destroyed_walls(&self) -> &[(Vec2u, Tile)] {
+        &self.destroyed_walls
+    }
+
+    /// Rebuild destroyed walls and remove all damage, e.g. for a new match.
+    /// Returns whether anything changed.
+    pub fn repair_walls(&mut self) -> bool {
+        let changed = !self.wall_damage.is_empty() || !self.destroyed_walls.is_empty();
+        self.wall_damage.clear();
+        for (index, tile) in self.destroyed_walls.drain(..).rev() {
+            self.tiles[index.y][index.x] = tile;
+        }
+        if changed {
+            self.index_tiles();
+        }
+        changed
+    }
+
+    /// Set the state of destructible walls, e.g. when restoring a snapshot.
+    ///
+    /// Repairs all walls first, then destroys `destroyed` (the tile each was before is taken from the map)
+    /// and applies `damage`.
+    pub fn set_wall_state(&mut self, destroyed: &[Vec2u], damage: &[(Vec2u, f64)]) {
+        self.repair_walls();
+        for &index in destroyed {
+            let hp = self
+                .surface_at_index(index)
+                .destructible
+                .map_or(0.0, |destructible| destructible.hp);
+            self.damage_wall(index, hp);
+        }
+        self.wall_damage.extend(damage.iter().copied());
+    }
+
+    fn check_index(&self, index: Vec2u) -> Result<(), EditError> {
+        if index.x >= self.width() || index.y >= self.height() {
+            return Err(EditError::OutOfBounds {
+                index,
+                size: self.size(),
+            });
+        }
+        Ok(())
+    }
+
+    fn check_surface(&self, tile: Tile) -> Result<(), EditError> {
+        if tile.surface_index >= self.surfaces.len() {
+            return Err(EditError::UnknownSurface(tile.surface_index));
+        }
+        Ok(())
+    }
+
+    pub fn pinned_bots(&self) -> &[String] {
+        &self.pinned_bots
     }
 
     pub fn spawns(&self) -> &Vec<Vec2u> {
@@ -247,23 +588,64 @@ impl Map {
 
     // LATER remove all #[allow(dead_code)] here (or the fns if they turn out useless)
 
-    #[allow(dead_code)]
     pub fn bases(&self) -> &Vec<Vec2u> {
         &self.bases
     }
 
+    /// Hazard tiles in row-major order.
+    pub fn hazards(&self) -> &[Hazard] {
+        &self.hazards
+    }
+
+    /// Tile and kind of each pickup placed by the map, in the order of the map file.
+    pub fn pickups(&self) -> &[(Vec2u, PickupKind)] {
+        &self.pickups
+    }
+
+    pub fn hazard_kind(&self, hazard: &Hazard) -> Kind {
+        self.surface_at_index(hazard.index).kind
+    }
+
+    /// Spawns reserved for `team` which are still spawn tiles.
+    pub fn team_spawns(&self, team: Team) -> Vec<Vec2u> {
+        self.team_spawns
+            .iter()
+            .filter(|&&(index, spawn_team)| spawn_team == team && self.spawns.contains(&index))
+            .map(|&(index, _)| index)
+            .collect()
+    }
+
     /// Returns (pos, angle).
-    pub fn random_spawn(&self, rng: &mut SmallRng) -> (Vec2f, f64) {
+    ///
+    /// A team only uses its own spawns, or the shared ones if the map gives it none.
+    /// Without a team (or if all spawns belong to teams), any spawn can be used.
+    pub fn random_spawn(&self, rng: &mut impl Rng, team: Option<Team>) -> (Vec2f, f64) {
+        let own = team.map(|team| self.team_spawns(team)).unwrap_or_default();
+        let candidates = if !own.is_empty() {
+            own
+        } else {
+            let shared: Vec<_> = self
+                .spawns
+                .iter()
+                .copied()
+                .filter(|&index| self.team_spawns.iter().all(|&(tagged, _)| tagged != index))
+                .collect();
+            if team.is_some() && !shared.is_empty() {
+                shared
+            } else {
+                self.spawns.clone()
+            }
+        };
         // TODO maps with no spawns (or even all walls)
-        let i = rng.gen_range(0..self.spawns().len());
-        let index = self.spawns()[i];
+        let i = rng.gen_range(0..candidates.len());
+        let index = candidates[i];
         let pos = self.tile_center(index);
         let angle = self[index].angle;
         (pos, angle)
     }
 
     /// Returns (pos, angle).
-    pub fn random_nonwall(&self, rng: &mut SmallRng) -> (Vec2f, f64) {
+    pub fn random_nonwall(&self, rng: &mut impl Rng) -> (Vec2f, f64) {
         loop {
             let c = rng.gen_range(0..self.width());
             let r = rng.gen_range(0..self.height());
@@ -292,6 +674,89 @@ pub struct TilePos {
     pub offset: Vec2f,
 }
 
+/// Cells of the infinite tile grid a segment passes through, in order.
+///
+/// Similar to the simple, unoptimized version here:
+/// https://playtechs.blogspot.com/2007/03/raytracing-on-grid.html
+/// Step into the next cell along whichever grid line the segment crosses first.
+/// Exactly through a corner steps diagonally.
+#[derive(Debug, Clone)]
+struct GridTraversal {
+    cell: Vec2<i64>,
+    step: Vec2<i64>,
+    /// Fraction of the segment where it crosses the next vertical / horizontal grid line.
+    t_next: Vec2f,
+    /// After the first crossing, the subsequent ones are all the same distance apart.
+    t_delta: Vec2f,
+    started: bool,
+}
+
+impl GridTraversal {
+    fn new(begin: Vec2f, end: Vec2f) -> Self {
+        let delta = end - begin;
+        let cell = (begin / TILE_SIZE).floor();
+        let axis = |begin: f64, delta: f64, cell: f64| {
+            if delta == 0.0 {
+                f64::INFINITY
+            } else {
+                let next = if delta > 0.0 { cell + 1.0 } else { cell };
+                (next * TILE_SIZE - begin) / delta
+            }
+        };
+        Self {
+            cell: cell.as_(),
+            step: delta.map(|d| d.signum() as i64),
+            t_next: Vec2f::new(
+                axis(begin.x, delta.x, cell.x),
+                axis(begin.y, delta.y, cell.y),
+            ),
+            t_delta: delta.map(|d| TILE_SIZE / d.abs()),
+            started: false,
+        }
+    }
+
+    /// The segment doesn't cross any grid lines.
+    fn stays_in_cell(&self) -> bool {
+        let t = self.t_next.x.min(self.t_next.y);
+        t > 1.0 || t.is_nan()
+    }
+}
+
+impl Iterator for GridTraversal {
+    type Item = Vec2<i64>;
+
+    fn next(&mut self) -> Option<Vec2<i64>> {
+        if !self.started {
+            self.started = true;
+            return Some(self.cell);
+        }
+        let t = self.t_next.x.min(self.t_next.y);
+        if t > 1.0 || t.is_nan() {
+            return None;
+        }
+        if self.t_next.x <= self.t_next.y {
+            self.cell.x += self.step.x;
+            self.t_next.x += self.t_delta.x;
+        }
+        if self.t_next.y <= t {
+            self.cell.y += self.step.y;
+            self.t_next.y += self.t_delta.y;
+        }
+        Some(self.cell)
+    }
+}
+
+/// Where a trace hit a wall, see `Map::wall_trace`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WallHit {
+    /// Nudged slightly inside the wall.
+    pub pos: Vec2f,
+    /// Exactly on the wall's face.
+    pub contact: Vec2f,
+    /// Unit vector pointing out of the face which was crossed, zero if the trace started inside a wall.
+    pub normal: Vec2f,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Tile {
     /// Index into texture_list.txt
@@ -300,14 +765,32 @@ pub struct Tile {
     pub angle: f64,
 }
 
+impl Tile {
+    /// `rotation` is the number of turns counterclockwise like in map files, see `load_map`.
+    pub fn new(surface_index: usize, rotation: usize) -> Self {
+        Self {
+            surface_index,
+            angle: (rotation % 4) as f64 * -PI / 2.0,
+        }
+    }
+
+    /// Number of turns counterclockwise, 0 to 3.
+    pub fn rotation(self) -> usize {
+        let turns = (-self.angle / (PI / 2.0)).round() as i64;
+        turns.rem_euclid(4) as usize
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Surface {
     pub name: String,
     pub kind: Kind,
     /// Seems to affect both turning and acceleration
     pub friction: f32,
-    /// Maybe a multiplier for speed
+    /// Multiplier for acceleration and max speed of vehicles on it
     pub speed: f32,
+    /// Set by maps, not the texture list - see `load_map`.
+    pub destructible: Option<Destructible>,
 }
 
 impl Surface {
@@ -317,10 +800,19 @@ impl Surface {
             kind,
             friction,
             speed,
+            destructible: None,
         }
     }
 }
 
+/// Walls of a destructible surface are replaced by `rubble` after taking `hp` damage.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Destructible {
+    pub hp: f64,
+    /// Index of the surface, it's never a wall.
+    pub rubble: usize,
+}
+
 /// Special behavior of some tiles.
 ///
 /// Reverse engineered by modifying RecWar's TextureList.txt and seeing what happens.
@@ -339,65 +831,758 @@ pub enum Kind {
     Snow = 4,
     /// Base for Capture the Cow
     Base = 5,
+    /// Damages vehicles on it continuously
+    DamageFloor = 6,
+    /// Periodically comes down and crushes vehicles on it
+    Crusher = 7,
+    /// Spike trap - fires once when a vehicle drives over it, then needs to rearm
+    Spikes = 8,
+}
+
+impl Kind {
+    pub fn is_hazard(self) -> bool {
+        matches!(self, Kind::DamageFloor | Kind::Crusher | Kind::Spikes)
+    }
 }
 
-pub fn load_map(text: &str, surfaces: Vec<Surface>) -> Map {
-    // TODO handle both CRLF and LF properly
-    // TODO move to Map::new()?
-    let tiles = text
-        .split_terminator("\r\n")
-        .map(|line| {
-            line.split(' ')
-                .map(|tile| {
-                    let val: usize = tile.parse().unwrap();
-                    // rotation is number of turns counterclockwise
-                    // angle is clockwise (see Vec2f for coord system explanation)
-                    // g_spawn: rotation - angle - meaning
-                    // 0    0           right
-                    // 1    -1/2*PI     up
-                    // 2    -PI         left
-                    // 3    -3/2*PI     down
-                    let rotation = val % 4;
-                    Tile {
-                        surface_index: val / 4,
+/// A hazard tile and its parameters.
+///
+/// Values not set by the map fall back to the `g_hazard_*` cvars.
+/// Not all of them make sense for all kinds, e.g. damage floors ignore timing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hazard {
+    pub index: Vec2u,
+    pub period: Option<f64>,
+    pub phase: Option<f64>,
+    /// How long a crusher stays down
+    pub duration: Option<f64>,
+    /// Damage per hit - for damage floors it's per second
+    pub damage: Option<f64>,
+    /// Spike trap rearm delay
+    pub rearm: Option<f64>,
+}
+
+/// Tokens which can be used instead of tile numbers in autotile mode and their plain surfaces.
+const LOGICAL_TILES: [(&str, &str); 3] = [(".", "g1"), ("~", "water"), ("#", "bunker1")];
+
+// Neighbor bits for autotiling.
+const NORTH: u8 = 1 << 0;
+const EAST: u8 = 1 << 1;
+const SOUTH: u8 = 1 << 2;
+const WEST: u8 = 1 << 3;
+const NORTH_EAST: u8 = 1 << 4;
+const SOUTH_EAST: u8 = 1 << 5;
+const SOUTH_WEST: u8 = 1 << 6;
+const NORTH_WEST: u8 = 1 << 7;
+const SIDES: u8 = NORTH | EAST | SOUTH | WEST;
+
+/// Column offset, row offset and bit of each neighbor.
+const NEIGHBORS: [(isize, isize, u8); 8] = [
+    (0, -1, NORTH),
+    (1, 0, EAST),
+    (0, 1, SOUTH),
+    (-1, 0, WEST),
+    (1, -1, NORTH_EAST),
+    (1, 1, SOUTH_EAST),
+    (-1, 1, SOUTH_WEST),
+    (-1, -1, NORTH_WEST),
+];
+
+/// Surfaces which `Map::autotile` replaces with each other depending on neighbors of the same kind.
+struct AutotileFamily {
+    kind: Kind,
+    /// Used when no piece fits.
+    plain: &'static str,
+    /// The first which fits is used.
+    pieces: &'static [AutotilePiece],
+}
+
+/// Fits when out of the neighbors in `care`, exactly those in `same` have the family's kind.
+struct AutotilePiece {
+    surface: &'static str,
+    /// Number of turns counterclockwise, same as in map files.
+    rotation: usize,
+    care: u8,
+    same: u8,
+}
+
+impl AutotilePiece {
+    const fn new(surface: &'static str, rotation: usize, care: u8, same: u8) -> Self {
+        Self {
+            surface,
+            rotation,
+            care,
+            same,
+        }
+    }
+}
+
+/// Orientation of the pieces is taken from how the original maps use them.
+/// There are no inner corners so those get the middle piece.
+/// Walls have no edge pieces at all but are listed so the wall token gets the plain surface.
+const AUTOTILE_FAMILIES: [AutotileFamily; 2] = [
+    AutotileFamily {
+        kind: Kind::Water,
+        plain: "water",
+        pieces: &[
+            AutotilePiece::new("water_middle", 0, SIDES, SIDES),
+            // Sides - shore to the west, south, east, north.
+            AutotilePiece::new(
+                "water_side",
+                0,
+                SIDES | NORTH_EAST | SOUTH_EAST,
+                NORTH | EAST | SOUTH | NORTH_EAST | SOUTH_EAST,
+            ),
+            AutotilePiece::new(
+                "water_side",
+                1,
+                SIDES | NORTH_EAST | NORTH_WEST,
+                NORTH | EAST | WEST | NORTH_EAST | NORTH_WEST,
+            ),
+            AutotilePiece::new(
+                "water_side",
+                2,
+                SIDES | NORTH_WEST | SOUTH_WEST,
+                NORTH | SOUTH | WEST | NORTH_WEST | SOUTH_WEST,
+            ),
+            AutotilePiece::new(
+                "water_side",
+                3,
+                SIDES | SOUTH_EAST | SOUTH_WEST,
+                EAST | SOUTH | WEST | SOUTH_EAST | SOUTH_WEST,
+            ),
+            // Corners - land to the north west, south west, south east, north east.
+            AutotilePiece::new(
+                "water_corner",
+                0,
+                SIDES | SOUTH_EAST,
+                EAST | SOUTH | SOUTH_EAST,
+            ),
+            AutotilePiece::new(
+                "water_corner",
+                1,
+                SIDES | NORTH_EAST,
+                NORTH | EAST | NORTH_EAST,
+            ),
+            AutotilePiece::new(
+                "water_corner",
+                2,
+                SIDES | NORTH_WEST,
+                NORTH | WEST | NORTH_WEST,
+            ),
+            AutotilePiece::new(
+                "water_corner",
+                3,
+                SIDES | SOUTH_WEST,
+                SOUTH | WEST | SOUTH_WEST,
+            ),
+        ],
+    },
+    AutotileFamily {
+        kind: Kind::Wall,
+        plain: "bunker1",
+        pieces: &[],
+    },
+];
+
+/// Error in a map file or the texture list, line and column are 1-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapError {
+    pub line: usize,
+    pub column: usize,
+    pub msg: String,
+}
+
+impl MapError {
+    fn new(line: usize, column: usize, msg: String) -> Self {
+        Self { line, column, msg }
+    }
+}
+
+impl Display for MapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.msg)
+    }
+}
+
+impl Error for MapError {}
+
+/// Invalid arguments to the map editing methods.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditError {
+    OutOfBounds {
+        index: Vec2u,
+        size: Vec2u,
+    },
+    UnknownSurface(usize),
+    /// Maps need at least one row and column.
+    EmptySize,
+}
+
+impl Display for EditError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::OutOfBounds { index, size } => write!(
+                f,
+                "tile {} {} is outside the {}x{} map",
+                index.x, index.y, size.x, size.y
+            ),
+            EditError::UnknownSurface(index) => write!(f, "no surface {}", index),
+            EditError::EmptySize => write!(f, "map can't be empty"),
+        }
+    }
+}
+
+impl Error for EditError {}
+
+/// Parse a map in the original RecWar format.
+///
+/// Each line is a row of space separated numbers, one per tile.
+/// The number is `surface_index * 4 + rotation` where `surface_index` is the line in texture_list.txt.
+/// The originals use CRLF but LF is accepted too.
+///
+/// Tiles can also be referenced by name so maps don't depend on the order of the texture list:
+/// `<name>` or `<name>:<rotation>`, e.g. `g_spawn:1`. All unknown names are reported at once.
+///
+/// Lines starting with `hazard` aren't rows, they set parameters of a hazard tile:
+/// `hazard <column> <row> [<key>=<value>]...` where keys are the fields of `Hazard`,
+/// column and row are 0-based.
+///
+/// Lines starting with `pickup` place a pickup in the center of a tile:
+/// `pickup <column> <row> <kind>` where kind is `ammo`, `repair`, `speed_boost`, `rapid_fire` or `shield`,
+/// column and row are 0-based.
+///
+/// Lines starting with `team_spawn` reserve a spawn tile for one team in team modes:
+/// `team_spawn <column> <row> <team>` where team is 0 for red and 1 for blue,
+/// column and row are 0-based. Untagged spawns are shared.
+///
+/// Lines starting with `bot` pin a bot persona to the map: `bot <name>`, the name can contain spaces.
+///
+/// Lines starting with `destructible` make walls of a surface destructible:
+/// `destructible <surface> hp=<hp> rubble=<surface>` where rubble is the surface they turn into
+/// and must not be a wall, e.g. `destructible bunker2 hp=300 rubble=desert`.
+///
+/// Lines starting with `damage` are damaged destructible walls (saved mid-match):
+/// `damage <column> <row> <amount>`, column and row are 0-based.
+///
+/// If the first line is `autotile`, tiles can also be `.` (grass), `~` (water) or `#` (wall)
+/// and water edges and corners are chosen automatically, see `Map::autotile`.
+pub fn load_map(text: &str, surfaces: Vec<Surface>) -> Result<Map, MapError> {
+    let mut tiles: Vec<Vec<Tile>> = Vec::new();
+    let mut hazard_lines = Vec::new();
+    let mut pickup_lines = Vec::new();
+    let mut team_spawn_lines = Vec::new();
+    let mut destructible_lines = Vec::new();
+    let mut damage_lines = Vec::new();
+    let mut pinned_bots = Vec::new();
+    let mut autotile = false;
+    let mut unknown_names: Vec<(usize, usize, &str)> = Vec::new();
+    for (line_index, line) in text.lines().enumerate() {
+        let line_num = line_index + 1;
+        if line_index == 0 && line.trim_end() == "autotile" {
+            autotile = true;
+            continue;
+        }
+        if line.starts_with("hazard") {
+            hazard_lines.push((line_num, line));
+            continue;
+        }
+        if line.starts_with("pickup") {
+            pickup_lines.push((line_num, line));
+            continue;
+        }
+        if line.starts_with("team_spawn") {
+            team_spawn_lines.push((line_num, line));
+            continue;
+        }
+        if line.starts_with("destructible") {
+            destructible_lines.push((line_num, line));
+            continue;
+        }
+        if line.starts_with("damage") {
+            damage_lines.push((line_num, line));
+            continue;
+        }
+        if line == "bot" || line.starts_with("bot ") {
+            let name = line["bot".len()..].trim();
+            if name.is_empty() {
+                return Err(MapError::new(line_num, 1, "expected bot name".to_owned()));
+            }
+            pinned_bots.push(name.to_owned());
+            continue;
+        }
+        let mut row = Vec::new();
+        for token in line.split_ascii_whitespace() {
+            let column = column(line, token);
+            let logical = LOGICAL_TILES
+                .iter()
+                .find(|&&(logical_token, _)| autotile && token == logical_token);
+            if let Some(&(_, name)) = logical {
+                let surface_index = surfaces
+                    .iter()
+                    .position(|surface| surface.name == name)
+                    .ok_or_else(|| {
+                        let msg = format!("tile \"{}\" needs surface {}", token, name);
+                        MapError::new(line_num, column, msg)
+                    })?;
+                row.push(Tile {
+                    surface_index,
+                    angle: 0.0,
+                });
+                continue;
+            }
+            let first = token.chars().next().unwrap();
+            if first.is_ascii_alphabetic() || first == '_' {
+                let (name, rotation) = match token.split_once(':') {
+                    Some((name, rotation)) => {
+                        let rotation = rotation.parse::<usize>().ok().filter(|&r| r < 4);
+                        let rotation = rotation.ok_or_else(|| {
+                            let msg = format!("invalid rotation in tile \"{}\"", token);
+                            MapError::new(line_num, column, msg)
+                        })?;
+                        (name, rotation)
+                    }
+                    None => (token, 0),
+                };
+                match surfaces.iter().position(|surface| surface.name == name) {
+                    Some(surface_index) => row.push(Tile {
+                        surface_index,
                         angle: rotation as f64 * -PI / 2.0,
+                    }),
+                    None => {
+                        if !unknown_names.iter().any(|&(_, _, unknown)| unknown == name) {
+                            unknown_names.push((line_num, column, name));
+                        }
+                        // Placeholder so the row length check still works.
+                        row.push(Tile {
+                            surface_index: 0,
+                            angle: 0.0,
+                        });
                     }
-                })
-                .collect()
-        })
-        .collect();
-    Map::new(tiles, surfaces)
-}
-
-pub fn load_tex_list(text: &str) -> Vec<Surface> {
-    // TODO handle both CRLF and LF properly OR use cvars instead
-    // if using cvars, update load_map docs
-    text.split_terminator("\r\n")
-        .map(|line| {
-            let mut parts = line.split(' ');
-            let name = parts.next().unwrap();
-            let kind_num = parts.next().unwrap().parse().unwrap();
-            let friction = parts.next().unwrap().parse().unwrap();
-            let speed = parts.next().unwrap().parse().unwrap();
-
-            let kind = Kind::n(kind_num).unwrap();
-            Surface::new(name.to_owned(), kind, friction, speed)
+                }
+                continue;
+            }
+
+            let val: usize = token.parse().map_err(|_| {
+                MapError::new(line_num, column, format!("invalid tile \"{}\"", token))
+            })?;
+            let surface_index = val / 4;
+            if surface_index >= surfaces.len() {
+                let msg = format!(
+                    "tile {} uses surface {} but there are only {} surfaces",
+                    val,
+                    surface_index,
+                    surfaces.len()
+                );
+                return Err(MapError::new(line_num, column, msg));
+            }
+
+            // rotation is number of turns counterclockwise
+            // angle is clockwise (see Vec2f for coord system explanation)
+            // g_spawn: rotation - angle - meaning
+            // 0    0           right
+            // 1    -1/2*PI     up
+            // 2    -PI         left
+            // 3    -3/2*PI     down
+            let rotation = val % 4;
+            row.push(Tile {
+                surface_index,
+                angle: rotation as f64 * -PI / 2.0,
+            });
+        }
+
+        if row.is_empty() {
+            return Err(MapError::new(line_num, 1, "empty row".to_owned()));
+        }
+        if let Some(first) = tiles.first() {
+            if row.len() != first.len() {
+                let msg = format!("row has {} tiles, expected {}", row.len(), first.len());
+                return Err(MapError::new(line_num, 1, msg));
+            }
+        }
+        tiles.push(row);
+    }
+
+    if let Some(&(line_num, column, _)) = unknown_names.first() {
+        let names: Vec<_> = unknown_names
+            .iter()
+            .map(|&(_, _, name)| format!("\"{}\"", name))
+            .collect();
+        let msg = format!("unknown tiles: {}", names.join(", "));
+        return Err(MapError::new(line_num, column, msg));
+    }
+    if tiles.is_empty() {
+        return Err(MapError::new(1, 1, "empty map".to_owned()));
+    }
+
+    let mut map = Map::new(tiles, surfaces);
+    if autotile {
+        map.autotile();
+    }
+    for (line_num, line) in hazard_lines {
+        parse_hazard(&mut map, line_num, line)?;
+    }
+    for (line_num, line) in pickup_lines {
+        parse_pickup(&mut map, line_num, line)?;
+    }
+    for (line_num, line) in team_spawn_lines {
+        parse_team_spawn(&mut map, line_num, line)?;
+    }
+    for (line_num, line) in destructible_lines {
+        parse_destructible(&mut map, line_num, line)?;
+    }
+    for (line_num, line) in damage_lines {
+        parse_damage(&mut map, line_num, line)?;
+    }
+    map.pinned_bots = pinned_bots;
+    Ok(map)
+}
+
+/// Write a map in the format `load_map` parses so that loading it gives the same map.
+///
+/// Tiles are numbers and lines end with CRLF like in the originals.
+/// Hazard parameters, pickups, team spawns, pinned bots, destructible surfaces and damaged walls follow the rows.
+/// Destroyed walls are saved as their rubble.
+/// Autotiled maps are saved with the pieces that were chosen, not as `autotile`.
+pub fn save_map(map: &Map) -> String {
+    let mut text = String::new();
+    for row in &map.tiles {
+        let tokens: Vec<_> = row
+            .iter()
+            .map(|tile| (tile.surface_index * 4 + tile.rotation()).to_string())
+            .collect();
+        text.push_str(&tokens.join(" "));
+        text.push_str("\r\n");
+    }
+    for hazard in &map.hazards {
+        let params = [
+            ("period", hazard.period),
+            ("phase", hazard.phase),
+            ("duration", hazard.duration),
+            ("damage", hazard.damage),
+            ("rearm", hazard.rearm),
+        ];
+        let params: Vec<_> = params
+            .iter()
+            .filter_map(|&(key, value)| Some(format!("{}={}", key, value?)))
+            .collect();
+        if !params.is_empty() {
+            text.push_str(&format!(
+                "hazard {} {} {}\r\n",
+                hazard.index.x,
+                hazard.index.y,
+                params.join(" ")
+            ));
+        }
+    }
+    for &(index, kind) in &map.pickups {
+        let kind = match kind {
+            PickupKind::Ammo => "ammo",
+            PickupKind::Repair => "repair",
+            PickupKind::Powerup(PowerupKind::SpeedBoost) => "speed_boost",
+            PickupKind::Powerup(PowerupKind::RapidFire) => "rapid_fire",
+            PickupKind::Powerup(PowerupKind::Shield) => "shield",
+            PickupKind::Armor => unreachable!("armor pickups are placed randomly"),
+        };
+        text.push_str(&format!("pickup {} {} {}\r\n", index.x, index.y, kind));
+    }
+    for &(index, team) in &map.team_spawns {
+        // Edits can turn them into other tiles.
+        if map.spawns.contains(&index) {
+            text.push_str(&format!(
+                "team_spawn {} {} {}\r\n",
+                index.x, index.y, team as usize
+            ));
+        }
+    }
+    for name in &map.pinned_bots {
+        text.push_str(&format!("bot {}\r\n", name));
+    }
+    for surface in &map.surfaces {
+        if let Some(destructible) = surface.destructible {
+            text.push_str(&format!(
+                "destructible {} hp={} rubble={}\r\n",
+                surface.name, destructible.hp, map.surfaces[destructible.rubble].name
+            ));
+        }
+    }
+    // Sorted so saving the same map always gives the same text.
+    let mut damage: Vec<_> = map.damaged_walls().collect();
+    damage.sort_unstable_by_key(|&(index, _)| (index.y, index.x));
+    for (index, amount) in damage {
+        text.push_str(&format!("damage {} {} {}\r\n", index.x, index.y, amount));
+    }
+    text
+}
+
+/// 1-based column of `token` in `line` for error messages.
+///
+/// `token` must be a substring of `line`, e.g. from `split_ascii_whitespace`.
+fn column(line: &str, token: &str) -> usize {
+    token.as_ptr() as usize - line.as_ptr() as usize + 1
+}
+
+/// Parse the column and row following the keyword at the start of `line`.
+fn parse_index<'a>(
+    line_num: usize,
+    line: &'a str,
+    tokens: &mut impl Iterator<Item = &'a str>,
+) -> Result<Vec2u, MapError> {
+    let mut coord = || {
+        let token = tokens.next().ok_or_else(|| {
+            MapError::new(
+                line_num,
+                line.len() + 1,
+                "expected column and row".to_owned(),
+            )
+        })?;
+        token.parse::<usize>().map_err(|_| {
+            let msg = format!("invalid coordinate \"{}\"", token);
+            MapError::new(line_num, column(line, token), msg)
         })
-        .collect()
+    };
+    Ok(Vec2u::new(coord()?, coord()?))
+}
+
+fn parse_pickup(map: &mut Map, line_num: usize, line: &str) -> Result<(), MapError> {
+    let mut tokens = line.split_ascii_whitespace().skip(1);
+    let index = parse_index(line_num, line, &mut tokens)?;
+    if index.x >= map.width() || index.y >= map.height() {
+        let msg = format!("tile {} {} is outside the map", index.x, index.y);
+        return Err(MapError::new(line_num, 1, msg));
+    }
+    let kind = match tokens.next() {
+        Some("ammo") => PickupKind::Ammo,
+        Some("repair") => PickupKind::Repair,
+        Some("speed_boost") => PickupKind::Powerup(PowerupKind::SpeedBoost),
+        Some("rapid_fire") => PickupKind::Powerup(PowerupKind::RapidFire),
+        Some("shield") => PickupKind::Powerup(PowerupKind::Shield),
+        Some(token) => {
+            let msg = format!("unknown pickup \"{}\"", token);
+            let column = column(line, token);
+            return Err(MapError::new(line_num, column, msg));
+        }
+        None => {
+            let msg = "expected pickup kind".to_owned();
+            return Err(MapError::new(line_num, line.len() + 1, msg));
+        }
+    };
+    map.pickups.push((index, kind));
+    Ok(())
+}
+
+fn parse_team_spawn(map: &mut Map, line_num: usize, line: &str) -> Result<(), MapError> {
+    let mut tokens = line.split_ascii_whitespace().skip(1);
+    let index = parse_index(line_num, line, &mut tokens)?;
+    if !map.spawns.contains(&index) {
+        let msg = format!("tile {} {} is not a spawn", index.x, index.y);
+        return Err(MapError::new(line_num, 1, msg));
+    }
+    let team = match tokens.next() {
+        Some(token) => token.parse::<u8>().ok().and_then(Team::n).ok_or_else(|| {
+            let msg = format!("invalid team \"{}\"", token);
+            let column = column(line, token);
+            MapError::new(line_num, column, msg)
+        })?,
+        None => {
+            let msg = "expected team".to_owned();
+            return Err(MapError::new(line_num, line.len() + 1, msg));
+        }
+    };
+    if map.team_spawns.iter().any(|&(tagged, _)| tagged == index) {
+        let msg = format!("spawn {} {} already has a team", index.x, index.y);
+        return Err(MapError::new(line_num, 1, msg));
+    }
+    map.team_spawns.push((index, team));
+    Ok(())
+}
+
+fn parse_hazard(map: &mut Map, line_num: usize, line: &str) -> Result<(), MapError> {
+    let mut tokens = line.split_ascii_whitespace().skip(1);
+    let index = parse_index(line_num, line, &mut tokens)?;
+
+    let hazard = map
+        .hazards
+        .iter_mut()
+        .find(|hazard| hazard.index == index)
+        .ok_or_else(|| {
+            let msg = format!("tile {} {} is not a hazard", index.x, index.y);
+            MapError::new(line_num, 1, msg)
+        })?;
+    for token in tokens {
+        let (key, value) = match token.split_once('=') {
+            Some(kv) => kv,
+            None => {
+                let msg = format!("expected key=value, got \"{}\"", token);
+                return Err(MapError::new(line_num, column(line, token), msg));
+            }
+        };
+        let value: f64 = value.parse().map_err(|_| {
+            let msg = format!("invalid value \"{}\"", value);
+            MapError::new(line_num, column(line, token), msg)
+        })?;
+        let field = match key {
+            "period" => &mut hazard.period,
+            "phase" => &mut hazard.phase,
+            "duration" => &mut hazard.duration,
+            "damage" => &mut hazard.damage,
+            "rearm" => &mut hazard.rearm,
+            _ => {
+                let msg = format!("unknown hazard parameter \"{}\"", key);
+                return Err(MapError::new(line_num, column(line, token), msg));
+            }
+        };
+        *field = Some(value);
+    }
+    Ok(())
+}
+
+fn parse_destructible(map: &mut Map, line_num: usize, line: &str) -> Result<(), MapError> {
+    let mut tokens = line.split_ascii_whitespace().skip(1);
+    let surface_index = |map: &Map, name: &str| {
+        map.surfaces
+            .iter()
+            .position(|surface| surface.name == name)
+            .ok_or_else(|| {
+                let msg = format!("unknown surface \"{}\"", name);
+                MapError::new(line_num, column(line, name), msg)
+            })
+    };
+    let name = tokens.next().ok_or_else(|| {
+        let msg = "expected surface".to_owned();
+        MapError::new(line_num, line.len() + 1, msg)
+    })?;
+    let wall = surface_index(map, name)?;
+    if map.surfaces[wall].kind != Kind::Wall {
+        let msg = format!("surface \"{}\" is not a wall", name);
+        return Err(MapError::new(line_num, column(line, name), msg));
+    }
+
+    let (mut hp, mut rubble) = (None, None);
+    for token in tokens {
+        match token.split_once('=') {
+            Some(("hp", value)) => {
+                let value = value.parse().ok().filter(|&hp: &f64| hp > 0.0);
+                let value = value.ok_or_else(|| {
+                    let msg = format!("invalid hp \"{}\"", token);
+                    MapError::new(line_num, column(line, token), msg)
+                })?;
+                hp = Some(value);
+            }
+            Some(("rubble", value)) => {
+                let index = surface_index(map, value)?;
+                if map.surfaces[index].kind == Kind::Wall {
+                    let msg = format!("rubble \"{}\" can't be a wall", value);
+                    return Err(MapError::new(line_num, column(line, value), msg));
+                }
+                rubble = Some(index);
+            }
+            _ => {
+                let msg = format!("expected hp=<hp> or rubble=<surface>, got \"{}\"", token);
+                return Err(MapError::new(line_num, column(line, token), msg));
+            }
+        }
+    }
+    match (hp, rubble) {
+        (Some(hp), Some(rubble)) => {
+            map.surfaces[wall].destructible = Some(Destructible { hp, rubble });
+            Ok(())
+        }
+        _ => {
+            let msg = "expected hp and rubble".to_owned();
+            Err(MapError::new(line_num, line.len() + 1, msg))
+        }
+    }
+}
+
+fn parse_damage(map: &mut Map, line_num: usize, line: &str) -> Result<(), MapError> {
+    let mut tokens = line.split_ascii_whitespace().skip(1);
+    let index = parse_index(line_num, line, &mut tokens)?;
+    let destructible = if index.x < map.width() && index.y < map.height() {
+        map.surface_at_index(index).destructible
+    } else {
+        None
+    };
+    let hp = match destructible {
+        Some(destructible) => destructible.hp,
+        None => {
+            let msg = format!("tile {} {} is not a destructible wall", index.x, index.y);
+            return Err(MapError::new(line_num, 1, msg));
+        }
+    };
+    let amount = tokens.next().and_then(|token| token.parse().ok());
+    match amount {
+        Some(amount) if amount > 0.0 && amount < hp => {
+            map.wall_damage.insert(index, amount);
+            Ok(())
+        }
+        _ => {
+            let msg = format!("expected damage between 0 and {}", hp);
+            Err(MapError::new(line_num, 1, msg))
+        }
+    }
+}
+
+/// Parse the tileset manifest in the original RecWar TextureList.txt format.
+///
+/// Each line is `<name> <kind> <friction> <speed>` where kind is the number of a `Kind`.
+/// The line number is the surface index used by maps and the image is `assets/tiles/<name>.bmp`.
+/// The original uses CRLF but LF is accepted too.
+pub fn load_tex_list(text: &str) -> Result<Vec<Surface>, MapError> {
+    let mut surfaces: Vec<Surface> = Vec::new();
+    for (line_index, line) in text.lines().enumerate() {
+        let line_num = line_index + 1;
+        let parts: Vec<_> = line.split_ascii_whitespace().collect();
+        if parts.len() != 4 {
+            let msg = format!("expected name, kind, friction and speed, got \"{}\"", line);
+            return Err(MapError::new(line_num, 1, msg));
+        }
+        let name = parts[0];
+        if surfaces.iter().any(|surface| surface.name == name) {
+            let msg = format!("duplicate surface \"{}\"", name);
+            return Err(MapError::new(line_num, 1, msg));
+        }
+        let first = name.chars().next().unwrap();
+        if !(first.is_ascii_alphabetic() || first == '_') || name.contains(':') {
+            let msg = format!("surface name \"{}\" can't be used in maps", name);
+            return Err(MapError::new(line_num, 1, msg));
+        }
+        let kind = parts[1].parse().ok().and_then(Kind::n).ok_or_else(|| {
+            let msg = format!("invalid kind \"{}\" of surface \"{}\"", parts[1], name);
+            MapError::new(line_num, 1, msg)
+        })?;
+        let number = |part: &str| {
+            part.parse().map_err(|_| {
+                let msg = format!("invalid number \"{}\" in surface \"{}\"", part, name);
+                MapError::new(line_num, 1, msg)
+            })
+        };
+        let friction = number(parts[2])?;
+        let speed = number(parts[3])?;
+        surfaces.push(Surface::new(name.to_owned(), kind, friction, speed));
+    }
+    Ok(surfaces)
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
-    use std::fs;
+    use std::{f64::consts::TAU, fs, iter, time::Instant};
 
+    use rand::{prelude::SmallRng, SeedableRng};
     use walkdir::WalkDir;
 
+    /// Load a map from `text` with the surfaces from the real texture list.
+    pub(crate) fn load_test_map(text: &str) -> Map {
+        let tex_list_text = fs::read_to_string("assets/texture_list.txt").unwrap();
+        load_map(text, load_tex_list(&tex_list_text).unwrap()).unwrap()
+    }
+
     #[test]
     fn test_loading_tex_list() {
         let text = fs::read_to_string("assets/texture_list.txt").unwrap();
-        let surfaces = load_tex_list(&text);
+        let surfaces = load_tex_list(&text).unwrap();
         assert_ne!(surfaces.len(), 0);
     }
 
@@ -406,7 +1591,7 @@ mod tests {
         let mut cnt = 0;
 
         let tex_list_text = fs::read_to_string("assets/texture_list.txt").unwrap();
-        let surfaces = load_tex_list(&tex_list_text);
+        let surfaces = load_tex_list(&tex_list_text).unwrap();
         for entry in WalkDir::new("maps") {
             let entry = entry.unwrap();
             let is_map = entry.file_name().to_str().unwrap().ends_with(".map");
@@ -416,7 +1601,7 @@ mod tests {
 
             dbg!(entry.file_name());
             let map_text = fs::read_to_string(entry.path()).unwrap();
-            let map = load_map(&map_text, surfaces.clone());
+            let map = load_map(&map_text, surfaces.clone()).unwrap();
             assert_ne!(map.width(), 0);
             assert_ne!(map.height(), 0);
             cnt += 1;
@@ -426,10 +1611,7 @@ mod tests {
 
     #[test]
     fn test_map_a_simple_plan() {
-        let tex_list_text = fs::read_to_string("assets/texture_list.txt").unwrap();
-        let surfaces = load_tex_list(&tex_list_text);
-        let map_text = fs::read_to_string("maps/A simple plan (2).map").unwrap();
-        let map = load_map(&map_text, surfaces);
+        let map = load_test_map(&fs::read_to_string("maps/A simple plan (2).map").unwrap());
         assert_eq!(map.width(), 55);
         assert_eq!(map.height(), 23);
         assert_eq!(map.size(), Vec2u::new(55, 23));
@@ -448,10 +1630,7 @@ mod tests {
 
     #[test]
     fn test_collisions_between() {
-        let tex_list_text = fs::read_to_string("assets/texture_list.txt").unwrap();
-        let surfaces = load_tex_list(&tex_list_text);
-        let map_text = fs::read_to_string("maps/Corners (4).map").unwrap();
-        let map = load_map(&map_text, surfaces);
+        let map = load_test_map(&fs::read_to_string("maps/Corners (4).map").unwrap());
 
         let outside = Vec2f::new(-50.0, -50.0);
 
@@ -472,4 +1651,705 @@ mod tests {
         assert!(map.is_wall_trace(bottom_left, top_right + up).is_none());
         assert!(map.is_wall_trace(bottom_left, top_right - up).is_some());
     }
+
+    /// `Map::is_wall` before it cached which tiles are walls.
+    fn is_wall_by_surface(map: &Map, pos: Vec2f) -> bool {
+        let maxs = map.maxs();
+        if pos.x <= 0.0 || pos.y <= 0.0 || pos.x >= maxs.x || pos.y >= maxs.y {
+            return true;
+        }
+        map.surface_at_pos(pos).kind == Kind::Wall
+    }
+
+    /// The stepping `wall_trace` used before `GridTraversal`, kept to check it gives the same results
+    /// and as the baseline for `bench_wall_trace`.
+    fn wall_trace_stepping(map: &Map, begin: Vec2f, end: Vec2f) -> Option<WallHit> {
+        if is_wall_by_surface(map, begin) {
+            return Some(WallHit {
+                pos: begin,
+                contact: begin,
+                normal: Vec2f::zero(),
+            });
+        }
+
+        let delta = end - begin;
+        let nudge = delta.normalized() * 0.01;
+        let first = |begin: f64, delta: f64| {
+            if delta == 0.0 {
+                f64::INFINITY
+            } else if delta > 0.0 {
+                ((begin / TILE_SIZE).ceil() * TILE_SIZE - begin) / delta
+            } else {
+                ((begin / TILE_SIZE).floor() * TILE_SIZE - begin) / delta
+            }
+        };
+        let mut t_x = first(begin.x, delta.x);
+        let mut t_y = first(begin.y, delta.y);
+        let t_step_x = TILE_SIZE / delta.x.abs();
+        let t_step_y = TILE_SIZE / delta.y.abs();
+        loop {
+            let t = t_x.min(t_y);
+            let normal = if t_x < t_y {
+                t_x += t_step_x;
+                Vec2f::new(-delta.x.signum(), 0.0)
+            } else {
+                t_y += t_step_y;
+                Vec2f::new(0.0, -delta.y.signum())
+            };
+            if t > 1.0 {
+                return None;
+            }
+            let intersection = begin + delta * t;
+            let wall = intersection + nudge;
+            if is_wall_by_surface(map, wall) {
+                return Some(WallHit {
+                    pos: wall,
+                    contact: intersection,
+                    normal,
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn test_wall_trace_matches_stepping() {
+        let map = load_test_map(&fs::read_to_string("maps/Corners (4).map").unwrap());
+
+        let mut rng = SmallRng::seed_from_u64(5);
+        let maxs = map.maxs();
+        let mut hits = 0;
+        for i in 0..100_000 {
+            let begin = Vec2f::new(rng.gen_range(0.0..maxs.x), rng.gen_range(0.0..maxs.y));
+            let len = match i % 4 {
+                0 => rng.gen_range(0.0..TILE_SIZE),
+                1 => rng.gen_range(0.0..TILE_SIZE * 10.0),
+                2 => rng.gen_range(0.0..maxs.x),
+                _ => 100_000.0,
+            };
+            let mut end = begin + rng.gen_range(0.0..TAU).to_vec2f() * len;
+            // Grid lines and corners are where the two are most likely to disagree.
+            if i % 3 == 0 {
+                end = (end / 32.0).round() * 32.0;
+            }
+            let begin = if i % 5 == 0 {
+                (begin / 32.0).round() * 32.0
+            } else {
+                begin
+            };
+            let expected = wall_trace_stepping(&map, begin, end);
+            let actual = map.wall_trace(begin, end);
+            let points = |hit: Option<WallHit>| hit.map(|hit| (hit.pos, hit.contact));
+            assert_eq!(points(actual), points(expected), "{} -> {}", begin, end);
+            // Starting exactly on a grid line, the old version saw a crossing of that line at t 0
+            // even when moving away from it, so the normal of a hit right at `begin` can differ.
+            if let (Some(actual), Some(expected)) = (actual, expected) {
+                if actual.contact != begin {
+                    assert_eq!(actual.normal, expected.normal, "{} -> {}", begin, end);
+                }
+            }
+            hits += expected.is_some() as usize;
+        }
+        // Make sure both outcomes get tested.
+        assert!(hits > 20_000 && hits < 80_000, "{} hits", hits);
+    }
+
+    /// How long `wall_trace` and the old stepping take on a big map for each kind of segment:
+    /// short ones like a projectile's move in one frame, segments which stay inside one tile
+    /// and the railgun's 100k-unit rays.
+    ///
+    /// Timing is noisy so this only prints the results, run it with:
+    /// `cargo test --release -- --ignored --nocapture bench_wall_trace`
+    #[test]
+    #[ignore]
+    fn bench_wall_trace() {
+        let map = load_test_map(&fs::read_to_string("maps/extra/Large.map").unwrap());
+
+        let mut rng = SmallRng::seed_from_u64(7);
+        let maxs = map.maxs();
+        let mut random_pos = || Vec2f::new(rng.gen_range(0.0..maxs.x), rng.gen_range(0.0..maxs.y));
+        let count = 100_000;
+        let mut segments = |end: &mut dyn FnMut(Vec2f) -> Vec2f| -> Vec<(Vec2f, Vec2f)> {
+            (0..count)
+                .map(|_| {
+                    let begin = random_pos();
+                    (begin, end(begin))
+                })
+                .collect()
+        };
+        let mut rng = SmallRng::seed_from_u64(8);
+        let short = segments(&mut |begin| {
+            begin + rng.gen_range(0.0..TAU).to_vec2f() * rng.gen_range(0.0..TILE_SIZE / 2.0)
+        });
+        let mut rng = SmallRng::seed_from_u64(9);
+        let same_tile = segments(&mut |begin| {
+            let mins = (begin / TILE_SIZE).floor() * TILE_SIZE;
+            mins + Vec2f::new(rng.gen_range(0.0..TILE_SIZE), rng.gen_range(0.0..TILE_SIZE))
+        });
+        let mut rng = SmallRng::seed_from_u64(10);
+        let long = segments(&mut |begin| begin + rng.gen_range(0.0..TAU).to_vec2f() * 100_000.0);
+
+        let time = |segments: &[(Vec2f, Vec2f)],
+                    trace: &dyn Fn(Vec2f, Vec2f) -> Option<WallHit>| {
+            let rounds = 10;
+            let mut hits = 0;
+            let start = Instant::now();
+            for _ in 0..rounds {
+                for &(begin, end) in segments {
+                    hits += trace(begin, end).is_some() as usize;
+                }
+            }
+            let ns = start.elapsed().as_secs_f64() * 1e9 / (rounds * segments.len()) as f64;
+            (ns, hits / rounds)
+        };
+        for (name, segments) in [
+            ("short", &short),
+            ("same tile", &same_tile),
+            ("long", &long),
+        ] {
+            let (ns, hits) = time(segments, &|begin, end| map.wall_trace(begin, end));
+            let (ns_stepping, hits_stepping) = time(segments, &|begin, end| {
+                wall_trace_stepping(&map, begin, end)
+            });
+            assert_eq!(hits, hits_stepping);
+            println!(
+                "{:>9}: {:6.1} ns per trace, stepping {:6.1} ns ({:.2}x), {} of {} hit a wall",
+                name,
+                ns,
+                ns_stepping,
+                ns_stepping / ns,
+                hits,
+                segments.len()
+            );
+        }
+    }
+
+    fn plain_map(width: usize, height: usize) -> Map {
+        let text = vec![vec!["0"; width].join(" "); height].join("\n");
+        load_test_map(&text)
+    }
+
+    #[test]
+    fn test_tile_queries() {
+        let map = plain_map(10, 8);
+
+        let tile_pos = map.tile_at(Vec2f::new(65.0, 130.0)).unwrap();
+        assert_eq!(tile_pos.index, Vec2u::new(1, 2));
+        assert_eq!(tile_pos.offset, Vec2f::new(1.0, 2.0));
+        assert!(map.tile_at(Vec2f::new(0.0, 0.0)).is_some());
+        assert!(map.tile_at(Vec2f::new(-1.0, 5.0)).is_none());
+        assert!(map.tile_at(map.maxs()).is_none());
+        assert!(map.tile_at(Vec2f::new(5.0, map.maxs().y)).is_none());
+
+        let rect: Vec<_> = map
+            .tiles_in_rect(Vec2f::new(60.0, 60.0), Vec2f::new(130.0, 70.0))
+            .collect();
+        let expected: Vec<_> = [(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)]
+            .iter()
+            .map(|&(x, y)| Vec2u::new(x, y))
+            .collect();
+        assert_eq!(rect, expected);
+        // Clipped to the map.
+        let rect: Vec<_> = map
+            .tiles_in_rect(Vec2f::new(-100.0, -100.0), Vec2f::new(10.0, 64.0))
+            .collect();
+        assert_eq!(rect, [Vec2u::new(0, 0)]);
+        assert_eq!(map.tiles_in_rect(map.mins(), map.maxs() * 2.0).count(), 80);
+        assert_eq!(map.tiles_in_rect(map.maxs(), map.maxs() + 100.0).count(), 0);
+        assert_eq!(
+            map.tiles_in_rect(Vec2f::new(100.0, 100.0), Vec2f::new(50.0, 200.0))
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_tiles_on_segment() {
+        let map = plain_map(10, 8);
+
+        // Cells touched by points along the segment, avoiding exact fractions
+        // where it could go exactly through a corner.
+        let sampled = |from: Vec2f, to: Vec2f| {
+            let samples = 20_000;
+            let mut cells: Vec<Vec2u> = Vec::new();
+            let ts = iter::once(0.0)
+                .chain((0..samples).map(|i| (i as f64 + 0.5) / samples as f64))
+                .chain(iter::once(1.0));
+            for t in ts {
+                if let Some(tile_pos) = map.tile_at(from + (to - from) * t) {
+                    if cells.last() != Some(&tile_pos.index) {
+                        cells.push(tile_pos.index);
+                    }
+                }
+            }
+            cells
+        };
+        let check = |from: Vec2f, to: Vec2f| {
+            let visited: Vec<_> = map.tiles_on_segment(from, to).collect();
+            assert_eq!(visited, sampled(from, to), "{} -> {}", from, to);
+        };
+
+        // Axis-aligned, including along grid lines.
+        check(Vec2f::new(10.0, 10.0), Vec2f::new(300.0, 10.0));
+        check(Vec2f::new(300.0, 64.0), Vec2f::new(10.0, 64.0));
+        check(Vec2f::new(128.0, 10.0), Vec2f::new(128.0, 400.0));
+        check(Vec2f::new(100.0, 400.0), Vec2f::new(100.0, 20.0));
+        // Exactly through corners in all diagonal directions.
+        check(Vec2f::new(32.0, 32.0), Vec2f::new(224.0, 224.0));
+        check(Vec2f::new(224.0, 32.0), Vec2f::new(32.0, 224.0));
+        check(Vec2f::new(224.0, 224.0), Vec2f::new(32.0, 32.0));
+        check(Vec2f::new(32.0, 224.0), Vec2f::new(224.0, 32.0));
+        check(Vec2f::new(32.0, 32.0), Vec2f::new(416.0, 224.0));
+        // Partly outside the map.
+        check(Vec2f::new(-50.0, 100.0), Vec2f::new(200.0, 150.0));
+        check(Vec2f::new(600.0, 500.0), Vec2f::new(700.0, 600.0));
+        // A point.
+        check(Vec2f::new(70.0, 70.0), Vec2f::new(70.0, 70.0));
+
+        let mut rng = SmallRng::seed_from_u64(3);
+        for _ in 0..200 {
+            let mut point = || Vec2f::new(rng.gen_range(0.0..640.0), rng.gen_range(0.0..512.0));
+            check(point(), point());
+        }
+    }
+
+    /// Surface index and rotation of each tile in the row - rotation is the number from the file, not the angle.
+    fn row_snapshot(map: &Map, r: usize) -> Vec<(usize, usize)> {
+        (0..map.width())
+            .map(|c| {
+                let tile = map.col_row(c, r);
+                let rotation = (-tile.angle / (PI / 2.0)).round() as usize;
+                (tile.surface_index, rotation)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_map_joust_snapshot() {
+        let map = load_test_map(&fs::read_to_string("maps/Joust (2).map").unwrap());
+        assert_eq!(map.size(), Vec2u::new(21, 34));
+        assert_eq!(map.spawns().len(), 12);
+
+        #[rustfmt::skip]
+        let first = vec![
+            (16, 2), (0, 0), (0, 0), (10, 0), (9, 3), (9, 3), (9, 3), (3, 3), (3, 3), (3, 3), (3, 3),
+            (3, 3), (3, 3), (3, 3), (9, 3), (9, 3), (9, 3), (10, 0), (0, 0), (0, 0), (16, 0),
+        ];
+        assert_eq!(row_snapshot(&map, 0), first);
+        #[rustfmt::skip]
+        let last = vec![
+            (16, 2), (0, 0), (0, 0), (10, 0), (9, 1), (9, 1), (9, 1), (3, 1), (3, 1), (3, 1), (3, 1),
+            (3, 1), (3, 1), (3, 1), (9, 1), (9, 1), (9, 1), (10, 0), (0, 0), (0, 0), (16, 0),
+        ];
+        assert_eq!(row_snapshot(&map, 33), last);
+    }
+
+    #[test]
+    fn test_map_corners_snapshot() {
+        let map = load_test_map(&fs::read_to_string("maps/Corners (4).map").unwrap());
+        assert_eq!(map.size(), Vec2u::new(30, 30));
+        assert_eq!(map.spawns().len(), 32);
+
+        #[rustfmt::skip]
+        let second = vec![
+            (3, 0), (0, 0), (0, 3), (0, 3), (0, 0), (0, 0), (0, 2), (0, 0), (0, 2), (3, 0),
+            (0, 2), (0, 3), (0, 3), (0, 0), (14, 0), (0, 3), (0, 2), (0, 2), (0, 0), (0, 3),
+            (3, 3), (0, 2), (0, 0), (0, 2), (0, 0), (0, 0), (0, 3), (0, 2), (0, 3), (3, 3),
+        ];
+        assert_eq!(row_snapshot(&map, 1), second);
+        assert_eq!(map.surface_of(map.col_row(14, 1)).kind, Kind::Wall);
+        assert_eq!(map.surface_of(map.col_row(2, 0)).kind, Kind::Spawn);
+    }
+
+    #[test]
+    fn test_map_errors() {
+        let tex_list_text = fs::read_to_string("assets/texture_list.txt").unwrap();
+        let surfaces = load_tex_list(&tex_list_text).unwrap();
+
+        // LF works as well as CRLF
+        let map = load_map("0 4 8\n0 0 0\n", surfaces.clone()).unwrap();
+        assert_eq!(map.size(), Vec2u::new(3, 2));
+
+        let err = load_map("0 0 0\r\n0 x 0\r\n", surfaces.clone()).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 3));
+
+        let err = load_map("0 0 0\r\n0 0 0 0\r\n", surfaces.clone()).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 1));
+
+        let too_big = (surfaces.len() * 4).to_string();
+        let err = load_map(&format!("0 0 {}", too_big), surfaces.clone()).unwrap_err();
+        assert_eq!((err.line, err.column), (1, 5));
+
+        assert!(load_map("", surfaces.clone()).is_err());
+        assert!(load_map("0 0\n\n0 0", surfaces).is_err());
+    }
+
+    #[test]
+    fn test_named_tiles() {
+        let tex_list_text = fs::read_to_string("assets/texture_list.txt").unwrap();
+        let surfaces = load_tex_list(&tex_list_text).unwrap();
+
+        let named = load_map("g1 g_spawn:1 bunker1\nwater:3 g1 road\n", surfaces.clone()).unwrap();
+        let numbered = load_map("0 37 16\n47 0 40\n", surfaces.clone()).unwrap();
+        for r in 0..2 {
+            assert_eq!(row_snapshot(&named, r), row_snapshot(&numbered, r));
+        }
+        assert_eq!(named.spawns(), numbered.spawns());
+
+        let err = load_map("g1 grass\nlava lava\n", surfaces.clone()).unwrap_err();
+        assert_eq!(
+            err,
+            MapError::new(1, 4, "unknown tiles: \"grass\", \"lava\"".to_owned())
+        );
+        let err = load_map("g1 g_spawn:4\n", surfaces).unwrap_err();
+        assert_eq!((err.line, err.column), (1, 4));
+    }
+
+    #[test]
+    fn test_save_map_round_trip() {
+        let tex_list_text = fs::read_to_string("assets/texture_list.txt").unwrap();
+        let surfaces = load_tex_list(&tex_list_text).unwrap();
+        let mut texts: Vec<String> = WalkDir::new("maps")
+            .into_iter()
+            .map(Result::unwrap)
+            .filter(|entry| entry.file_name().to_str().unwrap().ends_with(".map"))
+            .map(|entry| fs::read_to_string(entry.path()).unwrap())
+            .collect();
+        texts.push("autotile\n. ~ ~\n. ~ ~\n# # .\nbot Dr. Dead\n".to_owned());
+        for text in texts {
+            let map = load_map(&text, surfaces.clone()).unwrap();
+            let saved = save_map(&map);
+            let reloaded = load_map(&saved, surfaces.clone()).unwrap();
+            assert_eq!(reloaded.size(), map.size());
+            for r in 0..map.height() {
+                assert_eq!(row_snapshot(&reloaded, r), row_snapshot(&map, r));
+            }
+            assert_eq!(reloaded.spawns(), map.spawns());
+            assert_eq!(reloaded.pinned_bots(), map.pinned_bots());
+            assert_eq!(save_map(&reloaded), saved);
+        }
+
+        let mut surfaces = vec![Surface::new("ground".to_owned(), Kind::Normal, 1.0, 1.0)];
+        surfaces.push(Surface::new("crusher".to_owned(), Kind::Crusher, 1.0, 1.0));
+        let text = "0 4 4\r\nhazard 2 0 period=3 phase=0.1 rearm=1.5\r\nbot Fireman\r\n";
+        let map = load_map(text, surfaces.clone()).unwrap();
+        assert_eq!(save_map(&map), text);
+        let reloaded = load_map(&save_map(&map), surfaces).unwrap();
+        assert_eq!(reloaded.hazards(), map.hazards());
+    }
+
+    #[test]
+    fn test_destructible_walls() {
+        let mut surfaces = vec![Surface::new("ground".to_owned(), Kind::Normal, 1.0, 1.0)];
+        surfaces.push(Surface::new("wall".to_owned(), Kind::Wall, 1.0, 1.0));
+        let text = "0 4 5\r\ndestructible wall hp=100 rubble=ground\r\ndamage 2 0 30\r\n";
+        let mut map = load_map(text, surfaces.clone()).unwrap();
+        assert_eq!(save_map(&map), text);
+        let (left, right) = (Vec2u::new(1, 0), Vec2u::new(2, 0));
+        assert_eq!(map.wall_damage(right), 30.0);
+
+        assert!(!map.damage_wall(left, 60.0));
+        assert!(map.damage_wall(right, 70.0));
+        assert!(!map.is_wall(map.tile_center(right)));
+        // Rubble keeps the wall's rotation.
+        assert_eq!(map.col_row(2, 0).rotation(), 1);
+        assert_eq!(
+            save_map(&map),
+            text.replace("0 4 5", "0 4 1").replace("2 0 30", "1 0 60")
+        );
+
+        // Restoring a snapshot's state and repairing.
+        let mut restored = load_map(text, surfaces.clone()).unwrap();
+        restored.set_wall_state(&[right], &[(left, 60.0)]);
+        assert_eq!(save_map(&restored), save_map(&map));
+        assert!(map.repair_walls());
+        assert!(!map.repair_walls());
+        assert_eq!(save_map(&map), text.replace("damage 2 0 30\r\n", ""));
+
+        let error = |text: &str| load_map(text, surfaces.clone()).unwrap_err();
+        let err = error("4\ndestructible ground hp=1 rubble=ground");
+        assert_eq!((err.line, err.column), (2, 14));
+        let err = error("4\ndestructible wall hp=1 rubble=wall");
+        assert_eq!((err.line, err.column), (2, 31));
+        let err = error("4\ndestructible wall hp=-1 rubble=ground");
+        assert_eq!((err.line, err.column), (2, 19));
+        let err = error("4\ndestructible wall hp=1");
+        assert_eq!((err.line, err.column), (2, 23));
+        let err = error("4\ndestructible wall hp=10 rubble=ground\ndamage 0 0 10");
+        assert_eq!(err.line, 3);
+        let err = error("0\ndestructible wall hp=10 rubble=ground\ndamage 0 0 5");
+        assert_eq!(err.line, 3);
+    }
+
+    #[test]
+    fn test_edit() {
+        let mut surfaces = vec![Surface::new("ground".to_owned(), Kind::Normal, 1.0, 1.0)];
+        surfaces.push(Surface::new("spawn".to_owned(), Kind::Spawn, 1.0, 1.0));
+        surfaces.push(Surface::new("crusher".to_owned(), Kind::Crusher, 1.0, 1.0));
+        surfaces.push(Surface::new("wall".to_owned(), Kind::Wall, 1.0, 1.0));
+        let mut map = load_map("0 8 0\nhazard 1 0 period=2\n0 0 0\n", surfaces.clone()).unwrap();
+
+        let prev = map.set_tile(Vec2u::new(2, 1), Tile::new(1, 3)).unwrap();
+        assert_eq!(prev.surface_index, 0);
+        let tile = map.tile(Vec2u::new(2, 1)).unwrap();
+        assert_eq!((tile.surface_index, tile.rotation()), (1, 3));
+        assert_eq!(map.spawns(), &vec![Vec2u::new(2, 1)]);
+        assert!(!map.is_wall(map.tile_center(Vec2u::new(0, 1))));
+        map.set_tile(Vec2u::new(0, 1), Tile::new(3, 0)).unwrap();
+        assert!(map.is_wall(map.tile_center(Vec2u::new(0, 1))));
+
+        // Other hazards keep their parameters.
+        map.set_tile(Vec2u::new(0, 0), Tile::new(2, 0)).unwrap();
+        assert_eq!(map.hazards().len(), 2);
+        assert_eq!(map.hazards()[0].period, None);
+        assert_eq!(map.hazards()[1].period, Some(2.0));
+
+        assert_eq!(
+            map.set_tile(Vec2u::new(3, 0), Tile::new(0, 0)).unwrap_err(),
+            EditError::OutOfBounds {
+                index: Vec2u::new(3, 0),
+                size: Vec2u::new(3, 2)
+            }
+        );
+        assert!(map.tile(Vec2u::new(0, 2)).is_err());
+        assert_eq!(
+            map.set_tile(Vec2u::new(0, 0), Tile::new(4, 0)).unwrap_err(),
+            EditError::UnknownSurface(4)
+        );
+
+        // Grow, then crop away the spawn and a hazard.
+        map.resize(Vec2u::new(4, 3), Tile::new(0, 0)).unwrap();
+        assert_eq!(map.size(), Vec2u::new(4, 3));
+        assert_eq!(map.tile(Vec2u::new(3, 2)).unwrap().surface_index, 0);
+        map.resize(Vec2u::new(2, 1), Tile::new(0, 0)).unwrap();
+        assert_eq!(save_map(&map), "8 8\r\nhazard 1 0 period=2\r\n");
+        assert!(map.spawns().is_empty());
+        assert_eq!(
+            map.resize(Vec2u::new(0, 1), Tile::new(0, 0)).unwrap_err(),
+            EditError::EmptySize
+        );
+
+        // Flipped tiles survive saving and loading.
+        let reloaded = load_map(&save_map(&map), surfaces).unwrap();
+        assert_eq!(row_snapshot(&reloaded, 0), row_snapshot(&map, 0));
+    }
+
+    #[test]
+    fn test_tex_list_errors() {
+        let surfaces = load_tex_list("g1 0 1 1\nwater 3 1 0.5").unwrap();
+        assert_eq!(surfaces.len(), 2);
+        assert_eq!(surfaces[1].kind, Kind::Water);
+
+        let err = load_tex_list("g1 0 1 1\r\ng1 0 1 1\r\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(err.msg.contains("duplicate"));
+        assert!(load_tex_list("g1 0 1\n").is_err());
+        assert!(load_tex_list("g1 99 1 1\n").is_err());
+        assert!(load_tex_list("g1 0 x 1\n").is_err());
+        assert!(load_tex_list("1g 0 1 1\n").is_err());
+    }
+
+    #[test]
+    fn test_hazard_metadata() {
+        let mut surfaces = vec![Surface::new("ground".to_owned(), Kind::Normal, 1.0, 1.0)];
+        surfaces.push(Surface::new(
+            "floor".to_owned(),
+            Kind::DamageFloor,
+            1.0,
+            1.0,
+        ));
+        surfaces.push(Surface::new("crusher".to_owned(), Kind::Crusher, 1.0, 1.0));
+        let text = "0 4 0\nhazard 2 1 period=3 phase=0.5\n0 0 8\n";
+        let map = load_map(text, surfaces.clone()).unwrap();
+        assert_eq!(map.size(), Vec2u::new(3, 2));
+        let expected = [
+            Hazard {
+                index: Vec2u::new(1, 0),
+                ..Hazard::default()
+            },
+            Hazard {
+                index: Vec2u::new(2, 1),
+                period: Some(3.0),
+                phase: Some(0.5),
+                ..Hazard::default()
+            },
+        ];
+        assert_eq!(map.hazards(), expected);
+        assert_eq!(map.hazard_kind(&map.hazards()[1]), Kind::Crusher);
+
+        let err = load_map("0 4\nhazard 0 0\n", surfaces.clone()).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 1));
+        let err = load_map("0 4\nhazard 1 0 speed=2\n", surfaces.clone()).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 12));
+        let err = load_map("0 4\nhazard 1 0 damage=x\n", surfaces.clone()).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 12));
+        let err = load_map("0 4\nhazard 1\n", surfaces.clone()).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 9));
+
+        let map = load_map(
+            "0 4\npickup 1 0 repair\npickup 0 0 ammo\npickup 0 0 shield\n",
+            surfaces.clone(),
+        )
+        .unwrap();
+        let pickups = [
+            (Vec2u::new(1, 0), PickupKind::Repair),
+            (Vec2u::new(0, 0), PickupKind::Ammo),
+            (Vec2u::new(0, 0), PickupKind::Powerup(PowerupKind::Shield)),
+        ];
+        assert_eq!(map.pickups(), pickups);
+        let saved = save_map(&map);
+        let expected = "0 4\r\npickup 1 0 repair\r\npickup 0 0 ammo\r\npickup 0 0 shield\r\n";
+        assert_eq!(saved, expected);
+        let err = load_map("0 4\npickup 2 0 ammo\n", surfaces.clone()).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 1));
+        let err = load_map("0 4\npickup 1 0 armor\n", surfaces.clone()).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 12));
+        let err = load_map("0 4\npickup 1 0\n", surfaces.clone()).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 11));
+
+        let map = load_map("bot Dr. Dead\n0 4\nbot  Fireman \n", surfaces.clone()).unwrap();
+        assert_eq!(map.size(), Vec2u::new(2, 1));
+        assert_eq!(map.pinned_bots(), ["Dr. Dead", "Fireman"]);
+        let err = load_map("0 4\nbot\n", surfaces).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 1));
+    }
+
+    #[test]
+    fn test_team_spawns() {
+        let mut surfaces = vec![Surface::new("ground".to_owned(), Kind::Normal, 1.0, 1.0)];
+        surfaces.push(Surface::new("spawn".to_owned(), Kind::Spawn, 1.0, 1.0));
+        let text = "4 4 4 0\r\nteam_spawn 0 0 0\r\nteam_spawn 2 0 1\r\n";
+        let mut map = load_map(text, surfaces.clone()).unwrap();
+        assert_eq!(save_map(&map), text);
+        assert_eq!(map.team_spawns(Team::Red), [Vec2u::new(0, 0)]);
+        assert_eq!(map.team_spawns(Team::Blue), [Vec2u::new(2, 0)]);
+
+        let mut rng = SmallRng::seed_from_u64(0);
+        let spawned = |map: &Map, rng: &mut SmallRng, team| {
+            let (pos, _) = map.random_spawn(rng, team);
+            (0..20).fold(vec![pos], |mut seen, _| {
+                let (pos, _) = map.random_spawn(rng, team);
+                if !seen.contains(&pos) {
+                    seen.push(pos);
+                }
+                seen
+            })
+        };
+        let red = map.tile_center(Vec2u::new(0, 0));
+        let shared = map.tile_center(Vec2u::new(1, 0));
+        assert_eq!(spawned(&map, &mut rng, Some(Team::Red)), [red]);
+        assert_eq!(spawned(&map, &mut rng, None).len(), 3);
+
+        // The blue spawn gets removed so blue falls back to the shared one.
+        let ground = Tile {
+            surface_index: 0,
+            angle: 0.0,
+        };
+        map.set_tile(Vec2u::new(2, 0), ground).unwrap();
+        assert_eq!(spawned(&map, &mut rng, Some(Team::Blue)), [shared]);
+        assert_eq!(save_map(&map), "4 4 0 0\r\nteam_spawn 0 0 0\r\n");
+
+        let err = load_map("4 0\nteam_spawn 1 0 0\n", surfaces.clone()).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 1));
+        let err = load_map("4 0\nteam_spawn 0 0 2\n", surfaces.clone()).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 16));
+        let err = load_map("4 0\nteam_spawn 0 0\n", surfaces.clone()).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 15));
+        let err = load_map("4 0\nteam_spawn 0 0 0\nteam_spawn 0 0 1\n", surfaces).unwrap_err();
+        assert_eq!((err.line, err.column), (3, 1));
+    }
+
+    fn autotiled(text: &str) -> Vec<Vec<(usize, usize)>> {
+        let map = load_test_map(text);
+        (0..map.height()).map(|r| row_snapshot(&map, r)).collect()
+    }
+
+    // Surface indices in texture_list.txt
+    const GRASS: usize = 0;
+    const WALL: usize = 4;
+    const WATER: usize = 11;
+    const SIDE: usize = 16;
+    const CORNER: usize = 17;
+    const MIDDLE: usize = 21;
+
+    #[test]
+    fn test_autotile_lake() {
+        let text = "\
+autotile
+. . . . .
+. ~ ~ ~ .
+. ~ ~ ~ .
+. ~ ~ ~ .
+. . . . .
+";
+        #[rustfmt::skip]
+        let expected = vec![
+            vec![(GRASS, 0), (GRASS, 0), (GRASS, 0), (GRASS, 0), (GRASS, 0)],
+            vec![(GRASS, 0), (CORNER, 0), (SIDE, 3), (CORNER, 3), (GRASS, 0)],
+            vec![(GRASS, 0), (SIDE, 0), (MIDDLE, 0), (SIDE, 2), (GRASS, 0)],
+            vec![(GRASS, 0), (CORNER, 1), (SIDE, 1), (CORNER, 2), (GRASS, 0)],
+            vec![(GRASS, 0), (GRASS, 0), (GRASS, 0), (GRASS, 0), (GRASS, 0)],
+        ];
+        assert_eq!(autotiled(text), expected);
+    }
+
+    #[test]
+    fn test_autotile_border() {
+        // Outside the map counts as water here so there's no shore along the edges.
+        let text = "\
+autotile
+~ ~ ~ .
+~ ~ ~ .
+. . . .
+";
+        #[rustfmt::skip]
+        let expected = vec![
+            vec![(MIDDLE, 0), (MIDDLE, 0), (SIDE, 2), (GRASS, 0)],
+            vec![(SIDE, 1), (SIDE, 1), (CORNER, 2), (GRASS, 0)],
+            vec![(GRASS, 0), (GRASS, 0), (GRASS, 0), (GRASS, 0)],
+        ];
+        assert_eq!(autotiled(text), expected);
+    }
+
+    #[test]
+    fn test_autotile_l_wall() {
+        // There are no wall edge pieces so walls are plain.
+        // The water continues past the edge so it's a shore, not a single tile wide stream.
+        // A single water tile has no edges so it's plain. Explicit tiles (a spawn) still work.
+        let text = "\
+autotile
+# . . ~
+# ~ 36 ~
+# # # ~
+";
+        #[rustfmt::skip]
+        let expected = vec![
+            vec![(WALL, 0), (GRASS, 0), (GRASS, 0), (SIDE, 0)],
+            vec![(WALL, 0), (WATER, 0), (9, 0), (SIDE, 0)],
+            vec![(WALL, 0), (WALL, 0), (WALL, 0), (SIDE, 0)],
+        ];
+        assert_eq!(autotiled(text), expected);
+    }
+
+    #[test]
+    fn test_autotile_missing_pieces() {
+        let surfaces = vec![
+            Surface::new("g1".to_owned(), Kind::Normal, 1.0, 1.0),
+            Surface::new("water".to_owned(), Kind::Water, 1.0, 0.5),
+        ];
+        let text = "autotile\n~ ~ ~\n~ ~ ~\n~ ~ ~\n";
+        let map = load_map(text, surfaces.clone()).unwrap();
+        for r in 0..map.height() {
+            assert_eq!(row_snapshot(&map, r), vec![(1, 0); 3]);
+        }
+
+        let err = load_map("autotile\n~ #\n", surfaces).unwrap_err();
+        assert_eq!(
+            err,
+            MapError::new(2, 3, "tile \"#\" needs surface bunker1".to_owned())
+        );
+
+        // Only in autotile mode.
+        let err = load_map(". .\n", Vec::new()).unwrap_err();
+        assert_eq!(err, MapError::new(1, 1, "invalid tile \".\"".to_owned()));
+    }
 }
@@ -0,0 +1,163 @@
+//! Minimap layout and terrain - the parts which don't need a rendering backend.
+
+use crate::{
+    derived::{self, Derivation, Step},
+    map::{Kind, Map, Vec2f},
+};
+
+/// Where the minimap is on screen and how it maps world coords to screen coords.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MinimapLayout {
+    /// Top left corner in screen coords
+    pub pos: Vec2f,
+    pub size: Vec2f,
+    /// Screen pixels per world pixel, same along both axes.
+    pub scale: f64,
+}
+
+impl MinimapLayout {
+    /// Fit a map of `map_size` into the area at `area_pos` of `area_size`.
+    ///
+    /// Keeps the aspect ratio - a non-square map leaves empty space along one axis
+    /// and the minimap is centered within the area.
+    pub fn fit(map_size: Vec2f, area_pos: Vec2f, area_size: Vec2f) -> Self {
+        let scale = (area_size.x / map_size.x).min(area_size.y / map_size.y);
+        let size = map_size * scale;
+        let pos = area_pos + (area_size - size) / 2.0;
+        Self { pos, size, scale }
+    }
+
+    pub fn to_screen(&self, world_pos: Vec2f) -> Vec2f {
+        self.pos + world_pos * self.scale
+    }
+}
+
+/// RGBA pixels, one per tile, row by row.
+///
+/// Walls are dark, water is blue, everything else is ground.
+/// This is meant to be rendered once and cached, it only changes with the map.
+/// Normally built incrementally by `derived::Derived`.
+pub fn terrain_pixels(map: &Map) -> Vec<u8> {
+    let mut builder = MinimapTerrainBuilder::new(map);
+    while builder.step(map, f64::INFINITY) == Step::NotDone {}
+    builder.pixels
+}
+
+/// Builds `terrain_pixels` a row at a time.
+#[derive(Debug, Clone)]
+pub struct MinimapTerrainBuilder {
+    pixels: Vec<u8>,
+    rows: usize,
+    height: usize,
+}
+
+impl MinimapTerrainBuilder {
+    pub fn new(map: &Map) -> Self {
+        Self {
+            pixels: Vec::with_capacity(map.width() * map.height() * 4),
+            rows: 0,
+            height: map.height(),
+        }
+    }
+
+    /// `None` until done.
+    pub fn pixels(&self) -> Option<&[u8]> {
+        if self.is_done() {
+            Some(&self.pixels)
+        } else {
+            None
+        }
+    }
+}
+
+impl Derivation for MinimapTerrainBuilder {
+    fn name(&self) -> &'static str {
+        "minimap terrain"
+    }
+
+    fn priority(&self) -> i32 {
+        1
+    }
+
+    fn cost(&self) -> usize {
+        self.pixels.capacity() / 4
+    }
+
+    fn is_done(&self) -> bool {
+        self.rows == self.height
+    }
+
+    fn step(&mut self, map: &Map, deadline: f64) -> Step {
+        while !self.is_done() {
+            let r = self.rows;
+            for c in 0..map.width() {
+                let color = match map.surface_of(map.col_row(c, r)).kind {
+                    Kind::Wall => [40, 40, 40, 255],
+                    Kind::Water => [40, 80, 200, 255],
+                    _ => [120, 130, 90, 255],
+                };
+                self.pixels.extend_from_slice(&color);
+            }
+            self.rows += 1;
+            if derived::now() >= deadline {
+                break;
+            }
+        }
+        if self.is_done() {
+            Step::Done
+        } else {
+            Step::NotDone
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::map::{self, TILE_SIZE};
+
+    #[test]
+    fn test_fit_non_square() {
+        // Joust (2) is 21x34 tiles - taller than wide.
+        let map_size = Vec2f::new(21.0, 34.0) * TILE_SIZE;
+        let layout = MinimapLayout::fit(map_size, Vec2f::new(10.0, 20.0), Vec2f::new(200.0, 200.0));
+        assert_eq!(layout.size.y, 200.0);
+        assert!((layout.size.x - 200.0 * 21.0 / 34.0).abs() < 1e-9);
+        assert_eq!(layout.pos.y, 20.0);
+        assert!((layout.pos.x - (10.0 + (200.0 - layout.size.x) / 2.0)).abs() < 1e-9);
+
+        // The corners of the map are the corners of the minimap.
+        assert_eq!(layout.to_screen(Vec2f::zero()), layout.pos);
+        let bottom_right = layout.to_screen(map_size);
+        assert!((bottom_right - (layout.pos + layout.size)).magnitude() < 1e-9);
+
+        // Wider than tall.
+        let layout = MinimapLayout::fit(
+            Vec2f::new(55.0, 23.0) * TILE_SIZE,
+            Vec2f::zero(),
+            Vec2f::new(200.0, 200.0),
+        );
+        assert_eq!(layout.size.x, 200.0);
+        assert!(layout.size.y < 100.0);
+        assert_eq!(layout.pos.x, 0.0);
+        assert!(layout.pos.y > 50.0);
+    }
+
+    #[test]
+    fn test_terrain_pixels() {
+        // 3 columns, 2 rows. Each tile value is surface_index * 4 + rotation,
+        // 16 is a wall (bunker1), 44 is water.
+        let map = map::tests::load_test_map("0 0 16\n44 0 0\n");
+
+        let pixels = terrain_pixels(&map);
+        let pixel = |c: usize, r: usize| {
+            let i = (r * 3 + c) * 4;
+            [pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3]]
+        };
+        assert_eq!(pixels.len(), 3 * 2 * 4);
+        assert_eq!(pixel(2, 0), [40, 40, 40, 255]);
+        assert_eq!(pixel(0, 1), [40, 80, 200, 255]);
+        assert_eq!(pixel(1, 1), [120, 130, 90, 255]);
+    }
+}
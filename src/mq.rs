@@ -1,22 +1,37 @@
 //! Native and WASM versions using the macroquad engine.
 
+use std::{cell::RefCell, collections::VecDeque, fs};
+
 use cvars_console_macroquad::MacroquadConsole;
+use fnv::FnvHashMap;
 use futures::future;
 use macroquad::prelude::*;
 use thunderdome::Index;
 
 use crate::{
+    assets::{AssetError, Loading, TexturePaths},
+    bindings::Bindings,
+    bitmap_font::{BitmapFont, FontDescriptor},
+    camera::{self, CameraMode, Intro, PlayerCamera, ViewTransform},
     cvars::Cvars,
-    game_state::Input,
-    map::Vec2f,
+    entities::Facing,
+    feedback::{HitStop, Recoils, Shake},
+    game_state::{self, Input, NotificationKind},
+    interpolation::Interpolation,
+    map::{Surface, Tile, Vec2f, Vec2u, VecExt},
+    palette,
+    prediction::{ClientUpdate, InputCmd, LatencyLink, Prediction},
+    render_data::RenderData,
     server::Server,
-    timing::{Durations, Fps},
+    timing::{Durations, Fps, PerfStats, SystemTimings},
 };
 
 #[derive(Debug)]
 pub struct MacroquadClient {
     pub imgs_tiles: Vec<Texture2D>,
     pub imgs_vehicles: Vec<Texture2D>,
+    /// Color -> `imgs_vehicles` recolored, see `palette`. Created when a color is first needed.
+    pub imgs_vehicles_tinted: FnvHashMap<[u8; 3], Vec<Texture2D>>,
     pub imgs_wrecks: Vec<Texture2D>,
     pub imgs_weapon_icons: Vec<Texture2D>,
     pub img_rocket: Texture2D,
@@ -24,13 +39,61 @@ pub struct MacroquadClient {
     pub img_gm: Texture2D,
     pub img_explosion: Texture2D,
     pub img_explosion_cyan: Texture2D,
+    /// Only if the atlas is in assets, see `cl_bitmap_font`.
+    pub bitmap_font: Option<BitmapFont>,
     pub render_fps: Fps,
     pub render_cmds_durations: Durations,
     pub rest_durations: Durations,
+    /// Drawing phases, measured with `d_timing_systems`.
+    pub draw_timings: SystemTimings,
+    /// For `d_draw_frametime_graph`.
+    pub frame_times: Durations,
+    /// Real time of the last `d_perf_stats_period` log.
+    pub perf_stats_logged: f64,
     pub viewport_size: Vec2f,
+    /// Scale of the world in all viewports, see `cl_zoom_min`.
+    pub zoom: f64,
     pub client_mode: ClientMode,
     pub last_key: Option<KeyCode>,
+    /// Player 1 (or the only player in singleplayer).
+    pub bindings1: Bindings,
+    /// Player 2 in splitscreen.
+    pub bindings2: Bindings,
     pub console: MacroquadConsole,
+    pub intro: Option<Intro>,
+    /// Terrain part of the minimap, created on first use since it only changes with the map.
+    pub minimap_terrain: Option<Texture2D>,
+    /// Sequence number of the next input command, see `prediction`.
+    pub input_seq: u32,
+    /// Only in singleplayer with `cl_prediction`.
+    pub prediction: Option<Prediction>,
+    /// Fake connection to the server, see `d_latency_frames`.
+    pub uplink: LatencyLink<InputCmd>,
+    pub downlink: LatencyLink<ClientUpdate>,
+    /// Other vehicles, only with `d_remote_update_interval`.
+    pub interpolation: Interpolation,
+    /// Server frame of the last fake remote update.
+    pub remote_update_frame: u64,
+    /// Refilled for each viewport before drawing it.
+    pub render_data: RenderData,
+    /// Game time when the line was added and the line, oldest first.
+    pub kill_feed: VecDeque<(f64, String)>,
+    /// Local player -> game time of the last damage taken and the flash's initial alpha.
+    pub damage_flashes: FnvHashMap<Index, (f64, f64)>,
+    /// Local player -> game time when they last hit someone in the rear.
+    pub rear_hits: FnvHashMap<Index, f64>,
+    /// What `cl_map_editor` paints.
+    pub editor_tile: Tile,
+    /// Where F5 saves the edited map, set from `--map-editor-path`.
+    pub editor_path: String,
+    /// Local player -> camera shake of their viewport.
+    pub shakes: FnvHashMap<Index, Shake>,
+    pub recoils: Recoils,
+    pub hit_stop: HitStop,
+    /// Local player -> what their camera follows, see `camera::PlayerCamera`.
+    pub cameras: FnvHashMap<Index, PlayerCamera>,
+    /// `Server::matches_played` when last checked, the client forgets the old match when it changes.
+    pub matches_played: u32,
 }
 
 #[derive(Debug)]
@@ -45,86 +108,45 @@ pub enum ClientMode {
 }
 
 impl MacroquadClient {
-    pub async fn new(cvars: &Cvars, player1_handle: Index, player2_handle: Option<Index>) -> Self {
+    pub async fn new(
+        cvars: &Cvars,
+        surfaces: &[Surface],
+        player1_handle: Index,
+        player2_handle: Option<Index>,
+    ) -> Result<Self, AssetError> {
         let loading_started = get_time();
 
-        let paths_tiles = [
-            "assets/tiles/g1.bmp",
-            "assets/tiles/g2.bmp",
-            "assets/tiles/g3.bmp",
-            "assets/tiles/g_stripes.bmp",
-            "assets/tiles/bunker1.bmp",
-            "assets/tiles/ice1.bmp",
-            "assets/tiles/ice.bmp",
-            "assets/tiles/ice_side.bmp",
-            "assets/tiles/ice_corner.bmp",
-            "assets/tiles/g_spawn.bmp",
-            "assets/tiles/road.bmp",
-            "assets/tiles/water.bmp",
-            "assets/tiles/snow.bmp",
-            "assets/tiles/snow2.bmp",
-            "assets/tiles/bunker2.bmp",
-            "assets/tiles/base.bmp",
-            "assets/tiles/water_side.bmp",
-            "assets/tiles/water_corner.bmp",
-            "assets/tiles/desert.bmp",
-            "assets/tiles/d_rock.bmp",
-            "assets/tiles/g2d.bmp",
-            "assets/tiles/water_middle.bmp",
-        ];
-        let paths_vehicles = [
-            "assets/vehicles/tank_chassis_flames.png",
-            "assets/vehicles/tank_turret_flames.png",
-            "assets/vehicles/hovercraft_chassis_flames.png",
-            "assets/vehicles/hovercraft_turret_flames.png",
-            "assets/vehicles/hummer_chassis_flames.png",
-            "assets/vehicles/hummer_turret_flames.png",
-        ];
-        let paths_wrecks = [
-            "assets/wrecks/tank.png",
-            "assets/wrecks/hovercraft.png",
-            "assets/wrecks/hummer.png",
-        ];
-        let paths_weapon_icons = [
-            "assets/weapon_icons/mg.png",
-            "assets/weapon_icons/rail.png",
-            "assets/weapon_icons/cb.png",
-            "assets/weapon_icons/rockets.png",
-            "assets/weapon_icons/hm.png",
-            "assets/weapon_icons/gm.png",
-            "assets/weapon_icons/bfg.png",
-        ];
-        let paths_rest = [
-            "assets/weapons/rocket.png",
-            "assets/weapons/hm.png",
-            "assets/weapons/gm.png",
-            "assets/explosion.png",
-            "assets/explosion_cyan.png",
-        ];
-        let paths = [
-            &paths_tiles[..],
-            &paths_vehicles[..],
-            &paths_wrecks[..],
-            &paths_weapon_icons[..],
-            &paths_rest[..],
-        ]
-        .concat();
-
-        let mut textures = future::try_join_all(paths.into_iter().map(|path| {
-            draw_text("Loading...", 400.0, 400.0, 32.0, YELLOW);
-            let tex = load_texture(path);
-            draw_text("Loading...", 400.0, 400.0, 32.0, GREEN);
-            tex
-        }))
-        .await
-        .unwrap()
-        .into_iter();
-        draw_text("Loading...", 400.0, 400.0, 32.0, WHITE);
-
-        let imgs_tiles = textures.by_ref().take(paths_tiles.len()).collect();
-        let imgs_vehicles = textures.by_ref().take(paths_vehicles.len()).collect();
-        let imgs_wrecks = textures.by_ref().take(paths_wrecks.len()).collect();
-        let imgs_weapon_icons = textures.by_ref().take(paths_weapon_icons.len()).collect();
+        let paths = TexturePaths::new(surfaces);
+        let all_paths = paths.all();
+        let loading = RefCell::new(Loading::new(&all_paths));
+        let load = future::join_all(all_paths.iter().enumerate().map(|(i, &path)| {
+            let loading = &loading;
+            async move {
+                let result = load_texture(path).await;
+                let size = match &result {
+                    Ok(tex) => Ok((tex.width(), tex.height())),
+                    Err(err) => Err(err.to_string()),
+                };
+                loading.borrow_mut().record(i, size);
+                result.ok()
+            }
+        }));
+        // Only takes frames when loading isn't instant, e.g. in browsers.
+        let screen = async {
+            while !loading.borrow().is_finished() {
+                draw_loading_screen(&loading.borrow());
+                next_frame().await;
+            }
+        };
+        let (textures, ()) = future::join(load, screen).await;
+        loading.into_inner().finish()?;
+        // All are `Some` if there were no problems.
+        let mut textures = textures.into_iter().flatten();
+
+        let imgs_tiles = textures.by_ref().take(paths.tiles.len()).collect();
+        let imgs_vehicles = textures.by_ref().take(paths.vehicles.len()).collect();
+        let imgs_wrecks = textures.by_ref().take(paths.wrecks.len()).collect();
+        let imgs_weapon_icons = textures.by_ref().take(paths.weapon_icons.len()).collect();
         let img_rocket = textures.next().unwrap();
         let img_hm = textures.next().unwrap();
         let img_gm = textures.next().unwrap();
@@ -137,6 +159,8 @@ impl MacroquadClient {
         img_explosion.set_filter(FilterMode::Nearest);
         img_explosion_cyan.set_filter(FilterMode::Nearest);
 
+        let bitmap_font = load_bitmap_font().await;
+
         let loading_done = get_time();
         dbg_logf!("Loaded assets in {:.2} s", loading_done - loading_started);
 
@@ -145,9 +169,9 @@ impl MacroquadClient {
             screen_width(),
             screen_height()
         );
+        let screen_size = Vec2f::new(screen_width() as f64, screen_height() as f64);
         let (viewport_size, client_mode) = if let Some(player2_handle) = player2_handle {
-            let viewport_width = (screen_width() as f64 - cvars.r_splitscreen_gap) / 2.0;
-            let viewport_size = Vec2f::new(viewport_width, screen_height() as f64);
+            let viewport_size = viewport_size(cvars, screen_size, true);
             let viewport_left = render_target(viewport_size.x as u32, viewport_size.y as u32);
             let viewport_right = render_target(viewport_size.x as u32, viewport_size.y as u32);
 
@@ -158,7 +182,7 @@ impl MacroquadClient {
 
             (viewport_size, client_mode)
         } else {
-            let viewport_size = Vec2f::new(screen_width() as f64, screen_height() as f64);
+            let viewport_size = viewport_size(cvars, screen_size, false);
 
             let client_mode = ClientMode::Singleplayer {
                 player_handle: player1_handle,
@@ -167,9 +191,10 @@ impl MacroquadClient {
             (viewport_size, client_mode)
         };
 
-        Self {
+        Ok(Self {
             imgs_tiles,
             imgs_vehicles,
+            imgs_vehicles_tinted: FnvHashMap::default(),
             imgs_wrecks,
             imgs_weapon_icons,
             img_rocket,
@@ -177,33 +202,403 @@ impl MacroquadClient {
             img_gm,
             img_explosion,
             img_explosion_cyan,
+            bitmap_font,
             render_fps: Fps::new(),
             render_cmds_durations: Durations::new(),
             rest_durations: Durations::new(),
+            draw_timings: SystemTimings::default(),
+            frame_times: Durations::new(),
+            perf_stats_logged: 0.0,
             viewport_size,
+            zoom: 1.0,
             client_mode,
             last_key: None,
+            bindings1: Bindings::player1(),
+            bindings2: Bindings::player2(),
             console: MacroquadConsole::new(),
+            intro: None,
+            minimap_terrain: None,
+            input_seq: 0,
+            prediction: None,
+            uplink: LatencyLink::new(),
+            downlink: LatencyLink::new(),
+            interpolation: Interpolation::new(),
+            remote_update_frame: 0,
+            render_data: RenderData::default(),
+            kill_feed: VecDeque::new(),
+            damage_flashes: FnvHashMap::default(),
+            rear_hits: FnvHashMap::default(),
+            editor_tile: Tile::new(0, 0),
+            editor_path: String::new(),
+            shakes: FnvHashMap::default(),
+            recoils: Recoils::default(),
+            hit_stop: HitStop::default(),
+            cameras: FnvHashMap::default(),
+            matches_played: 0,
+        })
+    }
+
+    /// Adapt the viewports to a new window size, nothing happens if it's the same.
+    pub fn resize(&mut self, cvars: &Cvars, width: f64, height: f64) {
+        let splitscreen = matches!(self.client_mode, ClientMode::Splitscreen { .. });
+        let viewport_size = viewport_size(cvars, Vec2f::new(width, height), splitscreen);
+        if viewport_size == self.viewport_size {
+            return;
+        }
+        dbg_logf!("Resized to {}x{}", width, height);
+        self.viewport_size = viewport_size;
+        if let ClientMode::Splitscreen { render_targets, .. } = &mut self.client_mode {
+            render_targets.0.delete();
+            render_targets.1.delete();
+            *render_targets = (
+                render_target(viewport_size.x as u32, viewport_size.y as u32),
+                render_target(viewport_size.x as u32, viewport_size.y as u32),
+            );
+        }
+    }
+
+    /// Zoom all viewports, clamped to `cl_zoom_min` and `cl_zoom_max`.
+    pub fn set_zoom(&mut self, cvars: &Cvars, factor: f64) {
+        self.zoom = factor.min(cvars.cl_zoom_max).max(cvars.cl_zoom_min);
+    }
+
+    /// Area each viewport shows in world units.
+    pub fn world_viewport_size(&self) -> Vec2f {
+        camera::world_viewport_size(self.viewport_size, self.zoom)
+    }
+
+    /// Switch cameras to free after death and back to the vehicle after respawning.
+    pub fn update_cameras(&mut self, server: &Server) {
+        let world_viewport = self.world_viewport_size();
+        for player_handle in self.local_players() {
+            self.cameras
+                .entry(player_handle)
+                .or_insert_with(|| PlayerCamera::new(player_handle))
+                .update(&server.gs, server.map.maxs(), world_viewport, player_handle);
+        }
+    }
+
+    /// Override what the local player's camera follows until they die or respawn.
+    pub fn set_camera_mode(&mut self, player_handle: Index, mode: CameraMode) {
+        self.cameras
+            .entry(player_handle)
+            .or_insert_with(|| PlayerCamera::new(player_handle))
+            .mode = mode;
+    }
+
+    /// Dead players can watch others - follow the next living vehicle.
+    pub fn spectate_next(&mut self, server: &Server, player_handle: Index) {
+        let mut camera = match self.cameras.get(&player_handle) {
+            Some(&camera) => camera,
+            None => return,
+        };
+        let dead =
+            matches!(camera.mode, CameraMode::Free(_)) || camera.spectated(player_handle).is_some();
+        if dead {
+            camera.spectate_next(&server.gs, player_handle);
+            self.set_camera_mode(player_handle, camera.mode);
+        }
+    }
+
+    /// Move free cameras and keep the keys used for it from the vehicles.
+    fn camera_input(
+        &mut self,
+        cvars: &Cvars,
+        server: &Server,
+        player_handle: Index,
+        input: Input,
+    ) -> Input {
+        self.update_cameras(server);
+        let world_viewport = self.world_viewport_size();
+        let camera = self.cameras.get_mut(&player_handle).unwrap();
+        camera.process_input(
+            input,
+            cvars.cl_spectate_speed,
+            server.real_time_delta,
+            server.map.maxs(),
+            world_viewport,
+        )
+    }
+
+    /// Recolor vehicle sprites for any player or team colors which don't have them yet.
+    pub fn tint_vehicles(&mut self, cvars: &Cvars, server: &Server) {
+        if !cvars.r_vehicle_colors {
+            return;
+        }
+        for (_, player) in server.gs.players.iter() {
+            let color = match palette::player_rgb(player) {
+                Some(color) => color,
+                None => continue,
+            };
+            let imgs_vehicles = &self.imgs_vehicles;
+            self.imgs_vehicles_tinted.entry(color).or_insert_with(|| {
+                imgs_vehicles
+                    .iter()
+                    .map(|img| {
+                        let mut image = img.get_texture_data();
+                        palette::recolor(&mut image.bytes, color);
+                        Texture2D::from_image(&image)
+                    })
+                    .collect()
+            });
+        }
+    }
+
+    /// Show each local player a flyby of the map ending at their vehicle.
+    /// Gamelogic waits until it's over.
+    pub fn start_intro(&mut self, cvars: &Cvars, server: &mut Server) {
+        let tracks = self
+            .local_players()
+            .into_iter()
+            .map(|player_handle| {
+                let vehicle_handle = server.gs.players[player_handle].vehicle.unwrap();
+                let spawn_pos = *server.gs.vehicles[vehicle_handle].pos;
+                let track = camera::flyby_track(
+                    &server.map,
+                    self.world_viewport_size(),
+                    spawn_pos,
+                    cvars.r_round_intro_duration,
+                );
+                (player_handle, track)
+            })
+            .collect();
+        self.intro = Some(Intro::new(get_time(), cvars.r_round_intro_max_wait, tracks));
+        server.intro = true;
+    }
+
+    fn local_players(&self) -> Vec<Index> {
+        match self.client_mode {
+            ClientMode::Singleplayer { player_handle } => vec![player_handle],
+            ClientMode::Splitscreen {
+                render_targets: _,
+                player_handles: (player1_handle, player2_handle),
+            } => vec![player1_handle, player2_handle],
+        }
+    }
+
+    pub fn perf_stats(&self, server: &Server) -> PerfStats {
+        PerfStats::new(
+            &server.update_durations,
+            &server.gamelogic_durations,
+            &self.render_cmds_durations,
+            &server.system_timings,
+            &self.draw_timings,
+        )
+    }
+
+    /// Print `perf_stats` as JSON to the console (devtools in the browser) every `d_perf_stats_period` seconds.
+    pub fn log_perf_stats(&mut self, cvars: &Cvars, server: &Server, real_time: f64) {
+        if cvars.d_perf_stats_period <= 0.0
+            || real_time - self.perf_stats_logged < cvars.d_perf_stats_period
+        {
+            return;
+        }
+        self.perf_stats_logged = real_time;
+        dbg_logf!("perf stats: {}", self.perf_stats(server).to_json());
+    }
+
+    /// Where the local player sees their vehicle - predicted if possible.
+    pub fn vehicle_pos(&self, server: &Server, player_handle: Index) -> Option<Vec2f> {
+        let vehicle_handle = server.gs.players[player_handle].vehicle?;
+        if let Some(prediction) = &self.prediction {
+            if let Some(pose) = prediction.pose() {
+                if prediction.player_handle == player_handle
+                    && pose.vehicle_handle == vehicle_handle
+                {
+                    return Some(pose.pos);
+                }
+            }
+        }
+        Some(*server.gs.vehicles[vehicle_handle].pos)
+    }
+
+    /// World to screen coords in the player's viewport.
+    fn view_transform(&self, server: &Server, player_handle: Index) -> Option<ViewTransform> {
+        // Same as in rendering - the camera follows the vehicle, not a guided missile,
+        // because that's what the turret is on.
+        let vehicle_pos = self.vehicle_pos(server, player_handle)?;
+        Some(ViewTransform::new(
+            server.map.maxs(),
+            self.viewport_size,
+            self.zoom,
+            vehicle_pos,
+        ))
+    }
+
+    /// Absolute angle from the player's vehicle to the mouse cursor.
+    fn mouse_angle(&self, server: &Server, player_handle: Index) -> Option<f64> {
+        let vehicle_pos = self.vehicle_pos(server, player_handle)?;
+        let transform = self.view_transform(server, player_handle)?;
+
+        let (x, y) = mouse_position();
+        let dir = transform.screen_to_world(Vec2f::new(x as f64, y as f64)) - vehicle_pos;
+        if dir == Vec2f::zero() {
+            None
+        } else {
+            Some(dir.to_angle())
+        }
+    }
+
+    /// Edit the map with the mouse and function keys, see `cl_map_editor`.
+    fn map_editor(&mut self, server: &mut Server, player_handle: Index) {
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        let (_, wheel) = mouse_wheel();
+        if wheel != 0.0 {
+            // Back by one is forward by all but one.
+            let tile = self.editor_tile;
+            self.editor_tile = if shift {
+                let turns = if wheel > 0.0 { 1 } else { 3 };
+                Tile::new(tile.surface_index, tile.rotation() + turns)
+            } else {
+                let count = server.map.surfaces().len();
+                let step = if wheel > 0.0 { 1 } else { count - 1 };
+                Tile::new((tile.surface_index + step) % count, tile.rotation())
+            };
+        }
+        dbg_textf!(
+            "map editor: {} rotation {}",
+            server.map.surfaces()[self.editor_tile.surface_index].name,
+            self.editor_tile.rotation()
+        );
+
+        let mut edited = false;
+        if let Some(transform) = self.view_transform(server, player_handle) {
+            let (x, y) = mouse_position();
+            let world_pos = transform.screen_to_world(Vec2f::new(x as f64, y as f64));
+            if let Some(tile_pos) = server.map.tile_at(world_pos) {
+                let index = tile_pos.index;
+                if let Ok(tile) = server.tile(index) {
+                    if is_mouse_button_pressed(MouseButton::Right) {
+                        self.editor_tile = tile;
+                    }
+                    let same = tile.surface_index == self.editor_tile.surface_index
+                        && tile.rotation() == self.editor_tile.rotation();
+                    if is_mouse_button_down(MouseButton::Left) && !same {
+                        edited |= server.set_tile(index, self.editor_tile).is_ok();
+                    }
+                }
+            }
+        }
+
+        for (key_code, grow) in [
+            (KeyCode::F6, Vec2u::new(1, 0)),
+            (KeyCode::F7, Vec2u::new(0, 1)),
+        ] {
+            if is_key_pressed(key_code) {
+                let size = server.map.size();
+                let size = if shift { size - grow } else { size + grow };
+                match server.resize_map(size, self.editor_tile) {
+                    Ok(()) => edited = true,
+                    Err(e) => {
+                        dbg_logf!("Can't resize map: {}", e);
+                    }
+                }
+            }
+        }
+        if edited {
+            self.minimap_terrain = None;
+        }
+
+        if is_key_pressed(KeyCode::F5) {
+            match fs::write(&self.editor_path, server.export_map()) {
+                Ok(()) => {
+                    dbg_logf!("Saved map to {}", self.editor_path);
+                }
+                Err(e) => {
+                    dbg_logf!("Can't save map to {}: {}", self.editor_path, e);
+                }
+            }
         }
     }
 
-    pub fn process_input(&mut self, server: &mut Server) {
+    pub fn process_input(&mut self, cvars: &Cvars, server: &mut Server, real_time: f64) {
+        // Forward key events even while the console is open and throw away the input
+        // so keys released while typing don't stay held.
+        for bindings in [&mut self.bindings1, &mut self.bindings2] {
+            forward_key_events(bindings);
+        }
+        let input1 = self.bindings1.take_input();
+        let input2 = self.bindings2.take_input();
+
         if self.console.is_open() {
             return;
         }
 
-        let input1 = get_input1();
-        let input2 = get_input2();
+        if cvars.cl_time_controls {
+            if is_key_pressed(KeyCode::LeftBracket) {
+                server.set_time_scale(server.time_scale / 2.0);
+            }
+            if is_key_pressed(KeyCode::RightBracket) {
+                server.set_time_scale(server.time_scale * 2.0);
+            }
+            if is_key_pressed(KeyCode::Backslash) {
+                server.step(cvars, 1);
+            }
+        }
+
+        if !cvars.cl_map_editor {
+            // The editor uses the wheel to pick tiles.
+            let (_, wheel) = mouse_wheel();
+            if wheel > 0.0 {
+                self.set_zoom(cvars, self.zoom * cvars.cl_zoom_step);
+            } else if wheel < 0.0 {
+                self.set_zoom(cvars, self.zoom / cvars.cl_zoom_step);
+            }
+        }
+
+        if let Some(intro) = &mut self.intro {
+            // Any key skips, the input isn't sent to the server
+            // so players don't start the round already moving.
+            let inputs = match self.client_mode {
+                ClientMode::Singleplayer { player_handle } => {
+                    vec![(player_handle, input1.merged(input2))]
+                }
+                ClientMode::Splitscreen {
+                    render_targets: _,
+                    player_handles: (player1_handle, player2_handle),
+                } => vec![(player1_handle, input1), (player2_handle, input2)],
+            };
+            for (player_handle, input) in inputs {
+                if input != Input::new() {
+                    intro.skip(player_handle);
+                }
+            }
+            if intro.is_finished(server.real_time) {
+                self.intro = None;
+                server.intro = false;
+            }
+            return;
+        }
 
         match self.client_mode {
             ClientMode::Singleplayer { player_handle } => {
-                let input = input1.merged(input2);
-                server.input(player_handle, input);
+                if is_key_pressed(KeyCode::Tab) {
+                    self.spectate_next(server, player_handle);
+                }
+                let mut input = input1.merged(input2);
+                if cvars.cl_mouse_joystick {
+                    let (x, y) = mouse_position();
+                    let offset = Vec2f::new(x as f64, y as f64) - self.viewport_size / 2.0;
+                    let axes = offset / cvars.cl_mouse_joystick_radius;
+                    input.set_analog(axes.x, -axes.y);
+                }
+                if cvars.cl_turret_follows_mouse {
+                    if let Some(angle) = self.mouse_angle(server, player_handle) {
+                        input.set_turret_target_angle(angle);
+                    }
+                }
+                if cvars.cl_map_editor {
+                    self.map_editor(server, player_handle);
+                }
+                let input = self.camera_input(cvars, server, player_handle, input);
+                self.send_input(cvars, server, player_handle, input, real_time);
             }
             ClientMode::Splitscreen {
                 render_targets: _,
                 player_handles: (player1_handle, player2_handle),
             } => {
+                let input1 = self.camera_input(cvars, server, player1_handle, input1);
+                let input2 = self.camera_input(cvars, server, player2_handle, input2);
                 server.input(player1_handle, input1);
                 server.input(player2_handle, input2);
             }
@@ -213,121 +608,349 @@ impl MacroquadClient {
             self.last_key = Some(key_code);
         }
     }
-}
 
-// Keys to avoid in defaults:
-//  - Ctrl - ctrl+W closes the browser tab
-//  - Alt - shows/hides the firefox menu bar on linux
-//  - Numpad - Some keyboards might not have it
-//  - Keys that often depend on layout - https://github.com/not-fl3/macroquad/issues/260
-// LATER Configurable input
-
-fn get_input1() -> Input {
-    let mut input = Input::new();
-    if was_input_pressed(&[KeyCode::A]) {
-        input.left = true;
-    }
-    if was_input_pressed(&[KeyCode::D]) {
-        input.right = true;
-    }
-    if was_input_pressed(&[KeyCode::W]) {
-        input.up = true;
-    }
-    if was_input_pressed(&[KeyCode::S]) {
-        input.down = true;
-    }
-    if was_input_pressed(&[KeyCode::Q]) {
-        input.turret_left = true;
-    }
-    if was_input_pressed(&[KeyCode::E]) {
-        input.turret_right = true;
-    }
-    if was_input_pressed(&[KeyCode::V]) {
-        input.prev_weapon = true;
-    }
-    if was_input_pressed(&[KeyCode::LeftShift, KeyCode::C]) {
-        input.next_weapon = true;
-    }
-    if was_input_pressed(&[KeyCode::Space]) {
-        input.fire = true;
-    }
-    if was_input_pressed(&[KeyCode::X]) {
-        input.mine = true;
-    }
-    if was_input_pressed(&[KeyCode::G]) {
-        input.self_destruct = true;
+    /// Send the input through the fake connection, predicting its effect if enabled.
+    fn send_input(
+        &mut self,
+        cvars: &Cvars,
+        server: &mut Server,
+        player_handle: Index,
+        input: Input,
+        real_time: f64,
+    ) {
+        let cmd = InputCmd {
+            seq: self.input_seq,
+            input,
+        };
+        self.input_seq = self.input_seq.wrapping_add(1);
+        if let Some(prediction) = &mut self.prediction {
+            // What the server will simulate next frame in `TickrateMode::Synchronized`.
+            let dt = if server.paused {
+                0.0
+            } else {
+                (real_time - server.real_time) * server.speed(cvars)
+            };
+            prediction.predict(cvars, &server.map, cmd, dt);
+        }
+
+        self.uplink.send(cvars.d_latency_frames, cmd);
+        for cmd in self.uplink.receive() {
+            server.input_cmd(player_handle, cmd);
+        }
     }
-    if was_input_pressed(&[KeyCode::R]) {
-        input.horn = true;
+
+    /// Get the server's state of the local player after its update and reconcile the prediction.
+    pub fn receive_updates(&mut self, cvars: &Cvars, server: &mut Server) {
+        if self.matches_played != server.matches_played {
+            self.matches_played = server.matches_played;
+            // Everything below refers to entities and times of the old match.
+            self.prediction = None;
+            self.interpolation.clear();
+            self.remote_update_frame = 0;
+            self.kill_feed.clear();
+            self.damage_flashes.clear();
+            self.rear_hits.clear();
+            self.shakes.clear();
+            self.recoils = Recoils::default();
+            // Destroyed walls were rebuilt.
+            self.minimap_terrain = None;
+        }
+        if let ClientMode::Singleplayer { player_handle } = self.client_mode {
+            let update = server.client_update(player_handle);
+            self.downlink.send(cvars.d_latency_frames, update);
+            for update in self.downlink.receive() {
+                if !cvars.cl_prediction {
+                    self.prediction = None;
+                } else if let Some(prediction) = &mut self.prediction {
+                    prediction.reconcile(cvars, &server.map, update);
+                } else {
+                    self.prediction = Some(Prediction::new(cvars, &server.map, update));
+                }
+            }
+        }
+        self.uplink.next_frame();
+        self.downlink.next_frame();
+
+        // Other vehicles as if their state arrived over a slow connection.
+        let frame_num = server.gs.frame_num;
+        if cvars.d_remote_update_interval == 0 {
+            self.interpolation.clear();
+        } else if frame_num >= self.remote_update_frame + cvars.d_remote_update_interval {
+            self.remote_update_frame = frame_num;
+            let local_players = self.local_players();
+            for (handle, vehicle) in server.gs.vehicles.iter() {
+                if local_players.contains(&vehicle.owner) {
+                    continue;
+                }
+                self.interpolation.push_remote_state(
+                    cvars,
+                    handle,
+                    frame_num,
+                    server.gs.game_time,
+                    *vehicle.pos,
+                    *vehicle.angle,
+                );
+            }
+        }
+        let vehicles = &server.gs.vehicles;
+        self.interpolation
+            .retain(|handle| vehicles.contains(handle));
+        if cvars.d_remote_update_interval > 0 {
+            dbg_textf!("interpolation snaps: {}", self.interpolation.snaps);
+        }
+
+        if let Some(prediction) = &self.prediction {
+            let stats = &prediction.stats;
+            dbg_textf!(
+                "prediction error: {:.2} (max {:.2})",
+                stats.error_last,
+                stats.error_max
+            );
+            dbg_textf!(
+                "prediction corrections: {}, snaps: {}, replayed: {}",
+                stats.corrections,
+                stats.snaps,
+                stats.replayed_last
+            );
+            dbg_textf!(
+                "predicted shots confirmed: {}, unconfirmed: {}",
+                stats.shots_confirmed,
+                stats.shots_unconfirmed
+            );
+        }
     }
 
-    // The rest are shared actions defined on is player 1 only
+    /// Turn the server's notifications into kill feed lines, damage flashes and rear hit markers.
+    pub fn receive_notifications(&mut self, cvars: &Cvars, server: &mut Server) {
+        let local_players = self.local_players();
+        for notification in game_state::drain_notifications(&mut server.gs) {
+            match notification.kind {
+                NotificationKind::DamageTaken { player, amount, .. } => {
+                    if local_players.contains(&player) {
+                        let alpha =
+                            (amount * cvars.hud_damage_flash_scale).min(cvars.hud_damage_flash_max);
+                        self.damage_flashes
+                            .insert(player, (notification.time, alpha));
+                        // Damage is never dealt to wrecks so this was the killing hit.
+                        let vehicle = server.gs.players[player].vehicle;
+                        if vehicle.map_or(false, |handle| server.gs.vehicles[handle].destroyed()) {
+                            self.hit_stop.start(cvars, server.real_time);
+                        }
+                    }
+                }
+                NotificationKind::DamageDealt {
+                    attacker,
+                    facing: Some(Facing::Rear),
+                    ..
+                } => {
+                    if local_players.contains(&attacker) {
+                        self.rear_hits.insert(attacker, notification.time);
+                    }
+                }
+                NotificationKind::WallDestroyed { .. } => {
+                    // The terrain has changed, it'll be redrawn from the rebuilt data.
+                    self.minimap_terrain = None;
+                }
+                kind => {
+                    if let Some(line) = kind.feed_line() {
+                        self.kill_feed.push_back((notification.time, line));
+                    }
+                }
+            }
+        }
 
-    if was_input_pressed(&[KeyCode::Enter, KeyCode::T]) {
-        input.chat = true;
+        let game_time = server.gs.game_time;
+        self.kill_feed
+            .retain(|&(time, _)| game_time - time < cvars.hud_kill_feed_duration);
+        while self.kill_feed.len() > cvars.hud_kill_feed_lines {
+            self.kill_feed.pop_front();
+        }
+        self.damage_flashes
+            .retain(|_, &mut (time, _)| game_time - time < cvars.hud_damage_flash_duration);
+        self.rear_hits
+            .retain(|_, &mut time| game_time - time < cvars.hud_rear_hit_duration);
     }
-    if was_input_pressed(&[KeyCode::Pause, KeyCode::P]) {
-        input.pause = true;
+
+    /// Shake the local players' cameras for new explosions and their heavy shots,
+    /// push vehicles back when they fire and slow the game down during hit-stop.
+    pub fn update_feedback(&mut self, cvars: &Cvars, server: &mut Server) {
+        for player_handle in self.local_players() {
+            let vehicle = server.gs.players[player_handle].vehicle;
+            let camera_center = self.vehicle_pos(server, player_handle).map(|pos| {
+                camera::camera_center(server.map.maxs(), self.world_viewport_size(), pos)
+            });
+            let shake = self
+                .shakes
+                .entry(player_handle)
+                .or_insert_with(|| Shake::new(&server.gs));
+            shake.decay(cvars, server.real_time_delta);
+            if let Some(camera_center) = camera_center {
+                shake.add_effects(cvars, &server.gs, camera_center, vehicle);
+            }
+        }
+        self.recoils.add_flashes(cvars, &server.gs);
+        server.hit_stop = self.hit_stop.scale(cvars, server.real_time);
     }
+}
 
-    input
+/// Progress of loading textures, see `assets`.
+fn draw_loading_screen(loading: &Loading) {
+    let (finished, total) = loading.progress();
+    clear_background(BLACK);
+    let text = format!("Loading assets {}/{}", finished, total);
+    draw_text(&text, 400.0, 400.0, 32.0, WHITE);
+    let fraction = finished as f32 / total.max(1) as f32;
+    draw_rectangle_lines(400.0, 420.0, 300.0, 20.0, 2.0, WHITE);
+    draw_rectangle(400.0, 420.0, 300.0 * fraction, 20.0, WHITE);
 }
 
-fn get_input2() -> Input {
-    let mut input = Input::new();
-    if was_input_pressed(&[KeyCode::Left]) {
-        input.left = true;
-    }
-    if was_input_pressed(&[KeyCode::Right]) {
-        input.right = true;
-    }
-    if was_input_pressed(&[KeyCode::Up]) {
-        input.up = true;
-    }
-    if was_input_pressed(&[KeyCode::Down]) {
-        input.down = true;
-    }
-    if was_input_pressed(&[KeyCode::Comma]) {
-        input.turret_left = true;
-    }
-    if was_input_pressed(&[KeyCode::Period]) {
-        input.turret_right = true;
-    }
-    if was_input_pressed(&[KeyCode::L]) {
-        input.prev_weapon = true;
-    }
-    if was_input_pressed(&[
-        KeyCode::Slash, // US layout
-        KeyCode::Minus, // Same key, CZ layout
-        KeyCode::Kp0,
-    ]) {
-        input.next_weapon = true;
-    }
-    if was_input_pressed(&[KeyCode::RightShift]) {
-        input.fire = true;
-    }
-    if was_input_pressed(&[KeyCode::M]) {
-        input.mine = true;
-    }
-    if was_input_pressed(&[KeyCode::J]) {
-        input.self_destruct = true;
-    }
-    if was_input_pressed(&[KeyCode::K]) {
-        input.horn = true;
+/// The atlas is optional - without it (or if it's broken), text is drawn by macroquad.
+async fn load_bitmap_font() -> Option<BitmapFont> {
+    let text = load_string("assets/font.txt").await.ok()?;
+    let descriptor = match FontDescriptor::parse(&text) {
+        Ok(descriptor) => descriptor,
+        Err(err) => {
+            dbg_logf!("Invalid assets/font.txt: {}", err);
+            return None;
+        }
+    };
+    match load_texture("assets/font.png").await {
+        Ok(atlas) => Some(BitmapFont::new(atlas, descriptor)),
+        Err(err) => {
+            dbg_logf!("Failed to load assets/font.png: {}", err);
+            None
+        }
     }
+}
 
-    // No binds for shared actions like chat, pause, console and esc.
-    // They're defined on player 1.
-
-    input
+/// Size of each viewport - splitscreen divides the screen in two.
+fn viewport_size(cvars: &Cvars, screen_size: Vec2f, splitscreen: bool) -> Vec2f {
+    if splitscreen {
+        let viewport_width = (screen_size.x - cvars.r_splitscreen_gap) / 2.0;
+        Vec2f::new(viewport_width, screen_size.y)
+    } else {
+        screen_size
+    }
 }
 
-fn was_input_pressed(key_codes: &[KeyCode]) -> bool {
-    for &key_code in key_codes {
-        // Check both to avoid skipping input if it's pressed and released within one frame.
-        if is_key_pressed(key_code) || is_key_down(key_code) {
-            return true;
+/// Translate this frame's key events to the names used by `Bindings`, same as a JS frontend would.
+fn forward_key_events(bindings: &mut Bindings) {
+    for &(key_code, name) in KEY_NAMES {
+        if is_key_pressed(key_code) {
+            bindings.key_event(name, true);
+        }
+        // Released and pressed again within one frame is still down.
+        if is_key_released(key_code) && !is_key_down(key_code) {
+            bindings.key_event(name, false);
         }
     }
-    false
 }
+
+/// Macroquad key codes and their JS `KeyboardEvent.code` names.
+const KEY_NAMES: &[(KeyCode, &str)] = &[
+    (KeyCode::Space, "Space"),
+    (KeyCode::Apostrophe, "Quote"),
+    (KeyCode::Comma, "Comma"),
+    (KeyCode::Minus, "Minus"),
+    (KeyCode::Period, "Period"),
+    (KeyCode::Slash, "Slash"),
+    (KeyCode::Key0, "Digit0"),
+    (KeyCode::Key1, "Digit1"),
+    (KeyCode::Key2, "Digit2"),
+    (KeyCode::Key3, "Digit3"),
+    (KeyCode::Key4, "Digit4"),
+    (KeyCode::Key5, "Digit5"),
+    (KeyCode::Key6, "Digit6"),
+    (KeyCode::Key7, "Digit7"),
+    (KeyCode::Key8, "Digit8"),
+    (KeyCode::Key9, "Digit9"),
+    (KeyCode::Semicolon, "Semicolon"),
+    (KeyCode::Equal, "Equal"),
+    (KeyCode::A, "KeyA"),
+    (KeyCode::B, "KeyB"),
+    (KeyCode::C, "KeyC"),
+    (KeyCode::D, "KeyD"),
+    (KeyCode::E, "KeyE"),
+    (KeyCode::F, "KeyF"),
+    (KeyCode::G, "KeyG"),
+    (KeyCode::H, "KeyH"),
+    (KeyCode::I, "KeyI"),
+    (KeyCode::J, "KeyJ"),
+    (KeyCode::K, "KeyK"),
+    (KeyCode::L, "KeyL"),
+    (KeyCode::M, "KeyM"),
+    (KeyCode::N, "KeyN"),
+    (KeyCode::O, "KeyO"),
+    (KeyCode::P, "KeyP"),
+    (KeyCode::Q, "KeyQ"),
+    (KeyCode::R, "KeyR"),
+    (KeyCode::S, "KeyS"),
+    (KeyCode::T, "KeyT"),
+    (KeyCode::U, "KeyU"),
+    (KeyCode::V, "KeyV"),
+    (KeyCode::W, "KeyW"),
+    (KeyCode::X, "KeyX"),
+    (KeyCode::Y, "KeyY"),
+    (KeyCode::Z, "KeyZ"),
+    (KeyCode::LeftBracket, "BracketLeft"),
+    (KeyCode::Backslash, "Backslash"),
+    (KeyCode::RightBracket, "BracketRight"),
+    (KeyCode::GraveAccent, "Backquote"),
+    (KeyCode::Escape, "Escape"),
+    (KeyCode::Enter, "Enter"),
+    (KeyCode::Tab, "Tab"),
+    (KeyCode::Backspace, "Backspace"),
+    (KeyCode::Insert, "Insert"),
+    (KeyCode::Delete, "Delete"),
+    (KeyCode::Right, "ArrowRight"),
+    (KeyCode::Left, "ArrowLeft"),
+    (KeyCode::Down, "ArrowDown"),
+    (KeyCode::Up, "ArrowUp"),
+    (KeyCode::PageUp, "PageUp"),
+    (KeyCode::PageDown, "PageDown"),
+    (KeyCode::Home, "Home"),
+    (KeyCode::End, "End"),
+    (KeyCode::CapsLock, "CapsLock"),
+    (KeyCode::ScrollLock, "ScrollLock"),
+    (KeyCode::NumLock, "NumLock"),
+    (KeyCode::PrintScreen, "PrintScreen"),
+    (KeyCode::Pause, "Pause"),
+    (KeyCode::F1, "F1"),
+    (KeyCode::F2, "F2"),
+    (KeyCode::F3, "F3"),
+    (KeyCode::F4, "F4"),
+    (KeyCode::F5, "F5"),
+    (KeyCode::F6, "F6"),
+    (KeyCode::F7, "F7"),
+    (KeyCode::F8, "F8"),
+    (KeyCode::F9, "F9"),
+    (KeyCode::F10, "F10"),
+    (KeyCode::F11, "F11"),
+    (KeyCode::F12, "F12"),
+    (KeyCode::Kp0, "Numpad0"),
+    (KeyCode::Kp1, "Numpad1"),
+    (KeyCode::Kp2, "Numpad2"),
+    (KeyCode::Kp3, "Numpad3"),
+    (KeyCode::Kp4, "Numpad4"),
+    (KeyCode::Kp5, "Numpad5"),
+    (KeyCode::Kp6, "Numpad6"),
+    (KeyCode::Kp7, "Numpad7"),
+    (KeyCode::Kp8, "Numpad8"),
+    (KeyCode::Kp9, "Numpad9"),
+    (KeyCode::KpDecimal, "NumpadDecimal"),
+    (KeyCode::KpDivide, "NumpadDivide"),
+    (KeyCode::KpMultiply, "NumpadMultiply"),
+    (KeyCode::KpSubtract, "NumpadSubtract"),
+    (KeyCode::KpAdd, "NumpadAdd"),
+    (KeyCode::KpEnter, "NumpadEnter"),
+    (KeyCode::KpEqual, "NumpadEqual"),
+    (KeyCode::LeftShift, "ShiftLeft"),
+    (KeyCode::LeftControl, "ControlLeft"),
+    (KeyCode::LeftAlt, "AltLeft"),
+    (KeyCode::LeftSuper, "MetaLeft"),
+    (KeyCode::RightShift, "ShiftRight"),
+    (KeyCode::RightControl, "ControlRight"),
+    (KeyCode::RightAlt, "AltRight"),
+    (KeyCode::RightSuper, "MetaRight"),
+    (KeyCode::Menu, "ContextMenu"),
+];
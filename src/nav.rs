@@ -0,0 +1,203 @@
+//! Static navigation data for bots - where they can drive and how far from walls it is.
+
+use std::collections::VecDeque;
+
+use crate::{
+    derived::{self, Derivation, Step},
+    map::{Kind, Map, Vec2u},
+};
+
+/// Per-tile navigation data, row by row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NavGrid {
+    pub size: Vec2u,
+    walkable: Vec<bool>,
+    /// Manhattan distance in tiles to the nearest wall, the map edge counts as a wall.
+    /// Bots can use it to keep away from walls.
+    wall_distance: Vec<u32>,
+}
+
+impl NavGrid {
+    /// Build all at once.
+    pub fn build(map: &Map) -> Self {
+        let mut builder = NavGridBuilder::new(map);
+        while builder.step(map, f64::INFINITY) == Step::NotDone {}
+        builder.grid.unwrap()
+    }
+
+    pub fn walkable(&self, index: Vec2u) -> bool {
+        self.walkable[index.y * self.size.x + index.x]
+    }
+
+    pub fn wall_distance(&self, index: Vec2u) -> u32 {
+        self.wall_distance[index.y * self.size.x + index.x]
+    }
+}
+
+/// Builds a `NavGrid` a row at a time, then the wall distances a row's worth of tiles at a time.
+#[derive(Debug, Clone)]
+pub struct NavGridBuilder {
+    /// Partially built until done.
+    partial: NavGrid,
+    /// Rows of walkability done so far.
+    rows: usize,
+    /// Wall distance BFS frontier.
+    queue: VecDeque<usize>,
+    grid: Option<NavGrid>,
+}
+
+impl NavGridBuilder {
+    pub fn new(map: &Map) -> Self {
+        let size = map.size();
+        Self {
+            partial: NavGrid {
+                size,
+                walkable: Vec::with_capacity(size.x * size.y),
+                wall_distance: vec![u32::MAX; size.x * size.y],
+            },
+            rows: 0,
+            queue: VecDeque::new(),
+            grid: None,
+        }
+    }
+
+    pub fn grid(&self) -> Option<&NavGrid> {
+        self.grid.as_ref()
+    }
+
+    fn step_row(&mut self, map: &Map) {
+        let r = self.rows;
+        let width = self.partial.size.x;
+        for c in 0..width {
+            let wall = map.surface_of(map.col_row(c, r)).kind == Kind::Wall;
+            self.partial.walkable.push(!wall);
+            if wall {
+                let i = r * width + c;
+                self.partial.wall_distance[i] = 0;
+                self.queue.push_back(i);
+            }
+        }
+        self.rows += 1;
+
+        // All walls are queued, the edge tiles go after them so the BFS stays in order of distance.
+        if self.rows == self.partial.size.y {
+            let size = self.partial.size;
+            for r in 0..size.y {
+                for c in 0..size.x {
+                    let on_edge = r == 0 || c == 0 || r == size.y - 1 || c == size.x - 1;
+                    let i = r * size.x + c;
+                    if on_edge && self.partial.wall_distance[i] == u32::MAX {
+                        self.partial.wall_distance[i] = 1;
+                        self.queue.push_back(i);
+                    }
+                }
+            }
+        }
+    }
+
+    fn step_distances(&mut self) {
+        let size = self.partial.size;
+        for _ in 0..size.x {
+            let i = match self.queue.pop_front() {
+                Some(i) => i,
+                None => return,
+            };
+            let (c, r) = (i % size.x, i / size.x);
+            let dist = self.partial.wall_distance[i] + 1;
+            let mut visit = |j: usize| {
+                if self.partial.wall_distance[j] == u32::MAX {
+                    self.partial.wall_distance[j] = dist;
+                    self.queue.push_back(j);
+                }
+            };
+            if c > 0 {
+                visit(i - 1);
+            }
+            if c + 1 < size.x {
+                visit(i + 1);
+            }
+            if r > 0 {
+                visit(i - size.x);
+            }
+            if r + 1 < size.y {
+                visit(i + size.x);
+            }
+        }
+    }
+}
+
+impl Derivation for NavGridBuilder {
+    fn name(&self) -> &'static str {
+        "nav grid"
+    }
+
+    fn priority(&self) -> i32 {
+        0
+    }
+
+    fn cost(&self) -> usize {
+        // Each tile is visited twice.
+        self.partial.size.x * self.partial.size.y * 2
+    }
+
+    fn is_done(&self) -> bool {
+        self.grid.is_some()
+    }
+
+    fn step(&mut self, map: &Map, deadline: f64) -> Step {
+        loop {
+            if self.grid.is_some() {
+                return Step::Done;
+            }
+            if self.rows < self.partial.size.y {
+                self.step_row(map);
+            } else if !self.queue.is_empty() {
+                self.step_distances();
+            } else {
+                self.grid = Some(self.partial.clone());
+                return Step::Done;
+            }
+            if derived::now() >= deadline {
+                return Step::NotDone;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::map::{self, Surface};
+
+    #[test]
+    fn test_wall_distance() {
+        let surface = |name: &str, kind| Surface {
+            name: name.to_owned(),
+            kind,
+            friction: 1.0,
+            speed: 1.0,
+            destructible: None,
+        };
+        let surfaces = vec![surface("ground", Kind::Normal), surface("wall", Kind::Wall)];
+        let text = ["0 0 0 0 0 0", "0 0 0 0 0 0", "0 0 0 0 0 4", "0 0 0 0 0 0"].join("\n");
+        let map = map::load_map(&text, surfaces).unwrap();
+        let grid = NavGrid::build(&map);
+        let distances: Vec<Vec<u32>> = (0..4)
+            .map(|r| {
+                (0..6)
+                    .map(|c| grid.wall_distance(Vec2u::new(c, r)))
+                    .collect()
+            })
+            .collect();
+        let expected = [
+            [1, 1, 1, 1, 1, 1],
+            [1, 2, 2, 2, 2, 1],
+            [1, 2, 2, 2, 1, 0],
+            [1, 1, 1, 1, 1, 1],
+        ];
+        assert_eq!(distances, expected);
+        assert!(!grid.walkable(Vec2u::new(5, 2)));
+        assert!(grid.walkable(Vec2u::new(4, 2)));
+    }
+}
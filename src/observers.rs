@@ -0,0 +1,103 @@
+//! Callbacks for game events as an alternative to polling `drain_notifications` every frame.
+//!
+//! The events are the notifications (kills, captures, damage, pickups, match start and end, ...)
+//! so observers and polling see the same things. Gamelogic only queues them,
+//! the server gives them to observers after each update, never in the middle of a tick,
+//! in the order they happened.
+//! Polling still works - observing doesn't remove anything from the queue.
+
+use std::{
+    collections::VecDeque,
+    fmt::{self, Debug, Formatter},
+};
+
+use crate::game_state::Notification;
+
+/// An error means the observer failed but the game goes on -
+/// it's logged and the remaining observers still get the event.
+pub type Observer = Box<dyn FnMut(&Notification) -> Result<(), String>>;
+
+#[derive(Default)]
+pub struct Observers {
+    observers: Vec<Observer>,
+}
+
+impl Observers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The observer gets events which happen from now on.
+    pub fn register(&mut self, observer: Observer) {
+        self.observers.push(observer);
+    }
+
+    /// Give each observer the notifications which haven't been observed yet, oldest first.
+    pub fn dispatch(&mut self, notifications: &mut VecDeque<Notification>) {
+        for notification in notifications.iter_mut().filter(|n| !n.observed) {
+            notification.observed = true;
+            for observer in &mut self.observers {
+                if let Err(err) = observer(notification) {
+                    dbg_logf!(
+                        "Observer failed on {} event: {}",
+                        notification.kind.name(),
+                        err
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl Debug for Observers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Observers({})", self.observers.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    use crate::{
+        cvars::Cvars,
+        game_state::{drain_notifications, push_notification, GameState, NotificationKind},
+    };
+
+    #[test]
+    fn test_dispatch() {
+        let cvars = Cvars::default();
+        let mut gs = GameState::new(0);
+        let mut observers = Observers::new();
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        observers.register(Box::new(|_| Err("broken".to_owned())));
+        let seen2 = Rc::clone(&seen);
+        observers.register(Box::new(move |notification| {
+            seen2.borrow_mut().push(notification.kind.clone());
+            Ok(())
+        }));
+
+        let kind = NotificationKind::MatchStarted;
+        push_notification(&cvars, &mut gs.notifications, 1.0, kind);
+        let kind = NotificationKind::MatchOver { winner: None };
+        push_notification(&cvars, &mut gs.notifications, 2.0, kind);
+        observers.dispatch(&mut gs.notifications);
+        // The failing observer doesn't stop the others and nothing is delivered twice.
+        observers.dispatch(&mut gs.notifications);
+        let expected = vec![
+            NotificationKind::MatchStarted,
+            NotificationKind::MatchOver { winner: None },
+        ];
+        assert_eq!(*seen.borrow(), expected);
+
+        // Polling still gets everything, exactly once.
+        let polled: Vec<_> = drain_notifications(&mut gs)
+            .into_iter()
+            .map(|n| n.kind)
+            .collect();
+        assert_eq!(polled, expected);
+        assert!(drain_notifications(&mut gs).is_empty());
+    }
+}
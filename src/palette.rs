@@ -0,0 +1,69 @@
+//! Recoloring vehicle sprites so players and teams can be told apart, like the original did per team.
+//!
+//! The body of the vehicle sprites is painted in shades of pure blue (red and green are 0),
+//! nothing else uses those colors so they work as a marker range.
+//! Each shade is replaced by the same shade of the new color, the rest (flames, wheels, outlines) is kept.
+//! This is done once per color when it's first needed, not every frame.
+
+use crate::entities::{Player, Team};
+
+pub fn team_rgb(team: Team) -> [u8; 3] {
+    match team {
+        Team::Red => [255, 77, 77],
+        Team::Blue => [102, 153, 255],
+    }
+}
+
+/// The team's color if teams are used, otherwise the player's own if they have one.
+pub fn player_rgb(player: &Player) -> Option<[u8; 3]> {
+    match player.team {
+        Some(team) => Some(team_rgb(team)),
+        None => player.color,
+    }
+}
+
+/// Whether the pixel is part of the recolorable body.
+pub fn is_marker(rgba: [u8; 4]) -> bool {
+    let [r, g, b, _] = rgba;
+    r == 0 && g == 0 && b > 0
+}
+
+/// Replace the marker range in RGBA8 pixel data with shades of `color`, keeping alpha.
+pub fn recolor(pixels: &mut [u8], color: [u8; 3]) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        if !is_marker([pixel[0], pixel[1], pixel[2], pixel[3]]) {
+            continue;
+        }
+        let shade = u16::from(pixel[2]);
+        for (channel, &target) in pixel.iter_mut().zip(&color) {
+            *channel = (u16::from(target) * shade / 255) as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recolor() {
+        let mut pixels = [
+            0, 0, 255, 255, // full marker
+            0, 0, 128, 255, // darker marker
+            255, 0, 0, 255, // flames stay
+            64, 64, 64, 255, // gray stays
+            0, 0, 192, 0, // transparent marker keeps its alpha
+        ];
+        recolor(&mut pixels, [200, 100, 0]);
+        assert_eq!(
+            pixels,
+            [
+                200, 100, 0, 255, //
+                100, 50, 0, 255, //
+                255, 0, 0, 255, //
+                64, 64, 64, 255, //
+                150, 75, 0, 0, //
+            ]
+        );
+    }
+}
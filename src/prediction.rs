@@ -0,0 +1,669 @@
+//! Client-side prediction of the local player's vehicle.
+//!
+//! The client runs its own inputs through the same systems as the server
+//! right away instead of waiting a round trip to see them applied.
+//! Each input is numbered and the server reports the last one it applied.
+//! When an update arrives, the vehicle is rewound to the server's state
+//! and the inputs the server hasn't seen yet are replayed on top of it.
+//! If the result differs from what was being displayed (e.g. something the client doesn't simulate
+//! like hazards or another vehicle pushed it), the difference is blended out over a few frames
+//! or snapped if it's too large.
+//!
+//! Predicted projectiles are only cosmetic - they fly straight until the server confirms
+//! the shot (same input and weapon), then the real one takes over. If the server doesn't fire it
+//! (e.g. the weapon was actually reloading), it's removed when the update acknowledging the input
+//! that fired it arrives - one round trip after firing - or after `cl_prediction_projectile_timeout`
+//! if no such update arrives.
+//!
+//! There's no networking yet so the protocol is just these structs
+//! and `LatencyLink` delays them to simulate a connection.
+//! Everything else is drawn straight from the server's state since it's local
+//! unless `d_remote_update_interval` pretends it arrives less often, see `interpolation`.
+
+use std::{collections::VecDeque, f64::consts::PI};
+
+use thunderdome::Index;
+
+use crate::{
+    cvars::Cvars,
+    entities::{Player, Projectile, Vehicle, Weapon},
+    game_state::{GameState, Input, MatchState},
+    map::{Map, Vec2f},
+    sys_ramming, systems,
+};
+
+/// Input sent from the client to the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputCmd {
+    /// Increases by one with each command, wraps around.
+    pub seq: u32,
+    pub input: Input,
+}
+
+/// A projectile fired by the server while processing the input `seq`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfirmedShot {
+    pub seq: u32,
+    pub weapon: Weapon,
+    pub projectile: Index,
+}
+
+/// Authoritative state of the client's player sent by the server after each frame.
+#[derive(Debug, Clone)]
+pub struct ClientUpdate {
+    /// The last input command applied, `None` before the first one.
+    pub ack: Option<u32>,
+    pub game_time: f64,
+    pub frame_num: u64,
+    /// Players can't move outside of `MatchState::Playing`.
+    pub match_state: MatchState,
+    pub player_handle: Index,
+    pub player: Player,
+    pub vehicle: Option<(Index, Vehicle)>,
+    /// Shots fired since the previous update.
+    pub shots: Vec<ConfirmedShot>,
+}
+
+/// How the predicted vehicle should be drawn this frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PredictedPose {
+    pub vehicle_handle: Index,
+    pub pos: Vec2f,
+    pub angle: f64,
+    pub turret_angle: f64,
+    /// The vehicle was snapped to a new position this frame so don't smooth anything towards it.
+    pub teleported: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct PredictedProjectile {
+    /// The input which fired it.
+    pub seq: u32,
+    pub fire_time: f64,
+    pub projectile: Projectile,
+}
+
+/// Misprediction metrics for the debug overlay.
+/// LATER Show them in a netgraph.
+#[derive(Debug, Clone, Default)]
+pub struct PredictionStats {
+    pub updates: u64,
+    /// Distance between where the client predicted the vehicle after the acknowledged input
+    /// and where the server actually had it.
+    pub error_last: f64,
+    pub error_max: f64,
+    /// Mispredictions blended out.
+    pub corrections: u64,
+    /// Mispredictions too large to blend.
+    pub snaps: u64,
+    /// Inputs replayed after the last update.
+    pub replayed_last: usize,
+    pub shots_confirmed: u64,
+    pub shots_unconfirmed: u64,
+}
+
+#[derive(Debug, Clone)]
+struct PendingInput {
+    seq: u32,
+    input: Input,
+    dt: f64,
+    /// Where the vehicle was predicted to be after this input.
+    pos: Option<Vec2f>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Prediction {
+    pub player_handle: Index,
+    /// Only the local player and their vehicle, at the same handles as on the server.
+    gs: GameState,
+    /// Inputs the server hasn't acknowledged yet, oldest first.
+    pending: VecDeque<PendingInput>,
+    /// What's being displayed minus what's predicted, shrinks to zero over `error_frames`.
+    error_pos: Vec2f,
+    error_angle: f64,
+    error_frames: usize,
+    teleported: bool,
+    pub projectiles: Vec<PredictedProjectile>,
+    pub stats: PredictionStats,
+}
+
+impl Prediction {
+    /// Start predicting from the server's current state.
+    pub fn new(cvars: &Cvars, map: &Map, update: ClientUpdate) -> Self {
+        let mut prediction = Self {
+            player_handle: update.player_handle,
+            gs: GameState::new(0),
+            pending: VecDeque::new(),
+            error_pos: Vec2f::zero(),
+            error_angle: 0.0,
+            error_frames: 0,
+            teleported: false,
+            projectiles: Vec::new(),
+            stats: PredictionStats::default(),
+        };
+        prediction.reconcile(cvars, map, update);
+        prediction
+    }
+
+    /// Apply the input locally. Call once per frame with the command sent to the server
+    /// and the time step the server is expected to simulate it for.
+    pub fn predict(&mut self, cvars: &Cvars, map: &Map, cmd: InputCmd, dt: f64) {
+        self.teleported = false;
+        if self.error_frames > 0 {
+            let remaining = (self.error_frames - 1) as f64 / self.error_frames as f64;
+            self.error_pos *= remaining;
+            self.error_angle *= remaining;
+            self.error_frames -= 1;
+        }
+
+        for predicted in &mut self.projectiles {
+            let projectile = &mut predicted.projectile;
+            projectile.pos += projectile.vel * dt;
+            // Landing is left to the server, the prediction only needs to look right until it's confirmed.
+            if let Some(altitude) = &mut projectile.altitude {
+                systems::fall(cvars, projectile.weapon, altitude, dt);
+            }
+        }
+
+        let fired = step(cvars, map, &mut self.gs, self.player_handle, cmd.input, dt);
+        let fire_time = self.gs.game_time;
+        self.projectiles
+            .extend(fired.into_iter().map(|projectile| PredictedProjectile {
+                seq: cmd.seq,
+                fire_time,
+                projectile,
+            }));
+        let timeout = cvars.cl_prediction_projectile_timeout;
+        self.projectiles.retain(|predicted| {
+            let pos = predicted.projectile.pos;
+            !map.is_wall(pos) && predicted.fire_time + timeout > fire_time
+        });
+
+        self.pending.push_back(PendingInput {
+            seq: cmd.seq,
+            input: cmd.input,
+            dt,
+            pos: vehicle_pos(&self.gs, self.player_handle),
+        });
+        while self.pending.len() > cvars.cl_prediction_max_replay {
+            self.pending.pop_front();
+        }
+    }
+
+    /// Rewind to the server's state and replay the inputs it hasn't applied yet.
+    pub fn reconcile(&mut self, cvars: &Cvars, map: &Map, update: ClientUpdate) {
+        self.stats.updates += 1;
+        let displayed = self.pose();
+
+        if let Some(ack) = update.ack {
+            while let Some(pending) = self.pending.front() {
+                if !seq_le(pending.seq, ack) {
+                    break;
+                }
+                if pending.seq == ack {
+                    if let (Some(predicted), Some((_, vehicle))) = (pending.pos, &update.vehicle) {
+                        let error = (predicted - *vehicle.pos).magnitude();
+                        self.stats.error_last = error;
+                        self.stats.error_max = self.stats.error_max.max(error);
+                    }
+                }
+                self.pending.pop_front();
+            }
+        }
+
+        for shot in &update.shots {
+            let matching = self.projectiles.iter().position(|predicted| {
+                predicted.seq == shot.seq && predicted.projectile.weapon == shot.weapon
+            });
+            if let Some(index) = matching {
+                self.projectiles.remove(index);
+                self.stats.shots_confirmed += 1;
+            }
+        }
+        if let Some(ack) = update.ack {
+            // The server has applied the input which fired these but didn't fire them.
+            let before = self.projectiles.len();
+            self.projectiles
+                .retain(|predicted| !seq_le(predicted.seq, ack));
+            self.stats.shots_unconfirmed += (before - self.projectiles.len()) as u64;
+        }
+
+        // Rewind. The RNG only affects spread of predicted projectiles which are replaced anyway.
+        let mut gs = GameState::new(update.frame_num);
+        gs.game_time = update.game_time;
+        gs.game_time_prev = update.game_time;
+        gs.frame_num = update.frame_num;
+        gs.match_state = update.match_state;
+        gs.players.insert_at(update.player_handle, update.player);
+        if let Some((handle, vehicle)) = update.vehicle {
+            gs.vehicles.insert_at(handle, vehicle);
+        }
+        self.gs = gs;
+
+        // Replay, projectiles were already predicted the first time.
+        for pending in &mut self.pending {
+            step(
+                cvars,
+                map,
+                &mut self.gs,
+                self.player_handle,
+                pending.input,
+                pending.dt,
+            );
+            pending.pos = vehicle_pos(&self.gs, self.player_handle);
+        }
+        self.stats.replayed_last = self.pending.len();
+
+        // Hide the correction.
+        let predicted = self.pose();
+        match (displayed, predicted) {
+            (Some(displayed), Some(predicted))
+                if displayed.vehicle_handle == predicted.vehicle_handle =>
+            {
+                // `predicted` still includes the old error so this is only the new part.
+                let error_pos = displayed.pos - predicted.pos;
+                let error_angle = wrap_angle(displayed.angle - predicted.angle);
+                let total = self.error_pos + error_pos;
+                if total.magnitude() > cvars.cl_prediction_snap_distance {
+                    self.snap();
+                } else if error_pos.magnitude() > 1e-6 || error_angle.abs() > 1e-6 {
+                    self.error_pos += error_pos;
+                    self.error_angle += error_angle;
+                    self.error_frames = cvars.cl_prediction_blend_frames;
+                    self.stats.corrections += 1;
+                }
+            }
+            // New vehicle (e.g. respawned) - nothing to blend from.
+            (Some(_), Some(_)) => self.snap(),
+            _ => {}
+        }
+    }
+
+    fn snap(&mut self) {
+        self.error_pos = Vec2f::zero();
+        self.error_angle = 0.0;
+        self.error_frames = 0;
+        self.teleported = true;
+        self.stats.snaps += 1;
+    }
+
+    /// Where to draw the local vehicle, including any correction still being blended out.
+    pub fn pose(&self) -> Option<PredictedPose> {
+        let vehicle_handle = self.gs.players.get(self.player_handle)?.vehicle?;
+        let vehicle = self.gs.vehicles.get(vehicle_handle)?;
+        Some(PredictedPose {
+            vehicle_handle,
+            pos: *vehicle.pos + self.error_pos,
+            angle: *vehicle.angle + self.error_angle,
+            turret_angle: vehicle.turret_angle_current,
+            teleported: self.teleported,
+        })
+    }
+}
+
+/// The part of a gamelogic frame which only depends on the player's own input.
+/// Returns fired projectiles, already moved for this frame.
+fn step(
+    cvars: &Cvars,
+    map: &Map,
+    gs: &mut GameState,
+    player_handle: Index,
+    input: Input,
+    dt: f64,
+) -> Vec<Projectile> {
+    gs.game_time_prev = gs.game_time;
+    gs.game_time += dt;
+    gs.dt = dt;
+    gs.frame_num += 1;
+
+    let input_prev = gs.players[player_handle].input;
+    gs.inputs_prev.0.insert(player_handle, input_prev);
+    gs.players[player_handle].input = input;
+
+    // Same order as on the server.
+    systems::cleanup(cvars, gs);
+    if gs.match_state == MatchState::Playing {
+        systems::player_logic(cvars, gs);
+        systems::vehicle_logic(cvars, gs);
+        systems::vehicle_movement(cvars, gs, map);
+        sys_ramming::vehicle_collisions(cvars, gs, map);
+        systems::shooting(cvars, gs);
+    }
+
+    gs.audio_events.clear();
+    gs.notifications.clear();
+    gs.projectiles
+        .drain()
+        .map(|(_, mut projectile)| {
+            projectile.pos += projectile.vel * dt;
+            if let Some(altitude) = &mut projectile.altitude {
+                systems::fall(cvars, projectile.weapon, altitude, dt);
+            }
+            projectile
+        })
+        .collect()
+}
+
+fn vehicle_pos(gs: &GameState, player_handle: Index) -> Option<Vec2f> {
+    let handle = gs.players.get(player_handle)?.vehicle?;
+    gs.vehicles.get(handle).map(|vehicle| *vehicle.pos)
+}
+
+/// Whether `a` is `b` or comes before it, taking wrapping into account.
+fn seq_le(a: u32, b: u32) -> bool {
+    b.wrapping_sub(a) < u32::MAX / 2
+}
+
+/// The same angle between -PI and PI.
+pub fn wrap_angle(angle: f64) -> f64 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+/// Delivers messages a fixed number of frames after they're sent, in order.
+#[derive(Debug, Clone)]
+pub struct LatencyLink<T> {
+    frame: u64,
+    queue: VecDeque<(u64, T)>,
+}
+
+impl<T> LatencyLink<T> {
+    pub fn new() -> Self {
+        Self {
+            frame: 0,
+            queue: VecDeque::new(),
+        }
+    }
+
+    pub fn send(&mut self, delay_frames: usize, msg: T) {
+        let mut arrival = self.frame + delay_frames as u64;
+        // Lowering the delay doesn't let messages overtake each other.
+        if let Some(&(last, _)) = self.queue.back() {
+            arrival = arrival.max(last);
+        }
+        self.queue.push_back((arrival, msg));
+    }
+
+    /// Messages arriving this frame.
+    pub fn receive(&mut self) -> Vec<T> {
+        let mut msgs = Vec::new();
+        while let Some(&(arrival, _)) = self.queue.front() {
+            if arrival > self.frame {
+                break;
+            }
+            msgs.push(self.queue.pop_front().unwrap().1);
+        }
+        msgs
+    }
+
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+}
+
+impl<T> Default for LatencyLink<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    use crate::{
+        entities::{Ammo, Obb},
+        map::{self, F64Ext},
+        server::Server,
+    };
+
+    /// 100 ms each way at 60 FPS.
+    const LATENCY_FRAMES: usize = 6;
+    const FPS: f64 = 60.0;
+
+    /// A server and a predicting client connected through `LatencyLink`s, same as in `mq`.
+    struct Harness {
+        cvars: Cvars,
+        server: Server,
+        player_handle: Index,
+        frame: u64,
+        seq: u32,
+        prediction: Prediction,
+        uplink: LatencyLink<InputCmd>,
+        downlink: LatencyLink<ClientUpdate>,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            let cvars = Cvars {
+                d_seed: 5,
+                bots_max: 0,
+                cl_prediction: true,
+                d_latency_frames: LATENCY_FRAMES,
+                g_match_countdown: 0.0,
+                ..Cvars::default()
+            };
+            let map = map::tests::load_test_map(&fs::read_to_string("maps/Atrium.map").unwrap());
+            let mut server = Server::new(&cvars, map);
+            let player_handle = server.connect(&cvars, "Player 1");
+            let update = server.client_update(player_handle);
+            let prediction = Prediction::new(&cvars, &server.map, update);
+            Self {
+                cvars,
+                server,
+                player_handle,
+                frame: 0,
+                seq: 0,
+                prediction,
+                uplink: LatencyLink::new(),
+                downlink: LatencyLink::new(),
+            }
+        }
+
+        /// One rendered frame in the same order as the main loop.
+        fn frame(&mut self, input: Input) {
+            self.frame += 1;
+            let real_time = self.frame as f64 / FPS;
+
+            self.server.snapshot_inputs();
+
+            let cmd = InputCmd {
+                seq: self.seq,
+                input,
+            };
+            self.seq += 1;
+            let dt = (real_time - self.server.real_time) * self.server.speed(&self.cvars);
+            self.prediction
+                .predict(&self.cvars, &self.server.map, cmd, dt);
+            self.uplink.send(self.cvars.d_latency_frames, cmd);
+            for cmd in self.uplink.receive() {
+                self.server.input_cmd(self.player_handle, cmd);
+            }
+
+            self.server.update(&self.cvars, real_time);
+
+            let update = self.server.client_update(self.player_handle);
+            self.downlink.send(self.cvars.d_latency_frames, update);
+            for update in self.downlink.receive() {
+                self.prediction
+                    .reconcile(&self.cvars, &self.server.map, update);
+            }
+            self.uplink.next_frame();
+            self.downlink.next_frame();
+        }
+
+        fn frames(&mut self, count: usize, input: Input) {
+            for _ in 0..count {
+                self.frame(input);
+            }
+        }
+
+        fn server_vehicle(&mut self) -> &mut Vehicle {
+            let handle = self.server.gs.players[self.player_handle].vehicle.unwrap();
+            &mut self.server.gs.vehicles[handle]
+        }
+
+        fn displayed(&self) -> PredictedPose {
+            self.prediction.pose().unwrap()
+        }
+
+        /// Move the vehicle on the server only, like a collision the client doesn't simulate.
+        fn push(&mut self, pos: Vec2f) {
+            let frame_num = self.server.gs.frame_num;
+            let vehicle = self.server_vehicle();
+            vehicle.pos.set(pos, frame_num);
+            vehicle.obb = Obb::new(vehicle.hitbox, pos, *vehicle.angle);
+        }
+
+        /// A nearby position `distance` away the vehicle fits into.
+        fn free_pos(&mut self, distance: f64) -> Vec2f {
+            let vehicle = self.server_vehicle().clone();
+            (0..16)
+                .map(|i| *vehicle.pos + (f64::from(i) * PI / 8.0).to_vec2f() * distance)
+                .find(|&pos| {
+                    let obb = Obb::new(vehicle.hitbox, pos, *vehicle.angle);
+                    obb.corners
+                        .iter()
+                        .all(|&corner| !self.server.map.is_wall(corner))
+                })
+                .unwrap()
+        }
+    }
+
+    fn pressed(f: impl FnOnce(&mut Input)) -> Input {
+        let mut input = Input::new();
+        f(&mut input);
+        input
+    }
+
+    #[test]
+    fn test_responds_within_one_frame() {
+        let mut h = Harness::new();
+        h.frames(10, Input::new());
+
+        let displayed_before = h.displayed().pos;
+        let server_before = *h.server_vehicle().pos;
+        h.frame(pressed(|input| input.up = true));
+        assert_ne!(h.displayed().pos, displayed_before);
+        assert_eq!(*h.server_vehicle().pos, server_before);
+
+        // The server only starts moving once the input arrives.
+        h.frames(LATENCY_FRAMES - 1, pressed(|input| input.up = true));
+        assert_eq!(*h.server_vehicle().pos, server_before);
+        h.frame(pressed(|input| input.up = true));
+        assert_ne!(*h.server_vehicle().pos, server_before);
+    }
+
+    #[test]
+    fn test_scripted_drive_error() {
+        let mut h = Harness::new();
+        for i in 0..600 {
+            let input = pressed(|input| {
+                input.up = i % 200 < 150;
+                input.down = i % 200 >= 170;
+                input.left = (40..70).contains(&(i % 100));
+                input.right = i % 150 > 120;
+                input.turret_left = i % 37 == 0;
+                input.fire = i % 90 < 20;
+            });
+            h.frame(input);
+        }
+        assert!(h.prediction.stats.updates > 500);
+        assert!(
+            h.prediction.stats.error_max < 0.01,
+            "{:?}",
+            h.prediction.stats
+        );
+        assert_eq!(h.prediction.stats.corrections, 0);
+        assert_eq!(h.prediction.stats.snaps, 0);
+
+        // Once the vehicle stops and everything arrives, the client sees what the server has.
+        h.frames(600, Input::new());
+        let server_pos = *h.server_vehicle().pos;
+        assert!((h.displayed().pos - server_pos).magnitude() < 0.01);
+    }
+
+    #[test]
+    fn test_unpredicted_push() {
+        let mut h = Harness::new();
+        h.frames(10, Input::new());
+
+        // Small push - blended out over several frames.
+        let pos = h.free_pos(20.0);
+        h.push(pos);
+        let mut prev = h.displayed().pos;
+        let mut max_jump: f64 = 0.0;
+        for _ in 0..2 * LATENCY_FRAMES + h.cvars.cl_prediction_blend_frames {
+            h.frame(Input::new());
+            let displayed = h.displayed();
+            assert!(!displayed.teleported);
+            max_jump = max_jump.max((displayed.pos - prev).magnitude());
+            prev = displayed.pos;
+        }
+        let blend_frames = h.cvars.cl_prediction_blend_frames as f64;
+        assert!(max_jump > 0.0);
+        assert!(max_jump <= 20.0 / blend_frames + 0.01, "{}", max_jump);
+        assert!((h.displayed().pos - pos).magnitude() < 0.01);
+        assert_eq!(h.prediction.stats.corrections, 1);
+        assert_eq!(h.prediction.stats.snaps, 0);
+
+        // Pushed too far - snapped.
+        let pos = h.free_pos(h.cvars.cl_prediction_snap_distance + 20.0);
+        h.push(pos);
+        let mut teleported = false;
+        for _ in 0..2 * LATENCY_FRAMES {
+            h.frame(Input::new());
+            teleported |= h.displayed().teleported;
+        }
+        assert!(teleported);
+        assert!((h.displayed().pos - pos).magnitude() < 0.01);
+        assert_eq!(h.prediction.stats.snaps, 1);
+    }
+
+    #[test]
+    fn test_predicted_rockets() {
+        let mut h = Harness::new();
+        h.frame(pressed(|input| input.select_weapon = Some(Weapon::Rockets)));
+        h.frames(2 * LATENCY_FRAMES, Input::new());
+
+        // Confirmed - replaced by the server's rocket.
+        h.frame(pressed(|input| input.fire = true));
+        assert_eq!(h.prediction.projectiles.len(), 1);
+        assert_eq!(
+            h.prediction.projectiles[0].projectile.weapon,
+            Weapon::Rockets
+        );
+        h.frames(2 * LATENCY_FRAMES, Input::new());
+        assert!(h.prediction.projectiles.is_empty());
+        assert_eq!(h.prediction.stats.shots_confirmed, 1);
+        assert_eq!(h.prediction.stats.shots_unconfirmed, 0);
+        h.frames(60, Input::new());
+
+        // The server's rockets are reloading but the client doesn't know yet.
+        let game_time = h.server.gs.game_time;
+        h.server_vehicle().ammos[Weapon::Rockets as usize] =
+            Ammo::Reloading(game_time, game_time + 10.0);
+        h.frame(pressed(|input| input.fire = true));
+        assert_eq!(h.prediction.projectiles.len(), 1);
+        h.frames(2 * LATENCY_FRAMES - 1, Input::new());
+        assert_eq!(h.prediction.projectiles.len(), 1);
+        // Gone once the update acknowledging the input arrives - one round trip.
+        h.frame(Input::new());
+        assert!(h.prediction.projectiles.is_empty());
+        assert_eq!(h.prediction.stats.shots_confirmed, 1);
+        assert_eq!(h.prediction.stats.shots_unconfirmed, 1);
+    }
+
+    #[test]
+    fn test_latency_link() {
+        let mut link = LatencyLink::new();
+        link.send(2, 1);
+        link.send(0, 2);
+        assert_eq!(link.receive(), Vec::<i32>::new());
+        link.next_frame();
+        assert_eq!(link.receive(), Vec::<i32>::new());
+        link.next_frame();
+        assert_eq!(link.receive(), vec![1, 2]);
+    }
+}
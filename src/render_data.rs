@@ -0,0 +1,758 @@
+//! Everything needed to draw one viewport, read out of the simulation but not drawn yet.
+//!
+//! `rendering` draws from this and so can any other renderer (e.g. an experimental WebGL one)
+//! so they can't disagree about what's visible and where.
+//!
+//! The buffers are struct of arrays - one `Vec` per attribute so each can be handed over as a typed array.
+//! They're cleared and refilled every frame so they stop allocating once they've grown large enough.
+//! All positions are in screen coords (already offset by the camera).
+//! Entities which are certainly outside the view are left out.
+
+use std::cmp::Reverse;
+
+use thunderdome::Index;
+use vek::Clamp;
+
+use crate::{
+    camera,
+    cvars::Cvars,
+    effects::Effect,
+    entities::{PowerupKind, Team, VehicleType, Weapon},
+    feedback::Recoils,
+    fixed::MilliHp,
+    game_state::MatchState,
+    interpolation::Interpolation,
+    map::{F64Ext, Kind, Vec2f, VecExt, TILE_SIZE},
+    palette,
+    prediction::Prediction,
+    server::Server,
+    sys_ctf::FlagState,
+    sys_match, systems,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct RenderData {
+    /// Top left corner of the camera in world coords.
+    pub camera_top_left: Vec2f,
+    /// Add this to world coords to get screen coords.
+    pub camera_offset: Vec2f,
+    /// Top left corner of the map on screen - it's centered when smaller than the viewport.
+    pub view_pos: Vec2f,
+    /// The part of the viewport covered by the map.
+    pub view_size: Vec2f,
+    pub tiles: Tiles,
+    /// Walls are separate because they're drawn above explosions and turrets.
+    pub walls: Tiles,
+    pub vehicles: Vehicles,
+    /// Includes the local player's predicted projectiles until the server confirms them.
+    pub projectiles: Projectiles,
+    pub beams: Beams,
+    pub flashes: Flashes,
+    /// In drawing order (see `r_explosions_reverse_order`).
+    pub explosions: Explosions,
+    /// Empty outside capture the flag.
+    pub flags: Flags,
+    /// One for each barrel of the current weapon.
+    pub aims: Vec<Aim>,
+    /// None if the local player has no vehicle.
+    pub hud: Option<Hud>,
+    /// For the countdown and victory screen.
+    pub match_state: MatchState,
+    /// Seconds until the match state changes, see `sys_match::time_left`.
+    pub match_time_left: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Tiles {
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+    pub surface_index: Vec<usize>,
+    pub angle: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Vehicles {
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+    pub angle: Vec<f64>,
+    /// Relative to the chassis.
+    pub turret_angle: Vec<f64>,
+    /// Turret displacement from recoil on top of the chassis, which already includes its own.
+    pub turret_recoil_x: Vec<f64>,
+    pub turret_recoil_y: Vec<f64>,
+    pub veh_type: Vec<VehicleType>,
+    pub destroyed: Vec<bool>,
+    /// Team or player color to tint the sprites with, see `palette`.
+    pub color: Vec<Option<[u8; 3]>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Projectiles {
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+    /// Direction of movement.
+    pub angle: Vec<f64>,
+    pub weapon: Vec<Weapon>,
+    /// Vehicle debris, drawn differently than its `weapon`.
+    pub debris: Vec<bool>,
+    /// Height above the ground, 0 unless it flies in an arc.
+    pub z: Vec<f64>,
+}
+
+/// Railgun and BFG beams. Not culled, they can cross the view without either end being in it.
+#[derive(Debug, Clone, Default)]
+pub struct Beams {
+    pub begin_x: Vec<f64>,
+    pub begin_y: Vec<f64>,
+    pub end_x: Vec<f64>,
+    pub end_y: Vec<f64>,
+    pub weapon: Vec<Weapon>,
+    /// Fraction of the lifetime left, for fading out.
+    pub remaining: Vec<f64>,
+}
+
+/// Muzzle flashes (see `cl_muzzle_flash`).
+#[derive(Debug, Clone, Default)]
+pub struct Flashes {
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+    /// Direction of the shot.
+    pub angle: Vec<f64>,
+    pub weapon: Vec<Weapon>,
+    /// Fraction of the lifetime left, for fading out.
+    pub remaining: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Explosions {
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+    pub scale: Vec<f64>,
+    /// Seconds since the explosion started.
+    pub age: Vec<f64>,
+    pub bfg: Vec<bool>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Flags {
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+    pub team: Vec<Team>,
+    pub carried: Vec<bool>,
+}
+
+/// Where one barrel of the local player's current weapon would shoot (see `cl_crosshair`).
+#[derive(Debug, Clone, Default)]
+pub struct Aim {
+    /// The first point is the shot origin.
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+    /// The points are a trajectory to draw as dots, otherwise they're connected by lines.
+    pub dotted: bool,
+    /// The last point is where a railgun shot would hit a wall.
+    pub wall_hit: bool,
+}
+
+/// Values shown in the screen-space HUD of the local player.
+#[derive(Debug, Clone)]
+pub struct Hud {
+    pub points: i32,
+    /// Starting from 1.
+    pub rank: usize,
+    pub players: usize,
+    /// To the leader or, if the player is the leader, to the second.
+    pub points_diff: i32,
+    pub hp: MilliHp,
+    pub hp_fraction: f64,
+    pub weapon: Weapon,
+    pub ammo: u32,
+    pub ammo_max: u32,
+    /// `RESERVE_UNLIMITED` if it doesn't run out.
+    pub reserve: u32,
+    /// Ammo left, heat or, while controlling a guided missile, its time left.
+    pub ammo_fraction: f64,
+    /// Between 0 and 1 while reloading or cooling down after overheating.
+    pub reload_progress: Option<f64>,
+    /// Between 0 and 1 for weapons limited by heat (see `FireLimiter`).
+    pub heat: Option<f64>,
+    /// Seconds until the controlled guided missile explodes.
+    pub gm_time_left: Option<f64>,
+    pub speed: f64,
+    /// Acceleration of airborne projectiles, zero when there's no wind.
+    pub wind: Vec2f,
+    /// Active powerups and the fraction of their duration left.
+    pub powerups: Vec<(PowerupKind, f64)>,
+}
+
+impl RenderData {
+    /// Refill all buffers for the viewport of `local_player_handle`.
+    ///
+    /// Positions are relative to the viewport but in world units (pixels at zoom 1), they're drawn scaled by `zoom`.
+    /// `camera_center` overrides the camera (round intro, spectating), `shake` displaces it (see `feedback`),
+    /// `prediction` is only used if it's for this player,
+    /// vehicles in `interpolation` are drawn where it says instead of where the server has them
+    /// and `recoils` displaces them after they fire.
+    pub fn fill(
+        &mut self,
+        cvars: &Cvars,
+        server: &Server,
+        viewport_size: Vec2f,
+        zoom: f64,
+        camera_center: Option<Vec2f>,
+        shake: Vec2f,
+        prediction: Option<&Prediction>,
+        interpolation: Option<&Interpolation>,
+        recoils: Option<&Recoils>,
+        local_player_handle: Index,
+    ) {
+        let gs = &server.gs;
+        let player = &gs.players[local_player_handle];
+        let prediction =
+            prediction.filter(|prediction| prediction.player_handle == local_player_handle);
+        // The local vehicle is drawn where the client predicts it, everything else where the server has it.
+        let predicted = prediction.and_then(|prediction| prediction.pose());
+        let player_vehicle_pos = player.vehicle.map(|handle| match predicted {
+            Some(pose) if pose.vehicle_handle == handle => pose.pos,
+            _ => *gs.vehicles[handle].pos,
+        });
+        let player_entity_pos = match player.guided_missile {
+            Some(gm_handle) => gs.projectiles[gm_handle].pos,
+            None => player_vehicle_pos.unwrap_or_default(),
+        };
+
+        // Camera
+        let map_size = server.map.maxs();
+        let world_viewport = camera::world_viewport_size(viewport_size, zoom);
+        self.view_size = Vec2f::partial_min(world_viewport, map_size);
+        let camera_center = camera_center
+            .unwrap_or_else(|| camera::camera_center(map_size, world_viewport, player_entity_pos));
+        // Clamped again so shaking never shows anything outside the map.
+        let transform =
+            camera::ViewTransform::new(map_size, viewport_size, zoom, camera_center + shake);
+        self.view_pos = transform.view_pos / zoom;
+        self.camera_top_left = transform.camera_top_left;
+        // Forgetting to add this to world coords is a recurring source of bugs.
+        // I've considered making a special type for screen coords (e.g. struct Vec2screen(Vec2f);)
+        // so you couldn't accidentally pass world coords to drawing fns but it turned out to be more work than expected:
+        // - The newtype had to manually impl all the needed operations of the underlying Vec2 type because ops don't autoderef.
+        // - What would be the result of ops that take one world coord and one screen coord? Lots of cases to think about.
+        // - Which type are sizes? Another type? E.g. `center = corner + size/2` makes sense in both screen and world coords.
+        self.camera_offset = -self.camera_top_left + self.view_pos;
+
+        // Tiles
+        self.tiles.clear();
+        self.walls.clear();
+        let top_left_tp = server.map.tile_pos(self.camera_top_left);
+        let top_left_index = top_left_tp.index;
+        let bg_offset = if cvars.r_align_to_pixels_background {
+            // Rounded on screen, after zooming, otherwise tiles shimmer when zoomed.
+            (top_left_tp.offset * zoom).floor() / zoom
+        } else {
+            top_left_tp.offset
+        };
+        let camera_bottom_right = self.camera_top_left + self.view_size;
+        for index in server
+            .map
+            .tiles_in_rect(self.camera_top_left, camera_bottom_right)
+        {
+            let tile = server.map[index];
+            let tiles = if server.map.surface_of(tile).kind == Kind::Wall {
+                &mut self.walls
+            } else {
+                &mut self.tiles
+            };
+            let pos = (index - top_left_index).as_::<f64>() * TILE_SIZE - bg_offset;
+            tiles.x.push(self.view_pos.x + pos.x);
+            tiles.y.push(self.view_pos.y + pos.y);
+            tiles.surface_index.push(tile.surface_index);
+            tiles.angle.push(tile.angle);
+        }
+
+        let outside_view_top_left = self.view_pos - TILE_SIZE;
+        let outside_view_bottom_right = self.view_pos + self.view_size + TILE_SIZE;
+        // Is the object certainly outside camera view?
+        // Only works on objects smaller that tile size, which is most.
+        let cull = |scr_pos: Vec2f| {
+            scr_pos.x < outside_view_top_left.x
+                || scr_pos.y < outside_view_top_left.y
+                || scr_pos.x > outside_view_bottom_right.x
+                || scr_pos.y > outside_view_bottom_right.y
+        };
+
+        // Vehicles
+        self.vehicles.clear();
+        for (handle, vehicle) in gs.vehicles.iter() {
+            let interpolated = interpolation
+                .and_then(|interpolation| interpolation.pose(cvars, handle, gs.game_time));
+            let (pos, angle, turret_angle) = match (predicted, interpolated) {
+                (Some(pose), _) if pose.vehicle_handle == handle => {
+                    (pose.pos, pose.angle, pose.turret_angle)
+                }
+                (_, Some((pos, angle))) => (pos, angle, vehicle.turret_angle_current),
+                _ => (*vehicle.pos, *vehicle.angle, vehicle.turret_angle_current),
+            };
+            let (chassis_recoil, turret_recoil) = recoils.map_or_else(
+                || (Vec2f::zero(), Vec2f::zero()),
+                |recoils| recoils.offsets(cvars, handle, gs.game_time),
+            );
+            let scr_pos = pos + chassis_recoil + self.camera_offset;
+            if cull(scr_pos) {
+                continue;
+            }
+            self.vehicles.x.push(scr_pos.x);
+            self.vehicles.y.push(scr_pos.y);
+            self.vehicles.angle.push(angle);
+            self.vehicles.turret_angle.push(turret_angle);
+            self.vehicles.turret_recoil_x.push(turret_recoil.x);
+            self.vehicles.turret_recoil_y.push(turret_recoil.y);
+            self.vehicles.veh_type.push(vehicle.veh_type);
+            self.vehicles.destroyed.push(vehicle.destroyed());
+            let color = if cvars.r_vehicle_colors {
+                palette::player_rgb(&gs.players[vehicle.owner])
+            } else {
+                None
+            };
+            self.vehicles.color.push(color);
+        }
+
+        // Projectiles
+        // Railgun beams only appear once confirmed, they're too fast to matter.
+        self.projectiles.clear();
+        let predicted_projectiles = prediction
+            .into_iter()
+            .flat_map(|prediction| &prediction.projectiles)
+            .map(|predicted_projectile| &predicted_projectile.projectile);
+        for (proj, debris) in gs
+            .projectiles
+            .iter()
+            .map(|(handle, proj)| (proj, gs.debris.contains_key(&handle)))
+            .chain(predicted_projectiles.map(|proj| (proj, false)))
+        {
+            let scr_pos = proj.pos + self.camera_offset;
+            if cull(scr_pos) {
+                continue;
+            }
+            self.projectiles.x.push(scr_pos.x);
+            self.projectiles.y.push(scr_pos.y);
+            self.projectiles.angle.push(proj.vel.to_angle());
+            self.projectiles.weapon.push(proj.weapon);
+            self.projectiles.debris.push(debris);
+            self.projectiles
+                .z
+                .push(proj.altitude.map_or(0.0, |altitude| altitude.z));
+        }
+
+        // Beams
+        self.beams.clear();
+        for effect in &gs.effects {
+            let (begin, end, weapon) = match *effect {
+                Effect::RailBeam { begin, end, .. } => (begin, end, Weapon::Rail),
+                Effect::BfgBeam { begin, end, .. } => (begin, end, Weapon::Bfg),
+                Effect::Explosion { .. } | Effect::MuzzleFlash { .. } => continue,
+            };
+            let scr_begin = begin + self.camera_offset;
+            let scr_end = end + self.camera_offset;
+            self.beams.begin_x.push(scr_begin.x);
+            self.beams.begin_y.push(scr_begin.y);
+            self.beams.end_x.push(scr_end.x);
+            self.beams.end_y.push(scr_end.y);
+            self.beams.weapon.push(weapon);
+            self.beams
+                .remaining
+                .push(effect.remaining(cvars, gs.game_time));
+        }
+
+        // Muzzle flashes
+        self.flashes.clear();
+        for effect in &gs.effects {
+            let (pos, angle, weapon) = match *effect {
+                Effect::MuzzleFlash {
+                    pos, angle, weapon, ..
+                } if cvars.cl_muzzle_flash => (pos, angle, weapon),
+                _ => continue,
+            };
+            let scr_pos = pos + self.camera_offset;
+            if cull(scr_pos) {
+                continue;
+            }
+            self.flashes.x.push(scr_pos.x);
+            self.flashes.y.push(scr_pos.y);
+            self.flashes.angle.push(angle);
+            self.flashes.weapon.push(weapon);
+            self.flashes
+                .remaining
+                .push(effect.remaining(cvars, gs.game_time));
+        }
+
+        // Explosions
+        self.explosions.clear();
+        for i in 0..gs.effects.len() {
+            let effect = if cvars.r_explosions_reverse_order {
+                &gs.effects[gs.effects.len() - 1 - i]
+            } else {
+                &gs.effects[i]
+            };
+            let (pos, scale, start_time, bfg) = match *effect {
+                Effect::Explosion {
+                    pos,
+                    scale,
+                    start_time,
+                    bfg,
+                } => (pos, scale, start_time, bfg),
+                _ => continue,
+            };
+            let scr_pos = pos + self.camera_offset;
+            if cull(scr_pos) {
+                continue;
+            }
+            self.explosions.x.push(scr_pos.x);
+            self.explosions.y.push(scr_pos.y);
+            self.explosions.scale.push(scale);
+            self.explosions.age.push(gs.game_time - start_time);
+            self.explosions.bfg.push(bfg);
+        }
+
+        // Flags
+        self.flags.clear();
+        if let Some(ctf) = &gs.ctf {
+            for (team, flag) in Team::all().into_iter().zip(&ctf.flags) {
+                let scr_pos = flag.pos + self.camera_offset;
+                if cull(scr_pos) {
+                    continue;
+                }
+                self.flags.x.push(scr_pos.x);
+                self.flags.y.push(scr_pos.y);
+                self.flags.team.push(team);
+                self.flags
+                    .carried
+                    .push(matches!(flag.state, FlagState::Carried(_)));
+            }
+        }
+
+        // Aim
+        // Uses the same shot transforms as shooting so they can't disagree.
+        self.aims.clear();
+        let aim_vehicle = player
+            .vehicle
+            .filter(|_| cvars.cl_crosshair && player.guided_missile.is_none())
+            .map(|handle| (handle, &gs.vehicles[handle]))
+            .filter(|(_, vehicle)| !vehicle.destroyed());
+        if let Some((handle, vehicle)) = aim_vehicle {
+            let mut vehicle = vehicle.clone();
+            let (pos, angle) = match predicted {
+                Some(pose) if pose.vehicle_handle == handle => {
+                    vehicle.turret_angle_current = pose.turret_angle;
+                    (pose.pos, pose.angle)
+                }
+                _ => (*vehicle.pos, *vehicle.angle),
+            };
+            let weapon = player.cur_weapon;
+            for (origin, shot_angle) in
+                systems::shot_transforms(cvars, &vehicle, weapon, pos, angle)
+            {
+                let mut aim = Aim::default();
+                aim.push(origin + self.camera_offset);
+                match weapon {
+                    Weapon::Rail => {
+                        // Longer than the map's diagonal and everything outside the map is a wall.
+                        let dir = shot_angle.to_vec2f();
+                        let end = origin + dir * map_size.magnitude();
+                        let hit = server.map.is_wall_trace(origin, end).unwrap_or(end);
+                        aim.push(hit + self.camera_offset);
+                        aim.wall_hit = true;
+                    }
+                    Weapon::Cb | Weapon::Rockets => {
+                        // Integrated the same way as `systems::projectiles` moves them.
+                        aim.dotted = true;
+                        let wind = Vec2f::new(cvars.g_wind_x, cvars.g_wind_y);
+                        let dt = cvars.cl_crosshair_dot_interval;
+                        let mut pos = origin;
+                        let mut vel =
+                            systems::shot_velocity(cvars, weapon, shot_angle, vehicle.vel);
+                        let mut altitude = systems::launch_altitude(cvars, weapon);
+                        let fuse = altitude.is_none() || cvars.g_weapon_arc_fuse;
+                        for i in 1..=cvars.cl_crosshair_dots {
+                            // Cluster bombs explode after a while (ignoring the spread).
+                            if fuse
+                                && weapon == Weapon::Cb
+                                && i as f64 * dt > cvars.g_cluster_bomb_time
+                            {
+                                break;
+                            }
+                            // Or when they land, the last dot is where.
+                            let landed = match &mut altitude {
+                                Some(altitude) => systems::fall(cvars, weapon, altitude, dt),
+                                None => false,
+                            };
+                            if weapon.is_airborne() {
+                                vel += wind * dt;
+                            }
+                            let next = pos + vel * dt;
+                            // Cluster bombs fly over walls unless they bounce - the preview ends at the first bounce.
+                            if weapon == Weapon::Rockets || cvars.g_cluster_bomb_bounce {
+                                if let Some(hit) = server.map.is_wall_trace(pos, next) {
+                                    aim.push(hit + self.camera_offset);
+                                    break;
+                                }
+                            }
+                            pos = next;
+                            aim.push(pos + self.camera_offset);
+                            if landed {
+                                break;
+                            }
+                        }
+                    }
+                    _ => {
+                        let dir = shot_angle.to_vec2f();
+                        aim.push(origin + dir * cvars.cl_crosshair_length + self.camera_offset);
+                    }
+                }
+                self.aims.push(aim);
+            }
+        }
+
+        // HUD
+        self.match_state = gs.match_state;
+        self.match_time_left = sys_match::time_left(cvars, gs);
+        self.hud = player.vehicle.map(|vehicle_handle| {
+            let player_vehicle = &gs.vehicles[vehicle_handle];
+
+            let mut player_points: Vec<_> = gs
+                .players
+                .iter()
+                .map(|(index, player)| (index, player.score.points(cvars)))
+                .collect();
+            player_points.sort_by_key(|&(_, points)| Reverse(points));
+            let current_index = player_points
+                .iter()
+                .position(|&(handle, _)| handle == local_player_handle)
+                .unwrap();
+            let points_diff = if current_index == 0 {
+                if player_points.len() == 1 {
+                    // The player is alone.
+                    0
+                } else {
+                    player_points[current_index].1 - player_points[1].1
+                }
+            } else {
+                player_points[current_index].1 - player_points[0].1
+            };
+
+            let status = player_vehicle.weapon_status(cvars, player.cur_weapon, gs.game_time);
+            let gm_time_left = player
+                .guided_missile
+                .map(|gm_handle| gs.projectiles[gm_handle].explode_time - gs.game_time);
+            let ammo_fraction = match gm_time_left {
+                Some(left) if cvars.g_guided_missile_time.is_finite() => {
+                    (left / cvars.g_guided_missile_time).clamped(0.0, 1.0)
+                }
+                Some(_) => 1.0,
+                None => status.fraction(),
+            };
+
+            let powerups = PowerupKind::ALL
+                .iter()
+                .filter_map(|&kind| {
+                    let powerup = player_vehicle.powerup(kind, gs.game_time)?;
+                    let left = (powerup.until - gs.game_time) / cvars.g_powerup_duration(kind);
+                    Some((kind, left.clamped(0.0, 1.0)))
+                })
+                .collect();
+
+            Hud {
+                points: player_points[current_index].1,
+                rank: current_index + 1,
+                players: player_points.len(),
+                points_diff,
+                hp: player_vehicle.hp,
+                hp_fraction: player_vehicle.hp_fraction(cvars),
+                weapon: status.weapon,
+                ammo: status.ammo,
+                ammo_max: status.ammo_max,
+                reserve: status.reserve,
+                ammo_fraction,
+                reload_progress: status.reload_progress,
+                heat: status.heat,
+                gm_time_left,
+                speed: player_vehicle.vel.magnitude(),
+                wind: Vec2f::new(cvars.g_wind_x, cvars.g_wind_y),
+                powerups,
+            }
+        });
+    }
+}
+
+impl Tiles {
+    fn clear(&mut self) {
+        self.x.clear();
+        self.y.clear();
+        self.surface_index.clear();
+        self.angle.clear();
+    }
+}
+
+impl Vehicles {
+    fn clear(&mut self) {
+        self.x.clear();
+        self.y.clear();
+        self.angle.clear();
+        self.turret_angle.clear();
+        self.turret_recoil_x.clear();
+        self.turret_recoil_y.clear();
+        self.veh_type.clear();
+        self.destroyed.clear();
+        self.color.clear();
+    }
+}
+
+impl Projectiles {
+    fn clear(&mut self) {
+        self.x.clear();
+        self.y.clear();
+        self.angle.clear();
+        self.weapon.clear();
+        self.debris.clear();
+        self.z.clear();
+    }
+}
+
+impl Beams {
+    fn clear(&mut self) {
+        self.begin_x.clear();
+        self.begin_y.clear();
+        self.end_x.clear();
+        self.end_y.clear();
+        self.weapon.clear();
+        self.remaining.clear();
+    }
+}
+
+impl Flashes {
+    fn clear(&mut self) {
+        self.x.clear();
+        self.y.clear();
+        self.angle.clear();
+        self.weapon.clear();
+        self.remaining.clear();
+    }
+}
+
+impl Explosions {
+    fn clear(&mut self) {
+        self.x.clear();
+        self.y.clear();
+        self.scale.clear();
+        self.age.clear();
+        self.bfg.clear();
+    }
+}
+
+impl Flags {
+    fn clear(&mut self) {
+        self.x.clear();
+        self.y.clear();
+        self.team.clear();
+        self.carried.clear();
+    }
+}
+
+impl Aim {
+    fn push(&mut self, scr_pos: Vec2f) {
+        self.x.push(scr_pos.x);
+        self.y.push(scr_pos.y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::map;
+
+    use super::*;
+
+    #[test]
+    fn test_fill_reuses_buffers() {
+        let cvars = Cvars {
+            d_seed: 5,
+            ..Cvars::default()
+        };
+        let map = map::tests::load_test_map(&fs::read_to_string("maps/Atrium.map").unwrap());
+        let mut server = Server::new(&cvars, map);
+        let player_handle = server.connect(&cvars, "Player 1");
+        let viewport_size = Vec2f::new(800.0, 600.0);
+
+        let mut data = RenderData::default();
+        data.fill(
+            &cvars,
+            &server,
+            viewport_size,
+            1.0,
+            None,
+            Vec2f::zero(),
+            None,
+            None,
+            None,
+            player_handle,
+        );
+
+        // The view is whole tiles plus one partial on each side at most.
+        let tiles = data.tiles.x.len() + data.walls.x.len();
+        assert!(tiles >= 13 * 10, "{}", tiles);
+        assert!(tiles <= 14 * 11, "{}", tiles);
+        // The local vehicle is in the middle of the view, unless the camera hits the map's edge.
+        assert!(!data.vehicles.x.is_empty());
+        let hud = data.hud.as_ref().unwrap();
+        assert_eq!(hud.players, server.gs.players.len());
+        assert_eq!(hud.hp_fraction, 1.0);
+
+        let tiles_ptr = data.tiles.x.as_ptr();
+        let vehicles_ptr = data.vehicles.x.as_ptr();
+        let vehicles = data.vehicles.x.len();
+        data.fill(
+            &cvars,
+            &server,
+            viewport_size,
+            1.0,
+            None,
+            Vec2f::zero(),
+            None,
+            None,
+            None,
+            player_handle,
+        );
+        assert_eq!(data.tiles.x.len() + data.walls.x.len(), tiles);
+        assert_eq!(data.vehicles.x.len(), vehicles);
+        assert_eq!(data.tiles.x.as_ptr(), tiles_ptr);
+        assert_eq!(data.vehicles.x.as_ptr(), vehicles_ptr);
+    }
+
+    #[test]
+    fn test_shake_stays_in_map() {
+        let cvars = Cvars::default();
+        let map = map::tests::load_test_map(&fs::read_to_string("maps/Atrium.map").unwrap());
+        let mut server = Server::new(&cvars, map);
+        let player_handle = server.connect(&cvars, "Player 1");
+        let viewport_size = Vec2f::new(800.0, 600.0);
+        let vehicle_handle = server.gs.players[player_handle].vehicle.unwrap();
+        server.gs.vehicles[vehicle_handle]
+            .pos
+            .set(Vec2f::new(10.0, 10.0), 0);
+
+        let mut data = RenderData::default();
+        let shake = Vec2f::new(-20.0, 15.0);
+        data.fill(
+            &cvars,
+            &server,
+            viewport_size,
+            1.0,
+            None,
+            shake,
+            None,
+            None,
+            None,
+            player_handle,
+        );
+        assert_eq!(data.camera_top_left, Vec2f::new(0.0, 15.0));
+    }
+}
@@ -1,53 +1,86 @@
 //! Rendering using the macroquad engine.
 
-use std::{cmp::Reverse, str};
+use std::{cmp::Reverse, f64::consts::PI, mem, str};
 
 use macroquad::prelude::*;
 use thunderdome::Index;
 use vek::Clamp;
 
 use crate::{
-    cvars::Cvars,
+    bitmap_font::BitmapFont,
+    camera,
+    cvars::{Cvars, Rgba},
     debugging::{DEBUG_CROSSES, DEBUG_LINES, DEBUG_TEXTS, DEBUG_TEXTS_WORLD},
-    entities::{Ammo, Weapon},
-    game_state::Explosion,
-    map::{F64Ext, Kind, Vec2f, VecExt, TILE_SIZE},
+    entities::{
+        Armor, Facing, PickupKind, PowerupKind, Team, VehicleType, Weapon, RESERVE_UNLIMITED,
+    },
+    fixed,
+    game_state::{DecalKind, MatchState, ParticleKind},
+    map::{F64Ext, Kind, Vec2f, TILE_SIZE},
+    minimap::MinimapLayout,
     mq::{ClientMode, MacroquadClient},
+    palette,
     server::Server,
+    sprites, sys_decals,
+    sys_hazards::HazardState,
+    sys_match, sys_range,
+    sys_threats::ThreatKind,
+    timing::SystemTimings,
 };
 
 // LATER clean up at least some of the casts here
 
 pub fn render(client: &mut MacroquadClient, server: &Server, cvars: &Cvars) {
     client.render_fps.tick(cvars.d_fps_period, server.real_time);
+    client
+        .frame_times
+        .add(cvars.d_frametime_graph_frames, server.real_time_delta);
     let start = get_time();
+    // Taken out so the drawing fns can borrow the client immutably.
+    let mut timings = mem::take(&mut client.draw_timings);
+    timings.start(cvars.d_timing_systems, cvars.d_timing_samples);
+
+    client.tint_vehicles(cvars, server);
+    client.update_cameras(server);
+
+    if cvars.hud_minimap && client.minimap_terrain.is_none() {
+        // Built incrementally after the map loads, there's a placeholder until then.
+        if let Some(pixels) = server.derived.minimap_terrain() {
+            let size = server.map.size();
+            let texture = Texture2D::from_rgba8(size.x as u16, size.y as u16, pixels);
+            texture.set_filter(FilterMode::Nearest);
+            client.minimap_terrain = Some(texture);
+        }
+    }
 
     match client.client_mode {
         ClientMode::Singleplayer { player_handle } => {
-            render_viewport(client, server, cvars, player_handle)
+            fill_render_data(client, server, cvars, player_handle);
+            timings.lap("fill");
+            render_viewport(client, server, cvars, player_handle, None, &mut timings);
+            set_default_camera();
         }
         ClientMode::Splitscreen {
             render_targets,
             player_handles,
         } => {
-            let rect = Rect::new(
-                0.0,
-                0.0,
-                client.viewport_size.x as f32,
-                client.viewport_size.y as f32,
-            );
-            let mut camera = Camera2D::from_display_rect(rect);
-            camera.zoom.y = -camera.zoom.y; // Macroquad bug https://github.com/not-fl3/macroquad/issues/171
-
-            camera.render_target = Some(render_targets.0);
-            set_camera(&camera);
-            clear_background(BLANK);
-            render_viewport(client, server, cvars, player_handles.0);
-
-            camera.render_target = Some(render_targets.1);
-            set_camera(&camera);
-            clear_background(BLANK);
-            render_viewport(client, server, cvars, player_handles.1);
+            for (player_handle, render_target) in [
+                (player_handles.0, render_targets.0),
+                (player_handles.1, render_targets.1),
+            ] {
+                set_viewport_camera(client.viewport_size, 1.0, Some(render_target));
+                clear_background(BLANK);
+                fill_render_data(client, server, cvars, player_handle);
+                timings.lap("fill");
+                render_viewport(
+                    client,
+                    server,
+                    cvars,
+                    player_handle,
+                    Some(render_target),
+                    &mut timings,
+                );
+            }
 
             set_default_camera();
             draw_texture(render_targets.0.texture, 0.0, 0.0, WHITE);
@@ -57,6 +90,8 @@ pub fn render(client: &mut MacroquadClient, server: &Server, cvars: &Cvars) {
     }
 
     render_shared(client, server, cvars);
+    timings.lap("shared");
+    client.draw_timings = timings;
 
     let end = get_time();
     client
@@ -64,88 +99,121 @@ pub fn render(client: &mut MacroquadClient, server: &Server, cvars: &Cvars) {
         .add(cvars.d_timing_samples, end - start);
 }
 
+fn fill_render_data(
+    client: &mut MacroquadClient,
+    server: &Server,
+    cvars: &Cvars,
+    local_player_handle: Index,
+) {
+    let camera_center = client
+        .intro
+        .as_ref()
+        .and_then(|intro| intro.camera_center(local_player_handle, server.real_time))
+        .or_else(|| {
+            client
+                .cameras
+                .get(&local_player_handle)
+                .and_then(|camera| camera.center_override(&server.gs, local_player_handle))
+        });
+    let shake = client
+        .shakes
+        .get_mut(&local_player_handle)
+        .map_or_else(Vec2f::zero, |shake| shake.offset(cvars));
+    client.render_data.fill(
+        cvars,
+        server,
+        client.viewport_size,
+        client.zoom,
+        camera_center,
+        shake,
+        client.prediction.as_ref(),
+        Some(&client.interpolation),
+        Some(&client.recoils),
+        local_player_handle,
+    );
+}
+
 fn render_viewport(
     client: &MacroquadClient,
     server: &Server,
     cvars: &Cvars,
     local_player_handle: Index,
+    render_target: Option<RenderTarget>,
+    timings: &mut SystemTimings,
 ) {
     // This is one long function. A lot of people will tell you that's bad™
     // because they've heard it from other people who think long functions are bad™.
     // Most of those people haven't written a game bigger than snake. Carmack says it's ok so it's ok:
     // http://number-none.com/blow/blog/programming/2014/09/26/carmack-on-inlined-code.html
 
+    let data = &client.render_data;
     let player = &server.gs.players[local_player_handle];
     let player_vehicle = &server.gs.vehicles[player.vehicle.unwrap()];
-    let player_entity_pos = if let Some(gm_handle) = player.guided_missile {
-        server.gs.projectiles[gm_handle].pos
-    } else {
-        player_vehicle.pos
-    };
-
-    // Don't put the camera so close to the edge that it would render area outside the map.
-    // Also properly handle maps smaller than view size. Note they can be smaller along X, Y or both.
-    // Example maps for testing: Joust (2), extra/OK Corral (2)
-    let map_size = server.map.maxs();
-    let view_size = Vec2f::new(
-        client.viewport_size.x.min(map_size.x),
-        client.viewport_size.y.min(map_size.y),
-    );
-    let empty_space_size = client.viewport_size - view_size;
-    let view_pos = empty_space_size / 2.0;
-
-    // Camera center in world coords.
-    let camera_center_min = view_size / 2.0;
-    let camera_center_max = map_size - camera_center_min;
-    let camera_center = player_entity_pos.clamped(camera_center_min, camera_center_max);
-
-    // Position of the camera's top left corner in world coords.
-    let camera_top_left = camera_center - camera_center_min;
-    // Add this to world coords to get screen coords.
-    // Forgetting to do this is a recurring source of bugs.
-    // I've considered making a special type for screen coords (e.g. struct Vec2screen(Vec2f);)
-    // so you couldn't accidentally pass world coords to drawing fns but it turned out to be more work than expected:
-    // - The newtype had to manually impl all the needed operations of the underlying Vec2 type because ops don't autoderef.
-    // - What would be the result of ops that take one world coord and one screen coord? Lots of cases to think about.
-    // - Which type are sizes? Another type? E.g. `center = corner + size/2` makes sense in both screen and world coords.
-    let camera_offset = -camera_top_left + view_pos;
-
-    let top_left_tp = server.map.tile_pos(camera_top_left);
-    let top_left_index = top_left_tp.index;
-    let bg_offset = if cvars.r_align_to_pixels_background {
-        top_left_tp.offset.floor()
-    } else {
-        top_left_tp.offset
-    };
+    let player_vehicle_pos = client.vehicle_pos(server, local_player_handle).unwrap();
+    let view_pos = data.view_pos;
+    let view_size = data.view_size;
+    let camera_offset = data.camera_offset;
+    // The world and world-space HUD are drawn zoomed, in world units, the rest in screen pixels.
+    let zoom = client.zoom;
+    let world_viewport = client.world_viewport_size();
+    set_viewport_camera(client.viewport_size, zoom, render_target);
 
     // Draw non-walls
-    let mut r = top_left_index.y;
-    let mut y = -bg_offset.y;
-    while y < view_size.y {
-        let mut c = top_left_index.x;
-        let mut x = -bg_offset.x;
-        while x < view_size.x {
-            let tile = server.map.col_row(c, r);
-
-            if server.map.surface_of(tile).kind != Kind::Wall {
-                let img = client.imgs_tiles[tile.surface_index];
-                render_tile(img, view_pos.x + x, view_pos.y + y, tile.angle);
-            }
+    let tiles = &data.tiles;
+    for i in 0..tiles.x.len() {
+        let img = client.imgs_tiles[tiles.surface_index[i]];
+        render_tile(img, tiles.x[i], tiles.y[i], tiles.angle[i]);
+    }
 
-            c += 1;
-            x += TILE_SIZE;
+    // Draw hazard states
+    // The state comes from the simulation so everyone sees the crusher telegraph at the same time.
+    for (hazard, state) in server.map.hazards().iter().zip(&server.gs.hazards) {
+        let color = match (server.map.hazard_kind(hazard), state) {
+            (_, HazardState::Idle) => continue,
+            (_, HazardState::Telegraph) => Color::new(1.0, 0.8, 0.0, 0.35),
+            (Kind::DamageFloor, HazardState::Active) => Color::new(1.0, 0.3, 0.0, 0.2),
+            (_, HazardState::Active) => Color::new(1.0, 0.0, 0.0, 0.45),
+            (_, HazardState::Rearming(_)) => Color::new(0.3, 0.3, 0.3, 0.45),
+        };
+        let index = hazard.index;
+        let pos = Vec2f::new(index.x as f64, index.y as f64) * TILE_SIZE;
+        let scr_pos = pos + camera_offset;
+        if scr_pos.x + TILE_SIZE < view_pos.x
+            || scr_pos.y + TILE_SIZE < view_pos.y
+            || scr_pos.x > view_pos.x + view_size.x
+            || scr_pos.y > view_pos.y + view_size.y
+        {
+            continue;
         }
-        r += 1;
-        y += TILE_SIZE;
+        draw_rectangle(
+            scr_pos.x as f32,
+            scr_pos.y as f32,
+            TILE_SIZE as f32,
+            TILE_SIZE as f32,
+            color,
+        );
     }
 
-    // Helper to filter projectiles by weapon.
+    // Helper to filter projectiles by weapon, returns screen position and angle.
+    let projectiles = &data.projectiles;
     let weapon_projectiles = |weapon| {
-        server
-            .gs
-            .projectiles
-            .iter()
-            .filter(move |(_, proj)| proj.weapon == weapon)
+        (0..projectiles.x.len())
+            .filter(move |&i| projectiles.weapon[i] == weapon && !projectiles.debris[i])
+            .map(move |i| {
+                let scr_pos = Vec2f::new(projectiles.x[i], projectiles.y[i]);
+                (scr_pos, projectiles.angle[i])
+            })
+    };
+    // Helper to filter beams by weapon, returns screen positions and the remaining lifetime.
+    let beams = &data.beams;
+    let weapon_beams = |weapon| {
+        (0..beams.begin_x.len())
+            .filter(move |&i| beams.weapon[i] == weapon)
+            .map(move |i| {
+                let scr_begin = Vec2f::new(beams.begin_x[i], beams.begin_y[i]);
+                let scr_end = Vec2f::new(beams.end_x[i], beams.end_y[i]);
+                (scr_begin, scr_end, beams.remaining[i] as f32)
+            })
     };
 
     let outside_view_top_left = view_pos - TILE_SIZE;
@@ -153,6 +221,7 @@ fn render_viewport(
     // Is the object certainly outside camera view?
     // Only works on objects smaller that tile size, which is most.
     // Exceptions are lines and text.
+    // Entities in `data` are already culled.
     let cull = |scr_pos: Vec2f| {
         scr_pos.x < outside_view_top_left.x
             || scr_pos.y < outside_view_top_left.y
@@ -160,14 +229,67 @@ fn render_viewport(
             || scr_pos.y > outside_view_bottom_right.y
     };
 
-    // Draw MGs
-    for (_, mg) in weapon_projectiles(Weapon::Mg) {
-        let scr_pos = mg.pos + camera_offset;
+    // Draw decals, oldest first.
+    let decals = &server.gs.decals;
+    for (i, decal) in decals.iter().enumerate() {
+        let scr_pos = decal.pos + camera_offset;
         if cull(scr_pos) {
             continue;
         }
+        let alpha = sys_decals::decal_alpha(cvars, decals.len(), i) as f32;
+        match decal.kind {
+            DecalKind::Tracks(veh_type) => {
+                // One short line under each side of the vehicle.
+                let color = Color::new(0.15, 0.12, 0.08, 0.4 * alpha);
+                let hitbox = cvars.g_vehicle_hitbox(veh_type);
+                let forward = decal.angle.to_vec2f() * 3.0;
+                let side = (decal.angle + PI / 2.0).to_vec2f();
+                for offset in [hitbox.mins.y + 3.0, hitbox.maxs.y - 3.0] {
+                    let center = scr_pos + side * offset;
+                    render_line(center - forward, center + forward, 3.0, color);
+                }
+            }
+            DecalKind::Scorch => {
+                let radius = cvars.cl_scorch_radius * decal.scale;
+                let color = Color::new(0.05, 0.05, 0.05, 0.35 * alpha);
+                draw_circle(scr_pos.x as f32, scr_pos.y as f32, radius as f32, color);
+            }
+        }
+    }
+
+    // Draw projectile trails - fading out towards the oldest position, broken where they ricocheted.
+    for (handle, trail) in &server.gs.trails {
+        let weapon = match server.gs.projectiles.get(*handle) {
+            Some(projectile) => projectile.weapon,
+            None => continue,
+        };
+        let (length, width, Rgba([r, g, b, a])) = match cvars.r_trail(weapon) {
+            Some(trail) => trail,
+            None => continue,
+        };
+        let newest = trail.samples().rev().take(length);
+        let older = trail.samples().rev().take(length).skip(1);
+        for (i, (end, begin)) in newest.zip(older).enumerate() {
+            if i == 0 && cull(end.pos + camera_offset) {
+                break;
+            }
+            if end.discontinuity {
+                continue;
+            }
+            let alpha = a * (1.0 - i as f32 / (length - 1) as f32);
+            render_line(
+                begin.pos + camera_offset,
+                end.pos + camera_offset,
+                width,
+                Color::new(r, g, b, alpha),
+            );
+        }
+    }
+
+    // Draw MGs
+    for (scr_pos, angle) in weapon_projectiles(Weapon::Mg) {
         // we're drawing from the bullet's position backwards
-        let scr_end = scr_pos - mg.vel.normalized() * cvars.cl_machine_gun_trail_length;
+        let scr_end = scr_pos - angle.to_vec2f() * cvars.cl_machine_gun_trail_length;
         render_line(
             scr_pos,
             scr_end,
@@ -176,49 +298,43 @@ fn render_viewport(
         );
     }
 
+    // Draw vehicle debris
+    for i in (0..projectiles.x.len()).filter(|&i| projectiles.debris[i]) {
+        let size = cvars.cl_debris_size as f32;
+        draw_rectangle(
+            projectiles.x[i] as f32 - size / 2.0,
+            projectiles.y[i] as f32 - size / 2.0,
+            size,
+            size,
+            DARKGRAY,
+        );
+    }
+
     // Draw railguns
-    for beam in &server.gs.rail_beams {
-        let scr_begin = beam.begin + camera_offset;
-        let scr_end = beam.end + camera_offset;
+    for (scr_begin, scr_end, remaining) in weapon_beams(Weapon::Rail) {
         render_line(
             scr_begin,
             scr_end,
             cvars.cl_railgun_trail_thickness,
-            Color::new(0.0, 0.0, 1.0, 1.0),
+            Color::new(0.0, 0.0, 1.0, remaining),
         );
     }
 
     // Draw rockets, homing and guided missiles
-    for (_, proj) in weapon_projectiles(Weapon::Rockets) {
-        let scr_pos = proj.pos + camera_offset;
-        if cull(scr_pos) {
-            continue;
-        }
-        render_img_center(client.img_rocket, scr_pos, proj.vel.to_angle());
+    for (scr_pos, angle) in weapon_projectiles(Weapon::Rockets) {
+        render_img_center(client.img_rocket, scr_pos, angle);
     }
-    for (_, proj) in weapon_projectiles(Weapon::Hm) {
-        let scr_pos = proj.pos + camera_offset;
-        if cull(scr_pos) {
-            continue;
-        }
-        render_img_center(client.img_hm, scr_pos, proj.vel.to_angle());
+    for (scr_pos, angle) in weapon_projectiles(Weapon::Hm) {
+        render_img_center(client.img_hm, scr_pos, angle);
     }
-    for (_, proj) in weapon_projectiles(Weapon::Gm) {
-        let scr_pos = proj.pos + camera_offset;
-        if cull(scr_pos) {
-            continue;
-        }
-        render_img_center(client.img_gm, scr_pos, proj.vel.to_angle());
+    for (scr_pos, angle) in weapon_projectiles(Weapon::Gm) {
+        render_img_center(client.img_gm, scr_pos, angle);
     }
 
     // Draw BFGs
     // client.context.set_fill_style(&"lime".into());
     // client.context.set_stroke_style(&"lime".into());
-    for (_, bfg) in weapon_projectiles(Weapon::Bfg) {
-        let scr_pos = bfg.pos + camera_offset;
-        if cull(scr_pos) {
-            continue;
-        }
+    for (scr_pos, _) in weapon_projectiles(Weapon::Bfg) {
         draw_circle(
             scr_pos.x as f32,
             scr_pos.y as f32,
@@ -226,107 +342,175 @@ fn render_viewport(
             GREEN,
         );
     }
-    for &(src, dest) in &server.gs.bfg_beams {
-        let scr_src = src + camera_offset;
-        let scr_dest = dest + camera_offset;
-        render_line(scr_src, scr_dest, 1.0, GREEN);
+    for (scr_src, scr_dest, remaining) in weapon_beams(Weapon::Bfg) {
+        render_line(
+            scr_src,
+            scr_dest,
+            1.0,
+            Color {
+                a: remaining,
+                ..GREEN
+            },
+        );
     }
 
-    // Draw chassis
-    for (_, vehicle) in server.gs.vehicles.iter() {
-        let scr_pos = vehicle.pos + camera_offset;
+    // Draw pickups
+    for (_, pickup) in server.gs.pickups.iter() {
+        let scr_pos = pickup.pos + camera_offset;
         if cull(scr_pos) {
             continue;
         }
-        let img = if vehicle.destroyed() {
-            client.imgs_wrecks[vehicle.veh_type as usize]
+        let mut color = pickup_color(pickup.kind);
+        let mut outline = BLACK;
+        // Collected crates are dimmed until they respawn.
+        if pickup.respawn.is_some() {
+            color.a = 0.3;
+            outline.a = 0.3;
+        }
+        let (x, y) = (scr_pos.x as f32, scr_pos.y as f32);
+        let radius = cvars.g_pickup_radius(pickup.kind) as f32;
+        match pickup.kind {
+            PickupKind::Armor => {
+                draw_poly(x, y, 4, radius, 0.0, color);
+                draw_poly_lines(x, y, 4, radius, 0.0, 1.0, outline);
+            }
+            PickupKind::Ammo => {
+                let side = radius * 2.0;
+                draw_rectangle(x - radius, y - radius, side, side, color);
+                draw_rectangle_lines(x - radius, y - radius, side, side, 2.0, outline);
+            }
+            PickupKind::Repair => {
+                // A cross on a pad the vehicle drives onto.
+                draw_circle_lines(x, y, radius, 2.0, color);
+                let arm = radius / 2.0;
+                draw_line(x - arm, y, x + arm, y, 4.0, color);
+                draw_line(x, y - arm, x, y + arm, 4.0, color);
+            }
+            PickupKind::Powerup(_) => {
+                draw_poly(x, y, 6, radius, 0.0, color);
+                draw_poly_lines(x, y, 6, radius, 0.0, 2.0, outline);
+            }
+        }
+    }
+
+    // Draw chassis
+    let vehicles = &data.vehicles;
+    for i in 0..vehicles.x.len() {
+        let scr_pos = Vec2f::new(vehicles.x[i], vehicles.y[i]);
+        let veh_type = vehicles.veh_type[i];
+        let img = if vehicles.destroyed[i] {
+            client.imgs_wrecks[veh_type as usize]
         } else {
-            client.imgs_vehicles[vehicle.veh_type as usize * 2]
+            vehicle_imgs(client, vehicles.color[i])[veh_type as usize * 2]
         };
-        render_img_center(img, scr_pos, vehicle.angle);
-        // LATER draw hitboxes
-        // if cvars.d_draw && cvars.d_draw_hitboxes {
-        //     client.context.set_stroke_style(&"yellow".into());
-        //     client.context.begin_path();
-        //     let corners = vehicle.hitbox.corners(scr_pos, vehicle.angle);
-        //     move_to(client, corners[0]);
-        //     line_to(client, corners[1]);
-        //     line_to(client, corners[2]);
-        //     line_to(client, corners[3]);
-        //     client.context.close_path();
-        //     client.context.stroke();
-        // }
+        render_img_center(img, scr_pos, vehicles.angle[i]);
     }
 
     // TODO Draw cow
 
     // Draw turrets
-    for (_, vehicle) in server.gs.vehicles.iter() {
-        if vehicle.destroyed() {
+    for i in 0..vehicles.x.len() {
+        if vehicles.destroyed[i] {
             continue;
         }
 
-        let scr_pos = vehicle.pos + camera_offset;
-        if cull(scr_pos) {
-            continue;
-        }
-
-        let img = client.imgs_vehicles[vehicle.veh_type as usize * 2 + 1];
-        let offset_chassis =
-            vehicle.angle.to_mat2f() * cvars.g_vehicle_turret_offset_chassis(vehicle.veh_type);
-        let turret_scr_pos = scr_pos + offset_chassis;
-        let offset_turret = cvars.g_vehicle_turret_offset_turret(vehicle.veh_type);
+        let scr_pos = Vec2f::new(vehicles.x[i], vehicles.y[i]);
+        let veh_type = vehicles.veh_type[i];
+        let angle = vehicles.angle[i];
+        let img = vehicle_imgs(client, vehicles.color[i])[veh_type as usize * 2 + 1];
+        let offset_chassis = angle.to_mat2f() * cvars.g_vehicle_turret_offset_chassis(veh_type);
+        let recoil = Vec2f::new(vehicles.turret_recoil_x[i], vehicles.turret_recoil_y[i]);
+        let turret_scr_pos = scr_pos + offset_chassis + recoil;
+        let offset_turret = cvars.g_vehicle_turret_offset_turret(veh_type);
         render_img_offset(
             img,
             turret_scr_pos,
-            vehicle.angle + vehicle.turret_angle_current,
+            angle + vehicles.turret_angle[i],
             offset_turret,
         );
     }
 
-    // Draw explosions
-    let iter: Box<dyn Iterator<Item = &Explosion>> = if cvars.r_explosions_reverse_order {
-        Box::new(server.gs.explosions.iter().rev())
-    } else {
-        Box::new(server.gs.explosions.iter())
-    };
-    for explosion in iter {
-        let scr_pos = explosion.pos + camera_offset;
+    // Draw muzzle flashes - a bright burst in front of the barrel, shrinking as it fades.
+    let flashes = &data.flashes;
+    for i in 0..flashes.x.len() {
+        let scr_pos = Vec2f::new(flashes.x[i], flashes.y[i]);
+        let remaining = flashes.remaining[i];
+        let mut size = cvars.cl_muzzle_flash_size * (0.5 + 0.5 * remaining);
+        if flashes.weapon[i].is_heavy() {
+            size *= 2.0;
+        }
+        let tip = scr_pos + flashes.angle[i].to_vec2f() * size * 2.0;
+        let alpha = remaining as f32;
+        let (x, y) = (scr_pos.x as f32, scr_pos.y as f32);
+        draw_circle(x, y, size as f32, Color::new(1.0, 0.7, 0.2, 0.6 * alpha));
+        render_line(scr_pos, tip, size, Color::new(1.0, 0.95, 0.7, alpha));
+    }
+
+    // Draw particles, oldest first.
+    for particle in &server.gs.particles {
+        let scr_pos = particle.pos + camera_offset;
         if cull(scr_pos) {
             continue;
         }
+        let progress = ((server.gs.game_time - particle.spawn_time) / particle.lifetime).min(1.0);
+        let alpha = (1.0 - progress) as f32;
+        let (radius, color) = match particle.kind {
+            // Smoke spreads out as it fades.
+            ParticleKind::Smoke => (
+                particle.size * (1.0 + progress),
+                Color::new(0.5, 0.5, 0.5, 0.6 * alpha),
+            ),
+            ParticleKind::Spark => (particle.size, Color::new(1.0, 0.8, 0.3, alpha)),
+            ParticleKind::Dust => (particle.size, Color::new(0.6, 0.5, 0.35, 0.5 * alpha)),
+        };
+        draw_circle(scr_pos.x as f32, scr_pos.y as f32, radius as f32, color);
+    }
 
-        // It looks like the original animation is made for 30 fps.
-        // Single stepping a recording of the original RecWars explosion in blender:
-        // 13 sprites, 31 frames - examples:
-        //      2,2,3,1,3,3,2,3,2,2,3,2,3
-        //      2,2,2,3,1,3,2,2,3,2,2,3,4
-        // Different each time probably because RecWar's and the recorder's framerate don't match exactly.
-        //
-        // This code produces similar results,
-        // though it might display a single sprite for 4 frames slightly more often.
-        let progress = (server.gs.game_time - explosion.start_time) / cvars.r_explosion_duration;
-        // 13 sprites in the sheet, 100x100 pixels per sprite
-        let frame = (progress * 13.0).floor();
-        let (offset, img);
-        if explosion.bfg {
-            offset = (12.0 - frame) * 100.0;
-            img = client.img_explosion_cyan;
+    // Draw flares - bright dots fading as they burn down.
+    for (_, flare) in server.gs.flares.iter() {
+        let scr_pos = flare.pos + camera_offset;
+        if cull(scr_pos) {
+            continue;
+        }
+        let progress = ((server.gs.game_time - flare.spawn_time) / cvars.g_flare_lifetime).min(1.0);
+        let alpha = (1.0 - progress) as f32;
+        let radius = cvars.cl_flare_size as f32;
+        let (x, y) = (scr_pos.x as f32, scr_pos.y as f32);
+        draw_circle(x, y, radius * 2.0, Color::new(1.0, 0.6, 0.2, 0.3 * alpha));
+        draw_circle(x, y, radius, Color::new(1.0, 1.0, 0.85, alpha));
+    }
+
+    // Draw explosions
+    let explosions = &data.explosions;
+    for i in 0..explosions.x.len() {
+        let scr_pos = Vec2f::new(explosions.x[i], explosions.y[i]);
+        let scale = explosions.scale[i];
+
+        let frame = match sprites::explosion_frame(explosions.bfg[i], explosions.age[i]) {
+            Some(frame) => frame,
+            None => continue,
+        };
+        let img = if explosions.bfg[i] {
+            client.img_explosion_cyan
         } else {
-            offset = frame * 100.0;
-            img = client.img_explosion;
+            client.img_explosion
         };
+        // Scaled around the center.
+        let dest_size = Vec2f::new(frame.w, frame.h) * scale;
+        let corner = scr_pos - dest_size / 2.0;
         draw_texture_ex(
             img,
-            (scr_pos.x - 50.0 * explosion.scale) as f32,
-            (scr_pos.y - 50.0 * explosion.scale) as f32,
+            corner.x as f32,
+            corner.y as f32,
             WHITE,
             DrawTextureParams {
-                dest_size: Some(Vec2::new(
-                    100.0 * explosion.scale as f32,
-                    100.0 * explosion.scale as f32,
+                dest_size: Some(Vec2::new(dest_size.x as f32, dest_size.y as f32)),
+                source: Some(Rect::new(
+                    frame.x as f32,
+                    frame.y as f32,
+                    frame.w as f32,
+                    frame.h as f32,
                 )),
-                source: Some(Rect::new(offset as f32, 0.0, 100.0, 100.0)),
                 ..Default::default()
             },
         );
@@ -334,78 +518,107 @@ fn render_viewport(
 
     // Draw walls
     // They are above explosions and turrets, just like in RecWar.
-    let mut r = top_left_index.y;
-    let mut y = -bg_offset.y;
-    while y < view_size.y {
-        let mut c = top_left_index.x;
-        let mut x = -bg_offset.x;
-        while x < view_size.x {
-            let tile = server.map.col_row(c, r);
-
-            if server.map.surface_of(tile).kind == Kind::Wall {
-                let img = client.imgs_tiles[tile.surface_index];
-                render_tile(img, view_pos.x + x, view_pos.y + y, tile.angle);
-            }
-
-            c += 1;
-            x += TILE_SIZE;
-        }
-        r += 1;
-        y += TILE_SIZE;
+    let walls = &data.walls;
+    for i in 0..walls.x.len() {
+        let img = client.imgs_tiles[walls.surface_index[i]];
+        render_tile(img, walls.x[i], walls.y[i], walls.angle[i]);
     }
 
     // Draw cluster bombs
     // TODO what about shadows (in general)?
     if cvars.r_draw_cluster_bombs {
-        for (_, cb) in weapon_projectiles(Weapon::Cb) {
-            let scr_pos = cb.pos + camera_offset;
-            if cull(scr_pos) {
+        for i in 0..projectiles.x.len() {
+            if projectiles.weapon[i] != Weapon::Cb || projectiles.debris[i] {
                 continue;
             }
-
-            let corner = scr_pos - cvars.cl_cluster_bomb_size / 2.0;
+            let scr_pos = Vec2f::new(projectiles.x[i], projectiles.y[i]);
+            // Higher up in their arc, they're closer to the camera and further from their shadow.
+            let height_scale = 1.0 + projectiles.z[i] * cvars.cl_arc_scale;
+            let size = cvars.cl_cluster_bomb_size * height_scale;
+            let corner = scr_pos - size / 2.0;
             // Tecnically, we should draw all shadows first, then all the projectiles,
             // but actually it barely matters and I think RecWar does it this way too.
             draw_rectangle(
-                (corner.x + cvars.g_cluster_bomb_shadow_x) as f32,
-                (corner.y + cvars.g_cluster_bomb_shadow_y) as f32,
-                cvars.cl_cluster_bomb_size as f32,
-                cvars.cl_cluster_bomb_size as f32,
+                (corner.x + cvars.g_cluster_bomb_shadow_x * height_scale) as f32,
+                (corner.y + cvars.g_cluster_bomb_shadow_y * height_scale) as f32,
+                size as f32,
+                size as f32,
                 Color::new(0.0, 0.0, 0.0, cvars.g_cluster_bomb_shadow_alpha as f32),
             );
             draw_rectangle(
                 corner.x as f32,
                 corner.y as f32,
-                cvars.cl_cluster_bomb_size as f32,
-                cvars.cl_cluster_bomb_size as f32,
+                size as f32,
+                size as f32,
                 Color::new(0.0, 1.0, 1.0, 1.0),
             );
         }
     }
 
+    // Draw flags
+    // LATER sprites
+    let flags = &data.flags;
+    for i in 0..flags.x.len() {
+        let x = flags.x[i] as f32;
+        let y = flags.y[i] as f32;
+        let color = team_color(flags.team[i]);
+        // Carried flags are drawn smaller so the carrier's turret stays visible.
+        let size = if flags.carried[i] { 8.0 } else { 14.0 };
+        draw_line(x, y, x, y - 2.0 * size, 2.0, BLACK);
+        draw_triangle(
+            Vec2::new(x, y - 2.0 * size),
+            Vec2::new(x + 1.5 * size, y - 1.5 * size),
+            Vec2::new(x, y - size),
+            color,
+        );
+    }
+
+    timings.lap("world");
+
     // Draw world-space HUD elements:
 
     // Names
     if cvars.hud_names {
-        for (_, vehicle) in server.gs.vehicles.iter() {
-            let scr_pos = vehicle.pos + camera_offset;
+        for (handle, vehicle) in server.gs.vehicles.iter() {
+            if vehicle.destroyed()
+                || (!cvars.hud_names_self && vehicle.owner == local_player_handle)
+            {
+                continue;
+            }
+            // Follow the sprite, not the authoritative position.
+            let pos = client
+                .interpolation
+                .pose(cvars, handle, server.gs.game_time)
+                .map_or(*vehicle.pos, |(pos, _)| pos);
+            let scr_pos = pos + camera_offset;
             if cull(scr_pos) {
-                // LATER, restrict name length
                 continue;
             }
 
-            let name = &server.gs.players[vehicle.owner].name;
-            let size = measure_text(name, None, cvars.hud_names_font_size as u16, 1.0);
+            let player = &server.gs.players[vehicle.owner];
+            let measure =
+                |text: &str| measure_hud_text(client, cvars, text, cvars.hud_names_font_size).width;
+            let name = truncate_label(&player.name, cvars.hud_names_max_width, measure);
+            let width = f64::from(measure(&name));
+            // Keep names of vehicles at the edge of the screen readable.
+            let x = (scr_pos.x - width / 2.0).clamped(
+                view_pos.x,
+                (view_pos.x + view_size.x - width).max(view_pos.x),
+            );
+            let brightness = cvars.hud_names_brightness as f32;
+            let [r, g, b] = palette::player_rgb(player).unwrap_or([255, 255, 255]);
+            let channel = |c: u8| f32::from(c) / 255.0 * brightness;
             render_text_with_shadow(
+                client,
                 cvars,
-                name,
-                scr_pos.x as f32 - size.width / 2.0,
+                &name,
+                x as f32,
                 (scr_pos.y + cvars.hud_names_y) as f32,
                 cvars.hud_names_font_size,
                 Color::new(
-                    cvars.hud_names_brightness as f32,
-                    cvars.hud_names_brightness as f32,
-                    cvars.hud_names_brightness as f32,
+                    channel(r),
+                    channel(g),
+                    channel(b),
                     cvars.hud_names_alpha as f32,
                 ),
                 cvars.hud_names_shadow_x,
@@ -415,24 +628,78 @@ fn render_viewport(
         }
     }
 
-    // Homing missile indicator
+    let local_threats = || {
+        server
+            .gs
+            .threats
+            .iter()
+            .filter(|threat| threat.player == local_player_handle)
+    };
+
+    // Enemies charging their railgun nearby
+    for threat in local_threats() {
+        if let ThreatKind::RailCharge { .. } = threat.kind {
+            let scr_pos = threat.pos + camera_offset;
+            draw_circle_lines(
+                scr_pos.x as f32,
+                scr_pos.y as f32,
+                cvars.g_hitcircle_radius as f32 * 1.5,
+                2.0,
+                RED,
+            );
+        }
+    }
+
+    // Homing missile indicator - points at the most urgent missile
     // TODO dashed lines (maybe use image)
-    let player_veh_scr_pos = player_vehicle.pos + camera_offset;
+    let player_veh_scr_pos = player_vehicle_pos + camera_offset;
+    let missile_threat =
+        local_threats().find(|threat| matches!(threat.kind, ThreatKind::Missile { .. }));
+    let indicator_color = if missile_threat.is_some() { RED } else { GREEN };
     draw_circle_lines(
         player_veh_scr_pos.x as f32,
         player_veh_scr_pos.y as f32,
         cvars.hud_missile_indicator_radius as f32,
         1.0,
-        GREEN,
+        indicator_color,
     );
-    let dir = 0.0.to_vec2f(); // TODO
-    let end = player_veh_scr_pos + dir * cvars.hud_missile_indicator_radius;
-    render_line(player_veh_scr_pos, end, 1.0, GREEN);
+    if let Some(threat) = missile_threat {
+        let dir = threat.bearing.to_vec2f();
+        let end = player_veh_scr_pos + dir * cvars.hud_missile_indicator_radius;
+        render_line(player_veh_scr_pos, end, 1.0, indicator_color);
+    }
+
+    // Aim preview
+    let aim_color = Color::new(1.0, 1.0, 1.0, cvars.cl_crosshair_alpha as f32);
+    for aim in &data.aims {
+        let aim_points = || (0..aim.x.len()).map(|i| Vec2f::new(aim.x[i], aim.y[i]));
+        if aim.dotted {
+            for scr_pos in aim_points().skip(1) {
+                let size = cvars.cl_crosshair_dot_size as f32;
+                draw_circle(scr_pos.x as f32, scr_pos.y as f32, size, aim_color);
+            }
+        } else {
+            for (begin, end) in aim_points().zip(aim_points().skip(1)) {
+                render_line(begin, end, cvars.cl_crosshair_thickness, aim_color);
+            }
+        }
+        if let Some(hit) = aim_points().next_back().filter(|_| aim.wall_hit) {
+            let size = cvars.cl_crosshair_marker_size;
+            let thickness = cvars.cl_crosshair_thickness;
+            render_line(hit - size, hit + size, thickness, aim_color);
+            render_line(
+                hit + Vec2f::new(-size, size),
+                hit + Vec2f::new(size, -size),
+                thickness,
+                aim_color,
+            );
+        }
+    }
 
     // Spawn location indicator
     let alive_time = server.gs.game_time - player_vehicle.spawn_time;
     if alive_time < cvars.cl_spawn_indicator_duration {
-        let vehicle_scr_pos = player_vehicle.pos + camera_offset;
+        let vehicle_scr_pos = player_vehicle_pos + camera_offset;
 
         // Radius here is distance from the square's center to its side.
         let max_radius = cvars.cl_spawn_indicator_square_side_begin / 2.0;
@@ -453,7 +720,7 @@ fn render_viewport(
         draw_line(
             vehicle_scr_pos.x as f32 + min_radius,
             vehicle_scr_pos.y as f32,
-            client.viewport_size.x as f32,
+            world_viewport.x as f32,
             vehicle_scr_pos.y as f32,
             cvars.cl_spawn_indicator_thickness,
             GREEN,
@@ -470,7 +737,7 @@ fn render_viewport(
             vehicle_scr_pos.x as f32,
             vehicle_scr_pos.y as f32 + min_radius,
             vehicle_scr_pos.x as f32,
-            client.viewport_size.y as f32,
+            world_viewport.y as f32,
             cvars.cl_spawn_indicator_thickness,
             GREEN,
         );
@@ -493,6 +760,8 @@ fn render_viewport(
         }
     }
 
+    timings.lap("world_hud");
+
     // Debug lines and crosses
     // LATER colors (also in other places below)
     //  It would be nice to use MQ's Color struct in debugging but then everything depends on MQ.
@@ -543,182 +812,446 @@ fn render_viewport(
         }
     });
 
-    // Draw screen-space HUD elements:
+    // Debug overlays
+    if cvars.d_draw && cvars.d_draw_grid {
+        let color = Color::new(1.0, 1.0, 1.0, 0.3);
+        let top = view_pos.y as f32;
+        let bottom = (view_pos.y + view_size.y) as f32;
+        let mut x = view_pos.x - data.camera_top_left.x.rem_euclid(TILE_SIZE);
+        while x < view_pos.x + view_size.x {
+            if x >= view_pos.x {
+                draw_line(x as f32, top, x as f32, bottom, 1.0, color);
+            }
+            x += TILE_SIZE;
+        }
+        let left = view_pos.x as f32;
+        let right = (view_pos.x + view_size.x) as f32;
+        let mut y = view_pos.y - data.camera_top_left.y.rem_euclid(TILE_SIZE);
+        while y < view_pos.y + view_size.y {
+            if y >= view_pos.y {
+                draw_line(left, y as f32, right, y as f32, 1.0, color);
+            }
+            y += TILE_SIZE;
+        }
+    }
+    if cvars.d_draw && cvars.d_draw_hitboxes {
+        for (_, vehicle) in server.gs.vehicles.iter() {
+            let scr_pos = *vehicle.pos + camera_offset;
+            if cull(scr_pos) {
+                continue;
+            }
+            let corners = vehicle.hitbox.corners(scr_pos, *vehicle.angle);
+            for i in 0..4 {
+                render_line(corners[i], corners[(i + 1) % 4], 1.0, YELLOW);
+            }
+        }
+    }
+    if cvars.d_draw && cvars.d_draw_velocities {
+        let vehicles = server
+            .gs
+            .vehicles
+            .iter()
+            .map(|(_, vehicle)| (*vehicle.pos, vehicle.vel));
+        let projectiles = server
+            .gs
+            .projectiles
+            .iter()
+            .map(|(_, projectile)| (projectile.pos, projectile.vel));
+        for (pos, vel) in vehicles.chain(projectiles) {
+            let scr_pos = pos + camera_offset;
+            if cull(scr_pos) || vel == Vec2f::zero() {
+                continue;
+            }
+            let scr_end = scr_pos + vel * cvars.d_draw_velocities_scale;
+            render_line(scr_pos, scr_end, 1.0, SKYBLUE);
+            // Arrowhead
+            let back = -vel.normalized() * 5.0;
+            render_line(scr_end, scr_end + back.rotated_z(PI / 6.0), 1.0, SKYBLUE);
+            render_line(scr_end, scr_end + back.rotated_z(-PI / 6.0), 1.0, SKYBLUE);
+        }
+    }
+    if cvars.d_draw && cvars.d_draw_projectile_rays {
+        // Not culled, they can be long enough to cross the view without either end being in it.
+        for &(begin, end) in &server.gs.projectile_rays {
+            render_line(begin + camera_offset, end + camera_offset, 1.0, MAGENTA);
+        }
+    }
 
-    let mut player_points: Vec<_> = server
-        .gs
-        .players
-        .iter()
-        .map(|(index, player)| (index, player.score.points(cvars)))
-        .collect();
-    player_points.sort_by_key(|&(_, points)| Reverse(points));
-
-    // Score
-    let score_pos = hud_pos(view_pos, view_size, cvars.hud_score_x, cvars.hud_score_y);
-    let points = player.score.points(cvars).to_string();
-    render_text_with_shadow(
-        cvars,
-        &points,
-        score_pos.x,
-        score_pos.y,
-        cvars.hud_score_font_size,
-        WHITE,
-        cvars.hud_score_shadow_x,
-        cvars.hud_score_shadow_y,
-        1.0,
-    );
+    timings.lap("debug");
 
-    // Ranking
-    // Original RW shows "current rank / total players (+/- points difference to leader or second)"
-    // as a big but not bold number with a 1px shadow. E.g. "1/3 (+5)" or "2/3 (0)".
-    // There's no special treatement for players with the same number of points.
-    let ranking_pos = hud_pos(
-        view_pos,
-        view_size,
-        cvars.hud_ranking_x,
-        cvars.hud_ranking_y,
-    );
-    let current_index = player_points
-        .iter()
-        .position(|&(handle, _)| handle == local_player_handle)
-        .unwrap();
-    let points_diff = if current_index == 0 {
-        if player_points.len() == 1 {
-            // The player is alone.
-            0
-        } else {
-            player_points[current_index].1 - player_points[1].1
+    // Draw screen-space HUD elements:
+    set_viewport_camera(client.viewport_size, 1.0, render_target);
+    let view_pos = view_pos * zoom;
+    let view_size = view_size * zoom;
+
+    // Damage flash - under the rest of the HUD so it stays readable.
+    if let Some(&(start_time, alpha)) = client.damage_flashes.get(&local_player_handle) {
+        let duration = cvars.hud_damage_flash_duration;
+        let age = server.gs.game_time - start_time;
+        if duration > 0.0 && age < duration {
+            let alpha = alpha * (1.0 - age / duration);
+            let color = Color::new(1.0, 0.0, 0.0, alpha as f32);
+            let x = view_pos.x as f32;
+            let y = view_pos.y as f32;
+            let w = view_size.x as f32;
+            let h = view_size.y as f32;
+            let edge = (cvars.hud_damage_flash_width as f32)
+                .min(w / 2.0)
+                .min(h / 2.0);
+            draw_rectangle(x, y, w, edge, color);
+            draw_rectangle(x, y + h - edge, w, edge, color);
+            draw_rectangle(x, y + edge, edge, h - 2.0 * edge, color);
+            draw_rectangle(x + w - edge, y + edge, edge, h - 2.0 * edge, color);
         }
-    } else {
-        player_points[current_index].1 - player_points[0].1
-    };
-    let ranking = if points_diff > 0 {
-        // Only show the + sign for positive numbers, not 0
-        format!(
-            "{}/{} (+{})",
-            current_index + 1,
-            player_points.len(),
-            points_diff
-        )
-    } else {
-        format!(
-            "{}/{} ({})",
-            current_index + 1,
-            player_points.len(),
-            points_diff
-        )
-    };
-    render_text_with_shadow(
-        cvars,
-        &ranking,
-        ranking_pos.x,
-        ranking_pos.y,
-        cvars.hud_ranking_font_size,
-        WHITE,
-        cvars.hud_ranking_shadow_x,
-        cvars.hud_ranking_shadow_y,
-        1.0,
-    );
+    }
 
-    // Hit points (goes from green to red)
-    // Might wanna use https://crates.io/crates/colorsys if I need more color operations.
-    // Hit points to color (poor man's HSV):
-    // 0.0 = red
-    // 0.0..0.5 -> increase green channel
-    // 0.5 = yellow
-    // 0.5..1.0 -> decrease red channel
-    // 1.0 = green
-    let player_vehicle = &server.gs.vehicles[player.vehicle.unwrap()];
-    let r = 1.0 - (player_vehicle.hp_fraction.clamped(0.5, 1.0) - 0.5) * 2.0;
-    let g = player_vehicle.hp_fraction.clamped(0.0, 0.5) * 2.0;
-    let rgb = Color::new(r as f32, g as f32, 0.0, 1.0);
-    let hp_pos = hud_pos(view_pos, view_size, cvars.hud_hp_x, cvars.hud_hp_y);
-    draw_rectangle(
-        hp_pos.x,
-        hp_pos.y,
-        (cvars.hud_hp_width * player_vehicle.hp_fraction) as f32,
-        cvars.hud_hp_height as f32,
-        rgb,
-    );
-    if cvars.d_draw_text {
-        let hp_number = player_vehicle.hp_fraction * cvars.g_vehicle_hp(player_vehicle.veh_type);
-        let hp_text = format!("{}", hp_number);
+    if let (true, Some(hud)) = (cvars.hud, &data.hud) {
+        // Rear hit marker
+        if cvars.hud_rear_hit && client.rear_hits.contains_key(&local_player_handle) {
+            let text = "rear hit!";
+            let size = measure_hud_text(client, cvars, text, cvars.hud_rear_hit_font_size);
+            let center = view_pos + view_size / 2.0;
+            render_text_with_shadow(
+                client,
+                cvars,
+                text,
+                (center.x + cvars.hud_rear_hit_x) as f32 - size.width / 2.0,
+                (center.y + cvars.hud_rear_hit_y) as f32,
+                cvars.hud_rear_hit_font_size,
+                ORANGE,
+                1.0,
+                1.0,
+                0.5,
+            );
+        }
+
+        // Score
+        let score_pos = hud_pos(view_pos, view_size, cvars.hud_score_x, cvars.hud_score_y);
+        let points = hud.points.to_string();
         render_text_with_shadow(
+            client,
             cvars,
-            &hp_text,
-            hp_pos.x - 25.0,
-            hp_pos.y + cvars.hud_hp_height as f32,
-            16.0,
-            RED,
-            1.0,
+            &points,
+            score_pos.x,
+            score_pos.y,
+            cvars.hud_score_font_size,
+            WHITE,
+            cvars.hud_score_shadow_x,
+            cvars.hud_score_shadow_y,
             1.0,
-            0.5,
         );
-    }
 
-    // Ammo
-    let ammo = player_vehicle.ammos[player.cur_weapon as usize];
-    let ammo_fraction = match ammo {
-        Ammo::Loaded(_ready_time, count) => {
-            let max = cvars.g_weapon_reload_ammo(player.cur_weapon);
-            count as f64 / max as f64
-        }
-        Ammo::Reloading(start, end) => {
-            let max_diff = end - start;
-            let cur_diff = server.gs.game_time - start;
-            cur_diff / max_diff
-        }
-    };
-    let ammo_pos = hud_pos(view_pos, view_size, cvars.hud_ammo_x, cvars.hud_ammo_y);
-    draw_rectangle(
-        ammo_pos.x,
-        ammo_pos.y,
-        (cvars.hud_ammo_width * ammo_fraction) as f32,
-        cvars.hud_ammo_height as f32,
-        YELLOW,
-    );
-    if cvars.d_draw_text {
-        let ammo_number = match ammo {
-            Ammo::Loaded(_ready_time, count) => count,
-            Ammo::Reloading(_start, _end) => 0,
+        // Ranking
+        // Original RW shows "current rank / total players (+/- points difference to leader or second)"
+        // as a big but not bold number with a 1px shadow. E.g. "1/3 (+5)" or "2/3 (0)".
+        // There's no special treatement for players with the same number of points.
+        let ranking_pos = hud_pos(
+            view_pos,
+            view_size,
+            cvars.hud_ranking_x,
+            cvars.hud_ranking_y,
+        );
+        let ranking = if hud.points_diff > 0 {
+            // Only show the + sign for positive numbers, not 0
+            format!("{}/{} (+{})", hud.rank, hud.players, hud.points_diff)
+        } else {
+            format!("{}/{} ({})", hud.rank, hud.players, hud.points_diff)
         };
         render_text_with_shadow(
+            client,
             cvars,
-            &ammo_number.to_string(),
-            ammo_pos.x - 25.0,
-            ammo_pos.y + cvars.hud_ammo_height as f32,
-            16.0,
-            RED,
-            1.0,
+            &ranking,
+            ranking_pos.x,
+            ranking_pos.y,
+            cvars.hud_ranking_font_size,
+            WHITE,
+            cvars.hud_ranking_shadow_x,
+            cvars.hud_ranking_shadow_y,
             1.0,
-            0.5,
         );
-    }
 
-    // Weapon icon
-    // The original shadows were part of the image but this is good enough for now.
-    let weap_img = client.imgs_weapon_icons[player.cur_weapon as usize];
-    let weap_icon_pos = hud_pos(
-        view_pos,
-        view_size,
-        cvars.hud_weapon_icon_x,
-        cvars.hud_weapon_icon_y,
-    ) - Vec2::new(weap_img.width(), weap_img.height()) / 2.0;
-    draw_texture(
-        weap_img,
-        weap_icon_pos.x + cvars.hud_weapon_icon_shadow_x,
-        weap_icon_pos.y + cvars.hud_weapon_icon_shadow_y,
-        Color::new(0.0, 0.0, 0.0, cvars.hud_weapon_icon_shadow_alpha as f32),
-    );
-    draw_texture(weap_img, weap_icon_pos.x, weap_icon_pos.y, WHITE);
+        // Hit points (goes from green to red)
+        // Might wanna use https://crates.io/crates/colorsys if I need more color operations.
+        // Hit points to color (poor man's HSV):
+        // 0.0 = red
+        // 0.0..0.5 -> increase green channel
+        // 0.5 = yellow
+        // 0.5..1.0 -> decrease red channel
+        // 1.0 = green
+        let hp_fraction = hud.hp_fraction;
+        let r = 1.0 - (hp_fraction.clamped(0.5, 1.0) - 0.5) * 2.0;
+        let g = hp_fraction.clamped(0.0, 0.5) * 2.0;
+        let rgb = Color::new(r as f32, g as f32, 0.0, 1.0);
+        let hp_pos = hud_pos(view_pos, view_size, cvars.hud_hp_x, cvars.hud_hp_y);
+        draw_rectangle(
+            hp_pos.x,
+            hp_pos.y,
+            (cvars.hud_hp_width * hp_fraction) as f32,
+            cvars.hud_hp_height as f32,
+            rgb,
+        );
+        if cvars.d_draw && cvars.d_draw_text {
+            let hp_number = fixed::from_milli(hud.hp);
+            let hp_text = format!("{}", hp_number);
+            render_text_with_shadow(
+                client,
+                cvars,
+                &hp_text,
+                hp_pos.x - 25.0,
+                hp_pos.y + cvars.hud_hp_height as f32,
+                16.0,
+                RED,
+                1.0,
+                1.0,
+                0.5,
+            );
+        }
+
+        // Ammo - or the remaining time of the guided missile being controlled
+        let ammo_pos = hud_pos(view_pos, view_size, cvars.hud_ammo_x, cvars.hud_ammo_y);
+        draw_rectangle(
+            ammo_pos.x,
+            ammo_pos.y,
+            (cvars.hud_ammo_width * hud.ammo_fraction) as f32,
+            cvars.hud_ammo_height as f32,
+            if hud.heat.is_some() && hud.gm_time_left.is_none() {
+                RED
+            } else {
+                YELLOW
+            },
+        );
+        if cvars.d_draw && cvars.d_draw_text {
+            let ammo_text = match hud.heat {
+                Some(heat) => format!("{:.0}%", heat * 100.0),
+                None => hud.ammo.to_string(),
+            };
+            render_text_with_shadow(
+                client,
+                cvars,
+                &ammo_text,
+                ammo_pos.x - 25.0,
+                ammo_pos.y + cvars.hud_ammo_height as f32,
+                16.0,
+                RED,
+                1.0,
+                1.0,
+                0.5,
+            );
+        }
+
+        // Armor - a diamond with the front on top, turret in the middle
+        if cvars.hud_armor {
+            let armor_pos = hud_pos(view_pos, view_size, cvars.hud_armor_x, cvars.hud_armor_y);
+            let size = cvars.hud_armor_size as f32;
+            let armor = &player_vehicle.armor;
+            let facings = [
+                (Facing::Front, 0.0, -1.0),
+                (Facing::Side, -1.0, 0.0),
+                (Facing::Side, 1.0, 0.0),
+                (Facing::Rear, 0.0, 1.0),
+                (Facing::Turret, 0.0, 0.0),
+            ];
+            for (facing, x, y) in facings {
+                let color = armor_color(cvars, player_vehicle.veh_type, armor, facing);
+                let x = armor_pos.x + x * size * 2.0;
+                let y = armor_pos.y + y * size * 2.0;
+                draw_poly(x, y, 4, size, 0.0, color);
+            }
+        }
+
+        // Powerups - a hexagon like the pickup with the time left below it
+        if cvars.hud_powerups {
+            let pos = hud_pos(
+                view_pos,
+                view_size,
+                cvars.hud_powerups_x,
+                cvars.hud_powerups_y,
+            );
+            let size = cvars.hud_powerups_size as f32;
+            for (i, &(kind, left)) in hud.powerups.iter().enumerate() {
+                let x = pos.x + i as f32 * cvars.hud_powerups_spacing as f32;
+                let color = powerup_color(kind);
+                draw_poly(x, pos.y, 6, size, 0.0, color);
+                draw_poly_lines(x, pos.y, 6, size, 0.0, 1.0, BLACK);
+                let bar_y = pos.y + size + 3.0;
+                draw_rectangle(x - size, bar_y, 2.0 * size * left as f32, 3.0, color);
+            }
+        }
+
+        // Weapon icon
+        // The original shadows were part of the image but this is good enough for now.
+        let weap_img = client.imgs_weapon_icons[hud.weapon as usize];
+        let weap_icon_pos = hud_pos(
+            view_pos,
+            view_size,
+            cvars.hud_weapon_icon_x,
+            cvars.hud_weapon_icon_y,
+        ) - Vec2::new(weap_img.width(), weap_img.height()) / 2.0;
+        draw_texture(
+            weap_img,
+            weap_icon_pos.x + cvars.hud_weapon_icon_shadow_x,
+            weap_icon_pos.y + cvars.hud_weapon_icon_shadow_y,
+            Color::new(0.0, 0.0, 0.0, cvars.hud_weapon_icon_shadow_alpha as f32),
+        );
+        draw_texture(weap_img, weap_icon_pos.x, weap_icon_pos.y, WHITE);
+
+        // Reload indicator - an arc around the weapon icon, clockwise from the top
+        if cvars.hud_reload_indicator && hud.gm_time_left.is_none() {
+            if let Some(progress) = hud.reload_progress {
+                let center = hud_pos(
+                    view_pos,
+                    view_size,
+                    cvars.hud_weapon_icon_x,
+                    cvars.hud_weapon_icon_y,
+                );
+                let center = Vec2f::new(center.x.into(), center.y.into());
+                let segments = cvars.hud_reload_indicator_segments.max(1);
+                let point = |fraction: f64| {
+                    let angle = -std::f64::consts::FRAC_PI_2 + fraction * std::f64::consts::TAU;
+                    center + angle.to_vec2f() * cvars.hud_reload_indicator_radius
+                };
+                let filled = (progress * f64::from(segments)).ceil() as u32;
+                for i in 0..filled {
+                    let start = f64::from(i) / f64::from(segments);
+                    let end = (f64::from(i + 1) / f64::from(segments)).min(progress);
+                    render_line(
+                        point(start),
+                        point(end),
+                        cvars.hud_reload_indicator_thickness,
+                        YELLOW,
+                    );
+                }
+            }
+        }
+
+        // Weapon name and ammo
+        if cvars.hud_weapon_name {
+            let text = match hud.gm_time_left {
+                Some(left) if cvars.g_guided_missile_time.is_finite() => {
+                    format!("{} {:.1} s", Weapon::Gm.name(), left.max(0.0))
+                }
+                Some(_) => Weapon::Gm.name().to_owned(),
+                None if hud.heat.is_some() && hud.reload_progress.is_some() => {
+                    format!("{} overheated", hud.weapon.name())
+                }
+                None if hud.heat.is_some() => {
+                    let percent = hud.heat.unwrap() * 100.0;
+                    format!("{} heat {:.0}%", hud.weapon.name(), percent)
+                }
+                None if hud.reload_progress.is_some() => {
+                    format!("{} reloading", hud.weapon.name())
+                }
+                None if hud.reserve == RESERVE_UNLIMITED => {
+                    format!("{} {}/{}", hud.weapon.name(), hud.ammo, hud.ammo_max)
+                }
+                None => format!(
+                    "{} {}/{} +{}",
+                    hud.weapon.name(),
+                    hud.ammo,
+                    hud.ammo_max,
+                    hud.reserve
+                ),
+            };
+            let name_pos = hud_pos(
+                view_pos,
+                view_size,
+                cvars.hud_weapon_name_x,
+                cvars.hud_weapon_name_y,
+            );
+            render_text_with_shadow(
+                client,
+                cvars,
+                &text,
+                name_pos.x,
+                name_pos.y,
+                cvars.hud_weapon_name_font_size,
+                WHITE,
+                cvars.hud_weapon_name_shadow_x,
+                cvars.hud_weapon_name_shadow_y,
+                1.0,
+            );
+        }
+
+        // Speed
+        if cvars.hud_speed {
+            let speed = format!("{:.0} px/s", hud.speed);
+            let speed_pos = hud_pos(view_pos, view_size, cvars.hud_speed_x, cvars.hud_speed_y);
+            render_text_with_shadow(
+                client,
+                cvars,
+                &speed,
+                speed_pos.x,
+                speed_pos.y,
+                cvars.hud_speed_font_size,
+                WHITE,
+                cvars.hud_speed_shadow_x,
+                cvars.hud_speed_shadow_y,
+                1.0,
+            );
+        }
+
+        // Wind - an arrow centered on the position
+        if cvars.hud_wind && hud.wind != Vec2f::zero() {
+            let center = hud_pos(view_pos, view_size, cvars.hud_wind_x, cvars.hud_wind_y);
+            let center = Vec2f::new(center.x.into(), center.y.into());
+            let half = hud.wind * cvars.hud_wind_scale / 2.0;
+            let end = center + half;
+            render_line(center - half, end, 2.0, WHITE);
+            let back = -hud.wind.normalized() * 6.0;
+            render_line(end, end + back.rotated_z(PI / 6.0), 2.0, WHITE);
+            render_line(end, end + back.rotated_z(-PI / 6.0), 2.0, WHITE);
+        }
+
+        // Training range statistics
+        if let Some(payload) = sys_range::range_payload(cvars, &server.gs) {
+            let mut lines = vec![format!("DPS {:.1}", payload.dps)];
+            lines.push(match payload.ttk {
+                Some(ttk) => format!("TTK {:.2} s", ttk),
+                None => "TTK -".to_owned(),
+            });
+            lines.push(match payload.grouping_radius {
+                Some(radius) => format!("MG grouping {:.1} px", radius),
+                None => "MG grouping -".to_owned(),
+            });
+            for (weapon, damage) in payload.damage_by_weapon {
+                lines.push(format!("{} {:.0}", weapon.name(), damage));
+            }
+            let range_pos = hud_pos(view_pos, view_size, cvars.hud_range_x, cvars.hud_range_y);
+            for (i, line) in lines.iter().enumerate() {
+                let y = range_pos.y + i as f32 * cvars.hud_range_line_height as f32;
+                render_text_with_shadow(
+                    client,
+                    cvars,
+                    line,
+                    range_pos.x,
+                    y,
+                    cvars.hud_range_font_size,
+                    WHITE,
+                    1.0,
+                    1.0,
+                    1.0,
+                );
+            }
+        }
+    }
 
     // Scoreboard
     if player_vehicle.destroyed() {
+        let mut player_points: Vec<_> = server
+            .gs
+            .players
+            .iter()
+            .map(|(index, player)| (index, player.score.points(cvars)))
+            .collect();
+        player_points.sort_by_key(|&(_, points)| Reverse(points));
+
         let width = cvars.hud_scoreboard_width_name
             + cvars.hud_scoreboard_width_kills
             + cvars.hud_scoreboard_width_deaths
             + cvars.hud_scoreboard_width_points;
-        let height = (server.gs.players.len() + 1) as f32 * cvars.hud_scoreboard_line_height as f32;
+        // Header and the team score line.
+        let lines = server.gs.players.len() + 1 + usize::from(server.gs.ctf.is_some());
+        let height = lines as f32 * cvars.hud_scoreboard_line_height as f32;
         let x_start = view_pos.x as f32 + (view_size.x as f32 - width) / 2.0;
         let mut x = x_start.floor();
         let mut y = view_pos.y as f32 + (view_size.y as f32 - height) / 2.0;
@@ -728,46 +1261,67 @@ fn render_viewport(
         let sx = cvars.hud_scoreboard_shadow_x;
         let sy = cvars.hud_scoreboard_shadow_y;
 
+        if let Some(ctf) = &server.gs.ctf {
+            let captures = format!(
+                "{} {} : {} {}",
+                Team::Red.name(),
+                ctf.captures[Team::Red as usize],
+                ctf.captures[Team::Blue as usize],
+                Team::Blue.name()
+            );
+            render_text_with_shadow(client, cvars, &captures, x, y, fs, WHITE, sx, sy, 1.0);
+            y += cvars.hud_scoreboard_line_height as f32;
+        }
+
         // LATER bold header
-        render_text_with_shadow(cvars, "Name", x, y, fs, WHITE, sx, sy, 1.0);
+        render_text_with_shadow(client, cvars, "Name", x, y, fs, WHITE, sx, sy, 1.0);
         x += cvars.hud_scoreboard_width_name;
-        render_text_with_shadow(cvars, "Kills", x, y, fs, WHITE, sx, sy, 1.0);
+        render_text_with_shadow(client, cvars, "Kills", x, y, fs, WHITE, sx, sy, 1.0);
         x += cvars.hud_scoreboard_width_kills;
-        render_text_with_shadow(cvars, "Deaths", x, y, fs, WHITE, sx, sy, 1.0);
+        render_text_with_shadow(client, cvars, "Deaths", x, y, fs, WHITE, sx, sy, 1.0);
         x += cvars.hud_scoreboard_width_deaths;
-        render_text_with_shadow(cvars, "Points", x, y, fs, WHITE, sx, sy, 1.0);
+        render_text_with_shadow(client, cvars, "Points", x, y, fs, WHITE, sx, sy, 1.0);
 
         y += cvars.hud_scoreboard_line_height as f32;
 
         for (player_handle, points) in player_points {
-            let color = if player_handle == local_player_handle {
-                WHITE
-            } else {
-                Color::new(0.8, 0.8, 0.8, 1.0)
-            };
             let player = &server.gs.players[player_handle];
+            let color = match player.team {
+                Some(team) => team_color(team),
+                None if player_handle == local_player_handle => WHITE,
+                None => Color::new(0.8, 0.8, 0.8, 1.0),
+            };
             let name = &player.name;
             let kills = &player.score.kills.to_string();
             let deaths = &player.score.deaths.to_string();
             let points = &points.to_string();
 
             x = x_start;
-            render_text_with_shadow(cvars, name, x, y, fs, color, sx, sy, 1.0);
+            render_text_with_shadow(client, cvars, name, x, y, fs, color, sx, sy, 1.0);
             x += cvars.hud_scoreboard_width_name;
-            render_text_with_shadow(cvars, kills, x, y, fs, color, sx, sy, 1.0);
+            render_text_with_shadow(client, cvars, kills, x, y, fs, color, sx, sy, 1.0);
             x += cvars.hud_scoreboard_width_kills;
-            render_text_with_shadow(cvars, deaths, x, y, fs, color, sx, sy, 1.0);
+            render_text_with_shadow(client, cvars, deaths, x, y, fs, color, sx, sy, 1.0);
             x += cvars.hud_scoreboard_width_deaths;
-            render_text_with_shadow(cvars, points, x, y, fs, color, sx, sy, 1.0);
+            render_text_with_shadow(client, cvars, points, x, y, fs, color, sx, sy, 1.0);
 
             y += cvars.hud_scoreboard_line_height as f32;
         }
+
+        // Make it clear when the bots' difficulty is being changed.
+        if cvars.g_dda_enabled || server.gs.dda.level != 0.0 {
+            let dda = format!("Bot difficulty adjustment: {:+.2}", server.gs.dda.level);
+            render_text_with_shadow(client, cvars, &dda, x_start, y, fs, WHITE, sx, sy, 1.0);
+        }
     }
 
+    timings.lap("hud");
+
     // Clear background around the map if it's smaller than the screen.
     // This covers up any game entities which were drawn outside view.
     // It would also clear debug text from last frame if macroquad didn't do it automatically
     // (it calls clear_background at the begining of each frame anyway).
+    let empty_space_size = client.viewport_size - view_size;
     if empty_space_size.x > 0.0 {
         // Draw 4 black stripes (rectangles) around view:
         // +----+------------+----+
@@ -811,10 +1365,189 @@ fn render_viewport(
         );
     }
 
+    // Minimap - drawn after the stripes so it's visible even on maps smaller than the screen.
+    if cvars.hud && cvars.hud_minimap {
+        let area_pos = hud_pos(
+            view_pos,
+            view_size,
+            cvars.hud_minimap_x,
+            cvars.hud_minimap_y,
+        );
+        let area_pos = Vec2f::new(area_pos.x as f64, area_pos.y as f64);
+        let area_size = Vec2f::new(cvars.hud_minimap_width, cvars.hud_minimap_height);
+        let layout = MinimapLayout::fit(server.map.maxs(), area_pos, area_size);
+        let alpha = cvars.hud_minimap_alpha as f32;
+
+        if let Some(terrain) = client.minimap_terrain {
+            draw_texture_ex(
+                terrain,
+                layout.pos.x as f32,
+                layout.pos.y as f32,
+                Color::new(1.0, 1.0, 1.0, alpha),
+                DrawTextureParams {
+                    dest_size: Some(Vec2::new(layout.size.x as f32, layout.size.y as f32)),
+                    ..Default::default()
+                },
+            );
+        } else {
+            draw_rectangle(
+                layout.pos.x as f32,
+                layout.pos.y as f32,
+                layout.size.x as f32,
+                layout.size.y as f32,
+                Color::new(0.3, 0.3, 0.3, alpha),
+            );
+            let (done, total, _) = server.derived.derivation_progress();
+            draw_text(
+                &format!("loading {}/{}", done, total),
+                layout.pos.x as f32 + 4.0,
+                layout.pos.y as f32 + 16.0,
+                16.0,
+                Color::new(1.0, 1.0, 1.0, alpha),
+            );
+        }
+
+        let blip_size = cvars.hud_minimap_blip_size as f32;
+        for (_, pickup) in server.gs.pickups.iter() {
+            if pickup.respawn.is_some() {
+                continue;
+            }
+            let mut color = pickup_color(pickup.kind);
+            color.a = alpha;
+            let blip_pos = layout.to_screen(pickup.pos);
+            draw_circle(blip_pos.x as f32, blip_pos.y as f32, blip_size / 2.0, color);
+        }
+
+        for (_, vehicle) in server.gs.vehicles.iter() {
+            let mut color = if vehicle.destroyed() {
+                GRAY
+            } else if vehicle.owner == local_player_handle {
+                GREEN
+            } else {
+                RED
+            };
+            color.a = alpha;
+            let blip_pos = layout.to_screen(*vehicle.pos);
+            draw_rectangle(
+                blip_pos.x as f32 - blip_size / 2.0,
+                blip_pos.y as f32 - blip_size / 2.0,
+                blip_size,
+                blip_size,
+                color,
+            );
+        }
+
+        for threat in local_threats() {
+            if let ThreatKind::RailCharge { .. } = threat.kind {
+                let blip_pos = layout.to_screen(threat.pos);
+                draw_circle_lines(
+                    blip_pos.x as f32,
+                    blip_pos.y as f32,
+                    blip_size * 1.5,
+                    1.0,
+                    RED,
+                );
+            }
+        }
+
+        if let Some(gm_handle) = player.guided_missile {
+            let period = cvars.hud_minimap_gm_blink_period;
+            if server.real_time % period < period / 2.0 {
+                let gm_pos = layout.to_screen(server.gs.projectiles[gm_handle].pos);
+                draw_circle(gm_pos.x as f32, gm_pos.y as f32, blip_size / 2.0, YELLOW);
+            }
+        }
+
+        // What the camera sees
+        let view_rect_pos = layout.to_screen(data.camera_top_left);
+        let view_rect_size = data.view_size * layout.scale;
+        draw_rectangle_lines(
+            view_rect_pos.x as f32,
+            view_rect_pos.y as f32,
+            view_rect_size.x as f32,
+            view_rect_size.y as f32,
+            1.0,
+            Color::new(1.0, 1.0, 1.0, alpha),
+        );
+    }
+
+    timings.lap("minimap");
+
+    // Spectating
+    let spectated = client
+        .cameras
+        .get(&local_player_handle)
+        .and_then(|camera| camera.spectated(local_player_handle));
+    if let Some(spectated) = spectated {
+        let text = format!("SPECTATING {}", server.gs.players[spectated].name);
+        let size = measure_hud_text(client, cvars, &text, cvars.hud_spectating_font_size);
+        render_text_with_shadow(
+            client,
+            cvars,
+            &text,
+            (view_size.x as f32 - size.width) / 2.0 + view_pos.x as f32,
+            (view_pos.y + cvars.hud_spectating_y) as f32,
+            cvars.hud_spectating_font_size,
+            WHITE,
+            1.0,
+            1.0,
+            1.0,
+        );
+    }
+
+    // Countdown and victory screen
+    if cvars.hud {
+        let since_start = server.gs.game_time - server.gs.match_start;
+        let time_left = data.match_time_left.unwrap_or(0.0);
+        let (title, subtitle) = match data.match_state {
+            MatchState::Countdown { .. } => (time_left.ceil().to_string(), None),
+            MatchState::Playing
+                if cvars.g_match_countdown > 0.0
+                    && server.gs.range.is_none()
+                    && since_start < cvars.hud_match_go_duration =>
+            {
+                ("GO!".to_owned(), None)
+            }
+            MatchState::Playing => (String::new(), None),
+            MatchState::Finished { winner, .. } => {
+                let title = match winner {
+                    Some(winner) => format!("{} wins!", sys_match::winner_name(&server.gs, winner)),
+                    None => "Draw".to_owned(),
+                };
+                let subtitle = format!("Next match in {}", time_left.ceil());
+                (title, Some(subtitle))
+            }
+        };
+        let center = view_pos + view_size / 2.0;
+        let mut y = (center.y + cvars.hud_match_y) as f32;
+        for (text, font_size) in [
+            (Some(title), cvars.hud_match_font_size),
+            (subtitle, cvars.hud_match_font_size / 2.0),
+        ] {
+            if let Some(text) = text.filter(|text| !text.is_empty()) {
+                let size = measure_hud_text(client, cvars, &text, font_size);
+                render_text_with_shadow(
+                    client,
+                    cvars,
+                    &text,
+                    center.x as f32 - size.width / 2.0,
+                    y,
+                    font_size,
+                    YELLOW,
+                    2.0,
+                    2.0,
+                    1.0,
+                );
+                y += size.height * 1.5;
+            }
+        }
+    }
+
     // Pause
     if server.paused {
-        let paused_size = measure_text("PAUSED", None, cvars.hud_pause_font_size as u16, 1.0);
+        let paused_size = measure_hud_text(client, cvars, "PAUSED", cvars.hud_pause_font_size);
         render_text_with_shadow(
+            client,
             cvars,
             "PAUSED",
             (view_size.x as f32 - paused_size.width) / 2.0 + view_pos.x as f32,
@@ -838,8 +1571,11 @@ fn render_viewport(
                     // that it's culled overzealously but meh, perf is more important.
                     continue;
                 }
+                // Drawn unzoomed so it stays readable.
+                let scr_pos = scr_pos * zoom;
 
                 render_text_with_shadow(
+                    client,
                     cvars,
                     &text.msg,
                     scr_pos.x as f32,
@@ -853,15 +1589,53 @@ fn render_viewport(
             }
         }
     });
+    timings.lap("world_text");
+}
+
+/// Draw into the viewport (the screen or a splitscreen render target) with the world scaled by `zoom`.
+fn set_viewport_camera(viewport_size: Vec2f, zoom: f64, render_target: Option<RenderTarget>) {
+    let size = camera::world_viewport_size(viewport_size, zoom);
+    let rect = Rect::new(0.0, 0.0, size.x as f32, size.y as f32);
+    let mut camera = Camera2D::from_display_rect(rect);
+    if render_target.is_some() {
+        camera.zoom.y = -camera.zoom.y; // Macroquad bug https://github.com/not-fl3/macroquad/issues/171
+    }
+    camera.render_target = render_target;
+    set_camera(&camera);
 }
 
 fn render_shared(client: &MacroquadClient, server: &Server, cvars: &Cvars) {
     let screen_size = Vec2f::new(screen_width() as f64, screen_height() as f64);
 
+    // Draw kill feed
+    if cvars.hud && cvars.hud_kill_feed {
+        let pos = hud_pos(
+            Vec2f::zero(),
+            screen_size,
+            cvars.hud_kill_feed_x,
+            cvars.hud_kill_feed_y,
+        );
+        for (i, (_, line)) in client.kill_feed.iter().enumerate() {
+            render_text_with_shadow(
+                client,
+                cvars,
+                line,
+                pos.x,
+                pos.y + i as f32 * cvars.hud_kill_feed_line_height as f32,
+                cvars.hud_kill_feed_font_size,
+                WHITE,
+                1.0,
+                1.0,
+                0.5,
+            );
+        }
+    }
+
     // Draw FPS
     if cvars.d_fps {
         let fps_pos = hud_pos(Vec2f::zero(), screen_size, cvars.d_fps_x, cvars.d_fps_y);
         render_text_with_shadow(
+            client,
             cvars,
             &format!(
                 "update FPS: {:.1}   gamelogic FPS: {:.1}   render FPS: {:.1}",
@@ -879,9 +1653,33 @@ fn render_shared(client: &MacroquadClient, server: &Server, cvars: &Cvars) {
         );
     }
 
+    // Draw frame time graph
+    if cvars.d_draw_frametime_graph {
+        let pos = hud_pos(
+            Vec2f::zero(),
+            screen_size,
+            cvars.d_frametime_graph_x,
+            cvars.d_frametime_graph_y,
+        );
+        let scale = cvars.d_frametime_graph_scale as f32 * 1000.0;
+        // Reference lines at 60 and 30 FPS
+        for (fps, color) in [(60.0, GREEN), (30.0, YELLOW)] {
+            let y = pos.y - scale / fps;
+            let width = cvars.d_frametime_graph_frames as f32 * 2.0;
+            draw_line(pos.x, y, pos.x + width, y, 1.0, color);
+        }
+        for (i, frame_time) in client.frame_times.iter().enumerate() {
+            let height = frame_time as f32 * scale;
+            // Spikes stand out even when the graph is tiny.
+            let color = if frame_time > 2.0 / 60.0 { RED } else { WHITE };
+            draw_rectangle(pos.x + i as f32 * 2.0, pos.y - height, 2.0, height, color);
+        }
+    }
+
     // Draw perf info
     if cvars.d_draw && cvars.d_draw_perf {
         render_text_with_shadow(
+            client,
             cvars,
             &format!("last {} frames (in ms):", cvars.d_timing_samples),
             screen_size.x as f32 - 280.0,
@@ -895,6 +1693,7 @@ fn render_shared(client: &MacroquadClient, server: &Server, cvars: &Cvars) {
         if let Some((avg, max)) = server.update_durations.get_stats() {
             let text = format!("update avg: {:.1}, max: {:.1}", avg * 1000.0, max * 1000.0);
             render_text_with_shadow(
+                client,
                 cvars,
                 &text,
                 screen_size.x as f32 - 280.0,
@@ -913,6 +1712,7 @@ fn render_shared(client: &MacroquadClient, server: &Server, cvars: &Cvars) {
                 max * 1000.0
             );
             render_text_with_shadow(
+                client,
                 cvars,
                 &text,
                 screen_size.x as f32 - 280.0,
@@ -931,6 +1731,7 @@ fn render_shared(client: &MacroquadClient, server: &Server, cvars: &Cvars) {
                 max * 1000.0
             );
             render_text_with_shadow(
+                client,
                 cvars,
                 &text,
                 screen_size.x as f32 - 280.0,
@@ -945,6 +1746,7 @@ fn render_shared(client: &MacroquadClient, server: &Server, cvars: &Cvars) {
         if let Some((avg, max)) = client.rest_durations.get_stats() {
             let text = format!("rest avg: {:.1}, max: {:.1}", avg * 1000.0, max * 1000.0);
             render_text_with_shadow(
+                client,
                 cvars,
                 &text,
                 screen_size.x as f32 - 280.0,
@@ -958,10 +1760,50 @@ fn render_shared(client: &MacroquadClient, server: &Server, cvars: &Cvars) {
         }
     }
 
+    // Draw stats
+    if cvars.d_draw && cvars.d_stats {
+        let gs = &server.gs;
+        let counts = format!(
+            "{} frame {} - vehicles: {}, projectiles: {}, particles: {}, effects: {}, pickups: {}",
+            env!("GIT_VERSION"),
+            gs.frame_num,
+            gs.vehicles.len(),
+            gs.projectiles.len(),
+            gs.particles.len(),
+            gs.effects.len(),
+            gs.pickups.len(),
+        );
+        let mut durations = server.system_timings.durations.clone();
+        durations.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        let slowest: Vec<_> = durations
+            .iter()
+            .take(6)
+            .map(|(name, duration)| format!("{} {:.2}", name, duration * 1000.0))
+            .collect();
+        let y = screen_size.y as f32 - 30.0;
+        render_text_with_shadow(
+            client,
+            cvars,
+            &counts,
+            20.0,
+            y - 15.0,
+            16.0,
+            RED,
+            1.0,
+            1.0,
+            0.5,
+        );
+        if !slowest.is_empty() {
+            let timings = format!("slowest systems (ms): {}", slowest.join(", "));
+            render_text_with_shadow(client, cvars, &timings, 20.0, y, 16.0, RED, 1.0, 1.0, 0.5);
+        }
+    }
+
     // Draw last key
     if cvars.d_last_key {
         if let Some(key_code) = client.last_key {
             render_text_with_shadow(
+                client,
                 cvars,
                 &format!("{:?}", key_code),
                 300.0,
@@ -981,11 +1823,78 @@ fn render_shared(client: &MacroquadClient, server: &Server, cvars: &Cvars) {
         let texts = texts.borrow();
         if cvars.d_draw && cvars.d_draw_text {
             for text in texts.iter() {
-                render_text_with_shadow(cvars, text, 20.0, y as f32, 16.0, RED, 1.0, 1.0, 0.5);
+                render_text_with_shadow(
+                    client, cvars, text, 20.0, y as f32, 16.0, RED, 1.0, 1.0, 0.5,
+                );
                 y += cvars.d_draw_text_line_height;
             }
         }
     });
+    for i in 0..cvars.d_draw_text_bench {
+        let text = format!("text bench line {}: the quick brown fox 0123456789", i);
+        render_text_with_shadow(
+            client, cvars, &text, 400.0, y as f32, 16.0, RED, 1.0, 1.0, 0.5,
+        );
+        y += cvars.d_draw_text_line_height;
+    }
+}
+
+/// Color used to draw pickups of `kind`.
+fn pickup_color(kind: PickupKind) -> Color {
+    match kind {
+        PickupKind::Armor => SKYBLUE,
+        PickupKind::Ammo => ORANGE,
+        PickupKind::Repair => LIME,
+        PickupKind::Powerup(kind) => powerup_color(kind),
+    }
+}
+
+fn powerup_color(kind: PowerupKind) -> Color {
+    match kind {
+        PowerupKind::SpeedBoost => GOLD,
+        PowerupKind::RapidFire => RED,
+        PowerupKind::Shield => VIOLET,
+    }
+}
+
+/// Red for the default multiplier (or worse), green for the best achievable with pickups.
+fn armor_color(cvars: &Cvars, veh_type: VehicleType, armor: &Armor, facing: Facing) -> Color {
+    let default = cvars.g_vehicle_facing(veh_type, facing);
+    let range = default - cvars.g_armor_pickup_min;
+    let quality = if range > 0.0 {
+        ((default - armor.get(facing)) / range).clamped(0.0, 1.0)
+    } else {
+        1.0
+    };
+    Color::new(1.0 - quality as f32, quality as f32, 0.0, 1.0)
+}
+
+fn team_color(team: Team) -> Color {
+    let [r, g, b] = palette::team_rgb(team);
+    Color::from_rgba(r, g, b, 255)
+}
+
+/// Chassis and turret images, recolored if they've been created for this color already.
+fn vehicle_imgs(client: &MacroquadClient, color: Option<[u8; 3]>) -> &[Texture2D] {
+    color
+        .and_then(|color| client.imgs_vehicles_tinted.get(&color))
+        .unwrap_or(&client.imgs_vehicles)
+}
+
+/// Cut the text off with "..." so it's at most `max_width` wide according to `measure`.
+fn truncate_label(text: &str, max_width: f32, measure: impl Fn(&str) -> f32) -> String {
+    if measure(text) <= max_width {
+        return text.to_owned();
+    }
+    let mut end = text.len();
+    while end > 0 {
+        end = text[..end].char_indices().next_back().unwrap().0;
+        let label = format!("{}...", &text[..end]);
+        if measure(&label) <= max_width {
+            return label;
+        }
+    }
+    "...".to_owned()
 }
 
 /// Place the image's *center* at `scr_pos`,
@@ -1049,8 +1958,31 @@ fn render_line(src: Vec2f, dest: Vec2f, thickness: f64, color: Color) {
     );
 }
 
+/// The bitmap font if it's enabled and loaded.
+fn bitmap_font<'a>(client: &'a MacroquadClient, cvars: &Cvars) -> Option<&'a BitmapFont> {
+    client.bitmap_font.as_ref().filter(|_| cvars.cl_bitmap_font)
+}
+
+/// Size of text drawn by `render_text_with_shadow`, for centered and right-aligned HUD elements.
+fn measure_hud_text(
+    client: &MacroquadClient,
+    cvars: &Cvars,
+    text: &str,
+    font_size: f64,
+) -> TextDimensions {
+    match bitmap_font(client, cvars) {
+        Some(font) => font
+            .descriptor
+            .measure(text, font.descriptor.scale(font_size)),
+        None => measure_text(text, None, font_size as u16, 1.0),
+    }
+}
+
+/// Draw text with the bitmap font (see `cl_bitmap_font`) or macroquad's `draw_text`.
+/// Only the bitmap font supports multiple lines.
 #[allow(clippy::too_many_arguments)]
 fn render_text_with_shadow(
+    client: &MacroquadClient,
     cvars: &Cvars,
     text: &str,
     mut x: f32,
@@ -1065,16 +1997,19 @@ fn render_text_with_shadow(
         x = x.floor();
         y = y.floor();
     }
+    let font = bitmap_font(client, cvars);
+    let draw = |x, y, color| match font {
+        Some(font) => font.draw_text(text, x, y, font.descriptor.scale(font_size), color),
+        None => draw_text(text, x, y, font_size as f32, color),
+    };
     if shadow_offset_x != 0.0 || shadow_offset_y != 0.0 {
-        draw_text(
-            text,
+        draw(
             x + shadow_offset_x,
             y + shadow_offset_y,
-            font_size as f32,
             Color::new(0.0, 0.0, 0.0, shadow_alpha as f32),
         );
     }
-    draw_text(text, x, y, font_size as f32, color);
+    draw(x, y, color);
 }
 
 /// If x or y are negative, count them from the right or bottom respectively.
@@ -1088,3 +2023,18 @@ fn hud_pos(rect_pos: Vec2f, rect_size: Vec2f, mut x: f64, mut y: f64) -> Vec2 {
     }
     Vec2::new((rect_pos.x + x) as f32, (rect_pos.y + y) as f32)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_label() {
+        let measure = |text: &str| text.chars().count() as f32;
+        assert_eq!(truncate_label("short", 10.0, measure), "short");
+        assert_eq!(truncate_label("exactly10!", 10.0, measure), "exactly10!");
+        assert_eq!(truncate_label("much too long", 10.0, measure), "much to...");
+        assert_eq!(truncate_label("čćžšđčćžšđčćž", 6.0, measure), "čćž...");
+        assert_eq!(truncate_label("name", 2.0, measure), "...");
+    }
+}
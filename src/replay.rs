@@ -0,0 +1,602 @@
+//! Deterministic replays for debugging.
+//!
+//! A replay doesn't store any game state, it's a log of everything the server did
+//! which depends on the outside world: inputs, gamelogic ticks with their game time
+//! and the `gs` / `gs_fixed` juggling of `TickrateMode::FixedOrSmaller`.
+//! Together with the cvars (which include the seed) and the map, this is enough
+//! to reproduce the match exactly. Checksums of the game state are stored periodically
+//! so playback can detect where it diverged.
+//!
+//! Cvars and loadouts changed during the match are not recorded.
+//!
+//! Format (all numbers little endian, strings are a u16 length followed by UTF-8 bytes):
+//! - magic `RWRP`, version u8
+//! - map path
+//! - u32 number of cvars, then name and value (as a string) of each
+//! - u8 number of players, then the name and vehicle (u8, 255 for random) of each in order of connection
+//! - events until the end, each is a u8 tag followed by its data
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    hash::Hasher,
+};
+
+use fnv::FnvHasher;
+use rand::RngCore;
+use thunderdome::Index;
+
+use crate::{
+    cvars::Cvars,
+    entities::VehicleType,
+    game_state::{GameState, Input},
+};
+
+const MAGIC: &[u8; 4] = b"RWRP";
+const VERSION: u8 = 4;
+
+const TAG_SNAPSHOT: u8 = 0;
+const TAG_INPUT: u8 = 1;
+const TAG_TICK: u8 = 2;
+const TAG_RESTORE: u8 = 3;
+const TAG_SAVE: u8 = 4;
+const TAG_CHECKSUM: u8 = 5;
+
+const VEHICLE_RANDOM: u8 = 255;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub map_path: String,
+    /// Name and value of every cvar when the recording started.
+    pub cvars: Vec<(String, String)>,
+    /// Players who are not bots, in order of connection.
+    pub players: Vec<String>,
+    /// The vehicle each of `players` chose when connecting, `None` for random.
+    pub vehicles: Vec<Option<VehicleType>>,
+    pub events: Vec<Event>,
+}
+
+/// Everything the server does that can't be derived from the game state alone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    /// `Server::snapshot_inputs` - once per rendered frame.
+    Snapshot,
+    /// A player's input changed, `player` is the index into `Replay::players`.
+    Input { player: u8, input: Input },
+    /// A gamelogic tick ending at `game_time`.
+    Tick { game_time: f64 },
+    /// `gs` was reset to `gs_fixed`.
+    Restore,
+    /// `gs` was saved into `gs_fixed`.
+    Save,
+    /// Checksum of `gs` after the previous tick.
+    Checksum(u64),
+}
+
+impl Replay {
+    pub fn new(
+        cvars: &Cvars,
+        map_path: &str,
+        players: Vec<String>,
+        vehicles: Vec<Option<VehicleType>>,
+    ) -> Self {
+        Self {
+            map_path: map_path.to_owned(),
+            cvars: cvars.to_pairs(),
+            players,
+            vehicles,
+            events: Vec::new(),
+        }
+    }
+
+    /// Set all cvars to the values they had when recording.
+    pub fn apply_cvars(&self, cvars: &mut Cvars) -> Result<(), String> {
+        for (name, value) in &self.cvars {
+            cvars.set_str(name, value)?;
+        }
+        Ok(())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(MAGIC);
+        buf.push(VERSION);
+        write_str(&mut buf, &self.map_path);
+        buf.extend_from_slice(&(self.cvars.len() as u32).to_le_bytes());
+        for (name, value) in &self.cvars {
+            write_str(&mut buf, name);
+            write_str(&mut buf, value);
+        }
+        buf.push(self.players.len() as u8);
+        for (name, veh_type) in self.players.iter().zip(&self.vehicles) {
+            write_str(&mut buf, name);
+            buf.push(veh_type.map_or(VEHICLE_RANDOM, |veh_type| veh_type as u8));
+        }
+        for event in &self.events {
+            match *event {
+                Event::Snapshot => buf.push(TAG_SNAPSHOT),
+                Event::Input { player, input } => {
+                    buf.push(TAG_INPUT);
+                    buf.push(player);
+                    buf.extend_from_slice(&input.to_bits().to_le_bytes());
+                }
+                Event::Tick { game_time } => {
+                    buf.push(TAG_TICK);
+                    buf.extend_from_slice(&game_time.to_le_bytes());
+                }
+                Event::Restore => buf.push(TAG_RESTORE),
+                Event::Save => buf.push(TAG_SAVE),
+                Event::Checksum(checksum) => {
+                    buf.push(TAG_CHECKSUM);
+                    buf.extend_from_slice(&checksum.to_le_bytes());
+                }
+            }
+        }
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, ReplayError> {
+        let mut reader = Reader { data, pos: 0 };
+        if reader.bytes(4)? != MAGIC {
+            return Err(reader.error("not a replay"));
+        }
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(reader.error(format!("unsupported version {}", version)));
+        }
+        let map_path = reader.str()?;
+        let cvars_cnt = reader.u32()?;
+        let mut cvars = Vec::new();
+        for _ in 0..cvars_cnt {
+            cvars.push((reader.str()?, reader.str()?));
+        }
+        let players_cnt = reader.u8()?;
+        let mut players = Vec::new();
+        let mut vehicles = Vec::new();
+        for _ in 0..players_cnt {
+            players.push(reader.str()?);
+            let veh_type = match reader.u8()? {
+                VEHICLE_RANDOM => None,
+                num => Some(
+                    VehicleType::n(num)
+                        .ok_or_else(|| reader.error(format!("unknown vehicle {}", num)))?,
+                ),
+            };
+            vehicles.push(veh_type);
+        }
+        let mut events = Vec::new();
+        while reader.pos < data.len() {
+            let event = match reader.u8()? {
+                TAG_SNAPSHOT => Event::Snapshot,
+                TAG_INPUT => {
+                    let player = reader.u8()?;
+                    if player >= players_cnt {
+                        return Err(reader.error(format!("unknown player {}", player)));
+                    }
+                    let input = Input::from_bits(reader.u64()?);
+                    Event::Input { player, input }
+                }
+                TAG_TICK => Event::Tick {
+                    game_time: f64::from_bits(reader.u64()?),
+                },
+                TAG_RESTORE => Event::Restore,
+                TAG_SAVE => Event::Save,
+                TAG_CHECKSUM => Event::Checksum(reader.u64()?),
+                tag => return Err(reader.error(format!("unknown event {}", tag))),
+            };
+            events.push(event);
+        }
+        Ok(Self {
+            map_path,
+            cvars,
+            players,
+            vehicles,
+            events,
+        })
+    }
+}
+
+/// A replay being recorded by the server.
+#[derive(Debug, Clone)]
+pub struct Recording {
+    pub replay: Replay,
+    /// Handles of `Replay::players` in this server.
+    pub player_handles: Vec<Index>,
+}
+
+impl Recording {
+    pub fn new(replay: Replay, player_handles: Vec<Index>) -> Self {
+        Self {
+            replay,
+            player_handles,
+        }
+    }
+
+    pub fn player_index(&self, player_handle: Index) -> u8 {
+        self.player_handles
+            .iter()
+            .position(|&handle| handle == player_handle)
+            .expect("Player connected after recording started") as u8
+    }
+}
+
+/// A replay being played back by the server.
+#[derive(Debug, Clone)]
+pub struct Playback {
+    pub replay: Replay,
+    /// Handles of `Replay::players` in this server.
+    pub player_handles: Vec<Index>,
+    /// Index of the next event to process.
+    pub next: usize,
+    /// The gamelogic frame after which the first checksum didn't match.
+    pub mismatch: Option<u64>,
+}
+
+impl Playback {
+    pub fn new(replay: Replay, player_handles: Vec<Index>) -> Self {
+        Self {
+            replay,
+            player_handles,
+            next: 0,
+            mismatch: None,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.replay.events.len()
+    }
+}
+
+/// Error while decoding a replay, `pos` is the byte offset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayError {
+    pub pos: usize,
+    pub msg: String,
+}
+
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "byte {}: {}", self.pos, self.msg)
+    }
+}
+
+impl Error for ReplayError {}
+
+/// Hash of the parts of the game state that matter for gameplay.
+///
+/// Floats are hashed by their bits so any difference, however small, is detected.
+pub fn checksum(gs: &GameState) -> u64 {
+    let mut hasher = FnvHasher::default();
+    hasher.write_u64(gs.frame_num);
+    hasher.write_u64(gs.game_time.to_bits());
+    // Cloning so we don't advance the real RNGs.
+    for stream in gs.rng.streams() {
+        hasher.write_u64(stream.clone().next_u64());
+    }
+    for (handle, player) in gs.players.iter() {
+        hasher.write_u64(handle.to_bits());
+        hasher.write_u64(player.input.to_bits());
+        hasher.write_i32(player.score.kills);
+        hasher.write_i32(player.score.deaths);
+        hasher.write_i32(player.score.suicides);
+    }
+    for (handle, vehicle) in gs.vehicles.iter() {
+        hasher.write_u64(handle.to_bits());
+        hasher.write_u64(vehicle.pos.x.to_bits());
+        hasher.write_u64(vehicle.pos.y.to_bits());
+        hasher.write_u64(vehicle.vel.x.to_bits());
+        hasher.write_u64(vehicle.vel.y.to_bits());
+        hasher.write_u64(vehicle.angle.to_bits());
+        hasher.write_u64(vehicle.turret_angle_current.to_bits());
+        hasher.write_i64(vehicle.hp);
+    }
+    for (handle, projectile) in gs.projectiles.iter() {
+        hasher.write_u64(handle.to_bits());
+        hasher.write_u64(projectile.pos.x.to_bits());
+        hasher.write_u64(projectile.pos.y.to_bits());
+        hasher.write_u64(projectile.vel.x.to_bits());
+        hasher.write_u64(projectile.vel.y.to_bits());
+    }
+    hasher.finish()
+}
+
+fn write_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u16).to_le_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, msg: impl Into<String>) -> ReplayError {
+        ReplayError {
+            pos: self.pos,
+            msg: msg.into(),
+        }
+    }
+
+    fn bytes(&mut self, cnt: usize) -> Result<&'a [u8], ReplayError> {
+        if self.data.len() - self.pos < cnt {
+            return Err(self.error("unexpected end of data"));
+        }
+        let bytes = &self.data[self.pos..self.pos + cnt];
+        self.pos += cnt;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, ReplayError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ReplayError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, ReplayError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, ReplayError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn str(&mut self) -> Result<String, ReplayError> {
+        let len = self.u16()? as usize;
+        let start = self.pos;
+        let bytes = self.bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| ReplayError {
+            pos: start,
+            msg: "invalid UTF-8".to_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::{
+        cvars::TickrateMode,
+        map::{self, Vec2f},
+        server::Server,
+    };
+
+    fn new_server(cvars: &Cvars) -> Server {
+        let map = map::tests::load_test_map(&fs::read_to_string("maps/Atrium.map").unwrap());
+        Server::new(cvars, map)
+    }
+
+    fn scripted_input(frame: usize) -> Input {
+        Input {
+            up: frame % 200 < 150,
+            left: frame % 90 < 20,
+            right: frame % 130 > 100,
+            turret_left: frame % 70 < 10,
+            fire: frame % 40 < 25,
+            next_weapon: frame % 300 == 0,
+            ..Input::new()
+        }
+    }
+
+    /// Returns the replay and positions of all vehicles at the end.
+    fn record(
+        cvars: &Cvars,
+        frames: usize,
+        veh_type: Option<VehicleType>,
+    ) -> (Replay, Vec<(Index, Vec2f)>) {
+        let mut server = new_server(cvars);
+        let player_handle = server.connect_with_vehicle(cvars, "Player 1", veh_type);
+        server.start_recording(cvars, "maps/Atrium.map");
+        let mut real_time = 0.0;
+        for frame in 0..frames {
+            // Uneven frame times so the tickrate modes have leftover time to deal with.
+            real_time += if frame % 3 == 0 { 0.011 } else { 0.019 };
+            server.snapshot_inputs();
+            server.input(player_handle, scripted_input(frame));
+            server.update(cvars, real_time);
+        }
+        let data = server.stop_recording();
+        let replay = Replay::decode(&data).unwrap();
+        (replay, positions(&server))
+    }
+
+    fn play(cvars: &Cvars, replay: Replay) -> (Playback, Vec<(Index, Vec2f)>) {
+        let mut server = new_server(cvars);
+        for (name, &veh_type) in replay.players.iter().zip(&replay.vehicles) {
+            server.connect_with_vehicle(cvars, name, veh_type);
+        }
+        server.play_replay(replay);
+        while !server.playback.as_ref().unwrap().is_finished() {
+            // Live input and time are ignored.
+            server.snapshot_inputs();
+            server.input(
+                server.playback.as_ref().unwrap().player_handles[0],
+                Input::new(),
+            );
+            server.update(cvars, 0.0);
+        }
+        let positions = positions(&server);
+        (server.playback.unwrap(), positions)
+    }
+
+    fn positions(server: &Server) -> Vec<(Index, Vec2f)> {
+        server
+            .gs
+            .vehicles
+            .iter()
+            .map(|(handle, vehicle)| (handle, *vehicle.pos))
+            .collect()
+    }
+
+    #[test]
+    fn test_replay_identical() {
+        for mode in [
+            TickrateMode::Synchronized,
+            TickrateMode::FixedOrSmaller,
+            TickrateMode::Lockstep,
+        ] {
+            let cvars = Cvars {
+                d_seed: 42,
+                sv_tickrate_mode: mode,
+                ..Cvars::default()
+            };
+            let (replay, expected) = record(&cvars, 1000, None);
+            assert_eq!(replay.players, ["Player 1"]);
+            assert_eq!(replay.vehicles, [None]);
+            let ticks = replay
+                .events
+                .iter()
+                .filter(|event| matches!(event, Event::Tick { .. }))
+                .count();
+            assert!(ticks >= 1000);
+            assert!(replay
+                .events
+                .iter()
+                .any(|event| matches!(event, Event::Checksum(_))));
+
+            let (playback, positions) = play(&cvars, replay);
+            assert_eq!(playback.mismatch, None);
+            assert_eq!(positions, expected);
+        }
+    }
+
+    #[test]
+    fn test_replay_chosen_vehicle() {
+        // A chosen vehicle skips the random pick so playback has to choose the same one.
+        let cvars = Cvars {
+            d_seed: 42,
+            ..Cvars::default()
+        };
+        let (replay, expected) = record(&cvars, 300, Some(VehicleType::Hummer));
+        let replay = Replay::decode(&replay.encode()).unwrap();
+        assert_eq!(replay.vehicles, [Some(VehicleType::Hummer)]);
+
+        let (playback, positions) = play(&cvars, replay);
+        assert_eq!(playback.mismatch, None);
+        assert_eq!(positions, expected);
+    }
+
+    #[test]
+    fn test_fire_limits_default_checksum() {
+        // Recorded before cooldown groups and the fire rate cap existed,
+        // they must not change anything unless configured.
+        // Re-recorded without any gameplay changes when inputs grew to 64 bits.
+        // Re-recorded when hp became fixed-point - the checksum hashes hp so it changed
+        // but scores, deaths, hp (to 3 decimals) and positions every 100 frames were identical.
+        // Re-recorded when bots got personas - they pick different vehicles and have different skills.
+        // Re-recorded when projectiles got removed after leaving the map - cluster bombs
+        // flying over its edge no longer explode outside it.
+        // Re-recorded when vehicles started sliding along walls instead of bouncing off.
+        // Re-recorded when velocity into walls started stopping instead of bouncing back.
+        // Re-recorded when the RNG got split into streams - every random draw changed.
+        // Re-recorded when vehicles got lateral friction and tanks turn differently.
+        // Re-recorded when badly damaged bots started taking cover.
+        // Re-recorded when hovercraft got separate movement stats over water.
+        // Re-recorded when bots started steering their guided missiles.
+        // Re-recorded when cluster bombs started flying in an arc and exploding when they land.
+        // Re-recorded when bots' aim error and reaction time went back to 0 by default.
+        // Re-recorded when guided missiles stopped detonating on fire by default
+        // and fuses went back to exploding only after their time.
+        let cvars = Cvars {
+            d_seed: 42,
+            g_match_countdown: 0.0,
+            ..Cvars::default()
+        };
+        let (replay, _) = record(&cvars, 1000, None);
+        let last = replay
+            .events
+            .iter()
+            .rev()
+            .find_map(|event| match event {
+                Event::Checksum(checksum) => Some(*checksum),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(last, 13143497499842380541);
+    }
+
+    #[test]
+    fn test_identical_runs_identical_checksums() {
+        // Hp is hashed exactly so any divergence would show up here.
+        let cvars = Cvars {
+            d_seed: 7,
+            ..Cvars::default()
+        };
+        let checksums = |replay: &Replay| -> Vec<u64> {
+            replay
+                .events
+                .iter()
+                .filter_map(|event| match event {
+                    Event::Checksum(checksum) => Some(*checksum),
+                    _ => None,
+                })
+                .collect()
+        };
+        let (first, _) = record(&cvars, 3000, None);
+        let (second, _) = record(&cvars, 3000, None);
+        assert!(checksums(&first).len() > 10);
+        assert_eq!(checksums(&first), checksums(&second));
+    }
+
+    #[test]
+    fn test_replay_divergence() {
+        let cvars = Cvars {
+            d_seed: 42,
+            g_match_countdown: 0.0,
+            ..Cvars::default()
+        };
+        let (mut replay, _) = record(&cvars, 300, None);
+
+        // Drop the first input so the player doesn't move at all.
+        let first_input = replay
+            .events
+            .iter()
+            .position(|event| matches!(event, Event::Input { .. }))
+            .unwrap();
+        replay.events.remove(first_input);
+
+        let (playback, _) = play(&cvars, replay);
+        assert_eq!(playback.mismatch, Some(cvars.d_replay_checksum_interval));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let cvars = Cvars::default();
+        let replay = Replay::new(
+            &cvars,
+            "maps/Atrium.map",
+            vec!["Player 1".to_owned()],
+            vec![Some(VehicleType::Tank)],
+        );
+        let data = replay.encode();
+        assert_eq!(Replay::decode(&data).unwrap(), replay);
+
+        assert_eq!(Replay::decode(b"nope").unwrap_err().msg, "not a replay");
+        let err = Replay::decode(&data[..data.len() - 1]).unwrap_err();
+        assert_eq!(err.msg, "unexpected end of data");
+        let mut bad_event = data.clone();
+        bad_event.push(42);
+        assert_eq!(
+            Replay::decode(&bad_event).unwrap_err(),
+            ReplayError {
+                pos: data.len() + 1,
+                msg: "unknown event 42".to_owned(),
+            }
+        );
+        // The last byte is the player's vehicle.
+        let mut bad_vehicle = data.clone();
+        *bad_vehicle.last_mut().unwrap() = 7;
+        assert_eq!(
+            Replay::decode(&bad_vehicle).unwrap_err(),
+            ReplayError {
+                pos: data.len(),
+                msg: "unknown vehicle 7".to_owned(),
+            }
+        );
+    }
+}
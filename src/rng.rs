@@ -0,0 +1,129 @@
+//! Deterministic random numbers for the gamelogic, split into independent streams.
+//!
+//! Every stream is derived from one master seed (`d_seed`) so a match can be reproduced,
+//! but each subsystem draws from its own so adding or removing draws in one
+//! doesn't change what the others get - replays and lockstep stay in sync
+//! as long as the subsystem which changed doesn't affect the others.
+
+use rand::{prelude::SmallRng, Error, RngCore, SeedableRng};
+
+/// The streams implement `RngCore` so systems use them through `rand::Rng` as usual.
+#[derive(Debug, Clone)]
+pub struct GameRng {
+    /// What all the streams were derived from.
+    pub seed: u64,
+    /// Weapon spread (see `Cvars::g_projectile_params`), machine gun ricochets,
+    /// homing missiles choosing flares.
+    pub weapon_spread: Stream,
+    /// When projectiles with a fuse spread (cluster bombs) explode.
+    pub cb_timing: Stream,
+    /// Bot decisions and aim error.
+    pub ai: Stream,
+    /// Vehicle debris.
+    pub effects: Stream,
+    /// Spawn points, random vehicles, pickup placement and choosing bots.
+    pub world: Stream,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            weapon_spread: stream(seed, 0),
+            cb_timing: stream(seed, 1),
+            ai: stream(seed, 2),
+            effects: stream(seed, 3),
+            world: stream(seed, 4),
+        }
+    }
+
+    /// Restart all streams from a new master seed.
+    pub fn reseed(&mut self, seed: u64) {
+        *self = Self::new(seed);
+    }
+
+    /// All streams in a fixed order, for snapshots, checksums and `trace`.
+    pub fn streams(&self) -> [&Stream; 5] {
+        [
+            &self.weapon_spread,
+            &self.cb_timing,
+            &self.ai,
+            &self.effects,
+            &self.world,
+        ]
+    }
+
+    pub fn streams_mut(&mut self) -> [&mut Stream; 5] {
+        [
+            &mut self.weapon_spread,
+            &mut self.cb_timing,
+            &mut self.ai,
+            &mut self.effects,
+            &mut self.world,
+        ]
+    }
+}
+
+/// Names of the streams in the order of `GameRng::streams`.
+pub const STREAM_NAMES: [&str; 5] = ["weapon_spread", "cb_timing", "ai", "effects", "world"];
+
+/// A `SmallRng` which counts how many times it's been used.
+#[derive(Debug, Clone)]
+pub struct Stream {
+    pub rng: SmallRng,
+    /// Calls of the `RngCore` methods since the stream was created.
+    ///
+    /// Only for finding where two runs diverged (see `trace`), it's not part of the game state
+    /// so it's not in snapshots or checksums.
+    /// Some distributions draw a varying number of times so this is not the same as values generated.
+    pub draws: u64,
+}
+
+impl RngCore for Stream {
+    fn next_u32(&mut self) -> u32 {
+        self.draws += 1;
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.draws += 1;
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.draws += 1;
+        self.rng.fill_bytes(dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.draws += 1;
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+/// The index is mixed in with a large odd constant so nearby seeds don't give related streams.
+fn stream(seed: u64, index: u64) -> Stream {
+    Stream {
+        rng: SmallRng::seed_from_u64(seed ^ index.wrapping_mul(0x9e37_79b9_7f4a_7c15)),
+        draws: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_streams() {
+        // Different streams of the same seed aren't the same sequence.
+        let mut rng = GameRng::new(7);
+        assert_ne!(rng.weapon_spread.gen::<u64>(), rng.ai.gen::<u64>());
+
+        rng.reseed(8);
+        assert_eq!(rng.seed, 8);
+        assert_eq!(rng.world.gen::<u64>(), GameRng::new(8).world.gen::<u64>());
+        assert_ne!(rng.world.gen::<u64>(), GameRng::new(7).world.gen::<u64>());
+    }
+}
@@ -0,0 +1,562 @@
+//! Named bot personas and which of them play a match.
+//!
+//! Instead of anonymous bots, each bot is played by a persona from the roster.
+//! A persona has a preferred vehicle, a difficulty profile (multipliers of the `bots_*` cvars)
+//! and a name tag color. Maps can pin personas which then always play on them (`bot <name>` lines).
+//!
+//! Which personas play is decided once when the match starts using `gs.rng.world`
+//! so the same seed, map and roster always give the same opponents.
+//! Replays don't store the roster - playing one back with different extra personas
+//! gives different opponents.
+//!
+//! The roster can be extended with personas in JSON, one flat object per persona, e.g.
+//! `{"name": "Hazard", "vehicle": "Tank", "reaction_time": 0.8, "color": "#ff8000"}`.
+//! Everything except the name is optional.
+//!
+//! LATER Bots don't chat yet so `chatter` is unused.
+//! LATER Race mode should only pick personas with `racer` set, team modes should fill slots from the roster.
+//! LATER Per-persona records ("killed by Hazard 37 times") once there's persistent player stats.
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+use rand::{prelude::SliceRandom, Rng};
+
+use crate::{
+    cvars::Cvars,
+    entities::{Ai, Player, VehicleType},
+    json::{self, JsonError, Value},
+};
+
+pub const PERSONA_NAME_MAX_LEN: usize = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chatter {
+    Quiet,
+    Friendly,
+    Taunting,
+}
+
+impl Chatter {
+    const ALL: [Chatter; 3] = [Chatter::Quiet, Chatter::Friendly, Chatter::Taunting];
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BotPersona {
+    pub name: String,
+    /// `None` means random.
+    pub veh_type: Option<VehicleType>,
+    /// Multiplies `bots_reaction_time`.
+    pub reaction_time: f64,
+    /// Multiplies `bots_aim_error`.
+    pub aim_error: f64,
+    pub chatter: Chatter,
+    /// Name tag color.
+    pub color: [u8; 3],
+    /// Can be picked in race mode.
+    pub racer: bool,
+}
+
+impl BotPersona {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            veh_type: None,
+            reaction_time: 1.0,
+            aim_error: 1.0,
+            chatter: Chatter::Quiet,
+            color: [255, 255, 255],
+            racer: false,
+        }
+    }
+
+    /// Set up a bot's player and AI to play as this persona.
+    pub fn apply(&self, cvars: &Cvars, player: &mut Player, ai: &mut Ai) {
+        player.pending_loadout.veh_type = self.veh_type;
+        player.color = Some(self.color);
+        ai.reaction_time_scale = self.reaction_time;
+        ai.aim_error_scale = self.aim_error;
+        ai.reaction_time = ai.base_reaction_time(cvars);
+        ai.aim_error = ai.base_aim_error(cvars);
+    }
+}
+
+/// A bot chosen to play the match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BotAssignment {
+    /// The persona's name, numbered if the persona plays more than once.
+    pub name: String,
+    /// Index into `Roster::personas`, `None` if the roster is empty.
+    pub persona: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Roster {
+    pub personas: Vec<BotPersona>,
+}
+
+impl Roster {
+    /// The personas that come with the game.
+    pub fn builtin() -> Self {
+        use VehicleType::*;
+
+        let persona =
+            |name: &str, veh_type, reaction_time, aim_error, chatter, color, racer| BotPersona {
+                name: name.to_owned(),
+                veh_type,
+                reaction_time,
+                aim_error,
+                chatter,
+                color,
+                racer,
+            };
+        #[rustfmt::skip]
+        let personas = vec![
+            persona("Dr. Dead",        None,             1.0, 1.0, Chatter::Taunting, [255, 255, 255], false),
+            persona("Sir Hurt",        Some(Tank),       1.2, 0.8, Chatter::Friendly, [200, 200, 255], false),
+            persona("Mr. Pain",        Some(Hummer),     0.9, 1.2, Chatter::Taunting, [255, 120, 120], true),
+            persona("PhD. Torture",    Some(Tank),       0.8, 1.0, Chatter::Quiet,    [180, 255, 180], false),
+            persona("Mrs. Chestwound", Some(Hovercraft), 1.1, 1.1, Chatter::Friendly, [255, 180, 255], true),
+            persona("Ms. Dismember",   None,             0.9, 0.9, Chatter::Taunting, [255, 80, 160],  false),
+            persona("Don Lobotomy",    Some(Tank),       1.3, 0.7, Chatter::Quiet,    [160, 160, 160], false),
+            persona("Lt. Dead",        Some(Hummer),     1.0, 1.1, Chatter::Quiet,    [120, 200, 120], true),
+            persona("Sgt. Dead",       Some(Tank),       1.0, 0.9, Chatter::Taunting, [100, 180, 100], false),
+            persona("Private Dead",    None,             1.2, 1.3, Chatter::Friendly, [140, 220, 140], false),
+            persona("Colonel Dead",    Some(Tank),       0.9, 0.8, Chatter::Quiet,    [80, 160, 80],   false),
+            persona("Captain Dead",    Some(Hovercraft), 0.9, 1.0, Chatter::Friendly, [120, 160, 255], true),
+            persona("Major Dead",      Some(Hummer),     1.0, 1.0, Chatter::Quiet,    [60, 140, 60],   false),
+            persona("Commander Dead",  None,             0.8, 0.9, Chatter::Taunting, [40, 120, 40],   false),
+            persona("Díotóir",         Some(Hovercraft), 0.8, 1.2, Chatter::Quiet,    [255, 160, 60],  true),
+            persona("Fireman",         Some(Hummer),     1.1, 1.0, Chatter::Friendly, [255, 60, 30],   true),
+            persona("Goldfinger",      None,             0.9, 0.8, Chatter::Taunting, [255, 215, 0],   false),
+            persona("Silverfinger",    None,             1.0, 0.9, Chatter::Taunting, [200, 200, 210], false),
+            persona("Bronzefinger",    None,             1.1, 1.0, Chatter::Taunting, [205, 127, 50],  false),
+            persona("President Dead",  Some(Tank),       0.8, 0.8, Chatter::Quiet,    [255, 255, 160], false),
+        ];
+        Self { personas }
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.personas
+            .iter()
+            .position(|persona| persona.name == name)
+    }
+
+    /// Parse a persona from JSON and add it to the roster.
+    pub fn add_bot_persona(&mut self, json: &str) -> Result<(), PersonaError> {
+        let persona = parse_persona(json)?;
+        if self.find(&persona.name).is_some() {
+            return Err(PersonaError::field(
+                "name",
+                "a persona with this name already exists",
+            ));
+        }
+        self.personas.push(persona);
+        Ok(())
+    }
+
+    /// Choose `count` bots for a match.
+    ///
+    /// Personas in `pinned` come first, unknown names are skipped.
+    /// The rest is drawn in random order from the roster (only racers if `racers_only`)
+    /// without repeating personas until all of them are used,
+    /// after that personas play again with numbered names.
+    pub fn assign<R: Rng>(
+        &self,
+        rng: &mut R,
+        count: usize,
+        pinned: &[String],
+        racers_only: bool,
+    ) -> Vec<BotAssignment> {
+        let mut order = Vec::new();
+        for name in pinned {
+            match self.find(name) {
+                Some(index) => order.push(index),
+                None => {
+                    dbg_logf!("Pinned bot persona {:?} is not in the roster", name);
+                }
+            }
+        }
+
+        let eligible: Vec<usize> = (0..self.personas.len())
+            .filter(|&index| !racers_only || self.personas[index].racer)
+            .collect();
+        let mut first_round: Vec<usize> = eligible
+            .iter()
+            .copied()
+            .filter(|index| !order.contains(index))
+            .collect();
+        first_round.shuffle(rng);
+        order.extend(first_round);
+        while order.len() < count && !eligible.is_empty() {
+            let mut round = eligible.clone();
+            round.shuffle(rng);
+            order.extend(round);
+        }
+        order.truncate(count);
+
+        let mut uses = vec![0; self.personas.len()];
+        let mut assignments: Vec<BotAssignment> = order
+            .into_iter()
+            .map(|index| {
+                uses[index] += 1;
+                let name = &self.personas[index].name;
+                let name = if uses[index] == 1 {
+                    name.clone()
+                } else {
+                    format!("{} {}", name, uses[index])
+                };
+                BotAssignment {
+                    name,
+                    persona: Some(index),
+                }
+            })
+            .collect();
+        // Only when there's nobody eligible.
+        for i in assignments.len()..count {
+            assignments.push(BotAssignment {
+                name: format!("Bot {}", i + 1),
+                persona: None,
+            });
+        }
+        assignments
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PersonaError {
+    /// Not a flat JSON object, `pos` is the byte offset.
+    Syntax { pos: usize, msg: String },
+    /// A field is missing, unknown or has an invalid value.
+    Field { field: String, msg: String },
+}
+
+impl PersonaError {
+    fn field(field: &str, msg: &str) -> Self {
+        PersonaError::Field {
+            field: field.to_owned(),
+            msg: msg.to_owned(),
+        }
+    }
+}
+
+impl Display for PersonaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PersonaError::Syntax { pos, msg } => write!(f, "byte {}: {}", pos, msg),
+            PersonaError::Field { field, msg } => write!(f, "field {:?}: {}", field, msg),
+        }
+    }
+}
+
+impl Error for PersonaError {}
+
+impl From<JsonError> for PersonaError {
+    fn from(err: JsonError) -> Self {
+        PersonaError::Syntax {
+            pos: err.pos,
+            msg: err.msg,
+        }
+    }
+}
+
+fn parse_persona(json: &str) -> Result<BotPersona, PersonaError> {
+    let mut persona = BotPersona::new("");
+    let mut has_name = false;
+    for (key, value) in json::parse_object(json)? {
+        let err = |msg: &str| PersonaError::field(&key, msg);
+        match (key.as_str(), value) {
+            ("name", Value::Str(name)) => {
+                if name.trim().is_empty() || name.trim() != name {
+                    return Err(err("must not be empty or start or end with whitespace"));
+                }
+                if name.chars().count() > PERSONA_NAME_MAX_LEN {
+                    return Err(err("too long"));
+                }
+                persona.name = name;
+                has_name = true;
+            }
+            ("vehicle", Value::Null) => persona.veh_type = None,
+            ("vehicle", Value::Str(name)) => {
+                let veh_type = VehicleType::all()
+                    .find(|veh_type| format!("{:?}", veh_type) == name)
+                    .ok_or_else(|| err("unknown vehicle type"))?;
+                persona.veh_type = Some(veh_type);
+            }
+            ("reaction_time", Value::Num(num)) => {
+                if !(num >= 0.0 && num.is_finite()) {
+                    return Err(err("must be a non-negative number"));
+                }
+                persona.reaction_time = num;
+            }
+            ("aim_error", Value::Num(num)) => {
+                if !(num >= 0.0 && num.is_finite()) {
+                    return Err(err("must be a non-negative number"));
+                }
+                persona.aim_error = num;
+            }
+            ("chatter", Value::Str(name)) => {
+                persona.chatter = Chatter::ALL
+                    .iter()
+                    .copied()
+                    .find(|chatter| format!("{:?}", chatter) == name)
+                    .ok_or_else(|| err("expected Quiet, Friendly or Taunting"))?;
+            }
+            ("color", Value::Str(color)) => {
+                persona.color = parse_color(&color).ok_or_else(|| err("expected #rrggbb"))?;
+            }
+            ("racer", Value::Bool(racer)) => persona.racer = racer,
+            (
+                "name" | "vehicle" | "reaction_time" | "aim_error" | "chatter" | "color" | "racer",
+                _,
+            ) => {
+                return Err(err("wrong type"));
+            }
+            _ => return Err(err("unknown field")),
+        }
+    }
+    if !has_name {
+        return Err(PersonaError::field("name", "missing"));
+    }
+    Ok(persona)
+}
+
+fn parse_color(text: &str) -> Option<[u8; 3]> {
+    let hex = text.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use rand::{prelude::SmallRng, SeedableRng};
+
+    use crate::{map, server::Server};
+
+    fn names(assignments: &[BotAssignment]) -> Vec<&str> {
+        assignments
+            .iter()
+            .map(|assignment| assignment.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_assign_deterministic() {
+        let roster = Roster::builtin();
+        let assign = |seed| roster.assign(&mut SmallRng::seed_from_u64(seed), 8, &[], false);
+        assert_eq!(assign(42), assign(42));
+        assert_ne!(assign(42), assign(43));
+    }
+
+    #[test]
+    fn test_assign_pinned() {
+        let roster = Roster::builtin();
+        let pinned = vec!["Goldfinger".to_owned(), "Nobody".to_owned()];
+        for seed in 0..50 {
+            let mut rng = SmallRng::seed_from_u64(seed);
+            for count in 1..5 {
+                let assignments = roster.assign(&mut rng, count, &pinned, false);
+                assert_eq!(assignments.len(), count);
+                assert_eq!(assignments[0].name, "Goldfinger");
+                assert_eq!(
+                    names(&assignments)
+                        .iter()
+                        .filter(|&&name| name == "Goldfinger")
+                        .count(),
+                    1
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_assign_no_duplicates_until_exhausted() {
+        let mut roster = Roster {
+            personas: vec![
+                BotPersona::new("A"),
+                BotPersona::new("B"),
+                BotPersona::new("C"),
+            ],
+        };
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut first = names(&roster.assign(&mut rng, 3, &[], false))
+            .iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        first.sort();
+        assert_eq!(first, ["A", "B", "C"]);
+
+        let assignments = roster.assign(&mut rng, 7, &[], false);
+        for round in assignments.chunks(3) {
+            let mut personas: Vec<_> = round.iter().map(|a| a.persona).collect();
+            personas.sort();
+            personas.dedup();
+            assert_eq!(personas.len(), round.len());
+        }
+        for (i, assignment) in assignments.iter().enumerate() {
+            let persona = &roster.personas[assignment.persona.unwrap()];
+            let uses = assignments[..i]
+                .iter()
+                .filter(|a| a.persona == assignment.persona)
+                .count();
+            let expected = if uses == 0 {
+                persona.name.clone()
+            } else {
+                format!("{} {}", persona.name, uses + 1)
+            };
+            assert_eq!(assignment.name, expected);
+        }
+
+        roster.personas[1].racer = true;
+        let racers = roster.assign(&mut rng, 2, &[], true);
+        assert_eq!(names(&racers), ["B", "B 2"]);
+
+        let empty = Roster {
+            personas: Vec::new(),
+        };
+        let anonymous = empty.assign(&mut rng, 2, &[], false);
+        assert_eq!(names(&anonymous), ["Bot 1", "Bot 2"]);
+        assert_eq!(anonymous[0].persona, None);
+    }
+
+    #[test]
+    fn test_add_bot_persona() {
+        let mut roster = Roster::builtin();
+        let json = r##" { "name": "Hazard", "vehicle": "Tank", "reaction_time": 0.8,
+            "aim_error": 1.5e-1, "chatter": "Taunting", "color": "#ff8000", "racer": true } "##;
+        roster.add_bot_persona(json).unwrap();
+        let persona = &roster.personas[roster.find("Hazard").unwrap()];
+        assert_eq!(persona.veh_type, Some(VehicleType::Tank));
+        assert_eq!(persona.reaction_time, 0.8);
+        assert_eq!(persona.aim_error, 0.15);
+        assert_eq!(persona.chatter, Chatter::Taunting);
+        assert_eq!(persona.color, [255, 128, 0]);
+        assert!(persona.racer);
+
+        roster
+            .add_bot_persona(r##"{"name": "Café \"Q\"", "vehicle": null}"##)
+            .unwrap();
+        assert!(roster.find("Café \"Q\"").is_some());
+
+        let field = |field: &str, msg: &str| {
+            Err(PersonaError::Field {
+                field: field.to_owned(),
+                msg: msg.to_owned(),
+            })
+        };
+        let mut roster2 = roster.clone();
+        assert_eq!(
+            roster2.add_bot_persona(r##"{"name": "Hazard"}"##),
+            field("name", "a persona with this name already exists")
+        );
+        assert_eq!(
+            roster2.add_bot_persona(r##"{"vehicle": "Tank"}"##),
+            field("name", "missing")
+        );
+        assert_eq!(
+            roster2.add_bot_persona(r##"{"name": "X", "vehicle": "Boat"}"##),
+            field("vehicle", "unknown vehicle type")
+        );
+        assert_eq!(
+            roster2.add_bot_persona(r##"{"name": "X", "aim_error": -1}"##),
+            field("aim_error", "must be a non-negative number")
+        );
+        assert_eq!(
+            roster2.add_bot_persona(r##"{"name": "X", "racer": "yes"}"##),
+            field("racer", "wrong type")
+        );
+        assert_eq!(
+            roster2.add_bot_persona(r##"{"name": "X", "color": "red"}"##),
+            field("color", "expected #rrggbb")
+        );
+        assert_eq!(
+            roster2.add_bot_persona(r##"{"name": "X", "hp": 100}"##),
+            field("hp", "unknown field")
+        );
+        assert_eq!(
+            roster2.add_bot_persona(r##"{"name": ""}"##),
+            field("name", "must not be empty or start or end with whitespace")
+        );
+        assert!(matches!(
+            roster2.add_bot_persona(r##"{"name": "X""##),
+            Err(PersonaError::Syntax { .. })
+        ));
+        assert!(matches!(
+            roster2.add_bot_persona(r##"{"name": ["X"]}"##),
+            Err(PersonaError::Syntax { pos: 9, .. })
+        ));
+        assert!(matches!(
+            roster2.add_bot_persona(r##"{"name": "X"} x"##),
+            Err(PersonaError::Syntax { .. })
+        ));
+        assert_eq!(roster2, roster);
+    }
+
+    #[test]
+    fn test_map_pinned_personas() {
+        let tex_list_text = fs::read_to_string("assets/texture_list.txt").unwrap();
+        let surfaces = map::load_tex_list(&tex_list_text).unwrap();
+        let map_text = fs::read_to_string("maps/Atrium.map").unwrap();
+        let map_text = format!("bot Hazard\n{}", map_text);
+
+        let mut roster = Roster::builtin();
+        let json =
+            r##"{"name": "Hazard", "vehicle": "Hummer", "reaction_time": 2, "color": "#ff0000"}"##;
+        roster.add_bot_persona(json).unwrap();
+        let cvars = Cvars {
+            bots_max: 3,
+            bots_reaction_time: 0.2,
+            ..Cvars::default()
+        };
+        let mut opponents = Vec::new();
+        for seed in 0..10 {
+            let cvars = Cvars {
+                d_seed: seed,
+                ..cvars.clone()
+            };
+            let map = map::load_map(&map_text, surfaces.clone()).unwrap();
+            let server = Server::with_roster(&cvars, map, &roster);
+            let gs = &server.gs;
+            let names: Vec<_> = gs
+                .players
+                .iter()
+                .map(|(_, player)| player.name.clone())
+                .collect();
+            assert_eq!(names.len(), 3);
+            assert!(names.contains(&"Hazard".to_owned()));
+
+            let (handle, player) = gs
+                .players
+                .iter()
+                .find(|(_, player)| player.name == "Hazard")
+                .unwrap();
+            assert_eq!(player.color, Some([255, 0, 0]));
+            assert_eq!(
+                gs.vehicles[player.vehicle.unwrap()].veh_type,
+                VehicleType::Hummer
+            );
+            let ai = gs.ais.iter().find(|(_, ai)| ai.player == handle).unwrap().1;
+            assert_eq!(ai.reaction_time, cvars.bots_reaction_time * 2.0);
+            opponents.push(names);
+        }
+        // Same seed, same opponents.
+        let map = map::load_map(&map_text, surfaces).unwrap();
+        let server = Server::with_roster(&Cvars { d_seed: 3, ..cvars }, map, &roster);
+        let names: Vec<_> = server
+            .gs
+            .players
+            .iter()
+            .map(|(_, player)| player.name.clone())
+            .collect();
+        assert_eq!(names, opponents[3]);
+    }
+}
@@ -0,0 +1,289 @@
+//! The order gamelogic systems run in each tick and when they're skipped.
+//!
+//! The order matters - e.g. shooting is after movement so shots start from the barrel
+//! as it's drawn at the end of the frame. Keeping it in one list means it can be inspected
+//! (`Schedule::describe`) instead of being spread through the server's tick.
+//!
+//! Skipped systems still count as having run (with nothing to do) so timings
+//! and trace dumps (see `trace`) have the same systems every tick.
+
+use crate::{
+    cvars::Cvars,
+    game_state::{GameState, MatchState},
+    map::Map,
+    sys_ai, sys_ctf, sys_decals, sys_hazards, sys_homing, sys_match, sys_particles, sys_ramming,
+    sys_range, sys_sound, sys_threats, sys_trails, systems,
+    timing::SystemTimings,
+};
+
+pub type SystemFn = fn(&Cvars, &mut GameState, &mut Map);
+
+/// Whether a system runs this tick.
+pub type Condition = fn(&Cvars, &GameState) -> bool;
+
+#[derive(Debug, Clone)]
+pub struct SystemEntry {
+    pub name: &'static str,
+    pub system: SystemFn,
+    pub condition: Option<Condition>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Schedule {
+    systems: Vec<SystemEntry>,
+}
+
+/// Players are frozen during the countdown and after the match, see `sys_match`.
+pub fn playing(_cvars: &Cvars, gs: &GameState) -> bool {
+    gs.match_state == MatchState::Playing
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The systems of a normal gamelogic tick.
+    pub fn gamelogic() -> Self {
+        Self::new()
+            .add("cleanup", |cvars, gs, _| systems::cleanup(cvars, gs))
+            .add("particles", |cvars, gs, _| {
+                sys_particles::update_particles(cvars, gs)
+            })
+            .add("dda", |cvars, gs, _| sys_ai::dda(cvars, gs))
+            .add_if("ai", playing, |cvars, gs, map| sys_ai::ai(cvars, gs, map))
+            .add_if("respawning", playing, |cvars, gs, map| {
+                systems::respawning(cvars, gs, map)
+            })
+            .add("range", |cvars, gs, _| sys_range::range(cvars, gs))
+            .add_if("player_logic", playing, |cvars, gs, _| {
+                systems::player_logic(cvars, gs)
+            })
+            .add_if("vehicle_logic", playing, |cvars, gs, _| {
+                systems::vehicle_logic(cvars, gs)
+            })
+            .add_if("gm_detonation", playing, systems::gm_detonation)
+            .add_if("vehicle_movement", playing, |cvars, gs, map| {
+                systems::vehicle_movement(cvars, gs, map)
+            })
+            .add_if("vehicle_collisions", playing, |cvars, gs, map| {
+                sys_ramming::vehicle_collisions(cvars, gs, map)
+            })
+            // Shooting is after movement so shots start from the barrel as it's drawn at the end of the frame.
+            // Shots fired earlier in the frame are placed back along the vehicle's path (see `Vehicle::displacement`).
+            // Anything else which runs in between would have to keep the displacement up to date.
+            .add_if("shooting", playing, |cvars, gs, _| {
+                systems::shooting(cvars, gs)
+            })
+            .add_if("deploy_flares", playing, |cvars, gs, _| {
+                sys_homing::deploy_flares(cvars, gs)
+            })
+            .add("pickups", |cvars, gs, map| systems::pickups(cvars, gs, map))
+            .add("ctf", |cvars, gs, map| sys_ctf::ctf(cvars, gs, map))
+            .add("hazards", |cvars, gs, map| {
+                sys_hazards::hazards(cvars, gs, map)
+            })
+            .add_if("gm_turning", playing, |cvars, gs, _| {
+                systems::gm_turning(cvars, gs)
+            })
+            .add("homing", |cvars, gs, _| sys_homing::homing(cvars, gs))
+            .add("projectiles", systems::projectiles)
+            .add("projectiles_timeout", systems::projectiles_timeout)
+            .add_if("self_destruct", playing, systems::self_destruct)
+            .add("vehicle_explosions", systems::vehicle_explosions)
+            .add("match_flow", |cvars, gs, _| {
+                sys_match::match_flow(cvars, gs)
+            })
+            .add("decals", |cvars, gs, _| {
+                sys_decals::update_decals(cvars, gs)
+            })
+            .add("trails", |cvars, gs, _| {
+                sys_trails::update_trails(cvars, gs)
+            })
+            .add("threats", |cvars, gs, _| sys_threats::threats(cvars, gs))
+            .add("sounds", |cvars, gs, _| sys_sound::sounds(cvars, gs))
+    }
+
+    /// Run `system` every tick after the systems added before it.
+    pub fn add(mut self, name: &'static str, system: SystemFn) -> Self {
+        self.push(name, system, None);
+        self
+    }
+
+    /// Run `system` after the systems added before it in ticks when `condition` is true.
+    pub fn add_if(mut self, name: &'static str, condition: Condition, system: SystemFn) -> Self {
+        self.push(name, system, Some(condition));
+        self
+    }
+
+    fn push(&mut self, name: &'static str, system: SystemFn, condition: Option<Condition>) {
+        assert!(
+            self.systems.iter().all(|entry| entry.name != name),
+            "system {} is already scheduled",
+            name
+        );
+        self.systems.push(SystemEntry {
+            name,
+            system,
+            condition,
+        });
+    }
+
+    /// The names of the systems in the order they run.
+    pub fn describe(&self) -> Vec<&'static str> {
+        self.systems.iter().map(|entry| entry.name).collect()
+    }
+
+    /// Run all the systems (or skip them if their condition is false) in order.
+    ///
+    /// After each, it's timed (see `d_timing_systems`), its trace events are recorded
+    /// (see `d_trace`) and `after_system` is called with its name.
+    pub fn run(
+        &self,
+        cvars: &Cvars,
+        gs: &mut GameState,
+        map: &mut Map,
+        timings: &mut SystemTimings,
+        mut after_system: impl FnMut(&'static str, &GameState),
+    ) {
+        for entry in &self.systems {
+            let enabled = entry
+                .condition
+                .map_or(true, |condition| condition(cvars, gs));
+            if enabled {
+                (entry.system)(cvars, gs, map);
+            }
+            timings.lap(entry.name);
+            gs.trace
+                .system_done(cvars, gs.frame_num, entry.name, &gs.rng);
+            after_system(entry.name, gs);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{effects::Effect, game_state::Input, map, map::Vec2f, server::Server, snapshot};
+
+    use super::*;
+
+    #[test]
+    fn test_gamelogic_order() {
+        // Changing this changes the game, replays and trace dumps.
+        let expected = [
+            "cleanup",
+            "particles",
+            "dda",
+            "ai",
+            "respawning",
+            "range",
+            "player_logic",
+            "vehicle_logic",
+            "gm_detonation",
+            "vehicle_movement",
+            "vehicle_collisions",
+            "shooting",
+            "deploy_flares",
+            "pickups",
+            "ctf",
+            "hazards",
+            "gm_turning",
+            "homing",
+            "projectiles",
+            "projectiles_timeout",
+            "self_destruct",
+            "vehicle_explosions",
+            "match_flow",
+            "decals",
+            "trails",
+            "threats",
+            "sounds",
+        ];
+        assert_eq!(Schedule::gamelogic().describe(), expected);
+    }
+
+    #[test]
+    fn test_skipped_outside_match() {
+        let cvars = Cvars {
+            g_match_countdown: 0.0,
+            d_timing_systems: true,
+            ..Cvars::default()
+        };
+        let map = map::tests::load_test_map(&fs::read_to_string("maps/Atrium.map").unwrap());
+        let mut server = Server::new(&cvars, map);
+        let player = server.connect(&cvars, "Player 1");
+        server.gs.players[player].input.fire = true;
+        let Server { gs, map, .. } = &mut server;
+        let schedule = Schedule::gamelogic();
+        let mut timings = SystemTimings::default();
+
+        let mut run = |gs: &mut GameState| {
+            let mut shots = 0;
+            gs.game_time += 1.0 / 60.0;
+            gs.dt = 1.0 / 60.0;
+            timings.start(cvars.d_timing_systems, 10);
+            schedule.run(&cvars, gs, map, &mut timings, |system, gs| {
+                if system == "shooting" {
+                    shots += gs.shots.len();
+                }
+            });
+            // Every system counts even if it did nothing.
+            assert_eq!(timings.durations.len(), schedule.describe().len());
+            shots
+        };
+        assert!(run(gs) > 0);
+
+        gs.match_state = MatchState::Countdown { until: f64::MAX };
+        gs.effects.push(Effect::Explosion {
+            pos: Vec2f::zero(),
+            scale: 1.0,
+            start_time: gs.game_time,
+            bfg: false,
+        });
+        for _ in 0..300 {
+            assert_eq!(run(gs), 0);
+        }
+        // Cosmetic systems keep running.
+        assert!(gs.effects.is_empty());
+    }
+
+    #[test]
+    fn test_dda_off_same_as_without_dda() {
+        let cvars = Cvars {
+            d_seed: 1234,
+            bots_max: 6,
+            g_match_countdown: 0.0,
+            ..Cvars::default()
+        };
+        assert!(!cvars.g_dda_enabled);
+        // A scripted human and bots fighting, returns the checksum after each tick
+        // and the final snapshot so everything in the game state is compared.
+        let battle = |schedule: Schedule| {
+            let map = map::tests::load_test_map(&fs::read_to_string("maps/Atrium.map").unwrap());
+            let mut server = Server::new(&cvars, map);
+            server.schedule = schedule;
+            let player = server.connect(&cvars, "Player");
+            let mut checksums = Vec::new();
+            for tick in 0..3000 {
+                let input = Input {
+                    up: tick % 300 < 200,
+                    left: tick % 500 < 50,
+                    fire: tick % 3 == 0,
+                    next_weapon: tick % 600 == 0,
+                    ..Input::new()
+                };
+                server.tick(&cvars, &[(player, input)]);
+                checksums.push(server.state_checksum());
+            }
+            let deaths: i32 = server.gs.players.iter().map(|(_, p)| p.score.deaths).sum();
+            assert!(deaths > 0);
+            (checksums, snapshot::snapshot(&server.gs, &server.map))
+        };
+
+        let mut without_dda = Schedule::gamelogic();
+        without_dda.systems.retain(|entry| entry.name != "dda");
+        assert!(battle(Schedule::gamelogic()) == battle(without_dda));
+    }
+}
@@ -1,44 +1,38 @@
 //! The authoritative server in a client-server game architecture - all data affecting gameplay, no networking yet.
 
-use rand::{prelude::SmallRng, SeedableRng};
+use fnv::FnvHashMap;
 use thunderdome::Index;
 
 use crate::{
     cvars::{Cvars, TickrateMode},
     debugging,
-    entities::{Ai, Player},
-    game_state::{ArenaExt, GameState, Input},
-    map::Map,
-    sys_ai, systems,
-    timing::{Durations, Fps},
+    derived::Derived,
+    editor,
+    entities::{Player, VehicleType},
+    game_state::{ArenaExt, GameMode, GameState, Input},
+    input_buffer::InputBuffer,
+    map::{self, EditError, Map, Tile, Vec2u},
+    observers::{Observer, Observers},
+    prediction::{ClientUpdate, ConfirmedShot, InputCmd},
+    replay::{self, Event, Playback, Recording, Replay},
+    roster::Roster,
+    schedule::Schedule,
+    sys_ai, sys_ctf, sys_match, sys_range, systems,
+    timing::{Durations, Fps, SystemTimings},
 };
 
-const BOT_NAMES: [&str; 20] = [
-    "Dr. Dead",
-    "Sir Hurt",
-    "Mr. Pain",
-    "PhD. Torture",
-    "Mrs. Chestwound",
-    "Ms. Dismember",
-    "Don Lobotomy",
-    "Lt. Dead",
-    "Sgt. Dead",
-    "Private Dead",
-    "Colonel Dead",
-    "Captain Dead",
-    "Major Dead",
-    "Commander Dead",
-    "Díotóir",
-    "Fireman",
-    "Goldfinger",
-    "Silverfinger",
-    "Bronzefinger",
-    "President Dead",
-];
+pub const TIME_SCALE_MIN: f64 = 0.01;
+pub const TIME_SCALE_MAX: f64 = 10.0;
 
 #[derive(Debug)]
 pub struct Server {
     pub map: Map,
+    /// Where bots are drawn from at the start of each match.
+    pub roster: Roster,
+    /// Number of matches which ended and were replaced by a new one, see `reset_match`.
+    pub matches_played: u32,
+    /// Built during the first frames after the map loads.
+    pub derived: Derived,
     pub gs: GameState,
     /// Game time left over from previous update.
     pub dt_carry: f64,
@@ -50,43 +44,47 @@ pub struct Server {
     pub real_time_prev: f64,
     pub real_time_delta: f64,
     pub paused: bool,
+    /// Multiplies the game time each update and `step` simulates, on top of `d_speed`, see `set_time_scale`.
+    pub time_scale: f64,
+    /// Multiplies `time_scale` during the client's hit-stop (see `feedback`), 1 otherwise.
+    pub hit_stop: f64,
+    /// Gamelogic doesn't run during the round intro.
+    pub intro: bool,
+    pub recording: Option<Recording>,
+    /// When playing back a replay, live input is ignored.
+    pub playback: Option<Playback>,
+    /// Live input received since the last tick, see `input`.
+    pub input_buffers: FnvHashMap<Index, InputBuffer>,
+    /// Sequence number of the last input command applied for each player who sends them.
+    pub input_acks: FnvHashMap<Index, u32>,
+    /// Shots of players who send input commands, waiting to be sent to them in `client_update`.
+    pub confirmed_shots: Vec<(Index, ConfirmedShot)>,
     pub update_fps: Fps,
     pub update_durations: Durations,
     pub gamelogic_fps: Fps,
     pub gamelogic_durations: Durations,
+    /// Last gamelogic frame's duration of each system, only measured with `d_stats`.
+    pub system_timings: SystemTimings,
+    /// The gamelogic systems in the order they run each tick.
+    pub schedule: Schedule,
+    /// Get game events after each update, see `on_event`.
+    pub observers: Observers,
 }
 
 impl Server {
     pub fn new(cvars: &Cvars, map: Map) -> Self {
-        let rng = SmallRng::seed_from_u64(cvars.d_seed);
-        let mut gs = GameState::new(rng);
+        Self::with_roster(cvars, map, &Roster::builtin())
+    }
 
-        let bots_count = map.spawns().len().min(cvars.bots_max);
-        dbg_logf!(
-            "Spawns per bot: {}",
-            map.spawns().len() as f64 / bots_count as f64
-        );
-        dbg_logf!(
-            "Tiles per bot: {}",
-            (map.width() * map.height()) as f64 / bots_count as f64
-        );
-        for i in 0..bots_count {
-            let name = if i < BOT_NAMES.len() {
-                BOT_NAMES[i].to_owned()
-            } else {
-                format!("Bot {}", i + 1)
-            };
-            let player = Player::new(name);
-            let player_handle = gs.players.insert(player);
-            gs.ais.insert(Ai::new(player_handle));
-        }
-
-        for handle in gs.players.iter_handles() {
-            systems::spawn_vehicle(cvars, &mut gs, &map, handle, false);
-        }
+    /// Bots are drawn from `roster`, see `Roster::assign`.
+    pub fn with_roster(cvars: &Cvars, map: Map, roster: &Roster) -> Self {
+        let gs = new_match(cvars, &map, roster, cvars.d_seed);
 
         Self {
+            derived: Derived::new(&map),
             map,
+            roster: roster.clone(),
+            matches_played: 0,
             gs: gs.clone(),
             dt_carry: 0.0,
             gs_fixed: gs,
@@ -94,33 +92,353 @@ impl Server {
             real_time_prev: 0.0,
             real_time_delta: 0.0,
             paused: false,
+            time_scale: 1.0,
+            hit_stop: 1.0,
+            intro: false,
+            recording: None,
+            playback: None,
+            input_buffers: FnvHashMap::default(),
+            input_acks: FnvHashMap::default(),
+            confirmed_shots: Vec::new(),
             update_fps: Fps::new(),
             update_durations: Durations::new(),
             gamelogic_fps: Fps::new(),
             gamelogic_durations: Durations::new(),
+            system_timings: SystemTimings::default(),
+            schedule: Schedule::gamelogic(),
+            observers: Observers::new(),
         }
     }
 
+    /// Call `observer` with each game event (notification) once gamelogic for the update, step or tick is done.
+    ///
+    /// Events still stay queued for `drain_notifications`.
+    pub fn on_event(&mut self, observer: Observer) {
+        self.observers.register(observer);
+    }
+
     pub fn connect(&mut self, cvars: &Cvars, name: &str) -> Index {
-        let player = Player::new(name.to_owned());
-        let player_handle = self.gs.players.insert(player.clone());
-        let player_handle2 = self.gs_fixed.players.insert(player);
+        self.connect_with_vehicle(cvars, name, None)
+    }
+
+    /// Connect a player who chose their vehicle, `None` is a random one each spawn.
+    pub fn connect_with_vehicle(
+        &mut self,
+        cvars: &Cvars,
+        name: &str,
+        veh_type: Option<VehicleType>,
+    ) -> Index {
+        let mut player = Player::new(name.to_owned());
+        player.pending_loadout.veh_type = veh_type;
+        let player_handle = join(cvars, &mut self.gs, &self.map, player.clone());
+        // Both need a vehicle, otherwise `TickrateMode::FixedOrSmaller` loses it when restoring `gs`.
+        let player_handle2 = join(cvars, &mut self.gs_fixed, &self.map, player);
         assert_eq!(player_handle, player_handle2);
-        systems::spawn_vehicle(cvars, &mut self.gs, &self.map, player_handle, true);
         player_handle
     }
 
+    /// Throw away the current match and start a new one from `seed` on the same map.
+    ///
+    /// The result is the same as creating a new server with `d_seed` set to `seed`
+    /// and connecting the same human players in the same order - bots are drawn from the roster again
+    /// and all entities, scores and RNG streams start over, game time included.
+    /// Destroyed walls are rebuilt.
+    /// Humans keep their handles (as long as the number of bots doesn't change),
+    /// names, colors and loadouts.
+    pub fn reset_match(&mut self, cvars: &Cvars, seed: u64) {
+        if self.map.repair_walls() {
+            self.derived = Derived::new(&self.map);
+        }
+        let mut gs = new_match(cvars, &self.map, &self.roster, seed);
+        for old_handle in self.human_players() {
+            let old = &self.gs.players[old_handle];
+            let player = Player {
+                pending_loadout: old.pending_loadout.clone(),
+                loadout_presets: old.loadout_presets.clone(),
+                color: old.color,
+                ..Player::new(old.name.clone())
+            };
+            let player_handle = join(cvars, &mut gs, &self.map, player);
+            soft_assert!(
+                player_handle == old_handle,
+                "{} changed handles after restart",
+                gs.players[player_handle].name
+            );
+        }
+        self.gs_fixed = gs.clone();
+        self.gs = gs;
+        self.dt_carry = 0.0;
+        self.confirmed_shots.clear();
+        self.matches_played += 1;
+    }
+
+    /// Players who are not bots or training range dummies, in order of connection.
+    fn human_players(&self) -> Vec<Index> {
+        self.gs
+            .players
+            .iter()
+            .filter(|(_, player)| !self.gs.vehicles[player.vehicle.unwrap()].is_dummy)
+            .map(|(handle, _)| handle)
+            .filter(|&handle| !self.gs.ais.iter().any(|(_, ai)| ai.player == handle))
+            .collect()
+    }
+
+    /// Record everything needed to replay the match.
+    ///
+    /// Must be called after all players connect and before the first gamelogic frame.
+    pub fn start_recording(&mut self, cvars: &Cvars, map_path: &str) {
+        assert_eq!(
+            self.gs.frame_num, 0,
+            "Recording must start before the first gamelogic frame"
+        );
+        let player_handles = self.human_players();
+        let names = player_handles
+            .iter()
+            .map(|&handle| self.gs.players[handle].name.clone())
+            .collect();
+        let vehicles = player_handles
+            .iter()
+            .map(|&handle| self.gs.players[handle].loadout.veh_type)
+            .collect();
+        let replay = Replay::new(cvars, map_path, names, vehicles);
+        self.recording = Some(Recording::new(replay, player_handles));
+    }
+
+    /// Returns the encoded replay.
+    pub fn stop_recording(&mut self) -> Vec<u8> {
+        let recording = self.recording.take().expect("Not recording");
+        recording.replay.encode()
+    }
+
+    /// Play back a replay, live input is ignored from now on.
+    ///
+    /// The server must have been created with the replay's cvars and map
+    /// and the replay's players must already be connected.
+    pub fn play_replay(&mut self, replay: Replay) {
+        assert_eq!(
+            self.gs.frame_num, 0,
+            "Playback must start before the first gamelogic frame"
+        );
+        assert!(self.recording.is_none(), "Can't record during playback");
+        let player_handles = self.human_players();
+        let names: Vec<_> = player_handles
+            .iter()
+            .map(|&handle| self.gs.players[handle].name.clone())
+            .collect();
+        assert_eq!(names, replay.players, "Players don't match the replay");
+        self.playback = Some(Playback::new(replay, player_handles));
+    }
+
+    fn record(&mut self, event: Event) {
+        if let Some(recording) = &mut self.recording {
+            recording.replay.events.push(event);
+        }
+    }
+
     pub fn snapshot_inputs(&mut self) {
+        if self.playback.is_some() {
+            return;
+        }
+        self.record(Event::Snapshot);
+        self.snapshot_inputs_impl();
+    }
+
+    fn snapshot_inputs_impl(&mut self) {
         self.gs.inputs_prev.snapshot(&self.gs.players);
         self.gs_fixed.inputs_prev.snapshot(&self.gs_fixed.players);
     }
 
+    /// The player's current input. It takes effect at the next tick,
+    /// together with anything else received before it (see `input_buffer`).
     pub fn input(&mut self, local_player_handle: Index, input: Input) {
-        // LATER Keep timestamps of input events. When splitting frame into multiple steps, update input each step.
+        if self.playback.is_some() {
+            return;
+        }
+        self.input_buffers
+            .entry(local_player_handle)
+            .or_default()
+            .push(input);
+    }
+
+    /// Give players the input from their buffers right before a tick.
+    ///
+    /// `consume` is false for ticks which get thrown away (the last one of `TickrateMode::FixedOrSmaller`)
+    /// so the next tick sees the same taps.
+    fn apply_input_buffers(&mut self, consume: bool) {
+        let mut inputs: Vec<_> = self
+            .input_buffers
+            .iter_mut()
+            .map(|(&handle, buffer)| {
+                let input = buffer.input();
+                if consume {
+                    buffer.consume();
+                }
+                (handle, input)
+            })
+            .collect();
+        // Recorded in a fixed order so the same inputs always make the same replay.
+        inputs.sort_unstable_by_key(|&(handle, _)| handle.slot());
+        for (handle, input) in inputs {
+            if let Some(recording) = &mut self.recording {
+                if self.gs.players[handle].input != input {
+                    let player = recording.player_index(handle);
+                    recording.replay.events.push(Event::Input { player, input });
+                }
+            }
+            self.input_impl(handle, input);
+        }
+    }
+
+    /// Like `input` but numbered so the client can tell which inputs the server has applied.
+    pub fn input_cmd(&mut self, player_handle: Index, cmd: InputCmd) {
+        self.input_acks.insert(player_handle, cmd.seq);
+        self.input(player_handle, cmd.input);
+    }
+
+    /// The player's authoritative state for reconciling their client's prediction.
+    pub fn client_update(&mut self, player_handle: Index) -> ClientUpdate {
+        let player = self.gs.players[player_handle].clone();
+        let vehicle = player
+            .vehicle
+            .map(|handle| (handle, self.gs.vehicles[handle].clone()));
+        let mut shots = Vec::new();
+        self.confirmed_shots.retain(|&(owner, shot)| {
+            if owner == player_handle {
+                shots.push(shot);
+                false
+            } else {
+                true
+            }
+        });
+        ClientUpdate {
+            ack: self.input_acks.get(&player_handle).copied(),
+            game_time: self.gs.game_time,
+            frame_num: self.gs.frame_num,
+            match_state: self.gs.match_state,
+            player_handle,
+            player,
+            vehicle,
+            shots,
+        }
+    }
+
+    fn input_impl(&mut self, local_player_handle: Index, input: Input) {
         self.gs.players[local_player_handle].input = input;
         self.gs_fixed.players[local_player_handle].input = input;
     }
 
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Nothing pressed while paused piles up, only keys which are still held count.
+    pub fn resume(&mut self) {
+        self.paused = false;
+        for buffer in self.input_buffers.values_mut() {
+            buffer.consume();
+        }
+    }
+
+    /// Slow motion below 1, fast forward above, clamped to `TIME_SCALE_MIN..=TIME_SCALE_MAX`.
+    pub fn set_time_scale(&mut self, time_scale: f64) {
+        self.time_scale = time_scale.clamp(TIME_SCALE_MIN, TIME_SCALE_MAX);
+    }
+
+    /// How much game time passes per second of real time.
+    pub fn speed(&self, cvars: &Cvars) -> f64 {
+        cvars.d_speed * self.time_scale * self.hit_stop
+    }
+
+    /// Pause if running and simulate exactly `ticks` gamelogic frames
+    /// of `1 / sv_tickrate_fixed_fps` seconds scaled by `speed`.
+    pub fn step(&mut self, cvars: &Cvars, ticks: u32) {
+        if self.playback.is_some() {
+            return;
+        }
+        self.paused = true;
+        let dt = self.speed(cvars) / cvars.sv_tickrate_fixed_fps;
+        for _ in 0..ticks {
+            self.live_tick(cvars, self.gs.game_time + dt, true);
+        }
+        // Otherwise the next update would start again from before the steps.
+        if cvars.sv_tickrate_mode == TickrateMode::FixedOrSmaller {
+            self.record(Event::Save);
+            self.gs_fixed = self.gs.clone();
+        }
+        self.observers.dispatch(&mut self.gs.notifications);
+    }
+
+    /// Advance the simulation by exactly one tick of `1 / sv_tickrate_fixed_fps` seconds
+    /// with the given inputs - for lockstep networking where every peer has to simulate
+    /// the same ticks with the same inputs.
+    ///
+    /// Players not listed keep their last input. Pause, time scale and wall clock time don't matter,
+    /// game time is derived from the tick count (`gs.frame_num`) so it doesn't accumulate rounding errors.
+    /// Inputs and ticks are recorded like in `update` so replays work.
+    pub fn tick(&mut self, cvars: &Cvars, inputs: &[(Index, Input)]) {
+        if self.playback.is_some() {
+            return;
+        }
+        self.snapshot_inputs();
+        for &(player_handle, input) in inputs {
+            self.input(player_handle, input);
+        }
+        self.lockstep_tick(cvars);
+        // Otherwise the next update would start again from before the tick.
+        if cvars.sv_tickrate_mode == TickrateMode::FixedOrSmaller {
+            self.record(Event::Save);
+            self.gs_fixed = self.gs.clone();
+        }
+        self.observers.dispatch(&mut self.gs.notifications);
+    }
+
+    fn lockstep_tick(&mut self, cvars: &Cvars) {
+        let dt = 1.0 / cvars.sv_tickrate_fixed_fps;
+        // Never going back in case the server ran in another mode before.
+        let game_time = ((self.gs.frame_num + 1) as f64 * dt).max(self.gs.game_time);
+        self.live_tick(cvars, game_time, true);
+    }
+
+    /// Hash of the simulation state, two servers which simulated the same ticks
+    /// with the same inputs from the same seed have the same one. See `replay::checksum`.
+    pub fn state_checksum(&self) -> u64 {
+        replay::checksum(&self.gs)
+    }
+
+    /// The map tile at `index`, for editors.
+    pub fn tile(&self, index: Vec2u) -> Result<Tile, EditError> {
+        self.map.tile(index)
+    }
+
+    /// Change a tile of the running map, returns the previous one. See `editor`.
+    pub fn set_tile(&mut self, index: Vec2u, tile: Tile) -> Result<Tile, EditError> {
+        let prev = self.map.set_tile(index, tile)?;
+        self.map_edited();
+        Ok(prev)
+    }
+
+    /// Grow or crop the running map, see `Map::resize`.
+    pub fn resize_map(&mut self, size: Vec2u, fill: Tile) -> Result<(), EditError> {
+        self.map.resize(size, fill)?;
+        self.map_edited();
+        Ok(())
+    }
+
+    /// The current map in the format of map files.
+    pub fn export_map(&self) -> String {
+        map::save_map(&self.map)
+    }
+
+    fn map_edited(&mut self) {
+        // Rebuilt over the next frames like after loading, frontends need to drop their copies.
+        self.derived = Derived::new(&self.map);
+        let moved = editor::map_edited(&mut self.gs, &self.map);
+        editor::map_edited(&mut self.gs_fixed, &self.map);
+        if !moved.is_empty() {
+            dbg_logf!("Pushed {} vehicles out of walls", moved.len());
+        }
+    }
+
     /// Run gamelogic frame(s) up to current time (in seconds).
     pub fn update(&mut self, cvars: &Cvars, real_time: f64) {
         // Recommended reading:
@@ -128,30 +446,84 @@ impl Server {
         // https://medium.com/@tglaiel/how-to-make-your-game-run-at-60fps-24c61210fe75
 
         self.update_fps.tick(cvars.d_fps_period, self.real_time);
-        let start = macroquad::time::get_time();
+        let start = macroquad::miniquad::date::now();
 
         // Update time tracking variables
         self.real_time_prev = self.real_time;
         self.real_time = real_time;
         self.real_time_delta = self.real_time - self.real_time_prev;
 
-        // Handle pause outside gamelogic so it works properly.
-        for (handle, player) in self.gs.players.iter() {
-            let input_prev = self.gs.inputs_prev.get(handle);
-            if !input_prev.pause && player.input.pause {
-                self.paused = !self.paused;
+        if self.playback.is_some() {
+            self.playback_frame(cvars);
+        } else {
+            // Handle pause outside gamelogic so it works properly.
+            let mut toggle = false;
+            for buffer in self.input_buffers.values_mut() {
+                toggle ^= buffer.take_pause();
+            }
+            if toggle {
+                if self.paused {
+                    self.resume();
+                } else {
+                    self.pause();
+                }
+            }
+            if !self.paused && !self.intro {
+                let dt_update = self.real_time_delta * self.speed(cvars);
+                for buffer in self.input_buffers.values_mut() {
+                    buffer.hold(dt_update);
+                }
+                self.gamelogic(cvars, dt_update);
             }
         }
-        if !self.paused {
-            let dt_update = self.real_time_delta * cvars.d_speed;
-            self.gamelogic(cvars, dt_update);
-        }
+        self.observers.dispatch(&mut self.gs.notifications);
 
-        let end = macroquad::time::get_time();
+        // Whatever time is left for this frame.
+        self.derived
+            .run(&self.map, cvars.sv_derive_budget_ms / 1000.0);
+
+        let end = macroquad::miniquad::date::now();
         self.update_durations
             .add(cvars.d_timing_samples, end - start);
     }
 
+    /// Process the replay's events for one rendered frame - up to the next input snapshot.
+    fn playback_frame(&mut self, cvars: &Cvars) {
+        let mut first = true;
+        loop {
+            let playback = self.playback.as_mut().unwrap();
+            if playback.is_finished() {
+                break;
+            }
+            let event = playback.replay.events[playback.next];
+            if event == Event::Snapshot && !first {
+                break;
+            }
+            first = false;
+            playback.next += 1;
+
+            match event {
+                Event::Snapshot => self.snapshot_inputs_impl(),
+                Event::Input { player, input } => {
+                    let player_handle = playback.player_handles[player as usize];
+                    self.input_impl(player_handle, input);
+                }
+                Event::Tick { game_time } => self.gamelogic_tick(cvars, game_time),
+                Event::Restore => self.gs = self.gs_fixed.clone(),
+                Event::Save => self.gs_fixed = self.gs.clone(),
+                Event::Checksum(expected) => {
+                    if playback.mismatch.is_none() && replay::checksum(&self.gs) != expected {
+                        playback.mismatch = Some(self.gs.frame_num);
+                        dbg_logf!(
+                            "Replay diverged after gamelogic frame {}",
+                            self.gs.frame_num
+                        );
+                    }
+                }
+            }
+        }
+    }
+
     fn gamelogic(&mut self, cvars: &Cvars, dt_update: f64) {
         // TODO prevent death spirals
         // LATER impl the other modes
@@ -159,7 +531,7 @@ impl Server {
         match cvars.sv_tickrate_mode {
             TickrateMode::Synchronized => {
                 let game_time_target = self.gs.game_time + dt_update;
-                self.gamelogic_tick(cvars, game_time_target);
+                self.live_tick(cvars, game_time_target, true);
             }
             TickrateMode::Fixed => {
                 let game_time_target = self.gs.game_time + self.dt_carry + dt_update;
@@ -174,7 +546,12 @@ impl Server {
                     if cvars.d_tickrate_remaining {
                         dbg_logf!("Remaining time: {}", remaining);
                     }
-                    self.gamelogic_tick(cvars, self.gs.game_time + dt);
+                    self.live_tick(cvars, self.gs.game_time + dt, true);
+                    if self.gs.frame_num == 0 {
+                        // A new match started, game time went back to 0.
+                        self.dt_carry = 0.0;
+                        break;
+                    }
                 }
             }
             TickrateMode::FixedOrSmaller => {
@@ -184,6 +561,7 @@ impl Server {
 
                 let dt_fixed = self.gs.game_time - self.gs_fixed.game_time;
                 let game_time_target = self.gs_fixed.game_time + dt_fixed + dt_update;
+                self.record(Event::Restore);
                 self.gs = self.gs_fixed.clone();
                 let mut remaining;
                 loop {
@@ -191,23 +569,44 @@ impl Server {
                     remaining = game_time_target - self.gs.game_time;
                     let dt = 1.0 / cvars.sv_tickrate_fixed_fps;
                     if remaining < dt {
+                        self.record(Event::Save);
                         self.gs_fixed = self.gs.clone();
                         break;
                     }
-                    self.gamelogic_tick(cvars, self.gs.game_time + dt);
+                    self.live_tick(cvars, self.gs.game_time + dt, true);
+                    if self.gs.frame_num == 0 {
+                        // A new match started, game time went back to 0 and `gs_fixed` is already the new match.
+                        return;
+                    }
                 }
                 if cvars.d_tickrate_remaining {
                     dbg_logf!("Remaining time: {}", remaining);
                 }
-                self.gamelogic_tick(cvars, self.gs.game_time + remaining);
+                self.live_tick(cvars, self.gs.game_time + remaining, false);
                 // TODO skip too small steps?
             }
+            TickrateMode::Lockstep => {
+                // The input was already collected and snapshotted for this frame.
+                self.lockstep_tick(cvars);
+            }
         }
         // TODO don't use game_time here?
     }
 
+    /// A tick with the input received since the previous one, see `apply_input_buffers`.
+    fn live_tick(&mut self, cvars: &Cvars, game_time: f64, consume: bool) {
+        self.apply_input_buffers(consume);
+        self.gamelogic_tick(cvars, game_time);
+        if consume {
+            // Taps were used up, players now hold only what's actually held
+            // so a key released and pressed again before the next tick is a new press.
+            self.apply_input_buffers(false);
+        }
+    }
+
     fn gamelogic_tick(&mut self, cvars: &Cvars, game_time: f64) {
-        let start = macroquad::time::get_time();
+        let start = macroquad::miniquad::date::now();
+        self.record(Event::Tick { game_time });
         self.gamelogic_fps.tick(cvars.d_fps_period, self.real_time);
 
         // Update time tracking variables (in seconds)
@@ -220,43 +619,564 @@ impl Server {
         self.gs.game_time_prev = self.gs.game_time;
         self.gs.game_time = game_time;
         self.gs.dt = self.gs.game_time - self.gs.game_time_prev;
+        self.gs.frame_num += 1;
+
+        debugging::cleanup(cvars);
+        let destroyed_walls = self.map.destroyed_walls().len();
+
+        self.system_timings
+            .start(cvars.d_timing_systems, cvars.d_timing_samples);
+        // Whatever happened since the last tick, e.g. players connecting.
+        self.gs
+            .trace
+            .system_done(cvars, self.gs.frame_num, "between_ticks", &self.gs.rng);
+
+        let Self {
+            schedule,
+            gs,
+            map,
+            system_timings,
+            input_acks,
+            confirmed_shots,
+            ..
+        } = self;
+        // Right after shooting - projectiles can hit something and get removed later in the same tick.
+        schedule.run(cvars, gs, map, system_timings, |system, gs| {
+            if system == "shooting" {
+                confirm_shots(gs, input_acks, confirmed_shots);
+            }
+        });
+
+        // Edges (e.g. switching weapons) are relative to the previous tick, not the previous rendered frame,
+        // otherwise a key held during a frame with several ticks would trigger in each of them.
+        self.gs.inputs_prev.snapshot(&self.gs.players);
+
+        // Walls destroyed this frame changed which tiles are passable.
+        if self.map.destroyed_walls().len() != destroyed_walls {
+            self.derived = Derived::new(&self.map);
+        }
+
+        // Last so the state after this frame is the same as a freshly created server's.
+        // Each match gets the next seed so they're different but still reproducible from `d_seed`.
+        if sys_match::restart_due(&self.gs) {
+            let seed = self.gs.rng.seed.wrapping_add(1);
+            self.reset_match(cvars, seed);
+        }
+
+        let interval = cvars.d_replay_checksum_interval;
+        if self.recording.is_some() && interval > 0 && self.gs.frame_num % interval == 0 {
+            let checksum = self.state_checksum();
+            self.record(Event::Checksum(checksum));
+        }
 
-        debugging::cleanup();
+        let end = macroquad::miniquad::date::now();
+        self.gamelogic_durations
+            .add(cvars.d_timing_samples, end - start);
+    }
+}
 
-        dbg_textf!("{}", env!("GIT_VERSION"));
-        dbg_textd!(self.gs.game_time);
-        dbg_textd!(self.gs.game_time_prev);
+/// Remember which input fired each new projectile so the owner's client can match its predictions.
+fn confirm_shots(
+    gs: &GameState,
+    input_acks: &FnvHashMap<Index, u32>,
+    confirmed_shots: &mut Vec<(Index, ConfirmedShot)>,
+) {
+    for &handle in &gs.shots {
+        let projectile = &gs.projectiles[handle];
+        if let Some(&seq) = input_acks.get(&projectile.owner) {
+            let shot = ConfirmedShot {
+                seq,
+                weapon: projectile.weapon,
+                projectile: handle,
+            };
+            confirmed_shots.push((projectile.owner, shot));
+        }
+    }
+}
 
-        systems::cleanup(cvars, &mut self.gs);
+/// Bots, their vehicles and everything the game mode needs but no human players yet.
+fn new_match(cvars: &Cvars, map: &Map, roster: &Roster, seed: u64) -> GameState {
+    let mut gs = GameState::new(seed);
 
-        sys_ai::ai(cvars, &mut self.gs);
+    // The training range has dummies instead of bots.
+    let bots_count = if cvars.g_range {
+        0
+    } else {
+        map.spawns().len().min(cvars.bots_max)
+    };
+    dbg_logf!(
+        "Spawns per bot: {}",
+        map.spawns().len() as f64 / bots_count as f64
+    );
+    dbg_logf!(
+        "Tiles per bot: {}",
+        (map.width() * map.height()) as f64 / bots_count as f64
+    );
+    // LATER Race mode should pass `racers_only`.
+    let assignments = roster.assign(&mut gs.rng.world, bots_count, map.pinned_bots(), false);
+    for assignment in assignments {
+        let ai_handle = sys_ai::spawn_bot(cvars, &mut gs, assignment.name, cvars.bots_difficulty);
+        if let Some(index) = assignment.persona {
+            let ai = &mut gs.ais[ai_handle];
+            let player = &mut gs.players[ai.player];
+            roster.personas[index].apply(cvars, player, ai);
+        }
+    }
 
-        systems::respawning(cvars, &mut self.gs, &self.map);
+    for handle in gs.players.iter_handles() {
+        systems::spawn_vehicle(cvars, &mut gs, map, handle, false);
+    }
 
-        systems::player_logic(&mut self.gs);
+    if cvars.g_range {
+        let scenario = sys_range::training_range(map);
+        sys_range::start(cvars, &mut gs, scenario);
+    }
 
-        systems::vehicle_logic(cvars, &mut self.gs);
+    if cvars.g_game_mode == GameMode::CaptureTheFlag {
+        sys_ctf::start(&mut gs, map);
+    }
 
-        // It's probably a good idea to shoot before movement so that when turning
-        // the shot angle corresponds to the vehicle angle the player saw last frame.
-        systems::shooting(cvars, &mut self.gs);
+    systems::spawn_map_pickups(cvars, &mut gs, map);
 
-        systems::vehicle_movement(cvars, &mut self.gs, &self.map);
+    sys_match::start(cvars, &mut gs);
 
-        systems::gm_turning(cvars, &mut self.gs);
+    gs
+}
 
-        systems::projectiles(cvars, &mut self.gs, &self.map);
+/// Add the player to the match and spawn their vehicle.
+fn join(cvars: &Cvars, gs: &mut GameState, map: &Map, player: Player) -> Index {
+    let player_handle = gs.players.insert(player);
+    sys_ctf::assign_team(gs, player_handle);
+    systems::spawn_vehicle(cvars, gs, map, player_handle, true);
+    player_handle
+}
 
-        systems::projectiles_timeout(cvars, &mut self.gs);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        systems::self_destruct(cvars, &mut self.gs);
+    use std::fs;
 
-        dbg_textf!("vehicle count: {}", self.gs.vehicles.len());
-        dbg_textf!("projectile count: {}", self.gs.projectiles.len());
-        dbg_textf!("explosion count: {}", self.gs.explosions.len());
+    use crate::{
+        game_state::{MatchState, Winner},
+        map::{Kind, Vec2f},
+        snapshot,
+    };
 
-        let end = macroquad::time::get_time();
-        self.gamelogic_durations
-            .add(cvars.d_timing_samples, end - start);
+    /// An empty map with a single spawn in the middle and one player driving forward.
+    fn driving_server(cvars: &Cvars) -> (Server, Index) {
+        let size = 41;
+        let rows: Vec<_> = (0..size)
+            .map(|y| {
+                let tiles: Vec<_> = (0..size)
+                    .map(|x| {
+                        if x == size / 2 && y == size / 2 {
+                            "g_spawn"
+                        } else {
+                            "g1"
+                        }
+                    })
+                    .collect();
+                tiles.join(" ")
+            })
+            .collect();
+        let map = map::tests::load_test_map(&rows.join("\n"));
+        let mut server = Server::new(cvars, map);
+        let player_handle = server.connect_with_vehicle(cvars, "Player", Some(VehicleType::Tank));
+        server.input(player_handle, Input::new_up());
+        (server, player_handle)
+    }
+
+    fn vehicle_pos(server: &Server, player_handle: Index) -> Vec2f {
+        let vehicle_handle = server.gs.players[player_handle].vehicle.unwrap();
+        *server.gs.vehicles[vehicle_handle].pos
+    }
+
+    #[test]
+    fn test_pause_and_step() {
+        let cvars = Cvars {
+            bots_max: 0,
+            g_match_countdown: 0.0,
+            ..Cvars::default()
+        };
+        let (mut server, player_handle) = driving_server(&cvars);
+        server.update(&cvars, 0.1);
+        let game_time = server.gs.game_time;
+        assert!(game_time > 0.0);
+
+        // Real time keeps going but the game doesn't.
+        server.pause();
+        server.update(&cvars, 5.0);
+        assert_eq!(server.gs.game_time, game_time);
+
+        let pos = vehicle_pos(&server, player_handle);
+        server.step(&cvars, 3);
+        assert!(server.paused);
+        let dt = 1.0 / cvars.sv_tickrate_fixed_fps;
+        assert!((server.gs.game_time - (game_time + 3.0 * dt)).abs() < 1e-9);
+        assert_ne!(vehicle_pos(&server, player_handle), pos);
+
+        // Only the time after resuming counts, not the time spent paused.
+        server.resume();
+        let game_time = server.gs.game_time;
+        server.update(&cvars, 5.1);
+        assert!((server.gs.game_time - (game_time + 0.1)).abs() < 1e-9);
+
+        server.set_time_scale(1000.0);
+        assert_eq!(server.time_scale, TIME_SCALE_MAX);
+        server.set_time_scale(0.0);
+        assert_eq!(server.time_scale, TIME_SCALE_MIN);
+    }
+
+    #[test]
+    fn test_input_between_ticks() {
+        // Two rendered frames per tick.
+        let cvars = Cvars {
+            bots_max: 0,
+            g_match_countdown: 0.0,
+            sv_tickrate_mode: TickrateMode::Fixed,
+            sv_tickrate_fixed_fps: 8.0,
+            ..Cvars::default()
+        };
+        let frame = |server: &mut Server, player_handle, input, real_time| {
+            server.snapshot_inputs();
+            server.input(player_handle, input);
+            server.update(&cvars, real_time);
+        };
+        let tap = Input {
+            next_weapon: true,
+            ..Input::new_up()
+        };
+        let weapon =
+            |server: &Server, player_handle: Index| server.gs.players[player_handle].cur_weapon;
+
+        // Pressed and released before the tick - still switches, exactly once.
+        let (mut server, player_handle) = driving_server(&cvars);
+        let initial = weapon(&server, player_handle);
+        frame(&mut server, player_handle, tap, 1.0 / 16.0);
+        assert_eq!(server.gs.frame_num, 0);
+        frame(&mut server, player_handle, Input::new_up(), 2.0 / 16.0);
+        assert_eq!(server.gs.frame_num, 1);
+        let switched = weapon(&server, player_handle);
+        assert_ne!(switched, initial);
+        frame(&mut server, player_handle, Input::new_up(), 3.0 / 16.0);
+        frame(&mut server, player_handle, Input::new_up(), 4.0 / 16.0);
+        assert_eq!(server.gs.frame_num, 2);
+        assert_eq!(weapon(&server, player_handle), switched);
+
+        // Held through a long frame with several ticks - one more switch, not one per tick.
+        frame(&mut server, player_handle, tap, 1.0);
+        assert!(server.gs.frame_num > 3);
+        frame(&mut server, player_handle, tap, 1.5);
+        let (mut reference, reference_handle) = driving_server(&cvars);
+        for i in 0..2 {
+            frame(
+                &mut reference,
+                reference_handle,
+                tap,
+                (i * 2 + 1) as f64 / 16.0,
+            );
+            let real_time = (i * 2 + 2) as f64 / 16.0;
+            frame(&mut reference, reference_handle, Input::new_up(), real_time);
+        }
+        assert_eq!(
+            weapon(&server, player_handle),
+            weapon(&reference, reference_handle)
+        );
+
+        // Throttle released halfway to the tick gives half the acceleration.
+        // Without friction so speed only depends on acceleration.
+        let cvars = Cvars {
+            g_tank_friction_const: 0.0,
+            g_tank_friction_linear: 0.0,
+            ..cvars
+        };
+        let frame = |server: &mut Server, player_handle, input, real_time| {
+            server.snapshot_inputs();
+            server.input(player_handle, input);
+            server.update(&cvars, real_time);
+        };
+        let speed = |release| {
+            let (mut server, player_handle) = driving_server(&cvars);
+            frame(&mut server, player_handle, Input::new_up(), 1.0 / 16.0);
+            let input = if release {
+                Input::new()
+            } else {
+                Input::new_up()
+            };
+            frame(&mut server, player_handle, input, 2.0 / 16.0);
+            assert_eq!(server.gs.frame_num, 1);
+            let vehicle_handle = server.gs.players[player_handle].vehicle.unwrap();
+            server.gs.vehicles[vehicle_handle].vel.magnitude()
+        };
+        let ratio = speed(true) / speed(false);
+        assert!((ratio - 0.5).abs() < 0.01, "{}", ratio);
+    }
+
+    #[test]
+    fn test_determinism() {
+        let cvars = Cvars {
+            d_seed: 1234,
+            bots_max: 6,
+            ..Cvars::default()
+        };
+        // A scripted human and bots fighting for 1000 ticks, returns the final snapshot.
+        let battle = || {
+            let map = map::tests::load_test_map(&fs::read_to_string("maps/Atrium.map").unwrap());
+            let mut server = Server::new(&cvars, map);
+            let player_handle = server.connect(&cvars, "Player");
+            let mut shots = 0;
+            for tick in 0..1000 {
+                if tick % 100 == 0 {
+                    let input = Input {
+                        up: tick % 200 == 0,
+                        left: tick % 300 == 0,
+                        fire: true,
+                        next_weapon: tick % 400 == 0,
+                        ..Input::new()
+                    };
+                    server.input(player_handle, input);
+                }
+                server.snapshot_inputs();
+                server.step(&cvars, 1);
+                shots += server.gs.shots.len();
+            }
+            assert_eq!(server.gs.frame_num, 1000);
+            assert!(shots > 0);
+            snapshot::snapshot(&server.gs, &server.map)
+        };
+        assert!(battle() == battle());
+    }
+
+    #[test]
+    fn test_tick_lockstep() {
+        let cvars = Cvars {
+            d_seed: 99,
+            bots_max: 2,
+            ..Cvars::default()
+        };
+        let new_server = || {
+            let map = map::tests::load_test_map(&fs::read_to_string("maps/Atrium.map").unwrap());
+            let mut server = Server::new(&cvars, map);
+            let player1 = server.connect(&cvars, "Player 1");
+            let player2 = server.connect(&cvars, "Player 2");
+            (server, player1, player2)
+        };
+        let (mut a, a1, a2) = new_server();
+        let (mut b, b1, b2) = new_server();
+        // Wall clock settings don't affect ticks.
+        b.pause();
+        b.set_time_scale(4.0);
+
+        for tick in 0..2000 {
+            let input1 = Input {
+                up: tick % 300 < 200,
+                left: tick % 500 < 50,
+                fire: tick % 3 == 0,
+                ..Input::new()
+            };
+            let input2 = Input {
+                down: tick % 400 < 100,
+                right: tick % 250 < 60,
+                fire: tick % 7 == 0,
+                next_weapon: tick % 600 == 0,
+                ..Input::new()
+            };
+            a.tick(&cvars, &[(a1, input1), (a2, input2)]);
+            b.tick(&cvars, &[(b1, input1), (b2, input2)]);
+            assert_eq!(a.state_checksum(), b.state_checksum(), "tick {}", tick);
+        }
+        assert_eq!(a.gs.frame_num, 2000);
+        assert_eq!(a.gs.game_time, 2000.0 * (1.0 / cvars.sv_tickrate_fixed_fps));
+        let deaths: i32 = a.gs.players.iter().map(|(_, p)| p.score.deaths).sum();
+        assert!(deaths > 0);
+    }
+
+    #[test]
+    fn test_edit_map() {
+        let cvars = Cvars {
+            bots_max: 0,
+            ..Cvars::default()
+        };
+        let (mut server, player_handle) = driving_server(&cvars);
+        let surfaces = server.map.surfaces();
+        let wall = surfaces.iter().position(|s| s.kind == Kind::Wall).unwrap();
+        let wall = Tile::new(wall, 0);
+
+        // The vehicle is in the center of the spawn tile so it's moved to a neighbor.
+        let spawn = Vec2u::new(20, 20);
+        let pos = vehicle_pos(&server, player_handle);
+        assert_eq!(server.map.tile_pos(pos).index, spawn);
+        server.set_tile(spawn, wall).unwrap();
+        let pos = vehicle_pos(&server, player_handle);
+        assert!(!server.map.is_wall(pos));
+        assert_eq!(pos.distance(server.map.tile_center(spawn)), map::TILE_SIZE);
+        let vehicle_handle = server.gs_fixed.players[player_handle].vehicle.unwrap();
+        assert_eq!(*server.gs_fixed.vehicles[vehicle_handle].pos, pos);
+        assert!(server.map.spawns().is_empty());
+
+        assert!(server.tile(Vec2u::new(41, 0)).is_err());
+        assert!(server.set_tile(Vec2u::new(0, 41), wall).is_err());
+
+        // Cropping pushes the vehicle back inside.
+        server.resize_map(Vec2u::new(10, 12), wall).unwrap();
+        server.set_tile(Vec2u::new(3, 4), wall).unwrap();
+        let pos = vehicle_pos(&server, player_handle);
+        assert_eq!(server.map.tile_pos(pos).index, Vec2u::new(9, 11));
+        server.update(&cvars, 0.1);
+
+        let text = server.export_map();
+        let reloaded = map::load_map(&text, server.map.surfaces().to_vec()).unwrap();
+        assert_eq!(map::save_map(&reloaded), text);
+        assert_eq!(reloaded.size(), Vec2u::new(10, 12));
+        assert_eq!(
+            reloaded.tile(Vec2u::new(3, 4)).unwrap().surface_index,
+            wall.surface_index
+        );
+    }
+
+    #[test]
+    fn test_step_time_scale() {
+        let cvars = Cvars {
+            bots_max: 0,
+            sv_tickrate_fixed_fps: 60.0,
+            ..Cvars::default()
+        };
+
+        let (mut normal, player_normal) = driving_server(&cvars);
+        let start = vehicle_pos(&normal, player_normal);
+        normal.step(&cvars, 600);
+
+        let (mut fast, player_fast) = driving_server(&cvars);
+        fast.set_time_scale(2.0);
+        fast.step(&cvars, 300);
+
+        // The same simulated time, the physics only differ by the length of the timestep.
+        assert_eq!(normal.gs.frame_num, 600);
+        assert_eq!(fast.gs.frame_num, 300);
+        assert!((normal.gs.game_time - 10.0).abs() < 1e-9);
+        assert!((fast.gs.game_time - 10.0).abs() < 1e-9);
+        let normal_pos = vehicle_pos(&normal, player_normal);
+        let fast_pos = vehicle_pos(&fast, player_fast);
+        let distance = normal_pos.distance(start);
+        assert!(distance > 500.0, "{}", distance);
+        assert!(
+            normal_pos.distance(fast_pos) < distance * 0.01,
+            "{} {}",
+            normal_pos,
+            fast_pos
+        );
+
+        // Stepping in smaller chunks is exactly the same.
+        let (mut chunked, player_chunked) = driving_server(&cvars);
+        chunked.set_time_scale(2.0);
+        for _ in 0..10 {
+            chunked.step(&cvars, 30);
+        }
+        assert_eq!(replay::checksum(&chunked.gs), replay::checksum(&fast.gs));
+        assert_eq!(vehicle_pos(&chunked, player_chunked), fast_pos);
+    }
+
+    #[test]
+    fn test_match_flow() {
+        let cvars = Cvars {
+            bots_max: 0,
+            g_match_countdown: 1.0,
+            g_match_kill_limit: 3,
+            g_match_restart_delay: 2.0,
+            sv_tickrate_fixed_fps: 60.0,
+            ..Cvars::default()
+        };
+        let (mut server, player_handle) = driving_server(&cvars);
+        let start = vehicle_pos(&server, player_handle);
+
+        // Frozen and invulnerable during the countdown.
+        server.step(&cvars, 30);
+        assert_eq!(server.gs.match_state, MatchState::Countdown { until: 1.0 });
+        assert_eq!(vehicle_pos(&server, player_handle), start);
+        let vehicle_handle = server.gs.players[player_handle].vehicle.unwrap();
+        let hp = server.gs.vehicles[vehicle_handle].hp;
+        systems::damage(
+            &cvars,
+            &mut server.gs,
+            player_handle,
+            vehicle_handle,
+            None,
+            hp,
+            crate::cvars::DamageType::Kinetic,
+            None,
+        );
+        assert_eq!(server.gs.vehicles[vehicle_handle].hp, hp);
+
+        server.step(&cvars, 31);
+        assert_eq!(server.gs.match_state, MatchState::Playing);
+        assert_ne!(vehicle_pos(&server, player_handle), start);
+
+        server.gs.players[player_handle].score.kills = 3;
+        server.step(&cvars, 1);
+        let winner = Some(Winner::Player(player_handle));
+        assert!(matches!(
+            server.gs.match_state,
+            MatchState::Finished { winner: w, .. } if w == winner
+        ));
+        let end = vehicle_pos(&server, player_handle);
+        server.step(&cvars, 60);
+        assert_eq!(vehicle_pos(&server, player_handle), end);
+        assert_eq!(server.matches_played, 0);
+
+        // A new match with the next seed, the player keeps their handle.
+        server.step(&cvars, 65);
+        assert_eq!(server.matches_played, 1);
+        assert_eq!(server.gs.rng.seed, cvars.d_seed + 1);
+        assert!(server.gs.game_time < 1.0);
+        assert!(matches!(
+            server.gs.match_state,
+            MatchState::Countdown { .. }
+        ));
+        assert_eq!(server.gs.players[player_handle].score.kills, 0);
+        assert_eq!(server.gs.players[player_handle].name, "Player");
+    }
+
+    #[test]
+    fn test_reset_match() {
+        let new_server = |seed| {
+            let cvars = Cvars {
+                d_seed: seed,
+                bots_max: 4,
+                g_match_countdown: 0.0,
+                ..Cvars::default()
+            };
+            let map = map::tests::load_test_map(&fs::read_to_string("maps/Atrium.map").unwrap());
+            let mut server = Server::new(&cvars, map);
+            let player1 = server.connect(&cvars, "Player 1");
+            let player2 =
+                server.connect_with_vehicle(&cvars, "Player 2", Some(VehicleType::Hummer));
+            (cvars, server, player1, player2)
+        };
+
+        let (cvars, mut server, player1, player2) = new_server(5);
+        let input = Input {
+            up: true,
+            fire: true,
+            ..Input::new()
+        };
+        server.input(player1, input);
+        server.input(player2, input);
+        server.step(&cvars, 500);
+        assert_ne!(server.state_checksum(), new_server(5).1.state_checksum());
+
+        server.reset_match(&cvars, 6);
+        let (_, fresh, fresh1, fresh2) = new_server(6);
+        assert_eq!((player1, player2), (fresh1, fresh2));
+        assert_eq!(server.state_checksum(), fresh.state_checksum());
+        assert_eq!(
+            snapshot::snapshot(&server.gs, &server.map),
+            snapshot::snapshot(&fresh.gs, &fresh.map)
+        );
+        assert_eq!(
+            snapshot::snapshot(&server.gs_fixed, &server.map),
+            snapshot::snapshot(&fresh.gs_fixed, &fresh.map)
+        );
+        assert_eq!(server.matches_played, 1);
     }
 }
@@ -0,0 +1,1160 @@
+//! Saving the whole game state into bytes and loading it back.
+//!
+//! The state of destructible walls is part of the map but changes during the match so it's saved too.
+//!
+//! Restoring a snapshot and continuing the simulation gives exactly the same results
+//! as never having taken it. This includes the RNG and the internal layout of arenas
+//! so new entities get the same handles.
+//!
+//! Not saved because they're derived from the rest or only for debugging:
+//! - `range_uniform11` (constant)
+//! - `flybys`, `sound_channels` and `engine_sounds` (recomputed every frame)
+//! - `threats` (recomputed every frame)
+//! - `particles`, `decals`, `track_distances` and `trails` (cosmetic, don't affect anything else)
+//! - `audio_events` and `notifications` (for the frontend)
+//! - `shots` and `scraping` (only this frame's, cleared every frame)
+//! - `vehicle_grid` (rebuilt on the first update)
+//! - `collision_trace`, `trace` and `projectile_rays`
+//! - `life_records` (bookkeeping, restoring starts with empty records)
+
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+use rand::{prelude::SmallRng, SeedableRng};
+use thunderdome::{Arena, Index};
+
+use crate::{
+    cvars::{CooldownGroup, Cvars},
+    effects::Effect,
+    entities::{
+        Ai, Altitude, Ammo, Armor, Flare, Hitbox, Loadout, LoadoutPreset, Lock, Obb, Perception,
+        Pickup, PickupKind, Player, Powerup, PowerupKind, Projectile, Respawn, Score, Team,
+        Tracked, Vehicle, VehicleType, Weapon, WeaponStats,
+    },
+    game_state::{Dda, GameState, Input, InputsPrev, Kill, MatchState, Winner},
+    map::{Map, Vec2f, Vec2u},
+    sys_ctf::{Ctf, Flag, FlagState},
+    sys_hazards::HazardState,
+    sys_range::{DummySpec, DummyState, Range, RangeHit, RangeScenario},
+};
+
+const MAGIC: &[u8; 4] = b"RWSS";
+const VERSION: u8 = 29;
+
+pub fn snapshot(gs: &GameState, map: &Map) -> Vec<u8> {
+    let mut w = Vec::new();
+    w.extend_from_slice(MAGIC);
+    w.push(VERSION);
+
+    gs.rng.seed.write(&mut w);
+    for stream in gs.rng.streams() {
+        write_rng(&mut w, &stream.rng);
+    }
+    gs.game_time.write(&mut w);
+    gs.game_time_prev.write(&mut w);
+    gs.dt.write(&mut w);
+    gs.frame_num.write(&mut w);
+
+    // Sorted so the same state always gives the same bytes.
+    let mut rail_hits: Vec<_> = gs.rail_hits.iter().map(|(&k, &v)| (k, v)).collect();
+    rail_hits.sort_unstable();
+    rail_hits.write(&mut w);
+    let mut bounces: Vec<_> = gs.bounces.iter().map(|(&k, &v)| (k, v)).collect();
+    bounces.sort_unstable();
+    bounces.write(&mut w);
+    let mut debris: Vec<_> = gs.debris.iter().map(|(&k, &v)| (k, v)).collect();
+    debris.sort_unstable();
+    debris.write(&mut w);
+    let mut projectile_hits: Vec<_> = gs.projectile_hits.iter().copied().collect();
+    projectile_hits.sort_unstable();
+    projectile_hits.write(&mut w);
+    gs.effects.write(&mut w);
+
+    write_arena(&mut w, &gs.ais);
+    write_arena(&mut w, &gs.players);
+    write_arena(&mut w, &gs.vehicles);
+    write_arena(&mut w, &gs.projectiles);
+    write_arena(&mut w, &gs.pickups);
+    write_arena(&mut w, &gs.flares);
+
+    gs.dda.write(&mut w);
+    gs.hazards.write(&mut w);
+    gs.range.write(&mut w);
+    gs.ctf.write(&mut w);
+    gs.match_state.write(&mut w);
+    gs.match_start.write(&mut w);
+    gs.match_end.write(&mut w);
+    gs.match_ticks.write(&mut w);
+
+    let mut inputs_prev: Vec<_> = gs.inputs_prev.0.iter().map(|(&k, &v)| (k, v)).collect();
+    inputs_prev.sort_unstable_by_key(|&(handle, _)| handle);
+    inputs_prev.write(&mut w);
+
+    let destroyed: Vec<_> = map
+        .destroyed_walls()
+        .iter()
+        .map(|&(index, _)| index)
+        .collect();
+    destroyed.write(&mut w);
+    let mut damage: Vec<_> = map.damaged_walls().collect();
+    damage.sort_unstable_by_key(|&(index, _)| (index.y, index.x));
+    damage.write(&mut w);
+
+    w
+}
+
+/// The map must be the one the snapshot was taken on, its destructible walls are set to the saved state.
+pub fn restore(data: &[u8], map: &mut Map) -> Result<GameState, SnapshotError> {
+    let r = &mut Reader { data, pos: 0 };
+    if r.bytes(4)? != MAGIC {
+        return Err(r.error("not a snapshot"));
+    }
+    let version = u8::read(r)?;
+    if version != VERSION {
+        return Err(r.error(format!("unsupported version {}", version)));
+    }
+
+    let mut gs = GameState::new(Snap::read(r)?);
+    for stream in gs.rng.streams_mut() {
+        stream.rng = read_rng(r)?;
+    }
+    gs.game_time = Snap::read(r)?;
+    gs.game_time_prev = Snap::read(r)?;
+    gs.dt = Snap::read(r)?;
+    gs.frame_num = Snap::read(r)?;
+
+    let rail_hits: Vec<(Index, Index)> = Snap::read(r)?;
+    gs.rail_hits = rail_hits.into_iter().collect();
+    let bounces: Vec<(Index, u32)> = Snap::read(r)?;
+    gs.bounces = bounces.into_iter().collect();
+    let debris: Vec<(Index, Index)> = Snap::read(r)?;
+    gs.debris = debris.into_iter().collect();
+    let projectile_hits: Vec<Index> = Snap::read(r)?;
+    gs.projectile_hits = projectile_hits.into_iter().collect();
+    gs.effects = Snap::read(r)?;
+
+    gs.ais = read_arena(r)?;
+    gs.players = read_arena(r)?;
+    gs.vehicles = read_arena(r)?;
+    gs.projectiles = read_arena(r)?;
+    gs.pickups = read_arena(r)?;
+    gs.flares = read_arena(r)?;
+
+    gs.dda = Snap::read(r)?;
+    gs.hazards = Snap::read(r)?;
+    gs.range = Snap::read(r)?;
+    gs.ctf = Snap::read(r)?;
+    gs.match_state = Snap::read(r)?;
+    gs.match_start = Snap::read(r)?;
+    gs.match_end = Snap::read(r)?;
+    gs.match_ticks = Snap::read(r)?;
+
+    let inputs_prev: Vec<(Index, Input)> = Snap::read(r)?;
+    gs.inputs_prev = InputsPrev(inputs_prev.into_iter().collect());
+
+    let destroyed: Vec<Vec2u> = Snap::read(r)?;
+    let damage: Vec<(Vec2u, f64)> = Snap::read(r)?;
+
+    if r.pos != data.len() {
+        return Err(r.error("trailing data"));
+    }
+    // Only touch the map once everything was read successfully.
+    map.set_wall_state(&destroyed, &damage);
+    Ok(gs)
+}
+
+/// Error while restoring a snapshot, `pos` is the byte offset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotError {
+    pub pos: usize,
+    pub msg: String,
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "byte {}: {}", self.pos, self.msg)
+    }
+}
+
+impl Error for SnapshotError {}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, msg: impl Into<String>) -> SnapshotError {
+        SnapshotError {
+            pos: self.pos,
+            msg: msg.into(),
+        }
+    }
+
+    fn bytes(&mut self, cnt: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.data.len() - self.pos < cnt {
+            return Err(self.error("unexpected end of data"));
+        }
+        let bytes = &self.data[self.pos..self.pos + cnt];
+        self.pos += cnt;
+        Ok(bytes)
+    }
+}
+
+/// The RNG's state is private but its `Debug` impl prints it
+/// and `from_seed` takes it as is.
+fn write_rng(w: &mut Vec<u8>, rng: &SmallRng) {
+    let text = format!("{:?}", rng);
+    let start = text.find('[').unwrap() + 1;
+    let end = text.find(']').unwrap();
+    for part in text[start..end].split(", ") {
+        part.parse::<u64>().unwrap().write(w);
+    }
+}
+
+fn read_rng(r: &mut Reader<'_>) -> Result<SmallRng, SnapshotError> {
+    let mut seed = [0; 32];
+    for chunk in seed.chunks_mut(8) {
+        chunk.copy_from_slice(&u64::read(r)?.to_le_bytes());
+    }
+    Ok(SmallRng::from_seed(seed))
+}
+
+/// Occupied entries and the free list.
+///
+/// The free list isn't public so it's found by inserting into a copy
+/// until the arena has to grow - that's when new entries start with generation 1.
+fn write_arena<T: ArenaItem>(w: &mut Vec<u8>, arena: &Arena<T>) {
+    let mut probe = arena.clone();
+    let mut free = Vec::new();
+    let storage_len = loop {
+        let index = probe.insert(T::placeholder());
+        if index.generation() == 1 {
+            break index.slot();
+        }
+        free.push(index);
+    };
+
+    storage_len.write(w);
+    free.write(w);
+    (arena.len() as u32).write(w);
+    for (index, value) in arena.iter() {
+        index.write(w);
+        value.write(w);
+    }
+}
+
+fn read_arena<T: ArenaItem>(r: &mut Reader<'_>) -> Result<Arena<T>, SnapshotError> {
+    let storage_len = u32::read(r)?;
+    let free: Vec<Index> = Snap::read(r)?;
+    let len = u32::read(r)?;
+    let mut values = Vec::new();
+    for _ in 0..len {
+        let index = Index::read(r)?;
+        let value = T::read(r)?;
+        values.push((index, value));
+    }
+
+    // Fill slots in order so the arena never has to create free entries on its own.
+    // Free slots get a placeholder with the generation before the one the next insert will use.
+    let mut slots: Vec<Option<(Index, T)>> = (0..storage_len).map(|_| None).collect();
+    for (index, value) in values {
+        let slot = slots
+            .get_mut(index.slot() as usize)
+            .ok_or_else(|| r.error(format!("slot {} out of bounds", index.slot())))?;
+        *slot = Some((index, value));
+    }
+    let mut removed = Vec::new();
+    for &index in &free {
+        let slot = slots
+            .get_mut(index.slot() as usize)
+            .ok_or_else(|| r.error(format!("slot {} out of bounds", index.slot())))?;
+        let prev = Index::from_bits(index.to_bits() - (1 << 32))
+            .ok_or_else(|| r.error("invalid generation of a free slot"))?;
+        *slot = Some((prev, T::placeholder()));
+        removed.push(prev);
+    }
+
+    let mut arena = Arena::new();
+    for (slot, entry) in slots.into_iter().enumerate() {
+        let (index, value) = entry.ok_or_else(|| r.error(format!("slot {} missing", slot)))?;
+        arena.insert_at(index, value);
+    }
+    // Removed entries are reused last-in first-out.
+    for &index in removed.iter().rev() {
+        arena.remove(index);
+    }
+    Ok(arena)
+}
+
+/// Entities stored in arenas need a value to fill free slots with while saving and loading.
+trait ArenaItem: Snap + Clone {
+    fn placeholder() -> Self;
+}
+
+fn placeholder_handle() -> Index {
+    Index::from_bits(1 << 32).unwrap()
+}
+
+impl ArenaItem for Ai {
+    fn placeholder() -> Self {
+        Ai::new(&Cvars::default(), placeholder_handle(), 0)
+    }
+}
+
+impl ArenaItem for Player {
+    fn placeholder() -> Self {
+        Player::new(String::new())
+    }
+}
+
+impl ArenaItem for Vehicle {
+    fn placeholder() -> Self {
+        let cvars = Cvars::default();
+        let owner = placeholder_handle();
+        Vehicle::new(&cvars, Vec2f::zero(), 0.0, VehicleType::Tank, 0, 0.0, owner)
+    }
+}
+
+impl ArenaItem for Projectile {
+    fn placeholder() -> Self {
+        Projectile {
+            weapon: Weapon::Mg,
+            pos: Vec2f::zero(),
+            vel: Vec2f::zero(),
+            angle: 0.0,
+            turn_rate: 0.0,
+            explode_time: 0.0,
+            spawn_time: 0.0,
+            owner: placeholder_handle(),
+            lock: None,
+            retarget_time: 0.0,
+            altitude: None,
+        }
+    }
+}
+
+impl ArenaItem for Pickup {
+    fn placeholder() -> Self {
+        Pickup::new(Vec2f::zero(), PickupKind::Armor)
+    }
+}
+
+impl ArenaItem for Flare {
+    fn placeholder() -> Self {
+        Flare {
+            pos: Vec2f::zero(),
+            spawn_time: 0.0,
+            owner: placeholder_handle(),
+        }
+    }
+}
+
+trait Snap: Sized {
+    fn write(&self, w: &mut Vec<u8>);
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError>;
+}
+
+macro_rules! snap_num {
+    ($($t:ty),*) => {
+        $(
+            impl Snap for $t {
+                fn write(&self, w: &mut Vec<u8>) {
+                    w.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+                    let bytes = r.bytes(std::mem::size_of::<$t>())?;
+                    Ok(<$t>::from_le_bytes(bytes.try_into().unwrap()))
+                }
+            }
+        )*
+    };
+}
+
+snap_num!(u8, u16, u32, u64, i32, i64, f64);
+
+impl Snap for bool {
+    fn write(&self, w: &mut Vec<u8>) {
+        (*self as u8).write(w);
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        match u8::read(r)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(r.error(format!("invalid bool {}", other))),
+        }
+    }
+}
+
+impl Snap for String {
+    fn write(&self, w: &mut Vec<u8>) {
+        (self.len() as u32).write(w);
+        w.extend_from_slice(self.as_bytes());
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        let len = u32::read(r)? as usize;
+        let bytes = r.bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| r.error("invalid UTF-8"))
+    }
+}
+
+impl Snap for Vec2f {
+    fn write(&self, w: &mut Vec<u8>) {
+        self.x.write(w);
+        self.y.write(w);
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        Ok(Vec2f::new(Snap::read(r)?, Snap::read(r)?))
+    }
+}
+
+impl Snap for Vec2u {
+    fn write(&self, w: &mut Vec<u8>) {
+        (self.x as u32).write(w);
+        (self.y as u32).write(w);
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        let x = u32::read(r)? as usize;
+        let y = u32::read(r)? as usize;
+        Ok(Vec2u::new(x, y))
+    }
+}
+
+impl Snap for Index {
+    fn write(&self, w: &mut Vec<u8>) {
+        self.to_bits().write(w);
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        let bits = u64::read(r)?;
+        Index::from_bits(bits).ok_or_else(|| r.error(format!("invalid handle {}", bits)))
+    }
+}
+
+impl<T: Snap> Snap for Option<T> {
+    fn write(&self, w: &mut Vec<u8>) {
+        match self {
+            Some(value) => {
+                true.write(w);
+                value.write(w);
+            }
+            None => false.write(w),
+        }
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        if bool::read(r)? {
+            Ok(Some(T::read(r)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<T: Snap> Snap for Vec<T> {
+    fn write(&self, w: &mut Vec<u8>) {
+        (self.len() as u32).write(w);
+        for item in self {
+            item.write(w);
+        }
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        let len = u32::read(r)?;
+        (0..len).map(|_| T::read(r)).collect()
+    }
+}
+
+/// Fixed size so no length prefix.
+impl<T: Snap, const N: usize> Snap for [T; N] {
+    fn write(&self, w: &mut Vec<u8>) {
+        for item in self {
+            item.write(w);
+        }
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        let items = (0..N).map(|_| T::read(r)).collect::<Result<Vec<_>, _>>()?;
+        Ok(items.try_into().ok().unwrap())
+    }
+}
+
+impl<T: Snap> Snap for VecDeque<T> {
+    fn write(&self, w: &mut Vec<u8>) {
+        (self.len() as u32).write(w);
+        for item in self {
+            item.write(w);
+        }
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        let len = u32::read(r)?;
+        (0..len).map(|_| T::read(r)).collect()
+    }
+}
+
+impl<A: Snap, B: Snap> Snap for (A, B) {
+    fn write(&self, w: &mut Vec<u8>) {
+        self.0.write(w);
+        self.1.write(w);
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        Ok((A::read(r)?, B::read(r)?))
+    }
+}
+
+impl<T: Snap + Copy> Snap for Tracked<T> {
+    fn write(&self, w: &mut Vec<u8>) {
+        (**self).write(w);
+        self.changed().write(w);
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        let value = T::read(r)?;
+        Ok(Tracked::new(value, Snap::read(r)?))
+    }
+}
+
+impl Snap for Input {
+    fn write(&self, w: &mut Vec<u8>) {
+        self.to_bits().write(w);
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        Ok(Input::from_bits(Snap::read(r)?))
+    }
+}
+
+impl Snap for Weapon {
+    fn write(&self, w: &mut Vec<u8>) {
+        (*self as u8).write(w);
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        let num = u8::read(r)?;
+        Weapon::n(num).ok_or_else(|| r.error(format!("invalid weapon {}", num)))
+    }
+}
+
+impl Snap for VehicleType {
+    fn write(&self, w: &mut Vec<u8>) {
+        (*self as u8).write(w);
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        let num = u8::read(r)?;
+        VehicleType::n(num).ok_or_else(|| r.error(format!("invalid vehicle type {}", num)))
+    }
+}
+
+impl Snap for Team {
+    fn write(&self, w: &mut Vec<u8>) {
+        (*self as u8).write(w);
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        let num = u8::read(r)?;
+        Team::n(num).ok_or_else(|| r.error(format!("invalid team {}", num)))
+    }
+}
+
+impl Snap for CooldownGroup {
+    fn write(&self, w: &mut Vec<u8>) {
+        let num: u8 = match self {
+            CooldownGroup::None => 0,
+            CooldownGroup::Explosive => 1,
+            CooldownGroup::Energy => 2,
+            CooldownGroup::Heavy => 3,
+        };
+        num.write(w);
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        match u8::read(r)? {
+            0 => Ok(CooldownGroup::None),
+            1 => Ok(CooldownGroup::Explosive),
+            2 => Ok(CooldownGroup::Energy),
+            3 => Ok(CooldownGroup::Heavy),
+            other => Err(r.error(format!("invalid cooldown group {}", other))),
+        }
+    }
+}
+
+impl Snap for Respawn {
+    fn write(&self, w: &mut Vec<u8>) {
+        let num: u8 = match self {
+            Respawn::No => 0,
+            Respawn::Pressed => 1,
+            Respawn::Scheduled => 2,
+        };
+        num.write(w);
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        match u8::read(r)? {
+            0 => Ok(Respawn::No),
+            1 => Ok(Respawn::Pressed),
+            2 => Ok(Respawn::Scheduled),
+            other => Err(r.error(format!("invalid respawn {}", other))),
+        }
+    }
+}
+
+impl Snap for PickupKind {
+    fn write(&self, w: &mut Vec<u8>) {
+        match self {
+            PickupKind::Armor => 0u8.write(w),
+            PickupKind::Ammo => 1u8.write(w),
+            PickupKind::Repair => 2u8.write(w),
+            PickupKind::Powerup(kind) => {
+                3u8.write(w);
+                kind.write(w);
+            }
+        }
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        match u8::read(r)? {
+            0 => Ok(PickupKind::Armor),
+            1 => Ok(PickupKind::Ammo),
+            2 => Ok(PickupKind::Repair),
+            3 => Ok(PickupKind::Powerup(PowerupKind::read(r)?)),
+            other => Err(r.error(format!("invalid pickup kind {}", other))),
+        }
+    }
+}
+
+impl Snap for Lock {
+    fn write(&self, w: &mut Vec<u8>) {
+        match *self {
+            Lock::Vehicle(handle) => {
+                0u8.write(w);
+                handle.write(w);
+            }
+            Lock::Flare(handle) => {
+                1u8.write(w);
+                handle.write(w);
+            }
+        }
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        match u8::read(r)? {
+            0 => Ok(Lock::Vehicle(Index::read(r)?)),
+            1 => Ok(Lock::Flare(Index::read(r)?)),
+            other => Err(r.error(format!("invalid lock {}", other))),
+        }
+    }
+}
+
+impl Snap for PowerupKind {
+    fn write(&self, w: &mut Vec<u8>) {
+        let num: u8 = match self {
+            PowerupKind::SpeedBoost => 0,
+            PowerupKind::RapidFire => 1,
+            PowerupKind::Shield => 2,
+        };
+        num.write(w);
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        match u8::read(r)? {
+            0 => Ok(PowerupKind::SpeedBoost),
+            1 => Ok(PowerupKind::RapidFire),
+            2 => Ok(PowerupKind::Shield),
+            other => Err(r.error(format!("invalid powerup kind {}", other))),
+        }
+    }
+}
+
+impl Snap for Ammo {
+    fn write(&self, w: &mut Vec<u8>) {
+        match *self {
+            Ammo::Loaded(refire_end, count) => {
+                0u8.write(w);
+                refire_end.write(w);
+                count.write(w);
+            }
+            Ammo::Reloading(start, end) => {
+                1u8.write(w);
+                start.write(w);
+                end.write(w);
+            }
+            Ammo::Heat(refire_end, heat, locked_until) => {
+                2u8.write(w);
+                refire_end.write(w);
+                heat.write(w);
+                locked_until.write(w);
+            }
+        }
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        match u8::read(r)? {
+            0 => Ok(Ammo::Loaded(Snap::read(r)?, Snap::read(r)?)),
+            1 => Ok(Ammo::Reloading(Snap::read(r)?, Snap::read(r)?)),
+            2 => Ok(Ammo::Heat(Snap::read(r)?, Snap::read(r)?, Snap::read(r)?)),
+            other => Err(r.error(format!("invalid ammo {}", other))),
+        }
+    }
+}
+
+impl Snap for HazardState {
+    fn write(&self, w: &mut Vec<u8>) {
+        match *self {
+            HazardState::Idle => 0u8.write(w),
+            HazardState::Telegraph => 1u8.write(w),
+            HazardState::Active => 2u8.write(w),
+            HazardState::Rearming(armed_time) => {
+                3u8.write(w);
+                armed_time.write(w);
+            }
+        }
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        match u8::read(r)? {
+            0 => Ok(HazardState::Idle),
+            1 => Ok(HazardState::Telegraph),
+            2 => Ok(HazardState::Active),
+            3 => Ok(HazardState::Rearming(Snap::read(r)?)),
+            other => Err(r.error(format!("invalid hazard state {}", other))),
+        }
+    }
+}
+
+impl Snap for FlagState {
+    fn write(&self, w: &mut Vec<u8>) {
+        match *self {
+            FlagState::AtBase => 0u8.write(w),
+            FlagState::Carried(vehicle_handle) => {
+                1u8.write(w);
+                vehicle_handle.write(w);
+            }
+            FlagState::Dropped { return_time } => {
+                2u8.write(w);
+                return_time.write(w);
+            }
+        }
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        match u8::read(r)? {
+            0 => Ok(FlagState::AtBase),
+            1 => Ok(FlagState::Carried(Snap::read(r)?)),
+            2 => Ok(FlagState::Dropped {
+                return_time: Snap::read(r)?,
+            }),
+            other => Err(r.error(format!("invalid flag state {}", other))),
+        }
+    }
+}
+
+impl Snap for MatchState {
+    fn write(&self, w: &mut Vec<u8>) {
+        match *self {
+            MatchState::Countdown { until } => {
+                0u8.write(w);
+                until.write(w);
+            }
+            MatchState::Playing => 1u8.write(w),
+            MatchState::Finished {
+                winner,
+                until_restart,
+            } => {
+                2u8.write(w);
+                winner.write(w);
+                until_restart.write(w);
+            }
+        }
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        match u8::read(r)? {
+            0 => Ok(MatchState::Countdown {
+                until: Snap::read(r)?,
+            }),
+            1 => Ok(MatchState::Playing),
+            2 => Ok(MatchState::Finished {
+                winner: Snap::read(r)?,
+                until_restart: Snap::read(r)?,
+            }),
+            other => Err(r.error(format!("invalid match state {}", other))),
+        }
+    }
+}
+
+impl Snap for Winner {
+    fn write(&self, w: &mut Vec<u8>) {
+        match *self {
+            Winner::Player(player_handle) => {
+                0u8.write(w);
+                player_handle.write(w);
+            }
+            Winner::Team(team) => {
+                1u8.write(w);
+                team.write(w);
+            }
+        }
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        match u8::read(r)? {
+            0 => Ok(Winner::Player(Snap::read(r)?)),
+            1 => Ok(Winner::Team(Snap::read(r)?)),
+            other => Err(r.error(format!("invalid winner {}", other))),
+        }
+    }
+}
+
+impl Snap for Effect {
+    fn write(&self, w: &mut Vec<u8>) {
+        match *self {
+            Effect::Explosion {
+                pos,
+                scale,
+                start_time,
+                bfg,
+            } => {
+                0u8.write(w);
+                pos.write(w);
+                scale.write(w);
+                start_time.write(w);
+                bfg.write(w);
+            }
+            Effect::RailBeam {
+                begin,
+                end,
+                start_time,
+            } => {
+                1u8.write(w);
+                begin.write(w);
+                end.write(w);
+                start_time.write(w);
+            }
+            Effect::BfgBeam {
+                begin,
+                end,
+                start_time,
+            } => {
+                2u8.write(w);
+                begin.write(w);
+                end.write(w);
+                start_time.write(w);
+            }
+            Effect::MuzzleFlash {
+                pos,
+                angle,
+                weapon,
+                vehicle,
+                start_time,
+            } => {
+                3u8.write(w);
+                pos.write(w);
+                angle.write(w);
+                weapon.write(w);
+                vehicle.write(w);
+                start_time.write(w);
+            }
+        }
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+        match u8::read(r)? {
+            0 => Ok(Effect::Explosion {
+                pos: Snap::read(r)?,
+                scale: Snap::read(r)?,
+                start_time: Snap::read(r)?,
+                bfg: Snap::read(r)?,
+            }),
+            1 => Ok(Effect::RailBeam {
+                begin: Snap::read(r)?,
+                end: Snap::read(r)?,
+                start_time: Snap::read(r)?,
+            }),
+            2 => Ok(Effect::BfgBeam {
+                begin: Snap::read(r)?,
+                end: Snap::read(r)?,
+                start_time: Snap::read(r)?,
+            }),
+            3 => Ok(Effect::MuzzleFlash {
+                pos: Snap::read(r)?,
+                angle: Snap::read(r)?,
+                weapon: Snap::read(r)?,
+                vehicle: Snap::read(r)?,
+                start_time: Snap::read(r)?,
+            }),
+            other => Err(r.error(format!("invalid effect {}", other))),
+        }
+    }
+}
+
+/// Implement `Snap` for a struct by listing all its fields.
+macro_rules! snap_struct {
+    ($t:ident { $($field:ident),* $(,)? }) => {
+        impl Snap for $t {
+            fn write(&self, w: &mut Vec<u8>) {
+                $( self.$field.write(w); )*
+            }
+
+            fn read(r: &mut Reader<'_>) -> Result<Self, SnapshotError> {
+                Ok($t {
+                    $( $field: Snap::read(r)?, )*
+                })
+            }
+        }
+    };
+}
+
+snap_struct!(Hitbox { mins, maxs });
+snap_struct!(Obb {
+    corners,
+    mins,
+    maxs
+});
+snap_struct!(Armor {
+    front,
+    side,
+    rear,
+    turret
+});
+snap_struct!(Score {
+    kills,
+    deaths,
+    suicides,
+    captures,
+    weapons
+});
+snap_struct!(WeaponStats {
+    shots,
+    hits,
+    damage
+});
+snap_struct!(Player {
+    name,
+    input,
+    respawn,
+    death_time,
+    vehicle,
+    guided_missile,
+    cur_weapon,
+    score,
+    loadout,
+    pending_loadout,
+    loadout_presets,
+    color,
+    team,
+});
+snap_struct!(Loadout { veh_type, weapons });
+snap_struct!(LoadoutPreset { name, loadout });
+snap_struct!(Perception {
+    time,
+    target,
+    pos,
+    vel,
+});
+snap_struct!(Ai {
+    player,
+    difficulty,
+    perceptions,
+    movement,
+    turning,
+    firing,
+    firing_decision_time,
+    reaction_time,
+    aim_error,
+    reaction_time_scale,
+    aim_error_scale,
+});
+snap_struct!(Vehicle {
+    pos,
+    vel,
+    displacement,
+    angle,
+    turn_rate,
+    water_blend,
+    veh_type,
+    hitbox,
+    obb,
+    turret_angle_current,
+    turret_angle_wanted,
+    hp,
+    damage_remainder,
+    armor,
+    ammos,
+    reserves,
+    rail_charge_start,
+    group_cooldowns,
+    last_shot_time,
+    powerups,
+    flares,
+    flare_ready,
+    spawn_time,
+    owner,
+    is_dummy,
+});
+snap_struct!(Projectile {
+    weapon,
+    pos,
+    vel,
+    angle,
+    turn_rate,
+    explode_time,
+    spawn_time,
+    owner,
+    lock,
+    retarget_time,
+    altitude,
+});
+snap_struct!(Altitude { z, vz });
+snap_struct!(Pickup { pos, kind, respawn });
+snap_struct!(Flare {
+    pos,
+    spawn_time,
+    owner
+});
+snap_struct!(Powerup {
+    kind,
+    until,
+    shield
+});
+snap_struct!(Kill {
+    time,
+    attacker,
+    victim
+});
+snap_struct!(Dda {
+    level,
+    error_integral,
+    kills
+});
+snap_struct!(Range {
+    scenario,
+    dummies,
+    hits,
+    mg_impacts,
+    damage_by_weapon,
+    last_target,
+    last_ttk,
+});
+snap_struct!(RangeScenario {
+    dummies,
+    console_mins,
+    console_maxs,
+});
+snap_struct!(DummySpec {
+    pos,
+    angle,
+    veh_type,
+    repair,
+    patrol,
+});
+snap_struct!(DummyState {
+    player,
+    last_damage_time,
+    first_hit_time,
+    patrol_distance,
+});
+snap_struct!(Ctf { flags, captures });
+snap_struct!(Flag { base, pos, state });
+snap_struct!(RangeHit {
+    time,
+    target,
+    damage
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::{map, server::Server};
+
+    fn new_server(cvars: &Cvars) -> Server {
+        let map = map::tests::load_test_map(&fs::read_to_string("maps/Atrium.map").unwrap());
+        Server::new(cvars, map)
+    }
+
+    /// Runs the game with bots and a scripted player,
+    /// optionally replacing the game state with a restored snapshot at `restore_frame`.
+    fn run(cvars: &Cvars, frames: usize, restore_frame: Option<usize>) -> Server {
+        let mut server = new_server(cvars);
+        let player_handle = server.connect(cvars, "Player 1");
+        for frame in 0..frames {
+            if Some(frame) == restore_frame {
+                let data = snapshot(&server.gs, &server.map);
+                let gs = restore(&data, &mut server.map).unwrap();
+                assert_eq!(
+                    format!("{:?}", gs.vehicles),
+                    format!("{:?}", server.gs.vehicles)
+                );
+                assert_eq!(
+                    format!("{:?}", gs.players),
+                    format!("{:?}", server.gs.players)
+                );
+                assert_eq!(snapshot(&gs, &server.map), data);
+                server.gs = gs;
+            }
+            server.snapshot_inputs();
+            let input = Input {
+                up: frame % 200 < 150,
+                left: frame % 90 < 20,
+                fire: frame % 40 < 25,
+                next_weapon: frame % 300 == 0,
+                ..Input::new()
+            };
+            server.input(player_handle, input);
+            server.update(cvars, frame as f64 * 0.016);
+        }
+        server
+    }
+
+    #[test]
+    fn test_restore_continues_identically() {
+        let cvars = Cvars {
+            d_seed: 7,
+            ..Cvars::default()
+        };
+        let expected = run(&cvars, 1500, None);
+        // Late enough for deaths, respawns and removed projectiles to leave free slots in arenas.
+        let restored = run(&cvars, 1500, Some(900));
+
+        assert!(expected.gs.dda.kills.len() + expected.gs.projectiles.len() > 0);
+        assert_eq!(
+            snapshot(&restored.gs, &restored.map),
+            snapshot(&expected.gs, &expected.map)
+        );
+        assert_eq!(
+            format!("{:?}", restored.gs.projectiles),
+            format!("{:?}", expected.gs.projectiles)
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut map = new_server(&Cvars::default()).map;
+        let gs = GameState::new(5);
+        let data = snapshot(&gs, &map);
+        assert!(restore(&data, &mut map).is_ok());
+
+        let err = restore(b"RWXX", &mut map).unwrap_err();
+        assert_eq!(err.msg, "not a snapshot");
+
+        let err = restore(&data[..data.len() - 1], &mut map).unwrap_err();
+        assert_eq!(err.msg, "unexpected end of data");
+
+        let mut extra = data.clone();
+        extra.push(0);
+        assert_eq!(restore(&extra, &mut map).unwrap_err().msg, "trailing data");
+    }
+}
@@ -0,0 +1,171 @@
+//! Sprite sheet animations - which part of a sheet to draw at a given time since the animation started.
+//!
+//! The frames only depend on the elapsed game time, not on how often they're drawn,
+//! so animations play at the same speed at any frame rate.
+
+/// A horizontal strip of equally sized frames.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteSheet {
+    pub frames: usize,
+    pub frame_width: f64,
+    pub frame_height: f64,
+    pub fps: f64,
+    /// Start over after the last frame instead of finishing.
+    pub looping: bool,
+    /// The frames are stored last to first.
+    pub reversed: bool,
+}
+
+/// The part of the sheet to draw, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameRect {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+// It looks like the original animation is made for 30 fps.
+// Single stepping a recording of the original RecWars explosion in blender:
+// 13 sprites, 31 frames - examples:
+//      2,2,3,1,3,3,2,3,2,2,3,2,3
+//      2,2,2,3,1,3,2,2,3,2,2,3,4
+// Different each time probably because RecWar's and the recorder's framerate don't match exactly.
+//
+// 26 fps produces similar results,
+// though it might display a single sprite for 4 frames slightly more often.
+
+/// `assets/explosion.png`
+pub const EXPLOSION: SpriteSheet = SpriteSheet {
+    frames: 13,
+    frame_width: 100.0,
+    frame_height: 100.0,
+    fps: 26.0,
+    looping: false,
+    reversed: false,
+};
+
+/// `assets/explosion_cyan.png`
+pub const EXPLOSION_BFG: SpriteSheet = SpriteSheet {
+    reversed: true,
+    ..EXPLOSION
+};
+
+impl SpriteSheet {
+    /// Seconds until a non-looping animation finishes.
+    pub fn duration(&self) -> f64 {
+        self.frames as f64 / self.fps
+    }
+
+    /// None before the start and, unless it's looping, once the last frame has been shown.
+    pub fn frame(&self, elapsed: f64) -> Option<FrameRect> {
+        if elapsed < 0.0 || self.frames == 0 {
+            return None;
+        }
+        let mut index = (elapsed * self.fps).floor() as usize;
+        if self.looping {
+            index %= self.frames;
+        } else if index >= self.frames {
+            return None;
+        }
+        if self.reversed {
+            index = self.frames - 1 - index;
+        }
+        Some(FrameRect {
+            x: index as f64 * self.frame_width,
+            y: 0.0,
+            w: self.frame_width,
+            h: self.frame_height,
+        })
+    }
+}
+
+pub fn explosion_sheet(bfg: bool) -> &'static SpriteSheet {
+    if bfg {
+        &EXPLOSION_BFG
+    } else {
+        &EXPLOSION
+    }
+}
+
+/// None when the explosion is over - that's also when `effects::retire` removes it.
+pub fn explosion_frame(bfg: bool, elapsed: f64) -> Option<FrameRect> {
+    explosion_sheet(bfg).frame(elapsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_boundaries() {
+        let frame_x = |bfg, elapsed| explosion_frame(bfg, elapsed).map(|rect| rect.x);
+        let frame_time = 1.0 / EXPLOSION.fps;
+
+        assert_eq!(frame_x(false, 0.0), Some(0.0));
+        assert_eq!(frame_x(false, frame_time * 0.5), Some(0.0));
+        assert_eq!(frame_x(false, frame_time * 1.5), Some(100.0));
+        let last = (EXPLOSION.frames - 1) as f64 * EXPLOSION.frame_width;
+        assert_eq!(frame_x(false, EXPLOSION.duration() - 1e-9), Some(last));
+        // Finished at the end and after.
+        assert_eq!(frame_x(false, EXPLOSION.duration()), None);
+        assert_eq!(frame_x(false, 10.0), None);
+        assert_eq!(frame_x(false, -0.1), None);
+
+        // The BFG sheet is stored backwards.
+        assert_eq!(frame_x(true, 0.0), Some(last));
+        assert_eq!(frame_x(true, EXPLOSION.duration() - 1e-9), Some(0.0));
+        assert_eq!(frame_x(true, EXPLOSION.duration()), None);
+
+        // Exactly at an edge is already the next frame.
+        // Edges of a power of two fps are exact in binary.
+        let sheet = SpriteSheet {
+            frames: 4,
+            fps: 4.0,
+            ..EXPLOSION
+        };
+        assert_eq!(sheet.frame(0.5).unwrap().x, 200.0);
+        assert_eq!(sheet.frame(0.5 - 1e-9).unwrap().x, 100.0);
+        assert_eq!(sheet.frame(1.0), None);
+
+        let looping = SpriteSheet {
+            looping: true,
+            ..EXPLOSION
+        };
+        assert_eq!(looping.frame(EXPLOSION.duration()), looping.frame(0.0));
+        assert_eq!(
+            looping.frame(EXPLOSION.duration() + 0.25),
+            looping.frame(0.25)
+        );
+    }
+
+    #[test]
+    fn test_frame_rate_independent() {
+        // The animation ends at the same time whether it's drawn often or rarely.
+        for fps in [10.0, 30.0, 60.0, 144.0] {
+            let mut frame_starts = Vec::new();
+            let mut prev = None;
+            let mut frame = 0;
+            loop {
+                let elapsed = frame as f64 / fps;
+                let rect = explosion_frame(false, elapsed);
+                if rect.is_none() {
+                    break;
+                }
+                if rect != prev {
+                    frame_starts.push(rect.unwrap().x);
+                    prev = rect;
+                }
+                frame += 1;
+            }
+            let finished = frame as f64 / fps;
+            assert!(finished >= EXPLOSION.duration());
+            assert!(finished < EXPLOSION.duration() + 1.0 / fps);
+            // Fast rendering shows every frame, slow skips some but always moves forward.
+            assert!(frame_starts.windows(2).all(|pair| pair[0] < pair[1]));
+            if fps >= EXPLOSION.fps {
+                assert_eq!(frame_starts.len(), EXPLOSION.frames);
+            }
+        }
+    }
+}
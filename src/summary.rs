@@ -0,0 +1,231 @@
+//! Machine-readable results of a match for tournaments and tracking progress against bots.
+//!
+//! Everything comes from the game state (scores and `WeaponStats`)
+//! so it survives snapshots and starts over with each match (see `Server::reset_match`).
+//! Nobody can shoot or get hurt once the match is finished so the summary stays the same until the restart.
+//! Before that it's marked `"partial": true` for live dashboards.
+//!
+//! Map hashes and damage are strings and numbers in hit points because JSON numbers are floats.
+
+use std::{fmt::Write, hash::Hasher};
+
+use fnv::FnvHasher;
+
+use crate::{
+    cvars::Cvars,
+    entities::{Player, Weapon},
+    fixed,
+    game_state::{GameState, MatchState, Winner},
+    json,
+    map::{self, Map},
+};
+
+/// The match so far as a JSON object.
+///
+/// `map_name` is only for people reading it, `map_hash` identifies the layout.
+pub fn match_summary_json(cvars: &Cvars, map_name: &str, map: &Map, gs: &GameState) -> String {
+    let (partial, end, winner) = match gs.match_state {
+        MatchState::Finished { winner, .. } => (false, gs.match_end, winner),
+        _ => (true, gs.game_time, None),
+    };
+    let winner = match winner {
+        Some(Winner::Player(handle)) => {
+            format!(r#"{{"player": {}}}"#, json::quote(&gs.players[handle].name))
+        }
+        Some(Winner::Team(team)) => format!(r#"{{"team": {}}}"#, json::quote(team.name())),
+        None => "null".to_owned(),
+    };
+    let players: Vec<_> = gs
+        .players
+        .iter()
+        .map(|(handle, player)| {
+            let bot = gs.ais.iter().any(|(_, ai)| ai.player == handle);
+            player_json(cvars, player, bot)
+        })
+        .collect();
+    format!(
+        r#"{{"partial": {}, "mode": {}, "map": {}, "map_hash": "{:016x}", "ticks": {}, "seconds": {:.3}, "winner": {}, "players": [{}]}}"#,
+        partial,
+        json::quote(&cvars.g_game_mode.to_string()),
+        json::quote(map_name),
+        map_hash(map),
+        gs.match_ticks,
+        (end - gs.match_start).max(0.0),
+        winner,
+        players.join(", "),
+    )
+}
+
+fn player_json(cvars: &Cvars, player: &Player, bot: bool) -> String {
+    let score = &player.score;
+    let team = match player.team {
+        Some(team) => json::quote(team.name()),
+        None => "null".to_owned(),
+    };
+    let mut weapons = String::new();
+    for weapon in Weapon::all() {
+        let stats = score.weapons[weapon as usize];
+        if !weapons.is_empty() {
+            weapons.push_str(", ");
+        }
+        write!(
+            weapons,
+            r#""{:?}": {{"shots": {}, "hits": {}, "damage": {:.3}}}"#,
+            weapon,
+            stats.shots,
+            stats.hits,
+            fixed::from_milli(stats.damage)
+        )
+        .unwrap();
+    }
+    format!(
+        r#"{{"name": {}, "bot": {}, "team": {}, "points": {}, "kills": {}, "deaths": {}, "suicides": {}, "captures": {}, "weapons": {{{}}}}}"#,
+        json::quote(&player.name),
+        bot,
+        team,
+        score.points(cvars),
+        score.kills,
+        score.deaths,
+        score.suicides,
+        score.captures,
+        weapons,
+    )
+}
+
+/// Identifies the map's layout as loaded, walls destroyed during the match don't change it.
+fn map_hash(map: &Map) -> u64 {
+    let mut original = map.clone();
+    original.repair_walls();
+    let mut hasher = FnvHasher::default();
+    hasher.write(map::save_map(&original).as_bytes());
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{
+        entities::{WeaponStats, WEAPS_CNT},
+        json::Value,
+        server::Server,
+        snapshot,
+    };
+
+    use super::*;
+
+    fn field<'a>(object: &'a [(String, Value)], key: &str) -> &'a Value {
+        &object.iter().find(|(k, _)| k == key).unwrap().1
+    }
+
+    fn num(object: &[(String, Value)], key: &str) -> f64 {
+        match field(object, key) {
+            Value::Num(num) => *num,
+            other => panic!("{} is {:?}", key, other),
+        }
+    }
+
+    fn summary(cvars: &Cvars, server: &Server) -> Vec<(String, Value)> {
+        let json = match_summary_json(cvars, "Atrium", &server.map, &server.gs);
+        json::parse_nested(&json).unwrap_or_else(|err| panic!("{}: {}", err, json))
+    }
+
+    #[test]
+    fn test_bot_match() {
+        let cvars = Cvars {
+            d_seed: 7,
+            bots_max: 2,
+            g_match_countdown: 0.0,
+            g_match_kill_limit: 2,
+            g_match_time_limit: 60.0,
+            ..Cvars::default()
+        };
+        let map = map::tests::load_test_map(&fs::read_to_string("maps/Atrium.map").unwrap());
+        let mut server = Server::new(&cvars, map);
+
+        server.step(&cvars, 600);
+        let live = summary(&cvars, &server);
+        assert_eq!(field(&live, "partial"), &Value::Bool(true));
+        assert_eq!(field(&live, "winner"), &Value::Null);
+        assert_eq!(num(&live, "ticks"), 600.0);
+
+        // A restored game reports the same totals.
+        let data = snapshot::snapshot(&server.gs, &server.map);
+        let mut restored_map = server.map.clone();
+        let restored = snapshot::restore(&data, &mut restored_map).unwrap();
+        assert_eq!(
+            match_summary_json(&cvars, "Atrium", &restored_map, &restored),
+            match_summary_json(&cvars, "Atrium", &server.map, &server.gs)
+        );
+
+        // The time limit ends it if nobody reaches the kill limit.
+        let max_ticks = cvars.g_match_time_limit * cvars.sv_tickrate_fixed_fps;
+        for _ in 0..max_ticks as usize {
+            if matches!(server.gs.match_state, MatchState::Finished { .. }) {
+                break;
+            }
+            server.step(&cvars, 1);
+        }
+        let done = summary(&cvars, &server);
+        assert_eq!(field(&done, "partial"), &Value::Bool(false));
+        assert_eq!(field(&done, "mode"), &Value::Str("FreeForAll".to_owned()));
+        assert!(num(&done, "seconds") > 10.0);
+
+        let players = match field(&done, "players") {
+            Value::Array(players) => players,
+            other => panic!("players is {:?}", other),
+        };
+        assert_eq!(players.len(), 2);
+        let mut total_shots = 0.0;
+        for player in players {
+            let player = match player {
+                Value::Object(player) => player,
+                other => panic!("player is {:?}", other),
+            };
+            assert_eq!(field(player, "bot"), &Value::Bool(true));
+            let weapons = match field(player, "weapons") {
+                Value::Object(weapons) => weapons,
+                other => panic!("weapons is {:?}", other),
+            };
+            assert_eq!(weapons.len(), Weapon::all().count());
+            let mut damage = 0.0;
+            for (weapon, stats) in weapons {
+                let stats = match stats {
+                    Value::Object(stats) => stats,
+                    other => panic!("{} is {:?}", weapon, other),
+                };
+                let (shots, hits) = (num(stats, "shots"), num(stats, "hits"));
+                assert!(shots >= hits, "{}: {} shots, {} hits", weapon, shots, hits);
+                if num(stats, "damage") > 0.0 {
+                    assert!(hits > 0.0, "{} did damage without hits", weapon);
+                }
+                total_shots += shots;
+                damage += num(stats, "damage");
+            }
+            // Debris doesn't count as a weapon but there's none before the first kill.
+            if num(player, "kills") > 0.0 {
+                assert!(damage > 0.0, "{:?}", player);
+            }
+        }
+        assert!(total_shots > 0.0);
+        let limit_reached = server
+            .gs
+            .players
+            .iter()
+            .any(|(_, p)| p.score.kills >= cvars.g_match_kill_limit);
+        if limit_reached {
+            assert!(matches!(field(&done, "winner"), Value::Object(_)));
+        }
+
+        // The next match starts from zero.
+        server.reset_match(&cvars, 8);
+        for (_, player) in server.gs.players.iter() {
+            assert_eq!(
+                player.score.weapons,
+                [WeaponStats::default(); WEAPS_CNT as usize]
+            );
+        }
+        let fresh = summary(&cvars, &server);
+        assert_eq!(num(&fresh, "ticks"), 0.0);
+    }
+}
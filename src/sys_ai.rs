@@ -1,18 +1,70 @@
-//! Stub. So far bots move and shoot randomly.
+//! Bots still move randomly but attack the nearest enemy they can see.
+//!
+//! How well depends on their difficulty (see `bots_difficulty`), the mistakes are modeled explicitly:
+//! - They react to what they saw `reaction_time` ago (a queue of perceptions), not to what's there now.
+//! - Shots get a random angular error (see `shooting`), the easier the bigger.
+//! - They lead moving targets only partially, see `Ai::lead_quality` and `intercept`.
+//! - They fire only in range and roughly aimed, easier bots are sloppier about the latter.
+//! - Easy bots don't use the railgun.
+//!
+//! Badly damaged bots drive to the nearest cover (see `visibility::nearest_cover`) from the enemy they see.
+//!
+//! Bots guiding a missile turn it towards the enemy they see (or let it fly straight)
+//! and detonate it once the enemy is in its explosion radius.
 
+use std::{collections::VecDeque, f64::consts::PI};
+
+use fnv::FnvHashMap;
 use rand::Rng;
+use thunderdome::Index;
+use vek::Clamp;
 
 use crate::{
     cvars::Cvars,
-    game_state::{GameState, Input},
+    entities::{Ai, Perception, Player, Weapon},
+    game_state::{ArenaExt, GameState, Input},
+    map::{F64Ext, Kind, Map, Vec2f, Vec2u, VecExt, TILE_SIZE},
+    nav::NavGrid,
+    sys_hazards, visibility,
 };
 
-pub fn ai(cvars: &Cvars, gs: &mut GameState) {
+/// Add a bot player, its vehicle is spawned like everyone else's. Returns the AI's handle.
+pub fn spawn_bot(cvars: &Cvars, gs: &mut GameState, name: String, difficulty: u32) -> Index {
+    let player_handle = gs.players.insert(Player::new(name));
+    gs.ais.insert(Ai::new(cvars, player_handle, difficulty))
+}
+
+pub fn ai(cvars: &Cvars, gs: &mut GameState, map: &Map) {
     if !cvars.ai {
         return;
     }
 
-    for (_, ai) in gs.ais.iter_mut() {
+    for ai_handle in gs.ais.iter_handles() {
+        let ai = &gs.ais[ai_handle];
+        let seen = nearest_enemy(cvars, gs, map, ai.player);
+        let vehicle = &gs.vehicles[gs.players[ai.player].vehicle.unwrap()];
+        // Don't drive onto hazards which will hurt before the bot gets across.
+        let direction = match ai.movement {
+            1 => 1.0,
+            -1 => -1.0,
+            _ => 0.0,
+        };
+        let ahead = *vehicle.pos + vehicle.angle.to_vec2f() * direction * TILE_SIZE;
+        let crossing_time = tile_time(cvars, gs, ai.player);
+        let ahead_index = map
+            .tile_pos(ahead.clamped(map.mins(), map.maxs() - 1.0))
+            .index;
+        let avoid = direction != 0.0
+            && sys_hazards::is_dangerous(
+                cvars,
+                gs,
+                map,
+                ahead_index,
+                gs.game_time,
+                gs.game_time + 2.0 * crossing_time,
+            );
+
+        let ai = &mut gs.ais[ai_handle];
         let player = &mut gs.players[ai.player];
         let vehicle = &gs.vehicles[player.vehicle.unwrap()];
 
@@ -20,8 +72,8 @@ pub fn ai(cvars: &Cvars, gs: &mut GameState) {
         let age = gs.game_time - vehicle.spawn_time;
         if age < 0.5 {
             ai.movement = 1;
-        } else if gs.rng.gen_bool(0.01) {
-            let r: f64 = gs.rng.gen();
+        } else if gs.rng.ai.gen_bool(0.01) {
+            let r: f64 = gs.rng.ai.gen();
             if r < 0.5 {
                 ai.movement = 1;
             } else if r < 0.65 {
@@ -31,33 +83,739 @@ pub fn ai(cvars: &Cvars, gs: &mut GameState) {
             }
         }
 
-        if gs.rng.gen_bool(0.03) {
-            ai.turning = gs.rng.gen_range(-1..=1);
+        if gs.rng.ai.gen_bool(0.03) {
+            ai.turning = gs.rng.ai.gen_range(-1..=1);
+        }
+
+        // React to the newest perception that's at least reaction time old.
+        ai.perceptions.push_back(Perception {
+            time: gs.game_time,
+            target: seen.map(|(handle, _, _)| handle),
+            pos: seen.map_or_else(Vec2f::zero, |(_, pos, _)| pos),
+            vel: seen.map_or_else(Vec2f::zero, |(_, _, vel)| vel),
+        });
+        while ai.perceptions.len() >= 2 && gs.game_time - ai.perceptions[1].time >= ai.reaction_time
+        {
+            ai.perceptions.pop_front();
         }
+        let perceived = ai
+            .perceptions
+            .front()
+            .filter(|perception| gs.game_time - perception.time >= ai.reaction_time)
+            .filter(|perception| perception.target.is_some())
+            .copied();
 
-        if !ai.firing && gs.rng.gen_bool(0.01) {
-            ai.firing = true;
-        } else if ai.firing && gs.rng.gen_bool(0.03) {
-            ai.firing = false;
+        // Hide when badly damaged, still shooting back if possible.
+        if let Some(perception) = perceived {
+            if vehicle.hp_fraction(cvars) < cvars.bots_cover_hp {
+                let pos = *vehicle.pos;
+                let cover =
+                    visibility::nearest_cover(map, pos, perception.pos, cvars.bots_cover_radius);
+                if let Some(cover) = cover {
+                    let wanted = (cover - pos).to_angle();
+                    let misalignment = (wanted - *vehicle.angle + PI).rem_euclid(2.0 * PI) - PI;
+                    ai.turning = if misalignment.abs() < 0.1 {
+                        0
+                    } else {
+                        misalignment.signum() as i32
+                    };
+                    let arrived = pos.distance(cover) < TILE_SIZE / 4.0;
+                    ai.movement = if arrived || misalignment.abs() > PI / 2.0 {
+                        0
+                    } else {
+                        1
+                    };
+                }
+            }
+        }
+        if avoid {
+            ai.movement = 0;
+        }
+
+        let weapon = player.cur_weapon;
+        let weapon_allowed =
+            weapon != Weapon::Rail || ai.difficulty >= cvars.bots_railgun_difficulty;
+
+        let mut turret_target = None;
+        let mut aimed = false;
+        if let Some(perception) = perceived {
+            // Projectiles inherit some of the vehicle's velocity which makes the target seem slower.
+            let inherited = cvars.g_weapon_vehicle_velocity_factor(weapon) * vehicle.vel;
+            let rel_vel = perception.vel - inherited;
+            let speed = cvars.g_weapon_speed(weapon);
+            let lead = intercept(*vehicle.pos, perception.pos, rel_vel, speed)
+                .map_or_else(Vec2f::zero, |point| point - perception.pos);
+            let aim_point = perception.pos + lead * ai.lead_quality();
+            let wanted = (aim_point - *vehicle.pos).to_angle();
+            turret_target = Some(wanted);
+
+            let turret_angle = *vehicle.angle + vehicle.turret_angle_current;
+            let misalignment = ((wanted - turret_angle + PI).rem_euclid(2.0 * PI) - PI).abs();
+            let in_range = perception.pos.distance(*vehicle.pos) <= cvars.bots_fire_range;
+            aimed = in_range && misalignment <= cvars.bots_fire_alignment * ai.difficulty_scale();
+        }
+        if aimed && !ai.firing {
+            ai.firing_decision_time = gs.game_time;
         }
+        ai.firing = aimed;
+        let fire = ai.firing && weapon_allowed;
 
-        dbg_world_textf!(vehicle.pos, "{:.1} {}", age, ai.movement);
+        dbg_world_textf!(*vehicle.pos, "{:.1} {}", age, ai.movement);
 
-        player.input = Input {
+        let turret_left = gs.rng.ai.gen_bool(0.01);
+        let turret_right = gs.rng.ai.gen_bool(0.01);
+        let prev_weapon = gs.rng.ai.gen_bool(0.02);
+        let next_weapon = gs.rng.ai.gen_bool(0.01);
+        // Switching happens when the key goes down so it has to be released in between.
+        let next_weapon = if weapon_allowed {
+            next_weapon
+        } else {
+            !player.input.next_weapon
+        };
+        let mut input = Input {
             up: ai.movement == 1,
             down: ai.movement == -1,
             left: ai.turning == -1,
             right: ai.turning == 1,
-            turret_left: gs.rng.gen_bool(0.01),
-            turret_right: gs.rng.gen_bool(0.01),
-            prev_weapon: gs.rng.gen_bool(0.02),
-            next_weapon: gs.rng.gen_bool(0.01),
-            fire: ai.firing,
-            mine: gs.rng.gen_bool(0.001),
-            self_destruct: gs.rng.gen_bool(0.0001),
-            horn: gs.rng.gen_bool(0.0001),
+            turret_left: turret_left && turret_target.is_none(),
+            turret_right: turret_right && turret_target.is_none(),
+            prev_weapon: prev_weapon && weapon_allowed,
+            next_weapon,
+            fire,
+            mine: gs.rng.ai.gen_bool(0.001),
+            self_destruct: gs.rng.ai.gen_bool(0.0001),
+            horn: gs.rng.ai.gen_bool(0.0001),
             chat: false,
             pause: false, // :)
+            // LATER Deploy flares when a homing missile is coming (see `sys_threats`).
+            flare: false,
+            turret_target: None,
+            select_weapon: None,
+            steer: None,
+            throttle: None,
+        };
+        if let Some(angle) = turret_target {
+            input.set_turret_target_angle(angle);
+        }
+        // The vehicle doesn't move while guiding so the same keys steer the missile, see `gm_turning`.
+        if let Some(gm_handle) = player.guided_missile {
+            let gm = &gs.projectiles[gm_handle];
+            let mut turning = 0;
+            let mut detonate = false;
+            if let Some(perception) = perceived {
+                let wanted = (perception.pos - gm.pos).to_angle();
+                let misalignment = (wanted - gm.angle + PI).rem_euclid(2.0 * PI) - PI;
+                if misalignment.abs() >= 0.1 {
+                    turning = misalignment.signum() as i32;
+                }
+                let radius = cvars.g_weapon_explosion_radius(Weapon::Gm);
+                detonate = gm.pos.distance(perception.pos) <= radius;
+            }
+            input.left = turning == -1;
+            input.right = turning == 1;
+            // Only pressing fire detonates so release it first if it's still held.
+            input.fire = detonate && !player.input.fire;
+        }
+        player.input = input;
+    }
+}
+
+/// The closest living enemy vehicle the player can see - handle, position and velocity.
+fn nearest_enemy(
+    cvars: &Cvars,
+    gs: &GameState,
+    map: &Map,
+    player_handle: Index,
+) -> Option<(Index, Vec2f, Vec2f)> {
+    let player = &gs.players[player_handle];
+    let pos = *gs.vehicles[player.vehicle?].pos;
+    gs.vehicles
+        .iter()
+        .filter(|(_, vehicle)| vehicle.owner != player_handle && !vehicle.destroyed())
+        .filter(|(_, vehicle)| {
+            let team = gs.players[vehicle.owner].team;
+            team.is_none() || team != player.team
+        })
+        .map(|(handle, vehicle)| (handle, *vehicle.pos, vehicle.vel))
+        .filter(|&(_, target_pos, _)| {
+            target_pos.distance(pos) <= cvars.bots_vision_range
+                && visibility::line_of_sight(map, pos, target_pos)
+        })
+        .min_by(|a, b| {
+            let dist_a = a.1.distance_squared(pos);
+            let dist_b = b.1.distance_squared(pos);
+            dist_a.partial_cmp(&dist_b).unwrap()
+        })
+}
+
+/// Where a projectile fired now from `shooter` at `projectile_speed` meets a target
+/// moving from `target` at a constant `target_vel`.
+///
+/// `target_vel` is relative to the projectile's inherited velocity, if any.
+/// `None` if the projectile can't catch up.
+pub fn intercept(
+    shooter: Vec2f,
+    target: Vec2f,
+    target_vel: Vec2f,
+    projectile_speed: f64,
+) -> Option<Vec2f> {
+    // |target + target_vel * t - shooter| = projectile_speed * t, solved for the earliest t >= 0.
+    let offset = target - shooter;
+    let a = target_vel.dot(target_vel) - projectile_speed * projectile_speed;
+    let b = 2.0 * offset.dot(target_vel);
+    let c = offset.dot(offset);
+    let time = if a.abs() < 1e-9 {
+        // Same speed - only targets coming closer can be hit.
+        if c == 0.0 {
+            0.0
+        } else if b < 0.0 {
+            -c / b
+        } else {
+            return None;
+        }
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        let t1 = (-b - root) / (2.0 * a);
+        let t2 = (-b + root) / (2.0 * a);
+        [t1, t2]
+            .iter()
+            .copied()
+            .filter(|&t| t >= 0.0)
+            .fold(None, |min: Option<f64>, t| {
+                Some(min.map_or(t, |min| min.min(t)))
+            })?
+    };
+    Some(target + target_vel * time)
+}
+
+/// How long it takes the player's vehicle to cross a tile at full speed.
+fn tile_time(cvars: &Cvars, gs: &GameState, player_handle: Index) -> f64 {
+    let veh_type = gs.vehicles[gs.players[player_handle].vehicle.unwrap()].veh_type;
+    TILE_SIZE
+        / cvars
+            .g_vehicle_movement_stats(veh_type, Kind::Normal)
+            .speed_max
+}
+
+/// Shortest path between tiles which avoids walls and hazards, including `start` and `goal`.
+///
+/// Walls come from the nav grid, see `Derived::nav_grid`.
+///
+/// The bot is assumed to start at the current game time and need `tile_time` per tile.
+/// A tile is blocked if a hazard on it is dangerous at any point while the bot is on it.
+/// This is an approximation - the bot never waits for a crusher to go up.
+pub fn find_path(
+    cvars: &Cvars,
+    gs: &GameState,
+    map: &Map,
+    nav: &NavGrid,
+    start: Vec2u,
+    goal: Vec2u,
+    tile_time: f64,
+) -> Option<Vec<Vec2u>> {
+    let mut came_from = FnvHashMap::default();
+    came_from.insert(start, start);
+    let mut queue = VecDeque::new();
+    queue.push_back((start, 0));
+    while let Some((index, steps)) = queue.pop_front() {
+        if index == goal {
+            let mut path = vec![goal];
+            let mut cur = goal;
+            while cur != start {
+                cur = came_from[&cur];
+                path.push(cur);
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        let arrival = gs.game_time + (steps + 1) as f64 * tile_time;
+        let mut neighbors = Vec::with_capacity(4);
+        if index.x > 0 {
+            neighbors.push(Vec2u::new(index.x - 1, index.y));
+        }
+        if index.y > 0 {
+            neighbors.push(Vec2u::new(index.x, index.y - 1));
+        }
+        if index.x + 1 < map.width() {
+            neighbors.push(Vec2u::new(index.x + 1, index.y));
+        }
+        if index.y + 1 < map.height() {
+            neighbors.push(Vec2u::new(index.x, index.y + 1));
+        }
+        for next in neighbors {
+            if came_from.contains_key(&next)
+                || !nav.walkable(next)
+                || sys_hazards::is_dangerous(cvars, gs, map, next, arrival, arrival + tile_time)
+            {
+                continue;
+            }
+            came_from.insert(next, index);
+            queue.push_back((next, steps + 1));
+        }
+    }
+    None
+}
+
+/// Dynamic difficulty adjustment.
+///
+/// Compares the human's recent kill/death ratio to the target
+/// and slowly moves the bots' reaction time and aim error within the configured bounds.
+/// It never touches movement so it doesn't feel like the bots are cheating.
+///
+/// When DDA gets disabled during a match, the current level stays in effect.
+/// Kills are only recorded while it's enabled.
+pub fn dda(cvars: &Cvars, gs: &mut GameState) {
+    // Leave the bots exactly as they'd be without DDA.
+    if !cvars.g_dda_enabled && gs.dda.level == 0.0 {
+        return;
+    }
+
+    let game_time = gs.game_time; // borrowck
+    while let Some(kill) = gs.dda.kills.front() {
+        if kill.time + cvars.g_dda_window < game_time {
+            gs.dda.kills.pop_front();
+        } else {
+            break;
+        }
+    }
+
+    // Only adjust against a single human, with more it's not clear who to adjust for.
+    let mut humans = gs
+        .players
+        .iter()
+        .map(|(handle, _)| handle)
+        .filter(|&handle| !gs.ais.iter().any(|(_, ai)| ai.player == handle));
+    let human = humans.next();
+    let solo = humans.next().is_none();
+
+    if let Some(human) = human.filter(|_| cvars.g_dda_enabled && solo) {
+        let kills = gs
+            .dda
+            .kills
+            .iter()
+            .filter(|kill| kill.attacker == human && kill.victim != human)
+            .count();
+        let deaths = gs
+            .dda
+            .kills
+            .iter()
+            .filter(|kill| kill.victim == human)
+            .count();
+
+        // +1 so the first kill or death doesn't immediately push the ratio to an extreme.
+        let ratio = (kills + 1) as f64 / (deaths + 1) as f64;
+        // Log scale so doing twice as well and twice as badly are symmetric.
+        let error = ratio.ln() - cvars.g_dda_target_ratio.ln();
+
+        gs.dda.error_integral += error * gs.dt;
+        if cvars.g_dda_gain_integral > 0.0 {
+            // Anti-windup - the integral alone shouldn't be able to go past the bounds.
+            let limit = 1.0 / cvars.g_dda_gain_integral;
+            gs.dda.error_integral = gs.dda.error_integral.clamped(-limit, limit);
+        }
+
+        let wanted = (cvars.g_dda_gain_proportional * error
+            + cvars.g_dda_gain_integral * gs.dda.error_integral)
+            .clamped(-1.0, 1.0);
+        let max_change = cvars.g_dda_rate_max * gs.dt / 60.0;
+        let change = (wanted - gs.dda.level).clamped(-max_change, max_change);
+        gs.dda.level += change;
+    }
+
+    let level = gs.dda.level;
+    for (_, ai) in gs.ais.iter_mut() {
+        let reaction_time = ai.base_reaction_time(cvars);
+        ai.reaction_time = (reaction_time - level * cvars.g_dda_reaction_time_range).max(0.0);
+        let aim_error = ai.base_aim_error(cvars);
+        ai.aim_error = (aim_error - level * cvars.g_dda_aim_error_range).max(0.0);
+    }
+
+    if cvars.g_dda_enabled || level != 0.0 {
+        dbg_textf!("DDA level: {:+.3}", level);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use thunderdome::Arena;
+
+    use crate::{
+        entities::{Projectile, Vehicle, VehicleType, BOTS_DIFFICULTY_MAX},
+        map::{self, Kind, Surface},
+    };
+
+    const DT: f64 = 1.0 / 60.0;
+
+    /// Returns the game state and handles of the human and one of the bots.
+    fn setup(cvars: &Cvars) -> (GameState, Index, Index) {
+        let mut gs = GameState::new(0);
+        let human = gs.players.insert(Player::new("Human".to_owned()));
+        let mut bot = None;
+        for i in 0..3 {
+            let handle = gs.players.insert(Player::new(format!("Bot {}", i)));
+            gs.ais.insert(Ai::new(cvars, handle, cvars.bots_difficulty));
+            bot = Some(handle);
+        }
+        (gs, human, bot.unwrap())
+    }
+
+    /// Simulate `secs` seconds, the human kills a bot every `kill_period`
+    /// and gets killed by one every `death_period` seconds.
+    fn run(
+        cvars: &Cvars,
+        gs: &mut GameState,
+        human: Index,
+        bot: Index,
+        secs: f64,
+        kill_period: f64,
+        death_period: f64,
+    ) -> Vec<f64> {
+        let mut levels = Vec::new();
+        let steps = (secs / DT) as usize;
+        for _ in 0..steps {
+            gs.game_time_prev = gs.game_time;
+            gs.game_time += DT;
+            gs.dt = DT;
+            if (gs.game_time / kill_period).floor() != (gs.game_time_prev / kill_period).floor() {
+                gs.dda.record_kill(gs.game_time, human, bot);
+            }
+            if (gs.game_time / death_period).floor() != (gs.game_time_prev / death_period).floor() {
+                gs.dda.record_kill(gs.game_time, bot, human);
+            }
+            dda(cvars, gs);
+            levels.push(gs.dda.level);
+        }
+        levels
+    }
+
+    fn assert_rate(cvars: &Cvars, levels: &[f64]) {
+        let max_change = cvars.g_dda_rate_max * DT / 60.0;
+        let mut prev = 0.0;
+        for &level in levels {
+            assert!((level - prev).abs() <= max_change + 1e-12);
+            assert!((-1.0..=1.0).contains(&level));
+            prev = level;
+        }
+    }
+
+    #[test]
+    fn test_dda_dominant_human() {
+        let cvars = Cvars {
+            g_dda_enabled: true,
+            bots_aim_error: 0.05,
+            bots_reaction_time: 0.2,
+            ..Cvars::default()
+        };
+        let (mut gs, human, bot) = setup(&cvars);
+
+        let levels = run(&cvars, &mut gs, human, bot, 300.0, 5.0, 60.0);
+        assert_rate(&cvars, &levels);
+        assert!(gs.dda.level > 0.5);
+        for (_, ai) in gs.ais.iter() {
+            assert!(ai.reaction_time < cvars.bots_reaction_time);
+            assert!(ai.aim_error < cvars.bots_aim_error);
+        }
+    }
+
+    #[test]
+    fn test_dda_struggling_human() {
+        let cvars = Cvars {
+            g_dda_enabled: true,
+            bots_aim_error: 0.05,
+            bots_reaction_time: 0.2,
+            ..Cvars::default()
+        };
+        let (mut gs, human, bot) = setup(&cvars);
+
+        let levels = run(&cvars, &mut gs, human, bot, 300.0, 60.0, 5.0);
+        assert_rate(&cvars, &levels);
+        assert!(gs.dda.level < -0.5);
+        for (_, ai) in gs.ais.iter() {
+            assert!(ai.reaction_time > cvars.bots_reaction_time);
+            assert!(ai.aim_error > cvars.bots_aim_error);
+        }
+    }
+
+    #[test]
+    fn test_dda_disabled_mid_match() {
+        let mut cvars = Cvars {
+            g_dda_enabled: true,
+            bots_reaction_time: 0.2,
+            ..Cvars::default()
+        };
+        let (mut gs, human, bot) = setup(&cvars);
+
+        run(&cvars, &mut gs, human, bot, 60.0, 5.0, 60.0);
+        let frozen = gs.dda.level;
+        assert!(frozen > 0.0);
+        let reaction_time = gs.ais.iter().next().unwrap().1.reaction_time;
+
+        cvars.g_dda_enabled = false;
+        let levels = run(&cvars, &mut gs, human, bot, 60.0, 5.0, 60.0);
+        assert!(levels.iter().all(|&level| level == frozen));
+        for (_, ai) in gs.ais.iter() {
+            assert_eq!(ai.reaction_time, reaction_time);
+        }
+    }
+
+    #[test]
+    fn test_path_avoids_crusher() {
+        let cvars = Cvars::default();
+        let surface = |name: &str, kind| Surface {
+            name: name.to_owned(),
+            kind,
+            friction: 1.0,
+            speed: 1.0,
+            destructible: None,
+        };
+        let surfaces = vec![
+            surface("ground", Kind::Normal),
+            surface("crusher", Kind::Crusher),
+        ];
+        let text = "0 0 0 0 0\n0 0 4 0 0\n0 0 0 0 0\nhazard 2 1 period=4 phase=1 duration=1";
+        let map = map::load_map(text, surfaces).unwrap();
+        let nav = NavGrid::build(&map);
+        let mut gs = GameState::new(0);
+        let (start, goal, crusher) = (Vec2u::new(0, 1), Vec2u::new(4, 1), Vec2u::new(2, 1));
+
+        // Would reach the crusher at 1.0 - exactly when it comes down.
+        let path = find_path(&cvars, &gs, &map, &nav, start, goal, 0.5).unwrap();
+        assert!(!path.contains(&crusher), "{:?}", path);
+        assert_eq!(path.len(), 7);
+
+        // Would cross it between 3.0 and 3.5 while it's up.
+        gs.game_time = 2.0;
+        let path = find_path(&cvars, &gs, &map, &nav, start, goal, 0.5).unwrap();
+        assert_eq!(path.len(), 5);
+        assert_eq!(path[2], crusher);
+    }
+
+    #[test]
+    fn test_take_cover() {
+        let cvars = Cvars::default();
+        let surface = |name: &str, kind| Surface {
+            name: name.to_owned(),
+            kind,
+            friction: 1.0,
+            speed: 1.0,
+            destructible: None,
+        };
+        let surfaces = vec![surface("ground", Kind::Normal), surface("wall", Kind::Wall)];
+        let text = [
+            "0 0 0 0 0 0 0",
+            "0 0 0 0 0 0 0",
+            "0 0 0 4 0 0 0",
+            "0 0 0 0 0 0 0",
+        ]
+        .join("\n");
+        let map = map::load_map(&text, surfaces).unwrap();
+        let mut gs = GameState::new(0);
+        gs.game_time = 1.0;
+        let spawn = |gs: &mut GameState, name: &str, tile, angle| {
+            let player = gs.players.insert(Player::new(name.to_owned()));
+            let pos = map.tile_center(tile);
+            let vehicle = Vehicle::new(&cvars, pos, angle, VehicleType::Tank, 0, 0.0, player);
+            gs.players[player].vehicle = Some(gs.vehicles.insert(vehicle));
+            player
+        };
+        spawn(&mut gs, "Human", Vec2u::new(0, 2), 0.0);
+        // Facing the tile behind the wall.
+        let bot = spawn(&mut gs, "Bot", Vec2u::new(4, 0), PI / 2.0);
+        let mut bot_ai = Ai::new(&cvars, bot, cvars.bots_difficulty);
+        bot_ai.reaction_time = 0.1;
+        gs.ais.insert(bot_ai);
+
+        let bot_vehicle = gs.players[bot].vehicle.unwrap();
+        let hp_max = gs.vehicles[bot_vehicle].hp_max(&cvars);
+        gs.vehicles[bot_vehicle].hp = hp_max / 10;
+        ai(&cvars, &mut gs, &map);
+        gs.game_time += 0.1;
+        ai(&cvars, &mut gs, &map);
+        let input = &gs.players[bot].input;
+        assert!(input.up && !input.down && !input.left && !input.right);
+
+        // Stops once there, it still remembers the enemy because of its reaction time.
+        let cover = map.tile_center(Vec2u::new(4, 2));
+        gs.vehicles[bot_vehicle].pos.set(cover, 0);
+        gs.game_time += 0.05;
+        ai(&cvars, &mut gs, &map);
+        assert!(!gs.players[bot].input.up);
+    }
+
+    #[test]
+    fn test_steer_gm() {
+        let cvars = Cvars::default();
+        let text = ["0 0 0 0 0 0 0 0"; 3].join("\n");
+        let surfaces = vec![Surface {
+            name: "ground".to_owned(),
+            kind: Kind::Normal,
+            friction: 1.0,
+            speed: 1.0,
+            destructible: None,
+        }];
+        let map = map::load_map(&text, surfaces).unwrap();
+        let mut gs = GameState::new(0);
+        gs.game_time = 1.0;
+        let human = gs.players.insert(Player::new("Human".to_owned()));
+        let human_pos = map.tile_center(Vec2u::new(0, 1));
+        let vehicle = Vehicle::new(&cvars, human_pos, 0.0, VehicleType::Tank, 0, 0.0, human);
+        gs.players[human].vehicle = Some(gs.vehicles.insert(vehicle));
+        let bot = gs.players.insert(Player::new("Bot".to_owned()));
+        let pos = map.tile_center(Vec2u::new(7, 1));
+        let vehicle = Vehicle::new(&cvars, pos, PI, VehicleType::Tank, 0, 0.0, bot);
+        gs.players[bot].vehicle = Some(gs.vehicles.insert(vehicle));
+        let mut bot_ai = Ai::new(&cvars, bot, cvars.bots_difficulty);
+        bot_ai.reaction_time = 0.1;
+        gs.ais.insert(bot_ai);
+
+        // Flying up, away from the human.
+        let gm = gs.projectiles.insert(Projectile {
+            weapon: Weapon::Gm,
+            pos: map.tile_center(Vec2u::new(4, 1)),
+            vel: Vec2f::new(0.0, -100.0),
+            angle: -PI / 2.0,
+            turn_rate: 0.0,
+            explode_time: f64::MAX,
+            spawn_time: 0.0,
+            owner: bot,
+            lock: None,
+            retarget_time: 0.0,
+            altitude: None,
+        });
+        gs.players[bot].guided_missile = Some(gm);
+        ai(&cvars, &mut gs, &map);
+        gs.game_time += 0.1;
+        ai(&cvars, &mut gs, &map);
+        let input = gs.players[bot].input;
+        assert!(input.left != input.right);
+        assert!(!input.fire);
+
+        // Pointed at the human, it flies straight.
+        gs.projectiles[gm].angle = PI;
+        gs.game_time += 0.05;
+        ai(&cvars, &mut gs, &map);
+        let input = gs.players[bot].input;
+        assert!(!input.left && !input.right && !input.fire);
+
+        // Close enough - press fire once, then release it so it can be pressed again.
+        gs.projectiles[gm].pos = human_pos + Vec2f::new(10.0, 0.0);
+        gs.game_time += 0.05;
+        ai(&cvars, &mut gs, &map);
+        assert!(gs.players[bot].input.fire);
+        gs.game_time += 0.05;
+        ai(&cvars, &mut gs, &map);
+        assert!(!gs.players[bot].input.fire);
+    }
+
+    #[test]
+    fn test_intercept() {
+        let shooter = Vec2f::zero();
+        let target = Vec2f::new(100.0, 0.0);
+
+        // Stationary - aim straight at it.
+        assert_eq!(
+            intercept(shooter, target, Vec2f::zero(), 500.0),
+            Some(target)
+        );
+
+        // Crossing - both get to the point at the same time, ahead of the target.
+        let vel = Vec2f::new(0.0, 100.0);
+        let point = intercept(shooter, target, vel, 500.0).unwrap();
+        let time = point.distance(shooter) / 500.0;
+        assert!(point.distance(target + vel * time) < 1e-9);
+        assert!(point.y > 0.0);
+
+        // Receding slower than the projectile - caught further away.
+        let point = intercept(shooter, target, Vec2f::new(50.0, 0.0), 100.0).unwrap();
+        assert!(point.distance(Vec2f::new(200.0, 0.0)) < 1e-9);
+
+        // Receding at the same speed or faster - never caught.
+        assert_eq!(
+            intercept(shooter, target, Vec2f::new(100.0, 0.0), 100.0),
+            None
+        );
+        assert_eq!(
+            intercept(shooter, target, Vec2f::new(200.0, 0.0), 100.0),
+            None
+        );
+
+        // Approaching at the same speed - they meet halfway.
+        let point = intercept(shooter, target, Vec2f::new(-100.0, 0.0), 100.0).unwrap();
+        assert!(point.distance(Vec2f::new(50.0, 0.0)) < 1e-9);
+    }
+
+    #[test]
+    fn test_difficulty() {
+        let cvars = Cvars {
+            bots_aim_error: 0.05,
+            bots_reaction_time: 0.2,
+            ..Cvars::default()
+        };
+        let text = ["0 0 0 0 0 0 0 0"; 3].join("\n");
+        let surfaces = vec![Surface {
+            name: "ground".to_owned(),
+            kind: Kind::Normal,
+            friction: 1.0,
+            speed: 1.0,
+            destructible: None,
+        }];
+        let map = map::load_map(&text, surfaces).unwrap();
+
+        for difficulty in [0, BOTS_DIFFICULTY_MAX] {
+            let mut gs = GameState::new(0);
+            let ai_handle = spawn_bot(&cvars, &mut gs, "Bot".to_owned(), difficulty);
+            let bot = gs.ais[ai_handle].player;
+            let human = gs.players.insert(Player::new("Human".to_owned()));
+            for (player_handle, x) in [(bot, 50.0), (human, 350.0)] {
+                let pos = Vec2f::new(x, 96.0);
+                let vehicle =
+                    Vehicle::new(&cvars, pos, 0.0, VehicleType::Tank, 0, 0.0, player_handle);
+                let vehicle_handle = gs.vehicles.insert(vehicle);
+                gs.players[player_handle].vehicle = Some(vehicle_handle);
+            }
+            gs.players[bot].cur_weapon = Weapon::Rail;
+
+            // The bot doesn't react to the human until its reaction time has passed.
+            let reaction_time = gs.ais[ai_handle].reaction_time;
+            let mut reacted = None;
+            let mut fired = false;
+            let mut switched = false;
+            for frame in 1..=120 {
+                gs.game_time_prev = gs.game_time;
+                gs.game_time = frame as f64 * DT;
+                gs.dt = DT;
+                ai(&cvars, &mut gs, &map);
+                let input = gs.players[bot].input;
+                if reacted.is_none() && input.turret_target.is_some() {
+                    reacted = Some(gs.game_time);
+                    let angle = input.turret_target_angle().unwrap();
+                    assert!(angle.abs() < 0.01 || (angle - 2.0 * PI).abs() < 0.01);
+                }
+                fired |= input.fire;
+                switched |= input.next_weapon;
+            }
+            let reacted = reacted.unwrap();
+            assert!(reacted >= reaction_time - 1e-9);
+            assert!(reacted < reaction_time + 2.0 * DT);
+
+            // The turret starts aimed at the human so only the weapon decides.
+            // Easy bots switch away from the railgun instead.
+            assert_eq!(fired, difficulty >= cvars.bots_railgun_difficulty);
+            assert!(fired || switched);
         }
+        // Easier bots react slower.
+        let player = Arena::<()>::new().insert(());
+        let easy = Ai::new(&cvars, player, 0);
+        let hard = Ai::new(&cvars, player, BOTS_DIFFICULTY_MAX);
+        assert!(easy.reaction_time > cvars.bots_reaction_time);
+        assert!(hard.reaction_time < cvars.bots_reaction_time);
+        assert!(easy.aim_error > hard.aim_error);
     }
 }
@@ -0,0 +1,494 @@
+//! Capture the flag - two teams try to bring the enemy flag to their own base.
+//!
+//! Red's flag starts at the map's first base tile and Blue's at the base farthest from it.
+//! Any other base tiles belong to the team whose flag starts closer.
+//!
+//! - Enemies pick up a flag by touching it with their hitbox, within `g_ctf_flag_radius`.
+//!   When several touch it during the same frame, the one whose center is closest gets it.
+//! - A carrier drops the flag where it gets destroyed.
+//!   The flag returns to its base after `g_ctf_flag_return_time`
+//!   or immediately when touched by one of its own team - this wins over enemies touching it in the same frame.
+//! - A carrier captures by touching one of its own team's base tiles, the own flag doesn't need to be home.
+//!
+//! Win conditions are in `sys_match`.
+//!
+//! LATER Spawning near the own base, bots which know about teams and flags.
+
+use thunderdome::Index;
+
+use crate::{
+    cvars::Cvars,
+    entities::Team,
+    game_state::{push_notification, ArenaExt, GameState, NotificationKind},
+    map::{Kind, Map, Vec2f},
+};
+
+#[derive(Debug, Clone)]
+pub struct Ctf {
+    /// Indexed by `Team as usize`.
+    pub flags: [Flag; 2],
+    /// Indexed by `Team as usize`.
+    pub captures: [i32; 2],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flag {
+    /// Center of the base tile where the flag starts and returns to.
+    pub base: Vec2f,
+    pub pos: Vec2f,
+    pub state: FlagState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlagState {
+    AtBase,
+    /// Follows the vehicle with this handle.
+    Carried(Index),
+    Dropped {
+        return_time: f64,
+    },
+}
+
+impl Flag {
+    fn new(base: Vec2f) -> Self {
+        Self {
+            base,
+            pos: base,
+            state: FlagState::AtBase,
+        }
+    }
+
+    fn return_home(&mut self) {
+        self.pos = self.base;
+        self.state = FlagState::AtBase;
+    }
+}
+
+impl Ctf {
+    /// The team owning the base tile at `pos`, if there's one.
+    pub fn base_team(&self, map: &Map, pos: Vec2f) -> Option<Team> {
+        if map.surface_at_pos(pos).kind != Kind::Base {
+            return None;
+        }
+        let red_dist = self.flags[Team::Red as usize].base.distance_squared(pos);
+        let blue_dist = self.flags[Team::Blue as usize].base.distance_squared(pos);
+        if red_dist <= blue_dist {
+            Some(Team::Red)
+        } else {
+            Some(Team::Blue)
+        }
+    }
+}
+
+/// Place the flags and put all players into teams.
+///
+/// Maps with fewer than 2 bases can't be played this way and stay free for all.
+pub fn start(gs: &mut GameState, map: &Map) {
+    let bases = map.bases();
+    if bases.len() < 2 {
+        dbg_logf!(
+            "Capture the flag needs 2 bases, the map has {}",
+            bases.len()
+        );
+        return;
+    }
+
+    let red = map.tile_center(bases[0]);
+    let mut blue = red;
+    for &base in &bases[1..] {
+        let pos = map.tile_center(base);
+        if pos.distance_squared(red) > blue.distance_squared(red) {
+            blue = pos;
+        }
+    }
+    gs.ctf = Some(Ctf {
+        flags: [Flag::new(red), Flag::new(blue)],
+        captures: [0, 0],
+    });
+
+    for player_handle in gs.players.iter_handles() {
+        assign_team(gs, player_handle);
+    }
+}
+
+/// Put the player into the smaller team, Red when they're equal.
+/// Does nothing outside team modes.
+pub fn assign_team(gs: &mut GameState, player_handle: Index) {
+    if gs.ctf.is_none() {
+        return;
+    }
+
+    let mut counts = [0; 2];
+    for (_, player) in gs.players.iter() {
+        if let Some(team) = player.team {
+            counts[team as usize] += 1;
+        }
+    }
+    let team = if counts[Team::Blue as usize] < counts[Team::Red as usize] {
+        Team::Blue
+    } else {
+        Team::Red
+    };
+    gs.players[player_handle].team = Some(team);
+}
+
+/// Drop the flag the vehicle is carrying (if any) where it is now.
+pub fn drop_flag(cvars: &Cvars, gs: &mut GameState, vehicle_handle: Index) {
+    let ctf = match &mut gs.ctf {
+        Some(ctf) => ctf,
+        None => return,
+    };
+
+    let pos = *gs.vehicles[vehicle_handle].pos;
+    for team in Team::all() {
+        let flag = &mut ctf.flags[team as usize];
+        if flag.state == FlagState::Carried(vehicle_handle) {
+            flag.pos = pos;
+            flag.state = FlagState::Dropped {
+                return_time: gs.game_time + cvars.g_ctf_flag_return_time,
+            };
+            let kind = NotificationKind::FlagDropped { team };
+            push_notification(cvars, &mut gs.notifications, gs.game_time, kind);
+        }
+    }
+}
+
+/// Move carried flags, return, pick up and capture them.
+pub fn ctf(cvars: &Cvars, gs: &mut GameState, map: &Map) {
+    let ctf = match &mut gs.ctf {
+        Some(ctf) => ctf,
+        None => return,
+    };
+
+    for team in Team::all() {
+        let flag = &mut ctf.flags[team as usize];
+        match flag.state {
+            FlagState::AtBase => {}
+            FlagState::Carried(vehicle_handle) => match gs.vehicles.get(vehicle_handle) {
+                Some(vehicle) if !vehicle.destroyed() => flag.pos = *vehicle.pos,
+                // Destroyed carriers drop the flag in `systems::damage`,
+                // this is for vehicles removed some other way, e.g. when their player disconnects.
+                _ => {
+                    flag.state = FlagState::Dropped {
+                        return_time: gs.game_time + cvars.g_ctf_flag_return_time,
+                    };
+                    let kind = NotificationKind::FlagDropped { team };
+                    push_notification(cvars, &mut gs.notifications, gs.game_time, kind);
+                }
+            },
+            FlagState::Dropped { return_time } => {
+                if gs.game_time >= return_time {
+                    flag.return_home();
+                    let kind = NotificationKind::FlagReturned { team };
+                    push_notification(cvars, &mut gs.notifications, gs.game_time, kind);
+                }
+            }
+        }
+    }
+
+    for flag_index in 0..ctf.flags.len() {
+        let vehicle_handle = match ctf.flags[flag_index].state {
+            FlagState::Carried(vehicle_handle) => vehicle_handle,
+            _ => continue,
+        };
+        let vehicle = &gs.vehicles[vehicle_handle];
+        let player = &mut gs.players[vehicle.owner];
+        if let Some(team) = player.team {
+            if ctf.base_team(map, *vehicle.pos) == Some(team) {
+                ctf.captures[team as usize] += 1;
+                player.score.captures += 1;
+                ctf.flags[flag_index].return_home();
+                let kind = NotificationKind::FlagCaptured {
+                    team,
+                    name: player.name.clone(),
+                };
+                push_notification(cvars, &mut gs.notifications, gs.game_time, kind);
+            }
+        }
+    }
+
+    for (flag_index, flag) in ctf.flags.iter_mut().enumerate() {
+        if let FlagState::Carried(_) = flag.state {
+            continue;
+        }
+
+        let flag_team = Team::n(flag_index as u8).unwrap();
+        let mut returned = false;
+        let mut taker: Option<(Index, f64)> = None;
+        for (vehicle_handle, vehicle) in gs.vehicles.iter() {
+            if vehicle.destroyed() {
+                continue;
+            }
+            let team = match gs.players[vehicle.owner].team {
+                Some(team) => team,
+                None => continue,
+            };
+            if vehicle.obb.distance(flag.pos) > cvars.g_ctf_flag_radius {
+                continue;
+            }
+            if team == flag_team {
+                returned = true;
+                continue;
+            }
+            // Distance from the center because the flag can be inside several hitboxes.
+            // Ties go to the vehicle which comes first in the arena.
+            let dist_sq = vehicle.pos.distance_squared(flag.pos);
+            if taker.map_or(true, |(_, taker_dist_sq)| dist_sq < taker_dist_sq) {
+                taker = Some((vehicle_handle, dist_sq));
+            }
+        }
+
+        if returned && flag.state != FlagState::AtBase {
+            flag.return_home();
+            let kind = NotificationKind::FlagReturned { team: flag_team };
+            push_notification(cvars, &mut gs.notifications, gs.game_time, kind);
+        } else if let Some((vehicle_handle, _)) = taker {
+            let vehicle = &gs.vehicles[vehicle_handle];
+            flag.pos = *vehicle.pos;
+            flag.state = FlagState::Carried(vehicle_handle);
+            let kind = NotificationKind::FlagTaken {
+                team: flag_team,
+                name: gs.players[vehicle.owner].name.clone(),
+            };
+            push_notification(cvars, &mut gs.notifications, gs.game_time, kind);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::{
+        cvars::{DamageType, FriendlyFire},
+        entities::{Player, Vehicle, VehicleType},
+        fixed, map, systems,
+    };
+
+    fn setup(cvars: &Cvars, players: usize) -> (GameState, Map, Vec<Index>) {
+        let map =
+            map::tests::load_test_map(&fs::read_to_string("maps/A simple plan (2).map").unwrap());
+
+        let mut gs = GameState::new(0);
+        let mut vehicles = Vec::new();
+        for i in 0..players {
+            let player_handle = gs.players.insert(Player::new(format!("Player {}", i)));
+            let pos = map.tile_center(map.spawns()[i]);
+            let vehicle = Vehicle::new(cvars, pos, 0.0, VehicleType::Tank, 0, 0.0, player_handle);
+            let vehicle_handle = gs.vehicles.insert(vehicle);
+            gs.players[player_handle].vehicle = Some(vehicle_handle);
+            vehicles.push(vehicle_handle);
+        }
+        start(&mut gs, &map);
+        (gs, map, vehicles)
+    }
+
+    fn teleport(gs: &mut GameState, vehicle_handle: Index, pos: Vec2f) {
+        let vehicle = &mut gs.vehicles[vehicle_handle];
+        vehicle.pos.set(pos, gs.frame_num);
+        vehicle.update_obb();
+    }
+
+    fn flag(gs: &GameState, team: Team) -> &Flag {
+        &gs.ctf.as_ref().unwrap().flags[team as usize]
+    }
+
+    fn kill(cvars: &Cvars, gs: &mut GameState, attacker: Index, vehicle_handle: Index) {
+        let owner = gs.vehicles[vehicle_handle].owner;
+        let attacker = gs.vehicles[attacker].owner;
+        let hp = gs.vehicles[vehicle_handle].hp;
+        systems::damage(
+            cvars,
+            gs,
+            attacker,
+            vehicle_handle,
+            None,
+            hp,
+            DamageType::Kinetic,
+            None,
+        );
+        assert!(gs.vehicles[vehicle_handle].destroyed());
+        assert_eq!(gs.players[owner].score.deaths, 1);
+    }
+
+    #[test]
+    fn test_start() {
+        let cvars = Cvars::default();
+        let (gs, map, vehicles) = setup(&cvars, 5);
+        let ctf = gs.ctf.as_ref().unwrap();
+        assert_eq!(ctf.flags[0].base, map.tile_center(map.bases()[0]));
+        assert_eq!(ctf.flags[1].base, map.tile_center(map.bases()[1]));
+        assert_eq!(ctf.base_team(&map, ctf.flags[0].base), Some(Team::Red));
+        assert_eq!(ctf.base_team(&map, ctf.flags[1].base), Some(Team::Blue));
+        assert_eq!(ctf.base_team(&map, *gs.vehicles[vehicles[0]].pos), None);
+
+        let teams: Vec<_> = gs.players.iter().map(|(_, p)| p.team.unwrap()).collect();
+        let reds = teams.iter().filter(|&&team| team == Team::Red).count();
+        assert_eq!(reds, 3);
+    }
+
+    #[test]
+    fn test_pickup_and_capture() {
+        let cvars = Cvars::default();
+        let (mut gs, map, vehicles) = setup(&cvars, 2);
+        let red = vehicles[0];
+        let blue_base = flag(&gs, Team::Blue).base;
+        let red_base = flag(&gs, Team::Red).base;
+
+        // Touching your own flag at home does nothing.
+        teleport(&mut gs, red, red_base);
+        ctf(&cvars, &mut gs, &map);
+        assert_eq!(flag(&gs, Team::Red).state, FlagState::AtBase);
+
+        // Just out of reach.
+        let reach = cvars.g_ctf_flag_radius + gs.vehicles[red].hitbox.maxs.x;
+        teleport(&mut gs, red, blue_base - Vec2f::new(reach + 1.0, 0.0));
+        ctf(&cvars, &mut gs, &map);
+        assert_eq!(flag(&gs, Team::Blue).state, FlagState::AtBase);
+
+        teleport(&mut gs, red, blue_base - Vec2f::new(reach - 1.0, 0.0));
+        ctf(&cvars, &mut gs, &map);
+        assert_eq!(flag(&gs, Team::Blue).state, FlagState::Carried(red));
+
+        let halfway = (blue_base + red_base) / 2.0;
+        teleport(&mut gs, red, halfway);
+        ctf(&cvars, &mut gs, &map);
+        assert_eq!(flag(&gs, Team::Blue).pos, halfway);
+
+        teleport(&mut gs, red, red_base);
+        ctf(&cvars, &mut gs, &map);
+        let ctf = gs.ctf.as_ref().unwrap();
+        assert_eq!(ctf.captures, [1, 0]);
+        assert_eq!(ctf.flags[Team::Blue as usize].state, FlagState::AtBase);
+        assert_eq!(ctf.flags[Team::Blue as usize].pos, blue_base);
+        let owner = gs.vehicles[red].owner;
+        assert_eq!(gs.players[owner].score.captures, 1);
+        assert_eq!(
+            gs.players[owner].score.points(&cvars),
+            cvars.g_ctf_score_capture
+        );
+    }
+
+    #[test]
+    fn test_carrier_death() {
+        let cvars = Cvars::default();
+        let (mut gs, map, vehicles) = setup(&cvars, 3);
+        let (red, blue, red2) = (vehicles[0], vehicles[1], vehicles[2]);
+        let blue_base = flag(&gs, Team::Blue).base;
+
+        teleport(&mut gs, red, blue_base);
+        ctf(&cvars, &mut gs, &map);
+        assert_eq!(flag(&gs, Team::Blue).state, FlagState::Carried(red));
+
+        let death_pos = blue_base + Vec2f::new(300.0, 0.0);
+        teleport(&mut gs, red, death_pos);
+        gs.game_time = 10.0;
+        kill(&cvars, &mut gs, blue, red);
+        let return_time = 10.0 + cvars.g_ctf_flag_return_time;
+        assert_eq!(flag(&gs, Team::Blue).pos, death_pos);
+        assert_eq!(
+            flag(&gs, Team::Blue).state,
+            FlagState::Dropped { return_time }
+        );
+
+        // The wreck is still touching it but can't pick it up again.
+        ctf(&cvars, &mut gs, &map);
+        assert_eq!(
+            flag(&gs, Team::Blue).state,
+            FlagState::Dropped { return_time }
+        );
+
+        // A teammate can.
+        teleport(&mut gs, red2, death_pos);
+        ctf(&cvars, &mut gs, &map);
+        assert_eq!(flag(&gs, Team::Blue).state, FlagState::Carried(red2));
+
+        // The vehicle disappears without getting destroyed, e.g. the player disconnected.
+        gs.vehicles.remove(red2);
+        ctf(&cvars, &mut gs, &map);
+        assert_eq!(
+            flag(&gs, Team::Blue).state,
+            FlagState::Dropped { return_time }
+        );
+        assert_eq!(flag(&gs, Team::Blue).pos, death_pos);
+
+        gs.game_time = return_time - 0.1;
+        ctf(&cvars, &mut gs, &map);
+        assert_eq!(flag(&gs, Team::Blue).pos, death_pos);
+        gs.game_time = return_time;
+        ctf(&cvars, &mut gs, &map);
+        assert_eq!(flag(&gs, Team::Blue).state, FlagState::AtBase);
+        assert_eq!(flag(&gs, Team::Blue).pos, blue_base);
+        assert_eq!(gs.ctf.as_ref().unwrap().captures, [0, 0]);
+    }
+
+    #[test]
+    fn test_simultaneous_touch() {
+        let cvars = Cvars::default();
+        let (mut gs, map, vehicles) = setup(&cvars, 4);
+        let (red, blue, red2, blue2) = (vehicles[0], vehicles[1], vehicles[2], vehicles[3]);
+        let blue_base = flag(&gs, Team::Blue).base;
+
+        // Two attackers at once - the closer one gets it, regardless of arena order.
+        teleport(&mut gs, red, blue_base + Vec2f::new(10.0, 0.0));
+        teleport(&mut gs, red2, blue_base - Vec2f::new(5.0, 0.0));
+        // Defenders at home don't prevent stealing.
+        teleport(&mut gs, blue, blue_base);
+        ctf(&cvars, &mut gs, &map);
+        assert_eq!(flag(&gs, Team::Blue).state, FlagState::Carried(red2));
+
+        // An attacker and a defender touching a dropped flag - it goes home.
+        kill(&cvars, &mut gs, blue, red2);
+        teleport(&mut gs, red, Vec2f::new(100.0, 100.0));
+        teleport(&mut gs, blue, Vec2f::new(100.0, 100.0));
+        let dropped_pos = flag(&gs, Team::Blue).pos;
+        teleport(&mut gs, red, dropped_pos);
+        teleport(&mut gs, blue2, dropped_pos);
+        ctf(&cvars, &mut gs, &map);
+        assert_eq!(flag(&gs, Team::Blue).state, FlagState::AtBase);
+
+        // Both carriers reach their bases in the same frame - both score.
+        let red_base = flag(&gs, Team::Red).base;
+        teleport(&mut gs, red, blue_base);
+        teleport(&mut gs, blue, red_base);
+        teleport(&mut gs, blue2, Vec2f::new(100.0, 100.0));
+        ctf(&cvars, &mut gs, &map);
+        assert_eq!(flag(&gs, Team::Blue).state, FlagState::Carried(red));
+        assert_eq!(flag(&gs, Team::Red).state, FlagState::Carried(blue));
+        teleport(&mut gs, red, red_base);
+        teleport(&mut gs, blue, blue_base);
+        ctf(&cvars, &mut gs, &map);
+        assert_eq!(gs.ctf.as_ref().unwrap().captures, [1, 1]);
+        assert_eq!(flag(&gs, Team::Red).state, FlagState::AtBase);
+        assert_eq!(flag(&gs, Team::Blue).state, FlagState::AtBase);
+    }
+
+    #[test]
+    fn test_friendly_fire() {
+        let dealt = |friendly_fire| {
+            let cvars = Cvars {
+                g_friendly_fire: friendly_fire,
+                ..Cvars::default()
+            };
+            let (mut gs, _map, vehicles) = setup(&cvars, 3);
+            let (red, red2) = (vehicles[0], vehicles[2]);
+            let attacker = gs.vehicles[red].owner;
+            let hp = gs.vehicles[red2].hp;
+            systems::damage(
+                &cvars,
+                &mut gs,
+                attacker,
+                red2,
+                None,
+                fixed::to_milli(10.0),
+                DamageType::Kinetic,
+                None,
+            );
+            hp - gs.vehicles[red2].hp
+        };
+        assert_eq!(dealt(FriendlyFire::Off), 0);
+        assert_eq!(dealt(FriendlyFire::On), fixed::to_milli(10.0));
+    }
+}
@@ -0,0 +1,189 @@
+//! Track marks and scorch marks on the ground - purely cosmetic, nothing in the gamelogic reads them.
+//!
+//! Vehicles leave track marks every `cl_tracks_interval` pixels they travel
+//! (not once per frame) so the spacing doesn't depend on speed or frame rate.
+//! Explosions leave scorch marks scaled by their size.
+//!
+//! Decals are kept in a ring buffer of at most `cl_decals_max`, the oldest are evicted first.
+//! They're not in snapshots or checksums.
+
+use crate::{
+    cvars::Cvars,
+    effects::Effect,
+    entities::VehicleType,
+    game_state::{Decal, DecalKind, GameState},
+};
+
+/// Add a decal, evicting the oldest ones if the buffer is full.
+pub fn push(cvars: &Cvars, gs: &mut GameState, decal: Decal) {
+    if cvars.cl_decals_max == 0 {
+        return;
+    }
+    while gs.decals.len() >= cvars.cl_decals_max {
+        gs.decals.pop_front();
+    }
+    gs.decals.push_back(decal);
+}
+
+/// Emit track marks behind moving vehicles and scorch marks under this frame's explosions.
+///
+/// Has to run after everything that can spawn explosions.
+pub fn update_decals(cvars: &Cvars, gs: &mut GameState) {
+    if cvars.cl_decals_max == 0 {
+        gs.decals.clear();
+        gs.track_distances.clear();
+        return;
+    }
+
+    let vehicles = &gs.vehicles;
+    gs.track_distances
+        .retain(|&handle, _| vehicles.contains(handle));
+
+    let mut tracks = Vec::new();
+    for (handle, vehicle) in gs.vehicles.iter() {
+        // Hovercraft don't touch the ground and wrecks don't drive.
+        if vehicle.veh_type == VehicleType::Hovercraft || vehicle.destroyed() {
+            continue;
+        }
+        let step = vehicle.vel.magnitude() * gs.dt;
+        if step == 0.0 {
+            continue;
+        }
+        let dir = vehicle.vel / vehicle.vel.magnitude();
+        let distance = gs.track_distances.entry(handle).or_insert(0.0);
+        *distance += step;
+        // Place each mark where the vehicle was when it crossed the interval,
+        // not where it is now, so marks are evenly spaced even when several are due.
+        while *distance >= cvars.cl_tracks_interval {
+            *distance -= cvars.cl_tracks_interval;
+            tracks.push(Decal {
+                pos: *vehicle.pos - dir * *distance,
+                angle: *vehicle.angle,
+                scale: 1.0,
+                kind: DecalKind::Tracks(vehicle.veh_type),
+                spawn_time: gs.game_time,
+            });
+        }
+    }
+
+    // Explosions don't have a single place where they're created so look for the new ones.
+    let scorches: Vec<_> = gs
+        .effects
+        .iter()
+        .filter_map(|effect| match *effect {
+            Effect::Explosion {
+                pos,
+                scale,
+                start_time,
+                ..
+            } if start_time == gs.game_time => Some(Decal {
+                pos,
+                // Not all scorch marks should look the same, this is cheap and deterministic.
+                angle: (pos.x * 7.0 + pos.y * 13.0).rem_euclid(360.0).to_radians(),
+                scale,
+                kind: DecalKind::Scorch,
+                spawn_time: start_time,
+            }),
+            _ => None,
+        })
+        .collect();
+
+    for decal in tracks.into_iter().chain(scorches) {
+        push(cvars, gs, decal);
+    }
+}
+
+/// How visible a decal is, 1 until it gets close to being evicted from a full buffer, then down to 0.
+///
+/// `index` is its position in `GameState::decals`, 0 is the oldest.
+pub fn decal_alpha(cvars: &Cvars, decals: usize, index: usize) -> f64 {
+    // Number of decals which have to be added before this one is evicted.
+    let left = cvars.cl_decals_max.saturating_sub(decals) + index;
+    let fade = (cvars.cl_decals_max as f64 * cvars.cl_decals_fade).max(1.0);
+    (left as f64 / fade).min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use thunderdome::{Arena, Index};
+
+    use crate::{entities::Vehicle, map::Vec2f};
+
+    use super::*;
+
+    fn tank(cvars: &Cvars, gs: &mut GameState, vel: Vec2f) -> Index {
+        let owner = Arena::<()>::new().insert(());
+        let mut vehicle = Vehicle::new(
+            cvars,
+            Vec2f::new(100.0, 100.0),
+            0.0,
+            VehicleType::Tank,
+            0,
+            0.0,
+            owner,
+        );
+        vehicle.vel = vel;
+        gs.vehicles.insert(vehicle)
+    }
+
+    #[test]
+    fn test_track_spacing() {
+        let cvars = Cvars::default();
+        // Same distance at different speeds and frame rates gives the same number of marks.
+        let mut counts = Vec::new();
+        for (speed, fps) in [(100.0, 60.0), (300.0, 30.0), (50.0, 144.0)] {
+            let mut gs = GameState::new(1);
+            let handle = tank(&cvars, &mut gs, Vec2f::new(speed, 0.0));
+            gs.dt = 1.0 / fps;
+            let distance = 10.5 * cvars.cl_tracks_interval;
+            let frames = (distance / (speed * gs.dt)).round() as usize;
+            for _ in 0..frames {
+                gs.game_time += gs.dt;
+                let pos = *gs.vehicles[handle].pos + gs.vehicles[handle].vel * gs.dt;
+                gs.vehicles[handle].pos.set(pos, 0);
+                update_decals(&cvars, &mut gs);
+            }
+            counts.push(gs.decals.len());
+
+            for pair in gs.decals.make_contiguous().windows(2) {
+                let spacing = pair[0].pos.distance(pair[1].pos);
+                assert!((spacing - cvars.cl_tracks_interval).abs() < 1e-6);
+            }
+        }
+        assert_eq!(counts, [10, 10, 10]);
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let cvars = Cvars {
+            cl_decals_max: 4,
+            ..Cvars::default()
+        };
+        let mut gs = GameState::new(1);
+        for i in 0..6 {
+            gs.game_time = i as f64;
+            gs.effects.push(Effect::Explosion {
+                pos: Vec2f::new(i as f64, 0.0),
+                scale: 0.5,
+                start_time: gs.game_time,
+                bfg: false,
+            });
+            update_decals(&cvars, &mut gs);
+        }
+        let xs: Vec<_> = gs.decals.iter().map(|decal| decal.pos.x).collect();
+        assert_eq!(xs, [2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(gs.decals[0].kind, DecalKind::Scorch);
+        assert_eq!(gs.decals[0].scale, 0.5);
+
+        // The oldest fade out, the newest don't.
+        assert!(decal_alpha(&cvars, 4, 0) < decal_alpha(&cvars, 4, 3));
+        assert_eq!(decal_alpha(&cvars, 4, 3), 1.0);
+
+        let cvars = Cvars {
+            cl_decals_max: 0,
+            ..cvars
+        };
+        update_decals(&cvars, &mut gs);
+        assert!(gs.decals.is_empty());
+    }
+}
@@ -0,0 +1,322 @@
+//! Map hazards - damage floors, crushers and spike traps.
+//!
+//! Hazard tiles are defined by their surface kind, their parameters come from the map
+//! (see `map::load_map`) or the `g_hazard_*` cvars.
+//!
+//! Crusher state is derived purely from game time so it's the same for everyone
+//! and the telegraph can be trusted. Spike traps need to remember when they fired.
+//!
+//! Hazards have no attacker - the damage is environmental and deaths count as suicides.
+
+use thunderdome::Index;
+
+use crate::{
+    cvars::{Cvars, DamageType},
+    fixed,
+    game_state::GameState,
+    map::{Hazard, Kind, Map, Vec2f, Vec2u, TILE_SIZE},
+    systems,
+};
+
+/// What a hazard is doing right now, mostly for choosing the animation frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HazardState {
+    /// Crusher is up
+    Idle,
+    /// Crusher is about to come down
+    Telegraph,
+    /// Crusher is down, spike trap is armed, damage floors are always active
+    Active,
+    /// Spike trap fired and will be armed again at the given time
+    Rearming(f64),
+}
+
+/// State of a crusher at `time`.
+///
+/// It comes down at `phase` and then every `period` seconds and stays down for `duration`.
+/// The telegraph starts `g_hazard_crusher_telegraph` seconds before it comes down.
+pub fn crusher_state(cvars: &Cvars, hazard: &Hazard, time: f64) -> HazardState {
+    let (period, phase, duration) = crusher_timing(cvars, hazard);
+    let t = (time - phase).rem_euclid(period);
+    if t < duration {
+        HazardState::Active
+    } else if t >= period - cvars.g_hazard_crusher_telegraph {
+        HazardState::Telegraph
+    } else {
+        HazardState::Idle
+    }
+}
+
+fn crusher_timing(cvars: &Cvars, hazard: &Hazard) -> (f64, f64, f64) {
+    let period = hazard.period.unwrap_or(cvars.g_hazard_crusher_period);
+    let phase = hazard.phase.unwrap_or(cvars.g_hazard_crusher_phase);
+    let duration = hazard.duration.unwrap_or(cvars.g_hazard_crusher_duration);
+    (period, phase, duration)
+}
+
+/// Update the state of all hazards and damage vehicles on the active ones.
+pub fn hazards(cvars: &Cvars, gs: &mut GameState, map: &Map) {
+    let map_hazards = map.hazards();
+    if gs.hazards.len() != map_hazards.len() {
+        gs.hazards = vec![HazardState::Idle; map_hazards.len()];
+    }
+    if map_hazards.is_empty() {
+        return;
+    }
+
+    gs.vehicle_grid.update(
+        cvars.sv_collision_grid_cell_size,
+        map.maxs(),
+        &gs.vehicles,
+        cvars.g_hitcircle_radius,
+        gs.frame_num,
+    );
+
+    for (i, hazard) in map_hazards.iter().enumerate() {
+        let kind = map.hazard_kind(hazard);
+        let state = match (kind, gs.hazards[i]) {
+            (Kind::Crusher, _) => crusher_state(cvars, hazard, gs.game_time),
+            (Kind::Spikes, HazardState::Rearming(armed_time)) if gs.game_time < armed_time => {
+                HazardState::Rearming(armed_time)
+            }
+            _ => HazardState::Active,
+        };
+        gs.hazards[i] = state;
+        if state != HazardState::Active {
+            continue;
+        }
+
+        let victims = vehicles_on(gs, map, hazard.index);
+        if victims.is_empty() {
+            continue;
+        }
+        // Damage floors deal damage per second, the rest all at once.
+        let (amount, dps) = match kind {
+            Kind::DamageFloor => (
+                0,
+                Some(hazard.damage.unwrap_or(cvars.g_hazard_damage_floor_dps)),
+            ),
+            Kind::Crusher => (
+                fixed::to_milli(hazard.damage.unwrap_or(cvars.g_hazard_crusher_damage)),
+                None,
+            ),
+            _ => {
+                let rearm = hazard.rearm.unwrap_or(cvars.g_hazard_spikes_rearm);
+                gs.hazards[i] = HazardState::Rearming(gs.game_time + rearm);
+                let damage = hazard.damage.unwrap_or(cvars.g_hazard_spikes_damage);
+                (fixed::to_milli(damage), None)
+            }
+        };
+        for vehicle_handle in victims {
+            let vehicle = &mut gs.vehicles[vehicle_handle];
+            // An earlier hazard this frame might have killed it.
+            if vehicle.destroyed() {
+                continue;
+            }
+            let owner = vehicle.owner;
+            let amount = match dps {
+                Some(dps) => fixed::rate(dps, gs.dt, &mut vehicle.damage_remainder),
+                None => amount,
+            };
+            systems::damage(
+                cvars,
+                gs,
+                owner,
+                vehicle_handle,
+                None,
+                amount,
+                DamageType::Environmental,
+                None,
+            );
+        }
+    }
+}
+
+/// Vehicles whose center is on the tile - the same rule as for surface effects.
+fn vehicles_on(gs: &GameState, map: &Map, index: Vec2u) -> Vec<Index> {
+    let mins = Vec2f::new(index.x as f64, index.y as f64) * TILE_SIZE;
+    let maxs = mins + TILE_SIZE;
+    gs.vehicle_grid
+        .query_rect(mins, maxs)
+        .into_iter()
+        .filter(|&handle| {
+            let vehicle = &gs.vehicles[handle];
+            !vehicle.destroyed() && map.tile_pos(*vehicle.pos).index == index
+        })
+        .collect()
+}
+
+/// Whether a vehicle on the tile anytime between `begin` and `end` could get hurt.
+///
+/// Used by bots to avoid hazards. Damage floors and armed spike traps always count,
+/// crushers only if they're down at some point during the interval.
+pub fn is_dangerous(
+    cvars: &Cvars,
+    gs: &GameState,
+    map: &Map,
+    index: Vec2u,
+    begin: f64,
+    end: f64,
+) -> bool {
+    let i = match map
+        .hazards()
+        .iter()
+        .position(|hazard| hazard.index == index)
+    {
+        Some(i) => i,
+        None => return false,
+    };
+    let hazard = &map.hazards()[i];
+    match map.hazard_kind(hazard) {
+        Kind::Crusher => {
+            let (period, phase, duration) = crusher_timing(cvars, hazard);
+            let t = (begin - phase).rem_euclid(period);
+            let next_down = begin + period - t;
+            t < duration || next_down <= end
+        }
+        Kind::Spikes => match gs.hazards.get(i) {
+            Some(HazardState::Rearming(armed_time)) => *armed_time <= end,
+            _ => true,
+        },
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        entities::{Player, Vehicle, VehicleType},
+        map::{self, Surface},
+    };
+
+    fn surfaces() -> Vec<Surface> {
+        let surface = |name: &str, kind| Surface {
+            name: name.to_owned(),
+            kind,
+            friction: 1.0,
+            speed: 1.0,
+            destructible: None,
+        };
+        vec![
+            surface("ground", Kind::Normal),
+            surface("floor", Kind::DamageFloor),
+            surface("crusher", Kind::Crusher),
+            surface("spikes", Kind::Spikes),
+        ]
+    }
+
+    /// A 3x1 map with the hazard in the middle and a tank parked on it.
+    fn setup(cvars: &Cvars, tile: usize, meta: &str) -> (GameState, Map, Index) {
+        let text = format!("0 {} 0\n{}", tile * 4, meta);
+        let map = map::load_map(&text, surfaces()).unwrap();
+        let mut gs = GameState::new(0);
+        let player_handle = gs.players.insert(Player::new("Player".to_owned()));
+        let pos = map.tile_center(Vec2u::new(1, 0));
+        let vehicle = Vehicle::new(cvars, pos, 0.0, VehicleType::Tank, 0, 0.0, player_handle);
+        let vehicle_handle = gs.vehicles.insert(vehicle);
+        gs.players[player_handle].vehicle = Some(vehicle_handle);
+        (gs, map, vehicle_handle)
+    }
+
+    /// Advance to the next frame and run the system.
+    fn tick(cvars: &Cvars, gs: &mut GameState, map: &Map) {
+        gs.frame_num += 1;
+        gs.game_time_prev = gs.game_time;
+        gs.game_time = gs.frame_num as f64 * gs.dt;
+        hazards(cvars, gs, map);
+    }
+
+    #[test]
+    fn test_crusher_timing() {
+        let cvars = Cvars {
+            g_hazard_crusher_telegraph: 0.75,
+            ..Cvars::default()
+        };
+        let hazard = Hazard {
+            period: Some(4.0),
+            phase: Some(1.5),
+            duration: Some(0.5),
+            ..Hazard::default()
+        };
+        let state = |time| crusher_state(&cvars, &hazard, time);
+        for k in -1..3 {
+            let down = 1.5 + k as f64 * 4.0;
+            assert_eq!(state(down - 0.75 - 1e-9), HazardState::Idle);
+            assert_eq!(state(down - 0.75), HazardState::Telegraph);
+            assert_eq!(state(down - 1e-9), HazardState::Telegraph);
+            assert_eq!(state(down), HazardState::Active);
+            assert_eq!(state(down + 0.5 - 1e-9), HazardState::Active);
+            assert_eq!(state(down + 0.5), HazardState::Idle);
+        }
+
+        // Lethal in the sim - the parked tank dies on the first frame the crusher is down.
+        let cvars = Cvars::default();
+        let (mut gs, map, vehicle_handle) =
+            setup(&cvars, 2, "hazard 1 0 period=2 phase=1.5 duration=0.5");
+        gs.dt = 1.0 / 8.0;
+        let mut states = Vec::new();
+        while !gs.vehicles[vehicle_handle].destroyed() {
+            tick(&cvars, &mut gs, &map);
+            if states.last() != Some(&gs.hazards[0]) {
+                states.push(gs.hazards[0]);
+            }
+        }
+        let expected = [
+            HazardState::Idle,
+            HazardState::Telegraph,
+            HazardState::Active,
+        ];
+        assert_eq!(states, expected);
+        assert_eq!(gs.game_time, 1.5);
+        assert_eq!(gs.hazards[0], HazardState::Active);
+        let owner = gs.vehicles[vehicle_handle].owner;
+        assert_eq!(gs.players[owner].score.suicides, 1);
+    }
+
+    #[test]
+    fn test_damage_floor_death_tick() {
+        let cvars = Cvars::default();
+        let hp = cvars.g_vehicle_hp(VehicleType::Tank);
+        // Chosen so the death doesn't land exactly on a frame boundary.
+        let dps = hp / 2.51;
+        let meta = format!("hazard 1 0 damage={}", dps);
+        let (mut gs, map, vehicle_handle) = setup(&cvars, 1, &meta);
+        gs.dt = 1.0 / 60.0;
+        let predicted = (hp / (dps * gs.dt)).ceil() as u64;
+        while !gs.vehicles[vehicle_handle].destroyed() {
+            tick(&cvars, &mut gs, &map);
+            assert!(gs.frame_num <= predicted);
+        }
+        assert_eq!(gs.frame_num, predicted);
+    }
+
+    #[test]
+    fn test_spikes_rearm() {
+        let cvars = Cvars::default();
+        let meta = "hazard 1 0 damage=10 rearm=1.5";
+        let (mut gs, map, vehicle_handle) = setup(&cvars, 3, meta);
+        let hp_max = fixed::to_milli(cvars.g_vehicle_hp(VehicleType::Tank));
+        let hp = |gs: &GameState| gs.vehicles[vehicle_handle].hp;
+        gs.dt = 0.25;
+
+        tick(&cvars, &mut gs, &map);
+        assert_eq!(hp(&gs), hp_max - 10_000);
+        assert_eq!(gs.hazards[0], HazardState::Rearming(1.75));
+        assert!(is_dangerous(&cvars, &gs, &map, Vec2u::new(1, 0), 1.5, 2.0));
+        assert!(!is_dangerous(&cvars, &gs, &map, Vec2u::new(1, 0), 1.0, 1.5));
+
+        // Still on it but the trap only fires once.
+        while gs.game_time < 1.5 {
+            tick(&cvars, &mut gs, &map);
+        }
+        assert_eq!(hp(&gs), hp_max - 10_000);
+
+        // Fires again as soon as it rearms.
+        tick(&cvars, &mut gs, &map);
+        assert_eq!(gs.game_time, 1.75);
+        assert_eq!(hp(&gs), hp_max - 20_000);
+        assert_eq!(gs.hazards[0], HazardState::Rearming(3.25));
+    }
+}
@@ -0,0 +1,280 @@
+//! Homing missiles and the flares which decoy them.
+//!
+//! A homing missile locks onto the closest enemy vehicle in a cone in front of it
+//! (`g_homing_missile_lock_angle_deg` and `g_homing_missile_lock_range`) and turns towards it.
+//! It chooses again every `g_homing_missile_retarget_interval`.
+//!
+//! Enemy flares in the cone are a distraction - each time the missile chooses,
+//! it goes for the closest one with probability `g_homing_missile_flare_preference`.
+//! Then it stays on the flare until it burns out and looks for a new target right away.
+//!
+//! Vehicles deploy flares with the flare input, limited by `g_flare_count` per life and `g_flare_cooldown`.
+//! Guided missiles are steered by their owner and ignore flares.
+
+use std::f64::consts::PI;
+
+use rand::Rng;
+use thunderdome::{Arena, Index};
+use vek::Clamp;
+
+use crate::{
+    cvars::Cvars,
+    entities::{Flare, Lock, Player, Projectile, Vehicle, Weapon},
+    game_state::GameState,
+    map::{F64Ext, Vec2f, VecExt},
+    sys_particles,
+    trace::{self, EntitySummary, TraceEvent},
+};
+
+/// Drop a flare behind each vehicle whose player just pressed the flare input and has one ready.
+pub fn deploy_flares(cvars: &Cvars, gs: &mut GameState) {
+    let mut deployed = Vec::new();
+    for (_, vehicle) in gs.vehicles.iter_mut() {
+        let input = gs.players[vehicle.owner].input;
+        let input_prev = gs.inputs_prev.get(vehicle.owner);
+        if vehicle.destroyed() || !input.flare || input_prev.flare {
+            continue;
+        }
+        if vehicle.flares == 0 || gs.game_time < vehicle.flare_ready {
+            continue;
+        }
+        vehicle.flares -= 1;
+        vehicle.flare_ready = gs.game_time + cvars.g_flare_cooldown;
+        deployed.push((*vehicle.pos, *vehicle.angle, vehicle.owner));
+    }
+
+    for (pos, angle, owner) in deployed {
+        let handle = gs.flares.insert(Flare {
+            pos,
+            spawn_time: gs.game_time,
+            owner,
+        });
+        trace::record(cvars, &mut gs.trace, || {
+            TraceEvent::Spawn(EntitySummary::flare(handle, &gs.flares[handle]))
+        });
+        // Sparks fly back from the direction the vehicle is facing.
+        sys_particles::emit_sparks(cvars, gs, pos, angle.to_vec2f());
+    }
+}
+
+/// Remove burnt out flares, choose targets for homing missiles and turn them towards them.
+pub fn homing(cvars: &Cvars, gs: &mut GameState) {
+    let burnt_out: Vec<_> = gs
+        .flares
+        .iter()
+        .filter(|(_, flare)| gs.game_time >= flare.spawn_time + cvars.g_flare_lifetime)
+        .map(|(handle, _)| handle)
+        .collect();
+    for handle in burnt_out {
+        let flare = gs.flares.remove(handle).unwrap();
+        trace::record(cvars, &mut gs.trace, || {
+            TraceEvent::Remove(EntitySummary::flare(handle, &flare))
+        });
+    }
+
+    for (_, missile) in gs
+        .projectiles
+        .iter_mut()
+        .filter(|(_, proj)| proj.weapon == Weapon::Hm)
+    {
+        // Targets which are gone don't count, look for another one immediately.
+        let lost = match missile.lock {
+            Some(Lock::Vehicle(handle)) => gs
+                .vehicles
+                .get(handle)
+                .map_or(true, |vehicle| vehicle.destroyed()),
+            Some(Lock::Flare(handle)) => !gs.flares.contains(handle),
+            None => false,
+        };
+        if lost {
+            missile.lock = None;
+            missile.retarget_time = gs.game_time;
+        }
+
+        let on_flare = matches!(missile.lock, Some(Lock::Flare(_)));
+        if !on_flare && gs.game_time >= missile.retarget_time {
+            missile.lock = choose_target(
+                cvars,
+                &gs.players,
+                &gs.vehicles,
+                &gs.flares,
+                &mut gs.rng.weapon_spread,
+                missile,
+            );
+            missile.retarget_time = gs.game_time + cvars.g_homing_missile_retarget_interval;
+        }
+
+        let target_pos = match missile.lock {
+            Some(Lock::Vehicle(handle)) => *gs.vehicles[handle].pos,
+            Some(Lock::Flare(handle)) => gs.flares[handle].pos,
+            None => continue,
+        };
+        let heading = missile.vel.to_angle();
+        let wanted = (target_pos - missile.pos).to_angle();
+        let diff = (wanted - heading + PI).rem_euclid(2.0 * PI) - PI;
+        let max_turn = cvars.g_homing_missile_turn_rate_deg.to_radians() * gs.dt;
+        missile.vel.rotate_z(diff.clamped(-max_turn, max_turn));
+        missile.angle = missile.vel.to_angle();
+    }
+}
+
+/// The closest enemy flare or vehicle in the missile's lock cone.
+///
+/// Only rolls for the flare if there is one in the cone, so flares don't change anything
+/// (including the RNG) when nobody uses them.
+fn choose_target(
+    cvars: &Cvars,
+    players: &Arena<Player>,
+    vehicles: &Arena<Vehicle>,
+    flares: &Arena<Flare>,
+    rng: &mut impl Rng,
+    missile: &Projectile,
+) -> Option<Lock> {
+    let heading = missile.vel.to_angle();
+    let max_angle = cvars.g_homing_missile_lock_angle_deg.to_radians();
+    let range = cvars.g_homing_missile_lock_range;
+    // Distance to `pos` if it's in the cone.
+    let visible = |pos: Vec2f| {
+        let to_target = pos - missile.pos;
+        let dist = to_target.magnitude();
+        let angle = (to_target.to_angle() - heading + PI).rem_euclid(2.0 * PI) - PI;
+        if dist <= range && angle.abs() <= max_angle {
+            Some(dist)
+        } else {
+            None
+        }
+    };
+    let closest = |candidates: &mut dyn Iterator<Item = (Index, Vec2f)>| {
+        let mut best: Option<(Index, f64)> = None;
+        for (handle, pos) in candidates {
+            if let Some(dist) = visible(pos) {
+                if best.map_or(true, |(_, best_dist)| dist < best_dist) {
+                    best = Some((handle, dist));
+                }
+            }
+        }
+        best.map(|(handle, _)| handle)
+    };
+
+    let flare = closest(
+        &mut flares
+            .iter()
+            .filter(|(_, flare)| enemies(players, missile.owner, flare.owner))
+            .map(|(handle, flare)| (handle, flare.pos)),
+    );
+    if let Some(handle) = flare {
+        let preference = cvars.g_homing_missile_flare_preference;
+        if preference >= 1.0 || (preference > 0.0 && rng.gen::<f64>() < preference) {
+            return Some(Lock::Flare(handle));
+        }
+    }
+
+    closest(
+        &mut vehicles
+            .iter()
+            .filter(|(_, vehicle)| {
+                !vehicle.destroyed() && enemies(players, missile.owner, vehicle.owner)
+            })
+            .map(|(handle, vehicle)| (handle, *vehicle.pos)),
+    )
+    .map(Lock::Vehicle)
+}
+
+/// Different players who aren't on the same team.
+fn enemies(players: &Arena<Player>, a: Index, b: Index) -> bool {
+    if a == b {
+        return false;
+    }
+    let team = players[a].team;
+    team.is_none() || team != players[b].team
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::entities::VehicleType;
+
+    fn add_player(cvars: &Cvars, gs: &mut GameState, pos: Vec2f) -> (Index, Index) {
+        let player_handle = gs.players.insert(Player::new("Player".to_owned()));
+        let vehicle = Vehicle::new(cvars, pos, 0.0, VehicleType::Tank, 0, 0.0, player_handle);
+        let vehicle_handle = gs.vehicles.insert(vehicle);
+        gs.players[player_handle].vehicle = Some(vehicle_handle);
+        (player_handle, vehicle_handle)
+    }
+
+    /// A missile flying towards an enemy vehicle which then pops a flare in front of it.
+    fn decoy(cvars: &Cvars) -> (GameState, Index, Index) {
+        let mut gs = GameState::new(0);
+        gs.dt = 1.0 / 60.0;
+        let (me, _) = add_player(cvars, &mut gs, Vec2f::new(-100.0, 0.0));
+        let (enemy, enemy_vehicle) = add_player(cvars, &mut gs, Vec2f::new(450.0, 0.0));
+        let vel = Vec2f::new(300.0, 0.0);
+        let missile = gs.projectiles.insert(Projectile {
+            weapon: Weapon::Hm,
+            pos: Vec2f::zero(),
+            vel,
+            angle: vel.to_angle(),
+            turn_rate: 0.0,
+            explode_time: f64::MAX,
+            spawn_time: 0.0,
+            owner: me,
+            lock: None,
+            retarget_time: 0.0,
+            altitude: None,
+        });
+        homing(cvars, &mut gs);
+        assert_eq!(
+            gs.projectiles[missile].lock,
+            Some(Lock::Vehicle(enemy_vehicle))
+        );
+
+        gs.flares.insert(Flare {
+            pos: Vec2f::new(300.0, 20.0),
+            spawn_time: gs.game_time,
+            owner: enemy,
+        });
+        gs.game_time = gs.projectiles[missile].retarget_time;
+        homing(cvars, &mut gs);
+        (gs, missile, enemy_vehicle)
+    }
+
+    #[test]
+    fn test_flare_decoys() {
+        let cvars = Cvars {
+            g_homing_missile_flare_preference: 1.0,
+            ..Cvars::default()
+        };
+        let (mut gs, missile, enemy_vehicle) = decoy(&cvars);
+        let lock = gs.projectiles[missile].lock;
+        assert!(matches!(lock, Some(Lock::Flare(_))), "{:?}", lock);
+        // Turning towards the flare, slightly to the left.
+        assert!(gs.projectiles[missile].vel.y > 0.0);
+
+        // Reacquires the vehicle as soon as the flare burns out.
+        gs.game_time += cvars.g_flare_lifetime;
+        homing(&cvars, &mut gs);
+        assert!(gs.flares.is_empty());
+        assert_eq!(
+            gs.projectiles[missile].lock,
+            Some(Lock::Vehicle(enemy_vehicle))
+        );
+    }
+
+    #[test]
+    fn test_flare_ignored() {
+        let cvars = Cvars {
+            g_homing_missile_flare_preference: 0.0,
+            ..Cvars::default()
+        };
+        let (mut gs, missile, enemy_vehicle) = decoy(&cvars);
+        for _ in 0..10 {
+            assert_eq!(
+                gs.projectiles[missile].lock,
+                Some(Lock::Vehicle(enemy_vehicle))
+            );
+            gs.game_time += cvars.g_homing_missile_retarget_interval;
+            homing(&cvars, &mut gs);
+        }
+    }
+}
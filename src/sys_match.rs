@@ -0,0 +1,217 @@
+//! Match flow - a countdown, playing until somebody wins and a new match after a short break.
+//!
+//! - During the countdown (`g_match_countdown`) nobody can move, shoot or get hurt.
+//! - Free for all is won by the first player with `g_match_kill_limit` kills,
+//!   capture the flag by the first team with `g_match_capture_limit` captures.
+//! - When `g_match_time_limit` runs out, whoever leads wins, a tie is a draw.
+//! - Once it's over, players are frozen and invulnerable again
+//!   and after `g_match_restart_delay` the server starts a new match (see `Server::reset_match`).
+//!
+//! The training range has no matches - it starts immediately and never ends.
+
+use std::cmp::Ordering;
+
+use crate::{
+    cvars::Cvars,
+    entities::Team,
+    game_state::{push_notification, GameState, MatchState, NotificationKind, Winner},
+};
+
+/// Start the countdown of a new match.
+pub fn start(cvars: &Cvars, gs: &mut GameState) {
+    if cvars.g_match_countdown > 0.0 && gs.range.is_none() {
+        let until = gs.game_time + cvars.g_match_countdown;
+        gs.match_state = MatchState::Countdown { until };
+        gs.match_start = until;
+    } else {
+        gs.match_state = MatchState::Playing;
+        gs.match_start = gs.game_time;
+        if gs.range.is_none() {
+            push_notification(
+                cvars,
+                &mut gs.notifications,
+                gs.game_time,
+                NotificationKind::MatchStarted,
+            );
+        }
+    }
+}
+
+/// End the countdown and check win conditions.
+///
+/// Runs after everything which changes scores so a match ends on the same frame as the deciding kill or capture.
+pub fn match_flow(cvars: &Cvars, gs: &mut GameState) {
+    match gs.match_state {
+        MatchState::Countdown { until } => {
+            if gs.game_time >= until {
+                gs.match_state = MatchState::Playing;
+                push_notification(
+                    cvars,
+                    &mut gs.notifications,
+                    gs.game_time,
+                    NotificationKind::MatchStarted,
+                );
+            }
+        }
+        MatchState::Playing => {
+            gs.match_ticks += 1;
+            if gs.range.is_some() {
+                return;
+            }
+            let time_up = cvars.g_match_time_limit > 0.0
+                && gs.game_time >= gs.match_start + cvars.g_match_time_limit;
+            if time_up || limit_reached(cvars, gs) {
+                finish(cvars, gs, leader(gs));
+            }
+        }
+        MatchState::Finished { .. } => {}
+    }
+}
+
+/// Whether the victory screen has been shown long enough and the server should start a new match.
+pub fn restart_due(gs: &GameState) -> bool {
+    match gs.match_state {
+        MatchState::Finished { until_restart, .. } => gs.game_time >= until_restart,
+        _ => false,
+    }
+}
+
+/// Seconds until the countdown ends, the time limit runs out or the next match starts.
+/// `None` while playing without a time limit.
+pub fn time_left(cvars: &Cvars, gs: &GameState) -> Option<f64> {
+    let end = match gs.match_state {
+        MatchState::Countdown { until } => until,
+        MatchState::Playing if cvars.g_match_time_limit > 0.0 && gs.range.is_none() => {
+            gs.match_start + cvars.g_match_time_limit
+        }
+        MatchState::Playing => return None,
+        MatchState::Finished { until_restart, .. } => until_restart,
+    };
+    Some((end - gs.game_time).max(0.0))
+}
+
+pub fn winner_name(gs: &GameState, winner: Winner) -> String {
+    match winner {
+        Winner::Player(player_handle) => gs.players[player_handle].name.clone(),
+        Winner::Team(team) => team.name().to_owned(),
+    }
+}
+
+fn limit_reached(cvars: &Cvars, gs: &GameState) -> bool {
+    match &gs.ctf {
+        Some(ctf) => {
+            let limit = cvars.g_match_capture_limit;
+            limit > 0 && ctf.captures.iter().any(|&captures| captures >= limit)
+        }
+        None => {
+            let limit = cvars.g_match_kill_limit;
+            limit > 0 && gs.players.iter().any(|(_, p)| p.score.kills >= limit)
+        }
+    }
+}
+
+/// The team with the most captures or the player with the most kills, `None` if it's a tie.
+fn leader(gs: &GameState) -> Option<Winner> {
+    if let Some(ctf) = &gs.ctf {
+        let [red, blue] = ctf.captures;
+        return match red.cmp(&blue) {
+            Ordering::Greater => Some(Winner::Team(Team::Red)),
+            Ordering::Less => Some(Winner::Team(Team::Blue)),
+            Ordering::Equal => None,
+        };
+    }
+
+    let mut best: Option<(_, i32)> = None;
+    let mut tie = false;
+    for (handle, player) in gs.players.iter() {
+        let kills = player.score.kills;
+        match best {
+            Some((_, best_kills)) if kills < best_kills => {}
+            Some((_, best_kills)) if kills == best_kills => tie = true,
+            _ => {
+                best = Some((handle, kills));
+                tie = false;
+            }
+        }
+    }
+    match best {
+        Some((handle, _)) if !tie => Some(Winner::Player(handle)),
+        _ => None,
+    }
+}
+
+fn finish(cvars: &Cvars, gs: &mut GameState, winner: Option<Winner>) {
+    gs.match_end = gs.game_time;
+    gs.match_state = MatchState::Finished {
+        winner,
+        until_restart: gs.game_time + cvars.g_match_restart_delay,
+    };
+    let winner = winner.map(|winner| winner_name(gs, winner));
+    let kind = NotificationKind::MatchOver { winner };
+    push_notification(cvars, &mut gs.notifications, gs.game_time, kind);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::entities::Player;
+
+    #[test]
+    fn test_time_limit() {
+        let cvars = Cvars {
+            g_match_countdown: 2.0,
+            g_match_kill_limit: 0,
+            g_match_restart_delay: 10.0,
+            g_match_time_limit: 60.0,
+            ..Cvars::default()
+        };
+        let mut gs = GameState::new(0);
+        let player1 = gs.players.insert(Player::new("Player 1".to_owned()));
+        let player2 = gs.players.insert(Player::new("Player 2".to_owned()));
+
+        start(&cvars, &mut gs);
+        assert_eq!(gs.match_state, MatchState::Countdown { until: 2.0 });
+        assert_eq!(time_left(&cvars, &gs), Some(2.0));
+        gs.game_time = 2.0;
+        match_flow(&cvars, &mut gs);
+        assert_eq!(gs.match_state, MatchState::Playing);
+        assert_eq!(time_left(&cvars, &gs), Some(60.0));
+        let kind = &gs.notifications.back().unwrap().kind;
+        assert_eq!(*kind, NotificationKind::MatchStarted);
+
+        // No kill limit, only time matters.
+        gs.players[player1].score.kills = 100;
+        gs.players[player2].score.kills = 100;
+        gs.game_time = 61.9;
+        match_flow(&cvars, &mut gs);
+        assert_eq!(gs.match_state, MatchState::Playing);
+
+        // Tied.
+        gs.game_time = 62.0;
+        match_flow(&cvars, &mut gs);
+        let draw = MatchState::Finished {
+            winner: None,
+            until_restart: 72.0,
+        };
+        assert_eq!(gs.match_state, draw);
+        let kind = &gs.notifications.back().unwrap().kind;
+        assert_eq!(*kind, NotificationKind::MatchOver { winner: None });
+        assert!(!restart_due(&gs));
+        gs.game_time = 72.0;
+        assert!(restart_due(&gs));
+
+        // Leading.
+        gs.match_state = MatchState::Playing;
+        gs.players[player2].score.kills += 1;
+        match_flow(&cvars, &mut gs);
+        let finished = MatchState::Finished {
+            winner: Some(Winner::Player(player2)),
+            until_restart: 82.0,
+        };
+        assert_eq!(gs.match_state, finished);
+        let kind = &gs.notifications.back().unwrap().kind;
+        let winner = Some("Player 2".to_owned());
+        assert_eq!(*kind, NotificationKind::MatchOver { winner });
+    }
+}
@@ -0,0 +1,234 @@
+//! Smoke, sparks and dust - purely cosmetic, nothing in the gamelogic reads them.
+//!
+//! Smoke trails are emitted at fixed multiples of `g_smoke_interval` in game time
+//! (not once per frame) so they look the same at any frame rate.
+//! Sparks and dust are emitted by impacts and explosions in `systems`.
+//!
+//! There's a hard cap (`sv_particles_max`) - when it's full, new particles are dropped
+//! and the existing ones are kept so trails don't flicker.
+
+use crate::{
+    cvars::Cvars,
+    entities::Weapon,
+    game_state::{GameState, Particle, ParticleKind},
+    map::{F64Ext, Vec2f, VecExt},
+};
+
+/// Add a particle unless the cap has been reached. Returns whether it was added.
+pub fn emit(cvars: &Cvars, particles: &mut Vec<Particle>, particle: Particle) -> bool {
+    if particles.len() >= cvars.sv_particles_max {
+        return false;
+    }
+    particles.push(particle);
+    true
+}
+
+/// Sparks flying back from a wall hit by a projectile moving in direction `dir`.
+pub fn emit_sparks(cvars: &Cvars, gs: &mut GameState, pos: Vec2f, dir: Vec2f) {
+    let count = cvars.g_sparks_count;
+    // Spread evenly over 120 degrees around the reverse direction.
+    let back = (-dir).to_angle();
+    for i in 0..count {
+        let offset = if count > 1 {
+            (i as f64 / (count - 1) as f64 - 0.5) * 120f64.to_radians()
+        } else {
+            0.0
+        };
+        let particle = Particle {
+            pos,
+            vel: (back + offset).to_vec2f() * cvars.g_sparks_speed,
+            spawn_time: gs.game_time,
+            lifetime: cvars.g_sparks_lifetime,
+            size: cvars.g_sparks_size,
+            kind: ParticleKind::Spark,
+        };
+        if !emit(cvars, &mut gs.particles, particle) {
+            return;
+        }
+    }
+}
+
+/// A ring of dust around an explosion.
+pub fn emit_dust(cvars: &Cvars, gs: &mut GameState, pos: Vec2f, scale: f64) {
+    let count = cvars.g_dust_count;
+    for i in 0..count {
+        let angle = i as f64 / count as f64 * std::f64::consts::TAU;
+        let particle = Particle {
+            pos,
+            vel: angle.to_vec2f() * cvars.g_dust_speed * scale,
+            spawn_time: gs.game_time,
+            lifetime: cvars.g_dust_lifetime,
+            size: cvars.g_dust_size * scale,
+            kind: ParticleKind::Dust,
+        };
+        if !emit(cvars, &mut gs.particles, particle) {
+            return;
+        }
+    }
+}
+
+/// Retire expired particles, move the rest and emit smoke trails for this frame.
+///
+/// Has to run before projectiles and vehicles move so their positions are still
+/// from the beginning of the frame (`game_time_prev`).
+pub fn update_particles(cvars: &Cvars, gs: &mut GameState) {
+    let game_time = gs.game_time; // borrowck
+    let dt = gs.dt;
+    gs.particles
+        .retain(|particle| game_time - particle.spawn_time < particle.lifetime);
+    for particle in &mut gs.particles {
+        particle.pos += particle.vel * dt;
+    }
+
+    let times = smoke_times(cvars.g_smoke_interval, gs.game_time_prev, gs.game_time);
+    if times.is_empty() {
+        return;
+    }
+
+    let mut sources = Vec::new();
+    for (_, projectile) in gs.projectiles.iter() {
+        if matches!(projectile.weapon, Weapon::Rockets | Weapon::Hm | Weapon::Gm) {
+            sources.push((projectile.pos, projectile.vel));
+        }
+    }
+    for (_, vehicle) in gs.vehicles.iter() {
+        if !vehicle.destroyed() && vehicle.hp_fraction(cvars) < cvars.g_smoke_vehicle_hp_fraction {
+            sources.push((*vehicle.pos, vehicle.vel));
+        }
+    }
+
+    for &time in &times {
+        for &(pos, vel) in &sources {
+            let particle = Particle {
+                pos: pos + vel * (time - gs.game_time_prev),
+                vel: Vec2f::zero(),
+                spawn_time: time,
+                lifetime: cvars.g_smoke_lifetime,
+                size: cvars.g_smoke_size,
+                kind: ParticleKind::Smoke,
+            };
+            if !emit(cvars, &mut gs.particles, particle) {
+                return;
+            }
+        }
+    }
+}
+
+/// Multiples of `interval` in the half-open range (`begin`, `end`].
+fn smoke_times(interval: f64, begin: f64, end: f64) -> Vec<f64> {
+    let mut times = Vec::new();
+    if interval <= 0.0 {
+        return times;
+    }
+    // Compare the products, not the quotient, so consecutive ranges never skip or repeat a time.
+    let mut k = (begin / interval).floor().max(0.0) as u64;
+    while k as f64 * interval <= begin {
+        k += 1;
+    }
+    loop {
+        let time = k as f64 * interval;
+        if time > end {
+            return times;
+        }
+        times.push(time);
+        k += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use thunderdome::Index;
+
+    use crate::entities::{Player, Projectile};
+
+    fn rocket(owner: Index, pos: Vec2f, vel: Vec2f) -> Projectile {
+        Projectile {
+            weapon: Weapon::Rockets,
+            pos,
+            vel,
+            angle: 0.0,
+            turn_rate: 0.0,
+            explode_time: f64::MAX,
+            spawn_time: 0.0,
+            owner,
+            lock: None,
+            retarget_time: 0.0,
+            altitude: None,
+        }
+    }
+
+    /// Smoke trail of a rocket flying for 1 s at the given frame rate.
+    fn trail(cvars: &Cvars, fps: u32) -> Vec<Particle> {
+        let mut gs = GameState::new(0);
+        let owner = gs.players.insert(Player::new("Player".to_owned()));
+        let handle = gs
+            .projectiles
+            .insert(rocket(owner, Vec2f::zero(), Vec2f::new(500.0, 0.0)));
+        gs.dt = 1.0 / fps as f64;
+        for frame in 1..=fps {
+            gs.game_time_prev = gs.game_time;
+            gs.game_time = frame as f64 * gs.dt;
+            update_particles(cvars, &mut gs);
+            let projectile = &mut gs.projectiles[handle];
+            projectile.pos += projectile.vel * gs.dt;
+        }
+        gs.particles
+    }
+
+    #[test]
+    fn test_trail_frame_rate_independent() {
+        let cvars = Cvars {
+            g_smoke_interval: 0.05,
+            g_smoke_lifetime: 10.0,
+            ..Cvars::default()
+        };
+        let slow = trail(&cvars, 30);
+        let fast = trail(&cvars, 144);
+        assert_eq!(slow.len(), 20);
+        assert_eq!(slow.len(), fast.len());
+        for (a, b) in slow.iter().zip(&fast) {
+            assert!((a.pos - b.pos).magnitude() < 1e-6, "{:?} {:?}", a, b);
+            assert!((a.spawn_time - b.spawn_time).abs() < 1e-9);
+        }
+        // Every 25 px along the path.
+        assert!((slow[3].pos.x - 100.0).abs() < 1e-6);
+
+        // Old ones expire.
+        let cvars = Cvars {
+            g_smoke_lifetime: 0.5,
+            ..cvars
+        };
+        let short = trail(&cvars, 60);
+        assert_eq!(
+            short.len(),
+            10,
+            "{:?}",
+            short.iter().map(|p| p.spawn_time).collect::<Vec<_>>()
+        );
+        assert!(short.iter().all(|particle| particle.spawn_time > 0.5));
+    }
+
+    #[test]
+    fn test_cap() {
+        let cvars = Cvars::default();
+        let mut gs = GameState::new(0);
+        let owner = gs.players.insert(Player::new("Player".to_owned()));
+        for i in 0..10_000 {
+            let pos = Vec2f::new(i as f64, 0.0);
+            gs.projectiles
+                .insert(rocket(owner, pos, Vec2f::new(0.0, 300.0)));
+        }
+        gs.dt = 1.0 / 60.0;
+        for frame in 1..=30 {
+            gs.game_time_prev = gs.game_time;
+            gs.game_time = frame as f64 * gs.dt;
+            update_particles(&cvars, &mut gs);
+            emit_dust(&cvars, &mut gs, Vec2f::zero(), 1.0);
+            emit_sparks(&cvars, &mut gs, Vec2f::zero(), Vec2f::new(1.0, 0.0));
+            assert!(gs.particles.len() <= cvars.sv_particles_max);
+        }
+        assert_eq!(gs.particles.len(), cvars.sv_particles_max);
+    }
+}
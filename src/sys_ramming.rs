@@ -0,0 +1,395 @@
+//! Vehicles colliding with each other - pushing and ramming damage.
+//!
+//! Runs right after `vehicle_movement` and separates overlapping vehicles.
+//! Along the collision normal, both end up moving at the same speed (a perfectly inelastic collision)
+//! so momentum is shared by mass (`g_tank_mass` and co) - a tank shoves a hummer, a hummer barely moves a tank.
+//!
+//! Colliding faster than `g_ram_damage_threshold` damages both, each proportionally to the velocity change it got,
+//! attributed to the owner of the faster vehicle. Wrecks can be pushed around but take no more damage.
+//! Target dummies are immovable.
+//!
+//! Walls win over vehicles - if one of the pair can't be moved out of the overlap without hitting a wall,
+//! the other one takes the whole correction and the one against the wall takes `g_ram_crush_dps`
+//! for as long as it's being pushed.
+
+use thunderdome::Index;
+
+use crate::{
+    cvars::{Cvars, DamageType},
+    entities::{Obb, Vehicle},
+    fixed::{self, MilliHp},
+    game_state::GameState,
+    map::{Map, Vec2f},
+    systems,
+};
+
+/// Separate overlapping vehicles, exchange momentum and deal ramming and crushing damage.
+pub fn vehicle_collisions(cvars: &Cvars, gs: &mut GameState, map: &Map) {
+    let handles: Vec<_> = gs.vehicles.iter().map(|(handle, _)| handle).collect();
+    let mut damages = Vec::new();
+    for (i, &handle_a) in handles.iter().enumerate() {
+        for &handle_b in &handles[i + 1..] {
+            let (a, b) = gs.vehicles.get2_mut(handle_a, handle_b);
+            let (a, b) = (a.unwrap(), b.unwrap());
+            if !a.obb.aabb_overlaps(b.obb.mins, b.obb.maxs) {
+                continue;
+            }
+            let (normal, depth) = match penetration(&a.obb, &b.obb) {
+                Some(contact) => contact,
+                None => continue,
+            };
+
+            let inv_mass = |vehicle: &Vehicle| {
+                if vehicle.is_dummy {
+                    0.0
+                } else {
+                    1.0 / cvars.g_vehicle_mass(vehicle.veh_type)
+                }
+            };
+            let inv_a = inv_mass(a);
+            let inv_b = inv_mass(b);
+            let inv_sum = inv_a + inv_b;
+            if inv_sum == 0.0 {
+                continue;
+            }
+
+            let closing = (a.vel - b.vel).dot(normal);
+            if closing > 0.0 {
+                let impulse = closing / inv_sum;
+                if closing > cvars.g_ram_damage_threshold {
+                    let attacker = if a.vel.magnitude_squared() >= b.vel.magnitude_squared() {
+                        a.owner
+                    } else {
+                        b.owner
+                    };
+                    for (handle, vehicle, inv) in [(handle_a, &*a, inv_a), (handle_b, &*b, inv_b)] {
+                        if !vehicle.destroyed() {
+                            let amount = cvars.g_ram_damage_factor * impulse * inv;
+                            damages.push((attacker, handle, fixed::to_milli(amount)));
+                        }
+                    }
+                }
+                a.vel -= normal * impulse * inv_a;
+                b.vel += normal * impulse * inv_b;
+            }
+
+            let share_a = inv_a / inv_sum;
+            let share_b = inv_b / inv_sum;
+            let a_free = push(cvars, map, gs.frame_num, a, -normal * depth * share_a);
+            let b_free = push(cvars, map, gs.frame_num, b, normal * depth * share_b);
+            if !a_free && b_free && share_b > 0.0 {
+                push(cvars, map, gs.frame_num, b, normal * depth * share_a);
+            }
+            if !b_free && a_free && share_a > 0.0 {
+                push(cvars, map, gs.frame_num, a, -normal * depth * share_b);
+            }
+            let (owner_a, owner_b) = (a.owner, b.owner);
+            for (free, handle, vehicle, pusher) in [
+                (a_free, handle_a, a, owner_b),
+                (b_free, handle_b, b, owner_a),
+            ] {
+                if !free && !vehicle.destroyed() {
+                    let amount =
+                        fixed::rate(cvars.g_ram_crush_dps, gs.dt, &mut vehicle.damage_remainder);
+                    damages.push((pusher, handle, amount));
+                }
+            }
+        }
+    }
+
+    for (attacker, vehicle_handle, amount) in damages {
+        // An earlier collision this frame might have destroyed it.
+        if amount <= 0 || gs.vehicles[vehicle_handle].destroyed() {
+            continue;
+        }
+        damage(cvars, gs, attacker, vehicle_handle, amount);
+    }
+}
+
+fn damage(
+    cvars: &Cvars,
+    gs: &mut GameState,
+    attacker: Index,
+    vehicle_handle: Index,
+    amount: MilliHp,
+) {
+    systems::damage(
+        cvars,
+        gs,
+        attacker,
+        vehicle_handle,
+        None,
+        amount,
+        DamageType::Kinetic,
+        None,
+    );
+}
+
+/// Move the vehicle by `offset` unless it'd end up in a wall, return whether it moved.
+fn push(cvars: &Cvars, map: &Map, frame_num: u64, vehicle: &mut Vehicle, offset: Vec2f) -> bool {
+    if offset == Vec2f::zero() {
+        return true;
+    }
+    let water_passable = systems::movement_stats(cvars, vehicle).water_passable;
+    let is_blocked = systems::blocker(map, vehicle, water_passable);
+    let pos = *vehicle.pos + offset;
+    let obb = Obb::new(vehicle.hitbox, pos, *vehicle.angle);
+    if is_blocked(&obb).is_some() {
+        return false;
+    }
+    vehicle.pos.set(pos, frame_num);
+    vehicle.obb = obb;
+    // Shooting uses it to place shots fired earlier in the frame.
+    vehicle.displacement += offset;
+    true
+}
+
+/// The axis along which the boxes overlap the least (pointing from `a` to `b`) and by how much,
+/// `None` if they don't overlap.
+fn penetration(a: &Obb, b: &Obb) -> Option<(Vec2f, f64)> {
+    let project = |obb: &Obb, axis: Vec2f| {
+        let mut min = f64::INFINITY;
+        let mut max = f64::NEG_INFINITY;
+        for &corner in &obb.corners {
+            let dist = corner.dot(axis);
+            min = min.min(dist);
+            max = max.max(dist);
+        }
+        (min, max)
+    };
+
+    // Boxes only have 2 different edge directions each.
+    let mut best: Option<(Vec2f, f64)> = None;
+    for obb in [a, b] {
+        for i in 0..2 {
+            let edge = obb.corners[i + 1] - obb.corners[i];
+            let axis = Vec2f::new(-edge.y, edge.x).normalized();
+            let (a_min, a_max) = project(a, axis);
+            let (b_min, b_max) = project(b, axis);
+            let depth = a_max.min(b_max) - a_min.max(b_min);
+            if depth <= 0.0 {
+                return None;
+            }
+            if best.map_or(true, |(_, best_depth)| depth < best_depth) {
+                best = Some((axis, depth));
+            }
+        }
+    }
+
+    let (axis, depth) = best?;
+    let center = |obb: &Obb| obb.corners.iter().fold(Vec2f::zero(), |acc, &c| acc + c) / 4.0;
+    if (center(b) - center(a)).dot(axis) < 0.0 {
+        Some((-axis, depth))
+    } else {
+        Some((axis, depth))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        entities::{Player, VehicleType},
+        game_state::{drain_notifications, NotificationKind},
+        map,
+    };
+
+    fn add_vehicle(
+        cvars: &Cvars,
+        gs: &mut GameState,
+        veh_type: VehicleType,
+        pos: Vec2f,
+        vel: Vec2f,
+    ) -> Index {
+        let player_handle = gs.players.insert(Player::new("Player".to_owned()));
+        let mut vehicle = Vehicle::new(cvars, pos, 0.0, veh_type, 0, 0.0, player_handle);
+        vehicle.vel = vel;
+        let vehicle_handle = gs.vehicles.insert(vehicle);
+        gs.players[player_handle].vehicle = Some(vehicle_handle);
+        vehicle_handle
+    }
+
+    /// 10 tiles wide, 3 high, a wall on the right.
+    fn map() -> Map {
+        let row = "0 0 0 0 0 0 0 0 0 16";
+        let text = [row, row, row].join("\n");
+        map::tests::load_test_map(&text)
+    }
+
+    /// A vehicle driving at a standing one which is just touching it.
+    fn ram(
+        cvars: &Cvars,
+        rammer: VehicleType,
+        rammed: VehicleType,
+        speed: f64,
+    ) -> (GameState, Index, Index) {
+        let map = map();
+        let mut gs = GameState::new(0);
+        gs.dt = 1.0 / 60.0;
+        let a = add_vehicle(
+            cvars,
+            &mut gs,
+            rammer,
+            Vec2f::new(100.0, 96.0),
+            Vec2f::new(speed, 0.0),
+        );
+        let b = add_vehicle(
+            cvars,
+            &mut gs,
+            rammed,
+            Vec2f::new(135.0, 96.0),
+            Vec2f::zero(),
+        );
+        vehicle_collisions(cvars, &mut gs, &map);
+        (gs, a, b)
+    }
+
+    #[test]
+    fn test_mass_ratio() {
+        let cvars = Cvars::default();
+        let tank = cvars.g_tank_mass;
+        let hummer = cvars.g_hummer_mass;
+
+        let (gs, a, b) = ram(&cvars, VehicleType::Tank, VehicleType::Hummer, 50.0);
+        let shoved = gs.vehicles[b].vel.x;
+        assert!(
+            (shoved - 50.0 * tank / (tank + hummer)).abs() < 1e-9,
+            "{}",
+            shoved
+        );
+        assert!((gs.vehicles[a].vel.x - shoved).abs() < 1e-9);
+        // Separated, mostly by moving the hummer.
+        let overlap = penetration(&gs.vehicles[a].obb, &gs.vehicles[b].obb);
+        assert!(
+            overlap.map_or(true, |(_, depth)| depth < 1e-9),
+            "{:?}",
+            overlap
+        );
+        assert!(gs.vehicles[b].displacement.x > -gs.vehicles[a].displacement.x);
+
+        let (gs, _, b) = ram(&cvars, VehicleType::Hummer, VehicleType::Tank, 50.0);
+        let shoved = gs.vehicles[b].vel.x;
+        assert!(
+            (shoved - 50.0 * hummer / (tank + hummer)).abs() < 1e-9,
+            "{}",
+            shoved
+        );
+
+        // Below the threshold - no damage.
+        let hp_max = |gs: &GameState, handle| gs.vehicles[handle].hp_max(&cvars);
+        assert_eq!(gs.vehicles[b].hp, hp_max(&gs, b));
+    }
+
+    #[test]
+    fn test_ram_damage() {
+        let cvars = Cvars {
+            g_ram_damage_factor: 0.1,
+            ..Cvars::default()
+        };
+        let (mut gs, tank, hummer) = ram(&cvars, VehicleType::Tank, VehicleType::Hummer, 300.0);
+        let lost = |gs: &GameState, handle: Index| {
+            gs.vehicles[handle].hp_max(&cvars) - gs.vehicles[handle].hp
+        };
+        let tank_lost = lost(&gs, tank);
+        let hummer_lost = lost(&gs, hummer);
+        assert!(tank_lost > 0);
+        let ratio = hummer_lost as f64 / tank_lost as f64;
+        let expected = cvars.g_tank_mass / cvars.g_hummer_mass;
+        assert!((ratio - expected).abs() < 0.01, "{} {}", ratio, expected);
+
+        // The tank was faster so it gets the credit.
+        let tank_owner = gs.vehicles[tank].owner;
+        let attackers: Vec<_> = drain_notifications(&mut gs)
+            .into_iter()
+            .filter_map(|n| match n.kind {
+                NotificationKind::DamageDealt { attacker, .. } => Some(attacker),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(attackers, vec![tank_owner]);
+
+        // Wrecks are pushed but not damaged.
+        let (gs, _, wreck) = {
+            let map = map();
+            let mut gs = GameState::new(0);
+            gs.dt = 1.0 / 60.0;
+            let a = add_vehicle(
+                &cvars,
+                &mut gs,
+                VehicleType::Tank,
+                Vec2f::new(100.0, 96.0),
+                Vec2f::new(300.0, 0.0),
+            );
+            let b = add_vehicle(
+                &cvars,
+                &mut gs,
+                VehicleType::Hummer,
+                Vec2f::new(135.0, 96.0),
+                Vec2f::zero(),
+            );
+            gs.vehicles[b].hp = 0;
+            vehicle_collisions(&cvars, &mut gs, &map);
+            (gs, a, b)
+        };
+        assert_eq!(gs.vehicles[wreck].hp, 0);
+        assert!(gs.vehicles[wreck].vel.x > 0.0);
+    }
+
+    #[test]
+    fn test_crushed_against_wall() {
+        let cvars = Cvars::default();
+        let map = map();
+        let wall = 9.0 * 64.0;
+        let mut gs = GameState::new(0);
+        gs.dt = 1.0 / 60.0;
+        let tank = add_vehicle(
+            &cvars,
+            &mut gs,
+            VehicleType::Tank,
+            Vec2f::new(470.0, 96.0),
+            Vec2f::zero(),
+        );
+        let hummer = add_vehicle(
+            &cvars,
+            &mut gs,
+            VehicleType::Hummer,
+            Vec2f::new(520.0, 96.0),
+            Vec2f::zero(),
+        );
+        let tank_owner = gs.vehicles[tank].owner;
+        gs.players[tank_owner].input.up = true;
+
+        let mut hps = Vec::new();
+        for _ in 0..120 {
+            gs.frame_num += 1;
+            systems::vehicle_movement(&cvars, &mut gs, &map);
+            vehicle_collisions(&cvars, &mut gs, &map);
+            for (_, vehicle) in gs.vehicles.iter() {
+                assert!(vehicle.obb.maxs.x < wall, "{:?}", vehicle.obb);
+            }
+            hps.push(gs.vehicles[hummer].hp);
+        }
+        // Rammed, then pinned - taking damage every frame until it's destroyed.
+        let rammed = hps.iter().position(|&hp| hp < hps[0]).unwrap();
+        let destroyed = hps.iter().position(|&hp| hp == 0).unwrap();
+        for pair in hps[rammed..=destroyed].windows(2) {
+            assert!(pair[1] < pair[0], "{:?}", hps);
+        }
+        assert_eq!(gs.players[tank_owner].score.kills, 1);
+    }
+
+    #[test]
+    fn test_penetration() {
+        let hitbox = Cvars::default().g_vehicle_hitbox(VehicleType::Tank);
+        let a = Obb::new(hitbox, Vec2f::new(100.0, 100.0), 0.0);
+        let b = Obb::new(hitbox, Vec2f::new(130.0, 110.0), 0.0);
+        let (normal, depth) = penetration(&a, &b).unwrap();
+        let (normal2, depth2) = penetration(&b, &a).unwrap();
+        assert_eq!(depth, depth2);
+        assert_eq!(normal, -normal2);
+
+        let far = Obb::new(hitbox, Vec2f::new(200.0, 100.0), 0.0);
+        assert_eq!(penetration(&a, &far), None);
+    }
+}
@@ -0,0 +1,630 @@
+//! Training range - target dummies and statistics for practicing with weapons.
+//!
+//! Dummies are vehicles with `is_dummy` set. Each is owned by its own player
+//! with no AI and no input so they never shoot. They either stand still or patrol along a closed path,
+//! optionally repair after not taking damage for `g_range_dummy_repair_delay`
+//! and respawn in place `g_range_dummy_respawn_delay` after being destroyed.
+//!
+//! While the range is active, kills and deaths don't count towards score
+//! and players inside the console area get their ammo refilled for free.
+//!
+//! The statistics are computed from the damage and MG impact positions recorded here:
+//! - DPS against the most recently damaged dummy over the last `g_range_dps_window` seconds
+//! - time to kill from the first hit to destruction
+//! - grouping - mean distance of recent MG impacts from their centroid
+//! - total damage per weapon
+//!
+//! There's no general scenario system yet, the range's content is built in code by `training_range`.
+//! LATER There are no matches yet either - disable them in the range once there are.
+
+use std::{collections::VecDeque, f64::consts::PI};
+
+use thunderdome::Index;
+
+use crate::{
+    cvars::Cvars,
+    entities::{Ammo, Player, Vehicle, VehicleType, Weapon, WEAPS_CNT},
+    game_state::GameState,
+    map::{Map, Vec2f, VecExt, TILE_SIZE},
+    trace::{self, EntitySummary, TraceEvent},
+};
+
+/// One target in a range scenario.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DummySpec {
+    pub pos: Vec2f,
+    pub angle: f64,
+    pub veh_type: VehicleType,
+    pub repair: bool,
+    /// Waypoints of a closed loop which starts and ends at `pos`, empty for stationary dummies.
+    /// Patrolling dummies ignore walls so the path should be clear.
+    pub patrol: Vec<Vec2f>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeScenario {
+    pub dummies: Vec<DummySpec>,
+    /// Players in this rectangle get free ammo.
+    pub console_mins: Vec2f,
+    pub console_maxs: Vec2f,
+}
+
+#[derive(Debug, Clone)]
+pub struct Range {
+    pub scenario: RangeScenario,
+    /// Same order as `scenario.dummies`.
+    pub dummies: Vec<DummyState>,
+    /// Damage dealt to dummies, oldest first. Only the last `g_range_dps_window` seconds are kept.
+    pub hits: VecDeque<RangeHit>,
+    /// Oldest first, at most `g_range_grouping_samples`.
+    pub mg_impacts: VecDeque<Vec2f>,
+    /// Total damage dealt to dummies, indexed by weapon.
+    pub damage_by_weapon: Vec<f64>,
+    /// Owner of the most recently damaged dummy.
+    pub last_target: Option<Index>,
+    /// Time to kill of the most recently destroyed dummy.
+    pub last_ttk: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DummyState {
+    /// Owns the dummy's vehicle, stays the same across respawns.
+    pub player: Index,
+    pub last_damage_time: f64,
+    /// First hit since the dummy was last at full health.
+    pub first_hit_time: Option<f64>,
+    /// Distance traveled along the patrol path since spawning.
+    pub patrol_distance: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RangeHit {
+    pub time: f64,
+    /// Owner of the dummy.
+    pub target: Index,
+    pub damage: f64,
+}
+
+/// What the HUD shows in the training range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangePayload {
+    /// Owner of the most recently damaged dummy, the DPS is against it.
+    pub target: Option<Index>,
+    pub dps: f64,
+    pub ttk: Option<f64>,
+    /// `None` until there are at least 2 MG impacts.
+    pub grouping_radius: Option<f64>,
+    /// Only weapons which dealt any damage.
+    pub damage_by_weapon: Vec<(Weapon, f64)>,
+}
+
+impl Range {
+    pub fn new(scenario: RangeScenario) -> Self {
+        Self {
+            scenario,
+            dummies: Vec::new(),
+            hits: VecDeque::new(),
+            mg_impacts: VecDeque::new(),
+            damage_by_weapon: vec![0.0; WEAPS_CNT as usize],
+            last_target: None,
+            last_ttk: None,
+        }
+    }
+
+    fn dummy_mut(&mut self, player_handle: Index) -> Option<&mut DummyState> {
+        self.dummies
+            .iter_mut()
+            .find(|dummy| dummy.player == player_handle)
+    }
+
+    /// Called by `systems::damage` with the damage actually dealt.
+    pub fn record_damage(&mut self, time: f64, target: Index, weapon: Option<Weapon>, damage: f64) {
+        if let Some(dummy) = self.dummy_mut(target) {
+            dummy.last_damage_time = time;
+            dummy.first_hit_time.get_or_insert(time);
+        }
+        self.hits.push_back(RangeHit {
+            time,
+            target,
+            damage,
+        });
+        self.last_target = Some(target);
+        if let Some(weapon) = weapon {
+            self.damage_by_weapon[weapon as usize] += damage;
+        }
+    }
+
+    pub fn record_kill(&mut self, time: f64, target: Index) {
+        if let Some(dummy) = self.dummy_mut(target) {
+            if let Some(first_hit_time) = dummy.first_hit_time.take() {
+                self.last_ttk = Some(time - first_hit_time);
+            }
+        }
+    }
+
+    pub fn record_mg_impact(&mut self, cvars: &Cvars, pos: Vec2f) {
+        self.mg_impacts.push_back(pos);
+        while self.mg_impacts.len() > cvars.g_range_grouping_samples {
+            self.mg_impacts.pop_front();
+        }
+    }
+
+    /// Damage per second against the last target over the last `g_range_dps_window` seconds.
+    pub fn dps(&self, cvars: &Cvars, time: f64) -> f64 {
+        let target = match self.last_target {
+            Some(target) => target,
+            None => return 0.0,
+        };
+        let window_start = time - cvars.g_range_dps_window;
+        let total: f64 = self
+            .hits
+            .iter()
+            .filter(|hit| hit.target == target && hit.time > window_start)
+            .map(|hit| hit.damage)
+            .sum();
+        total / cvars.g_range_dps_window
+    }
+
+    /// Mean distance of the recent MG impacts from their centroid.
+    pub fn grouping_radius(&self) -> Option<f64> {
+        if self.mg_impacts.len() < 2 {
+            return None;
+        }
+        let count = self.mg_impacts.len() as f64;
+        let centroid = self
+            .mg_impacts
+            .iter()
+            .fold(Vec2f::zero(), |sum, &pos| sum + pos)
+            / count;
+        let total: f64 = self
+            .mg_impacts
+            .iter()
+            .map(|pos| pos.distance(centroid))
+            .sum();
+        Some(total / count)
+    }
+}
+
+/// The built-in training range: one stationary dummy of each type and two patrolling ones
+/// near the center of the map, the console is around the first spawn.
+pub fn training_range(map: &Map) -> RangeScenario {
+    let center = map.maxs() / 2.0;
+    let at = |x, y| center + Vec2f::new(x, y);
+    let stationary = |pos, veh_type, repair| DummySpec {
+        pos,
+        angle: PI,
+        veh_type,
+        repair,
+        patrol: Vec::new(),
+    };
+    let mut dummies = vec![
+        stationary(at(150.0, -100.0), VehicleType::Tank, true),
+        stationary(at(150.0, 0.0), VehicleType::Hummer, true),
+        stationary(at(150.0, 100.0), VehicleType::Hovercraft, false),
+        DummySpec {
+            pos: at(300.0, -150.0),
+            angle: PI / 2.0,
+            veh_type: VehicleType::Hummer,
+            repair: true,
+            patrol: vec![at(300.0, 150.0)],
+        },
+        DummySpec {
+            pos: at(-250.0, -100.0),
+            angle: 0.0,
+            veh_type: VehicleType::Hovercraft,
+            repair: false,
+            patrol: vec![at(-50.0, -100.0), at(-50.0, 100.0), at(-250.0, 100.0)],
+        },
+    ];
+    // Maps aren't made for the range, skip dummies that would end up in walls.
+    dummies.retain(|dummy| {
+        !map.is_wall(dummy.pos) && dummy.patrol.iter().all(|&pos| !map.is_wall(pos))
+    });
+
+    let console_center = map
+        .spawns()
+        .first()
+        .map_or(center, |&index| map.tile_center(index));
+    let half_size = Vec2f::broadcast(TILE_SIZE * 1.5);
+    RangeScenario {
+        dummies,
+        console_mins: console_center - half_size,
+        console_maxs: console_center + half_size,
+    }
+}
+
+/// Add the scenario's dummies and make the range active.
+pub fn start(cvars: &Cvars, gs: &mut GameState, scenario: RangeScenario) {
+    let mut range = Range::new(scenario);
+    for (i, spec) in range.scenario.dummies.iter().enumerate() {
+        let player = Player::new(format!("Dummy {}", i + 1));
+        let player_handle = gs.players.insert(player);
+        spawn_dummy(cvars, gs, spec, player_handle);
+        range.dummies.push(DummyState {
+            player: player_handle,
+            last_damage_time: f64::NEG_INFINITY,
+            first_hit_time: None,
+            patrol_distance: 0.0,
+        });
+    }
+    gs.range = Some(range);
+}
+
+fn spawn_dummy(cvars: &Cvars, gs: &mut GameState, spec: &DummySpec, player_handle: Index) {
+    let mut vehicle = Vehicle::new(
+        cvars,
+        spec.pos,
+        spec.angle,
+        spec.veh_type,
+        gs.frame_num,
+        gs.game_time,
+        player_handle,
+    );
+    vehicle.is_dummy = true;
+    vehicle.hp = vehicle.hp_max(cvars);
+    let vehicle_handle = gs.vehicles.insert(vehicle);
+    gs.players[player_handle].vehicle = Some(vehicle_handle);
+    trace::record(cvars, &mut gs.trace, || {
+        TraceEvent::Spawn(EntitySummary::vehicle(
+            vehicle_handle,
+            &gs.vehicles[vehicle_handle],
+        ))
+    });
+}
+
+/// Respawn, repair and move dummies, refill ammo in the console area.
+pub fn range(cvars: &Cvars, gs: &mut GameState) {
+    // borrowck - dummies need to be spawned into `gs` while reading the scenario.
+    let mut range = match gs.range.take() {
+        Some(range) => range,
+        None => return,
+    };
+
+    for (spec, dummy) in range.scenario.dummies.iter().zip(&mut range.dummies) {
+        let vehicle_handle = gs.players[dummy.player].vehicle.unwrap();
+        let vehicle = &mut gs.vehicles[vehicle_handle];
+
+        if vehicle.destroyed() {
+            let death_time = gs.players[dummy.player].death_time;
+            if gs.game_time >= death_time + cvars.g_range_dummy_respawn_delay {
+                let vehicle = gs.vehicles.remove(vehicle_handle).unwrap();
+                trace::record(cvars, &mut gs.trace, || {
+                    TraceEvent::Remove(EntitySummary::vehicle(vehicle_handle, &vehicle))
+                });
+                spawn_dummy(cvars, gs, spec, dummy.player);
+                dummy.first_hit_time = None;
+                dummy.patrol_distance = 0.0;
+            }
+            continue;
+        }
+
+        if spec.repair
+            && vehicle.hp < vehicle.hp_max(cvars)
+            && gs.game_time - dummy.last_damage_time >= cvars.g_range_dummy_repair_delay
+        {
+            vehicle.hp = vehicle.hp_max(cvars);
+            dummy.first_hit_time = None;
+        }
+
+        if !spec.patrol.is_empty() {
+            dummy.patrol_distance += cvars.g_range_dummy_speed * gs.dt;
+            let (pos, dir) = patrol_point(spec, dummy.patrol_distance);
+            vehicle.pos.set(pos, gs.frame_num);
+            vehicle.vel = dir * cvars.g_range_dummy_speed;
+            if dir != Vec2f::zero() {
+                vehicle.angle.set(dir.to_angle(), gs.frame_num);
+            }
+            vehicle.update_obb();
+        }
+    }
+
+    for (_, vehicle) in gs.vehicles.iter_mut() {
+        let pos = *vehicle.pos;
+        let mins = range.scenario.console_mins;
+        let maxs = range.scenario.console_maxs;
+        let inside = pos.x >= mins.x && pos.y >= mins.y && pos.x <= maxs.x && pos.y <= maxs.y;
+        if vehicle.is_dummy || vehicle.destroyed() || !inside {
+            continue;
+        }
+        for weapon in Weapon::all() {
+            vehicle.refill_reserve(cvars, weapon);
+            let full = cvars.g_weapon_reload_ammo(weapon);
+            let ammo = &mut vehicle.ammos[weapon as usize];
+            *ammo = match *ammo {
+                Ammo::Loaded(ready_time, _) => Ammo::Loaded(ready_time, full),
+                Ammo::Reloading(_, _) => Ammo::Loaded(gs.game_time, full),
+                Ammo::Heat(ready_time, _, _) => Ammo::Heat(ready_time, 0.0, 0.0),
+            };
+        }
+    }
+
+    let window_start = gs.game_time - cvars.g_range_dps_window;
+    while let Some(hit) = range.hits.front() {
+        if hit.time > window_start {
+            break;
+        }
+        range.hits.pop_front();
+    }
+
+    gs.range = Some(range);
+}
+
+/// Position and direction at `distance` along the dummy's patrol loop.
+fn patrol_point(spec: &DummySpec, distance: f64) -> (Vec2f, Vec2f) {
+    let mut points = vec![spec.pos];
+    points.extend_from_slice(&spec.patrol);
+    let segment = |i: usize| (points[i], points[(i + 1) % points.len()]);
+    let total: f64 = (0..points.len())
+        .map(|i| {
+            let (a, b) = segment(i);
+            a.distance(b)
+        })
+        .sum();
+    if total <= 0.0 {
+        return (spec.pos, Vec2f::zero());
+    }
+
+    let mut remaining = distance % total;
+    for i in 0..points.len() {
+        let (a, b) = segment(i);
+        let len = a.distance(b);
+        if len > 0.0 && remaining < len {
+            let dir = (b - a) / len;
+            return (a + dir * remaining, dir);
+        }
+        remaining -= len;
+    }
+    // Only reachable through floating point errors at the very end of the loop.
+    (spec.pos, Vec2f::zero())
+}
+
+pub fn range_payload(cvars: &Cvars, gs: &GameState) -> Option<RangePayload> {
+    let range = gs.range.as_ref()?;
+    let damage_by_weapon = Weapon::all()
+        .map(|weapon| (weapon, range.damage_by_weapon[weapon as usize]))
+        .filter(|&(_, damage)| damage > 0.0)
+        .collect();
+    Some(RangePayload {
+        target: range.last_target,
+        dps: range.dps(cvars, gs.game_time),
+        ttk: range.last_ttk,
+        grouping_radius: range.grouping_radius(),
+        damage_by_weapon,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::{cvars::DamageType, fixed, map, server::Server, systems};
+
+    /// An empty 20x5 tile map.
+    fn open_map() -> Map {
+        let row = vec!["0"; 20].join(" ");
+        let text = vec![row; 5].join("\n");
+        map::tests::load_test_map(&text)
+    }
+
+    fn stationary(pos: Vec2f, repair: bool) -> DummySpec {
+        DummySpec {
+            pos,
+            angle: PI,
+            veh_type: VehicleType::Tank,
+            repair,
+            patrol: Vec::new(),
+        }
+    }
+
+    /// A shooter facing right and a range with the given dummies.
+    fn setup(cvars: &Cvars, dummies: Vec<DummySpec>) -> (GameState, Index) {
+        let mut gs = GameState::new(42);
+        let shooter = gs.players.insert(Player::new("Player".to_owned()));
+        let pos = Vec2f::new(100.0, 160.0);
+        let vehicle = Vehicle::new(cvars, pos, 0.0, VehicleType::Tank, 0, 0.0, shooter);
+        gs.players[shooter].vehicle = Some(gs.vehicles.insert(vehicle));
+        let scenario = RangeScenario {
+            dummies,
+            console_mins: Vec2f::zero(),
+            console_maxs: Vec2f::zero(),
+        };
+        start(cvars, &mut gs, scenario);
+        (gs, shooter)
+    }
+
+    fn dummy_vehicle(gs: &GameState, i: usize) -> &Vehicle {
+        let player = gs.range.as_ref().unwrap().dummies[i].player;
+        &gs.vehicles[gs.players[player].vehicle.unwrap()]
+    }
+
+    fn hit_dummy(cvars: &Cvars, gs: &mut GameState, attacker: Index, i: usize, amount: f64) {
+        let player = gs.range.as_ref().unwrap().dummies[i].player;
+        let vehicle_handle = gs.players[player].vehicle.unwrap();
+        systems::damage(
+            cvars,
+            gs,
+            attacker,
+            vehicle_handle,
+            Some(Weapon::Mg),
+            fixed::to_milli(amount),
+            DamageType::Environmental,
+            None,
+        );
+    }
+
+    fn step_to(cvars: &Cvars, gs: &mut GameState, time: f64) {
+        gs.game_time_prev = gs.game_time;
+        gs.game_time = time;
+        gs.dt = gs.game_time - gs.game_time_prev;
+        range(cvars, gs);
+    }
+
+    #[test]
+    fn test_dps_and_ttk() {
+        let cvars = Cvars {
+            g_range_dummy_hp: 50.0,
+            ..Cvars::default()
+        };
+        let (mut gs, shooter) = setup(&cvars, vec![stationary(Vec2f::new(600.0, 160.0), false)]);
+
+        // 10 hits of 5 every 0.1 s, the last one kills.
+        for i in 1..=10 {
+            step_to(&cvars, &mut gs, f64::from(i) * 0.1);
+            hit_dummy(&cvars, &mut gs, shooter, 0, 5.0);
+        }
+        assert!(dummy_vehicle(&gs, 0).destroyed());
+        let payload = range_payload(&cvars, &gs).unwrap();
+        assert!((payload.dps - 50.0 / 3.0).abs() < 1e-9);
+        assert!((payload.ttk.unwrap() - 0.9).abs() < 1e-9);
+        assert_eq!(payload.damage_by_weapon, [(Weapon::Mg, 50.0)]);
+
+        // The first half of the burst is outside the window.
+        step_to(&cvars, &mut gs, 3.55);
+        let payload = range_payload(&cvars, &gs).unwrap();
+        assert!((payload.dps - 25.0 / 3.0).abs() < 1e-9);
+        assert_eq!(gs.range.as_ref().unwrap().hits.len(), 5);
+
+        step_to(&cvars, &mut gs, 10.0);
+        assert_eq!(range_payload(&cvars, &gs).unwrap().dps, 0.0);
+    }
+
+    #[test]
+    fn test_grouping() {
+        let mut range = Range::new(RangeScenario {
+            dummies: Vec::new(),
+            console_mins: Vec2f::zero(),
+            console_maxs: Vec2f::zero(),
+        });
+        let cvars = Cvars {
+            g_range_grouping_samples: 4,
+            ..Cvars::default()
+        };
+        range.record_mg_impact(&cvars, Vec2f::new(100.0, 100.0));
+        assert_eq!(range.grouping_radius(), None);
+        // The first one gets evicted.
+        for &(x, y) in &[(0.0, 0.0), (2.0, 0.0), (0.0, 2.0), (2.0, 2.0)] {
+            range.record_mg_impact(&cvars, Vec2f::new(x, y));
+        }
+        assert!((range.grouping_radius().unwrap() - 2f64.sqrt()).abs() < 1e-9);
+    }
+
+    /// Grouping radius of 2 s of MG fire at a dummy 500 px away.
+    fn mg_grouping(cvars: &Cvars) -> f64 {
+        let mut map = open_map();
+        let (mut gs, shooter) = setup(cvars, vec![stationary(Vec2f::new(600.0, 160.0), true)]);
+        gs.players[shooter].input.fire = true;
+        for frame in 1..=120 {
+            step_to(cvars, &mut gs, f64::from(frame) / 60.0);
+            systems::shooting(cvars, &mut gs);
+            systems::projectiles(cvars, &mut gs, &mut map);
+        }
+        let range = gs.range.as_ref().unwrap();
+        assert_eq!(range.mg_impacts.len(), cvars.g_range_grouping_samples);
+        range.grouping_radius().unwrap()
+    }
+
+    #[test]
+    fn test_grouping_seeded() {
+        let cvars = Cvars::default();
+        let radius = mg_grouping(&cvars);
+        // Same seed, same spread.
+        assert_eq!(radius, mg_grouping(&cvars));
+        // 500 px away, the spread's standard deviation is about 7.5 px
+        // and the nearest point on the path of each bullet is within the hit circle.
+        assert!(radius > 1.0 && radius < 10.0, "{}", radius);
+
+        let cvars = Cvars {
+            g_machine_gun_angle_spread: 0.0,
+            ..Cvars::default()
+        };
+        assert!(mg_grouping(&cvars) < 1e-6);
+    }
+
+    #[test]
+    fn test_repair_and_respawn() {
+        let cvars = Cvars {
+            g_range_dummy_hp: 50.0,
+            g_range_dummy_repair_delay: 3.0,
+            g_range_dummy_respawn_delay: 2.0,
+            ..Cvars::default()
+        };
+        let pos = Vec2f::new(600.0, 160.0);
+        let (mut gs, shooter) = setup(&cvars, vec![stationary(pos, true), stationary(pos, false)]);
+
+        step_to(&cvars, &mut gs, 1.0);
+        hit_dummy(&cvars, &mut gs, shooter, 0, 10.0);
+        hit_dummy(&cvars, &mut gs, shooter, 1, 10.0);
+        step_to(&cvars, &mut gs, 3.9);
+        assert_eq!(dummy_vehicle(&gs, 0).hp, 40_000);
+        step_to(&cvars, &mut gs, 4.0);
+        assert_eq!(dummy_vehicle(&gs, 0).hp, 50_000);
+        // Not all dummies repair.
+        assert_eq!(dummy_vehicle(&gs, 1).hp, 40_000);
+
+        hit_dummy(&cvars, &mut gs, shooter, 0, 100.0);
+        let old_vehicle = gs.players[gs.range.as_ref().unwrap().dummies[0].player].vehicle;
+        step_to(&cvars, &mut gs, 5.9);
+        assert!(dummy_vehicle(&gs, 0).destroyed());
+        step_to(&cvars, &mut gs, 6.0);
+        let vehicle = dummy_vehicle(&gs, 0);
+        assert!(!vehicle.destroyed());
+        assert!(vehicle.is_dummy);
+        assert_eq!(*vehicle.pos, pos);
+        let new_vehicle = gs.players[gs.range.as_ref().unwrap().dummies[0].player].vehicle;
+        assert_ne!(new_vehicle, old_vehicle);
+        assert_eq!(gs.vehicles.len(), 3);
+    }
+
+    #[test]
+    fn test_patrol() {
+        let cvars = Cvars {
+            g_range_dummy_speed: 100.0,
+            ..Cvars::default()
+        };
+        let spec = DummySpec {
+            patrol: vec![Vec2f::new(400.0, 100.0), Vec2f::new(400.0, 200.0)],
+            ..stationary(Vec2f::new(200.0, 100.0), false)
+        };
+        let (mut gs, _) = setup(&cvars, vec![spec]);
+        step_to(&cvars, &mut gs, 2.5);
+        assert_eq!(*dummy_vehicle(&gs, 0).pos, Vec2f::new(400.0, 150.0));
+        assert_eq!(dummy_vehicle(&gs, 0).vel, Vec2f::new(0.0, 100.0));
+        // The loop is 200 + 100 + 224 px long, after one more lap it's back in the same place.
+        step_to(
+            &cvars,
+            &mut gs,
+            2.5 + (200.0 + 100.0 + 5f64.sqrt() * 100.0) / 100.0,
+        );
+        let pos = *dummy_vehicle(&gs, 0).pos;
+        assert!((pos - Vec2f::new(400.0, 150.0)).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn test_no_score_in_range() {
+        let cvars = Cvars {
+            g_range: true,
+            ..Cvars::default()
+        };
+        let map = map::tests::load_test_map(&fs::read_to_string("maps/Atrium.map").unwrap());
+        let mut server = Server::new(&cvars, map);
+        let player = server.connect(&cvars, "Player 1");
+        let gs = &mut server.gs;
+
+        assert!(gs.ais.is_empty());
+        let dummies = gs.range.as_ref().unwrap().dummies.len();
+        assert!(dummies > 0);
+        assert_eq!(gs.vehicles.len(), dummies + 1);
+
+        for i in 0..dummies {
+            hit_dummy(&cvars, gs, player, i, f64::MAX);
+            assert!(dummy_vehicle(gs, i).destroyed());
+        }
+        for (_, player) in gs.players.iter() {
+            assert_eq!(player.score.kills, 0);
+            assert_eq!(player.score.deaths, 0);
+        }
+        assert_eq!(gs.range.as_ref().unwrap().last_ttk, Some(0.0));
+    }
+}
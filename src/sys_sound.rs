@@ -0,0 +1,550 @@
+//! Sound data derived from the game state.
+//!
+//! There's no audio playback yet, this only computes what a player would hear
+//! so a frontend can play and pitch-shift sounds. Nothing here affects gameplay.
+//!
+//! One-shot sounds are queued as `AudioEvent`s for everyone, including bots far away -
+//! it's up to the frontend to cull them by distance.
+//! Engines are continuous so they're `EngineSound`s recomputed every frame instead, see `engine_sounds`.
+
+use std::collections::VecDeque;
+
+use thunderdome::Index;
+
+use crate::{
+    cvars::Cvars,
+    effects::Effect,
+    entities::Weapon,
+    game_state::{AudioEvent, AudioEventKind, EngineSound, Flyby, GameState, SoundChannel},
+    map::Vec2f,
+    systems,
+};
+
+/// Queue a sound, dropping the oldest ones if the frontend stopped draining the queue.
+///
+/// Takes just the queue so it can be called while other parts of the game state are borrowed.
+pub fn push_audio_event(
+    cvars: &Cvars,
+    audio_events: &mut VecDeque<AudioEvent>,
+    kind: AudioEventKind,
+    pos: Vec2f,
+) {
+    audio_events.push_back(AudioEvent { kind, pos });
+    while audio_events.len() > cvars.cl_audio_events_max {
+        audio_events.pop_front();
+    }
+}
+
+/// Take all queued sounds, oldest first, with positions relative to the camera for stereo panning.
+///
+/// `camera_pos` is the center of the view in world coords.
+pub fn drain_audio_events(gs: &mut GameState, camera_pos: Vec2f) -> Vec<AudioEvent> {
+    gs.audio_events
+        .drain(..)
+        .map(|event| AudioEvent {
+            kind: event.kind,
+            pos: event.pos - camera_pos,
+        })
+        .collect()
+}
+
+/// Speed at which the source moves away from the listener, negative when approaching.
+///
+/// Returns 0 when the source is exactly at the listener's position since there's no direction.
+pub fn radial_velocity(
+    listener_pos: Vec2f,
+    listener_vel: Vec2f,
+    source_pos: Vec2f,
+    source_vel: Vec2f,
+) -> f64 {
+    let rel_pos = source_pos - listener_pos;
+    let rel_vel = source_vel - listener_vel;
+    match rel_pos.try_normalized() {
+        Some(dir) => rel_vel.dot(dir),
+        None => 0.0,
+    }
+}
+
+/// Closest approach during the frame if it happened strictly during the frame.
+///
+/// Computed in the listener's frame of reference, `rel_end` is the source's position
+/// relative to the listener at the end of the frame.
+/// Returns (relative position at the closest approach, relative position at the start of the frame).
+fn closest_approach(rel_end: Vec2f, rel_vel: Vec2f, dt: f64) -> Option<(Vec2f, Vec2f)> {
+    let rel_start = rel_end - rel_vel * dt;
+    // Approaching at the start and not anymore at the end.
+    if rel_start.dot(rel_vel) >= 0.0 || rel_end.dot(rel_vel) < 0.0 {
+        return None;
+    }
+    let t = -rel_start.dot(rel_vel) / rel_vel.magnitude_squared();
+    Some((rel_start + rel_vel * t, rel_start))
+}
+
+/// Update `GameState::engine_sounds` - one record per vehicle including bots and destroyed ones, sorted by handle
+/// so the frontend can map them to persistent audio nodes.
+///
+/// Vehicles removed since the last frame are kept one more frame with `removed` set
+/// so the frontend can tear down their nodes. The vec is reused to avoid allocating every frame.
+pub fn engine_sounds(cvars: &Cvars, gs: &mut GameState) {
+    let vehicles = &gs.vehicles;
+    gs.engine_sounds
+        .retain(|sound| !sound.removed && !vehicles.contains(sound.vehicle));
+    for sound in &mut gs.engine_sounds {
+        sound.removed = true;
+    }
+
+    for (handle, vehicle) in gs.vehicles.iter() {
+        let destroyed = vehicle.destroyed();
+        let throttle = if destroyed {
+            0.0
+        } else {
+            let input = &gs.players[vehicle.owner].input;
+            (input.up() - input.down()).abs().min(1.0)
+        };
+        let top_speed = systems::movement_stats(cvars, vehicle).top_speed();
+        let speed_fraction = if top_speed > 0.0 {
+            vehicle.vel.magnitude() / top_speed
+        } else {
+            0.0
+        };
+        gs.engine_sounds.push(EngineSound {
+            vehicle: handle,
+            pos: *vehicle.pos,
+            throttle,
+            speed_fraction,
+            scraping: gs.scraping.contains(&handle),
+            destroyed,
+            removed: false,
+        });
+    }
+    gs.engine_sounds.sort_by_key(|sound| sound.vehicle);
+}
+
+/// Flyby events and continuous channels for everyone who isn't a bot,
+/// engine sounds and engine and explosion audio events for everyone.
+pub fn sounds(cvars: &Cvars, gs: &mut GameState) {
+    gs.flybys.clear();
+    gs.sound_channels.clear();
+    engine_sounds(cvars, gs);
+
+    for (_, vehicle) in gs.vehicles.iter() {
+        if vehicle.destroyed() {
+            continue;
+        }
+        let input = &gs.players[vehicle.owner].input;
+        let throttle = input.up() - input.down();
+        let kind = AudioEventKind::VehicleEngine { throttle };
+        push_audio_event(cvars, &mut gs.audio_events, kind, *vehicle.pos);
+    }
+    // Explosions don't have a single place where they're created so look for the new ones.
+    for effect in &gs.effects {
+        if let Effect::Explosion {
+            pos,
+            scale,
+            start_time,
+            ..
+        } = *effect
+        {
+            if start_time == gs.game_time {
+                let kind = AudioEventKind::Explosion {
+                    large: scale >= 1.0,
+                };
+                push_audio_event(cvars, &mut gs.audio_events, kind, pos);
+            }
+        }
+    }
+
+    let listeners: Vec<Index> = gs
+        .players
+        .iter()
+        .filter(|&(handle, _)| !gs.ais.iter().any(|(_, ai)| ai.player == handle))
+        .map(|(handle, _)| handle)
+        .collect();
+
+    for listener in listeners {
+        let vehicle_handle = match gs.players[listener].vehicle {
+            Some(handle) => handle,
+            None => continue,
+        };
+        let vehicle = &gs.vehicles[vehicle_handle];
+        let listener_pos = *vehicle.pos;
+        let listener_vel = vehicle.vel;
+
+        let mut flybys = Vec::new();
+        for (proj_handle, projectile) in gs.projectiles.iter() {
+            if projectile.owner == listener {
+                continue;
+            }
+
+            let is_loud = projectile.weapon == Weapon::Bfg || projectile.weapon == Weapon::Gm;
+            if is_loud && projectile.pos.distance(listener_pos) <= cvars.cl_sound_channel_range {
+                gs.sound_channels.push(SoundChannel {
+                    listener,
+                    projectile: proj_handle,
+                    weapon: projectile.weapon,
+                    pos: projectile.pos,
+                    vel: projectile.vel,
+                    radial_vel: radial_velocity(
+                        listener_pos,
+                        listener_vel,
+                        projectile.pos,
+                        projectile.vel,
+                    ),
+                });
+            }
+
+            let speed = projectile.vel.magnitude();
+            let max_distance = cvars.cl_flyby_distance(projectile.weapon);
+            if speed < cvars.cl_flyby_speed_min || max_distance <= 0.0 {
+                continue;
+            }
+            let rel_end = projectile.pos - listener_pos;
+            let rel_vel = projectile.vel - listener_vel;
+            if let Some((rel_closest, rel_start)) = closest_approach(rel_end, rel_vel, gs.dt) {
+                let distance = rel_closest.magnitude();
+                if distance <= max_distance {
+                    flybys.push(Flyby {
+                        listener,
+                        projectile: proj_handle,
+                        weapon: projectile.weapon,
+                        pos: listener_pos + rel_closest,
+                        speed,
+                        distance,
+                        radial_vel: radial_velocity(
+                            listener_pos,
+                            listener_vel,
+                            listener_pos + rel_start,
+                            projectile.vel,
+                        ),
+                    });
+                }
+            }
+        }
+
+        // Too many at once would just be noise, keep the closest.
+        flybys.sort_by(|a, b| {
+            a.distance
+                .partial_cmp(&b.distance)
+                .unwrap()
+                .then(a.projectile.cmp(&b.projectile))
+        });
+        flybys.truncate(cvars.cl_flyby_max_per_frame);
+        gs.flybys.extend(flybys);
+    }
+
+    if cvars.d_flybys {
+        for flyby in &gs.flybys {
+            dbg_line!(flyby.pos, flyby.pos + Vec2f::new(0.0, -flyby.distance), 0.5);
+            dbg_world_textf!(
+                flyby.pos,
+                "{:?} {:?}->{:?} {:.0} {:+.0}",
+                flyby.weapon,
+                flyby.projectile,
+                flyby.listener,
+                flyby.speed,
+                flyby.radial_vel
+            );
+        }
+        for channel in &gs.sound_channels {
+            dbg_line!(channel.pos, channel.pos + channel.vel * 0.1);
+            dbg_world_textf!(
+                channel.pos,
+                "{:?} {:?}->{:?} {:+.0}",
+                channel.weapon,
+                channel.projectile,
+                channel.listener,
+                channel.radial_vel
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        entities::{Player, Projectile, Vehicle, VehicleType},
+        map::Kind,
+    };
+
+    #[test]
+    fn test_radial_velocity() {
+        let zero = Vec2f::zero();
+        // Approaching head on
+        let v = radial_velocity(zero, zero, Vec2f::new(100.0, 0.0), Vec2f::new(-50.0, 0.0));
+        assert_eq!(v, -50.0);
+        // Receding
+        let v = radial_velocity(zero, zero, Vec2f::new(100.0, 0.0), Vec2f::new(50.0, 0.0));
+        assert_eq!(v, 50.0);
+        // Perpendicular - no radial component
+        let v = radial_velocity(zero, zero, Vec2f::new(100.0, 0.0), Vec2f::new(0.0, 50.0));
+        assert_eq!(v, 0.0);
+        // 3-4-5 triangle: source at (30, 40), moving left at 100 -> radial = -100 * 30 / 50
+        let v = radial_velocity(zero, zero, Vec2f::new(30.0, 40.0), Vec2f::new(-100.0, 0.0));
+        assert!((v - -60.0).abs() < 1e-9);
+        // Listener chasing a slower source
+        let v = radial_velocity(
+            zero,
+            Vec2f::new(80.0, 0.0),
+            Vec2f::new(100.0, 0.0),
+            Vec2f::new(50.0, 0.0),
+        );
+        assert_eq!(v, -30.0);
+        // Same position
+        let v = radial_velocity(zero, zero, zero, Vec2f::new(50.0, 0.0));
+        assert_eq!(v, 0.0);
+    }
+
+    /// A game with one human at (500, 500) and nothing else.
+    fn setup() -> (Cvars, GameState, Index, Index) {
+        let cvars = Cvars::default();
+        let mut gs = GameState::new(0);
+        let human = gs.players.insert(Player::new("Human".to_owned()));
+        let other = gs.players.insert(Player::new("Other".to_owned()));
+        let pos = Vec2f::new(500.0, 500.0);
+        let vehicle = Vehicle::new(&cvars, pos, 0.0, VehicleType::Tank, 0, 0.0, human);
+        gs.players[human].vehicle = Some(gs.vehicles.insert(vehicle));
+        gs.dt = 1.0 / 60.0;
+        (cvars, gs, human, other)
+    }
+
+    fn shoot(gs: &mut GameState, owner: Index, weapon: Weapon, pos: Vec2f, vel: Vec2f) -> Index {
+        gs.projectiles.insert(Projectile {
+            weapon,
+            pos,
+            vel,
+            angle: 0.0,
+            turn_rate: 0.0,
+            explode_time: f64::MAX,
+            spawn_time: 0.0,
+            owner,
+            lock: None,
+            retarget_time: 0.0,
+            altitude: None,
+        })
+    }
+
+    #[test]
+    fn test_flyby() {
+        let (cvars, mut gs, human, other) = setup();
+        // Rocket moving right at 600 passing 20 below the listener.
+        // It passed x = 500 during this frame.
+        let vel = Vec2f::new(600.0, 0.0);
+        let handle = shoot(
+            &mut gs,
+            other,
+            Weapon::Rockets,
+            Vec2f::new(505.0, 520.0),
+            vel,
+        );
+        sounds(&cvars, &mut gs);
+
+        assert_eq!(gs.flybys.len(), 1);
+        let flyby = &gs.flybys[0];
+        assert_eq!(flyby.listener, human);
+        assert_eq!(flyby.projectile, handle);
+        assert!((flyby.distance - 20.0).abs() < 1e-9);
+        assert!((flyby.pos - Vec2f::new(500.0, 520.0)).magnitude() < 1e-9);
+        assert!(flyby.radial_vel < 0.0);
+        assert!(flyby.radial_vel > -600.0);
+
+        // Next frame it's receding - no new event.
+        gs.projectiles[handle].pos += vel * gs.dt;
+        sounds(&cvars, &mut gs);
+        assert!(gs.flybys.is_empty());
+    }
+
+    #[test]
+    fn test_flyby_through_listener() {
+        let (cvars, mut gs, _, other) = setup();
+        // Ends exactly at the listener.
+        let vel = Vec2f::new(0.0, -1000.0);
+        shoot(&mut gs, other, Weapon::Mg, Vec2f::new(500.0, 500.0), vel);
+        // Passes exactly through the listener.
+        shoot(&mut gs, other, Weapon::Mg, Vec2f::new(500.0, 490.0), vel);
+        sounds(&cvars, &mut gs);
+
+        for flyby in &gs.flybys {
+            assert!(!flyby.distance.is_nan());
+            assert!(!flyby.radial_vel.is_nan());
+            assert!(!flyby.pos.x.is_nan() && !flyby.pos.y.is_nan());
+        }
+        assert_eq!(gs.flybys.len(), 2);
+        assert_eq!(gs.flybys[0].distance, 0.0);
+    }
+
+    #[test]
+    fn test_flyby_thresholds() {
+        let (cvars, mut gs, _, other) = setup();
+        let vel = Vec2f::new(1000.0, 0.0);
+        // MG too far
+        shoot(&mut gs, other, Weapon::Mg, Vec2f::new(505.0, 600.0), vel);
+        // CB never
+        let cb_vel = Vec2f::new(cvars.g_cluster_bomb_speed, 0.0);
+        shoot(&mut gs, other, Weapon::Cb, Vec2f::new(505.0, 505.0), cb_vel);
+        // Too slow
+        let slow = Vec2f::new(100.0, 0.0);
+        shoot(
+            &mut gs,
+            other,
+            Weapon::Rockets,
+            Vec2f::new(501.0, 505.0),
+            slow,
+        );
+        // Own projectile
+        let human = gs.vehicles.iter().next().unwrap().1.owner;
+        shoot(
+            &mut gs,
+            human,
+            Weapon::Rockets,
+            Vec2f::new(505.0, 505.0),
+            vel,
+        );
+        sounds(&cvars, &mut gs);
+        assert!(gs.flybys.is_empty());
+
+        // Rockets at the same distance as the MG are heard.
+        shoot(
+            &mut gs,
+            other,
+            Weapon::Rockets,
+            Vec2f::new(505.0, 600.0),
+            vel,
+        );
+        sounds(&cvars, &mut gs);
+        assert_eq!(gs.flybys.len(), 1);
+    }
+
+    #[test]
+    fn test_flyby_cap() {
+        let (cvars, mut gs, _, other) = setup();
+        let vel = Vec2f::new(1000.0, 0.0);
+        for i in 0..100 {
+            let offset = (i % 30) as f64;
+            shoot(
+                &mut gs,
+                other,
+                Weapon::Mg,
+                Vec2f::new(505.0, 470.0 + offset),
+                vel,
+            );
+        }
+        sounds(&cvars, &mut gs);
+        assert_eq!(gs.flybys.len(), cvars.cl_flyby_max_per_frame);
+        // 3 projectiles each at distance 1 and 2, the closest are kept.
+        let distances: Vec<_> = gs
+            .flybys
+            .iter()
+            .map(|flyby| flyby.distance.round())
+            .collect();
+        assert_eq!(distances, [1.0, 1.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn test_sound_channels() {
+        let (cvars, mut gs, _, other) = setup();
+        let vel = Vec2f::new(-150.0, 0.0);
+        let near = shoot(&mut gs, other, Weapon::Bfg, Vec2f::new(700.0, 500.0), vel);
+        shoot(&mut gs, other, Weapon::Bfg, Vec2f::new(5000.0, 500.0), vel);
+        shoot(
+            &mut gs,
+            other,
+            Weapon::Rockets,
+            Vec2f::new(700.0, 500.0),
+            vel,
+        );
+        sounds(&cvars, &mut gs);
+        assert_eq!(gs.sound_channels.len(), 1);
+        assert_eq!(gs.sound_channels[0].projectile, near);
+        assert_eq!(gs.sound_channels[0].radial_vel, -150.0);
+    }
+
+    #[test]
+    fn test_audio_events() {
+        let cvars = Cvars {
+            cl_audio_events_max: 3,
+            ..Cvars::default()
+        };
+        let mut gs = GameState::new(0);
+        for i in 0..5 {
+            let pos = Vec2f::new(i as f64 * 1000.0, 0.0);
+            push_audio_event(&cvars, &mut gs.audio_events, AudioEventKind::RailHit, pos);
+        }
+        let events = drain_audio_events(&mut gs, Vec2f::new(100.0, 50.0));
+        let positions: Vec<_> = events.iter().map(|event| event.pos).collect();
+        let expected = [
+            Vec2f::new(1900.0, -50.0),
+            Vec2f::new(2900.0, -50.0),
+            Vec2f::new(3900.0, -50.0),
+        ];
+        assert_eq!(positions, expected);
+        assert!(gs.audio_events.is_empty());
+
+        // Far away bots are included, culling is up to the frontend.
+        let cvars = Cvars::default();
+        let bot = gs.players.insert(Player::new("Bot".to_owned()));
+        gs.players[bot].input.up = true;
+        let pos = Vec2f::new(100_000.0, 0.0);
+        let vehicle = Vehicle::new(&cvars, pos, 0.0, VehicleType::Tank, 0, 0.0, bot);
+        gs.vehicles.insert(vehicle);
+        sounds(&cvars, &mut gs);
+        let events = drain_audio_events(&mut gs, Vec2f::zero());
+        let expected = AudioEvent {
+            kind: AudioEventKind::VehicleEngine { throttle: 1.0 },
+            pos,
+        };
+        assert_eq!(events, [expected]);
+    }
+
+    #[test]
+    fn test_engine_sounds() {
+        let (cvars, mut gs, human, other) = setup();
+        let human_vehicle = gs.players[human].vehicle.unwrap();
+        let top_speed = cvars
+            .g_vehicle_movement_stats(VehicleType::Tank, Kind::Normal)
+            .top_speed();
+        gs.vehicles[human_vehicle].vel = Vec2f::new(top_speed / 2.0, 0.0);
+        gs.players[human].input.down = true;
+        gs.scraping.push(human_vehicle);
+        let vehicle = Vehicle::new(
+            &cvars,
+            Vec2f::zero(),
+            0.0,
+            VehicleType::Hummer,
+            0,
+            0.0,
+            other,
+        );
+        let other_vehicle = gs.vehicles.insert(vehicle);
+
+        sounds(&cvars, &mut gs);
+        let handles: Vec<_> = gs.engine_sounds.iter().map(|sound| sound.vehicle).collect();
+        let mut sorted = vec![human_vehicle, other_vehicle];
+        sorted.sort();
+        assert_eq!(handles, sorted);
+        let human_sound = gs
+            .engine_sounds
+            .iter()
+            .find(|sound| sound.vehicle == human_vehicle)
+            .unwrap();
+        assert_eq!(human_sound.throttle, 1.0);
+        assert!((human_sound.speed_fraction - 0.5).abs() < 1e-9);
+        assert!(human_sound.scraping && !human_sound.destroyed && !human_sound.removed);
+
+        // Removed vehicles appear once more, then they're gone.
+        gs.vehicles.remove(other_vehicle);
+        sounds(&cvars, &mut gs);
+        let removed: Vec<_> = gs
+            .engine_sounds
+            .iter()
+            .map(|sound| (sound.vehicle, sound.removed))
+            .collect();
+        let mut expected = vec![(human_vehicle, false), (other_vehicle, true)];
+        expected.sort();
+        assert_eq!(removed, expected);
+        sounds(&cvars, &mut gs);
+        assert_eq!(gs.engine_sounds.len(), 1);
+        assert_eq!(gs.engine_sounds[0].vehicle, human_vehicle);
+    }
+}
@@ -0,0 +1,312 @@
+//! Threat warnings - what's about to hurt each player, for the HUD and audio events.
+//!
+//! Every frame each player with a living vehicle gets the threats against them:
+//! - enemy homing and guided missiles within `g_missile_warning_range` which are getting closer
+//! - enemies charging their railgun within `g_railgun_charge_exposure_radius`
+//!
+//! Urgency is the inverse of the time until the threat hurts -
+//! time to impact at the current closing speed for missiles, remaining charge time for railguns.
+//!
+//! LATER There's no fog of war yet. When there is, missile warnings should ignore it
+//! and railgun charge exposure should respect it (probably configurable).
+//! LATER Off-screen indicators for threats outside the view.
+
+use thunderdome::Index;
+
+use crate::{
+    cvars::Cvars,
+    entities::Weapon,
+    game_state::{AudioEventKind, GameState},
+    map::{Vec2f, VecExt},
+    sys_sound,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Threat {
+    /// The threatened player
+    pub player: Index,
+    pub kind: ThreatKind,
+    /// Where the threat is
+    pub pos: Vec2f,
+    /// Angle from the threatened vehicle to the threat
+    pub bearing: f64,
+    /// 1 / seconds until the threat hurts, higher is more urgent.
+    pub urgency: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreatKind {
+    Missile { projectile: Index, weapon: Weapon },
+    RailCharge { vehicle: Index },
+}
+
+/// Rebuild the threat list, most urgent first.
+///
+/// Newly detected missiles also produce a warning audio event,
+/// it's only sent once per missile so the queue doesn't get flooded.
+pub fn threats(cvars: &Cvars, gs: &mut GameState) {
+    let prev = std::mem::take(&mut gs.threats);
+
+    let mut threats = Vec::new();
+    for (player_handle, player) in gs.players.iter() {
+        let vehicle = match player.vehicle {
+            Some(handle) => &gs.vehicles[handle],
+            None => continue,
+        };
+        if vehicle.destroyed() {
+            continue;
+        }
+        let pos = *vehicle.pos;
+        let mut threat = |kind, source_pos: Vec2f, urgency| {
+            threats.push(Threat {
+                player: player_handle,
+                kind,
+                pos: source_pos,
+                bearing: (source_pos - pos).to_angle(),
+                urgency,
+            });
+        };
+
+        for (proj_handle, projectile) in gs.projectiles.iter() {
+            let is_missile = projectile.weapon == Weapon::Hm || projectile.weapon == Weapon::Gm;
+            if !is_missile || projectile.owner == player_handle {
+                continue;
+            }
+            let distance = projectile.pos.distance(pos);
+            if distance > cvars.g_missile_warning_range {
+                continue;
+            }
+            let radial_vel =
+                sys_sound::radial_velocity(pos, vehicle.vel, projectile.pos, projectile.vel);
+            if radial_vel >= 0.0 {
+                continue;
+            }
+            let kind = ThreatKind::Missile {
+                projectile: proj_handle,
+                weapon: projectile.weapon,
+            };
+            // Avoid infinity when it's right on top of us.
+            threat(kind, projectile.pos, -radial_vel / distance.max(1.0));
+        }
+
+        for (other_handle, other) in gs.vehicles.iter() {
+            let charge_start = match other.rail_charge_start {
+                Some(start) => start,
+                None => continue,
+            };
+            if other.owner == player_handle
+                || other.destroyed()
+                || other.pos.distance(pos) > cvars.g_railgun_charge_exposure_radius
+            {
+                continue;
+            }
+            let remaining = charge_start + cvars.g_railgun_charge_time - gs.game_time;
+            let kind = ThreatKind::RailCharge {
+                vehicle: other_handle,
+            };
+            threat(kind, *other.pos, 1.0 / remaining.max(0.001));
+        }
+    }
+    // Stable so threats with the same urgency keep a deterministic order.
+    threats.sort_by(|a, b| b.urgency.partial_cmp(&a.urgency).unwrap());
+
+    for threat in &threats {
+        if let ThreatKind::Missile { .. } = threat.kind {
+            if !prev
+                .iter()
+                .any(|prev| prev.player == threat.player && prev.kind == threat.kind)
+            {
+                let kind = AudioEventKind::MissileWarning {
+                    player: threat.player,
+                    bearing: threat.bearing,
+                };
+                sys_sound::push_audio_event(cvars, &mut gs.audio_events, kind, threat.pos);
+            }
+        }
+    }
+    gs.threats = threats;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        entities::{Player, Projectile, Vehicle, VehicleType},
+        systems,
+    };
+
+    fn add_player(cvars: &Cvars, gs: &mut GameState, pos: Vec2f) -> (Index, Index) {
+        let player_handle = gs.players.insert(Player::new("Player".to_owned()));
+        let vehicle = Vehicle::new(cvars, pos, 0.0, VehicleType::Tank, 0, 0.0, player_handle);
+        let vehicle_handle = gs.vehicles.insert(vehicle);
+        gs.players[player_handle].vehicle = Some(vehicle_handle);
+        (player_handle, vehicle_handle)
+    }
+
+    fn missile(weapon: Weapon, owner: Index, pos: Vec2f, vel: Vec2f) -> Projectile {
+        Projectile {
+            weapon,
+            pos,
+            vel,
+            angle: vel.to_angle(),
+            turn_rate: 0.0,
+            explode_time: f64::MAX,
+            spawn_time: 0.0,
+            owner,
+            lock: None,
+            retarget_time: 0.0,
+            altitude: None,
+        }
+    }
+
+    #[test]
+    fn test_missile_closing() {
+        let cvars = Cvars::default();
+        let mut gs = GameState::new(0);
+        let (me, _) = add_player(&cvars, &mut gs, Vec2f::zero());
+        let (enemy, _) = add_player(&cvars, &mut gs, Vec2f::new(1000.0, 0.0));
+
+        let pos = Vec2f::new(200.0, 0.0);
+        let away = missile(Weapon::Hm, enemy, pos, Vec2f::new(300.0, 0.0));
+        let handle = gs.projectiles.insert(away);
+        threats(&cvars, &mut gs);
+        assert!(gs.threats.is_empty());
+        assert!(gs.audio_events.is_empty());
+
+        gs.projectiles[handle].vel = Vec2f::new(-300.0, 0.0);
+        threats(&cvars, &mut gs);
+        assert_eq!(gs.threats.len(), 1);
+        assert_eq!(gs.threats[0].player, me);
+        assert_eq!(gs.threats[0].bearing, 0.0);
+        assert_eq!(gs.threats[0].urgency, 1.5);
+        assert_eq!(gs.audio_events.len(), 1);
+
+        // Only warned once per missile.
+        threats(&cvars, &mut gs);
+        assert_eq!(gs.threats.len(), 1);
+        assert_eq!(gs.audio_events.len(), 1);
+    }
+
+    #[test]
+    fn test_rail_charge() {
+        let cvars = Cvars {
+            g_railgun_charge_time: 0.25,
+            ..Cvars::default()
+        };
+        let mut gs = GameState::new(0);
+        let (me, _) = add_player(&cvars, &mut gs, Vec2f::zero());
+        let (enemy, enemy_vehicle) = add_player(&cvars, &mut gs, Vec2f::new(300.0, 0.0));
+        gs.players[enemy].cur_weapon = Weapon::Rail;
+        gs.players[enemy].input.fire = true;
+
+        gs.dt = 1.0 / 16.0;
+        let mut exposed_frames = Vec::new();
+        let mut fired_frame = None;
+        for frame in 1..=8 {
+            gs.game_time_prev = gs.game_time;
+            gs.game_time = frame as f64 * gs.dt;
+            systems::shooting(&cvars, &mut gs);
+            threats(&cvars, &mut gs);
+            if !gs.threats.is_empty() {
+                assert_eq!(gs.threats[0].player, me);
+                let kind = ThreatKind::RailCharge {
+                    vehicle: enemy_vehicle,
+                };
+                assert_eq!(gs.threats[0].kind, kind);
+                exposed_frames.push(frame);
+            }
+            if fired_frame.is_none() && !gs.projectiles.is_empty() {
+                fired_frame = Some(frame);
+            }
+        }
+        // Starts charging at the beginning of the first frame, charged 0.25 s (4 frames) later.
+        assert_eq!(fired_frame, Some(4));
+        assert_eq!(exposed_frames, [1, 2, 3]);
+        assert!(gs.vehicles[enemy_vehicle].rail_charge_start.is_none());
+    }
+
+    #[test]
+    fn test_threat_order() {
+        let cvars = Cvars {
+            g_railgun_charge_time: 1.0,
+            ..Cvars::default()
+        };
+        let mut gs = GameState::new(0);
+        gs.game_time = 10.0;
+        let (me, _) = add_player(&cvars, &mut gs, Vec2f::zero());
+        let (enemy, enemy_vehicle) = add_player(&cvars, &mut gs, Vec2f::new(0.0, 400.0));
+        // 0.25 s left
+        gs.vehicles[enemy_vehicle].rail_charge_start = Some(9.25);
+
+        let left = Vec2f::new(-1.0, 0.0);
+        let gm = missile(Weapon::Gm, enemy, Vec2f::new(200.0, 0.0), left * 100.0);
+        let gm = gs.projectiles.insert(gm);
+        let hm = missile(Weapon::Hm, enemy, Vec2f::new(100.0, 0.0), left * 200.0);
+        let hm = gs.projectiles.insert(hm);
+        // Not missiles, own, receding or out of range
+        gs.projectiles.insert(missile(
+            Weapon::Rockets,
+            enemy,
+            Vec2f::new(50.0, 0.0),
+            left * 500.0,
+        ));
+        gs.projectiles
+            .insert(missile(Weapon::Hm, me, Vec2f::new(50.0, 0.0), left * 500.0));
+        gs.projectiles.insert(missile(
+            Weapon::Gm,
+            enemy,
+            Vec2f::new(50.0, 0.0),
+            -left * 500.0,
+        ));
+        gs.projectiles.insert(missile(
+            Weapon::Gm,
+            enemy,
+            Vec2f::new(5000.0, 0.0),
+            left * 500.0,
+        ));
+
+        threats(&cvars, &mut gs);
+        let mine: Vec<_> = gs
+            .threats
+            .iter()
+            .filter(|threat| threat.player == me)
+            .map(|threat| (threat.kind, threat.urgency))
+            .collect();
+        let expected = [
+            (
+                ThreatKind::RailCharge {
+                    vehicle: enemy_vehicle,
+                },
+                4.0,
+            ),
+            (
+                ThreatKind::Missile {
+                    projectile: hm,
+                    weapon: Weapon::Hm,
+                },
+                2.0,
+            ),
+            (
+                ThreatKind::Missile {
+                    projectile: gm,
+                    weapon: Weapon::Gm,
+                },
+                0.5,
+            ),
+        ];
+        assert_eq!(mine, expected);
+        // The enemy's own missiles don't threaten them, only mine does.
+        assert!(gs
+            .threats
+            .iter()
+            .filter(|threat| threat.player == enemy)
+            .all(|threat| matches!(
+                threat.kind,
+                ThreatKind::Missile {
+                    weapon: Weapon::Hm,
+                    ..
+                }
+            )));
+    }
+}
@@ -0,0 +1,168 @@
+//! Trails behind fast projectiles - purely cosmetic, nothing in the gamelogic reads them.
+//!
+//! Every gamelogic frame, each projectile whose weapon has a trail (see `Cvars::r_trail`)
+//! records its position in a ring buffer of the last `TRAIL_CAPACITY` positions
+//! and the trail is drawn as a fading line through them.
+//! A projectile which ricocheted marks its next position as a discontinuity
+//! so the trail is broken instead of cutting through the corner of the wall.
+//!
+//! Trails are not in snapshots or checksums.
+
+use crate::{
+    cvars::Cvars,
+    game_state::{GameState, Trail, TrailSample},
+};
+
+/// Record the current position of every projectile with a trail.
+///
+/// Has to run after projectiles move and bounce.
+pub fn update_trails(cvars: &Cvars, gs: &mut GameState) {
+    let projectiles = &gs.projectiles;
+    gs.trails.retain(|&handle, _| projectiles.contains(handle));
+
+    for (handle, projectile) in gs.projectiles.iter() {
+        if cvars.r_trail(projectile.weapon).is_none() {
+            continue;
+        }
+        let bounces = gs.bounces.get(&handle).copied().unwrap_or(0);
+        let trail = gs
+            .trails
+            .entry(handle)
+            .or_insert_with(|| Trail::new(bounces));
+        let discontinuity = bounces != trail.bounces;
+        trail.bounces = bounces;
+        trail.push(TrailSample {
+            pos: projectile.pos,
+            discontinuity,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use thunderdome::{Arena, Index};
+
+    use super::*;
+
+    use crate::{
+        entities::{Projectile, Weapon},
+        game_state::TRAIL_CAPACITY,
+        map::{self, Vec2f},
+        systems,
+    };
+
+    fn add_projectile(gs: &mut GameState, weapon: Weapon, pos: Vec2f, vel: Vec2f) -> Index {
+        let owner = Arena::<()>::new().insert(());
+        gs.projectiles.insert(Projectile {
+            weapon,
+            pos,
+            vel,
+            angle: 0.0,
+            turn_rate: 0.0,
+            explode_time: f64::MAX,
+            spawn_time: 0.0,
+            owner,
+            lock: None,
+            retarget_time: 0.0,
+            altitude: None,
+        })
+    }
+
+    fn xs(trail: &Trail) -> Vec<f64> {
+        trail.samples().map(|sample| sample.pos.x).collect()
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let cvars = Cvars::default();
+        let mut gs = GameState::new(0);
+        let mg = add_projectile(&mut gs, Weapon::Mg, Vec2f::zero(), Vec2f::zero());
+        let bfg = add_projectile(&mut gs, Weapon::Bfg, Vec2f::zero(), Vec2f::zero());
+
+        // Fewer samples than capacity at first.
+        for i in 0..3 {
+            gs.projectiles[mg].pos.x = i as f64;
+            update_trails(&cvars, &mut gs);
+        }
+        assert_eq!(xs(&gs.trails[&mg]), [0.0, 1.0, 2.0]);
+        assert!(!gs.trails.contains_key(&bfg));
+
+        // Then the oldest get replaced.
+        for i in 3..12 {
+            gs.projectiles[mg].pos.x = i as f64;
+            update_trails(&cvars, &mut gs);
+        }
+        let expected: Vec<_> = (12 - TRAIL_CAPACITY..12).map(|i| i as f64).collect();
+        assert_eq!(xs(&gs.trails[&mg]), expected);
+
+        gs.projectiles.remove(mg);
+        update_trails(&cvars, &mut gs);
+        assert!(gs.trails.is_empty());
+    }
+
+    #[test]
+    fn test_ricochet_breaks_trail() {
+        let cvars = Cvars::default();
+        let mut gs = GameState::new(0);
+        let mg = add_projectile(&mut gs, Weapon::Mg, Vec2f::zero(), Vec2f::zero());
+        update_trails(&cvars, &mut gs);
+        gs.projectiles[mg].pos.x = 10.0;
+        gs.bounces.insert(mg, 1);
+        update_trails(&cvars, &mut gs);
+        gs.projectiles[mg].pos.x = 20.0;
+        update_trails(&cvars, &mut gs);
+
+        let breaks: Vec<_> = gs.trails[&mg]
+            .samples()
+            .map(|sample| sample.discontinuity)
+            .collect();
+        assert_eq!(breaks, [false, true, false]);
+    }
+
+    /// Compares how long trails take to how long it takes to move the projectiles they belong to.
+    ///
+    /// Timing is noisy so this only prints the results, run it with:
+    /// `cargo test --release -- --ignored --nocapture bench_trails`
+    #[test]
+    #[ignore]
+    fn bench_trails() {
+        let cvars = Cvars::default();
+        let row = vec!["0"; 20].join(" ");
+        let text = vec![row; 20].join("\n");
+        let mut map = map::tests::load_test_map(&text);
+
+        let mut gs = GameState::new(0);
+        gs.dt = 1.0 / 60.0;
+        for i in 0..500 {
+            let pos = Vec2f::new(
+                100.0 + (i % 25) as f64 * 40.0,
+                100.0 + (i / 25) as f64 * 40.0,
+            );
+            let weapon = if i % 2 == 0 {
+                Weapon::Mg
+            } else {
+                Weapon::Rockets
+            };
+            add_projectile(&mut gs, weapon, pos, Vec2f::new(1.0, 1.0));
+        }
+
+        let (mut projectiles, mut trails) = (0.0, 0.0);
+        for _ in 0..1000 {
+            let start = Instant::now();
+            systems::projectiles(&cvars, &mut gs, &mut map);
+            let moved = Instant::now();
+            update_trails(&cvars, &mut gs);
+            projectiles += (moved - start).as_secs_f64();
+            trails += moved.elapsed().as_secs_f64();
+        }
+        assert_eq!(gs.trails.len(), 500);
+        println!(
+            "projectiles: {:.3} ms, trails: {:.3} ms per frame ({:.1}%)",
+            projectiles,
+            trails,
+            trails / projectiles * 100.0
+        );
+    }
+}
@@ -7,28 +7,37 @@
 use std::f64::consts::PI;
 
 use rand::Rng;
-use rand_distr::StandardNormal;
-use thunderdome::Index;
+use rand_distr::{StandardNormal, Uniform};
+use thunderdome::{Arena, Index};
 use vek::{Clamp, LineSegment2, Wrap};
 
 use crate::{
-    cvars::{Cvars, Hardpoint, MovementStats},
-    entities::{Ammo, Projectile, Respawn, Vehicle, VehicleType, Weapon, WEAPS_CNT},
+    collision_debug::{self, CollisionEntry, Resolution, TraceEntity},
+    cvars::{Cvars, DamageType, FriendlyFire, Hardpoint, MovementStats, ProjectileSpawnParams},
+    effects::{self, Effect},
+    entities::{
+        Altitude, Ammo, Facing, Obb, Pickup, PickupKind, Player, Projectile, Respawn, Vehicle,
+        VehicleType, Weapon, WEAPS_CNT,
+    },
+    fixed::{self, MilliHp},
     game_state::ArenaExt,
-    game_state::{Explosion, GameState, Input, RailBeam},
-    map::{F64Ext, Map, Vec2f},
+    game_state::{
+        push_notification, AudioEventKind, GameState, Input, MatchState, NotificationKind,
+    },
+    lives::Death,
+    map::{F64Ext, Kind, Map, Vec2f, Vec2u, VecExt, WallHit, TILE_SIZE},
+    rng::GameRng,
+    sys_ctf, sys_particles, sys_sound,
+    trace::{self, EntitySummary, Trace, TraceEvent},
+    visibility::line_of_sight,
 };
 
 /// Delete data from previous frames that's no longer needed.
 pub fn cleanup(cvars: &Cvars, gs: &mut GameState) {
-    let game_time = gs.game_time; // borrowck
-    gs.rail_beams
-        .retain(|beam| beam.start_time + cvars.cl_railgun_trail_duration > game_time);
-    gs.bfg_beams.clear();
-    gs.explosions.retain(|explosion| {
-        let progress = (game_time - explosion.start_time) / cvars.r_explosion_duration;
-        progress <= 1.0
-    });
+    gs.shots.clear();
+    gs.scraping.clear();
+    gs.projectile_rays.clear();
+    effects::retire(cvars, gs);
 }
 
 pub fn respawning(cvars: &Cvars, gs: &mut GameState, map: &Map) {
@@ -59,7 +68,10 @@ pub fn respawning(cvars: &Cvars, gs: &mut GameState, map: &Map) {
             && player.death_time + cvars.g_respawn_delay < gs.game_time
         {
             player.respawn = Respawn::No;
-            gs.vehicles.remove(vehicle_handle).unwrap();
+            let vehicle = gs.vehicles.remove(vehicle_handle).unwrap();
+            trace::record(cvars, &mut gs.trace, || {
+                TraceEvent::Remove(EntitySummary::vehicle(vehicle_handle, &vehicle))
+            });
             spawn_vehicle(cvars, gs, map, player_handle, true);
         }
     }
@@ -72,14 +84,25 @@ pub fn spawn_vehicle(
     player_handle: Index,
     use_spawns: bool,
 ) {
-    let veh_type = VehicleType::n(gs.rng.gen_range(0..3)).unwrap();
+    let player = &mut gs.players[player_handle];
+    player.loadout = player.pending_loadout.clone();
+    let veh_type = match player.loadout.veh_type {
+        Some(veh_type) => veh_type,
+        None => VehicleType::n(gs.rng.world.gen_range(0..3)).unwrap(),
+    };
+    let usable = |weapon| usable_weapon(cvars, player, Some(veh_type), weapon);
+    if !usable(player.cur_weapon) {
+        if let Some(weapon) = Weapon::all().find(|&weapon| usable(weapon)) {
+            player.cur_weapon = weapon;
+        }
+    }
     let (spawn_pos, spawn_angle) = if use_spawns {
-        map.random_spawn(&mut gs.rng)
+        map.random_spawn(&mut gs.rng.world, player.team)
     } else {
-        let (pos, _angle) = map.random_nonwall(&mut gs.rng);
+        let (pos, _angle) = map.random_nonwall(&mut gs.rng.world);
         // Most grass tiles have no rotation so everyone ends up facing right which looks bad.
         // Throw away their angle and use a random one.
-        let angle = gs.rng.gen_range(0.0..2.0 * PI);
+        let angle = gs.rng.world.gen_range(0.0..2.0 * PI);
         (pos, angle)
     };
 
@@ -88,18 +111,27 @@ pub fn spawn_vehicle(
         spawn_pos,
         spawn_angle,
         veh_type,
+        gs.frame_num,
         gs.game_time,
         player_handle,
     ));
+    trace::record(cvars, &mut gs.trace, || {
+        TraceEvent::Spawn(EntitySummary::vehicle(
+            vehicle_handle,
+            &gs.vehicles[vehicle_handle],
+        ))
+    });
 
     let player = &mut gs.players[player_handle];
     player.vehicle = Some(vehicle_handle);
+    gs.life_records
+        .spawn(player_handle, gs.game_time, spawn_pos, veh_type);
 }
 
-pub fn self_destruct(cvars: &Cvars, gs: &mut GameState) {
+pub fn self_destruct(cvars: &Cvars, gs: &mut GameState, map: &mut Map) {
     for vehicle_handle in gs.vehicles.iter_handles() {
         let vehicle = &gs.vehicles[vehicle_handle];
-        let pos = vehicle.pos;
+        let pos = *vehicle.pos;
         let owner = vehicle.owner;
         let input = &gs.players[owner].input;
         if !input.self_destruct || vehicle.destroyed() {
@@ -107,35 +139,164 @@ pub fn self_destruct(cvars: &Cvars, gs: &mut GameState) {
         }
 
         // 1) the big explosion
-        gs.explosions.push(Explosion::new(
+        gs.spawn_effect(Effect::Explosion {
             pos,
-            cvars.g_self_destruct_explosion_scale,
-            gs.game_time,
-            false,
-        ));
+            scale: cvars.g_self_destruct_explosion_scale,
+            start_time: gs.game_time,
+            bfg: false,
+        });
+        sys_particles::emit_dust(cvars, gs, pos, cvars.g_self_destruct_explosion_scale);
 
-        // 2) all vehicles in range
+        // 2) all vehicles (and destructible walls) in range
         explosion_damage(
             cvars,
             gs,
+            map,
             owner,
             pos,
-            cvars.g_self_destruct_damage_center,
-            cvars.g_self_destruct_damage_edge,
+            fixed::to_milli(cvars.g_self_destruct_damage_center),
+            fixed::to_milli(cvars.g_self_destruct_damage_edge),
             cvars.g_self_destruct_radius,
+            None,
+            cvars.g_self_destruct_damage_type,
             Some(vehicle_handle),
         );
 
         // 3) the player vehicle to create the small explosion on top.
-        damage(cvars, gs, owner, vehicle_handle, f64::MAX);
+        let dmg_type = cvars.g_self_destruct_damage_type;
+        damage(
+            cvars,
+            gs,
+            owner,
+            vehicle_handle,
+            None,
+            MilliHp::MAX,
+            dmg_type,
+            None,
+        );
 
         // LATER What was the order of explosions in the original RecWar? Make it configurable?
     }
 }
 
+/// Throw debris from vehicles destroyed this frame and damage those around them.
+///
+/// Vehicles destroyed by this explode too, still in this frame.
+/// All damage is credited to whoever destroyed the first vehicle in the chain.
+/// Each vehicle is only queued once, when it gets destroyed, so the chain always ends.
+///
+/// Guided missiles whose owner died (see `g_guided_missile_detonate_on_owner_death`) are detonated here too.
+pub fn vehicle_explosions(cvars: &Cvars, gs: &mut GameState, map: &mut Map) {
+    loop {
+        if let Some(gm_handle) = gs.pending_detonations.pop_front() {
+            // It might have hit something since.
+            if let Some(gm) = gs.projectiles.get(gm_handle) {
+                let pos = gm.pos;
+                projectile_impact(cvars, gs, map, gm_handle, pos);
+            }
+        } else if let Some((vehicle_handle, attacker)) = gs.pending_explosions.pop_front() {
+            vehicle_explosion(cvars, gs, map, vehicle_handle, attacker);
+        } else {
+            break;
+        }
+    }
+}
+
+fn vehicle_explosion(
+    cvars: &Cvars,
+    gs: &mut GameState,
+    map: &mut Map,
+    vehicle_handle: Index,
+    attacker: Index,
+) {
+    let vehicle = &gs.vehicles[vehicle_handle];
+    let pos = *vehicle.pos;
+    let owner = vehicle.owner;
+
+    for _ in 0..cvars.g_vehicle_debris_count {
+        let angle = gs.rng.effects.gen_range(0.0..2.0 * PI);
+        let speed = gs
+            .rng
+            .effects
+            .gen_range(cvars.g_vehicle_debris_speed_min..=cvars.g_vehicle_debris_speed_max);
+        // The wreck is destroyed so its debris can't hit it.
+        let projectile = Projectile {
+            weapon: Weapon::Mg,
+            pos,
+            vel: angle.to_vec2f() * speed,
+            angle,
+            turn_rate: 0.0,
+            explode_time: gs.game_time + cvars.g_vehicle_debris_time,
+            spawn_time: gs.game_time,
+            owner,
+            lock: None,
+            retarget_time: 0.0,
+            altitude: None,
+        };
+        let handle = gs.projectiles.insert(projectile);
+        gs.debris.insert(handle, attacker);
+        trace::record(cvars, &mut gs.trace, || {
+            TraceEvent::Spawn(EntitySummary::projectile(handle, &gs.projectiles[handle]))
+        });
+    }
+
+    let expl_damage = fixed::to_milli(cvars.g_vehicle_explosion_damage);
+    if expl_damage > 0 && cvars.g_vehicle_explosion_radius > 0.0 {
+        explosion_damage(
+            cvars,
+            gs,
+            map,
+            attacker,
+            pos,
+            expl_damage,
+            expl_damage,
+            cvars.g_vehicle_explosion_radius,
+            None,
+            cvars.g_vehicle_explosion_damage_type,
+            Some(vehicle_handle),
+        );
+    }
+}
+
+/// Turn, then move each vehicle, bouncing off walls.
+///
+/// Both steps test the would-be `Obb` and only commit it if it's not blocked.
+/// The cached `Vehicle::obb` is refreshed right after each commit
+/// so the movement step and later systems see the turned and moved box.
 pub fn vehicle_movement(cvars: &Cvars, gs: &mut GameState, map: &Map) {
-    for (_, vehicle) in gs.vehicles.iter_mut() {
-        let stats = cvars.g_vehicle_movement_stats(vehicle.veh_type);
+    let frame_num = gs.frame_num;
+    for (vehicle_handle, vehicle) in gs.vehicles.iter_mut() {
+        vehicle.displacement = Vec2f::zero();
+        // Dummies are moved by `sys_range`.
+        if vehicle.is_dummy {
+            continue;
+        }
+
+        // Surfaces like water or snow slow vehicles down.
+        // Hovercraft also handle differently over water, blended so crossing a shoreline is smooth.
+        let surface = map.surface_at_pos(*vehicle.pos);
+        let target = if surface.kind == Kind::Water {
+            1.0
+        } else {
+            0.0
+        };
+        vehicle.water_blend = if cvars.g_vehicle_surface_blend_time > 0.0 {
+            let step = gs.dt / cvars.g_vehicle_surface_blend_time;
+            vehicle.water_blend + (target - vehicle.water_blend).clamped(-step, step)
+        } else {
+            target
+        };
+        let mut stats = movement_stats(cvars, vehicle);
+        let speed = f64::from(surface.speed);
+        stats.accel_forward *= speed;
+        stats.accel_backward *= speed;
+        stats.speed_max *= speed;
+
+        let boost = vehicle.speed_multiplier(cvars, gs.game_time);
+        stats.accel_forward *= boost;
+        stats.speed_max *= boost;
+
+        let is_blocked = blocker(map, vehicle, stats.water_passable);
 
         // No movement after death or when guiding
         let input = if vehicle.destroyed() {
@@ -157,31 +318,169 @@ pub fn vehicle_movement(cvars: &Cvars, gs: &mut GameState, map: &Map) {
             gs.dt,
         );
 
-        if vehicle
-            .hitbox
-            .corners(vehicle.pos, new_angle)
-            .iter()
-            .any(|&corner| map.is_wall(corner))
-        {
-            vehicle.turn_rate *= -0.5;
-        } else {
-            vehicle.angle = new_angle;
+        let new_obb = Obb::new(vehicle.hitbox, *vehicle.pos, new_angle);
+        if let Some(corner) = is_blocked(&new_obb) {
+            // Bouncing makes the heading oscillate while rubbing a wall.
+            if cvars.g_vehicle_wall_bounce {
+                vehicle.turn_rate *= -0.5;
+            } else {
+                vehicle.turn_rate = 0.0;
+            }
+            let entry = CollisionEntry {
+                frame_num,
+                entity: TraceEntity::Vehicle(vehicle_handle),
+                from: *vehicle.pos,
+                to: *vehicle.pos,
+                from_angle: *vehicle.angle,
+                to_angle: new_angle,
+                corner: Some(corner),
+                contact: new_obb.corners[corner],
+                resolution: Resolution::TurnBounce,
+            };
+            collision_debug::trace(cvars, &mut gs.collision_trace, entry);
+        } else if new_angle != *vehicle.angle {
+            vehicle.angle.set(new_angle, frame_num);
+            vehicle.obb = new_obb;
         }
 
-        accel_decel(&stats, &mut vehicle.vel, &mut vehicle.angle, input, gs.dt);
+        accel_decel(&stats, &mut vehicle.vel, *vehicle.angle, input, gs.dt);
 
-        let new_pos = vehicle.pos + vehicle.vel * gs.dt;
-        if vehicle
-            .hitbox
-            .corners(new_pos, vehicle.angle)
-            .iter()
-            .any(|&corner| map.is_wall(corner))
-        {
-            // LATER map edge in original RW absorbs the impact - there's no bounce
-            vehicle.vel *= -0.5;
+        // Only the corners are checked so move in substeps shorter than a tile,
+        // otherwise a fast vehicle could end up on the other side of a thin wall.
+        // When a substep hits a wall, try to slide along it - walls are tiles so only one axis is blocked
+        // unless it's a corner. Only the velocity going into the wall stops (or bounces),
+        // otherwise barely touching a wall would stop the vehicle.
+        // The current only moves the vehicle, it doesn't become part of its velocity.
+        let current = if surface.kind == Kind::Water {
+            Vec2f::new(cvars.g_water_current_x, cvars.g_water_current_y)
         } else {
-            vehicle.pos = new_pos;
+            Vec2f::zero()
+        };
+        let displacement = (vehicle.vel + current) * gs.dt;
+        let steps = (displacement.magnitude() / cvars.g_vehicle_max_step)
+            .ceil()
+            .max(1.0);
+        let step = displacement / steps;
+        let mut pos = *vehicle.pos;
+        let mut obb = vehicle.obb;
+        let mut blocked_x = false;
+        let mut blocked_y = false;
+        let mut collision = None;
+        for _ in 0..steps as usize {
+            let mut step = step;
+            if blocked_x {
+                step.x = 0.0;
+            }
+            if blocked_y {
+                step.y = 0.0;
+            }
+            if step == Vec2f::zero() {
+                break;
+            }
+
+            let new_pos = pos + step;
+            let new_obb = Obb::new(vehicle.hitbox, new_pos, *vehicle.angle);
+            let corner = match is_blocked(&new_obb) {
+                Some(corner) => corner,
+                None => {
+                    pos = new_pos;
+                    obb = new_obb;
+                    continue;
+                }
+            };
+            if collision.is_none() {
+                collision = Some((pos, new_pos, corner, new_obb.corners[corner]));
+            }
+
+            let free = |pos| {
+                let obb = Obb::new(vehicle.hitbox, pos, *vehicle.angle);
+                is_blocked(&obb).is_none()
+            };
+            let slide_x = pos + Vec2f::new(step.x, 0.0);
+            let slide_y = pos + Vec2f::new(0.0, step.y);
+            if step.x != 0.0 && free(slide_x) {
+                blocked_y = true;
+                pos = slide_x;
+            } else if step.y != 0.0 && free(slide_y) {
+                blocked_x = true;
+                pos = slide_y;
+            } else {
+                blocked_x = true;
+                blocked_y = true;
+            }
+            obb = Obb::new(vehicle.hitbox, pos, *vehicle.angle);
+        }
+
+        // LATER map edge in original RW absorbs the impact - there's no bounce
+        let (into_wall, along_wall) = if cvars.g_vehicle_wall_bounce {
+            (-0.5, 1.0)
+        } else {
+            (0.0, (1.0 - cvars.g_vehicle_wall_friction * gs.dt).max(0.0))
+        };
+        if blocked_x {
+            vehicle.vel.x *= into_wall;
         }
+        if blocked_y {
+            vehicle.vel.y *= into_wall;
+        }
+        if blocked_x != blocked_y {
+            vehicle.vel *= along_wall;
+        }
+        if let Some((from, to, corner, contact)) = collision {
+            gs.scraping.push(vehicle_handle);
+            trace::record(cvars, &mut gs.trace, || TraceEvent::Blocked {
+                vehicle: vehicle_handle,
+                from,
+                to,
+                pos,
+            });
+            let resolution = if blocked_x && blocked_y {
+                Resolution::MoveBounce
+            } else {
+                Resolution::MoveSlide
+            };
+            let entry = CollisionEntry {
+                frame_num,
+                entity: TraceEntity::Vehicle(vehicle_handle),
+                from,
+                to,
+                from_angle: *vehicle.angle,
+                to_angle: *vehicle.angle,
+                corner: Some(corner),
+                contact,
+                resolution,
+            };
+            collision_debug::trace(cvars, &mut gs.collision_trace, entry);
+        }
+        if pos != *vehicle.pos {
+            vehicle.displacement = pos - *vehicle.pos;
+            vehicle.pos.set(pos, frame_num);
+            vehicle.obb = obb;
+        }
+    }
+}
+
+fn is_water(map: &Map, pos: Vec2f) -> bool {
+    map.surface_at_pos(pos).kind == Kind::Water
+}
+
+/// The first corner of the vehicle's box which is blocked - by walls and, unless `water_passable`, water.
+///
+/// If the vehicle somehow ended up in water it can't cross (e.g. spawned there),
+/// it's allowed to drive out instead of getting stuck. That's when its center or most of its corners are in water.
+/// With fewer (e.g. pushed by another vehicle), the corners already in water can stay there but no others can enter it.
+pub fn blocker<'a>(
+    map: &'a Map,
+    vehicle: &Vehicle,
+    water_passable: bool,
+) -> impl Fn(&Obb) -> Option<usize> + 'a {
+    let wet = vehicle.obb.corners.map(|corner| is_water(map, corner));
+    let in_water = is_water(map, *vehicle.pos) || wet.iter().filter(|&&wet| wet).count() > 2;
+    let water_passable = water_passable || in_water;
+    move |obb| {
+        obb.corners.iter().zip(wet).position(|(&corner, wet)| {
+            map.is_wall(corner) || (!water_passable && !wet && is_water(map, corner))
+        })
     }
 }
 
@@ -229,34 +528,101 @@ fn turning(
     (angle + turn).rem_euclid(2.0 * PI)
 }
 
-fn accel_decel(stats: &MovementStats, vel: &mut Vec2f, angle: &mut f64, input: Input, dt: f64) {
+/// The vehicle's stats between land and water according to `Vehicle::water_blend`,
+/// before the surface's speed and powerups.
+pub fn movement_stats(cvars: &Cvars, vehicle: &Vehicle) -> MovementStats {
+    let land = cvars.g_vehicle_movement_stats(vehicle.veh_type, Kind::Normal);
+    let water = cvars.g_vehicle_movement_stats(vehicle.veh_type, Kind::Water);
+    land.lerp(&water, vehicle.water_blend)
+}
+
+fn accel_decel(stats: &MovementStats, vel: &mut Vec2f, angle: f64, input: Input, dt: f64) {
+    let forward = angle.to_vec2f();
     let vel_change = (input.up() * stats.accel_forward - input.down() * stats.accel_backward) * dt;
-    *vel += angle.to_vec2f() * vel_change;
+    *vel += forward * vel_change;
 
-    // Friction's constant component - always the same no matter the speed
-    let vel_fric_const = stats.friction_const * dt;
-    let vel_norm = vel.try_normalized().unwrap_or_default();
-    *vel -= (vel_fric_const).min(vel.magnitude()) * vel_norm;
+    // Friction is applied separately along the heading and sideways
+    // so tracks and wheels can grip while hovercraft keep drifting.
+    let sideways = Vec2f::new(-forward.y, forward.x);
+    let speed_forward = friction(
+        vel.dot(forward),
+        stats.friction_const,
+        stats.friction_linear,
+        dt,
+    );
+    let speed_sideways = friction(
+        vel.dot(sideways),
+        stats.lateral_friction_const,
+        stats.lateral_friction_linear,
+        dt,
+    );
+    *vel = forward * speed_forward + sideways * speed_sideways;
 
-    // Friction's linear component - increases with speed
-    *vel *= (1.0 - stats.friction_linear).powf(dt);
     if vel.magnitude_squared() > stats.speed_max.powi(2) {
-        *vel = vel_norm * stats.speed_max;
+        *vel = vel.normalized() * stats.speed_max;
     }
 }
 
-pub fn player_logic(gs: &mut GameState) {
+/// Slow down one component of velocity, never past zero.
+fn friction(speed: f64, friction_const: f64, friction_linear: f64, dt: f64) -> f64 {
+    // Friction's constant component - always the same no matter the speed
+    let speed = speed.signum() * (speed.abs() - friction_const * dt).max(0.0);
+
+    // Friction's linear component - increases with speed
+    speed * (1.0 - friction_linear).powf(dt)
+}
+
+/// The weapon is in the player's loadout and allowed in this match.
+/// Whether the player can switch to the weapon - it has to be in their loadout,
+/// allowed on the server and carried by their vehicle type (if they have a vehicle).
+fn usable_weapon(
+    cvars: &Cvars,
+    player: &Player,
+    veh_type: Option<VehicleType>,
+    weapon: Weapon,
+) -> bool {
+    player.loadout.weapons.contains(&weapon)
+        && cvars.g_weapon_allowed(weapon)
+        && veh_type.map_or(true, |veh_type| {
+            cvars.g_vehicle_weapons(veh_type).contains(&weapon)
+        })
+}
+
+pub fn player_logic(cvars: &Cvars, gs: &mut GameState) {
     for (player_handle, player) in gs.players.iter_mut() {
         let input_prev = gs.inputs_prev.get(player_handle);
+        let veh_type = player.vehicle.map(|handle| gs.vehicles[handle].veh_type);
+
+        // Direct selection, ignored if the player can't use the weapon.
+        if let Some(weapon) = player.input.select_weapon {
+            if input_prev.select_weapon != Some(weapon)
+                && usable_weapon(cvars, player, veh_type, weapon)
+            {
+                player.cur_weapon = weapon;
+            }
+        }
 
-        // Change weapon
+        // Change weapon, skipping those the player can't use or which are out of ammo.
+        // If there are none, the current weapon stays.
+        let mut step = 0;
         if !input_prev.prev_weapon && player.input.prev_weapon {
-            let prev = (player.cur_weapon as u8 + WEAPS_CNT - 1) % WEAPS_CNT;
-            player.cur_weapon = Weapon::n(prev).unwrap();
+            step += WEAPS_CNT - 1;
         }
         if !input_prev.next_weapon && player.input.next_weapon {
-            let next = (player.cur_weapon as u8 + 1) % WEAPS_CNT;
-            player.cur_weapon = Weapon::n(next).unwrap();
+            step += 1;
+        }
+        if step % WEAPS_CNT != 0 {
+            let mut weapon = player.cur_weapon;
+            for _ in 0..WEAPS_CNT {
+                weapon = Weapon::n((weapon as u8 + step) % WEAPS_CNT).unwrap();
+                let empty = player
+                    .vehicle
+                    .map_or(false, |handle| gs.vehicles[handle].weapon_empty(weapon));
+                if usable_weapon(cvars, player, veh_type, weapon) && !empty {
+                    player.cur_weapon = weapon;
+                    break;
+                }
+            }
         }
     }
 }
@@ -270,157 +636,384 @@ pub fn vehicle_logic(cvars: &Cvars, gs: &mut GameState) {
         let input_prev = gs.inputs_prev.get(vehicle.owner);
 
         // Turret turning
-        if !input_prev.turret_left && player.input.turret_left {
-            vehicle.turret_angle_wanted -= cvars.g_turret_turn_step_angle_deg.to_radians();
-        }
-        if !input_prev.turret_right && player.input.turret_right {
-            vehicle.turret_angle_wanted += cvars.g_turret_turn_step_angle_deg.to_radians();
+        let mut step = 0.0;
+        if let Some(target) = player.input.turret_target_angle() {
+            // The target is absolute, turret angles are relative to the chassis.
+            vehicle.turret_angle_wanted = target - *vehicle.angle;
+        } else {
+            if !input_prev.turret_left && player.input.turret_left {
+                step -= cvars.g_turret_turn_step_angle_deg.to_radians();
+            }
+            if !input_prev.turret_right && player.input.turret_right {
+                step += cvars.g_turret_turn_step_angle_deg.to_radians();
+            }
         }
-        vehicle.turret_angle_wanted = vehicle.turret_angle_wanted.rem_euclid(2.0 * PI);
 
-        let delta = vehicle
-            .turret_angle_current
-            .delta_angle(vehicle.turret_angle_wanted);
+        let delta = if let Some((min, max)) = cvars.g_vehicle_turret_limits(vehicle.veh_type) {
+            // Can't take the shortest path if it crosses the forbidden arc.
+            // The allowed range doesn't include PI so signed angles are continuous within it.
+            // Stepping happens before wrapping so stepping past a limit stops at it.
+            let signed = |angle: f64| (angle + PI).rem_euclid(2.0 * PI) - PI;
+            let wanted = (signed(vehicle.turret_angle_wanted) + step).clamped(min, max);
+            vehicle.turret_angle_wanted = wanted.rem_euclid(2.0 * PI);
+            wanted - signed(vehicle.turret_angle_current)
+        } else {
+            vehicle.turret_angle_wanted = (vehicle.turret_angle_wanted + step).rem_euclid(2.0 * PI);
+            vehicle
+                .turret_angle_current
+                .delta_angle(vehicle.turret_angle_wanted)
+        };
         let change = cvars.g_turret_turn_speed_deg.to_radians() * gs.dt * delta.signum();
         let change_clamped = change.clamped(-delta.abs(), delta.abs());
         vehicle.turret_angle_current += change_clamped;
         vehicle.turret_angle_current = vehicle.turret_angle_current.rem_euclid(2.0 * PI);
 
+        // Cooling down
+        // All weapons, not just the current one, otherwise switching would be a way to cool faster.
+        vehicle.dissipate_heat(cvars, gs.dt);
+
         // Reloading
-        let ammo = &mut vehicle.ammos[player.cur_weapon as usize];
-        if let Ammo::Reloading(_, end) = ammo {
-            if gs.game_time >= *end {
-                *ammo = Ammo::Loaded(gs.game_time, cvars.g_weapon_reload_ammo(player.cur_weapon));
+        // An empty weapon starts reloading once it gets reserve ammo from a pickup.
+        vehicle.start_reload(cvars, player.cur_weapon, gs.game_time);
+        if let Ammo::Reloading(_, end) = vehicle.ammos[player.cur_weapon as usize] {
+            if gs.game_time >= end {
+                vehicle.finish_reload(cvars, player.cur_weapon, gs.game_time);
+                let kind = AudioEventKind::ReloadFinished(player.cur_weapon);
+                sys_sound::push_audio_event(cvars, &mut gs.audio_events, kind, *vehicle.pos);
+            }
+        }
+    }
+}
+
+/// Detonate guided missiles when their owner presses fire again.
+pub fn gm_detonation(cvars: &Cvars, gs: &mut GameState, map: &mut Map) {
+    if !cvars.g_guided_missile_detonate_on_fire {
+        return;
+    }
+
+    for player_handle in gs.players.iter_handles() {
+        let player = &gs.players[player_handle];
+        let input_prev = gs.inputs_prev.get(player_handle);
+        if let Some(gm_handle) = player.guided_missile {
+            // Only on press, the fire key is usually still held from launching the missile.
+            if !input_prev.fire && player.input.fire {
+                let pos = gs.projectiles[gm_handle].pos;
+                projectile_impact(cvars, gs, map, gm_handle, pos);
             }
         }
     }
 }
 
 pub fn shooting(cvars: &Cvars, gs: &mut GameState) {
-    for (_, vehicle) in gs.vehicles.iter_mut() {
+    for (vehicle_handle, vehicle) in gs.vehicles.iter_mut() {
         let player = &mut gs.players[vehicle.owner];
-        // Note: vehicles can shoot while controlling a missile
+        // Letting go of fire or switching weapons cancels the railgun charge.
+        if vehicle.destroyed() || !player.input.fire || player.cur_weapon != Weapon::Rail {
+            vehicle.rail_charge_start = None;
+        }
         if vehicle.destroyed() || !player.input.fire {
             continue;
         }
+        // Fire is used to detonate the missile so don't shoot while guiding.
+        // Otherwise vehicles can shoot while controlling a missile like in the original.
+        if cvars.g_guided_missile_detonate_on_fire && player.guided_missile.is_some() {
+            continue;
+        }
 
-        let ammo = &mut vehicle.ammos[player.cur_weapon as usize];
-        if let Ammo::Loaded(ready_time, count) = ammo {
-            if gs.game_time < *ready_time {
-                continue;
+        // Fire every shot that became due since the last frame, not just one,
+        // otherwise weapons which refire faster than the frame rate would shoot slower at low FPS.
+        loop {
+            // One guided missile per player - don't even use ammo while one is flying.
+            if player.cur_weapon == Weapon::Gm && player.guided_missile.is_some() {
+                break;
             }
-
-            *ready_time = gs.game_time + cvars.g_weapon_refire(player.cur_weapon);
-            *count -= 1;
-            if *count == 0 {
-                let reload_time = cvars.g_weapon_reload_time(player.cur_weapon);
-                *ammo = Ammo::Reloading(gs.game_time, gs.game_time + reload_time);
+            let limits_ready = vehicle.fire_limits_ready(cvars, player.cur_weapon);
+            let refire = cvars.g_weapon_refire(player.cur_weapon)
+                * vehicle.refire_multiplier(cvars, gs.game_time);
+            let ready_time = match vehicle.ammos[player.cur_weapon as usize] {
+                Ammo::Loaded(ready_time, count) if count > 0 => ready_time,
+                // Heat doesn't stop the shot which overheats it, only the ones after it.
+                Ammo::Heat(ready_time, _, locked_until) => ready_time.max(locked_until),
+                _ => break,
+            };
+            if gs.game_time < ready_time {
+                break;
             }
-
-            let (hardpoint, weapon_offset) = cvars.g_hardpoint(vehicle.veh_type, player.cur_weapon);
-            let (shot_angle, shot_origin);
-            match hardpoint {
-                Hardpoint::Chassis => {
-                    shot_angle = vehicle.angle;
-                    shot_origin = vehicle.pos + weapon_offset.rotated_z(shot_angle);
-                }
-                Hardpoint::Turret => {
-                    shot_angle = vehicle.angle + vehicle.turret_angle_current;
-                    let turret_offset = cvars.g_vehicle_turret_offset_chassis(vehicle.veh_type);
-                    shot_origin = vehicle.pos
-                        + turret_offset.rotated_z(vehicle.angle)
-                        + weapon_offset.rotated_z(shot_angle);
-                }
+            // A weapon which has been ready for a while doesn't get to catch up
+            // on the shots it could have fired before this frame.
+            let mut shot_time = ready_time.max(gs.game_time_prev);
+            // The cooldown group and fire rate cap only delay the shot,
+            // it stays pending (without using ammo) and fires as soon as they allow.
+            // LATER Fire buffering and weapon switch time should keep it across switches.
+            shot_time = shot_time.max(limits_ready);
+            if shot_time > gs.game_time {
+                break;
             }
-
-            // Some sane defaults to be overriden later
-            let mut projectile = Projectile {
-                weapon: Weapon::Mg,
-                pos: shot_origin,
-                vel: Vec2f::zero(),
-                angle: shot_angle,
-                turn_rate: 0.0,
-                explode_time: f64::MAX,
-                owner: vehicle.owner,
-            };
-
-            match player.cur_weapon {
-                Weapon::Mg => {
-                    let r: f64 = gs.rng.sample(StandardNormal);
-                    let spread = cvars.g_machine_gun_angle_spread * r;
-                    // Using spread as shot_vel.y would mean the resulting spread depends on speed
-                    // so it's better to use spread on angle.
-                    projectile.vel = Vec2f::new(cvars.g_machine_gun_speed, 0.0)
-                        .rotated_z(shot_angle + spread)
-                        + cvars.g_machine_gun_vehicle_velocity_factor * vehicle.vel;
-                    gs.projectiles.insert(projectile);
-                }
-                Weapon::Rail => {
-                    projectile.weapon = Weapon::Rail;
-                    projectile.vel = Vec2f::new(cvars.g_railgun_speed, 0.0).rotated_z(shot_angle)
-                        + cvars.g_railgun_vehicle_velocity_factor * vehicle.vel;
-                    gs.projectiles.insert(projectile);
+            if player.cur_weapon == Weapon::Rail && cvars.g_railgun_charge_time > 0.0 {
+                // Nearby enemies see the charge (see `sys_threats`) so they can react.
+                let charge_start = *vehicle.rail_charge_start.get_or_insert(shot_time);
+                let charged = charge_start + cvars.g_railgun_charge_time;
+                if gs.game_time < charged {
+                    break;
                 }
-                Weapon::Cb => {
-                    projectile.weapon = Weapon::Cb;
-                    for _ in 0..cvars.g_cluster_bomb_count {
-                        let speed = cvars.g_cluster_bomb_speed;
-                        let spread_forward;
-                        let spread_sideways;
-                        if cvars.g_cluster_bomb_speed_spread_gaussian {
-                            // Broken type inference (works with rand crate but distributions are deprecated).
-                            let r: f64 = gs.rng.sample(StandardNormal);
-                            spread_forward = cvars.g_cluster_bomb_speed_spread_forward * r;
-                            let r: f64 = gs.rng.sample(StandardNormal);
-                            spread_sideways = cvars.g_cluster_bomb_speed_spread_sideways * r;
-                        } else {
-                            let r = gs.rng.sample(gs.range_uniform11);
-                            spread_forward = cvars.g_cluster_bomb_speed_spread_forward * r;
-                            let r = gs.rng.sample(gs.range_uniform11);
-                            spread_sideways = cvars.g_cluster_bomb_speed_spread_sideways * r;
-                        }
-                        projectile.vel = Vec2f::new(speed + spread_forward, spread_sideways)
-                            .rotated_z(shot_angle)
-                            + cvars.g_cluster_bomb_vehicle_velocity_factor * vehicle.vel;
-                        projectile.explode_time = gs.game_time
-                            + cvars.g_cluster_bomb_time
-                            + gs.rng.sample(gs.range_uniform11) * cvars.g_cluster_bomb_time_spread;
-                        gs.projectiles.insert(projectile.clone());
+                vehicle.rail_charge_start = None;
+                shot_time = charged;
+            }
+            match &mut vehicle.ammos[player.cur_weapon as usize] {
+                Ammo::Loaded(ready_time, count) => {
+                    *ready_time = shot_time + refire;
+                    *count -= 1;
+                    if *count == 0 {
+                        // Stays empty if there's no reserve.
+                        vehicle.start_reload(cvars, player.cur_weapon, shot_time);
                     }
                 }
-                Weapon::Rockets => {
-                    projectile.weapon = Weapon::Rockets;
-                    projectile.vel = Vec2f::new(cvars.g_rockets_speed, 0.0).rotated_z(shot_angle)
-                        + cvars.g_rockets_vehicle_velocity_factor * vehicle.vel;
-                    gs.projectiles.insert(projectile);
-                }
-                Weapon::Hm => {
-                    projectile.weapon = Weapon::Hm;
-                    projectile.vel = Vec2f::new(cvars.g_homing_missile_speed_initial, 0.0)
-                        .rotated_z(shot_angle)
-                        + cvars.g_homing_missile_vehicle_velocity_factor * vehicle.vel;
-                    gs.projectiles.insert(projectile);
+                Ammo::Heat(ready_time, heat, locked_until) => {
+                    *ready_time = shot_time + refire;
+                    *heat += cvars.g_weapon_heat_per_shot(player.cur_weapon);
+                    // Tolerance so per shot heat like 0.1 overheats after 10 shots despite rounding.
+                    if *heat >= 1.0 - 1e-9 {
+                        // Locked out until it dissipates down to `g_weapon_heat_unlock`.
+                        let dissipation = cvars.g_weapon_heat_dissipation(player.cur_weapon);
+                        *locked_until =
+                            shot_time + (*heat - cvars.g_weapon_heat_unlock) / dissipation;
+                    }
                 }
-                Weapon::Gm => {
-                    projectile.weapon = Weapon::Gm;
-                    projectile.vel = Vec2f::new(cvars.g_guided_missile_speed_initial, 0.0)
-                        .rotated_z(shot_angle)
-                        + cvars.g_guided_missile_vehicle_velocity_factor * vehicle.vel;
-                    // TODO angle (maybe also HM)
-                    let handle = gs.projectiles.insert(projectile);
-                    player.guided_missile = Some(handle);
+                Ammo::Reloading(_, _) => unreachable!(),
+            }
+            vehicle.fire_limits_shot(cvars, player.cur_weapon, shot_time);
+            gs.life_records.shot(vehicle.owner, player.cur_weapon);
+
+            // Shooting happens after movement so the vehicle is where it is at the end of this frame.
+            // Move it back along the path it actually took (walls, currents, acceleration)
+            // to where it was at the time of this shot, otherwise fast vehicles shoot from behind themselves.
+            let shot_age = shot_time - gs.game_time_prev;
+            let vehicle_pos = shot_pos(vehicle, shot_age, gs.dt);
+
+            let transforms = shot_transforms(
+                cvars,
+                vehicle,
+                player.cur_weapon,
+                vehicle_pos,
+                *vehicle.angle,
+            );
+
+            // Bots are less accurate than their turret so they're not too hard to beat.
+            // Only use the RNG if needed so setting aim error to 0 doesn't change anything else.
+            // All barrels are off by the same angle.
+            let aim_error = gs
+                .ais
+                .iter()
+                .find(|(_, ai)| ai.player == vehicle.owner)
+                .map_or(0.0, |(_, ai)| ai.aim_error);
+            let mut aim_offset = 0.0;
+            if aim_error > 0.0 {
+                let r: f64 = gs.rng.ai.sample(StandardNormal);
+                aim_offset = aim_error * r;
+            }
+
+            let kind = AudioEventKind::WeaponFired(player.cur_weapon);
+            sys_sound::push_audio_event(cvars, &mut gs.audio_events, kind, transforms[0].0);
+
+            // Each barrel fires a full shot, they share the ammo and refire.
+            let weapon = player.cur_weapon;
+            let params = cvars.g_projectile_params(weapon);
+            let mut handle = None;
+            for (shot_origin, shot_angle) in transforms {
+                let shot_angle = shot_angle + aim_offset;
+                if cvars.cl_muzzle_flash || cvars.cl_recoil {
+                    // Same as `spawn_effect` but `gs.vehicles` is borrowed.
+                    gs.effects.push(Effect::MuzzleFlash {
+                        pos: shot_origin,
+                        angle: shot_angle,
+                        weapon,
+                        vehicle: vehicle_handle,
+                        start_time: shot_time,
+                    });
                 }
-                Weapon::Bfg => {
-                    projectile.weapon = Weapon::Bfg;
-                    projectile.vel = Vec2f::new(cvars.g_bfg_speed, 0.0).rotated_z(shot_angle)
-                        + cvars.g_bfg_vehicle_velocity_factor * vehicle.vel;
-                    gs.projectiles.insert(projectile);
+                for _ in 0..params.count {
+                    let (vel, explode_time) = spread_shot(
+                        cvars,
+                        &params,
+                        &mut gs.rng,
+                        gs.range_uniform11,
+                        shot_angle,
+                        vehicle.vel,
+                        shot_time,
+                    );
+                    let projectile = Projectile {
+                        weapon,
+                        pos: shot_origin,
+                        vel,
+                        angle: shot_angle,
+                        turn_rate: 0.0,
+                        explode_time,
+                        spawn_time: shot_time,
+                        owner: vehicle.owner,
+                        lock: None,
+                        retarget_time: 0.0,
+                        altitude: launch_altitude(cvars, weapon),
+                    };
+                    handle = Some(insert_fired(
+                        cvars,
+                        &mut gs.projectiles,
+                        &mut gs.shots,
+                        &mut gs.trace,
+                        projectile,
+                        shot_age,
+                    ));
+                    player.score.weapons[weapon as usize].shots += 1;
                 }
             }
+            // TODO angle (maybe also HM)
+            if weapon == Weapon::Gm {
+                player.guided_missile = handle;
+            }
         }
     }
 }
 
+/// Where shots of `weapon` start and which way they go when the vehicle's chassis is at `pos` and `angle`,
+/// one for each barrel (see `Cvars::g_hardpoints`).
+///
+/// Shared by `shooting` and the client's aim preview so they can't disagree.
+/// Doesn't include weapon spread or bots' aim error.
+pub fn shot_transforms(
+    cvars: &Cvars,
+    vehicle: &Vehicle,
+    weapon: Weapon,
+    pos: Vec2f,
+    angle: f64,
+) -> Vec<(Vec2f, f64)> {
+    let hardpoints = cvars.g_hardpoints(vehicle.veh_type, weapon);
+    hardpoints
+        .barrels()
+        .iter()
+        .map(|&(hardpoint, weapon_offset)| match hardpoint {
+            Hardpoint::Chassis => (pos + weapon_offset.rotated_z(angle), angle),
+            Hardpoint::Turret => {
+                let shot_angle = angle + vehicle.turret_angle_current;
+                let turret_offset = cvars.g_vehicle_turret_offset_chassis(vehicle.veh_type);
+                let shot_origin =
+                    pos + turret_offset.rotated_z(angle) + weapon_offset.rotated_z(shot_angle);
+                (shot_origin, shot_angle)
+            }
+        })
+        .collect()
+}
+
+/// Where the vehicle was `shot_age` seconds after the start of this frame, see `Vehicle::displacement`.
+///
+/// Shots fired at the end of the frame (and previews like the crosshair, which are for now)
+/// start at its current position.
+fn shot_pos(vehicle: &Vehicle, shot_age: f64, dt: f64) -> Vec2f {
+    let fraction = if dt > 0.0 { shot_age / dt } else { 1.0 };
+    *vehicle.pos - vehicle.displacement * (1.0 - fraction)
+}
+
+/// Initial velocity of a shot fired towards `angle` from a vehicle moving at `vehicle_vel`, without spread.
+pub fn shot_velocity(cvars: &Cvars, weapon: Weapon, angle: f64, vehicle_vel: Vec2f) -> Vec2f {
+    let params = cvars.g_projectile_params(weapon);
+    projectile_velocity(&params, angle, 0.0, 0.0, vehicle_vel)
+}
+
+/// Velocity of one projectile of a shot towards `angle` including the sampled speed spread.
+fn projectile_velocity(
+    params: &ProjectileSpawnParams,
+    angle: f64,
+    forward: f64,
+    sideways: f64,
+    vehicle_vel: Vec2f,
+) -> Vec2f {
+    Vec2f::new(params.speed + forward, sideways).rotated_z(angle)
+        + params.velocity_factor * vehicle_vel
+}
+
+/// Sample the spread of one projectile of a shot fired at `shot_time`, returns its velocity and explode time.
+///
+/// Only uses the RNG for spreads which aren't 0 so disabling one doesn't change the others.
+fn spread_shot(
+    cvars: &Cvars,
+    params: &ProjectileSpawnParams,
+    rng: &mut GameRng,
+    range_uniform11: Uniform<f64>,
+    angle: f64,
+    vehicle_vel: Vec2f,
+    shot_time: f64,
+) -> (Vec2f, f64) {
+    let mut spread = |amount: f64| {
+        if amount == 0.0 {
+            0.0
+        } else if cvars.g_weapon_spread_gaussian {
+            let r: f64 = rng.weapon_spread.sample(StandardNormal);
+            amount * r
+        } else {
+            amount * rng.weapon_spread.sample(range_uniform11)
+        }
+    };
+    // Using spread on the angle instead of sideways speed means the resulting spread doesn't depend on speed.
+    let angle = angle + spread(params.angular_spread);
+    let forward = spread(params.forward_spread);
+    let sideways = spread(params.sideways_spread);
+    let vel = projectile_velocity(params, angle, forward, sideways, vehicle_vel);
+
+    let mut explode_time = f64::MAX;
+    if params.fuse.is_finite() {
+        explode_time = shot_time + params.fuse;
+        if params.fuse_spread != 0.0 {
+            explode_time += rng.cb_timing.sample(range_uniform11) * params.fuse_spread;
+        }
+    }
+    (vel, explode_time)
+}
+
+/// Insert a projectile fired `shot_age` seconds after the start of this frame.
+///
+/// It's moved back along its path so that after this frame's movement it ends up
+/// as far from the muzzle as it would have flown since it was fired.
+/// Shots from one burst then form an evenly spaced stream instead of bunching up.
+/// Weapons with `g_weapon_arc_gravity` start on the ground, flying up.
+pub fn launch_altitude(cvars: &Cvars, weapon: Weapon) -> Option<Altitude> {
+    if cvars.g_weapon_arc_gravity(weapon) > 0.0 {
+        Some(Altitude {
+            z: 0.0,
+            vz: cvars.g_weapon_arc_speed(weapon),
+        })
+    } else {
+        None
+    }
+}
+
+/// Move a projectile flying in an arc vertically, returns whether it landed.
+/// Integrated the same way as horizontal movement.
+pub fn fall(cvars: &Cvars, weapon: Weapon, altitude: &mut Altitude, dt: f64) -> bool {
+    altitude.vz -= cvars.g_weapon_arc_gravity(weapon) * dt;
+    altitude.z += altitude.vz * dt;
+    if altitude.z <= 0.0 {
+        altitude.z = 0.0;
+        true
+    } else {
+        false
+    }
+}
+
+fn insert_fired(
+    cvars: &Cvars,
+    projectiles: &mut Arena<Projectile>,
+    shots: &mut Vec<Index>,
+    trace: &mut Trace,
+    mut projectile: Projectile,
+    shot_age: f64,
+) -> Index {
+    projectile.pos -= projectile.vel * shot_age;
+    let handle = projectiles.insert(projectile);
+    shots.push(handle);
+    trace::record(cvars, trace, || {
+        TraceEvent::Spawn(EntitySummary::projectile(handle, &projectiles[handle]))
+    });
+    handle
+}
+
 /// The *guided* part of guided missile
 pub fn gm_turning(cvars: &Cvars, gs: &mut GameState) {
     for (gm_handle, gm) in gs
@@ -447,25 +1040,188 @@ pub fn gm_turning(cvars: &Cvars, gs: &mut GameState) {
             gs.dt,
         );
 
-        accel_decel(&stats, &mut gm.vel, &mut gm.angle, input, gs.dt);
+        accel_decel(&stats, &mut gm.vel, gm.angle, input, gs.dt);
+    }
+}
+
+/// Place the map's ammo crates and repair pads, see `map::load_map`.
+pub fn spawn_map_pickups(cvars: &Cvars, gs: &mut GameState, map: &Map) {
+    for &(index, kind) in map.pickups() {
+        let handle = gs.pickups.insert(Pickup::new(map.tile_center(index), kind));
+        trace::record(cvars, &mut gs.trace, || {
+            TraceEvent::Spawn(EntitySummary::pickup(handle, &gs.pickups[handle]))
+        });
+    }
+}
+
+/// Spawn armor pickups, reactivate ammo crates and powerups, expire powerups on vehicles
+/// and let vehicles collect pickups.
+///
+/// When more vehicles reach a pickup in the same frame, the first in iteration order gets it.
+pub fn pickups(cvars: &Cvars, gs: &mut GameState, map: &Map) {
+    let armor_count = gs
+        .pickups
+        .iter()
+        .filter(|(_, pickup)| pickup.kind == PickupKind::Armor)
+        .count();
+    for _ in armor_count..cvars.g_armor_pickup_count {
+        let (pos, _angle) = map.random_nonwall(&mut gs.rng.world);
+        let handle = gs.pickups.insert(Pickup::new(pos, PickupKind::Armor));
+        trace::record(cvars, &mut gs.trace, || {
+            TraceEvent::Spawn(EntitySummary::pickup(handle, &gs.pickups[handle]))
+        });
+    }
+
+    for (_, pickup) in gs.pickups.iter_mut() {
+        if pickup.respawn.map_or(false, |time| gs.game_time >= time) {
+            pickup.respawn = None;
+        }
+    }
+
+    for (_, vehicle) in gs.vehicles.iter_mut() {
+        vehicle.expire_powerups(gs.game_time);
+        if vehicle.destroyed() {
+            continue;
+        }
+
+        let mut collected = None;
+        let mut repaired = false;
+        for (pickup_handle, pickup) in gs.pickups.iter() {
+            if pickup.respawn.is_some() {
+                continue;
+            }
+            let reach = cvars.g_hitcircle_radius + cvars.g_pickup_radius(pickup.kind);
+            if pickup.pos.distance_squared(*vehicle.pos) > reach * reach {
+                continue;
+            }
+            match pickup.kind {
+                PickupKind::Armor => {
+                    let upgraded = vehicle
+                        .armor
+                        .upgrade(cvars.g_armor_pickup_step, cvars.g_armor_pickup_min);
+                    // Fully upgraded vehicles leave it for others.
+                    if upgraded.is_some() {
+                        gs.life_records
+                            .pickup(cvars, vehicle.owner, gs.game_time, pickup.kind);
+                        collected = Some(pickup_handle);
+                        break;
+                    }
+                }
+                PickupKind::Ammo => {
+                    let weapon = gs.players[vehicle.owner].cur_weapon;
+                    let ammo_max = cvars.g_weapon_reload_ammo(weapon);
+                    let refilled = vehicle.refill_reserve(cvars, weapon);
+                    let ammo = &mut vehicle.ammos[weapon as usize];
+                    // A reload in progress finishes normally, using the refilled reserve.
+                    // Full weapons leave it for others too.
+                    match *ammo {
+                        Ammo::Loaded(ready_time, count) if count < ammo_max => {
+                            *ammo = Ammo::Loaded(ready_time, ammo_max);
+                        }
+                        _ if refilled => {}
+                        _ => continue,
+                    }
+                    gs.life_records
+                        .pickup(cvars, vehicle.owner, gs.game_time, pickup.kind);
+                    collected = Some(pickup_handle);
+                    break;
+                }
+                PickupKind::Powerup(kind) => {
+                    vehicle.add_powerup(cvars, kind, gs.game_time);
+                    gs.life_records
+                        .pickup(cvars, vehicle.owner, gs.game_time, pickup.kind);
+                    collected = Some(pickup_handle);
+                    break;
+                }
+                PickupKind::Repair => {
+                    // Overlapping pads don't add up.
+                    if !repaired {
+                        repaired = true;
+                        let amount = fixed::to_milli(cvars.g_repair_amount * gs.dt);
+                        vehicle.hp = (vehicle.hp + amount).min(vehicle.hp_max(cvars));
+                    }
+                }
+            }
+        }
+        if let Some(pickup_handle) = collected {
+            let pickup = &mut gs.pickups[pickup_handle];
+            let kind = NotificationKind::Pickup {
+                player: vehicle.owner,
+                kind: pickup.kind,
+            };
+            push_notification(cvars, &mut gs.notifications, gs.game_time, kind);
+            match pickup.kind {
+                PickupKind::Armor => {
+                    let pickup = gs.pickups.remove(pickup_handle).unwrap();
+                    trace::record(cvars, &mut gs.trace, || {
+                        TraceEvent::Remove(EntitySummary::pickup(pickup_handle, &pickup))
+                    });
+                }
+                PickupKind::Powerup(_) => {
+                    pickup.respawn = Some(gs.game_time + cvars.g_powerup_respawn_time);
+                }
+                _ => pickup.respawn = Some(gs.game_time + cvars.g_pickup_respawn_time),
+            }
+        }
     }
 }
 
 /// Projectile movement and collisions / hit detection.
 /// Traces the projectile's path between positions to avoid passing through thin objects.
-pub fn projectiles(cvars: &Cvars, gs: &mut GameState, map: &Map) {
+pub fn projectiles(cvars: &Cvars, gs: &mut GameState, map: &mut Map) {
+    // Vehicles don't move during this system so the grid stays valid the whole time.
+    // Vehicles destroyed during it are still in the grid but get skipped below.
+    gs.vehicle_grid.update(
+        cvars.sv_collision_grid_cell_size,
+        map.maxs(),
+        &gs.vehicles,
+        cvars.g_hitcircle_radius,
+        gs.frame_num,
+    );
+
+    let wind = Vec2f::new(cvars.g_wind_x, cvars.g_wind_y);
     for proj_handle in gs.projectiles.iter_handles() {
         let projectile = &mut gs.projectiles[proj_handle];
+        if projectile.weapon.is_airborne() {
+            projectile.vel += wind * gs.dt;
+        }
         let max_new_pos = projectile.pos + projectile.vel * gs.dt;
+        let weapon = projectile.weapon;
+        let landed = match &mut projectile.altitude {
+            Some(altitude) => fall(cvars, weapon, altitude, gs.dt),
+            None => false,
+        };
+        let over_vehicles = projectile.altitude.is_some() && !landed;
 
         if projectile.weapon == Weapon::Cb {
-            projectile.pos = max_new_pos;
+            let hit = if cvars.g_cluster_bomb_bounce {
+                map.wall_trace(projectile.pos, max_new_pos)
+            } else {
+                None
+            };
+            match hit {
+                Some(hit) if can_bounce(cvars, gs, proj_handle, hit) => {
+                    bounce(gs, proj_handle, hit, cvars.g_cluster_bomb_bounce_retention);
+                }
+                Some(hit) if hit.normal != Vec2f::zero() => {
+                    // Out of bounces - it lies by the wall until its timer runs out.
+                    let projectile = &mut gs.projectiles[proj_handle];
+                    projectile.pos = bounce_pos(projectile.vel, hit);
+                    projectile.vel = Vec2f::zero();
+                }
+                // Started inside a wall (e.g. fired point blank) so it flies over them like without bouncing.
+                _ => gs.projectiles[proj_handle].pos = max_new_pos,
+            }
+            if landed {
+                let pos = gs.projectiles[proj_handle].pos;
+                projectile_impact(cvars, gs, map, proj_handle, pos);
+            }
             continue;
         }
 
-        let maybe_collision = map.is_wall_trace(projectile.pos, max_new_pos);
-        let new_pos = if let Some(hit_pos) = maybe_collision {
-            hit_pos
+        let maybe_collision = map.wall_trace(projectile.pos, max_new_pos);
+        let new_pos = if let Some(hit) = maybe_collision {
+            hit.pos
         } else {
             max_new_pos
         };
@@ -477,17 +1233,39 @@ pub fn projectiles(cvars: &Cvars, gs: &mut GameState, map: &Map) {
             start: projectile.pos,
             end: new_pos,
         };
-        let step_dir = (new_pos - projectile.pos).normalized();
+        // Zero when the step is empty (e.g. a tick with dt 0), otherwise the railgun push would be NaN.
+        let step_dir = (new_pos - projectile.pos)
+            .try_normalized()
+            .unwrap_or_default();
+        if cvars.d_draw && cvars.d_draw_projectile_rays {
+            gs.projectile_rays.push((step.start, step.end));
+        }
 
         projectile.pos = new_pos;
 
         let is_rail = projectile.weapon == Weapon::Rail;
+        let is_bfg = projectile.weapon == Weapon::Bfg;
         if is_rail {
-            let beam = RailBeam::new(step.start, step.end, gs.game_time);
-            gs.rail_beams.push(beam);
+            gs.spawn_effect(Effect::RailBeam {
+                begin: step.start,
+                end: step.end,
+                start_time: gs.game_time,
+            });
         }
 
-        for vehicle_handle in gs.vehicles.iter_handles() {
+        let candidates = if over_vehicles {
+            Vec::new()
+        } else if is_bfg {
+            // The BFG also damages vehicles in range of its path, not just those it hits.
+            let range = Vec2f::broadcast(cvars.g_bfg_beam_range.max(cvars.g_hitcircle_radius));
+            let mins = Vec2f::partial_min(step.start, step.end) - range;
+            let maxs = Vec2f::partial_max(step.start, step.end) + range;
+            gs.vehicle_grid.query_rect(mins, maxs)
+        } else {
+            gs.vehicle_grid.query_segment(step.start, step.end)
+        };
+
+        for vehicle_handle in candidates {
             // LATER immediately killing vehicles here means 2 players can't share a kill
             let vehicle = &mut gs.vehicles[vehicle_handle];
 
@@ -495,108 +1273,514 @@ pub fn projectiles(cvars: &Cvars, gs: &mut GameState, map: &Map) {
             // so the borrow ends before we pass `gs` to other functions.
             let projectile = &gs.projectiles[proj_handle];
 
+            let own = vehicle.owner == projectile.owner
+                && (!projectile.weapon.can_hit_owner()
+                    || gs.game_time - projectile.spawn_time < cvars.g_self_collision_delay);
             if vehicle.destroyed()
-                || vehicle.owner == projectile.owner
+                || own
                 || (is_rail && gs.rail_hits.get(&proj_handle) == Some(&vehicle_handle))
             {
                 continue;
             }
 
-            let nearest_point = step.projected_point(vehicle.pos);
-            let dist2 = nearest_point.distance_squared(vehicle.pos);
+            let nearest_point = step.projected_point(*vehicle.pos);
+            let dist2 = nearest_point.distance_squared(*vehicle.pos);
             if dist2 <= cvars.g_hitcircle_radius * cvars.g_hitcircle_radius {
                 if cvars.d_tracing {
                     dbg_cross!(nearest_point, 0.5);
                 }
-                let dmg = cvars.g_weapon_damage_direct(projectile.weapon);
+                // Debris isn't a weapon, it's credited to whoever destroyed the vehicle.
+                let debris = gs.debris.get(&proj_handle).copied();
+                let weapon = projectile.weapon;
+                let (dmg, dmg_type, dmg_weapon, attacker_handle) = match debris {
+                    Some(attacker) => (
+                        fixed::to_milli(cvars.g_vehicle_debris_damage),
+                        cvars.g_vehicle_debris_damage_type,
+                        None,
+                        attacker,
+                    ),
+                    None => (
+                        fixed::to_milli(cvars.g_weapon_damage_direct(weapon)),
+                        cvars.g_weapon_damage_type(weapon),
+                        Some(weapon),
+                        projectile.owner,
+                    ),
+                };
 
                 if is_rail {
                     gs.rail_hits.insert(proj_handle, vehicle_handle);
                     vehicle.vel += step_dir * cvars.g_railgun_push;
+                    let kind = AudioEventKind::RailHit;
+                    sys_sound::push_audio_event(cvars, &mut gs.audio_events, kind, *vehicle.pos);
                 }
 
-                let attacker_handle = projectile.owner;
-                damage(cvars, gs, attacker_handle, vehicle_handle, dmg);
+                if vehicle.is_dummy && dmg_weapon == Some(Weapon::Mg) {
+                    if let Some(range) = &mut gs.range {
+                        range.record_mg_impact(cvars, nearest_point);
+                    }
+                }
+
+                let facing = direct_hit_facing(cvars, vehicle, step);
+                let hurt = damage(
+                    cvars,
+                    gs,
+                    attacker_handle,
+                    vehicle_handle,
+                    dmg_weapon,
+                    dmg,
+                    dmg_type,
+                    Some(facing),
+                );
+                if hurt {
+                    credit_hit(gs, proj_handle);
+                }
                 if !is_rail {
-                    projectile_impact(cvars, gs, proj_handle, nearest_point);
+                    projectile_impact(cvars, gs, map, proj_handle, nearest_point);
                     break; // TODO actually ... what if the segment is long and 2 vehicles are in the path
                 }
-            } else if projectile.weapon == Weapon::Bfg
-                && dist2 <= cvars.g_bfg_beam_range * cvars.g_bfg_beam_range
-                && map.is_wall_trace(projectile.pos, vehicle.pos).is_none()
-            {
-                let dmg = cvars.g_bfg_beam_damage_per_sec * gs.dt;
-                gs.bfg_beams.push((projectile.pos, vehicle.pos));
-                let attacker_handle = projectile.owner;
-                damage(cvars, gs, attacker_handle, vehicle_handle, dmg);
+            } else if is_bfg && dist2 <= cvars.g_bfg_beam_range * cvars.g_bfg_beam_range {
+                // Candidates are unique so this is at most one beam per vehicle per frame.
+                if let Some(target) = bfg_beam_target(map, projectile.pos, vehicle) {
+                    let dmg = fixed::rate(
+                        cvars.g_bfg_beam_damage_per_sec,
+                        gs.dt,
+                        &mut vehicle.damage_remainder,
+                    );
+                    let dmg_type = cvars.g_weapon_damage_type(Weapon::Bfg);
+                    let facing = facing_towards(vehicle, projectile.pos);
+                    let attacker_handle = projectile.owner;
+                    gs.spawn_effect(Effect::BfgBeam {
+                        begin: projectile.pos,
+                        end: target,
+                        start_time: gs.game_time,
+                    });
+                    let hurt = damage(
+                        cvars,
+                        gs,
+                        attacker_handle,
+                        vehicle_handle,
+                        Some(Weapon::Bfg),
+                        dmg,
+                        dmg_type,
+                        Some(facing),
+                    );
+                    if hurt {
+                        credit_hit(gs, proj_handle);
+                    }
+                }
             }
         }
 
-        if let Some(hit_pos) = maybe_collision {
+        if let Some(hit) = maybe_collision {
             // Only hit the final wall if it didn't hit a vehicle first.
             // Otherwise this tries to remove the projectile a second time.
             // We could set a flag when hitting vehicles above instead of `.contains` but this is more future-proof.
             if gs.projectiles.contains(proj_handle) {
-                projectile_impact(cvars, gs, proj_handle, hit_pos);
+                let hit_pos = hit.pos;
+                let ricochet = mg_ricochets(cvars, gs, proj_handle, hit);
+                let angle = gs.projectiles[proj_handle].angle;
+                let entry = CollisionEntry {
+                    frame_num: gs.frame_num,
+                    entity: TraceEntity::Projectile(proj_handle),
+                    from: step.start,
+                    to: max_new_pos,
+                    from_angle: angle,
+                    to_angle: angle,
+                    corner: None,
+                    contact: hit_pos,
+                    resolution: if ricochet {
+                        Resolution::Ricochet
+                    } else {
+                        Resolution::Impact
+                    },
+                };
+                collision_debug::trace(cvars, &mut gs.collision_trace, entry);
+                sys_particles::emit_sparks(cvars, gs, hit_pos, step_dir);
+                if ricochet {
+                    bounce(gs, proj_handle, hit, cvars.g_machine_gun_ricochet_retention);
+                    continue;
+                }
+                if let Some(tile_pos) = map.tile_at(hit_pos) {
+                    let amount = wall_hit_damage(cvars, gs, proj_handle);
+                    damage_wall(cvars, gs, map, tile_pos.index, amount);
+                }
+                projectile_impact(cvars, gs, map, proj_handle, hit_pos);
                 if is_rail {
                     gs.rail_hits.remove(&proj_handle);
                 }
             }
         }
+
+        if landed && gs.projectiles.contains(proj_handle) {
+            projectile_impact(cvars, gs, map, proj_handle, new_pos);
+        }
     }
 }
 
-pub fn damage(
+/// How far back along their path bouncing projectiles are placed so they're never inside the wall.
+/// Going back instead of along the normal also works in inside corners where the normal points into another wall.
+const BOUNCE_OFFSET: f64 = 0.01;
+
+/// Hit a face of a wall (not started inside one) and has bounces left.
+fn can_bounce(cvars: &Cvars, gs: &GameState, proj_handle: Index, hit: WallHit) -> bool {
+    let bounces = gs.bounces.get(&proj_handle).copied().unwrap_or(0);
+    hit.normal != Vec2f::zero() && bounces < cvars.g_projectile_max_bounces
+}
+
+/// Machine gun rounds hitting a wall at a shallow angle sometimes ricochet instead of exploding.
+fn mg_ricochets(cvars: &Cvars, gs: &mut GameState, proj_handle: Index, hit: WallHit) -> bool {
+    let projectile = &gs.projectiles[proj_handle];
+    let speed = projectile.vel.magnitude();
+    if projectile.weapon != Weapon::Mg
+        || gs.debris.contains_key(&proj_handle)
+        || speed == 0.0
+        || !can_bounce(cvars, gs, proj_handle, hit)
+    {
+        return false;
+    }
+    // Angle between the path and the wall's face.
+    let angle = (-projectile.vel.dot(hit.normal) / speed).asin();
+    if angle > cvars.g_machine_gun_ricochet_max_angle.to_radians() {
+        return false;
+    }
+    // Only use the RNG if needed so disabling ricochets doesn't change anything else.
+    cvars.g_machine_gun_ricochet_chance > 0.0
+        && gs.rng.weapon_spread.gen::<f64>() < cvars.g_machine_gun_ricochet_chance
+}
+
+/// Reflect the projectile's velocity about the wall's normal, keeping `retention` of the speed,
+/// and move it just off the wall. The rest of this frame's movement is lost.
+fn bounce(gs: &mut GameState, proj_handle: Index, hit: WallHit, retention: f64) {
+    *gs.bounces.entry(proj_handle).or_insert(0) += 1;
+    let projectile = &mut gs.projectiles[proj_handle];
+    let vel = projectile.vel;
+    projectile.pos = bounce_pos(vel, hit);
+    projectile.vel = (vel - 2.0 * vel.dot(hit.normal) * hit.normal) * retention;
+    projectile.angle = projectile.vel.to_angle();
+}
+
+fn bounce_pos(vel: Vec2f, hit: WallHit) -> Vec2f {
+    hit.contact - vel.try_normalized().unwrap_or_default() * BOUNCE_OFFSET
+}
+
+/// The point the BFG beam from `pos` hits - the vehicle's center if it's visible,
+/// otherwise the nearest visible hitbox corner so a vehicle partially behind cover still gets hit.
+///
+/// `None` if the vehicle is completely behind walls.
+fn bfg_beam_target(map: &Map, pos: Vec2f, vehicle: &Vehicle) -> Option<Vec2f> {
+    if line_of_sight(map, pos, *vehicle.pos) {
+        return Some(*vehicle.pos);
+    }
+    let mut corners = vehicle.obb.corners;
+    corners.sort_by(|a, b| {
+        a.distance_squared(pos)
+            .partial_cmp(&b.distance_squared(pos))
+            .unwrap()
+    });
+    corners
+        .iter()
+        .copied()
+        .find(|&corner| line_of_sight(map, pos, corner))
+}
+
+/// Which part of the vehicle gets hit by damage coming from `dir` (pointing from the vehicle to the source).
+///
+/// Up to and including 45 degrees from the nose is the front, the same from the tail is the rear,
+/// everything else is the side. Never returns `Facing::Turret`.
+fn facing_from(vehicle: &Vehicle, dir: Vec2f) -> Facing {
+    // Comparing coordinates instead of angles keeps the boundaries exact.
+    let local = dir.rotated_z(-*vehicle.angle);
+    if local.x >= local.y.abs() {
+        Facing::Front
+    } else if -local.x >= local.y.abs() {
+        Facing::Rear
+    } else {
+        Facing::Side
+    }
+}
+
+/// Which part of the vehicle faces `pos`, used for splash damage and BFG beams. Never returns `Facing::Turret`.
+fn facing_towards(vehicle: &Vehicle, pos: Vec2f) -> Facing {
+    facing_from(vehicle, pos - *vehicle.pos)
+}
+
+/// Which part of the vehicle a projectile traveling along `step` hit - the one its velocity points into.
+fn direct_hit_facing(cvars: &Cvars, vehicle: &Vehicle, step: LineSegment2<f64>) -> Facing {
+    let turret_pos = *vehicle.pos
+        + vehicle.angle.to_mat2f() * cvars.g_vehicle_turret_offset_chassis(vehicle.veh_type);
+    if step.distance_to_point(turret_pos) <= cvars.g_facing_turret_radius {
+        Facing::Turret
+    } else {
+        facing_from(vehicle, step.start - step.end)
+    }
+}
+
+/// Deal damage (in milli-hp) to a vehicle, `facing` is the part that got hit, if any, and determines the armor.
+///
+/// This is the only place where damage gets modified. The multipliers are applied in this order,
+/// rounding after each (see `fixed`):
+/// 1) the armor of the facing that got hit (if any)
+/// 2) the vehicle type's resistance to `dmg_type`
+///
+/// Returns whether it hurt another player's vehicle, for counting hits (see `WeaponStats`).
+pub fn damage(
     cvars: &Cvars,
     gs: &mut GameState,
     attacker_handle: Index,
     vehicle_handle: Index,
-    dmg_amount: f64,
-) {
+    weapon: Option<Weapon>,
+    dmg_amount: MilliHp,
+    dmg_type: DamageType,
+    facing: Option<Facing>,
+) -> bool {
+    // Nobody gets hurt during the countdown or after the match, see `sys_match`.
+    if gs.match_state != MatchState::Playing {
+        return false;
+    }
+
+    // No friendly fire unless enabled, damaging yourself is always possible.
+    let owner = gs.vehicles[vehicle_handle].owner;
+    let team = gs.players[owner].team;
+    let teammate =
+        attacker_handle != owner && team.is_some() && team == gs.players[attacker_handle].team;
+    let mut reflect_to = None;
+    if teammate {
+        match cvars.g_friendly_fire {
+            FriendlyFire::Off => return false,
+            FriendlyFire::On => {}
+            FriendlyFire::Reflect => {
+                let attacker_vehicle = gs.players[attacker_handle]
+                    .vehicle
+                    .filter(|&handle| !gs.vehicles[handle].destroyed());
+                match attacker_vehicle {
+                    Some(handle) => reflect_to = Some(handle),
+                    // Dead attackers (e.g. their wreck exploding) can't take it.
+                    None => return false,
+                }
+            }
+        }
+    }
+
     let vehicle = &mut gs.vehicles[vehicle_handle];
 
     soft_assert!(!vehicle.destroyed());
 
-    vehicle.hp_fraction -= dmg_amount / cvars.g_vehicle_hp(vehicle.veh_type);
+    let armor = match facing {
+        Some(facing) => vehicle.armor.get(facing),
+        None => 1.0,
+    };
+    let resistance = cvars.g_vehicle_resistance(vehicle.veh_type, dmg_type);
+    let dmg_amount = fixed::mul_all(dmg_amount, &[armor, resistance]);
 
-    // Not using 0.0 here because of floating point errors.
-    // Some weapons should reduce health to exact 0 in a small number of hits but it ends up being a tiny bit above it.
-    if vehicle.hp_fraction > 0.001 {
-        return;
+    // Reflected damage is what the teammate would have taken before their shield,
+    // from then on it's as if the attacker hurt themself.
+    let (vehicle_handle, owner, facing, armor) = match reflect_to {
+        Some(handle) => (handle, attacker_handle, None, 1.0),
+        None => (vehicle_handle, owner, facing, armor),
+    };
+    let vehicle = &mut gs.vehicles[vehicle_handle];
+    // Whatever the shield can't absorb gets through.
+    let dmg_amount = vehicle.shield_absorb(dmg_amount, gs.game_time);
+    let dealt = dmg_amount.clamp(0, vehicle.hp);
+    if vehicle.is_dummy {
+        if let Some(range) = &mut gs.range {
+            let dealt = fixed::from_milli(dealt);
+            range.record_damage(gs.game_time, vehicle.owner, weapon, dealt);
+        }
+    }
+    gs.life_records.damage(
+        cvars,
+        gs.game_time,
+        attacker_handle,
+        vehicle.owner,
+        weapon,
+        fixed::from_milli(dealt),
+    );
+    let other = attacker_handle != owner;
+    if let Some(weapon) = weapon {
+        if other {
+            gs.players[attacker_handle].score.weapons[weapon as usize].damage += dealt;
+        }
+    }
+    vehicle.hp -= dealt;
+    let killed = vehicle.hp <= 0;
+    let hp = vehicle.hp;
+    trace::record(cvars, &mut gs.trace, || TraceEvent::Damage {
+        attacker: attacker_handle,
+        vehicle: vehicle_handle,
+        weapon,
+        amount: dmg_amount,
+        dealt,
+        hp,
+    });
+
+    if dealt > 0 {
+        let amount = fixed::from_milli(dealt);
+        push_notification(
+            cvars,
+            &mut gs.notifications,
+            gs.game_time,
+            NotificationKind::DamageTaken {
+                player: owner,
+                amount,
+                facing,
+                armor,
+            },
+        );
+        if attacker_handle != owner {
+            push_notification(
+                cvars,
+                &mut gs.notifications,
+                gs.game_time,
+                NotificationKind::DamageDealt {
+                    attacker: attacker_handle,
+                    target: owner,
+                    amount,
+                    facing,
+                    armor,
+                },
+            );
+        }
+    }
+
+    if !killed {
+        return other;
     }
 
     // Vehicle got killed
 
-    vehicle.hp_fraction = 0.0;
-    gs.explosions
-        .push(Explosion::new(vehicle.pos, 1.0, gs.game_time, false));
-    gs.players[vehicle.owner].guided_missile = None; // No guiding after death
+    // Its explosion can destroy more vehicles so it's not done here, see `vehicle_explosions`.
+    gs.pending_explosions
+        .push_back((vehicle_handle, attacker_handle));
+    let pos = *vehicle.pos; // borrowck dance
+    gs.spawn_effect(Effect::Explosion {
+        pos,
+        scale: 1.0,
+        start_time: gs.game_time,
+        bfg: false,
+    });
+    let vehicle = &gs.vehicles[vehicle_handle];
+    // No guiding after death
+    if let Some(gm_handle) = gs.players[vehicle.owner].guided_missile.take() {
+        if cvars.g_guided_missile_detonate_on_owner_death {
+            // Not detonating immediately because we might be in the middle of iterating projectiles.
+            gs.pending_detonations.push_back(gm_handle);
+        }
+    }
 
-    let attacker = &mut gs.players[attacker_handle];
-    if attacker_handle == vehicle.owner {
-        attacker.score.suicides += 1;
+    if let Some(range) = &mut gs.range {
+        // No scoring in the training range.
+        if vehicle.is_dummy {
+            range.record_kill(gs.game_time, vehicle.owner);
+        }
     } else {
-        attacker.score.kills += 1;
+        let attacker = &mut gs.players[attacker_handle];
+        if attacker_handle == vehicle.owner {
+            attacker.score.suicides += 1;
+        } else {
+            attacker.score.kills += 1;
+        }
+        gs.players[vehicle.owner].score.deaths += 1;
+
+        if cvars.g_dda_enabled {
+            let victim = vehicle.owner;
+            gs.dda.record_kill(gs.game_time, attacker_handle, victim);
+        }
     }
-    let victim = &mut gs.players[vehicle.owner];
-    victim.score.deaths += 1;
+    let death = Death {
+        time: gs.game_time,
+        pos: *vehicle.pos,
+        attacker: attacker_handle,
+        weapon,
+    };
+    gs.life_records.death(cvars, vehicle.owner, death);
+
+    let kind = NotificationKind::Kill {
+        killer_name: gs.players[attacker_handle].name.clone(),
+        victim_name: gs.players[vehicle.owner].name.clone(),
+        weapon,
+    };
+    push_notification(cvars, &mut gs.notifications, gs.game_time, kind);
 
-    victim.death_time = gs.game_time;
+    gs.players[vehicle.owner].death_time = gs.game_time;
+
+    sys_ctf::drop_flag(cvars, gs, vehicle_handle);
+
+    other
 }
 
-/// Right now, CBs are the only timed projectiles, long term, might wanna add timeouts to more
-/// to avoid too many entities on huge maps.
-pub fn projectiles_timeout(cvars: &Cvars, gs: &mut GameState) {
+/// Right now, CBs are the only timed projectiles,
+/// long term, might wanna add timeouts to more to avoid too many entities on huge maps.
+/// Explode projectiles whose time has come and quietly remove those
+/// which have been flying for too long or left the map.
+/// If there are still more than `g_projectiles_max`, the oldest are removed.
+pub fn projectiles_timeout(cvars: &Cvars, gs: &mut GameState, map: &mut Map) {
     for handle in gs.projectiles.iter_handles() {
         let projectile = &gs.projectiles[handle];
-        if gs.game_time > projectile.explode_time {
+        let fuse = projectile.altitude.is_none() || cvars.g_weapon_arc_fuse;
+        if fuse && gs.game_time > projectile.explode_time {
             let hit_pos = projectile.pos; // borrowck dance
-            projectile_impact(cvars, gs, handle, hit_pos);
+            projectile_impact(cvars, gs, map, handle, hit_pos);
+        }
+    }
+
+    // Most projectiles hit the map's edge like a wall but cluster bombs fly over walls.
+    let mins = Vec2f::broadcast(-TILE_SIZE);
+    let maxs = map.maxs() + TILE_SIZE;
+    for handle in gs.projectiles.iter_handles() {
+        let projectile = &gs.projectiles[handle];
+        let lifetime = cvars.g_weapon_lifetime(projectile.weapon);
+        let expired = gs.game_time >= projectile.spawn_time + lifetime;
+        let pos = projectile.pos;
+        let outside = pos.x < mins.x || pos.y < mins.y || pos.x > maxs.x || pos.y > maxs.y;
+        if expired || outside {
+            projectile_expire(cvars, gs, handle);
+        }
+    }
+
+    let excess = gs.projectiles.len().saturating_sub(cvars.g_projectiles_max);
+    if excess > 0 {
+        // The sort is stable so projectiles fired at the same time are removed in arena order.
+        let mut by_age: Vec<_> = gs
+            .projectiles
+            .iter()
+            .map(|(handle, projectile)| (projectile.spawn_time, handle))
+            .collect();
+        by_age.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        for &(_, handle) in &by_age[..excess] {
+            projectile_expire(cvars, gs, handle);
         }
     }
 }
 
-fn projectile_impact(cvars: &Cvars, gs: &mut GameState, projectile_handle: Index, hit_pos: Vec2f) {
+/// Remove a projectile without an explosion or damage.
+fn projectile_expire(cvars: &Cvars, gs: &mut GameState, projectile_handle: Index) {
+    remove_projectile(cvars, gs, projectile_handle);
+}
+
+/// The only place projectiles get removed so no references to them are left behind.
+fn remove_projectile(cvars: &Cvars, gs: &mut GameState, projectile_handle: Index) {
+    let projectile = gs.projectiles.remove(projectile_handle).unwrap();
+    trace::record(cvars, &mut gs.trace, || {
+        TraceEvent::Remove(EntitySummary::projectile(projectile_handle, &projectile))
+    });
+    gs.rail_hits.remove(&projectile_handle);
+    gs.bounces.remove(&projectile_handle);
+    gs.debris.remove(&projectile_handle);
+    gs.projectile_hits.remove(&projectile_handle);
+    let player = &mut gs.players[projectile.owner];
+    if player.guided_missile == Some(projectile_handle) {
+        player.guided_missile = None;
+    }
+}
+
+fn projectile_impact(
+    cvars: &Cvars,
+    gs: &mut GameState,
+    map: &mut Map,
+    projectile_handle: Index,
+    hit_pos: Vec2f,
+) {
     let projectile = &mut gs.projectiles[projectile_handle];
 
     // borrowck dance
@@ -606,52 +1790,71 @@ fn projectile_impact(cvars: &Cvars, gs: &mut GameState, projectile_handle: Index
     // Vehicle explosion first so it's below projectile explosion because it looks better.
     let expl_scale = cvars.g_weapon_explosion_scale(weapon);
     if expl_scale > 0.0 {
-        gs.explosions.push(Explosion::new(
-            hit_pos,
-            expl_scale,
-            gs.game_time,
-            weapon == Weapon::Bfg,
-        ));
+        gs.spawn_effect(Effect::Explosion {
+            pos: hit_pos,
+            scale: expl_scale,
+            start_time: gs.game_time,
+            bfg: weapon == Weapon::Bfg,
+        });
+        sys_particles::emit_dust(cvars, gs, hit_pos, expl_scale);
     }
 
-    let expl_damage = expl_scale * cvars.g_weapon_explosion_damage(weapon);
+    let expl_damage = fixed::mul(
+        fixed::to_milli(cvars.g_weapon_explosion_damage(weapon)),
+        expl_scale,
+    );
     let expl_radius = expl_scale * cvars.g_weapon_explosion_radius(weapon);
-    if expl_damage > 0.0 || expl_radius > 0.0 {
-        explosion_damage(
+    if expl_damage > 0 || expl_radius > 0.0 {
+        let hurt = explosion_damage(
             cvars,
             gs,
+            map,
             owner,
             hit_pos,
             expl_damage,
             expl_damage,
             expl_radius,
+            Some(weapon),
+            cvars.g_weapon_damage_type(weapon),
             None,
         );
+        if hurt {
+            credit_hit(gs, projectile_handle);
+        }
     }
 
-    if weapon == Weapon::Gm {
-        let player = &mut gs.players[owner];
-        if player.guided_missile == Some(projectile_handle) {
-            player.guided_missile = None;
-        }
+    remove_projectile(cvars, gs, projectile_handle);
+}
+
+/// Count the projectile as a hit for its owner the first time it hurts another player, see `WeaponStats`.
+///
+/// Debris isn't a weapon so it doesn't count.
+fn credit_hit(gs: &mut GameState, projectile_handle: Index) {
+    if gs.debris.contains_key(&projectile_handle) || !gs.projectile_hits.insert(projectile_handle) {
+        return;
     }
-    gs.projectiles.remove(projectile_handle).unwrap();
+    let projectile = &gs.projectiles[projectile_handle];
+    gs.players[projectile.owner].score.weapons[projectile.weapon as usize].hits += 1;
 }
 
 fn explosion_damage(
     cvars: &Cvars,
     gs: &mut GameState,
+    map: &mut Map,
     owner: Index,
     expl_pos: Vec2f,
-    damage_center: f64,
-    damage_edge: f64,
+    damage_center: MilliHp,
+    damage_edge: MilliHp,
     radius: f64,
+    weapon: Option<Weapon>,
+    dmg_type: DamageType,
     ignore: Option<Index>,
-) {
+) -> bool {
     if cvars.d_explosion_radius {
         dbg_line!(expl_pos, expl_pos + Vec2f::new(radius, 0.0), 5.0);
     }
 
+    let mut hurt = false;
     for vehicle_handle in gs.vehicles.iter_handles() {
         if let Some(ignore) = ignore {
             if vehicle_handle == ignore {
@@ -664,13 +1867,76 @@ fn explosion_damage(
             continue;
         }
 
-        let center_dist = (vehicle.pos - expl_pos).magnitude();
+        let center_dist = (*vehicle.pos - expl_pos).magnitude();
         let dist = (center_dist - cvars.g_hitcircle_radius).max(0.0);
         if dist < radius {
-            let expl_damage = map_ranges(dist, 0.0, radius, damage_center, damage_edge);
-            damage(cvars, gs, owner, vehicle_handle, expl_damage);
+            // The falloff is a multiplier so it's rounded the same way as armor and resistances.
+            let falloff = map_ranges(dist, 0.0, radius, 0.0, 1.0);
+            let expl_damage = damage_center + fixed::mul(damage_edge - damage_center, falloff);
+            let facing = facing_towards(vehicle, expl_pos);
+            hurt |= damage(
+                cvars,
+                gs,
+                owner,
+                vehicle_handle,
+                weapon,
+                expl_damage,
+                dmg_type,
+                Some(facing),
+            );
+        }
+    }
+
+    // Walls are damaged by distance to their closest point, there's no hitcircle.
+    let extent = Vec2f::broadcast(radius);
+    let tiles: Vec<_> = map
+        .tiles_in_rect(expl_pos - extent, expl_pos + extent)
+        .collect();
+    for index in tiles {
+        if map.surface_at_index(index).destructible.is_none() {
+            continue;
+        }
+        let mins = index.as_() * TILE_SIZE;
+        let closest = expl_pos.clamped(mins, mins + TILE_SIZE);
+        let dist = closest.distance(expl_pos);
+        if dist < radius {
+            let falloff = map_ranges(dist, 0.0, radius, 0.0, 1.0);
+            let expl_damage = damage_center + fixed::mul(damage_edge - damage_center, falloff);
+            damage_wall(cvars, gs, map, index, fixed::from_milli(expl_damage));
         }
     }
+    hurt
+}
+
+/// Damage a projectile deals to the wall it hit directly, before its explosion.
+fn wall_hit_damage(cvars: &Cvars, gs: &GameState, proj_handle: Index) -> f64 {
+    let weapon = gs.projectiles[proj_handle].weapon;
+    if gs.debris.contains_key(&proj_handle) {
+        cvars.g_vehicle_debris_damage
+    } else if weapon == Weapon::Rail {
+        cvars.g_weapon_damage_direct(weapon) * cvars.g_railgun_wall_damage_factor
+    } else {
+        cvars.g_weapon_damage_direct(weapon)
+    }
+}
+
+/// Damage the wall at `index` if it's destructible.
+/// If that destroys it, it explodes and frontends are notified.
+fn damage_wall(cvars: &Cvars, gs: &mut GameState, map: &mut Map, index: Vec2u, amount: f64) {
+    if !map.damage_wall(index, amount) {
+        return;
+    }
+    // The explosion also makes the sound and leaves a scorch mark.
+    let pos = map.tile_center(index);
+    gs.spawn_effect(Effect::Explosion {
+        pos,
+        scale: 1.0,
+        start_time: gs.game_time,
+        bfg: false,
+    });
+    sys_particles::emit_dust(cvars, gs, pos, 1.0);
+    let kind = NotificationKind::WallDestroyed { index };
+    push_notification(cvars, &mut gs.notifications, gs.game_time, kind);
 }
 
 /// Maps values between the src and dest range: `src_min` to `dest_min`, `src_max` to `dest_max`, values between them
@@ -685,3 +1951,3103 @@ fn map_ranges(value: f64, src_min: f64, src_max: f64, dest_min: f64, dest_max: f
     let ratio = (value - src_min) / src_diff;
     dest_min + dest_diff * ratio
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{prelude::SmallRng, SeedableRng};
+
+    use crate::{
+        cvars::{CooldownGroup, FireLimiter},
+        entities::{Armor, Loadout, PowerupKind, Team, RESERVE_UNLIMITED},
+        game_state::drain_notifications,
+        lives::LifeEntry,
+        map::{self, Surface},
+        nav::NavGrid,
+        rng::GameRng,
+    };
+
+    fn surfaces() -> Vec<Surface> {
+        let surface = |name: &str, kind, speed| Surface {
+            name: name.to_owned(),
+            kind,
+            friction: 1.0,
+            speed,
+            destructible: None,
+        };
+        vec![
+            surface("ground", Kind::Normal, 1.0),
+            surface("water", Kind::Water, 0.5),
+            surface("mud", Kind::Normal, 0.5),
+            surface("spawn", Kind::Spawn, 1.0),
+            surface("wall", Kind::Wall, 1.0),
+        ]
+    }
+
+    /// Drive forward from the left side of the middle row for `secs` seconds.
+    fn drive(cvars: &Cvars, map: &Map, veh_type: VehicleType, secs: f64) -> Vehicle {
+        let mut gs = GameState::new(0);
+        let player_handle = gs.players.insert(Player::new("Player".to_owned()));
+        gs.players[player_handle].input = Input::new_up();
+        let pos = Vec2f::new(64.0, 96.0);
+        let vehicle = Vehicle::new(cvars, pos, 0.0, veh_type, 0, 0.0, player_handle);
+        let vehicle_handle = gs.vehicles.insert(vehicle);
+
+        gs.dt = 1.0 / 60.0;
+        for _ in 0..(secs * 60.0) as usize {
+            gs.frame_num += 1;
+            vehicle_movement(cvars, &mut gs, map);
+        }
+        gs.vehicles[vehicle_handle].clone()
+    }
+
+    #[test]
+    fn test_water_passable() {
+        let cvars = Cvars::default();
+        let row = "0 0 0 0 4 4 0 0 0 0 0 0 0 0 0 0";
+        let text = [row, row, row].join("\n");
+        let map = map::load_map(&text, surfaces()).unwrap();
+        let water_begin = 4.0 * 64.0;
+        let water_end = 6.0 * 64.0;
+
+        let tank = drive(&cvars, &map, VehicleType::Tank, 5.0);
+        assert!(tank.pos.x < water_begin, "{}", tank.pos.x);
+
+        let hovercraft = drive(&cvars, &map, VehicleType::Hovercraft, 5.0);
+        assert!(hovercraft.pos.x > water_end, "{}", hovercraft.pos.x);
+
+        let cvars = Cvars {
+            g_tank_water_passable: true,
+            ..Cvars::default()
+        };
+        let tank = drive(&cvars, &map, VehicleType::Tank, 5.0);
+        assert!(tank.pos.x > water_end, "{}", tank.pos.x);
+    }
+
+    #[test]
+    fn test_water_shoreline_at_angle() {
+        let cvars = Cvars::default();
+        let row = "0 0 0 0 0 0 4 4 4 4 0 0";
+        let text = [row; 12].join("\n");
+        let map = map::load_map(&text, surfaces()).unwrap();
+        let water_begin = 6.0 * 64.0;
+
+        // Pushed (e.g. by another vehicle) so only the leading corner is over the water,
+        // then driving at the shore diagonally and turning along it.
+        for (angle, left, right) in [(0.6, false, false), (0.6, true, false), (-0.6, false, true)] {
+            let mut gs = GameState::new(0);
+            let player_handle = gs.players.insert(Player::new("Player".to_owned()));
+            let hitbox = cvars.g_vehicle_hitbox(VehicleType::Tank);
+            let obb = Obb::new(hitbox, Vec2f::zero(), angle);
+            let front = obb
+                .corners
+                .iter()
+                .map(|corner| corner.x)
+                .fold(f64::MIN, f64::max);
+            let pos = Vec2f::new(water_begin + 2.0 - front, 384.0);
+            let vehicle =
+                Vehicle::new(&cvars, pos, angle, VehicleType::Tank, 0, 0.0, player_handle);
+            let wet = |vehicle: &Vehicle| {
+                vehicle
+                    .obb
+                    .corners
+                    .iter()
+                    .filter(|&&corner| is_water(&map, corner))
+                    .count()
+            };
+            assert_eq!(wet(&vehicle), 1);
+            let vehicle_handle = gs.vehicles.insert(vehicle);
+
+            gs.dt = 1.0 / 60.0;
+            let frame = |gs: &mut GameState, input| {
+                gs.players[player_handle].input = input;
+                gs.frame_num += 1;
+                vehicle_movement(&cvars, gs, &map);
+                let vehicle = &gs.vehicles[vehicle_handle];
+                assert!(!is_water(&map, *vehicle.pos), "{}", *vehicle.pos);
+                assert!(wet(vehicle) <= 1, "{}", *vehicle.pos);
+            };
+            for _ in 0..300 {
+                let input = Input {
+                    up: true,
+                    left,
+                    right,
+                    ..Input::new()
+                };
+                frame(&mut gs, input);
+            }
+            // It can still back out.
+            for _ in 0..120 {
+                let input = Input {
+                    down: true,
+                    ..Input::new()
+                };
+                frame(&mut gs, input);
+            }
+            assert_eq!(wet(&gs.vehicles[vehicle_handle]), 0);
+        }
+    }
+
+    /// Drive a vehicle at `speed` into a one tile thick wall, return its positions each frame.
+    fn drive_at_wall(cvars: &Cvars, veh_type: VehicleType, speed: f64, dt: f64) -> Vec<Vec2f> {
+        let row = "0 0 0 0 16 0 0 0 0 0";
+        let text = [row, row, row].join("\n");
+        let map = map::load_map(&text, surfaces()).unwrap();
+        let mut gs = GameState::new(0);
+        let player_handle = gs.players.insert(Player::new("Player".to_owned()));
+        gs.players[player_handle].input = Input::new_up();
+        let pos = Vec2f::new(64.0, 96.0);
+        let mut vehicle = Vehicle::new(cvars, pos, 0.0, veh_type, 0, 0.0, player_handle);
+        vehicle.vel = Vec2f::new(speed, 0.0);
+        let vehicle_handle = gs.vehicles.insert(vehicle);
+
+        gs.dt = dt;
+        let mut positions = Vec::new();
+        for _ in 0..50 {
+            gs.frame_num += 1;
+            vehicle_movement(cvars, &mut gs, &map);
+            positions.push(*gs.vehicles[vehicle_handle].pos);
+        }
+        positions
+    }
+
+    #[test]
+    fn test_thin_wall() {
+        let wall_begin = 4.0 * 64.0;
+        // Faster than any vehicle normally goes - in one frame it'd jump over the wall and its own length.
+        let speed = 4000.0;
+        let cvars = Cvars::default();
+        for veh_type in [
+            VehicleType::Tank,
+            VehicleType::Hovercraft,
+            VehicleType::Hummer,
+        ] {
+            let positions = drive_at_wall(&cvars, veh_type, speed, 0.1);
+            for pos in positions {
+                assert!(pos.x < wall_begin, "{:?} {:?}", veh_type, pos);
+            }
+        }
+
+        // Without substeps it goes right through.
+        let cvars = Cvars {
+            g_vehicle_max_step: f64::INFINITY,
+            ..Cvars::default()
+        };
+        let positions = drive_at_wall(&cvars, VehicleType::Tank, speed, 0.1);
+        assert!(positions[0].x > wall_begin + 64.0, "{:?}", positions[0]);
+    }
+
+    #[test]
+    fn test_wall_slide() {
+        let wall = "16 16 16 16 16 16 16 16 16 16 16 16 16 16 16 16";
+        let ground = "0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0";
+        let text = [wall, ground, ground].join("\n");
+        let map = map::load_map(&text, surfaces()).unwrap();
+        let cvars = Cvars {
+            d_collision_trace: true,
+            ..Cvars::default()
+        };
+        let mut gs = GameState::new(0);
+        let player_handle = gs.players.insert(Player::new("Player".to_owned()));
+        gs.players[player_handle].input = Input::new_up();
+        // Heading slightly into the wall, a few pixels away from it.
+        let pos = Vec2f::new(64.0, 64.0 + 20.0);
+        let angle = -0.1;
+        let mut vehicle =
+            Vehicle::new(&cvars, pos, angle, VehicleType::Tank, 0, 0.0, player_handle);
+        vehicle.vel = Vec2f::new(200.0, 0.0).rotated_z(angle);
+        let vehicle_handle = gs.vehicles.insert(vehicle);
+
+        gs.dt = 1.0 / 60.0;
+        for _ in 0..60 {
+            gs.frame_num += 1;
+            vehicle_movement(&cvars, &mut gs, &map);
+        }
+        let vehicle = &gs.vehicles[vehicle_handle];
+        // It touched the wall but kept going along it.
+        assert!(gs
+            .collision_trace
+            .iter()
+            .any(|entry| entry.resolution == Resolution::MoveSlide));
+        assert!(gs.scraping.contains(&vehicle_handle));
+        assert!(vehicle.pos.x > pos.x + 150.0, "{:?}", vehicle.pos);
+        assert!(vehicle.vel.x > 150.0, "{:?}", vehicle.vel);
+    }
+
+    /// Drive a tank up and to the right at 45 degrees for `frames`, returning its velocity each frame.
+    /// There's a wall along the top and with `corner` also along the right side.
+    fn drive_diagonally(cvars: &Cvars, corner: bool, frames: usize) -> (Vehicle, Vec<Vec2f>) {
+        let wall = "16 16 16 16 16 16 16 16 16 16 16 16 16 16 16 16";
+        let ground = if corner {
+            "0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 16"
+        } else {
+            "0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0"
+        };
+        let text = [wall, ground, ground, ground].join("\n");
+        let map = map::load_map(&text, surfaces()).unwrap();
+        let mut gs = GameState::new(0);
+        let player_handle = gs.players.insert(Player::new("Player".to_owned()));
+        gs.players[player_handle].input = Input::new_up();
+        let pos = Vec2f::new(64.0, 3.0 * 64.0);
+        let angle = -PI / 4.0;
+        let mut vehicle = Vehicle::new(cvars, pos, angle, VehicleType::Tank, 0, 0.0, player_handle);
+        vehicle.vel = Vec2f::new(150.0, 0.0).rotated_z(angle);
+        let vehicle_handle = gs.vehicles.insert(vehicle);
+
+        gs.dt = 1.0 / 60.0;
+        let mut vels = Vec::new();
+        for _ in 0..frames {
+            gs.frame_num += 1;
+            vehicle_movement(cvars, &mut gs, &map);
+            vels.push(gs.vehicles[vehicle_handle].vel);
+        }
+        (gs.vehicles.remove(vehicle_handle).unwrap(), vels)
+    }
+
+    /// Tracks grip sideways so a tank pushing into a wall at 45 degrees barely slides along it,
+    /// these tests are about the wall response so friction is the same in all directions.
+    fn isotropic_friction() -> Cvars {
+        let cvars = Cvars::default();
+        Cvars {
+            g_tank_lateral_friction_const: cvars.g_tank_friction_const,
+            g_tank_lateral_friction_linear: cvars.g_tank_friction_linear,
+            ..cvars
+        }
+    }
+
+    #[test]
+    fn test_wall_slide_diagonal() {
+        let cvars = isotropic_friction();
+        let (tank, vels) = drive_diagonally(&cvars, false, 240);
+        // Pressed against the wall the whole second half, still going right the whole time.
+        assert!(tank.pos.y < 64.0 + 40.0, "{:?}", tank.pos);
+        for vel in &vels[120..] {
+            assert_eq!(vel.y, 0.0);
+            assert!(vel.x > 50.0, "{:?}", vel);
+        }
+        assert!(tank.pos.x > 64.0 + 400.0, "{:?}", tank.pos);
+
+        // Gripping tracks still slide, just slower.
+        let (gripping, vels) = drive_diagonally(&Cvars::default(), false, 240);
+        assert!(vels[120..].iter().all(|vel| vel.x > 0.0));
+        assert!(gripping.pos.x < tank.pos.x - 200.0, "{:?}", gripping.pos);
+
+        // The old response pushes it away from the wall.
+        let cvars = Cvars {
+            g_vehicle_wall_bounce: true,
+            ..isotropic_friction()
+        };
+        let (_, vels) = drive_diagonally(&cvars, false, 240);
+        assert!(vels.iter().any(|vel| vel.y > 0.0));
+    }
+
+    #[test]
+    fn test_wall_inside_corner() {
+        let cvars = isotropic_friction();
+        let (tank, vels) = drive_diagonally(&cvars, true, 600);
+        assert!(
+            tank.pos.x < 15.0 * 64.0 && tank.pos.y > 64.0,
+            "{:?}",
+            tank.pos
+        );
+        assert_eq!(*vels.last().unwrap(), Vec2f::zero());
+    }
+
+    #[test]
+    fn test_obb_cache() {
+        let cvars = Cvars::default();
+        let water = "0 0 0 4 4 0 0 0";
+        let ground = "0 0 0 0 0 0 0 0";
+        let text = [ground, ground, water, ground, ground].join("\n");
+        let map = map::load_map(&text, surfaces()).unwrap();
+        let mut gs = GameState::new(0);
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut handles = Vec::new();
+        for (i, &veh_type) in [VehicleType::Tank, VehicleType::Hovercraft]
+            .iter()
+            .enumerate()
+        {
+            let player_handle = gs.players.insert(Player::new(format!("Player {}", i)));
+            let pos = Vec2f::new(100.0 + 150.0 * i as f64, 100.0);
+            let vehicle = Vehicle::new(&cvars, pos, 0.0, veh_type, 0, 0.0, player_handle);
+            handles.push((player_handle, gs.vehicles.insert(vehicle)));
+        }
+
+        gs.dt = 1.0 / 60.0;
+        for _ in 0..2000 {
+            gs.frame_num += 1;
+            for &(player_handle, vehicle_handle) in &handles {
+                if rng.gen_bool(0.1) {
+                    gs.players[player_handle].input = Input {
+                        left: rng.gen_bool(0.5),
+                        right: rng.gen_bool(0.5),
+                        up: rng.gen_bool(0.7),
+                        down: rng.gen_bool(0.2),
+                        ..Input::new()
+                    };
+                }
+                if rng.gen_bool(0.01) {
+                    // Teleport like other systems might.
+                    let vehicle = &mut gs.vehicles[vehicle_handle];
+                    let pos = Vec2f::new(rng.gen_range(50.0..450.0), rng.gen_range(50.0..100.0));
+                    vehicle.pos.set(pos, gs.frame_num);
+                    vehicle.angle.set(rng.gen_range(0.0..6.0), gs.frame_num);
+                    vehicle.update_obb();
+                }
+            }
+            vehicle_movement(&cvars, &mut gs, &map);
+            for &(_, vehicle_handle) in &handles {
+                let vehicle = &gs.vehicles[vehicle_handle];
+                let fresh = Obb::new(vehicle.hitbox, *vehicle.pos, *vehicle.angle);
+                assert_eq!(vehicle.obb, fresh);
+                for corner in fresh.corners {
+                    assert!(fresh.aabb_overlaps(corner, corner));
+                }
+            }
+        }
+    }
+
+    /// A hovercraft coasting right from the second tile of the middle row with no input,
+    /// `stats` is called with it after each frame. Returns the distance travelled.
+    fn coast(cvars: &Cvars, map: &Map, speed: f64, mut stats: impl FnMut(&Vehicle)) -> f64 {
+        let mut gs = GameState::new(0);
+        let player_handle = gs.players.insert(Player::new("Player".to_owned()));
+        let start = map.tile_center(Vec2u::new(1, 1));
+        let mut vehicle = Vehicle::new(
+            cvars,
+            start,
+            0.0,
+            VehicleType::Hovercraft,
+            0,
+            0.0,
+            player_handle,
+        );
+        vehicle.vel = Vec2f::new(speed, 0.0);
+        let vehicle_handle = gs.vehicles.insert(vehicle);
+        gs.players[player_handle].vehicle = Some(vehicle_handle);
+        gs.dt = 1.0 / 60.0;
+        for _ in 0..60 * 20 {
+            gs.frame_num += 1;
+            vehicle_movement(cvars, &mut gs, map);
+            stats(&gs.vehicles[vehicle_handle]);
+        }
+        let vehicle = &gs.vehicles[vehicle_handle];
+        assert!(vehicle.vel.magnitude() < 1.0, "{}", vehicle.vel);
+        vehicle.pos.x - start.x
+    }
+
+    #[test]
+    fn test_hovercraft_water_drift() {
+        let cvars = Cvars::default();
+        let row = |water: bool| {
+            let mut tiles = vec![if water { "4" } else { "0" }; 20];
+            tiles[0] = "0";
+            tiles[1] = "0";
+            tiles.join(" ")
+        };
+        let text = |water| [row(false), row(water), row(false)].join("\n");
+        let land = map::load_map(&text(false), surfaces()).unwrap();
+        let water = map::load_map(&text(true), surfaces()).unwrap();
+
+        let on_land = coast(&cvars, &land, 300.0, |_| {});
+        let on_water = coast(&cvars, &water, 300.0, |_| {});
+        assert!(on_water > on_land * 1.5, "{} {}", on_water, on_land);
+
+        // Crossing the shoreline blends the stats instead of switching them.
+        let land_stats = cvars.g_vehicle_movement_stats(VehicleType::Hovercraft, Kind::Normal);
+        let water_stats = cvars.g_vehicle_movement_stats(VehicleType::Hovercraft, Kind::Water);
+        let mut frictions = Vec::new();
+        coast(&cvars, &water, 300.0, |vehicle| {
+            frictions.push(movement_stats(&cvars, vehicle).lateral_friction_linear);
+        });
+        assert_eq!(frictions[0], land_stats.lateral_friction_linear);
+        assert_eq!(
+            *frictions.last().unwrap(),
+            water_stats.lateral_friction_linear
+        );
+        for pair in frictions.windows(2) {
+            assert!(pair[1] <= pair[0], "{:?}", pair);
+        }
+        let between = |&&friction: &&f64| {
+            friction < land_stats.lateral_friction_linear
+                && friction > water_stats.lateral_friction_linear
+        };
+        let intermediate = frictions.iter().filter(between).count();
+        assert!(intermediate >= 5, "{:?}", frictions);
+
+        // Other vehicles don't care.
+        for veh_type in [VehicleType::Tank, VehicleType::Hummer] {
+            let land = cvars.g_vehicle_movement_stats(veh_type, Kind::Normal);
+            let water = cvars.g_vehicle_movement_stats(veh_type, Kind::Water);
+            assert_eq!(land.lateral_friction_linear, water.lateral_friction_linear);
+            assert_eq!(land.turn_effectiveness, water.turn_effectiveness);
+        }
+    }
+
+    #[test]
+    fn test_surface_speed() {
+        let cvars = Cvars::default();
+        let ground = ["0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0"; 3].join("\n");
+        let mud = ["8 8 8 8 8 8 8 8 8 8 8 8 8 8 8 8"; 3].join("\n");
+        let ground = map::load_map(&ground, surfaces()).unwrap();
+        let mud = map::load_map(&mud, surfaces()).unwrap();
+
+        let ground_speed = drive(&cvars, &ground, VehicleType::Tank, 2.0)
+            .vel
+            .magnitude();
+        let mud_speed = drive(&cvars, &mud, VehicleType::Tank, 2.0).vel.magnitude();
+        assert!(ground_speed > 0.0);
+        assert!(
+            mud_speed < ground_speed * 0.75,
+            "{} {}",
+            mud_speed,
+            ground_speed
+        );
+    }
+
+    /// Fraction of the distance driven in circles for 5 s which was sideways relative to the heading.
+    fn sideways_slide(cvars: &Cvars, veh_type: VehicleType) -> f64 {
+        let stats = cvars.g_vehicle_movement_stats(veh_type, Kind::Normal);
+        let input = Input {
+            up: true,
+            left: true,
+            ..Input::new()
+        };
+        let dt = 1.0 / 60.0;
+        let mut vel = Vec2f::zero();
+        let mut angle = 0.0;
+        let mut turn_rate = 0.0;
+        let mut sideways = 0.0;
+        let mut total = 0.0;
+        for _ in 0..300 {
+            angle = turning(&stats, &mut vel, &angle, &mut turn_rate, input, dt);
+            accel_decel(&stats, &mut vel, angle, input, dt);
+            let forward = angle.to_vec2f();
+            sideways += vel.dot(Vec2f::new(-forward.y, forward.x)).abs() * dt;
+            total += vel.magnitude() * dt;
+        }
+        assert!(total > 100.0, "{}", total);
+        sideways / total
+    }
+
+    #[test]
+    fn test_lateral_friction() {
+        let cvars = Cvars::default();
+        let tank = sideways_slide(&cvars, VehicleType::Tank);
+        let hovercraft = sideways_slide(&cvars, VehicleType::Hovercraft);
+        assert!(tank < 0.01, "{}", tank);
+        assert!(hovercraft > 0.2, "{}", hovercraft);
+
+        // Lateral friction alone keeps the tank on course, velocity doesn't need to turn with it.
+        let cvars = Cvars {
+            g_tank_turn_effectiveness: 0.0,
+            ..Cvars::default()
+        };
+        let tank = sideways_slide(&cvars, VehicleType::Tank);
+        assert!(tank < hovercraft * 0.1, "{} {}", tank, hovercraft);
+    }
+
+    #[test]
+    fn test_top_speed() {
+        let cvars = Cvars::default();
+        for veh_type in [
+            VehicleType::Tank,
+            VehicleType::Hovercraft,
+            VehicleType::Hummer,
+        ] {
+            let stats = cvars.g_vehicle_movement_stats(veh_type, Kind::Normal);
+            let mut vel = Vec2f::zero();
+            for _ in 0..60 * 60 {
+                accel_decel(&stats, &mut vel, 0.0, Input::new_up(), 1.0 / 60.0);
+            }
+            let top_speed = stats.top_speed();
+            let error = (vel.magnitude() - top_speed).abs() / top_speed;
+            assert!(
+                error < 0.02,
+                "{:?} {} {}",
+                veh_type,
+                vel.magnitude(),
+                top_speed
+            );
+        }
+    }
+
+    /// A tank facing right in the middle of a small map.
+    fn setup_tank(cvars: &Cvars) -> (GameState, Map, Index, Index) {
+        let text = ["0 0 0 0 0", "0 0 12 0 0", "0 0 0 0 0"].join("\n");
+        let map = map::load_map(&text, surfaces()).unwrap();
+        let mut gs = GameState::new(0);
+        let player_handle = gs.players.insert(Player::new("Player".to_owned()));
+        let pos = map.tile_center(map.spawns()[0]);
+        let vehicle = Vehicle::new(cvars, pos, 0.0, VehicleType::Tank, 0, 0.0, player_handle);
+        let vehicle_handle = gs.vehicles.insert(vehicle);
+        gs.players[player_handle].vehicle = Some(vehicle_handle);
+        (gs, map, player_handle, vehicle_handle)
+    }
+
+    /// Damage dealt by a hit of 10 HP.
+    fn hit(
+        cvars: &Cvars,
+        gs: &mut GameState,
+        vehicle_handle: Index,
+        facing: Option<Facing>,
+    ) -> MilliHp {
+        let attacker = gs.vehicles[vehicle_handle].owner;
+        let hp_max = gs.vehicles[vehicle_handle].hp_max(cvars);
+        gs.vehicles[vehicle_handle].hp = hp_max;
+        damage(
+            cvars,
+            gs,
+            attacker,
+            vehicle_handle,
+            None,
+            fixed::to_milli(10.0),
+            DamageType::Kinetic,
+            facing,
+        );
+        hp_max - gs.vehicles[vehicle_handle].hp
+    }
+
+    #[test]
+    fn test_armor_facings() {
+        let cvars = Cvars::default();
+        let (mut gs, _map, _, vehicle_handle) = setup_tank(&cvars);
+        let vehicle = &mut gs.vehicles[vehicle_handle];
+        vehicle.armor.front = 0.5;
+        vehicle.armor.side = 0.8;
+        vehicle.armor.rear = 1.2;
+        vehicle.armor.turret = 0.3;
+
+        let pos = *vehicle.pos;
+        assert_eq!(
+            facing_towards(vehicle, pos + Vec2f::new(50.0, 10.0)),
+            Facing::Front
+        );
+        assert_eq!(
+            facing_towards(vehicle, pos + Vec2f::new(10.0, 50.0)),
+            Facing::Side
+        );
+        assert_eq!(
+            facing_towards(vehicle, pos + Vec2f::new(10.0, -50.0)),
+            Facing::Side
+        );
+        assert_eq!(
+            facing_towards(vehicle, pos + Vec2f::new(-50.0, 10.0)),
+            Facing::Rear
+        );
+        let turret = pos + cvars.g_vehicle_turret_offset_chassis(VehicleType::Tank);
+        let through_turret = LineSegment2 {
+            start: turret + Vec2f::new(0.0, 50.0),
+            end: turret,
+        };
+        assert_eq!(
+            direct_hit_facing(&cvars, vehicle, through_turret),
+            Facing::Turret
+        );
+        let grazing = LineSegment2 {
+            start: pos + Vec2f::new(-50.0, 20.0),
+            end: pos + Vec2f::new(-20.0, 20.0),
+        };
+        assert_eq!(direct_hit_facing(&cvars, vehicle, grazing), Facing::Rear);
+
+        let base = hit(&cvars, &mut gs, vehicle_handle, None);
+        let expected = [
+            (Facing::Front, 0.5),
+            (Facing::Side, 0.8),
+            (Facing::Rear, 1.2),
+            (Facing::Turret, 0.3),
+        ];
+        for (facing, mult) in expected {
+            let dmg = hit(&cvars, &mut gs, vehicle_handle, Some(facing));
+            assert_eq!(dmg, fixed::mul(base, mult), "{:?}", facing);
+        }
+    }
+
+    #[test]
+    fn test_facing_boundaries() {
+        let cvars = Cvars::default();
+        let (mut gs, _map, _, vehicle_handle) = setup_tank(&cvars);
+        let dir = |deg: f64| Vec2f::new(1.0, 0.0).rotated_z(deg.to_radians());
+        let vehicle = &gs.vehicles[vehicle_handle];
+        // Exactly 45 degrees can't be made by rotating so use the diagonals.
+        let cases = [
+            (dir(44.0), Facing::Front),
+            (Vec2f::new(1.0, 1.0), Facing::Front),
+            (Vec2f::new(1.0, -1.0), Facing::Front),
+            (dir(46.0), Facing::Side),
+            (dir(-46.0), Facing::Side),
+            (dir(134.0), Facing::Side),
+            (Vec2f::new(-1.0, 1.0), Facing::Rear),
+            (Vec2f::new(-1.0, -1.0), Facing::Rear),
+            (dir(136.0), Facing::Rear),
+            (dir(-136.0), Facing::Rear),
+        ];
+        for (dir, facing) in cases {
+            assert_eq!(facing_from(vehicle, dir), facing, "{:?}", dir);
+        }
+
+        // Relative to the vehicle's angle.
+        let angle = PI / 2.0;
+        gs.vehicles[vehicle_handle].angle.set(angle, 1);
+        let vehicle = &gs.vehicles[vehicle_handle];
+        for (deg, facing) in [
+            (44.0, Facing::Front),
+            (46.0, Facing::Side),
+            (136.0, Facing::Rear),
+        ] {
+            let dir = dir(deg).rotated_z(angle);
+            assert_eq!(facing_from(vehicle, dir), facing, "{}", deg);
+        }
+    }
+
+    /// The facing and armor multiplier of the only damage dealt since the last call.
+    fn dealt_facing(gs: &mut GameState) -> (Option<Facing>, f64) {
+        let dealt: Vec<_> = drain_notifications(gs)
+            .into_iter()
+            .filter_map(|notification| match notification.kind {
+                NotificationKind::DamageDealt { facing, armor, .. } => Some((facing, armor)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(dealt.len(), 1);
+        dealt[0]
+    }
+
+    #[test]
+    fn test_facing_rail_and_bfg() {
+        let cvars = Cvars {
+            g_tank_facing_rear: 0.5,
+            ..Cvars::default()
+        };
+
+        // A rail from behind, far enough from the turret to hit the chassis.
+        let (mut gs, mut map, _, vehicle_handle) = setup_tank(&cvars);
+        let attacker = gs.players.insert(Player::new("Attacker".to_owned()));
+        gs.projectiles.insert(Projectile {
+            weapon: Weapon::Rail,
+            pos: *gs.vehicles[vehicle_handle].pos + Vec2f::new(-30.0, 10.0),
+            vel: Vec2f::new(1000.0, 0.0),
+            angle: 0.0,
+            turn_rate: 0.0,
+            explode_time: f64::MAX,
+            spawn_time: 0.0,
+            owner: attacker,
+            lock: None,
+            retarget_time: 0.0,
+            altitude: None,
+        });
+        gs.dt = 1.0 / 30.0;
+        gs.frame_num += 1;
+        projectiles(&cvars, &mut gs, &mut map);
+        assert_eq!(dealt_facing(&mut gs), (Some(Facing::Rear), 0.5));
+
+        // The BFG ball is behind the vehicle.
+        let (mut gs, mut map, _) = bfg_setup(&cvars, Vec2f::new(200.0, 32.0));
+        bfg_frame(&cvars, &mut gs, &mut map);
+        assert_eq!(bfg_beam_ends(&gs).len(), 1);
+        assert_eq!(dealt_facing(&mut gs), (Some(Facing::Rear), 0.5));
+    }
+
+    #[test]
+    fn test_armor_defaults_match_no_armor() {
+        let cvars = Cvars::default();
+        let (mut gs, _map, _, vehicle_handle) = setup_tank(&cvars);
+        let base = hit(&cvars, &mut gs, vehicle_handle, None);
+        assert_eq!(base, 10_000);
+        for facing in Armor::UPGRADE_ORDER {
+            assert_eq!(hit(&cvars, &mut gs, vehicle_handle, Some(facing)), base);
+        }
+    }
+
+    fn tank_resistances(kinetic: f64, explosive: f64, energy: f64) -> Cvars {
+        Cvars {
+            g_tank_resistance_kinetic: kinetic,
+            g_tank_resistance_explosive: explosive,
+            g_tank_resistance_energy: energy,
+            ..Cvars::default()
+        }
+    }
+
+    /// Damage dealt to a tank by one projectile fired at it by another player.
+    fn shoot(cvars: &Cvars, weapon: Weapon) -> MilliHp {
+        let (mut gs, mut map, _, vehicle_handle) = setup_tank(cvars);
+        let attacker = gs.players.insert(Player::new("Attacker".to_owned()));
+        let projectile = Projectile {
+            weapon,
+            pos: *gs.vehicles[vehicle_handle].pos - Vec2f::new(30.0, 0.0),
+            vel: Vec2f::new(1000.0, 0.0),
+            angle: 0.0,
+            turn_rate: 0.0,
+            // Only matters for CBs which don't hit vehicles directly.
+            explode_time: if weapon == Weapon::Cb { 0.0 } else { f64::MAX },
+            spawn_time: 0.0,
+            owner: attacker,
+            lock: None,
+            retarget_time: 0.0,
+            altitude: None,
+        };
+        gs.projectiles.insert(projectile);
+
+        gs.dt = 1.0 / 30.0;
+        gs.game_time = gs.dt;
+        gs.frame_num += 1;
+        projectiles(cvars, &mut gs, &mut map);
+        projectiles_timeout(cvars, &mut gs, &mut map);
+        let vehicle = &gs.vehicles[vehicle_handle];
+        vehicle.hp_max(cvars) - vehicle.hp
+    }
+
+    #[test]
+    fn test_damage_types() {
+        let weapons = [
+            (Weapon::Mg, DamageType::Kinetic),
+            (Weapon::Rail, DamageType::Kinetic),
+            (Weapon::Cb, DamageType::Explosive),
+            (Weapon::Rockets, DamageType::Explosive),
+            (Weapon::Hm, DamageType::Explosive),
+            (Weapon::Gm, DamageType::Explosive),
+            (Weapon::Bfg, DamageType::Energy),
+        ];
+        for (weapon, dmg_type) in weapons {
+            assert_eq!(Cvars::default().g_weapon_damage_type(weapon), dmg_type);
+
+            let only = |t| if t == dmg_type { 1.0 } else { 0.0 };
+            let cvars = tank_resistances(
+                only(DamageType::Kinetic),
+                only(DamageType::Explosive),
+                only(DamageType::Energy),
+            );
+            assert!(shoot(&cvars, weapon) > 0, "{:?}", weapon);
+
+            let except = |t| if t == dmg_type { 0.0 } else { 1.0 };
+            let cvars = tank_resistances(
+                except(DamageType::Kinetic),
+                except(DamageType::Explosive),
+                except(DamageType::Energy),
+            );
+            assert_eq!(shoot(&cvars, weapon), 0, "{:?}", weapon);
+        }
+    }
+
+    #[test]
+    fn test_resistances() {
+        let default = Cvars::default();
+        let rockets_direct = fixed::to_milli(default.g_rockets_damage_direct);
+        let rockets = shoot(&default, Weapon::Rockets);
+        let mg = shoot(&default, Weapon::Mg);
+        assert_eq!(rockets, rockets_direct);
+        assert_eq!(mg, fixed::to_milli(default.g_machine_gun_damage));
+
+        let cvars = tank_resistances(1.0, 0.5, 1.0);
+        assert_eq!(
+            shoot(&cvars, Weapon::Rockets),
+            fixed::mul(rockets_direct, 0.5)
+        );
+        assert_eq!(shoot(&cvars, Weapon::Mg), mg);
+    }
+
+    #[test]
+    fn test_armor_resistance_order() {
+        let cvars = Cvars {
+            g_tank_facing_rear: 0.5,
+            g_tank_resistance_kinetic: 0.8,
+            ..Cvars::default()
+        };
+        let (mut gs, _map, _, vehicle_handle) = setup_tank(&cvars);
+        assert_eq!(
+            hit(&cvars, &mut gs, vehicle_handle, Some(Facing::Rear)),
+            4_000
+        );
+        assert_eq!(hit(&cvars, &mut gs, vehicle_handle, None), 8_000);
+    }
+
+    #[test]
+    fn test_armor_pickup() {
+        let cvars = Cvars {
+            g_armor_pickup_count: 1,
+            g_armor_pickup_step: 0.25,
+            ..Cvars::default()
+        };
+        let (mut gs, map, player_handle, vehicle_handle) = setup_tank(&cvars);
+
+        // Ties are broken in a fixed order, always upgrading the weakest.
+        let mut upgraded = Vec::new();
+        for _ in 0..9 {
+            gs.pickups.clear();
+            let pos = *gs.vehicles[vehicle_handle].pos;
+            gs.pickups.insert(Pickup::new(pos, PickupKind::Armor));
+            pickups(&cvars, &mut gs, &map);
+            let armor = &gs.vehicles[vehicle_handle].armor;
+            upgraded.push((armor.front, armor.side, armor.rear, armor.turret));
+        }
+        assert_eq!(
+            upgraded,
+            [
+                (1.0, 1.0, 0.75, 1.0),
+                (1.0, 0.75, 0.75, 1.0),
+                (0.75, 0.75, 0.75, 1.0),
+                (0.75, 0.75, 0.75, 0.75),
+                (0.75, 0.75, 0.5, 0.75),
+                (0.75, 0.5, 0.5, 0.75),
+                (0.5, 0.5, 0.5, 0.75),
+                (0.5, 0.5, 0.5, 0.5),
+                (0.5, 0.5, 0.5, 0.5),
+            ]
+        );
+        // Nothing left to upgrade so the pickup stays.
+        assert_eq!(gs.pickups.len(), 1);
+
+        // Respawning restores the defaults.
+        gs.vehicles.remove(vehicle_handle);
+        spawn_vehicle(&cvars, &mut gs, &map, player_handle, true);
+        let vehicle_handle = gs.players[player_handle].vehicle.unwrap();
+        let vehicle = &gs.vehicles[vehicle_handle];
+        assert_eq!(vehicle.armor, Armor::new(&cvars, vehicle.veh_type));
+    }
+
+    #[test]
+    fn test_ammo_crate() {
+        let cvars = Cvars {
+            g_armor_pickup_count: 0,
+            ..Cvars::default()
+        };
+        let (mut gs, map, _, vehicle1) = setup_tank(&cvars);
+        let pos = *gs.vehicles[vehicle1].pos;
+        let player2 = gs.players.insert(Player::new("Player 2".to_owned()));
+        let vehicle = Vehicle::new(&cvars, pos, 0.0, VehicleType::Tank, 0, 0.0, player2);
+        let vehicle2 = gs.vehicles.insert(vehicle);
+        gs.players[player2].vehicle = Some(vehicle2);
+        for &vehicle_handle in &[vehicle1, vehicle2] {
+            // Reloading vehicles with unlimited reserve don't need it, empty ones do.
+            gs.vehicles[vehicle_handle].ammos[Weapon::Mg as usize] = Ammo::Loaded(0.0, 0);
+        }
+        let crate_handle = gs.pickups.insert(Pickup::new(pos, PickupKind::Ammo));
+        let ammo_max = cvars.g_weapon_reload_ammo(Weapon::Mg);
+        let loaded = |gs: &GameState, vehicle_handle: Index| match gs.vehicles[vehicle_handle].ammos
+            [Weapon::Mg as usize]
+        {
+            Ammo::Loaded(_, count) => count,
+            Ammo::Reloading(_, _) | Ammo::Heat(_, _, _) => 0,
+        };
+
+        // Both touch it in the same frame, only the first in iteration order gets it.
+        gs.game_time = 1.0;
+        pickups(&cvars, &mut gs, &map);
+        assert_eq!(loaded(&gs, vehicle1), ammo_max);
+        assert_eq!(loaded(&gs, vehicle2), 0);
+        let respawn = 1.0 + cvars.g_pickup_respawn_time;
+        assert_eq!(gs.pickups[crate_handle].respawn, Some(respawn));
+
+        // Inactive until it respawns, then the full vehicle leaves it for the other one.
+        gs.game_time = respawn - 0.01;
+        pickups(&cvars, &mut gs, &map);
+        assert_eq!(loaded(&gs, vehicle2), 0);
+        gs.game_time = respawn;
+        pickups(&cvars, &mut gs, &map);
+        assert_eq!(loaded(&gs, vehicle2), ammo_max);
+        assert_eq!(gs.pickups.len(), 1);
+    }
+
+    #[test]
+    fn test_powerup_expiry() {
+        let cvars = Cvars {
+            g_armor_pickup_count: 0,
+            g_powerup_speed_boost_duration: 2.0,
+            g_powerup_speed_boost_multiplier: 1.5,
+            ..Cvars::default()
+        };
+        let (mut gs, map, _, vehicle_handle) = setup_tank(&cvars);
+        gs.dt = 1.0 / 64.0;
+        let pos = *gs.vehicles[vehicle_handle].pos;
+        let kind = PickupKind::Powerup(PowerupKind::SpeedBoost);
+        let pickup_handle = gs.pickups.insert(Pickup::new(pos, kind));
+
+        gs.game_time = 1.0;
+        pickups(&cvars, &mut gs, &map);
+        let respawn = 1.0 + cvars.g_powerup_respawn_time;
+        assert_eq!(gs.pickups[pickup_handle].respawn, Some(respawn));
+        assert_eq!(
+            gs.vehicles[vehicle_handle].speed_multiplier(&cvars, 1.0),
+            1.5
+        );
+
+        // Collecting another one restarts it, the multipliers don't stack.
+        gs.game_time = 2.0;
+        gs.pickups.insert(Pickup::new(pos, kind));
+        pickups(&cvars, &mut gs, &map);
+        let vehicle = &gs.vehicles[vehicle_handle];
+        assert_eq!(vehicle.powerups.len(), 1);
+        assert_eq!(vehicle.powerups[0].until, 4.0);
+        assert_eq!(vehicle.speed_multiplier(&cvars, 2.0), 1.5);
+
+        // Still there on the last tick before it runs out, gone on the tick when it does.
+        gs.game_time = 4.0 - gs.dt;
+        pickups(&cvars, &mut gs, &map);
+        let vehicle = &gs.vehicles[vehicle_handle];
+        assert_eq!(vehicle.speed_multiplier(&cvars, gs.game_time), 1.5);
+        gs.game_time = 4.0;
+        assert_eq!(vehicle.speed_multiplier(&cvars, gs.game_time), 1.0);
+        pickups(&cvars, &mut gs, &map);
+        assert!(gs.vehicles[vehicle_handle].powerups.is_empty());
+    }
+
+    #[test]
+    fn test_shield() {
+        let cvars = Cvars::default();
+        let (mut gs, _map, _, vehicle_handle) = setup_tank(&cvars);
+        let base = hit(&cvars, &mut gs, vehicle_handle, None);
+        assert!(base > 0);
+
+        let vehicle = &mut gs.vehicles[vehicle_handle];
+        vehicle.add_powerup(&cvars, PowerupKind::Shield, 0.0);
+        vehicle.powerups[0].shield = base * 3 / 2;
+        assert_eq!(hit(&cvars, &mut gs, vehicle_handle, None), 0);
+        let left = gs.vehicles[vehicle_handle].powerups[0].shield;
+        assert_eq!(left, base * 3 / 2 - base);
+
+        // A hit bigger than what's left breaks the shield and the rest gets through.
+        assert_eq!(hit(&cvars, &mut gs, vehicle_handle, None), base - left);
+        assert!(gs.vehicles[vehicle_handle].powerups.is_empty());
+        assert_eq!(hit(&cvars, &mut gs, vehicle_handle, None), base);
+
+        // Running out of time ends it too.
+        let vehicle = &mut gs.vehicles[vehicle_handle];
+        vehicle.add_powerup(&cvars, PowerupKind::Shield, 0.0);
+        gs.game_time = cvars.g_powerup_shield_duration;
+        assert_eq!(hit(&cvars, &mut gs, vehicle_handle, None), base);
+    }
+
+    #[test]
+    fn test_reserve_ammo() {
+        let cvars = Cvars {
+            g_machine_gun_reserve: 75,
+            ..Cvars::default()
+        };
+        let (mut gs, map, player_handle, vehicle_handle) = setup_tank(&cvars);
+        let mg = Weapon::Mg as usize;
+        let ammo_max = cvars.g_weapon_reload_ammo(Weapon::Mg);
+        assert_eq!(ammo_max, 50);
+        let vehicle = &mut gs.vehicles[vehicle_handle];
+        assert_eq!(vehicle.reserves[mg], 75);
+
+        // A full magazine, then what's left.
+        vehicle.ammos[mg] = Ammo::Loaded(0.0, 0);
+        vehicle.start_reload(&cvars, Weapon::Mg, 0.0);
+        assert!(matches!(vehicle.ammos[mg], Ammo::Reloading(_, _)));
+        vehicle.finish_reload(&cvars, Weapon::Mg, 1.0);
+        assert_eq!(vehicle.ammos[mg], Ammo::Loaded(1.0, 50));
+        assert_eq!(vehicle.reserves[mg], 25);
+        vehicle.ammos[mg] = Ammo::Loaded(1.0, 0);
+        vehicle.start_reload(&cvars, Weapon::Mg, 1.0);
+        vehicle.finish_reload(&cvars, Weapon::Mg, 2.0);
+        assert_eq!(vehicle.ammos[mg], Ammo::Loaded(2.0, 25));
+        assert_eq!(vehicle.reserves[mg], 0);
+        assert!(!vehicle.weapon_empty(Weapon::Mg));
+
+        // Exactly one magazine left.
+        vehicle.reserves[mg] = 50;
+        vehicle.ammos[mg] = Ammo::Loaded(2.0, 0);
+        vehicle.start_reload(&cvars, Weapon::Mg, 2.0);
+        vehicle.finish_reload(&cvars, Weapon::Mg, 3.0);
+        assert_eq!(vehicle.ammos[mg], Ammo::Loaded(3.0, 50));
+        assert_eq!(vehicle.reserves[mg], 0);
+
+        // Nothing left - no reload.
+        vehicle.ammos[mg] = Ammo::Loaded(3.0, 0);
+        vehicle.start_reload(&cvars, Weapon::Mg, 3.0);
+        assert_eq!(vehicle.ammos[mg], Ammo::Loaded(3.0, 0));
+        assert!(vehicle.weapon_empty(Weapon::Mg));
+
+        // Cycling skips the empty weapon.
+        gs.players[player_handle].cur_weapon = Weapon::Rail;
+        gs.inputs_prev.snapshot(&gs.players);
+        gs.players[player_handle].input.prev_weapon = true;
+        player_logic(&cvars, &mut gs);
+        assert_eq!(gs.players[player_handle].cur_weapon, Weapon::Bfg);
+
+        // A pickup refills the reserve and the magazine.
+        gs.players[player_handle].cur_weapon = Weapon::Mg;
+        let pos = *gs.vehicles[vehicle_handle].pos;
+        let crate_handle = gs.pickups.insert(Pickup::new(pos, PickupKind::Ammo));
+        gs.game_time = 4.0;
+        pickups(&cvars, &mut gs, &map);
+        let vehicle = &mut gs.vehicles[vehicle_handle];
+        assert_eq!(vehicle.ammos[mg], Ammo::Loaded(3.0, 50));
+        assert_eq!(vehicle.reserves[mg], 75);
+        assert!(gs.pickups[crate_handle].respawn.is_some());
+
+        // A pickup during a reload doesn't cut it short but the reload uses the new reserve.
+        vehicle.reserves[mg] = 10;
+        vehicle.ammos[mg] = Ammo::Reloading(4.0, 5.0);
+        gs.pickups[crate_handle].respawn = None;
+        pickups(&cvars, &mut gs, &map);
+        let vehicle = &mut gs.vehicles[vehicle_handle];
+        assert_eq!(vehicle.ammos[mg], Ammo::Reloading(4.0, 5.0));
+        assert_eq!(vehicle.reserves[mg], 75);
+        vehicle.finish_reload(&cvars, Weapon::Mg, 5.0);
+        assert_eq!(vehicle.ammos[mg], Ammo::Loaded(5.0, 50));
+        assert_eq!(vehicle.reserves[mg], 25);
+
+        // Unlimited never runs out.
+        let cvars = Cvars::default();
+        let (mut gs, _, _, vehicle_handle) = setup_tank(&cvars);
+        let vehicle = &mut gs.vehicles[vehicle_handle];
+        vehicle.ammos[mg] = Ammo::Loaded(0.0, 0);
+        vehicle.start_reload(&cvars, Weapon::Mg, 0.0);
+        vehicle.finish_reload(&cvars, Weapon::Mg, 1.0);
+        assert_eq!(vehicle.ammos[mg], Ammo::Loaded(1.0, 50));
+        assert_eq!(vehicle.reserves[mg], RESERVE_UNLIMITED);
+        assert!(!vehicle.refill_reserve(&cvars, Weapon::Mg));
+    }
+
+    #[test]
+    fn test_chain_explosions() {
+        let cvars = Cvars::default();
+        let mut map = map::load_map("0 0 0 0 0 0", surfaces()).unwrap();
+        let mut gs = GameState::new(0);
+        let attacker = gs.players.insert(Player::new("Attacker".to_owned()));
+        // Each one only reaches its neighbors, the last one is out of reach.
+        let mut vehicles = Vec::new();
+        for &x in &[100.0, 150.0, 200.0, 300.0] {
+            let player = gs.players.insert(Player::new("Victim".to_owned()));
+            let pos = Vec2f::new(x, 100.0);
+            let mut vehicle = Vehicle::new(&cvars, pos, 0.0, VehicleType::Tank, 0, 0.0, player);
+            vehicle.hp = 1;
+            vehicles.push(gs.vehicles.insert(vehicle));
+        }
+
+        damage(
+            &cvars,
+            &mut gs,
+            attacker,
+            vehicles[0],
+            Some(Weapon::Mg),
+            MilliHp::MAX,
+            DamageType::Kinetic,
+            None,
+        );
+        assert_eq!(gs.pending_explosions.len(), 1);
+        vehicle_explosions(&cvars, &mut gs, &mut map);
+
+        let destroyed: Vec<_> = vehicles
+            .iter()
+            .map(|&h| gs.vehicles[h].destroyed())
+            .collect();
+        assert_eq!(destroyed, [true, true, true, false]);
+        assert!(gs.pending_explosions.is_empty());
+        // The whole chain is credited to the attacker.
+        assert_eq!(gs.players[attacker].score.kills, 3);
+        // Each destroyed vehicle exploded exactly once.
+        let debris = 3 * cvars.g_vehicle_debris_count as usize;
+        assert_eq!(gs.projectiles.len(), debris);
+        assert_eq!(gs.debris.len(), debris);
+        assert!(gs.debris.values().all(|&credited| credited == attacker));
+
+        // Debris disappears without a trace.
+        gs.game_time = cvars.g_vehicle_debris_time + 1.0 / 60.0;
+        let mut map = map::load_map("0 0 0 0 0 0", surfaces()).unwrap();
+        projectiles_timeout(&cvars, &mut gs, &mut map);
+        assert!(gs.projectiles.is_empty());
+        assert!(gs.debris.is_empty());
+    }
+
+    #[test]
+    fn test_repair_pad() {
+        let cvars = Cvars {
+            g_armor_pickup_count: 0,
+            ..Cvars::default()
+        };
+        let (mut gs, map, _, vehicle_handle) = setup_tank(&cvars);
+        let pos = *gs.vehicles[vehicle_handle].pos;
+        // Overlapping pads don't stack.
+        gs.pickups.insert(Pickup::new(pos, PickupKind::Repair));
+        gs.pickups.insert(Pickup::new(pos, PickupKind::Repair));
+        let hp_max = gs.vehicles[vehicle_handle].hp_max(&cvars);
+        gs.vehicles[vehicle_handle].hp = hp_max / 2;
+        gs.dt = 0.5;
+        pickups(&cvars, &mut gs, &map);
+        let repaired = fixed::to_milli(cvars.g_repair_amount * 0.5);
+        assert_eq!(gs.vehicles[vehicle_handle].hp, hp_max / 2 + repaired);
+
+        // Never above max HP.
+        for _ in 0..100 {
+            pickups(&cvars, &mut gs, &map);
+        }
+        assert_eq!(gs.vehicles[vehicle_handle].hp, hp_max);
+        assert_eq!(gs.pickups.len(), 2);
+
+        // Wrecks stay wrecks.
+        gs.vehicles[vehicle_handle].hp = 0;
+        pickups(&cvars, &mut gs, &map);
+        assert!(gs.vehicles[vehicle_handle].destroyed());
+    }
+
+    fn launch_gm(gs: &mut GameState, player_handle: Index) -> Index {
+        let vehicle = &gs.vehicles[gs.players[player_handle].vehicle.unwrap()];
+        let gm = Projectile {
+            weapon: Weapon::Gm,
+            pos: *vehicle.pos + Vec2f::new(100.0, 0.0),
+            vel: Vec2f::new(100.0, 0.0),
+            angle: 0.0,
+            turn_rate: 0.0,
+            explode_time: f64::MAX,
+            spawn_time: 0.0,
+            owner: player_handle,
+            lock: None,
+            retarget_time: 0.0,
+            altitude: None,
+        };
+        let gm_handle = gs.projectiles.insert(gm);
+        gs.players[player_handle].guided_missile = Some(gm_handle);
+        gm_handle
+    }
+
+    #[test]
+    fn test_gm_control_handoff() {
+        let cvars = Cvars::default();
+        let (mut gs, mut map, player_handle, vehicle_handle) = setup_tank(&cvars);
+        let gm_handle = launch_gm(&mut gs, player_handle);
+        gs.players[player_handle].input = Input {
+            up: true,
+            left: true,
+            ..Input::new()
+        };
+        let pos = *gs.vehicles[vehicle_handle].pos;
+        let angle = *gs.vehicles[vehicle_handle].angle;
+
+        gs.dt = 1.0 / 60.0;
+        for _ in 0..30 {
+            vehicle_movement(&cvars, &mut gs, &map);
+            gm_turning(&cvars, &mut gs);
+        }
+        assert_eq!(*gs.vehicles[vehicle_handle].pos, pos);
+        assert_eq!(*gs.vehicles[vehicle_handle].angle, angle);
+        assert_ne!(gs.projectiles[gm_handle].turn_rate, 0.0);
+
+        // Control returns as soon as the missile is gone.
+        let gm_pos = gs.projectiles[gm_handle].pos;
+        projectile_impact(&cvars, &mut gs, &mut map, gm_handle, gm_pos);
+        assert_eq!(gs.players[player_handle].guided_missile, None);
+        vehicle_movement(&cvars, &mut gs, &map);
+        assert_ne!(*gs.vehicles[vehicle_handle].pos, pos);
+    }
+
+    #[test]
+    fn test_analog_input() {
+        let cvars = Cvars::default();
+        let drive = |input: Input| {
+            let (mut gs, map, player_handle, vehicle_handle) = setup_tank(&cvars);
+            gs.players[player_handle].input = input;
+            gs.dt = 1.0 / 60.0;
+            for _ in 0..30 {
+                vehicle_movement(&cvars, &mut gs, &map);
+            }
+            let vehicle = &gs.vehicles[vehicle_handle];
+            (*vehicle.pos, *vehicle.angle, vehicle.vel.magnitude())
+        };
+
+        // Full deflection is the same as keys and the keys are ignored while analog is present.
+        let keys = Input {
+            up: true,
+            right: true,
+            ..Input::new()
+        };
+        let mut full = Input {
+            left: true,
+            ..Input::new()
+        };
+        full.set_analog(1.0, 5.0);
+        assert_eq!(drive(full), drive(keys));
+
+        let mut half = Input::new();
+        half.set_analog(0.0, 0.5);
+        let half_speed = drive(half).2;
+        assert!(half_speed > 0.0);
+        assert!(half_speed < drive(Input::new_up()).2);
+
+        let mut reverse = Input::new();
+        reverse.set_analog(-0.25, -1.0);
+        assert_eq!(reverse.right_left(), -32.0 / 127.0);
+        assert_eq!((reverse.up(), reverse.down()), (0.0, 1.0));
+        assert_eq!(Input::from_bits(reverse.to_bits()), reverse);
+        assert_eq!(Input::from_bits(keys.to_bits()), keys);
+
+        // Missiles keep the steering but always fly at full throttle.
+        let guiding = reverse.missile_while_guiding();
+        assert_eq!(guiding.right_left(), reverse.right_left());
+        assert_eq!((guiding.up(), guiding.down()), (1.0, 0.0));
+        assert_eq!(reverse.vehicle_while_guiding().right_left(), 0.0);
+    }
+
+    #[test]
+    fn test_gm_hits_owner() {
+        let cvars = Cvars::default();
+        let row = ["0"; 20].join(" ");
+        let text = vec![row; 20].join("\n");
+        let mut map = map::load_map(&text, surfaces()).unwrap();
+        let mut gs = GameState::new(0);
+        let player_handle = gs.players.insert(Player::new("Player".to_owned()));
+        let pos = Vec2f::new(640.0, 640.0);
+        let vehicle = Vehicle::new(&cvars, pos, 0.0, VehicleType::Tank, 0, 0.0, player_handle);
+        let vehicle_handle = gs.vehicles.insert(vehicle);
+        gs.players[player_handle].vehicle = Some(vehicle_handle);
+        gs.vehicles[vehicle_handle].hp = fixed::to_milli(1.0);
+        let gm_handle = launch_gm(&mut gs, player_handle);
+        gs.dt = 1.0 / 60.0;
+
+        // Launched on top of its owner, it flies through during the grace period.
+        gs.projectiles[gm_handle].pos = pos;
+        gs.game_time += gs.dt;
+        projectiles(&cvars, &mut gs, &mut map);
+        assert!(gs.projectiles.contains(gm_handle));
+        assert!(!gs.vehicles[vehicle_handle].destroyed());
+
+        // Loop it around and steer it back at the owner.
+        gs.projectiles[gm_handle].pos = pos + Vec2f::new(100.0, 0.0);
+        for _ in 0..600 {
+            if !gs.projectiles.contains(gm_handle) {
+                break;
+            }
+            let gm = &gs.projectiles[gm_handle];
+            let to_owner = pos - gm.pos;
+            let turned = gm.vel.dot(to_owner) > 0.0;
+            let left = !turned || gm.vel.rotated_z(-PI / 2.0).dot(to_owner) > 0.0;
+            gs.players[player_handle].input = Input {
+                left,
+                right: !left,
+                ..Input::new()
+            };
+            gs.frame_num += 1;
+            gs.game_time += gs.dt;
+            gm_turning(&cvars, &mut gs);
+            projectiles(&cvars, &mut gs, &mut map);
+        }
+        assert!(!gs.projectiles.contains(gm_handle));
+        assert!(gs.vehicles[vehicle_handle].destroyed());
+        let score = &gs.players[player_handle].score;
+        assert_eq!((score.kills, score.deaths, score.suicides), (0, 1, 1));
+    }
+
+    #[test]
+    fn test_gm_detonate_on_fire() {
+        let cvars = Cvars {
+            g_guided_missile_detonate_on_fire: true,
+            ..Cvars::default()
+        };
+        let (mut gs, mut map, player_handle, vehicle_handle) = setup_tank(&cvars);
+        let gm_handle = launch_gm(&mut gs, player_handle);
+        gs.players[player_handle].cur_weapon = Weapon::Mg;
+
+        // Still holding fire from the launch - no detonation and no shooting.
+        gs.players[player_handle].input.fire = true;
+        gs.inputs_prev.snapshot(&gs.players);
+        gm_detonation(&cvars, &mut gs, &mut map);
+        shooting(&cvars, &mut gs);
+        assert!(gs.projectiles.contains(gm_handle));
+        assert_eq!(gs.projectiles.len(), 1);
+
+        // Release and press again.
+        gs.players[player_handle].input.fire = false;
+        gs.inputs_prev.snapshot(&gs.players);
+        gs.players[player_handle].input.fire = true;
+        gm_detonation(&cvars, &mut gs, &mut map);
+        assert!(!gs.projectiles.contains(gm_handle));
+        assert_eq!(gs.players[player_handle].guided_missile, None);
+        assert_eq!(explosions(&gs), 1);
+        assert!(!gs.vehicles[vehicle_handle].destroyed());
+    }
+
+    #[test]
+    fn test_gm_time() {
+        let cvars = Cvars {
+            g_guided_missile_time: 2.0,
+            ..Cvars::default()
+        };
+        let (mut gs, mut map, player_handle, _vehicle_handle) = setup_tank(&cvars);
+        gs.players[player_handle].cur_weapon = Weapon::Gm;
+        gs.players[player_handle].input.fire = true;
+        gs.dt = 1.0 / 60.0;
+        gs.game_time_prev = 1.0;
+        gs.game_time = 1.0 + gs.dt;
+        shooting(&cvars, &mut gs);
+        let gm_handle = gs.players[player_handle].guided_missile.unwrap();
+        let explode_time = gs.projectiles[gm_handle].explode_time;
+        // The weapon was ready so it fired at the beginning of the frame.
+        assert_eq!(explode_time, gs.game_time_prev + 2.0);
+
+        // Out of fuel - it explodes normally and control returns to the vehicle.
+        gs.projectiles[gm_handle].pos.x += 200.0;
+        gs.game_time = explode_time + gs.dt;
+        projectiles_timeout(&cvars, &mut gs, &mut map);
+        assert!(!gs.projectiles.contains(gm_handle));
+        assert_eq!(gs.players[player_handle].guided_missile, None);
+        assert_eq!(explosions(&gs), 1);
+    }
+
+    #[test]
+    fn test_gm_single() {
+        let cvars = Cvars {
+            g_guided_missile_detonate_on_fire: false,
+            ..Cvars::default()
+        };
+        let (mut gs, _map, player_handle, vehicle_handle) = setup_tank(&cvars);
+        gs.players[player_handle].cur_weapon = Weapon::Gm;
+        gs.players[player_handle].input.fire = true;
+        gs.dt = 1.0 / 60.0;
+        gs.game_time = gs.dt;
+        shooting(&cvars, &mut gs);
+        let gm_handle = gs.players[player_handle].guided_missile.unwrap();
+
+        // Reloaded and still holding fire but the first missile is still flying.
+        gs.game_time_prev = 5.0;
+        gs.game_time = 5.0 + gs.dt;
+        gs.vehicles[vehicle_handle].ammos[Weapon::Gm as usize] = Ammo::Loaded(0.0, 1);
+        shooting(&cvars, &mut gs);
+        assert_eq!(gs.projectiles.len(), 1);
+        assert!(gs.projectiles.contains(gm_handle));
+        assert!(matches!(
+            gs.vehicles[vehicle_handle].ammos[Weapon::Gm as usize],
+            Ammo::Loaded(_, 1)
+        ));
+    }
+
+    #[test]
+    fn test_gm_per_owner() {
+        let cvars = Cvars {
+            g_guided_missile_detonate_on_fire: true,
+            ..Cvars::default()
+        };
+        let (mut gs, mut map, p1, v1) = setup_tank(&cvars);
+        let p2 = gs.players.insert(Player::new("Player 2".to_owned()));
+        let pos = map.tile_center(Vec2u::new(4, 2));
+        let vehicle = Vehicle::new(&cvars, pos, PI, VehicleType::Tank, 0, 0.0, p2);
+        let v2 = gs.vehicles.insert(vehicle);
+        gs.players[p2].vehicle = Some(v2);
+        for player_handle in [p1, p2] {
+            gs.players[player_handle].cur_weapon = Weapon::Gm;
+            gs.players[player_handle].input.fire = true;
+        }
+        gs.dt = 1.0 / 60.0;
+        gs.game_time = gs.dt;
+        shooting(&cvars, &mut gs);
+        let gm1 = gs.players[p1].guided_missile.unwrap();
+        let gm2 = gs.players[p2].guided_missile.unwrap();
+        assert_ne!(gm1, gm2);
+        assert_eq!(gs.projectiles[gm1].owner, p1);
+        assert_eq!(gs.projectiles[gm2].owner, p2);
+
+        // Each owner steers only their own missile.
+        gs.players[p1].input = Input {
+            left: true,
+            ..Input::new()
+        };
+        gs.players[p2].input = Input {
+            right: true,
+            ..Input::new()
+        };
+        let positions = [*gs.vehicles[v1].pos, *gs.vehicles[v2].pos];
+        for _ in 0..10 {
+            vehicle_movement(&cvars, &mut gs, &map);
+            gm_turning(&cvars, &mut gs);
+        }
+        assert_eq!([*gs.vehicles[v1].pos, *gs.vehicles[v2].pos], positions);
+        assert!(gs.projectiles[gm1].turn_rate < 0.0);
+        assert!(gs.projectiles[gm2].turn_rate > 0.0);
+
+        // Pressing fire detonates only the presser's missile.
+        gs.inputs_prev.snapshot(&gs.players);
+        gs.players[p1].input.fire = true;
+        gm_detonation(&cvars, &mut gs, &mut map);
+        assert!(!gs.projectiles.contains(gm1));
+        assert_eq!(gs.players[p1].guided_missile, None);
+        assert_eq!(gs.players[p2].guided_missile, Some(gm2));
+
+        gs.inputs_prev.snapshot(&gs.players);
+        gs.players[p2].input.fire = true;
+        gm_detonation(&cvars, &mut gs, &mut map);
+        assert!(!gs.projectiles.contains(gm2));
+        assert_eq!(gs.players[p2].guided_missile, None);
+        assert!(gs.projectiles.is_empty());
+    }
+
+    #[test]
+    fn test_rng_streams_independent() {
+        // Velocities of the MG rounds fired in a second after drawing from one stream.
+        let mg_vels = |draw: fn(&mut GameRng)| {
+            let cvars = Cvars::default();
+            let (mut gs, _map, player_handle, _vehicle_handle) = setup_tank(&cvars);
+            draw(&mut gs.rng);
+            gs.players[player_handle].cur_weapon = Weapon::Mg;
+            gs.players[player_handle].input.fire = true;
+            gs.dt = 1.0 / 60.0;
+            for frame in 1..=60 {
+                gs.game_time_prev = gs.game_time;
+                gs.game_time = frame as f64 / 60.0;
+                shooting(&cvars, &mut gs);
+            }
+            gs.projectiles
+                .iter()
+                .map(|(_, projectile)| projectile.vel)
+                .collect::<Vec<_>>()
+        };
+        let spread = mg_vels(|_| {});
+        assert!(spread.len() > 1);
+        assert_eq!(
+            mg_vels(|rng| {
+                let _: f64 = rng.ai.gen();
+            }),
+            spread
+        );
+        assert_ne!(
+            mg_vels(|rng| {
+                let _: f64 = rng.weapon_spread.gen();
+            }),
+            spread
+        );
+    }
+
+    /// Rounds fired by holding the MG trigger for `secs` at the given frame rate.
+    fn mg_rounds(cvars: &Cvars, fps: f64, secs: f64) -> usize {
+        let (mut gs, _map, player_handle, _vehicle_handle) = setup_tank(cvars);
+        gs.players[player_handle].cur_weapon = Weapon::Mg;
+        gs.players[player_handle].input.fire = true;
+        gs.dt = 1.0 / fps;
+        for frame in 1..=(secs * fps).round() as usize {
+            gs.game_time_prev = gs.game_time;
+            gs.game_time = frame as f64 / fps;
+            shooting(cvars, &mut gs);
+        }
+        gs.projectiles.len()
+    }
+
+    #[test]
+    fn test_refire_frame_rate_independent() {
+        let cvars = Cvars::default();
+        let slow = mg_rounds(&cvars, 10.0, 2.0);
+        let fast = mg_rounds(&cvars, 120.0, 2.0);
+        assert!(
+            (slow as i64 - fast as i64).abs() <= 1,
+            "{} vs {}",
+            slow,
+            fast
+        );
+        let expected = (2.0 / cvars.g_machine_gun_refire) as i64;
+        assert!(
+            (fast as i64 - expected).abs() <= 1,
+            "{} vs {}",
+            fast,
+            expected
+        );
+
+        // Running out of ammo in the middle of a frame's burst.
+        let cvars = Cvars {
+            g_machine_gun_reload_ammo: 7,
+            g_machine_gun_reload_time: 0.33,
+            ..Cvars::default()
+        };
+        let slow = mg_rounds(&cvars, 10.0, 3.0);
+        let fast = mg_rounds(&cvars, 120.0, 3.0);
+        assert!(
+            (slow as i64 - fast as i64).abs() <= 1,
+            "{} vs {}",
+            slow,
+            fast
+        );
+    }
+
+    #[test]
+    fn test_cooldown_group() {
+        let cvars = Cvars {
+            g_cooldown_group_explosive: 0.5,
+            g_homing_missile_cooldown_group: CooldownGroup::Explosive,
+            g_homing_missile_reload_time: 0.1,
+            g_rockets_cooldown_group: CooldownGroup::Explosive,
+            g_rockets_refire: 0.05,
+            g_rockets_reload_ammo: 100,
+            ..Cvars::default()
+        };
+        let (mut gs, _map, player_handle, vehicle_handle) = setup_tank(&cvars);
+        gs.players[player_handle].input.fire = true;
+        gs.dt = 1.0 / 60.0;
+        let mut shots = Vec::new();
+        for frame in 1..=180 {
+            // Keep switching to try to get around the refire of each weapon.
+            gs.players[player_handle].cur_weapon = if frame / 7 % 2 == 0 {
+                Weapon::Rockets
+            } else {
+                Weapon::Hm
+            };
+            gs.game_time_prev = gs.game_time;
+            gs.game_time = frame as f64 / 60.0;
+            let before = gs.projectiles.len();
+            vehicle_logic(&cvars, &mut gs);
+            shooting(&cvars, &mut gs);
+            if gs.projectiles.len() > before {
+                let vehicle = &gs.vehicles[vehicle_handle];
+                shots.push((vehicle.last_shot_time, gs.players[player_handle].cur_weapon));
+            }
+        }
+        assert!(shots.iter().any(|&(_, weapon)| weapon == Weapon::Rockets));
+        assert!(shots.iter().any(|&(_, weapon)| weapon == Weapon::Hm));
+        for pair in shots.windows(2) {
+            assert!(pair[1].0 - pair[0].0 >= 0.5 - 1e-9, "{:?}", shots);
+        }
+        assert_eq!(shots.len(), 7, "{:?}", shots);
+    }
+
+    #[test]
+    fn test_fire_rate_cap() {
+        let cvars = Cvars {
+            g_fire_rate_cap: 10.0,
+            g_machine_gun_refire: 0.000_001,
+            g_machine_gun_reload_ammo: 1_000_000,
+            ..Cvars::default()
+        };
+        // Shots over the cap are delayed, not lost - it keeps firing at exactly the cap.
+        for fps in [10.0, 60.0, 144.0] {
+            let rounds = mg_rounds(&cvars, fps, 2.0);
+            assert!((20..=21).contains(&rounds), "{} at {} FPS", rounds, fps);
+        }
+
+        // Deferred shots don't use ammo.
+        let (mut gs, _map, player_handle, vehicle_handle) = setup_tank(&cvars);
+        gs.players[player_handle].input.fire = true;
+        gs.dt = 1.0 / 60.0;
+        for frame in 1..=60 {
+            gs.game_time_prev = gs.game_time;
+            gs.game_time = frame as f64 / 60.0;
+            shooting(&cvars, &mut gs);
+        }
+        let shots = gs.projectiles.len() as u32;
+        match gs.vehicles[vehicle_handle].ammos[Weapon::Mg as usize] {
+            Ammo::Loaded(_, count) => assert_eq!(count, 1_000_000 - shots),
+            ammo => panic!("{:?}", ammo),
+        }
+    }
+
+    #[test]
+    fn test_heat_limiter() {
+        // Without dissipation it overheats after exactly 8 shots and never cools down,
+        // even when a single frame's burst would go past it.
+        let cvars = Cvars {
+            g_machine_gun_fire_limiter: FireLimiter::Heat,
+            g_machine_gun_heat_dissipation: 0.0,
+            g_machine_gun_heat_per_shot: 0.125,
+            ..Cvars::default()
+        };
+        for fps in [5.0, 60.0, 144.0] {
+            assert_eq!(mg_rounds(&cvars, fps, 3.0), 8, "at {} FPS", fps);
+        }
+
+        // With dissipation it's locked out until it cools down to `g_weapon_heat_unlock`.
+        let cvars = Cvars {
+            g_machine_gun_fire_limiter: FireLimiter::Heat,
+            g_machine_gun_heat_dissipation: 0.5,
+            g_machine_gun_heat_per_shot: 0.125,
+            ..Cvars::default()
+        };
+        let (mut gs, _map, player_handle, vehicle_handle) = setup_tank(&cvars);
+        gs.players[player_handle].cur_weapon = Weapon::Mg;
+        gs.players[player_handle].input.fire = true;
+        gs.dt = 1.0 / 60.0;
+        let mut shot_times = Vec::new();
+        for frame in 1..=300 {
+            gs.game_time_prev = gs.game_time;
+            gs.game_time = frame as f64 / 60.0;
+            vehicle_logic(&cvars, &mut gs);
+            shooting(&cvars, &mut gs);
+            for (_, proj) in gs.projectiles.drain() {
+                shot_times.push(proj.spawn_time);
+            }
+            let status =
+                gs.vehicles[vehicle_handle].weapon_status(&cvars, Weapon::Mg, gs.game_time);
+            let heat = status.heat.unwrap();
+            assert!((0.0..1.0 + 0.125).contains(&heat), "{}", heat);
+        }
+        shot_times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let lockouts: Vec<_> = shot_times
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .filter(|&gap| gap > 2.0 * cvars.g_machine_gun_refire)
+            .collect();
+        assert!(!lockouts.is_empty(), "{:?}", shot_times);
+        let min = (1.0 - cvars.g_weapon_heat_unlock) / 0.5 - gs.dt;
+        let max = (1.125 - cvars.g_weapon_heat_unlock) / 0.5 + cvars.g_machine_gun_refire + gs.dt;
+        for gap in lockouts {
+            assert!(gap >= min && gap <= max, "{} not in {}..{}", gap, min, max);
+        }
+    }
+
+    /// Where each MG round fired by a vehicle driving right started, in order of firing.
+    fn moving_mg_origins(cvars: &Cvars, fps: f64, secs: f64) -> Vec<Vec2f> {
+        let mut row = vec!["0"; 40];
+        row[1] = "12";
+        let text = [vec!["0"; 40], row, vec!["0"; 40]]
+            .iter()
+            .map(|row| row.join(" "))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let map = map::load_map(&text, surfaces()).unwrap();
+        let mut gs = GameState::new(0);
+        let player_handle = gs.players.insert(Player::new("Player".to_owned()));
+        let pos = map.tile_center(map.spawns()[0]);
+        let vehicle = Vehicle::new(cvars, pos, 0.0, VehicleType::Tank, 0, 0.0, player_handle);
+        let vehicle_handle = gs.vehicles.insert(vehicle);
+        gs.players[player_handle].vehicle = Some(vehicle_handle);
+        gs.players[player_handle].cur_weapon = Weapon::Mg;
+        gs.players[player_handle].input.fire = true;
+        gs.players[player_handle].input.up = true;
+        gs.dt = 1.0 / fps;
+
+        let mut origins = Vec::new();
+        for frame in 1..=(secs * fps).round() as usize {
+            gs.game_time_prev = gs.game_time;
+            gs.game_time = frame as f64 / fps;
+            let before = gs.projectiles.len();
+            vehicle_movement(cvars, &mut gs, &map);
+            shooting(cvars, &mut gs);
+            assert!(gs.projectiles.len() > before);
+            let mut new: Vec<_> = gs
+                .projectiles
+                .drain()
+                .map(|(_, proj)| {
+                    let shot_age = proj.spawn_time - gs.game_time_prev;
+                    (proj.spawn_time, proj.pos + proj.vel * shot_age)
+                })
+                .collect();
+            new.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            origins.extend(new.into_iter().map(|(_, origin)| origin));
+        }
+        origins
+    }
+
+    #[test]
+    fn test_shots_from_moving_vehicle() {
+        // Several rounds per frame at max speed - all evenly spaced along the path,
+        // including across frames.
+        let cvars = Cvars {
+            g_machine_gun_angle_spread: 0.0,
+            g_machine_gun_reload_ammo: 1000,
+            g_tank_accel_forward: 10_000.0,
+            g_tank_speed_max: 300.0,
+            ..Cvars::default()
+        };
+        let origins = moving_mg_origins(&cvars, 10.0, 1.0);
+        assert_eq!(origins.len(), 20);
+        let spacing = 300.0 * cvars.g_machine_gun_refire;
+        // The first frame accelerates.
+        for pair in origins[2..].windows(2) {
+            let step = pair[1] - pair[0];
+            assert!((step.x - spacing).abs() < 1e-6, "{:?}", origins);
+            assert!(step.y.abs() < 1e-6, "{:?}", origins);
+        }
+
+        // While accelerating, rounds fired later are never closer together,
+        // even when the frame's speed up happens between them.
+        let cvars = Cvars {
+            g_tank_accel_forward: 200.0,
+            g_tank_speed_max: f64::INFINITY,
+            ..cvars
+        };
+        let origins = moving_mg_origins(&cvars, 10.0, 1.0);
+        let steps: Vec<_> = origins
+            .windows(2)
+            .map(|pair| pair[1].x - pair[0].x)
+            .collect();
+        for pair in steps.windows(2) {
+            assert!(pair[1] >= pair[0] - 1e-9, "{:?}", steps);
+        }
+    }
+
+    #[test]
+    fn test_shot_transform() {
+        let cvars = Cvars::default();
+        let cases = [
+            // Turret
+            (VehicleType::Tank, Weapon::Mg),
+            (VehicleType::Tank, Weapon::Rail),
+            (VehicleType::Hovercraft, Weapon::Rockets),
+            (VehicleType::Hummer, Weapon::Cb),
+            // Chassis
+            (VehicleType::Hummer, Weapon::Mg),
+            (VehicleType::Hummer, Weapon::Bfg),
+            (VehicleType::Tank, Weapon::Hm),
+        ];
+        for (veh_type, weapon) in cases {
+            for angle in [0.0, 1.0, -2.5, PI] {
+                for turret_angle in [0.0, 0.7, -PI / 2.0] {
+                    let (mut gs, _map, player_handle, vehicle_handle) = setup_tank(&cvars);
+                    let vehicle = &mut gs.vehicles[vehicle_handle];
+                    vehicle.veh_type = veh_type;
+                    vehicle.angle.set(angle, 0);
+                    vehicle.turret_angle_current = turret_angle;
+                    vehicle.vel = Vec2f::new(30.0, -40.0);
+                    let player = &mut gs.players[player_handle];
+                    player.cur_weapon = weapon;
+                    player.input.fire = true;
+                    gs.dt = 1.0 / 60.0;
+                    gs.game_time = gs.dt;
+
+                    let vehicle = gs.vehicles[vehicle_handle].clone();
+                    let transforms = shot_transforms(&cvars, &vehicle, weapon, *vehicle.pos, angle);
+                    assert_eq!(transforms.len(), 1);
+                    let (origin, shot_angle) = transforms[0];
+                    shooting(&cvars, &mut gs);
+                    assert!(!gs.projectiles.is_empty());
+                    for (_, projectile) in gs.projectiles.iter() {
+                        assert_eq!(projectile.pos, origin);
+                        assert_eq!(projectile.angle, shot_angle);
+                    }
+
+                    // Chassis weapons ignore the turret.
+                    let (hardpoint, weapon_offset) =
+                        cvars.g_hardpoints(veh_type, weapon).barrels()[0];
+                    match hardpoint {
+                        Hardpoint::Chassis => {
+                            assert_eq!(shot_angle, angle);
+                            let offset = weapon_offset.rotated_z(angle);
+                            assert!((origin - *vehicle.pos - offset).magnitude() < 1e-9);
+                        }
+                        Hardpoint::Turret => assert_eq!(shot_angle, angle + turret_angle),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_mixed_mount() {
+        let cvars = Cvars {
+            g_hardpoint_tank_machine_gun: "Turret 12 -5; Chassis 10 9".parse().unwrap(),
+            ..Cvars::default()
+        };
+        assert_eq!(
+            cvars.g_hardpoint_tank_machine_gun.to_string(),
+            "Turret 12 -5; Chassis 10 9"
+        );
+        let too_many = ["Turret 0 0"; 5].join("; ");
+        assert!(cvars
+            .clone()
+            .set_str("g_hardpoint_tank_machine_gun", &too_many)
+            .is_err());
+        let (mut gs, _map, player_handle, vehicle_handle) = setup_tank(&cvars);
+        gs.vehicles[vehicle_handle].turret_angle_current = PI / 2.0;
+        let player = &mut gs.players[player_handle];
+        player.cur_weapon = Weapon::Mg;
+        player.input.fire = true;
+        gs.dt = 1.0 / 60.0;
+        gs.game_time = gs.dt;
+        let ammo_before = gs.vehicles[vehicle_handle].ammos[Weapon::Mg as usize];
+
+        shooting(&cvars, &mut gs);
+        let mut angles: Vec<_> = gs.projectiles.iter().map(|(_, proj)| proj.angle).collect();
+        angles.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(angles, vec![0.0, PI / 2.0]);
+
+        // Both barrels fired one shot's worth of ammo.
+        let ammo_count = |ammo: &Ammo| match *ammo {
+            Ammo::Loaded(_, count) => count,
+            Ammo::Reloading(..) | Ammo::Heat(..) => 0,
+        };
+        let ammo_after = &gs.vehicles[vehicle_handle].ammos[Weapon::Mg as usize];
+        assert_eq!(ammo_count(&ammo_before) - ammo_count(ammo_after), 1);
+    }
+
+    #[test]
+    fn test_muzzle_flashes() {
+        let flashes = |cvars: &Cvars| {
+            let (mut gs, _map, player_handle, vehicle_handle) = setup_tank(cvars);
+            let player = &mut gs.players[player_handle];
+            player.cur_weapon = Weapon::Mg;
+            player.input.fire = true;
+            gs.dt = 1.0 / 60.0;
+            gs.game_time = gs.dt;
+            shooting(cvars, &mut gs);
+
+            let mut angles: Vec<_> = gs.projectiles.iter().map(|(_, proj)| proj.angle).collect();
+            angles.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let mut flashes = Vec::new();
+            for effect in &gs.effects {
+                if let Effect::MuzzleFlash {
+                    angle,
+                    weapon,
+                    vehicle,
+                    start_time,
+                    ..
+                } = *effect
+                {
+                    assert_eq!(weapon, Weapon::Mg);
+                    assert_eq!(vehicle, vehicle_handle);
+                    assert!(start_time <= gs.game_time);
+                    flashes.push(angle);
+                }
+            }
+            flashes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            if !flashes.is_empty() {
+                assert_eq!(flashes, angles);
+            }
+            flashes.len()
+        };
+
+        // One per barrel.
+        let cvars = Cvars {
+            g_hardpoint_tank_machine_gun: "Turret 12 -5; Chassis 10 9".parse().unwrap(),
+            ..Cvars::default()
+        };
+        assert_eq!(flashes(&cvars), 2);
+
+        let cvars = Cvars {
+            cl_muzzle_flash: false,
+            cl_recoil: false,
+            ..cvars
+        };
+        assert_eq!(flashes(&cvars), 0);
+    }
+
+    #[test]
+    fn test_gm_owner_death() {
+        for detonate in [false, true] {
+            let cvars = Cvars {
+                g_guided_missile_detonate_on_owner_death: detonate,
+                ..Cvars::default()
+            };
+            let (mut gs, mut map, player_handle, vehicle_handle) = setup_tank(&cvars);
+            let gm_handle = launch_gm(&mut gs, player_handle);
+
+            damage(
+                &cvars,
+                &mut gs,
+                player_handle,
+                vehicle_handle,
+                None,
+                MilliHp::MAX,
+                DamageType::Explosive,
+                None,
+            );
+            assert_eq!(gs.players[player_handle].guided_missile, None);
+            vehicle_explosions(&cvars, &mut gs, &mut map);
+            assert_eq!(gs.projectiles.contains(gm_handle), !detonate);
+        }
+    }
+
+    #[test]
+    fn test_kill_after_respawn() {
+        // Projectiles, scores and notifications refer to players, not vehicles,
+        // so a rocket fired before its owner died and respawned still counts for them.
+        let cvars = Cvars::default();
+        let (mut gs, mut map, p1, v1) = setup_tank(&cvars);
+        let p2 = gs.players.insert(Player::new("Player 2".to_owned()));
+        let pos = map.tile_center(Vec2u::new(4, 1));
+        let vehicle = Vehicle::new(&cvars, pos, PI, VehicleType::Tank, 0, 0.0, p2);
+        let v2 = gs.vehicles.insert(vehicle);
+        gs.players[p2].vehicle = Some(v2);
+        let rocket = Projectile {
+            weapon: Weapon::Rockets,
+            pos: pos - Vec2f::new(40.0, 0.0),
+            vel: Vec2f::new(600.0, 0.0),
+            angle: 0.0,
+            turn_rate: 0.0,
+            explode_time: f64::MAX,
+            spawn_time: 0.0,
+            owner: p1,
+            lock: None,
+            retarget_time: 0.0,
+            altitude: None,
+        };
+        let rocket_handle = gs.projectiles.insert(rocket);
+
+        damage(
+            &cvars,
+            &mut gs,
+            p2,
+            v1,
+            None,
+            MilliHp::MAX,
+            DamageType::Explosive,
+            None,
+        );
+        assert!(gs.vehicles[v1].destroyed());
+        gs.players[p1].respawn = Respawn::Scheduled;
+        gs.game_time = cvars.g_respawn_delay + 1.0;
+        respawning(&cvars, &mut gs, &map);
+        let v1_new = gs.players[p1].vehicle.unwrap();
+        assert_ne!(v1_new, v1);
+        assert!(!gs.vehicles.contains(v1));
+        assert!(!gs.vehicles[v1_new].destroyed());
+
+        gs.vehicles[v2].hp = 1;
+        gs.dt = 1.0 / 60.0;
+        for _ in 0..10 {
+            projectiles(&cvars, &mut gs, &mut map);
+        }
+        assert!(!gs.projectiles.contains(rocket_handle));
+        assert!(gs.vehicles[v2].destroyed());
+        let (s1, s2) = (&gs.players[p1].score, &gs.players[p2].score);
+        assert_eq!((s1.kills, s1.deaths), (1, 1));
+        assert_eq!((s2.kills, s2.deaths), (1, 1));
+        assert_eq!(s1.weapons[Weapon::Rockets as usize].hits, 1);
+    }
+
+    #[test]
+    fn test_friendly_fire_splash() {
+        // HP lost by the shooter, two teammates on either side of the explosion and an enemy.
+        let blast = |friendly_fire| {
+            let cvars = Cvars {
+                g_friendly_fire: friendly_fire,
+                g_rockets_explosion_damage: 30.0,
+                g_rockets_explosion_radius: 40.0,
+                ..Cvars::default()
+            };
+            let (mut gs, mut map, shooter, shooter_vehicle) = setup_tank(&cvars);
+            gs.players[shooter].team = Some(Team::Red);
+            let mut vehicles = vec![shooter_vehicle];
+            for &(x, y, team) in &[
+                (40.0, 40.0, Team::Red),
+                (100.0, 40.0, Team::Red),
+                (70.0, 75.0, Team::Blue),
+            ] {
+                let player_handle = gs.players.insert(Player::new("Player".to_owned()));
+                gs.players[player_handle].team = Some(team);
+                let pos = Vec2f::new(x, y);
+                let vehicle =
+                    Vehicle::new(&cvars, pos, 0.0, VehicleType::Tank, 0, 0.0, player_handle);
+                let vehicle_handle = gs.vehicles.insert(vehicle);
+                gs.players[player_handle].vehicle = Some(vehicle_handle);
+                vehicles.push(vehicle_handle);
+            }
+            let hp_before: Vec<_> = vehicles.iter().map(|&v| gs.vehicles[v].hp).collect();
+
+            let rocket = fire_rocket(&mut gs, shooter, Vec2f::new(70.0, 40.0));
+            gs.projectiles[rocket].explode_time = gs.game_time;
+            gs.game_time += 1.0 / 60.0;
+            projectiles_timeout(&cvars, &mut gs, &mut map);
+            assert!(gs.projectiles.is_empty());
+
+            let lost: Vec<_> = vehicles
+                .iter()
+                .zip(hp_before)
+                .map(|(&v, hp)| hp - gs.vehicles[v].hp)
+                .collect();
+            lost
+        };
+
+        let off = blast(FriendlyFire::Off);
+        assert_eq!(off[..3], [0, 0, 0]);
+        assert!(off[3] > 0);
+
+        let on = blast(FriendlyFire::On);
+        assert_eq!(on[0], 0);
+        assert!(on[1] > 0 && on[2] > 0);
+        assert_eq!(on[3], off[3]);
+
+        // The shooter takes exactly what the teammates would have, after their armor.
+        let reflect = blast(FriendlyFire::Reflect);
+        assert_eq!(reflect, [on[1] + on[2], 0, 0, off[3]]);
+    }
+
+    #[test]
+    fn test_team_spawns() {
+        let cvars = Cvars::default();
+        let text = ["12 12 12", "team_spawn 0 0 0", "team_spawn 2 0 1"].join("\n");
+        let map = map::load_map(&text, surfaces()).unwrap();
+        let mut gs = GameState::new(0);
+        for &team in &[Team::Red, Team::Blue] {
+            let player_handle = gs.players.insert(Player::new("Player".to_owned()));
+            gs.players[player_handle].team = Some(team);
+            for _ in 0..10 {
+                spawn_vehicle(&cvars, &mut gs, &map, player_handle, true);
+                let vehicle_handle = gs.players[player_handle].vehicle.unwrap();
+                let tile = map.tile_pos(*gs.vehicles[vehicle_handle].pos).index;
+                assert_eq!(map.team_spawns(team), [tile]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_projectiles_soak() {
+        // Slow enough to never reach a wall so only their lifetime removes them.
+        // Fewer and longer frames than usual so it finishes quickly in debug builds.
+        let cvars = Cvars {
+            g_machine_gun_refire: 0.25,
+            g_machine_gun_speed: 5.0,
+            ..Cvars::default()
+        };
+        let (mut gs, mut map, player_handle, _) = setup_tank(&cvars);
+        gs.players[player_handle].input.fire = true;
+
+        let dt = 0.25;
+        let mut fired = 0;
+        let mut most = 0;
+        while gs.game_time < 10_000.0 {
+            gs.game_time_prev = gs.game_time;
+            gs.game_time += dt;
+            gs.dt = dt;
+            gs.frame_num += 1;
+            cleanup(&cvars, &mut gs);
+            vehicle_logic(&cvars, &mut gs);
+            shooting(&cvars, &mut gs);
+            fired += gs.shots.len();
+            projectiles(&cvars, &mut gs, &mut map);
+            projectiles_timeout(&cvars, &mut gs, &mut map);
+            most = most.max(gs.projectiles.len());
+        }
+
+        let lifetime_max = cvars.g_machine_gun_lifetime / cvars.g_machine_gun_refire;
+        assert!(fired > 10_000, "{}", fired);
+        assert!(most as f64 <= lifetime_max + 1.0, "{}", most);
+        assert!(most as f64 >= lifetime_max / 2.0, "{}", most);
+    }
+
+    #[test]
+    fn test_projectile_rays() {
+        let rays = |cvars: &Cvars| {
+            let (mut gs, mut map, player_handle, _) = setup_tank(cvars);
+            let pos = Vec2f::new(TILE_SIZE / 2.0, TILE_SIZE / 2.0);
+            gs.projectiles.insert(Projectile {
+                weapon: Weapon::Mg,
+                pos,
+                vel: Vec2f::new(100.0, 0.0),
+                angle: 0.0,
+                turn_rate: 0.0,
+                explode_time: f64::MAX,
+                spawn_time: 0.0,
+                owner: player_handle,
+                lock: None,
+                retarget_time: 0.0,
+                altitude: None,
+            });
+            gs.dt = 0.1;
+            projectiles(cvars, &mut gs, &mut map);
+            let rays = gs.projectile_rays.clone();
+            cleanup(cvars, &mut gs);
+            assert!(gs.projectile_rays.is_empty());
+            rays
+        };
+
+        assert!(rays(&Cvars::default()).is_empty());
+        let cvars = Cvars {
+            d_draw_projectile_rays: true,
+            ..Cvars::default()
+        };
+        let pos = Vec2f::new(TILE_SIZE / 2.0, TILE_SIZE / 2.0);
+        assert_eq!(rays(&cvars), [(pos, pos + Vec2f::new(10.0, 0.0))]);
+    }
+
+    #[test]
+    fn test_wind() {
+        let fly = |cvars: &Cvars, weapon| {
+            let (mut gs, mut map, player_handle, _) = setup_tank(cvars);
+            let handle = gs.projectiles.insert(Projectile {
+                weapon,
+                pos: Vec2f::new(TILE_SIZE / 2.0, TILE_SIZE / 2.0),
+                vel: Vec2f::new(100.0, 0.0),
+                angle: 0.0,
+                turn_rate: 0.0,
+                explode_time: f64::MAX,
+                spawn_time: 0.0,
+                owner: player_handle,
+                lock: None,
+                retarget_time: 0.0,
+                altitude: None,
+            });
+            gs.dt = 0.01;
+            for _ in 0..10 {
+                projectiles(cvars, &mut gs, &mut map);
+            }
+            gs.projectiles[handle].pos
+        };
+
+        let calm = Cvars::default();
+        let windy = Cvars {
+            g_wind_y: 100.0,
+            ..Cvars::default()
+        };
+        let still = fly(&calm, Weapon::Rockets);
+        let blown = fly(&windy, Weapon::Rockets);
+        assert_eq!(blown.x, still.x);
+        // Velocity grows by 1 px/s each tick so the displacement is (1 + 2 + ... + 10) * dt.
+        assert!((blown.y - still.y - 0.55).abs() < 1e-9);
+        assert_eq!(fly(&windy, Weapon::Rockets), blown);
+
+        assert_eq!(fly(&windy, Weapon::Rail), fly(&calm, Weapon::Rail));
+        assert_eq!(fly(&windy, Weapon::Bfg), fly(&calm, Weapon::Bfg));
+    }
+
+    #[test]
+    fn test_mg_ricochet() {
+        let cvars = Cvars {
+            g_machine_gun_ricochet_chance: 1.0,
+            g_projectile_max_bounces: 2,
+            ..Cvars::default()
+        };
+        let shoot = |gs: &mut GameState, player_handle, pos, vel| {
+            gs.projectiles.insert(Projectile {
+                weapon: Weapon::Mg,
+                pos,
+                vel,
+                angle: 0.0,
+                turn_rate: 0.0,
+                explode_time: f64::MAX,
+                spawn_time: 0.0,
+                owner: player_handle,
+                lock: None,
+                retarget_time: 0.0,
+                altitude: None,
+            })
+        };
+        let (mut gs, mut map, player_handle, _) = setup_tank(&cvars);
+        gs.dt = 1.0 / 60.0;
+
+        // Grazing the top edge of the map (outside counts as wall) ricochets.
+        let vel = Vec2f::new(1200.0, -300.0);
+        let handle = shoot(&mut gs, player_handle, Vec2f::new(40.0, 3.0), vel);
+        projectiles(&cvars, &mut gs, &mut map);
+        let projectile = &gs.projectiles[handle];
+        let expected = Vec2f::new(1200.0, 300.0) * cvars.g_machine_gun_ricochet_retention;
+        assert!((projectile.vel - expected).magnitude() < 1e-9);
+        assert!(!map.is_wall(projectile.pos));
+        assert!((projectile.pos.y - 0.0).abs() < 0.1);
+        assert_eq!(gs.bounces[&handle], 1);
+
+        // Head-on is too steep.
+        let handle = shoot(
+            &mut gs,
+            player_handle,
+            Vec2f::new(40.0, 3.0),
+            Vec2f::new(0.0, -1000.0),
+        );
+        projectiles(&cvars, &mut gs, &mut map);
+        assert!(!gs.projectiles.contains(handle));
+
+        // Out of bounces.
+        let handle = shoot(&mut gs, player_handle, Vec2f::new(40.0, 3.0), vel);
+        gs.bounces.insert(handle, cvars.g_projectile_max_bounces);
+        projectiles(&cvars, &mut gs, &mut map);
+        assert!(!gs.projectiles.contains(handle));
+        assert!(!gs.bounces.contains_key(&handle));
+
+        // Disabled - no RNG used.
+        let cvars = Cvars {
+            g_machine_gun_ricochet_chance: 0.0,
+            ..cvars
+        };
+        let rng_before = gs.rng.weapon_spread.clone().gen::<u64>();
+        let handle = shoot(&mut gs, player_handle, Vec2f::new(40.0, 3.0), vel);
+        projectiles(&cvars, &mut gs, &mut map);
+        assert!(!gs.projectiles.contains(handle));
+        assert_eq!(gs.rng.weapon_spread.gen::<u64>(), rng_before);
+    }
+
+    /// Fire an arcing projectile right along an open map, returns where it exploded.
+    fn fly_arc(cvars: &Cvars, weapon: Weapon, target: Option<Vec2f>) -> (GameState, Vec2f) {
+        let row = vec!["0"; 20].join(" ");
+        let text = vec![row; 3].join("\n");
+        let mut map = map::load_map(&text, surfaces()).unwrap();
+        let mut gs = GameState::new(0);
+        let owner = gs.players.insert(Player::new("Player".to_owned()));
+        if let Some(pos) = target {
+            let target = gs.players.insert(Player::new("Target".to_owned()));
+            let vehicle = Vehicle::new(cvars, pos, 0.0, VehicleType::Tank, 0, 0.0, target);
+            let vehicle_handle = gs.vehicles.insert(vehicle);
+            gs.players[target].vehicle = Some(vehicle_handle);
+        }
+        gs.projectiles.insert(Projectile {
+            weapon,
+            pos: map.tile_center(Vec2u::new(1, 1)),
+            vel: Vec2f::new(400.0, 0.0),
+            angle: 0.0,
+            turn_rate: 0.0,
+            explode_time: if weapon == Weapon::Cb {
+                cvars.g_cluster_bomb_time
+            } else {
+                f64::MAX
+            },
+            spawn_time: 0.0,
+            owner,
+            lock: None,
+            retarget_time: 0.0,
+            altitude: launch_altitude(cvars, weapon),
+        });
+        gs.dt = 1.0 / 150.0;
+        for _ in 0..1000 {
+            gs.game_time += gs.dt;
+            projectiles(cvars, &mut gs, &mut map);
+            projectiles_timeout(cvars, &mut gs, &mut map);
+            if gs.projectiles.is_empty() {
+                break;
+            }
+        }
+        let pos = gs
+            .effects
+            .iter()
+            .find_map(|effect| match *effect {
+                Effect::Explosion { pos, .. } => Some(pos),
+                _ => None,
+            })
+            .unwrap();
+        (gs, pos)
+    }
+
+    #[test]
+    fn test_arc_range() {
+        let cvars = Cvars {
+            g_cluster_bomb_arc_gravity: 500.0,
+            g_cluster_bomb_arc_speed: 250.0,
+            g_cluster_bomb_time: 0.5,
+            g_weapon_arc_fuse: false,
+            ..Cvars::default()
+        };
+        let start = TILE_SIZE * 1.5;
+        // In the air for 2 * 250 / 500 = 1 s.
+        let (_, pos) = fly_arc(&cvars, Weapon::Cb, None);
+        assert!((pos.x - (start + 400.0)).abs() < TILE_SIZE, "{}", pos);
+
+        // The timer is a fallback, whichever comes first.
+        let cvars = Cvars {
+            g_weapon_arc_fuse: true,
+            ..cvars
+        };
+        let (_, pos) = fly_arc(&cvars, Weapon::Cb, None);
+        assert!((pos.x - (start + 200.0)).abs() < TILE_SIZE, "{}", pos);
+    }
+
+    #[test]
+    fn test_arc_over_vehicle() {
+        // Rockets normally hit the first vehicle in their way.
+        let cvars = Cvars::default();
+        let start = TILE_SIZE * 1.5;
+        let target = Vec2f::new(start + 200.0, start);
+        let (gs, pos) = fly_arc(&cvars, Weapon::Rockets, Some(target));
+        assert!((pos.x - target.x).abs() < cvars.g_hitcircle_radius);
+        let (_, vehicle) = gs.vehicles.iter().next().unwrap();
+        assert!(vehicle.hp < vehicle.hp_max(&cvars));
+
+        // Flying in an arc, they pass over it in the middle of the trajectory and land behind it.
+        let cvars = Cvars {
+            g_rockets_arc_gravity: 500.0,
+            g_rockets_arc_speed: 250.0,
+            ..cvars
+        };
+        let (gs, pos) = fly_arc(&cvars, Weapon::Rockets, Some(target));
+        assert!((pos.x - (start + 400.0)).abs() < TILE_SIZE, "{}", pos);
+        let (_, vehicle) = gs.vehicles.iter().next().unwrap();
+        assert_eq!(vehicle.hp, vehicle.hp_max(&cvars));
+    }
+
+    #[test]
+    fn test_cb_bounce_corner() {
+        let cvars = Cvars {
+            g_cluster_bomb_bounce: true,
+            ..Cvars::default()
+        };
+        let (mut gs, mut map, player_handle, _) = setup_tank(&cvars);
+        gs.dt = 1.0 / 60.0;
+        let explode_time = 5.0;
+        let handle = gs.projectiles.insert(Projectile {
+            weapon: Weapon::Cb,
+            pos: Vec2f::new(10.0, 10.0),
+            vel: Vec2f::new(-600.0, -600.0),
+            angle: 0.0,
+            turn_rate: 0.0,
+            explode_time,
+            spawn_time: 0.0,
+            owner: player_handle,
+            lock: None,
+            retarget_time: 0.0,
+            altitude: None,
+        });
+
+        // Exactly into the map's top left corner - always the horizontal face first.
+        projectiles(&cvars, &mut gs, &mut map);
+        assert_eq!(gs.projectiles[handle].vel, Vec2f::new(-300.0, 300.0));
+        assert!(!map.is_wall(gs.projectiles[handle].pos));
+
+        // Then the vertical one and out of the corner, never inside the wall.
+        for _ in 0..20 {
+            projectiles(&cvars, &mut gs, &mut map);
+            assert!(!map.is_wall(gs.projectiles[handle].pos));
+        }
+        assert_eq!(gs.projectiles[handle].vel, Vec2f::new(150.0, 150.0));
+        assert_eq!(gs.bounces[&handle], 2);
+        assert_eq!(gs.projectiles[handle].explode_time, explode_time);
+
+        // Without bouncing, it flies over walls as before.
+        let cvars = Cvars {
+            g_cluster_bomb_bounce: false,
+            ..cvars
+        };
+        gs.projectiles[handle].vel = Vec2f::new(-6000.0, 0.0);
+        projectiles(&cvars, &mut gs, &mut map);
+        assert!(gs.projectiles[handle].pos.x < 0.0);
+    }
+
+    #[test]
+    fn test_projectiles_max() {
+        let cvars = Cvars {
+            g_projectiles_max: 3,
+            ..Cvars::default()
+        };
+        let (mut gs, mut map, player_handle, _) = setup_tank(&cvars);
+        gs.game_time = 5.0;
+        let mut insert = |weapon, pos, spawn_time| {
+            gs.projectiles.insert(Projectile {
+                weapon,
+                pos,
+                vel: Vec2f::zero(),
+                angle: 0.0,
+                turn_rate: 0.0,
+                explode_time: f64::MAX,
+                spawn_time,
+                owner: player_handle,
+                lock: None,
+                retarget_time: 0.0,
+                altitude: None,
+            })
+        };
+        let center = map.maxs() / 2.0;
+        for spawn_time in [4.0, 0.0, 3.0, 1.0, 2.0] {
+            insert(Weapon::Mg, center, spawn_time);
+        }
+        // Cluster bombs fly over walls, including the map's edge.
+        insert(Weapon::Cb, center, 4.5);
+        insert(Weapon::Cb, Vec2f::new(-TILE_SIZE - 1.0, center.y), 4.5);
+
+        projectiles_timeout(&cvars, &mut gs, &mut map);
+        let mut remaining: Vec<_> = gs
+            .projectiles
+            .iter()
+            .map(|(_, projectile)| (projectile.weapon, projectile.spawn_time))
+            .collect();
+        remaining.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        assert_eq!(
+            remaining,
+            [(Weapon::Mg, 3.0), (Weapon::Mg, 4.0), (Weapon::Cb, 4.5)]
+        );
+    }
+
+    #[test]
+    fn test_inputs_per_player() {
+        let cvars = Cvars::default();
+        let (mut gs, map, player1, vehicle1) = setup_tank(&cvars);
+        let player2 = gs.players.insert(Player::new("Player 2".to_owned()));
+        spawn_vehicle(&cvars, &mut gs, &map, player2, true);
+        let vehicle2 = gs.players[player2].vehicle.unwrap();
+        let weapon1 = gs.players[player1].cur_weapon;
+        let weapon2 = gs.players[player2].cur_weapon;
+        let turret2 = gs.vehicles[vehicle2].turret_angle_wanted;
+
+        gs.inputs_prev.snapshot(&gs.players);
+        gs.players[player1].input.next_weapon = true;
+        gs.players[player1].input.turret_left = true;
+        for _ in 0..3 {
+            // Holding the key only switches once.
+            player_logic(&cvars, &mut gs);
+            vehicle_logic(&cvars, &mut gs);
+            gs.inputs_prev.snapshot(&gs.players);
+        }
+
+        assert_ne!(gs.players[player1].cur_weapon, weapon1);
+        assert_eq!(
+            gs.players[player1].cur_weapon as u8,
+            (weapon1 as u8 + 1) % WEAPS_CNT
+        );
+        assert_ne!(gs.vehicles[vehicle1].turret_angle_wanted, 0.0);
+        assert_eq!(gs.players[player2].cur_weapon, weapon2);
+        assert_eq!(gs.vehicles[vehicle2].turret_angle_wanted, turret2);
+    }
+
+    #[test]
+    fn test_spawn_with_loadout() {
+        let cvars = Cvars {
+            g_railgun_allowed: false,
+            ..Cvars::default()
+        };
+        let (mut gs, map, player_handle, _) = setup_tank(&cvars);
+        gs.players[player_handle].pending_loadout = Loadout {
+            veh_type: Some(VehicleType::Hovercraft),
+            weapons: vec![Weapon::Bfg, Weapon::Rail, Weapon::Cb],
+        };
+        spawn_vehicle(&cvars, &mut gs, &map, player_handle, true);
+        let player = &gs.players[player_handle];
+        let vehicle = &gs.vehicles[player.vehicle.unwrap()];
+        assert!(matches!(vehicle.veh_type, VehicleType::Hovercraft));
+        // Mg isn't in the loadout, Rail is banned.
+        assert_eq!(player.cur_weapon, Weapon::Cb);
+
+        gs.inputs_prev.snapshot(&gs.players);
+        gs.players[player_handle].input.prev_weapon = true;
+        player_logic(&cvars, &mut gs);
+        assert_eq!(gs.players[player_handle].cur_weapon, Weapon::Bfg);
+    }
+
+    #[test]
+    fn test_vehicle_weapons_and_selection() {
+        let cvars = Cvars {
+            // MG, Rockets and BFG
+            g_tank_weapons: 0b100_1001,
+            ..Cvars::default()
+        };
+        let (mut gs, _map, player_handle, _) = setup_tank(&cvars);
+        let press = |gs: &mut GameState, input: Input| {
+            gs.inputs_prev.snapshot(&gs.players);
+            gs.players[player_handle].input = input;
+            player_logic(&cvars, gs);
+        };
+        let next = Input {
+            next_weapon: true,
+            ..Input::new()
+        };
+        let select = |weapon| Input {
+            select_weapon: Some(weapon),
+            ..Input::new()
+        };
+
+        let mut weapons = Vec::new();
+        for _ in 0..4 {
+            press(&mut gs, next);
+            weapons.push(gs.players[player_handle].cur_weapon);
+            press(&mut gs, Input::new());
+        }
+        let expected = [Weapon::Rockets, Weapon::Bfg, Weapon::Mg, Weapon::Rockets];
+        assert_eq!(weapons, expected);
+
+        // Not carried
+        press(&mut gs, select(Weapon::Hm));
+        assert_eq!(gs.players[player_handle].cur_weapon, Weapon::Rockets);
+        press(&mut gs, select(Weapon::Bfg));
+        assert_eq!(gs.players[player_handle].cur_weapon, Weapon::Bfg);
+        // Edge-triggered - holding the key doesn't undo cycling.
+        gs.players[player_handle].cur_weapon = Weapon::Mg;
+        press(&mut gs, select(Weapon::Bfg));
+        assert_eq!(gs.players[player_handle].cur_weapon, Weapon::Mg);
+
+        // Only one weapon - cycling stays on it.
+        let cvars = Cvars {
+            g_tank_weapons: 0b10,
+            ..Cvars::default()
+        };
+        let (mut gs, map, player_handle, _) = setup_tank(&cvars);
+        gs.players[player_handle].pending_loadout.veh_type = Some(VehicleType::Tank);
+        gs.vehicles.clear();
+        spawn_vehicle(&cvars, &mut gs, &map, player_handle, true);
+        assert_eq!(gs.players[player_handle].cur_weapon, Weapon::Rail);
+        gs.inputs_prev.snapshot(&gs.players);
+        gs.players[player_handle].input = next;
+        player_logic(&cvars, &mut gs);
+        assert_eq!(gs.players[player_handle].cur_weapon, Weapon::Rail);
+
+        let vehicle = &gs.vehicles[gs.players[player_handle].vehicle.unwrap()];
+        let status = vehicle.weapon_status(&cvars, Weapon::Rail, 0.0);
+        assert_eq!(status.ammo, status.ammo_max);
+        assert_eq!(status.reload_progress, None);
+        assert_eq!(status.fraction(), 1.0);
+    }
+
+    #[test]
+    fn test_life_records() {
+        let cvars = Cvars::default();
+        let (mut gs, map, player1, _) = setup_tank(&cvars);
+        let player2 = gs.players.insert(Player::new("Player 2".to_owned()));
+        spawn_vehicle(&cvars, &mut gs, &map, player1, true);
+        spawn_vehicle(&cvars, &mut gs, &map, player2, true);
+        let vehicle2 = gs.players[player2].vehicle.unwrap();
+
+        let dmg_type = DamageType::Explosive;
+        let weapon = Some(Weapon::Rockets);
+        damage(
+            &cvars,
+            &mut gs,
+            player1,
+            vehicle2,
+            weapon,
+            fixed::to_milli(10.0),
+            dmg_type,
+            None,
+        );
+        damage(
+            &cvars,
+            &mut gs,
+            player1,
+            vehicle2,
+            weapon,
+            MilliHp::MAX,
+            dmg_type,
+            None,
+        );
+
+        let lives = gs.life_records.life_records(player2);
+        assert_eq!(lives.len(), 1);
+        let death = lives[0].death.as_ref().unwrap();
+        assert_eq!((death.attacker, death.weapon), (player1, weapon));
+        // Overkill isn't counted.
+        let taken: f64 = lives[0]
+            .entries
+            .iter()
+            .map(|entry| match entry {
+                LifeEntry::DamageTaken(entry) => entry.amount,
+                _ => 0.0,
+            })
+            .sum();
+        assert!((taken - cvars.g_vehicle_hp(lives[0].veh_type)).abs() < 1e-9);
+
+        // The attacker is still alive so their record is partial.
+        let lives = gs.life_records.life_records(player1);
+        assert_eq!(lives.len(), 1);
+        assert!(lives[0].death.is_none());
+        assert_eq!(lives[0].entries.len(), 2);
+    }
+
+    #[test]
+    fn test_notifications() {
+        let cvars = Cvars::default();
+        let (mut gs, map, player1, _) = setup_tank(&cvars);
+        let player2 = gs.players.insert(Player::new("Player 2".to_owned()));
+        spawn_vehicle(&cvars, &mut gs, &map, player2, true);
+        let vehicle2 = gs.players[player2].vehicle.unwrap();
+
+        let dmg_type = DamageType::Explosive;
+        let weapon = Some(Weapon::Rockets);
+        damage(
+            &cvars,
+            &mut gs,
+            player1,
+            vehicle2,
+            weapon,
+            fixed::to_milli(10.0),
+            dmg_type,
+            None,
+        );
+        let notifications = drain_notifications(&mut gs);
+        let kinds: Vec<_> = notifications.into_iter().map(|n| n.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                NotificationKind::DamageTaken {
+                    player: player2,
+                    amount: 10.0,
+                    facing: None,
+                    armor: 1.0,
+                },
+                NotificationKind::DamageDealt {
+                    attacker: player1,
+                    target: player2,
+                    amount: 10.0,
+                    facing: None,
+                    armor: 1.0,
+                },
+            ]
+        );
+        assert!(gs.notifications.is_empty());
+
+        damage(
+            &cvars,
+            &mut gs,
+            player1,
+            vehicle2,
+            weapon,
+            MilliHp::MAX,
+            dmg_type,
+            None,
+        );
+        let kill = drain_notifications(&mut gs).pop().unwrap().kind;
+        assert_eq!(
+            kill,
+            NotificationKind::Kill {
+                killer_name: "Player".to_owned(),
+                victim_name: "Player 2".to_owned(),
+                weapon,
+            }
+        );
+        assert_eq!(
+            kill.feed_line().unwrap(),
+            "Player killed Player 2 (Rockets)"
+        );
+    }
+
+    /// Run vehicle_logic for one frame of `dt` seconds with the given input.
+    fn turret_frame(
+        cvars: &Cvars,
+        gs: &mut GameState,
+        player_handle: Index,
+        input: Input,
+        dt: f64,
+    ) {
+        gs.inputs_prev.snapshot(&gs.players);
+        gs.players[player_handle].input = input;
+        gs.dt = dt;
+        vehicle_logic(cvars, gs);
+    }
+
+    #[test]
+    fn test_turret_wrapping() {
+        let cvars = Cvars::default();
+        let (mut gs, _map, player_handle, vehicle_handle) = setup_tank(&cvars);
+        let left = Input {
+            turret_left: true,
+            ..Input::new()
+        };
+        for _ in 0..100 {
+            turret_frame(&cvars, &mut gs, player_handle, left, 0.5);
+            turret_frame(&cvars, &mut gs, player_handle, Input::new(), 0.5);
+        }
+        let vehicle = &gs.vehicles[vehicle_handle];
+        assert!((0.0..2.0 * PI).contains(&vehicle.turret_angle_wanted));
+        assert!((0.0..2.0 * PI).contains(&vehicle.turret_angle_current));
+        // 100 steps of 45 degrees is 12.5 turns.
+        assert!((vehicle.turret_angle_current - PI).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_turret_target_shortest_path() {
+        let cvars = Cvars {
+            g_turret_turn_speed_deg: 10.0,
+            ..Cvars::default()
+        };
+        let (mut gs, _map, player_handle, vehicle_handle) = setup_tank(&cvars);
+        gs.vehicles[vehicle_handle].turret_angle_current = 350_f64.to_radians();
+        gs.vehicles[vehicle_handle].turret_angle_wanted = 350_f64.to_radians();
+
+        // Chassis points at 0 so absolute and relative angles are the same.
+        // 350 -> 5 degrees should turn right across 0, not 345 degrees left.
+        let mut input = Input::new();
+        input.set_turret_target_angle(5_f64.to_radians());
+        turret_frame(&cvars, &mut gs, player_handle, input, 1.0);
+        let current = gs.vehicles[vehicle_handle]
+            .turret_angle_current
+            .to_degrees();
+        assert!((current - 0.0).abs() < 0.01 || (current - 360.0).abs() < 0.01);
+        turret_frame(&cvars, &mut gs, player_handle, input, 1.0);
+        let current = gs.vehicles[vehicle_handle]
+            .turret_angle_current
+            .to_degrees();
+        assert!((current - 5.0).abs() < 0.01, "{}", current);
+
+        // And back the other way, with the target given as a negative angle.
+        input.set_turret_target_angle((-15_f64).to_radians());
+        turret_frame(&cvars, &mut gs, player_handle, input, 1.0);
+        let current = gs.vehicles[vehicle_handle]
+            .turret_angle_current
+            .to_degrees();
+        assert!((current - 355.0).abs() < 0.01, "{}", current);
+
+        // The target is absolute so turning the chassis changes the relative angle.
+        gs.vehicles[vehicle_handle].angle.set(PI, 0);
+        gs.vehicles[vehicle_handle].update_obb();
+        input.set_turret_target_angle(PI);
+        turret_frame(&cvars, &mut gs, player_handle, input, 1.0);
+        assert_eq!(gs.vehicles[vehicle_handle].turret_angle_wanted, 0.0);
+    }
+
+    #[test]
+    fn test_turret_limits() {
+        let cvars = Cvars {
+            g_tank_turret_angle_min_deg: -30.0,
+            g_tank_turret_angle_max_deg: 60.0,
+            g_turret_turn_speed_deg: 10.0,
+            ..Cvars::default()
+        };
+        let (mut gs, _map, player_handle, vehicle_handle) = setup_tank(&cvars);
+        let left = Input {
+            turret_left: true,
+            ..Input::new()
+        };
+        turret_frame(&cvars, &mut gs, player_handle, left, 0.0);
+        assert!(
+            (gs.vehicles[vehicle_handle].turret_angle_wanted.to_degrees() - 330.0).abs() < 1e-9
+        );
+        turret_frame(&cvars, &mut gs, player_handle, Input::new(), 0.0);
+        turret_frame(&cvars, &mut gs, player_handle, left, 0.0);
+        assert!(
+            (gs.vehicles[vehicle_handle].turret_angle_wanted.to_degrees() - 330.0).abs() < 1e-9
+        );
+
+        // 200 is outside the range so the turret aims at the nearest limit
+        // and turns back through 0 instead of taking the shortest path through 180.
+        gs.vehicles[vehicle_handle].turret_angle_current = 50_f64.to_radians();
+        let mut input = Input::new();
+        input.set_turret_target_angle(200_f64.to_radians());
+        turret_frame(&cvars, &mut gs, player_handle, input, 1.0);
+        let vehicle = &gs.vehicles[vehicle_handle];
+        assert!((vehicle.turret_angle_wanted.to_degrees() - 330.0).abs() < 0.01);
+        assert!((vehicle.turret_angle_current.to_degrees() - 40.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_input_bits() {
+        let mut input = Input {
+            up: true,
+            fire: true,
+            pause: true,
+            ..Input::new()
+        };
+        assert_eq!(Input::from_bits(input.to_bits()), input);
+        input.set_turret_target_angle(-PI / 2.0);
+        assert_eq!(input.turret_target, Some(49152));
+        assert_eq!(Input::from_bits(input.to_bits()), input);
+        input.set_turret_target_angle(2.0 * PI - 1e-9);
+        assert_eq!(input.turret_target, Some(0));
+        for weapon in Weapon::all() {
+            input.select_weapon = Some(weapon);
+            assert_eq!(Input::from_bits(input.to_bits()), input);
+        }
+    }
+
+    /// A stationary BFG ball at (96, 32) and an enemy tank at `vehicle_pos`,
+    /// there's a wall tile from (128, 64) to (192, 128).
+    fn bfg_setup(cvars: &Cvars, vehicle_pos: Vec2f) -> (GameState, Map, Index) {
+        let surfaces = vec![
+            Surface {
+                name: "ground".to_owned(),
+                kind: Kind::Normal,
+                friction: 1.0,
+                speed: 1.0,
+                destructible: None,
+            },
+            Surface {
+                name: "wall".to_owned(),
+                kind: Kind::Wall,
+                friction: 1.0,
+                speed: 1.0,
+                destructible: None,
+            },
+        ];
+        let text = ["0 0 0 0 0", "0 0 4 0 0", "0 0 0 0 0", "0 0 0 0 0"].join("\n");
+        let map = map::load_map(&text, surfaces).unwrap();
+        let mut gs = GameState::new(0);
+        let attacker = gs.players.insert(Player::new("Attacker".to_owned()));
+        let owner = gs.players.insert(Player::new("Target".to_owned()));
+        let vehicle = Vehicle::new(cvars, vehicle_pos, 0.0, VehicleType::Tank, 0, 0.0, owner);
+        let vehicle_handle = gs.vehicles.insert(vehicle);
+        gs.players[owner].vehicle = Some(vehicle_handle);
+        gs.projectiles.insert(Projectile {
+            weapon: Weapon::Bfg,
+            pos: Vec2f::new(96.0, 32.0),
+            // Not zero so the step isn't degenerate.
+            vel: Vec2f::new(0.001, 0.0),
+            angle: 0.0,
+            turn_rate: 0.0,
+            explode_time: f64::MAX,
+            spawn_time: 0.0,
+            owner: attacker,
+            lock: None,
+            retarget_time: 0.0,
+            altitude: None,
+        });
+        gs.dt = 1.0 / 60.0;
+        (gs, map, vehicle_handle)
+    }
+
+    fn bfg_frame(cvars: &Cvars, gs: &mut GameState, map: &mut Map) {
+        // Same order as the server - effects from the previous frame are already older when retired.
+        gs.frame_num += 1;
+        gs.game_time += gs.dt;
+        cleanup(cvars, gs);
+        projectiles(cvars, gs, map);
+    }
+
+    fn explosions(gs: &GameState) -> usize {
+        gs.effects
+            .iter()
+            .filter(|effect| matches!(effect, Effect::Explosion { .. }))
+            .count()
+    }
+
+    fn bfg_beam_ends(gs: &GameState) -> Vec<Vec2f> {
+        gs.effects
+            .iter()
+            .filter_map(|effect| match *effect {
+                Effect::BfgBeam { end, .. } => Some(end),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bfg_beam_damage_over_time() {
+        let cvars = Cvars::default();
+        let (mut gs, mut map, vehicle_handle) = bfg_setup(&cvars, Vec2f::new(96.0, 140.0));
+        let hp_max = gs.vehicles[vehicle_handle].hp_max(&cvars);
+        for frame in 1..=60 {
+            bfg_frame(&cvars, &mut gs, &mut map);
+            assert_eq!(bfg_beam_ends(&gs).len(), 1);
+            assert_eq!(explosions(&gs), 0);
+            let dealt = hp_max - gs.vehicles[vehicle_handle].hp;
+            let expected = cvars.g_bfg_beam_damage_per_sec * f64::from(frame) / 60.0;
+            assert!((fixed::from_milli(dealt) - expected).abs() <= 0.001);
+        }
+
+        // Only explodes when the hp actually runs out.
+        while !gs.vehicles[vehicle_handle].destroyed() {
+            assert_eq!(explosions(&gs), 0);
+            bfg_frame(&cvars, &mut gs, &mut map);
+        }
+        assert_eq!(explosions(&gs), 1);
+        bfg_frame(&cvars, &mut gs, &mut map);
+        assert!(bfg_beam_ends(&gs).is_empty());
+        assert_eq!(explosions(&gs), 1);
+    }
+
+    #[test]
+    fn test_bfg_beam_cover() {
+        let cvars = Cvars {
+            g_bfg_beam_range: 200.0,
+            ..Cvars::default()
+        };
+
+        // The center is behind the wall but the rear left corner isn't.
+        let (mut gs, mut map, vehicle_handle) = bfg_setup(&cvars, Vec2f::new(140.0, 150.0));
+        bfg_frame(&cvars, &mut gs, &mut map);
+        let corner = gs.vehicles[vehicle_handle].obb.corners[0];
+        assert_eq!(bfg_beam_ends(&gs), [corner]);
+        assert!(gs.vehicles[vehicle_handle].hp < gs.vehicles[vehicle_handle].hp_max(&cvars));
+
+        // Completely behind the wall.
+        let (mut gs, mut map, vehicle_handle) = bfg_setup(&cvars, Vec2f::new(200.0, 150.0));
+        bfg_frame(&cvars, &mut gs, &mut map);
+        assert!(bfg_beam_ends(&gs).is_empty());
+        assert_eq!(
+            gs.vehicles[vehicle_handle].hp,
+            gs.vehicles[vehicle_handle].hp_max(&cvars)
+        );
+    }
+
+    #[test]
+    fn test_spread_matches_old_arms() {
+        // How the machine gun and cluster bomb arms of `shooting` used to sample their spread.
+        let old_mg = |cvars: &Cvars, rng: &mut SmallRng, angle: f64, vehicle_vel: Vec2f| {
+            let r: f64 = rng.sample(StandardNormal);
+            let spread = cvars.g_machine_gun_angle_spread * r;
+            Vec2f::new(cvars.g_machine_gun_speed, 0.0).rotated_z(angle + spread)
+                + cvars.g_machine_gun_vehicle_velocity_factor * vehicle_vel
+        };
+        let old_cb = |cvars: &Cvars,
+                      rng: &mut GameRng,
+                      angle: f64,
+                      vehicle_vel: Vec2f,
+                      shot_time: f64,
+                      gaussian: bool| {
+            let uniform11 = Uniform::new_inclusive(-1.0, 1.0);
+            let (spread_forward, spread_sideways);
+            if gaussian {
+                let r: f64 = rng.weapon_spread.sample(StandardNormal);
+                spread_forward = cvars.g_cluster_bomb_speed_spread_forward * r;
+                let r: f64 = rng.weapon_spread.sample(StandardNormal);
+                spread_sideways = cvars.g_cluster_bomb_speed_spread_sideways * r;
+            } else {
+                let r = rng.weapon_spread.sample(uniform11);
+                spread_forward = cvars.g_cluster_bomb_speed_spread_forward * r;
+                let r = rng.weapon_spread.sample(uniform11);
+                spread_sideways = cvars.g_cluster_bomb_speed_spread_sideways * r;
+            }
+            let vel = Vec2f::new(cvars.g_cluster_bomb_speed + spread_forward, spread_sideways)
+                .rotated_z(angle)
+                + cvars.g_cluster_bomb_vehicle_velocity_factor * vehicle_vel;
+            let explode_time = shot_time
+                + cvars.g_cluster_bomb_time
+                + rng.cb_timing.sample(uniform11) * cvars.g_cluster_bomb_time_spread;
+            (vel, explode_time)
+        };
+
+        let uniform11 = Uniform::new_inclusive(-1.0, 1.0);
+        let vehicle_vel = Vec2f::new(30.0, -70.0);
+        let shot_time = 1.25;
+        for gaussian in [true, false] {
+            let cvars = Cvars {
+                g_weapon_spread_gaussian: gaussian,
+                ..Cvars::default()
+            };
+            let mut new_rng = GameRng::new(7);
+            let mut old_rng = GameRng::new(7);
+            let mg = cvars.g_projectile_params(Weapon::Mg);
+            let cb = cvars.g_projectile_params(Weapon::Cb);
+            let mut angles = Vec::new();
+            let mut forwards = Vec::new();
+            for i in 0..2000 {
+                let angle = i as f64 * 0.01;
+                if gaussian {
+                    let (vel, explode_time) = spread_shot(
+                        &cvars,
+                        &mg,
+                        &mut new_rng,
+                        uniform11,
+                        angle,
+                        vehicle_vel,
+                        shot_time,
+                    );
+                    let expected =
+                        old_mg(&cvars, &mut old_rng.weapon_spread.rng, angle, vehicle_vel);
+                    assert_eq!(vel, expected);
+                    assert_eq!(explode_time, f64::MAX);
+                    let own_vel = vel - cvars.g_machine_gun_vehicle_velocity_factor * vehicle_vel;
+                    angles
+                        .push((own_vel.y.atan2(own_vel.x) - angle + PI).rem_euclid(2.0 * PI) - PI);
+                }
+
+                let new = spread_shot(
+                    &cvars,
+                    &cb,
+                    &mut new_rng,
+                    uniform11,
+                    angle,
+                    vehicle_vel,
+                    shot_time,
+                );
+                let expected = old_cb(
+                    &cvars,
+                    &mut old_rng,
+                    angle,
+                    vehicle_vel,
+                    shot_time,
+                    gaussian,
+                );
+                assert_eq!(new, expected);
+                let own_vel = (new.0 - vehicle_vel).rotated_z(-angle);
+                forwards.push(own_vel.x - cvars.g_cluster_bomb_speed);
+            }
+
+            // The distributions themselves, in case the old arms are ever changed together with the new code.
+            let std_dev = |samples: &[f64]| {
+                let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+                let var =
+                    samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / samples.len() as f64;
+                (mean, var.sqrt())
+            };
+            if gaussian {
+                let (mean, std) = std_dev(&angles);
+                assert!(mean.abs() < 0.002, "{}", mean);
+                assert!(
+                    (std / cvars.g_machine_gun_angle_spread - 1.0).abs() < 0.1,
+                    "{}",
+                    std
+                );
+            }
+            let (mean, std) = std_dev(&forwards);
+            assert!(mean.abs() < 5.0, "{}", mean);
+            // Uniform between -1 and 1 has a standard deviation of 1 / sqrt(3).
+            let expected = if gaussian { 1.0 } else { 1.0 / 3.0_f64.sqrt() };
+            let ratio = std / cvars.g_cluster_bomb_speed_spread_forward / expected;
+            assert!((ratio - 1.0).abs() < 0.1, "{}", ratio);
+        }
+
+        // Spread for other weapons is just a cvar.
+        let cvars = Cvars {
+            g_rockets_angle_spread: 0.1,
+            ..Cvars::default()
+        };
+        let rockets = cvars.g_projectile_params(Weapon::Rockets);
+        let mut rng = GameRng::new(7);
+        let vel = spread_shot(
+            &cvars,
+            &rockets,
+            &mut rng,
+            uniform11,
+            0.0,
+            Vec2f::zero(),
+            0.0,
+        )
+        .0;
+        assert!(vel.y.abs() > 0.0);
+        assert!((vel.magnitude() - cvars.g_rockets_speed).abs() < 1e-9);
+        let rockets = Cvars::default().g_projectile_params(Weapon::Rockets);
+        let vel = spread_shot(
+            &cvars,
+            &rockets,
+            &mut rng,
+            uniform11,
+            0.0,
+            Vec2f::zero(),
+            0.0,
+        )
+        .0;
+        assert_eq!(vel, Vec2f::new(cvars.g_rockets_speed, 0.0));
+    }
+
+    fn fire_rocket(gs: &mut GameState, owner: Index, pos: Vec2f) -> Index {
+        let vel = Vec2f::new(Cvars::default().g_rockets_speed, 0.0);
+        gs.projectiles.insert(Projectile {
+            weapon: Weapon::Rockets,
+            pos,
+            vel,
+            angle: 0.0,
+            turn_rate: 0.0,
+            explode_time: f64::MAX,
+            spawn_time: gs.game_time,
+            owner,
+            lock: None,
+            retarget_time: 0.0,
+            altitude: None,
+        })
+    }
+
+    #[test]
+    fn test_breach_wall() {
+        let cvars = Cvars::default();
+        let row = "0 0 0 0 0 0";
+        let destructible = "destructible wall hp=150 rubble=ground";
+        let text = [row, "0 0 0 16 0 0", row, destructible].join("\n");
+        let mut map = map::load_map(&text, surfaces()).unwrap();
+        let wall = Vec2u::new(3, 1);
+        let mut gs = GameState::new(0);
+        let owner = gs.players.insert(Player::new("Player".to_owned()));
+        gs.dt = 1.0 / 20.0;
+
+        // Rockets only do direct damage, their explosions don't hurt anything by default.
+        for i in 1..=5 {
+            fire_rocket(&mut gs, owner, Vec2f::new(180.0, 96.0));
+            gs.frame_num += 1;
+            projectiles(&cvars, &mut gs, &mut map);
+            assert!(gs.projectiles.is_empty());
+            assert_eq!(
+                map.wall_damage(wall),
+                f64::from(i) * cvars.g_rockets_damage_direct
+            );
+        }
+        assert_eq!(map[wall].surface_index, 4);
+        assert!(!NavGrid::build(&map).walkable(wall));
+        assert!(drain_notifications(&mut gs).is_empty());
+
+        // The sixth breaches it and the one right behind it flies into the gap in the same frame.
+        fire_rocket(&mut gs, owner, Vec2f::new(185.0, 96.0));
+        let follower = fire_rocket(&mut gs, owner, Vec2f::new(180.0, 96.0));
+        gs.frame_num += 1;
+        projectiles(&cvars, &mut gs, &mut map);
+        assert_eq!(gs.projectiles.len(), 1);
+        assert!(gs.projectiles[follower].pos.x > 3.0 * TILE_SIZE);
+        assert_eq!(map[wall].surface_index, 0);
+        assert_eq!(map.wall_damage(wall), 0.0);
+        assert!(NavGrid::build(&map).walkable(wall));
+        let kinds: Vec<_> = drain_notifications(&mut gs)
+            .into_iter()
+            .map(|n| n.kind)
+            .collect();
+        assert_eq!(kinds, [NotificationKind::WallDestroyed { index: wall }]);
+
+        assert!(map.repair_walls());
+        assert_eq!(map[wall].surface_index, 4);
+        assert!(map.destroyed_walls().is_empty());
+    }
+
+    #[test]
+    fn test_railgun_wall_damage() {
+        let cvars = Cvars {
+            g_railgun_wall_damage_factor: 0.5,
+            ..Cvars::default()
+        };
+        let row = "0 0 0 0 0 0";
+        let destructible = "destructible wall hp=1000 rubble=ground";
+        let text = [row, "0 0 0 16 0 0", row, destructible].join("\n");
+        let mut map = map::load_map(&text, surfaces()).unwrap();
+        let mut gs = GameState::new(0);
+        let owner = gs.players.insert(Player::new("Player".to_owned()));
+        let rail = fire_rocket(&mut gs, owner, Vec2f::new(180.0, 96.0));
+        gs.projectiles[rail].weapon = Weapon::Rail;
+        gs.dt = 1.0 / 20.0;
+        gs.frame_num += 1;
+        projectiles(&cvars, &mut gs, &mut map);
+        assert_eq!(
+            map.wall_damage(Vec2u::new(3, 1)),
+            cvars.g_weapon_damage_direct(Weapon::Rail) * 0.5
+        );
+    }
+}
@@ -2,6 +2,10 @@
 
 use std::{collections::VecDeque, fmt::Debug};
 
+use macroquad::miniquad::date;
+
+use crate::json;
+
 /// Saves frame times over some period of time to measure FPS.
 #[derive(Debug, Clone, Default)]
 pub struct Fps(VecDeque<f64>);
@@ -71,4 +75,216 @@ impl Durations {
 
         Some((avg, max))
     }
+
+    /// Like `get_stats` but with everything.
+    pub fn summary(&self) -> Option<DurationStats> {
+        let (avg, max) = self.get_stats()?;
+        let min = self.0.iter().copied().fold(f64::INFINITY, f64::min);
+        let last = *self.0.back().unwrap();
+        Some(DurationStats {
+            min,
+            avg,
+            max,
+            last,
+        })
+    }
+
+    /// Oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = f64> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+/// In seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DurationStats {
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+    pub last: f64,
+}
+
+impl DurationStats {
+    /// In milliseconds since that's what the browser's devtools use.
+    fn to_json(self) -> String {
+        format!(
+            "{{\"min\": {:.4}, \"avg\": {:.4}, \"max\": {:.4}, \"last\": {:.4}}}",
+            self.min * 1000.0,
+            self.avg * 1000.0,
+            self.max * 1000.0,
+            self.last * 1000.0,
+        )
+    }
+}
+
+/// How long each gamelogic system (or drawing phase) took during the last frames.
+///
+/// Only measured when enabled, otherwise `lap` returns immediately
+/// so the timer calls can stay in place.
+#[derive(Debug, Clone, Default)]
+pub struct SystemTimings {
+    enabled: bool,
+    samples_max: usize,
+    lap_start: f64,
+    /// Name and duration in seconds during the last frame, in the order they ran.
+    /// Reused between frames so it doesn't allocate.
+    pub durations: Vec<(&'static str, f64)>,
+    /// The last `samples_max` durations of each name, in the order they first ran.
+    /// Names which run more than once per frame (e.g. drawing each viewport in splitscreen)
+    /// add a sample each time.
+    pub history: Vec<(&'static str, Durations)>,
+}
+
+impl SystemTimings {
+    /// Forget the last frame and start measuring a new one if `enabled`.
+    pub fn start(&mut self, enabled: bool, samples_max: usize) {
+        self.enabled = enabled;
+        self.samples_max = samples_max;
+        self.durations.clear();
+        if enabled {
+            self.lap_start = date::now();
+        } else {
+            self.history.clear();
+        }
+    }
+
+    /// Record the time since the previous lap (or `start`) as the duration of `name`.
+    pub fn lap(&mut self, name: &'static str) {
+        if !self.enabled {
+            return;
+        }
+        let now = date::now();
+        let duration = now - self.lap_start;
+        self.lap_start = now;
+
+        self.durations.push((name, duration));
+        let index = match self.history.iter().position(|&(other, _)| other == name) {
+            Some(index) => index,
+            None => {
+                self.history.push((name, Durations::new()));
+                self.history.len() - 1
+            }
+        };
+        self.history[index].1.add(self.samples_max, duration);
+    }
+
+    fn summaries(&self) -> Vec<(&'static str, DurationStats)> {
+        self.history
+            .iter()
+            .filter_map(|(name, durations)| Some((*name, durations.summary()?)))
+            .collect()
+    }
+}
+
+/// Performance counters from inside the game for chasing hitches,
+/// over the last `d_timing_samples` frames.
+///
+/// Per-system and per-phase numbers are only available with `d_timing_systems`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PerfStats {
+    /// Everything the server does in one frame, including any number of gamelogic steps.
+    pub update: Option<DurationStats>,
+    /// One gamelogic step.
+    pub gamelogic: Option<DurationStats>,
+    /// Issuing draw commands.
+    pub draw: Option<DurationStats>,
+    pub systems: Vec<(&'static str, DurationStats)>,
+    pub draw_phases: Vec<(&'static str, DurationStats)>,
+}
+
+impl PerfStats {
+    pub fn new(
+        update: &Durations,
+        gamelogic: &Durations,
+        draw: &Durations,
+        system_timings: &SystemTimings,
+        draw_timings: &SystemTimings,
+    ) -> Self {
+        Self {
+            update: update.summary(),
+            gamelogic: gamelogic.summary(),
+            draw: draw.summary(),
+            systems: system_timings.summaries(),
+            draw_phases: draw_timings.summaries(),
+        }
+    }
+
+    /// A JSON object with times in milliseconds - a frontend can `JSON.parse` it.
+    /// Missing measurements are null.
+    pub fn to_json(&self) -> String {
+        let stats = |stats: Option<DurationStats>| match stats {
+            Some(stats) => stats.to_json(),
+            None => "null".to_owned(),
+        };
+        let group = |items: &[(&'static str, DurationStats)]| {
+            let fields: Vec<_> = items
+                .iter()
+                .map(|&(name, stats)| format!("{}: {}", json::quote(name), stats.to_json()))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        };
+        format!(
+            "{{\"update\": {}, \"gamelogic\": {}, \"draw\": {}, \"systems\": {}, \"draw_phases\": {}}}",
+            stats(self.update),
+            stats(self.gamelogic),
+            stats(self.draw),
+            group(&self.systems),
+            group(&self.draw_phases),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_system_timings() {
+        let mut timings = SystemTimings::default();
+        for _ in 0..3 {
+            timings.start(true, 2);
+            timings.lap("a");
+            timings.lap("b");
+            timings.lap("a");
+        }
+        let names: Vec<_> = timings.durations.iter().map(|&(name, _)| name).collect();
+        assert_eq!(names, ["a", "b", "a"]);
+        let samples: Vec<_> = timings
+            .history
+            .iter()
+            .map(|(name, durations)| (*name, durations.iter().count()))
+            .collect();
+        assert_eq!(samples, [("a", 2), ("b", 2)]);
+
+        timings.start(false, 2);
+        timings.lap("a");
+        assert!(timings.durations.is_empty());
+        assert!(timings.history.is_empty());
+    }
+
+    #[test]
+    fn test_perf_stats_json() {
+        let mut update = Durations::new();
+        for duration in [0.003, 0.001, 0.002] {
+            update.add(10, duration);
+        }
+        let stats = update.summary().unwrap();
+        assert_eq!(stats.min, 0.001);
+        assert_eq!(stats.max, 0.003);
+        assert_eq!(stats.last, 0.002);
+
+        let perf_stats = PerfStats {
+            update: Some(stats),
+            gamelogic: None,
+            draw: None,
+            systems: vec![("ai", stats)],
+            draw_phases: Vec::new(),
+        };
+        let ms = r#"{"min": 1.0000, "avg": 2.0000, "max": 3.0000, "last": 2.0000}"#;
+        let expected = format!(
+            r#"{{"update": {}, "gamelogic": null, "draw": null, "systems": {{"ai": {}}}, "draw_phases": {{}}}}"#,
+            ms, ms
+        );
+        assert_eq!(perf_stats.to_json(), expected);
+    }
 }
@@ -0,0 +1,520 @@
+//! Recording what the gamelogic did each tick to find where two runs diverged.
+//!
+//! Replays and the determinism tests compare checksums (see `replay::checksum`)
+//! which say that two runs diverged but not where or why. With `d_trace` on, the gamelogic
+//! records compact events for the last `d_trace_ticks` ticks: how many times each system used
+//! each RNG stream, damage, entities being spawned and removed and vehicles blocked by walls.
+//! `Trace::dump` exports them as newline-delimited JSON (one flat object per event)
+//! and `diff` finds the first tick, system and field where two dumps differ.
+//!
+//! Events don't know which system made them - the server calls `Trace::system_done` after each system
+//! which assigns them the system's name and the tick. Events from between ticks (e.g. players connecting)
+//! belong to the `between_ticks` pseudo-system at the start of the next tick.
+//!
+//! When disabled, recording costs a branch - events are built by closures which don't run.
+//!
+//! Not to be confused with `d_tracing` (drawing projectile paths) or `d_collision_trace` (see `collision_debug`).
+
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display, Formatter, Write},
+};
+
+use thunderdome::Index;
+
+use crate::{
+    cvars::Cvars,
+    entities::{Flare, Pickup, PickupKind, Projectile, Vehicle, VehicleType, Weapon},
+    fixed::MilliHp,
+    json::{self, JsonError, Value},
+    map::Vec2f,
+    rng::{GameRng, STREAM_NAMES},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceEvent {
+    /// How many times the system used each RNG stream (in `GameRng::streams` order).
+    /// Recorded even if it didn't use any so the dumps of two runs line up.
+    Rng([u64; 5]),
+    Damage {
+        attacker: Index,
+        vehicle: Index,
+        weapon: Option<Weapon>,
+        /// After armor, resistances and shields.
+        amount: MilliHp,
+        /// `amount` limited to the hp the vehicle had.
+        dealt: MilliHp,
+        /// What the vehicle has left.
+        hp: MilliHp,
+    },
+    Spawn(EntitySummary),
+    Remove(EntitySummary),
+    /// A vehicle's move in `vehicle_movement` hit a wall - `to` is where it tried to go, `pos` where it ended.
+    Blocked {
+        vehicle: Index,
+        from: Vec2f,
+        to: Vec2f,
+        pos: Vec2f,
+    },
+}
+
+/// What an entity was when it was spawned or removed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EntitySummary {
+    pub kind: EntityKind,
+    pub handle: Index,
+    /// The player who owns it, `None` for pickups.
+    pub owner: Option<Index>,
+    pub pos: Vec2f,
+    pub vel: Vec2f,
+}
+
+impl EntitySummary {
+    pub fn vehicle(handle: Index, vehicle: &Vehicle) -> Self {
+        Self {
+            kind: EntityKind::Vehicle(vehicle.veh_type),
+            handle,
+            owner: Some(vehicle.owner),
+            pos: *vehicle.pos,
+            vel: vehicle.vel,
+        }
+    }
+
+    pub fn projectile(handle: Index, projectile: &Projectile) -> Self {
+        Self {
+            kind: EntityKind::Projectile(projectile.weapon),
+            handle,
+            owner: Some(projectile.owner),
+            pos: projectile.pos,
+            vel: projectile.vel,
+        }
+    }
+
+    pub fn pickup(handle: Index, pickup: &Pickup) -> Self {
+        Self {
+            kind: EntityKind::Pickup(pickup.kind),
+            handle,
+            owner: None,
+            pos: pickup.pos,
+            vel: Vec2f::zero(),
+        }
+    }
+
+    pub fn flare(handle: Index, flare: &Flare) -> Self {
+        Self {
+            kind: EntityKind::Flare,
+            handle,
+            owner: Some(flare.owner),
+            pos: flare.pos,
+            vel: Vec2f::zero(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EntityKind {
+    Vehicle(VehicleType),
+    Projectile(Weapon),
+    Pickup(PickupKind),
+    Flare,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceRecord {
+    pub tick: u64,
+    pub system: &'static str,
+    pub event: TraceEvent,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    /// Oldest first.
+    records: VecDeque<TraceRecord>,
+    /// Events since the last `system_done`, they don't know their system yet.
+    pending: Vec<TraceEvent>,
+    /// Draw counts of the RNG streams at the last `system_done`, `None` while disabled.
+    draws: Option<[u64; 5]>,
+}
+
+/// Add an event to the trace if it's enabled. `event` is only called if it is.
+pub fn record(cvars: &Cvars, trace: &mut Trace, event: impl FnOnce() -> TraceEvent) {
+    if !cvars.d_trace {
+        return;
+    }
+    trace.pending.push(event());
+}
+
+impl Trace {
+    /// `system` finished running during gamelogic tick `tick`, everything recorded since the last call was done by it.
+    pub fn system_done(&mut self, cvars: &Cvars, tick: u64, system: &'static str, rng: &GameRng) {
+        if !cvars.d_trace {
+            self.draws = None;
+            return;
+        }
+
+        let streams = rng.streams();
+        let mut draws = [0; 5];
+        for (draw, stream) in draws.iter_mut().zip(streams.iter()) {
+            *draw = stream.draws;
+        }
+        // Just enabled - there's nothing to compare to.
+        if let Some(prev) = self.draws {
+            let mut used = [0; 5];
+            for i in 0..used.len() {
+                used[i] = draws[i].wrapping_sub(prev[i]);
+            }
+            self.pending.push(TraceEvent::Rng(used));
+        }
+        self.draws = Some(draws);
+
+        for event in self.pending.drain(..) {
+            self.records.push_back(TraceRecord {
+                tick,
+                system,
+                event,
+            });
+        }
+        while let Some(oldest) = self.records.front() {
+            if oldest.tick + cvars.d_trace_ticks > tick {
+                break;
+            }
+            self.records.pop_front();
+        }
+    }
+
+    /// Oldest first.
+    pub fn records(&self) -> impl Iterator<Item = &TraceRecord> {
+        self.records.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+        self.pending.clear();
+        self.draws = None;
+    }
+
+    /// All the records as newline-delimited JSON, one flat object per record.
+    ///
+    /// Handles are `thunderdome::Index::to_bits` as strings because JSON numbers are floats.
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        for record in &self.records {
+            write!(
+                out,
+                r#"{{"tick":{},"system":"{}""#,
+                record.tick, record.system
+            )
+            .unwrap();
+            match record.event {
+                TraceEvent::Rng(used) => {
+                    write!(out, r#","event":"rng""#).unwrap();
+                    for (name, count) in STREAM_NAMES.iter().zip(used.iter()) {
+                        write!(out, r#","{}":{}"#, name, count).unwrap();
+                    }
+                }
+                TraceEvent::Damage {
+                    attacker,
+                    vehicle,
+                    weapon,
+                    amount,
+                    dealt,
+                    hp,
+                } => {
+                    write!(out, r#","event":"damage""#).unwrap();
+                    write_handle(&mut out, "attacker", attacker);
+                    write_handle(&mut out, "vehicle", vehicle);
+                    match weapon {
+                        Some(weapon) => write!(out, r#","weapon":"{:?}""#, weapon).unwrap(),
+                        None => write!(out, r#","weapon":null"#).unwrap(),
+                    }
+                    write!(out, r#","amount":{},"dealt":{},"hp":{}"#, amount, dealt, hp).unwrap();
+                }
+                TraceEvent::Spawn(summary) => write_summary(&mut out, "spawn", &summary),
+                TraceEvent::Remove(summary) => write_summary(&mut out, "remove", &summary),
+                TraceEvent::Blocked {
+                    vehicle,
+                    from,
+                    to,
+                    pos,
+                } => {
+                    write!(out, r#","event":"blocked""#).unwrap();
+                    write_handle(&mut out, "vehicle", vehicle);
+                    write_vec(&mut out, "from", from);
+                    write_vec(&mut out, "to", to);
+                    write_vec(&mut out, "pos", pos);
+                }
+            }
+            out.push_str("}\n");
+        }
+        out
+    }
+}
+
+fn write_summary(out: &mut String, event: &str, summary: &EntitySummary) {
+    write!(out, r#","event":"{}""#, event).unwrap();
+    let (entity, kind) = match summary.kind {
+        EntityKind::Vehicle(veh_type) => ("vehicle", format!("{:?}", veh_type)),
+        EntityKind::Projectile(weapon) => ("projectile", format!("{:?}", weapon)),
+        EntityKind::Pickup(kind) => ("pickup", format!("{:?}", kind)),
+        EntityKind::Flare => ("flare", "Flare".to_owned()),
+    };
+    write!(out, r#","entity":"{}","kind":"{}""#, entity, kind).unwrap();
+    write_handle(out, "handle", summary.handle);
+    match summary.owner {
+        Some(owner) => write_handle(out, "owner", owner),
+        None => write!(out, r#","owner":null"#).unwrap(),
+    }
+    write_vec(out, "pos", summary.pos);
+    write_vec(out, "vel", summary.vel);
+}
+
+fn write_handle(out: &mut String, name: &str, handle: Index) {
+    write!(out, r#","{}":"{}""#, name, handle.to_bits()).unwrap();
+}
+
+/// JSON has no infinities or NaN so they're written as strings.
+fn write_vec(out: &mut String, name: &str, v: Vec2f) {
+    for (axis, value) in [("x", v.x), ("y", v.y)] {
+        if value.is_finite() {
+            write!(out, r#","{}_{}":{}"#, name, axis, value).unwrap();
+        } else {
+            write!(out, r#","{}_{}":"{}""#, name, axis, value).unwrap();
+        }
+    }
+}
+
+/// Where two dumps first differ, see `diff`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceDiff {
+    /// Starting from 1.
+    pub line: usize,
+    /// Of the earlier of the two records.
+    pub tick: u64,
+    pub system: String,
+    /// The first field which differs, `None` if one dump ended before the other.
+    pub field: Option<String>,
+    /// The field's value in each dump, `None` if it's missing.
+    pub a: Option<Value>,
+    pub b: Option<Value>,
+}
+
+impl Display for TraceDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: tick {} system {}",
+            self.line, self.tick, self.system
+        )?;
+        match &self.field {
+            Some(field) => write!(f, " field {}: {:?} vs {:?}", field, self.a, self.b),
+            None => write!(f, ": one dump ended"),
+        }
+    }
+}
+
+/// Find the first record where two dumps (see `Trace::dump`) differ, `None` if they're the same.
+///
+/// The dumps should cover the same ticks, e.g. both runs were traced from the start.
+pub fn diff(a: &str, b: &str) -> Result<Option<TraceDiff>, JsonError> {
+    let mut lines_a = a.lines();
+    let mut lines_b = b.lines();
+    let mut line = 0;
+    loop {
+        line += 1;
+        let (line_a, line_b) = match (lines_a.next(), lines_b.next()) {
+            (None, None) => return Ok(None),
+            (line_a, line_b) => (line_a, line_b),
+        };
+        if line_a == line_b {
+            continue;
+        }
+        let record_a = line_a.map(json::parse_object).transpose()?;
+        let record_b = line_b.map(json::parse_object).transpose()?;
+
+        // The earlier record is where the runs started doing something different.
+        let tick_system = |record: &Option<Vec<(String, Value)>>| {
+            let record = record.as_ref()?;
+            let tick = match field(record, "tick") {
+                Some(Value::Num(tick)) => *tick as u64,
+                _ => return None,
+            };
+            let system = match field(record, "system") {
+                Some(Value::Str(system)) => system.clone(),
+                _ => String::new(),
+            };
+            Some((tick, system))
+        };
+        let (tick, system) = match (tick_system(&record_a), tick_system(&record_b)) {
+            (Some(ta), Some(tb)) if tb.0 < ta.0 => tb,
+            (Some(ta), _) => ta,
+            (None, Some(tb)) => tb,
+            (None, None) => (0, String::new()),
+        };
+
+        let (record_a, record_b) = match (record_a, record_b) {
+            (Some(record_a), Some(record_b)) => (record_a, record_b),
+            _ => {
+                return Ok(Some(TraceDiff {
+                    line,
+                    tick,
+                    system,
+                    field: None,
+                    a: None,
+                    b: None,
+                }))
+            }
+        };
+        // Fields of `a` in order, then those only `b` has.
+        let names = record_a.iter().chain(record_b.iter()).map(|(name, _)| name);
+        for name in names {
+            let value_a = field(&record_a, name);
+            let value_b = field(&record_b, name);
+            if value_a != value_b {
+                return Ok(Some(TraceDiff {
+                    line,
+                    tick,
+                    system,
+                    field: Some(name.clone()),
+                    a: value_a.cloned(),
+                    b: value_b.cloned(),
+                }));
+            }
+        }
+        // Only whitespace or field order differs.
+    }
+}
+
+fn field<'a>(record: &'a [(String, Value)], name: &str) -> Option<&'a Value> {
+    record
+        .iter()
+        .find(|(other, _)| other == name)
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use rand::Rng;
+    use thunderdome::Arena;
+
+    use crate::{game_state::Input, map, server::Server};
+
+    use super::*;
+
+    fn new_server(cvars: &Cvars) -> Server {
+        let map = map::tests::load_test_map(&fs::read_to_string("maps/Atrium.map").unwrap());
+        Server::new(cvars, map)
+    }
+
+    /// A bot match with a player driving and shooting. Returns the dump and if `perturb_after` is set,
+    /// the tick after which the AI's RNG stream was used once more than it should be.
+    fn run(cvars: &Cvars, ticks: u64, perturb_after: Option<u64>) -> (String, Option<u64>) {
+        let mut server = new_server(cvars);
+        let player_handle = server.connect(cvars, "Player 1");
+        let mut perturbed = None;
+        let mut real_time = 0.0;
+        while server.gs.frame_num < ticks {
+            real_time += 1.0 / 60.0;
+            server.snapshot_inputs();
+            let input = Input {
+                up: true,
+                left: server.gs.frame_num % 100 < 20,
+                fire: true,
+                ..Input::new()
+            };
+            server.input(player_handle, input);
+            server.update(cvars, real_time);
+            let due = perturb_after.map_or(false, |tick| server.gs.frame_num >= tick);
+            if due && perturbed.is_none() {
+                let _: u64 = server.gs.rng.ai.gen();
+                perturbed = Some(server.gs.frame_num);
+            }
+        }
+        (server.gs.trace.dump(), perturbed)
+    }
+
+    #[test]
+    fn test_perturbed_run() {
+        let cvars = Cvars {
+            d_seed: 3,
+            d_trace: true,
+            d_trace_ticks: 2000,
+            g_match_countdown: 0.0,
+            ..Cvars::default()
+        };
+        let (first, _) = run(&cvars, 1200, None);
+        let (second, _) = run(&cvars, 1200, None);
+        assert_eq!(diff(&first, &second).unwrap(), None);
+        // Everything required is recorded.
+        for event in ["rng", "damage", "spawn", "remove", "blocked"] {
+            let needle = format!(r#""event":"{}""#, event);
+            assert!(first.contains(&needle), "no {} events", event);
+        }
+
+        let (perturbed, after) = run(&cvars, 1200, Some(600));
+        let diff = diff(&first, &perturbed).unwrap().unwrap();
+        assert_eq!(diff.tick, after.unwrap() + 1, "{}", diff);
+        assert_eq!(diff.system, "between_ticks", "{}", diff);
+        assert_eq!(diff.field.as_deref(), Some("ai"), "{}", diff);
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let cvars = Cvars {
+            d_trace: true,
+            d_trace_ticks: 3,
+            ..Cvars::default()
+        };
+        let mut rng = GameRng::new(0);
+        let mut trace = Trace::default();
+        let handle = Arena::<()>::new().insert(());
+        for tick in 1..=10 {
+            record(&cvars, &mut trace, || TraceEvent::Blocked {
+                vehicle: handle,
+                from: Vec2f::zero(),
+                to: Vec2f::new(1.0, 0.0),
+                pos: Vec2f::zero(),
+            });
+            let _: f64 = rng.world.gen();
+            trace.system_done(&cvars, tick, "movement", &rng);
+        }
+        let ticks: Vec<_> = trace.records().map(|record| record.tick).collect();
+        // The first tick has no RNG record because there was nothing to compare the draw counts to.
+        assert_eq!(ticks, [8, 8, 9, 9, 10, 10]);
+        assert_eq!(
+            trace.records().nth(1).unwrap().event,
+            TraceEvent::Rng([0, 0, 0, 0, 1])
+        );
+
+        // Disabled records nothing and doesn't even build the event.
+        let cvars = Cvars::default();
+        let mut trace = Trace::default();
+        record(&cvars, &mut trace, || unreachable!());
+        trace.system_done(&cvars, 1, "movement", &rng);
+        assert_eq!(trace.records().count(), 0);
+        assert_eq!(trace.dump(), "");
+    }
+
+    #[test]
+    fn test_diff() {
+        let a = "{\"tick\":1,\"system\":\"ai\",\"event\":\"rng\",\"ai\":2}\n\
+                 {\"tick\":2,\"system\":\"shooting\",\"event\":\"rng\",\"ai\":1}\n";
+        let b = "{\"tick\":1,\"system\":\"ai\",\"event\":\"rng\",\"ai\":2}\n\
+                 {\"tick\":2,\"system\":\"shooting\",\"event\":\"rng\",\"ai\":3}\n";
+        let found = diff(a, b).unwrap().unwrap();
+        assert_eq!(found.line, 2);
+        assert_eq!(found.tick, 2);
+        assert_eq!(found.system, "shooting");
+        assert_eq!(found.field.as_deref(), Some("ai"));
+        assert_eq!(found.a, Some(Value::Num(1.0)));
+        assert_eq!(found.b, Some(Value::Num(3.0)));
+
+        // One ended early.
+        let short = "{\"tick\":1,\"system\":\"ai\",\"event\":\"rng\",\"ai\":2}\n";
+        let found = diff(a, short).unwrap().unwrap();
+        assert_eq!((found.line, found.tick, found.field), (2, 2, None));
+
+        assert!(diff(a, "not json").is_err());
+    }
+}
@@ -0,0 +1,194 @@
+//! Line of sight and cover queries on top of the map's walls, used by BFG beams and bots.
+//!
+//! Only walls block sight, vehicles and other entities don't. Everything is a wall trace
+//! (see `Map::is_wall_trace`) so the cost grows with the distance and the number of queries,
+//! `nearest_cover` is by far the most expensive, see `bench_nearest_cover`.
+
+use crate::map::{Map, Vec2f, TILE_SIZE};
+
+/// `nearest_cover` never scans further than this to keep its cost bounded.
+pub const COVER_RADIUS_MAX: f64 = 8.0 * TILE_SIZE;
+
+/// Half the size of the square checked when looking for cover, a bit bigger than the vehicles' hitboxes.
+pub const COVER_HALF_SIZE: f64 = 16.0;
+
+/// A tile is cover if less than this fraction of the square is visible from the threat.
+pub const COVER_VISIBILITY_MAX: f64 = 0.25;
+
+/// Can something at `from` see `to`? Center to center, only walls block it.
+pub fn line_of_sight(map: &Map, from: Vec2f, to: Vec2f) -> bool {
+    map.is_wall_trace(from, to).is_none()
+}
+
+/// How exposed a hitbox is to someone at `from` - 0.0 fully covered to 1.0 fully exposed.
+///
+/// Samples the `corners` and their average (the center), each visible one counts the same.
+pub fn visibility_fraction(map: &Map, from: Vec2f, corners: &[Vec2f]) -> f64 {
+    if corners.is_empty() {
+        return 0.0;
+    }
+    let center = corners
+        .iter()
+        .fold(Vec2f::zero(), |sum, &corner| sum + corner)
+        / corners.len() as f64;
+    let visible = corners
+        .iter()
+        .chain(std::iter::once(&center))
+        .filter(|&&point| line_of_sight(map, from, point))
+        .count();
+    visible as f64 / (corners.len() + 1) as f64
+}
+
+/// The center of the closest tile within `radius` of `from` where a vehicle would be hidden from `threat`.
+///
+/// Only tiles a vehicle can drive on count and `radius` is capped at `COVER_RADIUS_MAX`.
+/// Ties are broken row by row so the result is deterministic.
+pub fn nearest_cover(map: &Map, from: Vec2f, threat: Vec2f, radius: f64) -> Option<Vec2f> {
+    let radius = radius.min(COVER_RADIUS_MAX);
+    let mut candidates: Vec<_> = map
+        .tiles_in_rect(from - radius, from + radius)
+        .map(|index| map.tile_center(index))
+        .filter(|center| center.distance_squared(from) <= radius * radius)
+        .collect();
+    // Stable so equally close tiles stay row by row.
+    candidates.sort_by(|a, b| {
+        a.distance_squared(from)
+            .partial_cmp(&b.distance_squared(from))
+            .unwrap()
+    });
+    candidates.into_iter().find(|&center| {
+        if map.is_wall(center) {
+            return false;
+        }
+        let corners = [
+            center + Vec2f::new(-COVER_HALF_SIZE, -COVER_HALF_SIZE),
+            center + Vec2f::new(COVER_HALF_SIZE, -COVER_HALF_SIZE),
+            center + Vec2f::new(COVER_HALF_SIZE, COVER_HALF_SIZE),
+            center + Vec2f::new(-COVER_HALF_SIZE, COVER_HALF_SIZE),
+        ];
+        visibility_fraction(map, threat, &corners) < COVER_VISIBILITY_MAX
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Instant};
+
+    use crate::map::{self, Kind, Surface, Vec2u};
+
+    use super::*;
+
+    /// `rows` of tiles, `#` is a wall, anything else is ground.
+    fn fixture(rows: &[&str]) -> Map {
+        let surface = |name: &str, kind| Surface {
+            name: name.to_owned(),
+            kind,
+            friction: 1.0,
+            speed: 1.0,
+            destructible: None,
+        };
+        let surfaces = vec![surface("ground", Kind::Normal), surface("wall", Kind::Wall)];
+        let text: Vec<String> = rows
+            .iter()
+            .map(|row| {
+                let tiles: Vec<_> = row
+                    .chars()
+                    .map(|c| if c == '#' { "4" } else { "0" })
+                    .collect();
+                tiles.join(" ")
+            })
+            .collect();
+        map::load_map(&text.join("\n"), surfaces).unwrap()
+    }
+
+    fn square(center: Vec2f) -> [Vec2f; 4] {
+        [
+            center + Vec2f::new(-16.0, -16.0),
+            center + Vec2f::new(16.0, -16.0),
+            center + Vec2f::new(16.0, 16.0),
+            center + Vec2f::new(-16.0, 16.0),
+        ]
+    }
+
+    #[test]
+    fn test_line_of_sight() {
+        let map = fixture(&[".....", "..#..", "....."]);
+        let left = map.tile_center(Vec2u::new(0, 1));
+        let right = map.tile_center(Vec2u::new(4, 1));
+        assert!(!line_of_sight(&map, left, right));
+        let top_left = map.tile_center(Vec2u::new(0, 0));
+        let top_right = map.tile_center(Vec2u::new(4, 0));
+        assert!(line_of_sight(&map, top_left, top_right));
+    }
+
+    #[test]
+    fn test_visibility_fraction() {
+        let map = fixture(&[".....", "..#..", ".....", "....."]);
+        let from = Vec2f::new(32.0, 124.0);
+
+        // Fully behind the wall.
+        let hidden = square(map.tile_center(Vec2u::new(3, 1)));
+        assert_eq!(visibility_fraction(&map, from, &hidden), 0.0);
+
+        // Sticking out below its corner.
+        let corner = square(Vec2f::new(224.0, 128.0));
+        let fraction = visibility_fraction(&map, from, &corner);
+        assert!(fraction > 0.25 && fraction < 0.75, "{}", fraction);
+
+        // In the open.
+        let open = square(map.tile_center(Vec2u::new(3, 3)));
+        assert_eq!(visibility_fraction(&map, from, &open), 1.0);
+    }
+
+    #[test]
+    fn test_nearest_cover() {
+        let map = fixture(&[".......", ".......", "...#...", ".......", "......."]);
+        let threat = map.tile_center(Vec2u::new(0, 2));
+        let from = map.tile_center(Vec2u::new(4, 0));
+        let cover = nearest_cover(&map, from, threat, 3.0 * TILE_SIZE);
+        assert_eq!(cover, Some(map.tile_center(Vec2u::new(4, 2))));
+
+        // Too far to reach.
+        assert_eq!(nearest_cover(&map, from, threat, TILE_SIZE), None);
+
+        // Nowhere to hide without walls.
+        let open = fixture(&["....", "....", "...."]);
+        let cover = nearest_cover(&open, open.tile_center(Vec2u::new(3, 1)), threat, 1000.0);
+        assert_eq!(cover, None);
+    }
+
+    /// How long `nearest_cover` takes on a real map with the largest radius.
+    ///
+    /// Timing is noisy so this only prints the results, run it with:
+    /// `cargo test --release -- --ignored --nocapture bench_nearest_cover`
+    #[test]
+    #[ignore]
+    fn bench_nearest_cover() {
+        let map = map::tests::load_test_map(&fs::read_to_string("maps/Atrium.map").unwrap());
+
+        let mut queries = 0;
+        let mut found = 0;
+        let start = Instant::now();
+        for y in (0..map.height()).step_by(3) {
+            for x in (0..map.width()).step_by(3) {
+                let from = map.tile_center(Vec2u::new(x, y));
+                let threat = from + Vec2f::new(5.0 * TILE_SIZE, 3.0 * TILE_SIZE);
+                if map.is_wall(from) || map.is_wall(threat) {
+                    continue;
+                }
+                queries += 1;
+                if nearest_cover(&map, from, threat, COVER_RADIUS_MAX).is_some() {
+                    found += 1;
+                }
+            }
+        }
+        let elapsed = start.elapsed().as_secs_f64();
+        assert!(queries > 0);
+        println!(
+            "{} queries ({} found cover): {:.3} ms per query",
+            queries,
+            found,
+            elapsed / queries as f64 * 1000.0
+        );
+    }
+}